@@ -0,0 +1,92 @@
+//! Benchmarks for what every request (or every login) pays for:
+//!
+//! ```sh
+//! cargo bench -p jelly
+//! cargo bench -p jelly --features fast-hash  # hashing as the tests do it
+//! ```
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use jelly::accounts::{jwt, password, User};
+use jelly::djangohashers::check_password;
+use tera::{Context, Tera};
+
+const LAYOUT: &str = r#"<!DOCTYPE html>
+<html>
+<head><title>{% block title %}{% endblock %}</title></head>
+<body>
+<nav>{% if user.is_anonymous %}<a href="/accounts/login">Sign in</a>{% else %}{{ user.name }}{% endif %}</nav>
+{% block content %}{% endblock %}
+</body>
+</html>"#;
+
+const PAGE: &str = r#"{% extends "layout.html" %}
+{% block title %}Dashboard{% endblock %}
+{% block content %}
+<img src="{{ avatar_url(email=email, size=64) }}">
+<ul>
+    {% for flash in flash_messages %}
+    <li><strong>{{ flash.title }}</strong><br/>{{ flash.message }}</li>
+    {% endfor %}
+</ul>
+<table>
+    {% for row in rows %}
+    <tr><td>{{ row.id }}</td><td>{{ row.name | title }}</td><td>{{ row.when | localtime(tz="Europe/London") }}</td></tr>
+    {% endfor %}
+</table>
+{% endblock %}"#;
+
+fn user() -> User {
+    User {
+        id: 42,
+        name: "Jane Doe".to_string(),
+        is_admin: false,
+        is_anonymous: false,
+        ..User::default()
+    }
+}
+
+fn render(c: &mut Criterion) {
+    let mut tera = Tera::default();
+    tera.add_raw_templates(vec![("layout.html", LAYOUT), ("dashboard.html", PAGE)])
+        .unwrap();
+    jelly::avatars::register(&mut tera);
+    jelly::timezones::register(&mut tera);
+
+    let mut context = Context::new();
+    context.insert("user", &user());
+    context.insert("email", "jane@example.com");
+    context.insert("flash_messages", &[serde_json::json!({ "title": "Saved", "message": "All done." })]);
+    let rows: Vec<_> = (0..50)
+        .map(|id| serde_json::json!({ "id": id, "name": "row name", "when": "2022-04-27T09:00:00Z" }))
+        .collect();
+    context.insert("rows", &rows);
+
+    c.bench_function("render dashboard", |b| {
+        b.iter(|| tera.render("dashboard.html", black_box(&context)).unwrap())
+    });
+}
+
+fn login(c: &mut Criterion) {
+    let encoded = password::hash("correct horse battery staple");
+
+    let mut group = c.benchmark_group("login");
+    group.sample_size(10);
+    group.bench_function("hash password", |b| {
+        b.iter(|| password::hash(black_box("correct horse battery staple")))
+    });
+    group.bench_function("check password", |b| {
+        b.iter(|| check_password(black_box("correct horse battery staple"), &encoded).unwrap())
+    });
+    group.finish();
+}
+
+fn tokens(c: &mut Criterion) {
+    std::env::set_var("SECRET_KEY", "bench-secret-key-bench-secret-key");
+    let (token, _) = jwt::issue(&user()).unwrap();
+
+    c.bench_function("issue token", |b| b.iter(|| jwt::issue(black_box(&user())).unwrap()));
+    c.bench_function("verify token", |b| b.iter(|| jwt::verify(black_box(&token)).unwrap()));
+}
+
+criterion_group!(benches, render, login, tokens);
+criterion_main!(benches);
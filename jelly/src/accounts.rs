@@ -3,21 +3,94 @@
 //! but not necessarily a full framework.
 
 use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgPool;
+
+use crate::error::Error;
+
+pub mod jwt;
+pub use jwt::{AuthMode, Bearer};
 
 pub mod password;
 pub use password::make_random_password;
 
+pub mod permissions;
+
+pub mod service;
+pub use service::ServiceAccount;
+
+pub mod remember;
+pub use remember::{RememberMe, RememberMeMiddleware};
+
+pub mod sessions;
+
 pub mod token_generator;
 pub use token_generator::OneTimeUseTokenGenerator;
 
 /// A smaller, serialize-able instance of an Account
 /// that can be used to avoid a database hit.
-#[derive(Debug, Serialize, Deserialize)]
+///
+/// Sessions keep it until the account's `session_version` changes (see
+/// `sessions::refresh`), when it's loaded again. Sessions from before the
+/// later fields were added get their defaults, which are refreshed the same
+/// way.
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct User {
     pub id: i32,
     pub name: String,
     pub is_admin: bool,
     pub is_anonymous: bool,
+    #[serde(default)]
+    pub email: String,
+    #[serde(default)]
+    pub has_verified_email: bool,
+    /// The names of the account's roles (see `permissions`).
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub session_version: i32,
+}
+
+impl User {
+    /// The signed in user for account `id`, as sessions keep it.
+    pub async fn load(pool: &PgPool, id: i32) -> Result<Self, Error> {
+        let (name, email, is_admin, has_verified_email, session_version, roles): (
+            String,
+            String,
+            bool,
+            bool,
+            i32,
+            Vec<String>,
+        ) = sqlx::query_as(
+            "
+            SELECT a.name, a.email, a.is_admin, a.has_verified_email, a.session_version,
+                array(
+                    SELECT r.name FROM account_roles ar
+                    JOIN roles r ON r.id = ar.role_id
+                    WHERE ar.account_id = a.id
+                    ORDER BY r.name
+                )
+            FROM accounts a WHERE a.id = $1
+        ",
+        )
+        .bind(id)
+        .fetch_one(pool)
+        .await?;
+
+        Ok(User {
+            id,
+            name,
+            is_admin,
+            is_anonymous: false,
+            email,
+            has_verified_email,
+            roles,
+            session_version,
+        })
+    }
+
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r == role)
+    }
 }
 
 impl Default for User {
@@ -28,6 +101,10 @@ impl Default for User {
             name: String::new(),
             is_admin: false,
             is_anonymous: true,
+            email: String::new(),
+            has_verified_email: false,
+            roles: Vec::new(),
+            session_version: 0,
         }
     }
 }
@@ -0,0 +1,493 @@
+//! Stateless authentication with JSON Web Tokens (HS256), for API clients
+//! that would rather not carry a session cookie around. Which of the two
+//! is accepted is picked on the `Server` builder with `auth_mode`:
+//!
+//! ```ignore
+//! jelly::Server::new().auth_mode(AuthMode::Both)
+//! ```
+//!
+//! Tokens are sent as `Authorization: Bearer <token>`. With
+//! `JWT_SIGNING_KEYS` set they're signed with ES256 (see
+//! `crypto::signing_keys`), and the public keys are published at
+//! `/.well-known/jwks.json` for other services to check tokens with;
+//! otherwise they're HS256, with `JWT_SECRET` (or `SECRET_KEY`, if that's
+//! not set). They last for `JWT_TTL_MINUTES` (15 by default), and being
+//! stateless, they can't be revoked before then - keep them short.
+//!
+//! Clients keep going with a refresh token instead (see `grant`), which
+//! lasts `JWT_REFRESH_DAYS` (30 by default), is kept in the
+//! `refresh_tokens` table so it can be revoked, and is swapped for a new
+//! one each time it's used.
+//!
+//! Each sign in is an API token (a row in `api_tokens`), which carries on
+//! through every refresh after it, so owners can see their clients' usage
+//! and sign them out one at a time. Access tokens name theirs in the `tid`
+//! claim.
+//!
+//! Service accounts (see `service`) get access tokens too, naming the
+//! service account in `svc` and what it may do in `scp`; handlers check
+//! the latter with `require_scope`. They're only good under `SERVICE_PATH`,
+//! so an integration can't wander into its owner's dashboard.
+//!
+//! With tokens accepted, `request.user()` falls back to the token's user,
+//! and the `Auth` guard lets token-carrying requests through; handlers that
+//! only take tokens can use the `Bearer` extractor instead.
+
+use std::env;
+
+use actix_web::dev::Payload;
+use actix_web::http::header::{AUTHORIZATION, CACHE_CONTROL};
+use actix_web::web::ServiceConfig;
+use actix_web::{FromRequest, HttpRequest, HttpResponse};
+use chrono::{DateTime, Duration, Utc};
+use constant_time_eq::constant_time_eq;
+use futures::future::{ready, Ready};
+use hmac::{Hmac, Mac, NewMac};
+use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use sqlx::postgres::PgPool;
+
+use super::{permissions, User};
+use crate::crypto;
+use crate::error::Error;
+use crate::request::Authentication;
+use crate::routes;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The header HS256 tokens are issued with.
+const HS256_HEADER: &str = r#"{"alg":"HS256","typ":"JWT"}"#;
+
+/// Where service account tokens are accepted.
+pub const SERVICE_PATH: &str = "/api/";
+
+/// Which credentials the app accepts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuthMode {
+    /// Cookie sessions only; bearer tokens are ignored.
+    Session,
+    /// Bearer tokens only; session users are ignored.
+    Jwt,
+    /// Either, with the session tried first.
+    Both,
+}
+
+impl Default for AuthMode {
+    fn default() -> Self {
+        AuthMode::Session
+    }
+}
+
+impl AuthMode {
+    /// The mode the app was started with.
+    pub fn of(request: &HttpRequest) -> Self {
+        request.app_data::<AuthMode>().copied().unwrap_or_default()
+    }
+
+    pub fn sessions(self) -> bool {
+        self != AuthMode::Jwt
+    }
+
+    pub fn tokens(self) -> bool {
+        self != AuthMode::Session
+    }
+}
+
+/// What a token says about its user.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Claims {
+    /// The account id.
+    pub sub: i32,
+    pub name: String,
+    #[serde(default)]
+    pub adm: bool,
+    pub iat: i64,
+    pub exp: i64,
+    /// The API token it was granted under, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tid: Option<i32>,
+    /// The service account it was issued to, if it was.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub svc: Option<i32>,
+    /// What it's limited to; people's tokens aren't.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scp: Option<Vec<String>>,
+}
+
+impl Claims {
+    /// Whether the token allows `scope`. Granted scopes can end in `*`,
+    /// like permissions (see `permissions::matches`).
+    pub fn allows(&self, scope: &str) -> bool {
+        match &self.scp {
+            Some(scopes) => scopes.iter().any(|granted| permissions::matches(granted, scope)),
+            None => true,
+        }
+    }
+}
+
+impl From<Claims> for User {
+    fn from(claims: Claims) -> Self {
+        User {
+            id: claims.sub,
+            name: claims.name,
+            is_admin: claims.adm,
+            is_anonymous: false,
+            ..User::default()
+        }
+    }
+}
+
+/// How long tokens last.
+pub fn ttl() -> Duration {
+    let minutes = env::var("JWT_TTL_MINUTES")
+        .ok()
+        .and_then(|minutes| minutes.parse().ok())
+        .filter(|minutes| *minutes > 0)
+        .unwrap_or(15);
+    Duration::minutes(minutes)
+}
+
+/// How long refresh tokens last.
+pub fn refresh_ttl() -> Duration {
+    let days = env::var("JWT_REFRESH_DAYS")
+        .ok()
+        .and_then(|days| days.parse().ok())
+        .filter(|days| *days > 0)
+        .unwrap_or(30);
+    Duration::days(days)
+}
+
+fn hs256_signature(signing_input: &str) -> String {
+    // This is enforced at server startup, so it's safe to do here.
+    let secret = env::var("JWT_SECRET")
+        .or_else(|_| env::var("SECRET_KEY"))
+        .expect("Unable to pull JWT_SECRET or SECRET_KEY for signing tokens");
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(signing_input.as_bytes());
+    base64::encode_config(mac.finalize().into_bytes(), base64::URL_SAFE_NO_PAD)
+}
+
+/// A token for `user`, and when it expires (as a timestamp).
+pub fn issue(user: &User) -> Result<(String, i64), Error> {
+    issue_under(user, None)
+}
+
+fn issue_under(user: &User, token_id: Option<i32>) -> Result<(String, i64), Error> {
+    let now = Utc::now();
+    let claims = Claims {
+        sub: user.id,
+        name: user.name.clone(),
+        adm: user.is_admin,
+        iat: now.timestamp(),
+        exp: (now + ttl()).timestamp(),
+        tid: token_id,
+        svc: None,
+        scp: None,
+    };
+    Ok((sign(&claims)?, claims.exp))
+}
+
+/// A token for service account `service_id`, working with `account_id`'s
+/// data but only within `scopes`, and when it expires (as a timestamp).
+pub fn issue_service(account_id: i32, service_id: i32, name: &str, scopes: &[String]) -> Result<(String, i64), Error> {
+    let now = Utc::now();
+    let claims = Claims {
+        sub: account_id,
+        name: name.to_string(),
+        adm: false,
+        iat: now.timestamp(),
+        exp: (now + ttl()).timestamp(),
+        tid: None,
+        svc: Some(service_id),
+        scp: Some(scopes.to_vec()),
+    };
+    Ok((sign(&claims)?, claims.exp))
+}
+
+fn sign(claims: &Claims) -> Result<String, Error> {
+    let payload = base64::encode_config(serde_json::to_vec(claims)?, base64::URL_SAFE_NO_PAD);
+    let token = match crypto::signing_keys().first() {
+        Some(key) => {
+            let header = json!({ "alg": "ES256", "typ": "JWT", "kid": key.kid });
+            let signing_input = format!(
+                "{}.{}",
+                base64::encode_config(serde_json::to_vec(&header)?, base64::URL_SAFE_NO_PAD),
+                payload
+            );
+            let signature = base64::encode_config(key.sign(signing_input.as_bytes()), base64::URL_SAFE_NO_PAD);
+            format!("{}.{}", signing_input, signature)
+        }
+        None => {
+            let signing_input = format!("{}.{}", base64::encode_config(HS256_HEADER, base64::URL_SAFE_NO_PAD), payload);
+            format!("{}.{}", signing_input, hs256_signature(&signing_input))
+        }
+    };
+    Ok(token)
+}
+
+/// Whether `signature` is right for `signing_input` with `header`'s key.
+/// Only what's issued is accepted: ES256 with one of our keys, or HS256
+/// when there are none. Anything else is refused rather than trusted (no
+/// "alg": "none", and no HS256 tokens once keys are set).
+fn verify_signature(header: &serde_json::Value, signing_input: &str, signature: &str) -> bool {
+    let keys = crypto::signing_keys();
+    match header.get("alg").and_then(|alg| alg.as_str()) {
+        Some("ES256") => {
+            let kid = header.get("kid").and_then(|kid| kid.as_str());
+            let key = keys.iter().find(|key| Some(key.kid.as_str()) == kid);
+            match (key, base64::decode_config(signature, base64::URL_SAFE_NO_PAD)) {
+                (Some(key), Ok(signature)) => key.verify(signing_input.as_bytes(), &signature),
+                _ => false,
+            }
+        }
+        Some("HS256") if keys.is_empty() => {
+            constant_time_eq(hs256_signature(signing_input).as_bytes(), signature.as_bytes())
+        }
+        _ => false,
+    }
+}
+
+/// The claims in `token`, if it's one of ours and hasn't expired.
+pub fn verify(token: &str) -> Option<Claims> {
+    let (signing_input, signature) = token.rsplit_once('.')?;
+    let (header, payload) = signing_input.split_once('.')?;
+    let header = base64::decode_config(header, base64::URL_SAFE_NO_PAD).ok()?;
+    let header: serde_json::Value = serde_json::from_slice(&header).ok()?;
+    if !verify_signature(&header, signing_input, signature) {
+        return None;
+    }
+
+    let payload = base64::decode_config(payload, base64::URL_SAFE_NO_PAD).ok()?;
+    let claims: Claims = serde_json::from_slice(&payload).ok()?;
+    if claims.exp <= Utc::now().timestamp() {
+        return None;
+    }
+    Some(claims)
+}
+
+/// What clients get for signing in: a short-lived access token, and a
+/// refresh token to get the next one with. Times are timestamps.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Grant {
+    pub access_token: String,
+    pub token_type: String,
+    pub expires: i64,
+    pub refresh_token: String,
+    pub refresh_expires: i64,
+}
+
+/// Refresh tokens are stored hashed, so a leaked table can't be used.
+fn refresh_hash(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Signs `user` in with a new API token, named for the client (e.g its
+/// user agent), issuing an access token and a refresh token.
+pub async fn grant(pool: &PgPool, user: &User, name: Option<&str>) -> Result<Grant, Error> {
+    let token_id: i32 =
+        sqlx::query_scalar("INSERT INTO api_tokens (account_id, name, expires) VALUES ($1, $2, $3) RETURNING id")
+            .bind(user.id)
+            .bind(name)
+            .bind(Utc::now() + refresh_ttl())
+            .fetch_one(pool)
+            .await?;
+
+    grant_under(pool, user, token_id).await
+}
+
+/// Issues an access token and a new refresh token for `user`, under an
+/// API token that's been granted already, pushing back when it expires.
+async fn grant_under(pool: &PgPool, user: &User, token_id: i32) -> Result<Grant, Error> {
+    let (access_token, expires) = issue_under(user, Some(token_id))?;
+
+    let bytes: [u8; 32] = thread_rng().gen();
+    let refresh_token = base64::encode_config(bytes, base64::URL_SAFE_NO_PAD);
+    let refresh_expires: DateTime<Utc> = Utc::now() + refresh_ttl();
+    sqlx::query(
+        "INSERT INTO refresh_tokens (account_id, token_hash, expires, api_token_id) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(user.id)
+    .bind(refresh_hash(&refresh_token))
+    .bind(refresh_expires)
+    .bind(token_id)
+    .execute(pool)
+    .await?;
+
+    // A renewed token gets a fresh expiry warning, when it's due.
+    sqlx::query("UPDATE api_tokens SET expires = $2, expiry_warned_at = NULL WHERE id = $1")
+        .bind(token_id)
+        .bind(refresh_expires)
+        .execute(pool)
+        .await?;
+
+    Ok(Grant {
+        access_token,
+        token_type: "Bearer".to_string(),
+        expires,
+        refresh_token,
+        refresh_expires: refresh_expires.timestamp(),
+    })
+}
+
+/// Uses up `refresh_token`, granting the next tokens under the same API
+/// token if it was valid: unexpired, unrevoked, and for an account that's
+/// still there. Each one only works once, so clients must keep the next.
+pub async fn redeem(pool: &PgPool, refresh_token: &str) -> Result<Option<Grant>, Error> {
+    let account: Option<(i32, String, bool, Option<i32>)> = sqlx::query_as(
+        "
+        WITH used AS (
+            DELETE FROM refresh_tokens
+            WHERE token_hash = $1
+            RETURNING account_id, expires, revoked_at, api_token_id
+        )
+        SELECT a.id, a.name, a.is_admin, u.api_token_id
+        FROM used u
+        JOIN accounts a ON a.id = u.account_id
+        WHERE u.expires > now() AND u.revoked_at IS NULL AND a.is_active AND a.deleted_at IS NULL
+    ",
+    )
+    .bind(refresh_hash(refresh_token))
+    .fetch_optional(pool)
+    .await?;
+
+    let (id, name, is_admin, token_id) = match account {
+        Some(account) => account,
+        None => return Ok(None),
+    };
+    let user = User {
+        id,
+        name,
+        is_admin,
+        is_anonymous: false,
+        ..User::default()
+    };
+
+    // Refresh tokens from before API tokens were kept start one now.
+    Ok(Some(match token_id {
+        Some(token_id) => grant_under(pool, &user, token_id).await?,
+        None => grant(pool, &user, None).await?,
+    }))
+}
+
+/// Revokes one of an account's API tokens and its refresh token. Access
+/// tokens already out still work until they expire.
+pub async fn revoke(pool: &PgPool, account_id: i32, token_id: i32) -> Result<(), Error> {
+    sqlx::query("UPDATE api_tokens SET revoked_at = now() WHERE account_id = $1 AND id = $2 AND revoked_at IS NULL")
+        .bind(account_id)
+        .bind(token_id)
+        .execute(pool)
+        .await?;
+    sqlx::query(
+        "UPDATE refresh_tokens SET revoked_at = now() WHERE account_id = $1 AND api_token_id = $2 AND revoked_at IS NULL",
+    )
+    .bind(account_id)
+    .bind(token_id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Revokes all of an account's API tokens and refresh tokens, e.g when
+/// its password changes. Access tokens already out still work until they
+/// expire.
+pub async fn revoke_all(pool: &PgPool, account_id: i32) -> Result<(), Error> {
+    sqlx::query("UPDATE api_tokens SET revoked_at = now() WHERE account_id = $1 AND revoked_at IS NULL")
+        .bind(account_id)
+        .execute(pool)
+        .await?;
+    sqlx::query("UPDATE refresh_tokens SET revoked_at = now() WHERE account_id = $1 AND revoked_at IS NULL")
+        .bind(account_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// The public keys tokens are signed with, as a JWKS. Empty if tokens
+/// are signed with a shared secret (which is never published).
+pub async fn jwks() -> HttpResponse {
+    let keys: Vec<serde_json::Value> = crypto::signing_keys().iter().map(|key| key.jwk()).collect();
+    HttpResponse::Ok()
+        .insert_header((CACHE_CONTROL, "public, max-age=3600"))
+        .json(json!({ "keys": keys }))
+}
+
+pub fn configure(config: &mut ServiceConfig) {
+    routes::resource("/.well-known/jwks.json").get(jwks).mount(config);
+}
+
+/// The claims in the request's bearer token, if tokens are accepted and
+/// it has a valid one. Service account tokens only count under
+/// `SERVICE_PATH`.
+pub fn bearer_claims(request: &HttpRequest) -> Option<Claims> {
+    if !AuthMode::of(request).tokens() {
+        return None;
+    }
+
+    let header = request.headers().get(AUTHORIZATION)?.to_str().ok()?;
+    let token = header
+        .strip_prefix("Bearer ")
+        .or_else(|| header.strip_prefix("bearer "))?;
+    verify(token.trim()).filter(|claims| claims.svc.is_none() || request.path().starts_with(SERVICE_PATH))
+}
+
+/// Fails with `Error::PermissionDenied` (a 403) if the request's bearer
+/// token is limited to scopes that don't include `scope`. Sessions and
+/// people's tokens can do anything their user can.
+pub fn require_scope(request: &HttpRequest, scope: &str) -> Result<(), Error> {
+    match bearer_claims(request) {
+        Some(claims) if !claims.allows(scope) => Err(Error::PermissionDenied(scope.to_string())),
+        _ => Ok(()),
+    }
+}
+
+/// The user from the request's bearer token, if tokens are accepted and
+/// it has a valid one.
+pub fn bearer_user(request: &HttpRequest) -> Option<User> {
+    bearer_claims(request).map(User::from)
+}
+
+/// Extracts the user from a bearer token, or fails with a 401. It's
+/// `Authentication` too, though there's no session to set anything in.
+#[derive(Debug)]
+pub struct Bearer(pub User);
+
+impl FromRequest for Bearer {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(request: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(
+            bearer_user(request)
+                .map(Bearer)
+                .ok_or_else(|| actix_web::error::ErrorUnauthorized("Missing or invalid bearer token")),
+        )
+    }
+}
+
+impl Authentication for Bearer {
+    fn is_authenticated(&self) -> Result<bool, Error> {
+        Ok(!self.0.is_anonymous)
+    }
+
+    fn set_user(&self, _account: User) -> Result<(), Error> {
+        Err(Error::Generic("Bearer tokens are stateless; issue a new one instead.".to_string()))
+    }
+
+    fn user(&self) -> Result<User, Error> {
+        Ok(self.0.clone())
+    }
+
+    fn remember(&self) -> Result<(), Error> {
+        Err(Error::Generic("Bearer tokens are stateless; there's nothing to remember.".to_string()))
+    }
+
+    fn set_password_expired(&self, _expired: bool) -> Result<(), Error> {
+        Err(Error::Generic("Bearer tokens are stateless; issue a new one instead.".to_string()))
+    }
+
+    fn password_expired(&self) -> Result<bool, Error> {
+        Ok(false)
+    }
+}
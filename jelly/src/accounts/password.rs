@@ -1,12 +1,49 @@
-use djangohashers::make_password;
+use std::env;
+
+use chrono::{DateTime, Duration, Utc};
+#[cfg(feature = "fast-hash")]
+use djangohashers::{Algorithm, Django, DjangoVersion};
 use rand::{thread_rng, Rng};
 
+use crate::error::Error;
+
+/// Where signed in accounts with an expired password are sent (and kept)
+/// until they change it.
+pub const CHANGE_PATH: &str = "/accounts/password";
+
 const PASSWORD_LEN: usize = 30;
 
 const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ\
     abcdefghijklmnopqrstuvwxyz\
     0123456789)(*&^%$#@!~";
 
+/// Hashes `password` for storing, Django style.
+#[cfg(not(feature = "fast-hash"))]
+pub fn hash(password: &str) -> String {
+    djangohashers::make_password(password)
+}
+
+/// Hashes `password` with Django 1.4's iteration count, which is dozens of
+/// times quicker to make and check. For test builds only (the `fast-hash`
+/// feature); `check_password` still accepts full strength hashes.
+#[cfg(feature = "fast-hash")]
+pub fn hash(password: &str) -> String {
+    Django {
+        version: DjangoVersion::V1_4,
+    }
+    .make_password_with_algorithm(password, Algorithm::PBKDF2)
+}
+
+/// Checks `password` against the `encoded` hash an account has, if it
+/// has one; a wrong password is an `Error::InvalidPassword`, not `Ok`.
+pub fn verify(password: &str, encoded: Option<&str>) -> Result<(), Error> {
+    let encoded = encoded.ok_or(Error::NoPasswordForAccount)?;
+    match djangohashers::check_password(password, encoded)? {
+        true => Ok(()),
+        false => Err(Error::InvalidPassword),
+    }
+}
+
 /// Generates a random password and returns it hashed.
 pub fn make_random_password() -> String {
     let mut rng = thread_rng();
@@ -19,5 +56,23 @@ pub fn make_random_password() -> String {
         })
         .collect();
 
-    make_password(&password)
+    hash(&password)
+}
+
+/// How long a password lasts before it has to be changed:
+/// `PASSWORD_MAX_AGE_DAYS`, or forever if that's unset or zero.
+pub fn max_age() -> Option<Duration> {
+    env::var("PASSWORD_MAX_AGE_DAYS")
+        .ok()
+        .and_then(|days| days.parse::<i64>().ok())
+        .filter(|days| *days > 0)
+        .map(Duration::days)
+}
+
+/// Whether a password last changed at `changed_at` has expired.
+pub fn is_expired(changed_at: DateTime<Utc>) -> bool {
+    match max_age() {
+        Some(max_age) => changed_at + max_age <= Utc::now(),
+        None => false,
+    }
 }
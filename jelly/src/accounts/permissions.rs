@@ -0,0 +1,157 @@
+//! Fine-grained permissions, named like `accounts.edit`. Accounts get them
+//! from their roles (`account_roles` and `role_permissions`), and per-account
+//! overrides (`account_permissions`) grant extra ones or take away ones a
+//! role gave. Admins have every permission.
+//!
+//! Granted names can end in `*` to cover a whole group: `accounts.*` grants
+//! `accounts.edit` and `accounts.delete`, and `*` grants everything.
+//!
+//! Views check them with `request::Permissions`:
+//!
+//! ```ignore
+//! request.require_permission("accounts.edit").await?;
+//! ```
+//!
+//! Whole sections (like `/admin/accounts`) can be kept to people with
+//! anything in their group by the `guards::Permission` guard.
+
+use sqlx::postgres::PgPool;
+
+use super::User;
+use crate::error::Error;
+
+/// The permissions an account has.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Granted {
+    /// Everything, as for admins.
+    pub all: bool,
+    pub allowed: Vec<String>,
+    /// Taken away by overrides; these win over `allowed`.
+    pub denied: Vec<String>,
+}
+
+/// Whether the granted name `pattern` covers `name`.
+pub fn matches(pattern: &str, name: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => pattern == name,
+    }
+}
+
+impl Granted {
+    /// Whether `name` is granted.
+    pub fn allows(&self, name: &str) -> bool {
+        if self.all {
+            return true;
+        }
+        if self.denied.iter().any(|pattern| matches(pattern, name)) {
+            return false;
+        }
+        self.allowed.iter().any(|pattern| matches(pattern, name))
+    }
+
+    /// Whether anything in `group` (e.g `accounts.`) could be granted: some
+    /// allowed name falls in it, or covers it, and the whole group hasn't
+    /// been taken away.
+    pub fn allows_any(&self, group: &str) -> bool {
+        if self.all {
+            return true;
+        }
+        if self.denied.iter().any(|pattern| covers(pattern, group)) {
+            return false;
+        }
+        self.allowed
+            .iter()
+            .any(|pattern| covers(pattern, group) || pattern.starts_with(group))
+    }
+
+    /// Whether this grants everything `other` does, so whoever has it can be
+    /// trusted to act on an account with `other`. What `other` has taken
+    /// away is ignored, so this errs towards saying no.
+    pub fn includes(&self, other: &Granted) -> bool {
+        if self.all {
+            return true;
+        }
+        if other.all {
+            return false;
+        }
+        other.allowed.iter().all(|pattern| match pattern.strip_suffix('*') {
+            Some(group) => self.allows_all(group),
+            None => self.allows(pattern),
+        })
+    }
+
+    /// Whether everything in `group` is granted, with nothing in it taken away.
+    fn allows_all(&self, group: &str) -> bool {
+        self.allowed.iter().any(|pattern| covers(pattern, group))
+            && !self
+                .denied
+                .iter()
+                .any(|pattern| covers(pattern, group) || pattern.starts_with(group))
+    }
+}
+
+/// Whether the granted name `pattern` covers the whole of `group`.
+fn covers(pattern: &str, group: &str) -> bool {
+    pattern.strip_suffix('*').map_or(false, |prefix| group.starts_with(prefix))
+}
+
+/// Loads `user`'s permissions. Anonymous users have none.
+pub async fn load(pool: &PgPool, user: &User) -> Result<Granted, Error> {
+    if user.is_anonymous {
+        return Ok(Granted::default());
+    }
+    if user.is_admin {
+        return Ok(Granted {
+            all: true,
+            ..Granted::default()
+        });
+    }
+    load_account(pool, user.id).await
+}
+
+/// Whether `actor` can act on account `target` (deactivate it, make it
+/// reset its password...). Admins can't be acted on from the app at all,
+/// and nobody can act on someone with permissions they don't have.
+pub async fn can_manage(pool: &PgPool, actor: &User, target: i32) -> Result<bool, Error> {
+    let target_is_admin: Option<(bool,)> = sqlx::query_as("SELECT is_admin FROM accounts WHERE id = $1")
+        .bind(target)
+        .fetch_optional(pool)
+        .await?;
+
+    match target_is_admin {
+        Some((true,)) => Ok(false),
+        Some((false,)) => {
+            let (actor, target) = (load(pool, actor).await?, load_account(pool, target).await?);
+            Ok(actor.includes(&target))
+        }
+        None => Ok(true),
+    }
+}
+
+/// The permissions account `id` has from its roles and overrides.
+async fn load_account(pool: &PgPool, id: i32) -> Result<Granted, Error> {
+    let rows: Vec<(String, bool)> = sqlx::query_as(
+        "
+        SELECT rp.permission, true
+        FROM account_roles ar
+        JOIN role_permissions rp ON rp.role_id = ar.role_id
+        WHERE ar.account_id = $1
+        UNION ALL
+        SELECT permission, granted FROM account_permissions WHERE account_id = $1
+    ",
+    )
+    .bind(id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut granted = Granted::default();
+    for (permission, allowed) in rows {
+        if allowed {
+            granted.allowed.push(permission);
+        } else {
+            granted.denied.push(permission);
+        }
+    }
+    Ok(granted)
+}
@@ -0,0 +1,205 @@
+//! "Remember me" logins. The session cookie only lasts as long as the
+//! browser; a remembered login also gets a long-lived cookie, tied to the
+//! session's server-side record (see `sessions`), that signs the user back
+//! in once the session cookie is gone. It's reissued with a new id about
+//! once a day, and stops working as soon as the session is revoked.
+//!
+//! Views opt in with `request.remember()` after `set_user`; `RememberMe`
+//! (wrapped inside the session middleware by `Server`) does the rest.
+
+use std::env;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+use actix_service::{Service, Transform};
+use actix_session::SessionExt;
+use actix_web::cookie::{time::Duration, Cookie, SameSite};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::HttpRequest;
+use chrono::{TimeZone, Utc};
+use futures::future::{ok, LocalBoxFuture, Ready};
+
+use super::{password, sessions, User};
+use crate::crypto;
+use crate::error::Error;
+use crate::request::{Authentication, DatabasePool, Timezone};
+use crate::{SESSION_ID, SESSION_REMEMBER};
+
+pub const COOKIE_NAME: &str = "remember";
+
+/// Keeps remember me signatures from being accepted anywhere else.
+const KEY_SALT: &str = "com.jelly.remember";
+
+/// How often the cookie is reissued with a new id, in seconds.
+const ROTATE_AFTER: i64 = 24 * 60 * 60;
+
+/// How long a remembered login lasts without being used:
+/// `REMEMBER_ME_DAYS`, 30 by default.
+fn max_age() -> Duration {
+    let days = env::var("REMEMBER_ME_DAYS")
+        .ok()
+        .and_then(|days| days.parse::<i64>().ok())
+        .unwrap_or(30);
+    Duration::days(days)
+}
+
+fn cookie(value: String) -> Cookie<'static> {
+    Cookie::build(COOKIE_NAME, value)
+        .path("/")
+        .http_only(true)
+        .secure(cfg!(feature = "production"))
+        .same_site(SameSite::Lax)
+        .max_age(max_age())
+        .finish()
+}
+
+/// The session id a cookie value carries, if it's genuine.
+fn verify(value: &str) -> Option<&str> {
+    let (id, signature) = value.split_once('.')?;
+    crypto::verify(KEY_SALT, id, signature).then(|| id)
+}
+
+/// Signs a signed out request back in from its remember me cookie.
+async fn restore(request: &HttpRequest) -> Result<(), Error> {
+    if !request.user()?.is_anonymous {
+        return Ok(());
+    }
+    let cookie = match request.cookie(COOKIE_NAME) {
+        Some(cookie) => cookie,
+        None => return Ok(()),
+    };
+    let id = match verify(cookie.value()) {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+
+    // When the password was last changed, as a timestamp; NULL if there's
+    // no password to expire.
+    let account: Option<(i32, Option<i64>, Option<String>)> = sqlx::query_as(
+        "
+        SELECT a.id,
+            CASE WHEN a.password IS NULL THEN NULL
+            ELSE EXTRACT(EPOCH FROM a.password_changed_at)::bigint END,
+            a.profile->>'timezone'
+        FROM user_sessions s
+        JOIN accounts a ON a.id = s.account_id
+        WHERE s.id = $1 AND s.revoked_at IS NULL AND a.is_active AND a.deleted_at IS NULL
+    ",
+    )
+    .bind(id)
+    .fetch_optional(request.db_pool()?)
+    .await?;
+
+    if let Some((account_id, password_changed_at, timezone)) = account {
+        request.set_user(User::load(request.db_pool()?, account_id).await?)?;
+        request.set_password_expired(
+            password_changed_at.map_or(false, |changed_at| password::is_expired(Utc.timestamp(changed_at, 0))),
+        )?;
+        request.set_timezone(timezone.as_deref().unwrap_or_default())?;
+        request.get_session().insert(SESSION_ID, id)?;
+        // The id has been used now, so reissue under a new one.
+        request.remember()?;
+    }
+
+    Ok(())
+}
+
+/// The remember me cookie to set on the way out, if any: a new one when
+/// it's been asked for or is due to rotate, or a removal once signed out.
+async fn issue(request: &HttpRequest) -> Result<Option<Cookie<'static>>, Error> {
+    let user = request.user()?;
+    if user.is_anonymous {
+        return Ok(request.cookie(COOKIE_NAME).map(|mut cookie| {
+            cookie.set_path("/");
+            cookie.make_removal();
+            cookie
+        }));
+    }
+
+    let session = request.get_session();
+    match session.get::<i64>(SESSION_REMEMBER)? {
+        Some(issued) if Utc::now().timestamp() - issued >= ROTATE_AFTER => {}
+        _ => return Ok(None),
+    }
+
+    let id = match session.get::<String>(SESSION_ID)? {
+        Some(id) => sessions::rotate(request, &id, user.id).await?,
+        // Signed in without a record; `Auth` signs it out.
+        None => None,
+    };
+    let id = match id {
+        Some(id) => id,
+        None => {
+            // Revoked in the meantime; `Auth` signs it out.
+            session.remove(SESSION_REMEMBER);
+            return Ok(None);
+        }
+    };
+
+    session.insert(SESSION_ID, &id)?;
+    session.insert(SESSION_REMEMBER, Utc::now().timestamp())?;
+    let signature = crypto::sign(KEY_SALT, &id);
+    Ok(Some(cookie(format!("{}.{}", id, signature))))
+}
+
+/// Middleware for remember me logins; see the module docs.
+#[derive(Debug, Default)]
+pub struct RememberMe;
+
+impl<S, B> Transform<S, ServiceRequest> for RememberMe
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type InitError = ();
+    type Transform = RememberMeMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RememberMeMiddleware {
+            service: Rc::new(service),
+        })
+    }
+}
+
+/// The middleware for `RememberMe`. You generally don't need this type, but
+/// it needs to be exported for compiler reasons.
+pub struct RememberMeMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RememberMeMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            if let Err(e) = restore(req.request()).await {
+                error!("Error restoring remembered login: {:?}", e);
+            }
+
+            let mut res = service.call(req).await?;
+            match issue(res.request()).await {
+                Ok(Some(cookie)) => res.response_mut().add_cookie(&cookie)?,
+                Ok(None) => {}
+                Err(e) => error!("Error issuing remember me cookie: {:?}", e),
+            }
+            Ok(res)
+        })
+    }
+}
@@ -0,0 +1,229 @@
+//! Service accounts, for integrations that shouldn't have to sign in as a
+//! person. Each belongs to an account, whose data it works with, and swaps
+//! its client id and secret for a short-lived access token, much like an
+//! OAuth2 client credentials grant: there's no password, email or refresh
+//! token, it just asks again. Its tokens are limited to the scopes it was
+//! given (see `jwt::require_scope`).
+//!
+//! Secrets are only shown when they're made, and stored hashed. Rotating
+//! one keeps the old secret working for `SERVICE_SECRET_OVERLAP_MINUTES`
+//! (60 by default), so integrations can be updated without downtime.
+
+use std::env;
+
+use chrono::{DateTime, Duration, Utc};
+use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::postgres::PgPool;
+
+use super::jwt;
+use crate::error::Error;
+
+/// Client ids start with this, so they're easy to tell from other tokens.
+const CLIENT_ID_PREFIX: &str = "svc_";
+
+/// A service account, without its secret.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ServiceAccount {
+    pub id: i32,
+    pub account_id: i32,
+    pub name: String,
+    pub client_id: String,
+    pub scopes: Vec<String>,
+    pub last_used: Option<DateTime<Utc>>,
+    pub created: DateTime<Utc>,
+    pub rotated_at: Option<DateTime<Utc>>,
+}
+
+/// A service account's credentials, as shown once when they're made.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Credentials {
+    pub client_id: String,
+    pub client_secret: String,
+}
+
+/// What a service account gets for its credentials. Times are timestamps.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ServiceGrant {
+    pub access_token: String,
+    pub token_type: String,
+    pub expires: i64,
+    /// The scopes granted, space separated.
+    pub scope: String,
+}
+
+/// How long a rotated secret keeps working.
+pub fn overlap() -> Duration {
+    let minutes = env::var("SERVICE_SECRET_OVERLAP_MINUTES")
+        .ok()
+        .and_then(|minutes| minutes.parse().ok())
+        .filter(|minutes| *minutes >= 0)
+        .unwrap_or(60);
+    Duration::minutes(minutes)
+}
+
+fn random_token() -> String {
+    let bytes: [u8; 32] = thread_rng().gen();
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+/// Secrets are stored hashed, so a leaked table can't be used.
+fn secret_hash(secret: &str) -> String {
+    format!("{:x}", Sha256::digest(secret.as_bytes()))
+}
+
+const COLUMNS: &str = "id, account_id, name, client_id, scopes, last_used, created, rotated_at";
+
+type Row = (
+    i32,
+    i32,
+    String,
+    String,
+    Vec<String>,
+    Option<DateTime<Utc>>,
+    DateTime<Utc>,
+    Option<DateTime<Utc>>,
+);
+
+impl From<Row> for ServiceAccount {
+    fn from(row: Row) -> Self {
+        let (id, account_id, name, client_id, scopes, last_used, created, rotated_at) = row;
+        ServiceAccount {
+            id,
+            account_id,
+            name,
+            client_id,
+            scopes,
+            last_used,
+            created,
+            rotated_at,
+        }
+    }
+}
+
+/// Adds a service account for `account_id`, returning its id and
+/// credentials.
+pub async fn create(pool: &PgPool, account_id: i32, name: &str, scopes: &[String]) -> Result<(i32, Credentials), Error> {
+    let credentials = Credentials {
+        client_id: format!("{}{}", CLIENT_ID_PREFIX, &random_token()[..24]),
+        client_secret: random_token(),
+    };
+    let id: i32 = sqlx::query_scalar(
+        "
+        INSERT INTO service_accounts (account_id, name, client_id, secret_hash, scopes)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id
+    ",
+    )
+    .bind(account_id)
+    .bind(name)
+    .bind(&credentials.client_id)
+    .bind(secret_hash(&credentials.client_secret))
+    .bind(scopes)
+    .fetch_one(pool)
+    .await?;
+
+    Ok((id, credentials))
+}
+
+/// `account_id`'s service accounts that haven't been revoked, newest first.
+pub async fn list(pool: &PgPool, account_id: i32) -> Result<Vec<ServiceAccount>, Error> {
+    let rows: Vec<Row> = sqlx::query_as(&format!(
+        "SELECT {} FROM service_accounts WHERE account_id = $1 AND revoked_at IS NULL ORDER BY created DESC",
+        COLUMNS
+    ))
+    .bind(account_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(ServiceAccount::from).collect())
+}
+
+/// Gives one of `account_id`'s service accounts a new secret, returning
+/// the new credentials, or `None` if there's no such service account. The
+/// old secret works for another `overlap()`.
+pub async fn rotate(pool: &PgPool, account_id: i32, id: i32) -> Result<Option<Credentials>, Error> {
+    let secret = random_token();
+    let client_id: Option<String> = sqlx::query_scalar(
+        "
+        UPDATE service_accounts
+        SET previous_secret_hash = secret_hash, previous_secret_expires = $3,
+            secret_hash = $4, rotated_at = now()
+        WHERE account_id = $1 AND id = $2 AND revoked_at IS NULL
+        RETURNING client_id
+    ",
+    )
+    .bind(account_id)
+    .bind(id)
+    .bind(Utc::now() + overlap())
+    .bind(secret_hash(&secret))
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(client_id.map(|client_id| Credentials {
+        client_id,
+        client_secret: secret,
+    }))
+}
+
+/// Revokes one of `account_id`'s service accounts. Access tokens already
+/// out still work until they expire.
+pub async fn revoke(pool: &PgPool, account_id: i32, id: i32) -> Result<(), Error> {
+    sqlx::query("UPDATE service_accounts SET revoked_at = now() WHERE account_id = $1 AND id = $2 AND revoked_at IS NULL")
+        .bind(account_id)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// The service account with these credentials, if they're right (with its
+/// current secret, or the last one while it overlaps), it hasn't been
+/// revoked, and its account's still active.
+pub async fn authenticate(pool: &PgPool, client_id: &str, client_secret: &str) -> Result<Option<ServiceAccount>, Error> {
+    let hash = secret_hash(client_secret);
+    let row: Option<Row> = sqlx::query_as(
+        "
+        UPDATE service_accounts s
+        SET last_used = now()
+        FROM accounts a
+        WHERE a.id = s.account_id AND s.client_id = $1 AND s.revoked_at IS NULL
+            AND a.is_active AND a.deleted_at IS NULL
+            AND (s.secret_hash = $2 OR (s.previous_secret_hash = $2 AND s.previous_secret_expires > now()))
+        RETURNING s.id, s.account_id, s.name, s.client_id, s.scopes, s.last_used, s.created, s.rotated_at
+    ",
+    )
+    .bind(client_id)
+    .bind(hash)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(ServiceAccount::from))
+}
+
+impl ServiceAccount {
+    /// The scopes a token asks for (space separated, as in OAuth2) that
+    /// this service account has, or all of them if it doesn't ask.
+    pub fn scopes_for(&self, requested: Option<&str>) -> Vec<String> {
+        match requested.map(str::trim).filter(|requested| !requested.is_empty()) {
+            Some(requested) => requested
+                .split_whitespace()
+                .filter(|scope| self.scopes.iter().any(|granted| granted == scope))
+                .map(String::from)
+                .collect(),
+            None => self.scopes.clone(),
+        }
+    }
+
+    /// Issues an access token limited to `scopes`.
+    pub fn grant(&self, scopes: Vec<String>) -> Result<ServiceGrant, Error> {
+        let (access_token, expires) = jwt::issue_service(self.account_id, self.id, &self.name, &scopes)?;
+        Ok(ServiceGrant {
+            access_token,
+            token_type: "Bearer".to_string(),
+            expires,
+            scope: scopes.join(" "),
+        })
+    }
+}
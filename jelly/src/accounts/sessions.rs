@@ -0,0 +1,150 @@
+//! Server-side records of signed in sessions, so they can be listed and
+//! revoked. The cookie only carries the record's id (`SESSION_ID`), set
+//! along with the user by `sign_in`; `Auth` calls `check` on every request
+//! it guards, which signs the session out once it's been revoked (or if it
+//! has no record at all, e.g from before they were kept). It also reloads the session's `User` once the account's `session_version`
+//! has moved on (see `refresh`).
+
+use actix_session::SessionExt;
+use actix_web::http::header::USER_AGENT;
+use actix_web::HttpRequest;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+
+use sqlx::postgres::PgPool;
+
+use super::User;
+use crate::error::Error;
+use crate::request::{Authentication, DatabasePool};
+use crate::SESSION_ID;
+
+/// Length of generated session ids.
+const ID_LEN: usize = 32;
+
+/// Whether the session signed in as `user` is still good: the account
+/// exists, is active and isn't deleted, and the session hasn't been
+/// revoked. Notes the IP and time while it's at it, reloads the user if
+/// it's out of date, and flags the password as expired if the account has
+/// to reset it (`must_reset_password`). Accounts without a password are
+/// never flagged, as they'd have nothing to reset.
+pub async fn check(request: &HttpRequest, user: &User) -> Result<bool, Error> {
+    let session = request.get_session();
+    let account_id = user.id;
+
+    if let Some(id) = session.get::<String>(SESSION_ID)? {
+        let ip = request.connection_info().realip_remote_addr().map(String::from);
+
+        // last_seen only needs to be roughly right, so spare the write
+        // on most requests.
+        let live: Option<(i32, bool)> = sqlx::query_as(
+            "
+            WITH live AS (
+                SELECT
+                    s.id, a.session_version,
+                    a.must_reset_password AND a.password IS NOT NULL AS must_reset_password
+                FROM user_sessions s
+                JOIN accounts a ON a.id = s.account_id
+                WHERE s.id = $1 AND s.account_id = $2
+                    AND s.revoked_at IS NULL AND a.is_active AND a.deleted_at IS NULL
+            ), touched AS (
+                UPDATE user_sessions SET last_seen = now(), ip = $3
+                WHERE id IN (SELECT id FROM live)
+                    AND last_seen < now() - interval '1 minute'
+            )
+            SELECT session_version, must_reset_password FROM live
+        ",
+        )
+        .bind(&id)
+        .bind(account_id)
+        .bind(&ip)
+        .fetch_optional(request.db_pool()?)
+        .await?;
+
+        let (version, must_reset_password) = match live {
+            Some(live) => live,
+            None => return Ok(false),
+        };
+        if version != user.session_version {
+            request.set_user(User::load(request.db_pool()?, account_id).await?)?;
+        }
+        if must_reset_password {
+            request.set_password_expired(true)?;
+        }
+        return Ok(true);
+    }
+
+    // Sessions get their record at sign in, so one without is as good as
+    // revoked; making it one now would bring back a signed out cookie.
+    Ok(false)
+}
+
+/// Signs `user` in: records a new session for them, and keeps its id and
+/// the user in the session. Fails with `AccountInactive` if the account's
+/// gone or deactivated.
+pub async fn sign_in(request: &HttpRequest, user: User) -> Result<(), Error> {
+    let id = start(request, user.id).await?.ok_or(Error::AccountInactive)?;
+    request.get_session().insert(SESSION_ID, id)?;
+    request.set_user(user)
+}
+
+/// Records a new session for `account_id`, returning its id - or `None`
+/// if the account is gone or deactivated. Callers store the id in
+/// `SESSION_ID`.
+pub async fn start(request: &HttpRequest, account_id: i32) -> Result<Option<String>, Error> {
+    let ip = request.connection_info().realip_remote_addr().map(String::from);
+    let user_agent = request
+        .headers()
+        .get(USER_AGENT)
+        .and_then(|ua| ua.to_str().ok())
+        .map(String::from);
+
+    Ok(sqlx::query_scalar(
+        "
+        INSERT INTO user_sessions (id, account_id, user_agent, ip)
+        SELECT $1, id, $3, $4 FROM accounts
+        WHERE id = $2 AND is_active AND deleted_at IS NULL
+        RETURNING id
+    ",
+    )
+    .bind(new_id())
+    .bind(account_id)
+    .bind(&user_agent)
+    .bind(&ip)
+    .fetch_optional(request.db_pool()?)
+    .await?)
+}
+
+/// Gives a live session a new id, returning it, so an id that's leaked
+/// (e.g in an old remember me cookie) stops working.
+pub async fn rotate(request: &HttpRequest, id: &str, account_id: i32) -> Result<Option<String>, Error> {
+    Ok(sqlx::query_scalar(
+        "
+        UPDATE user_sessions SET id = $3, last_seen = now()
+        WHERE id = $1 AND account_id = $2 AND revoked_at IS NULL
+        RETURNING id
+    ",
+    )
+    .bind(id)
+    .bind(account_id)
+    .bind(new_id())
+    .fetch_optional(request.db_pool()?)
+    .await?)
+}
+
+/// Has every session signed in as `account_id` reload its `User` on its
+/// next request; for when something it keeps (roles, say) has changed.
+pub async fn refresh(pool: &PgPool, account_id: i32) -> Result<(), Error> {
+    sqlx::query("UPDATE accounts SET session_version = session_version + 1 WHERE id = $1")
+        .bind(account_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+fn new_id() -> String {
+    thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(ID_LEN)
+        .map(char::from)
+        .collect()
+}
@@ -0,0 +1,135 @@
+//! Helpers for referencing static assets from templates.
+//!
+//! At startup every file under `STATIC_ROOT` is hashed, so templates can emit
+//! Subresource Integrity attributes alongside the URL:
+//!
+//! ```html
+//! <link rel="stylesheet" href="{{ static_url(path="app.css") }}"
+//!       integrity="{{ integrity(path="app.css") }}" crossorigin="anonymous">
+//! {{ script_tag(path="app.js", nonce=csp_nonce) | safe }}
+//! ```
+//!
+//! With the `template_watcher` feature enabled, hashes are recomputed on
+//! every lookup, so rebuilt assets don't need a restart.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::{env, fs, io};
+
+use sha2::{Digest, Sha384};
+use tera::{Tera, Value};
+
+#[cfg(feature = "asset_watcher")]
+pub mod supervisor;
+
+/// Maps asset paths (relative to `STATIC_ROOT`) to their SRI hashes.
+#[derive(Debug, Default)]
+pub struct AssetManifest {
+    root: Option<PathBuf>,
+    url_prefix: String,
+    integrity: HashMap<String, String>,
+}
+
+impl AssetManifest {
+    /// Hashes everything under `STATIC_ROOT`, if it's set. URLs are
+    /// built under `STATIC_URL`, which defaults to `/static`.
+    pub fn load() -> Self {
+        let url_prefix = env::var("STATIC_URL").unwrap_or_else(|_| "/static".to_string());
+        let root = env::var("STATIC_ROOT").ok().map(PathBuf::from);
+
+        let mut integrity = HashMap::new();
+        if let Some(root) = &root {
+            if let Err(e) = hash_dir(root, root, &mut integrity) {
+                warn!("Unable to hash static assets in {}: {:?}", root.display(), e);
+            }
+        }
+
+        AssetManifest {
+            root,
+            url_prefix: url_prefix.trim_end_matches('/').to_string(),
+            integrity,
+        }
+    }
+
+    /// The public URL for an asset.
+    pub fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.url_prefix, path.trim_start_matches('/'))
+    }
+
+    /// The `integrity` attribute value for an asset, e.g `sha384-...`.
+    pub fn integrity(&self, path: &str) -> Option<String> {
+        let path = path.trim_start_matches('/');
+
+        if cfg!(feature = "template_watcher") {
+            let root = self.root.as_ref()?;
+            return fs::read(root.join(path)).ok().map(|bytes| sri_hash(&bytes));
+        }
+
+        self.integrity.get(path).cloned()
+    }
+
+    /// Renders a full `<script>` tag for an asset.
+    pub fn script_tag(&self, path: &str, nonce: Option<&str>) -> String {
+        let mut tag = format!(r#"<script src="{}""#, escape(&self.url(path)));
+        if let Some(integrity) = self.integrity(path) {
+            tag.push_str(&format!(r#" integrity="{}" crossorigin="anonymous""#, integrity));
+        }
+        if let Some(nonce) = nonce {
+            tag.push_str(&format!(r#" nonce="{}""#, escape(nonce)));
+        }
+        tag.push_str("></script>");
+        tag
+    }
+
+    /// Registers `static_url`, `integrity` and `script_tag` functions on
+    /// a Tera instance.
+    pub fn register(self: &Arc<Self>, tera: &mut Tera) {
+        let manifest = self.clone();
+        tera.register_function("static_url", move |args: &HashMap<String, Value>| {
+            Ok(Value::String(manifest.url(path_arg("static_url", args)?)))
+        });
+
+        let manifest = self.clone();
+        tera.register_function("integrity", move |args: &HashMap<String, Value>| {
+            let path = path_arg("integrity", args)?;
+            Ok(Value::String(manifest.integrity(path).unwrap_or_default()))
+        });
+
+        let manifest = self.clone();
+        tera.register_function("script_tag", move |args: &HashMap<String, Value>| {
+            let path = path_arg("script_tag", args)?;
+            let nonce = args.get("nonce").and_then(Value::as_str).filter(|n| !n.is_empty());
+            Ok(Value::String(manifest.script_tag(path, nonce)))
+        });
+    }
+}
+
+/// Computes an SRI hash (`sha384-<base64>`) for some bytes.
+pub fn sri_hash(bytes: &[u8]) -> String {
+    format!("sha384-{}", base64::encode(Sha384::digest(bytes)))
+}
+
+fn hash_dir(root: &Path, dir: &Path, out: &mut HashMap<String, String>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            hash_dir(root, &path, out)?;
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            let key = relative.to_string_lossy().replace('\\', "/");
+            out.insert(key, sri_hash(&fs::read(&path)?));
+        }
+    }
+
+    Ok(())
+}
+
+fn path_arg<'a>(name: &str, args: &'a HashMap<String, Value>) -> tera::Result<&'a str> {
+    args.get("path")
+        .and_then(Value::as_str)
+        .ok_or_else(|| tera::Error::msg(format!("{}: missing `path` argument", name)))
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('"', "&quot;").replace('<', "&lt;")
+}
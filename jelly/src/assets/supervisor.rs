@@ -0,0 +1,162 @@
+//! A small dev-mode supervisor for frontend build tools (esbuild, tailwind,
+//! etc). Commands come from the environment, separated by `;`:
+//!
+//! - `ASSET_BUILD_COMMANDS` are run once, in order, at startup. Requests are
+//!   held until they've all finished, so the first page load never sees
+//!   missing or stale assets.
+//! - `ASSET_WATCH_COMMANDS` are long-running (e.g `tailwindcss --watch`), and
+//!   are restarted if they exit.
+
+use std::env;
+use std::process::Command;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::thread;
+use std::time::Duration;
+
+use actix_service::{Service, Transform};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::Error;
+use futures::channel::oneshot;
+use futures::future::{ok, FutureExt, LocalBoxFuture, Ready, Shared};
+
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Handle to the supervisor; hand `guard()` to each App to hold requests
+/// until the initial build is done.
+#[derive(Clone)]
+pub struct AssetSupervisor {
+    ready: Arc<AtomicBool>,
+    built: Shared<oneshot::Receiver<()>>,
+}
+
+impl AssetSupervisor {
+    /// Spawns the build and watch commands in a background thread.
+    pub fn start() -> Self {
+        let build = commands("ASSET_BUILD_COMMANDS");
+        let watch = commands("ASSET_WATCH_COMMANDS");
+
+        let ready = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = oneshot::channel();
+
+        let flag = ready.clone();
+        thread::spawn(move || {
+            for cmd in build.iter() {
+                info!("Building assets: {}", cmd);
+                match Command::new("sh").arg("-c").arg(cmd).status() {
+                    Ok(status) if status.success() => {}
+                    Ok(status) => error!("Asset build `{}` failed: {}", cmd, status),
+                    Err(e) => error!("Unable to run asset build `{}`: {:?}", cmd, e),
+                }
+            }
+
+            flag.store(true, Ordering::SeqCst);
+            let _ = tx.send(());
+
+            for cmd in watch {
+                thread::spawn(move || supervise(&cmd));
+            }
+        });
+
+        AssetSupervisor {
+            ready,
+            built: rx.shared(),
+        }
+    }
+
+    /// Middleware that holds requests until the initial build has finished.
+    pub fn guard(&self) -> AssetsReady {
+        AssetsReady {
+            supervisor: self.clone(),
+        }
+    }
+}
+
+/// Runs a watch command forever, restarting it (with backoff) when it exits.
+fn supervise(cmd: &str) {
+    let mut backoff = Duration::from_secs(1);
+
+    loop {
+        info!("Starting asset watcher: {}", cmd);
+        match Command::new("sh").arg("-c").arg(cmd).status() {
+            Ok(status) => warn!("Asset watcher `{}` exited ({}), restarting", cmd, status),
+            Err(e) => error!("Unable to run asset watcher `{}`: {:?}", cmd, e),
+        }
+
+        thread::sleep(backoff);
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+fn commands(key: &str) -> Vec<String> {
+    env::var(key)
+        .unwrap_or_default()
+        .split(';')
+        .map(str::trim)
+        .filter(|c| !c.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// See `AssetSupervisor::guard`.
+pub struct AssetsReady {
+    supervisor: AssetSupervisor,
+}
+
+impl<S, B> Transform<S, ServiceRequest> for AssetsReady
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = AssetsReadyMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(AssetsReadyMiddleware {
+            service: Rc::new(service),
+            supervisor: self.supervisor.clone(),
+        })
+    }
+}
+
+/// The middleware for `AssetsReady`. You generally don't need this
+/// type, but it needs to be exported for compiler reasons.
+pub struct AssetsReadyMiddleware<S> {
+    service: Rc<S>,
+    supervisor: AssetSupervisor,
+}
+
+impl<S, B> Service<ServiceRequest> for AssetsReadyMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        if self.supervisor.ready.load(Ordering::SeqCst) {
+            return Box::pin(self.service.call(req));
+        }
+
+        let built = self.supervisor.built.clone();
+        let service = self.service.clone();
+        Box::pin(async move {
+            // A dropped sender just means the build thread died; serve anyway.
+            let _ = built.await;
+            service.call(req).await
+        })
+    }
+}
@@ -0,0 +1,130 @@
+//! A Tera function for user avatars:
+//!
+//! ```html
+//! <img src="{{ avatar_url(email=account.email, size=64) }}">
+//! ```
+//!
+//! An uploaded avatar can be passed as `avatar=...` and wins if it's set;
+//! otherwise this is a Gravatar URL, which falls back to a generated
+//! identicon for addresses without one. `GRAVATAR_DEFAULT` picks a different
+//! fallback style (`retro`, `robohash`, `mp`, etc).
+//!
+//! With `AVATARS="identicon"`, Gravatar isn't asked at all: the fallback
+//! is an identicon generated here, served at `/avatars/{hash}.svg` and
+//! cached under `.identicons` in the storage root (see `storage`), so each
+//! one is only drawn once. Serving them needs the `storage` feature.
+
+use std::collections::HashMap;
+use std::env;
+
+use actix_web::web::{self, ServiceConfig};
+use actix_web::{HttpRequest, HttpResponse};
+use sha2::{Digest, Sha256};
+use tera::{Tera, Value};
+
+use crate::error::Error;
+use crate::routes;
+use crate::storage;
+
+const DEFAULT_SIZE: u64 = 80;
+
+/// Where generated identicons are cached, in the storage root.
+const IDENTICONS_DIR: &str = ".identicons";
+
+/// The hash both Gravatar and identicons are keyed by.
+fn email_hash(email: &str) -> String {
+    let hash = Sha256::digest(email.trim().to_lowercase().as_bytes());
+    hash.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Returns the Gravatar URL for an email address.
+pub fn gravatar_url(email: &str, size: u64) -> String {
+    let fallback = env::var("GRAVATAR_DEFAULT").unwrap_or_else(|_| "identicon".to_string());
+
+    format!("https://www.gravatar.com/avatar/{}?s={}&d={}", email_hash(email), size, fallback)
+}
+
+/// Returns the URL of the generated identicon for an email address. It's
+/// an SVG, so there's no size to ask for.
+pub fn identicon_url(email: &str) -> String {
+    format!("/avatars/{}.svg", email_hash(email))
+}
+
+/// Draws the identicon for `hash` (a hex SHA-256, as from `email_hash`):
+/// a 5x5 grid, mirrored left to right, in a colour picked by the hash.
+pub fn identicon_svg(hash: &str) -> String {
+    let bytes: Vec<u8> = (0..hash.len() / 2)
+        .filter_map(|i| u8::from_str_radix(hash.get(i * 2..i * 2 + 2)?, 16).ok())
+        .collect();
+    let byte = |i: usize| bytes.get(i).copied().unwrap_or(0);
+    let hue = u32::from(byte(0)) * 360 / 256;
+
+    let mut cells = String::new();
+    for row in 0..5 {
+        for col in 0..3 {
+            if byte(1 + row * 3 + col) % 2 == 0 {
+                continue;
+            }
+
+            cells.push_str(&format!(r#"<rect x="{}" y="{}" width="1" height="1"/>"#, col, row));
+            if col != 2 {
+                cells.push_str(&format!(r#"<rect x="{}" y="{}" width="1" height="1"/>"#, 4 - col, row));
+            }
+        }
+    }
+
+    format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 5 5" shape-rendering="crispEdges"><rect width="5" height="5" fill="#f0f0f0"/><g fill="hsl({}, 55%, 50%)">{}</g></svg>"##,
+        hue, cells
+    )
+}
+
+/// Serves an identicon, drawing and storing it the first time it's asked for.
+pub async fn identicon(request: HttpRequest, hash: web::Path<String>) -> Result<HttpResponse, Error> {
+    let hash = hash.into_inner();
+    let is_hash = hash.len() == 64 && hash.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'));
+    if !is_hash {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+
+    let path = storage::root().join(IDENTICONS_DIR).join(format!("{}.svg", hash));
+    if !path.is_file() {
+        let io_error = |e: std::io::Error| Error::Generic(format!("Error storing identicon: {:?}", e));
+        std::fs::create_dir_all(storage::root().join(IDENTICONS_DIR)).map_err(io_error)?;
+
+        // Written aside and moved into place, so a concurrent request
+        // never serves half a file.
+        let partial = path.with_extension(format!("svg.{}", std::process::id()));
+        std::fs::write(&partial, identicon_svg(&hash)).map_err(io_error)?;
+        std::fs::rename(&partial, &path).map_err(io_error)?;
+    }
+
+    storage::serve(&request, Some(path)).await
+}
+
+pub fn configure(config: &mut ServiceConfig) {
+    routes::resource("/avatars/{hash}.svg").get(identicon).mount(config);
+}
+
+/// Registers the `avatar_url` function on a Tera instance.
+pub fn register(tera: &mut Tera) {
+    tera.register_function("avatar_url", |args: &HashMap<String, Value>| {
+        if let Some(avatar) = args.get("avatar").and_then(Value::as_str) {
+            if !avatar.is_empty() {
+                return Ok(Value::String(avatar.to_string()));
+            }
+        }
+
+        let email = args
+            .get("email")
+            .and_then(Value::as_str)
+            .ok_or_else(|| tera::Error::msg("avatar_url: missing `email` argument"))?;
+        let size = args.get("size").and_then(Value::as_u64).unwrap_or(DEFAULT_SIZE);
+
+        let url = match env::var("AVATARS").as_deref() {
+            Ok("identicon") => identicon_url(email),
+            _ => gravatar_url(email, size),
+        };
+        Ok(Value::String(url))
+    });
+}
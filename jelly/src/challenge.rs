@@ -0,0 +1,177 @@
+//! Challenges for forms scripts would otherwise post over and over, like
+//! signing up. Which one a form uses, if any, is a setting (e.g
+//! `OAUTH_SIGNUP_CHALLENGE`):
+//!
+//! - `pow`, a proof of work: the form carries a signed `challenge`, and
+//!   the page's script finds a `challenge_solution` that, hashed with it,
+//!   starts with `CHALLENGE_POW_BITS` (16 by default) zero bits. That's a
+//!   second or so for a person, but adds up for a script. Each challenge
+//!   is good for one post, within `MAX_AGE`.
+//! - `turnstile`, Cloudflare's CAPTCHA, with `TURNSTILE_SITE_KEY` and
+//!   `TURNSTILE_SECRET_KEY`. Its script loads from
+//!   `https://challenges.cloudflare.com`, so `CONTENT_SECURITY_POLICY`
+//!   has to allow that.
+//!
+//! ```ignore
+//! let challenge = Challenge::from_env("OAUTH_SIGNUP_CHALLENGE");
+//! ctx.insert("challenge", &challenge.issue("oauth-signup"));
+//! // ...and when the form comes back:
+//! if !challenge.verify("oauth-signup", &response, ip).await? { .. }
+//! ```
+//!
+//! Templates can `{% include "challenge.html" %}` inside the form.
+
+use std::env;
+use std::time::Duration;
+
+use chrono::Utc;
+use rand::{thread_rng, Rng};
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::crypto;
+use crate::error::Error;
+use crate::ratelimit::{self, Limit};
+
+/// How long a proof of work challenge can be answered for.
+pub const MAX_AGE: Duration = Duration::from_secs(15 * 60);
+
+const TURNSTILE_VERIFY_URL: &str = "https://challenges.cloudflare.com/turnstile/v0/siteverify";
+
+/// The challenge a form asks for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Challenge {
+    Off,
+    ProofOfWork { bits: u32 },
+    Turnstile { site_key: String, secret_key: String },
+}
+
+/// What a form needs to show its challenge.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Issued {
+    ProofOfWork { token: String, bits: u32 },
+    Turnstile { site_key: String },
+}
+
+/// What came back with the form.
+#[derive(Clone, Debug, Default)]
+pub struct Response {
+    pub token: String,
+    pub solution: String,
+    pub turnstile: String,
+}
+
+impl Challenge {
+    /// The challenge `var` names: `pow`, `turnstile`, or `Off` if it's unset
+    /// or anything else.
+    pub fn from_env(var: &str) -> Self {
+        match env::var(var).unwrap_or_default().as_str() {
+            "pow" => Challenge::ProofOfWork {
+                bits: env::var("CHALLENGE_POW_BITS")
+                    .ok()
+                    .and_then(|bits| bits.parse().ok())
+                    .unwrap_or(16)
+                    .min(32),
+            },
+            "turnstile" => Challenge::Turnstile {
+                site_key: env::var("TURNSTILE_SITE_KEY").expect("TURNSTILE_SITE_KEY not set!"),
+                secret_key: env::var("TURNSTILE_SECRET_KEY").expect("TURNSTILE_SECRET_KEY not set!"),
+            },
+            _ => Challenge::Off,
+        }
+    }
+
+    /// A fresh challenge for a form, or none if it doesn't ask for one.
+    /// `salt` keeps challenges issued for one form from passing another.
+    pub fn issue(&self, salt: &str) -> Option<Issued> {
+        match self {
+            Challenge::Off => None,
+            Challenge::ProofOfWork { bits } => {
+                let nonce: [u8; 16] = thread_rng().gen();
+                let data = format!(
+                    "{}.{}",
+                    Utc::now().timestamp(),
+                    nonce.iter().map(|b| format!("{:02x}", b)).collect::<String>()
+                );
+                Some(Issued::ProofOfWork {
+                    token: format!("{}.{}", data, crypto::sign(salt, &data)),
+                    bits: *bits,
+                })
+            }
+            Challenge::Turnstile { site_key, .. } => Some(Issued::Turnstile {
+                site_key: site_key.clone(),
+            }),
+        }
+    }
+
+    /// Whether `response` answers a challenge issued for `salt`. Proof of
+    /// work tokens are used up here, so each only passes once.
+    pub async fn verify(&self, salt: &str, response: &Response, remote_ip: Option<&str>) -> Result<bool, Error> {
+        match self {
+            Challenge::Off => Ok(true),
+            Challenge::ProofOfWork { bits } => {
+                let data = match verify_token(salt, &response.token, Utc::now().timestamp()) {
+                    Some(data) => data,
+                    None => return Ok(false),
+                };
+                if !solves(&response.token, &response.solution, *bits) {
+                    return Ok(false);
+                }
+                let once = Limit { max: 1, window: MAX_AGE };
+                let status = ratelimit::check(&format!("challenge:{}", data), &once).await?;
+                Ok(!status.map_or(false, |status| status.exceeded))
+            }
+            Challenge::Turnstile { secret_key, .. } => {
+                if response.turnstile.is_empty() {
+                    return Ok(false);
+                }
+                verify_turnstile(secret_key, &response.turnstile, remote_ip).await
+            }
+        }
+    }
+}
+
+/// The signed part of a proof of work `token`, if it was signed for `salt`
+/// and hasn't expired by `now`.
+pub fn verify_token<'a>(salt: &str, token: &'a str, now: i64) -> Option<&'a str> {
+    let (data, signature) = token.rsplit_once('.')?;
+    let issued: i64 = data.split('.').next()?.parse().ok()?;
+    let fresh = issued <= now && now - issued <= MAX_AGE.as_secs() as i64;
+    (fresh && crypto::verify(salt, data, signature)).then(|| data)
+}
+
+/// Whether SHA-256 of `token:solution` starts with `bits` zero bits.
+pub fn solves(token: &str, solution: &str, bits: u32) -> bool {
+    if solution.is_empty() || solution.len() > 20 {
+        return false;
+    }
+
+    let digest = Sha256::digest(format!("{}:{}", token, solution).as_bytes());
+    let mut zeros = 0;
+    for byte in digest.iter() {
+        zeros += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    zeros >= bits
+}
+
+async fn verify_turnstile(secret_key: &str, response: &str, remote_ip: Option<&str>) -> Result<bool, Error> {
+    let request = minreq::post(TURNSTILE_VERIFY_URL)
+        .with_json(&json!({ "secret": secret_key, "response": response, "remoteip": remote_ip }))
+        .map_err(|e| Error::Generic(format!("Error building Turnstile request: {:?}", e)))?
+        .with_timeout(10);
+
+    // minreq blocks, so it's run off the runtime.
+    let response = actix_rt::task::spawn_blocking(move || request.send())
+        .await
+        .map_err(|e| Error::Generic(format!("Error verifying Turnstile response: {:?}", e)))?
+        .map_err(|e| Error::Generic(format!("Error verifying Turnstile response: {:?}", e)))?;
+    let body: Value = response
+        .json()
+        .map_err(|e| Error::Generic(format!("Error reading Turnstile response: {:?}", e)))?;
+    Ok(body.get("success").and_then(Value::as_bool).unwrap_or(false))
+}
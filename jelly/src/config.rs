@@ -0,0 +1,207 @@
+//! Configuration profiles. Settings are environment variables, filled in
+//! from layers of files, each only setting what the ones before it
+//! haven't:
+//!
+//! 1. the process's own environment,
+//! 2. `.env`, for this machine,
+//! 3. `config/{APP_ENV}.env`, the profile (`development` unless `APP_ENV`
+//!    says otherwise, in the environment or `.env`),
+//! 4. `config/default.env`, for what every profile shares.
+//!
+//! The files are all in `.env`'s format, and any of them can be missing;
+//! `CONFIG_DIR` moves `config/`. `cargo run -- config check` prints what
+//! each setting comes to and where from, with secrets redacted.
+
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// The profile when `APP_ENV` isn't set.
+pub const DEFAULT_PROFILE: &str = "development";
+
+/// Settings the server won't start without.
+pub const REQUIRED: &[&str] = &["BIND_TO", "DATABASE_URL", "JELLY_DOMAIN", "SECRET_KEY", "TEMPLATES_GLOB"];
+
+const REDACTED: &str = "[redacted]";
+
+/// A file's settings, and where they came from.
+#[derive(Clone, Debug, Default)]
+pub struct Layer {
+    pub source: String,
+    pub vars: Vec<(String, String)>,
+}
+
+/// What a setting comes to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Setting {
+    pub key: String,
+    pub value: String,
+    /// `environment`, or the file it came from.
+    pub source: String,
+}
+
+fn dir() -> PathBuf {
+    PathBuf::from(env::var("CONFIG_DIR").unwrap_or_else(|_| "config".to_string()))
+}
+
+fn read(path: &Path) -> Option<Layer> {
+    let vars = dotenv::from_path_iter(path).ok()?;
+    Some(Layer {
+        source: path.display().to_string(),
+        vars: vars
+            .filter_map(|var| var.map_err(|e| warn!("Error reading {}: {:?}", path.display(), e)).ok())
+            .collect(),
+    })
+}
+
+/// The profile in use: `APP_ENV`, from the environment or `.env`.
+pub fn profile() -> String {
+    env::var("APP_ENV")
+        .ok()
+        .or_else(|| {
+            read(Path::new(".env"))?
+                .vars
+                .into_iter()
+                .find(|(key, _)| key == "APP_ENV")
+                .map(|(_, value)| value)
+        })
+        .filter(|profile| !profile.is_empty())
+        .unwrap_or_else(|| DEFAULT_PROFILE.to_string())
+}
+
+/// The files for `profile`, first to last.
+pub fn layers(profile: &str) -> Vec<Layer> {
+    let dir = dir();
+    [
+        PathBuf::from(".env"),
+        dir.join(format!("{}.env", profile)),
+        dir.join("default.env"),
+    ]
+    .iter()
+    .filter_map(|path| read(path))
+    .collect()
+}
+
+/// Sets the environment from the layers, leaving what's already set
+/// alone, and returns the profile used. Call once, before anything reads
+/// the environment.
+pub fn load() -> String {
+    let profile = profile();
+    for layer in layers(&profile) {
+        for (key, value) in layer.vars {
+            if env::var_os(&key).is_none() {
+                env::set_var(key, value);
+            }
+        }
+    }
+    profile
+}
+
+/// What each setting comes to, by key: `environment`'s value if it has
+/// one, or else the first layer's that does. Only keys in a layer or
+/// `known` are listed, so the rest of the environment (`PATH` and so on)
+/// isn't.
+pub fn resolve(environment: &HashMap<String, String>, layers: &[Layer], known: &[String]) -> Vec<Setting> {
+    let mut settings: BTreeMap<&str, Setting> = BTreeMap::new();
+
+    for key in known.iter().chain(layers.iter().flat_map(|layer| layer.vars.iter().map(|(key, _)| key))) {
+        if let Some(value) = environment.get(key) {
+            settings.entry(key.as_str()).or_insert_with(|| Setting {
+                key: key.clone(),
+                value: value.clone(),
+                source: "environment".to_string(),
+            });
+        }
+    }
+    for layer in layers {
+        for (key, value) in layer.vars.iter() {
+            settings.entry(key.as_str()).or_insert_with(|| Setting {
+                key: key.clone(),
+                value: value.clone(),
+                source: layer.source.clone(),
+            });
+        }
+    }
+
+    settings.into_values().collect()
+}
+
+/// The keys an env file mentions, including commented out examples like
+/// `# JWT_TTL_MINUTES="15"`: the settings there are, for `check`.
+pub fn documented_keys(contents: &str) -> Vec<String> {
+    let mut keys: Vec<String> = contents
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim_start_matches(|c: char| c == '#' || c.is_whitespace());
+            let (key, _) = line.split_once('=')?;
+            let is_key = key.starts_with(|c: char| c.is_ascii_uppercase())
+                && key.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_');
+            is_key.then(|| key.to_string())
+        })
+        .collect();
+    keys.sort();
+    keys.dedup();
+    keys
+}
+
+/// Whether a setting holds a secret, by its name.
+pub fn is_secret(key: &str) -> bool {
+    let key = key.to_uppercase();
+    ["SECRET", "PASSWORD", "TOKEN", "PRIVATE"].iter().any(|word| key.contains(word))
+        || key.ends_with("_KEY")
+        || key.ends_with("_KEYS")
+}
+
+/// `value` as `check` shows it: secrets redacted, and so are passwords in
+/// URLs (e.g `DATABASE_URL`).
+pub fn redacted(key: &str, value: &str) -> String {
+    if value.is_empty() {
+        return String::new();
+    }
+    if is_secret(key) {
+        return REDACTED.to_string();
+    }
+
+    let password = value.find("://").and_then(|scheme_end| {
+        let authority_start = scheme_end + 3;
+        let at = authority_start + value[authority_start..].find('@')?;
+        let colon = authority_start + value[authority_start..at].find(':')?;
+        Some((colon + 1, at))
+    });
+    match password {
+        Some((start, end)) => format!("{}{}{}", &value[..start], REDACTED, &value[end..]),
+        None => value.to_string(),
+    }
+}
+
+/// Prints the profile and every setting it comes to, redacted, then any
+/// required settings that are missing. Returns whether none are.
+pub fn check() -> bool {
+    let profile = profile();
+    let layers = layers(&profile);
+    let environment: HashMap<String, String> = env::vars().collect();
+    let known = std::fs::read_to_string(".env.example")
+        .map(|contents| documented_keys(&contents))
+        .unwrap_or_default();
+    let settings = resolve(&environment, &layers, &known);
+
+    println!("Profile: {}", profile);
+    for layer in layers.iter() {
+        println!("Read: {}", layer.source);
+    }
+    println!();
+    for setting in settings.iter() {
+        println!("{}={} ({})", setting.key, redacted(&setting.key, &setting.value), setting.source);
+    }
+
+    let missing: Vec<&str> = REQUIRED
+        .iter()
+        .copied()
+        .filter(|key| !settings.iter().any(|setting| setting.key == *key && !setting.value.is_empty()))
+        .collect();
+    if !missing.is_empty() {
+        println!();
+        println!("Missing: {}", missing.join(", "));
+    }
+    missing.is_empty()
+}
@@ -0,0 +1,247 @@
+//! Application-level encryption for data at rest, using AES-256-GCM with
+//! the key in `ENCRYPTION_KEY` (32 bytes, base64 encoded). Generate one with
+//! `openssl rand -base64 32`.
+//!
+//! `Encrypted<T>` wraps a value so it's stored encrypted (e.g a field in a
+//! jsonb column, written with `Encrypted::sealed`), and decrypted
+//! transparently on the way back. Without a key, values are stored as-is, so turning
+//! encryption on later just means setting the key; existing plaintext
+//! values still load, and are encrypted the next time they're saved.
+//!
+//! `sign` and `verify` are for values handed out in URLs (e.g QR codes or
+//! calendar feeds), keyed on `SECRET_KEY` rather than `ENCRYPTION_KEY`.
+//!
+//! `signing_keys` are the ES256 (ECDSA P-256) keys API tokens are signed
+//! with, from `JWT_SIGNING_KEYS`: a comma separated list of base64 encoded
+//! 32 byte private keys, the first of which signs; the rest still verify,
+//! for rotating keys. Generate one with `openssl rand -base64 32`. Their
+//! public halves are published as a JWKS (see `accounts::jwt`).
+
+use std::env;
+use std::fmt;
+
+use aes_gcm::aead::{Aead, NewAead};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use anyhow::{anyhow, Result};
+use constant_time_eq::constant_time_eq;
+use hmac::{Hmac, Mac, NewMac};
+use lazy_static::lazy_static;
+use p256::ecdsa::signature::{Signer, Verifier};
+use p256::ecdsa::{Signature, SigningKey as EcdsaKey, VerifyingKey};
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use rand::{thread_rng, Rng};
+use serde::de::{DeserializeOwned, Error as _};
+use serde::ser::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Marks (and versions) encrypted values.
+const PREFIX: &str = "enc:v1:";
+
+const NONCE_LEN: usize = 12;
+
+lazy_static! {
+    static ref CIPHER: Option<Aes256Gcm> = env::var("ENCRYPTION_KEY").ok().map(|encoded| {
+        let key = base64::decode(encoded.trim()).expect("ENCRYPTION_KEY is not valid base64!");
+        if key.len() != 32 {
+            panic!("ENCRYPTION_KEY must be 32 bytes, not {}!", key.len());
+        }
+        Aes256Gcm::new(Key::from_slice(&key))
+    });
+}
+
+/// Whether `ENCRYPTION_KEY` is set.
+pub fn is_enabled() -> bool {
+    CIPHER.is_some()
+}
+
+/// Encrypts `plaintext` with a fresh nonce, returning a prefixed, base64
+/// encoded string safe to store anywhere text goes.
+pub fn encrypt(plaintext: &[u8]) -> Result<String> {
+    let cipher = CIPHER.as_ref().ok_or_else(|| anyhow!("ENCRYPTION_KEY not set!"))?;
+    let nonce: [u8; NONCE_LEN] = thread_rng().gen();
+    let mut sealed = cipher
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .map_err(|_| anyhow!("Encryption failed"))?;
+
+    let mut bytes = nonce.to_vec();
+    bytes.append(&mut sealed);
+    Ok(format!("{}{}", PREFIX, base64::encode(bytes)))
+}
+
+/// Whether `value` came from `encrypt`.
+pub fn is_encrypted(value: &str) -> bool {
+    value.starts_with(PREFIX)
+}
+
+/// Reverses `encrypt`. Fails if the key is missing or wrong, or the value
+/// was tampered with.
+pub fn decrypt(value: &str) -> Result<Vec<u8>> {
+    let cipher = CIPHER.as_ref().ok_or_else(|| anyhow!("ENCRYPTION_KEY not set!"))?;
+    let encoded = value
+        .strip_prefix(PREFIX)
+        .ok_or_else(|| anyhow!("Value is not encrypted"))?;
+    let bytes = base64::decode(encoded)?;
+    if bytes.len() < NONCE_LEN {
+        return Err(anyhow!("Encrypted value is truncated"));
+    }
+
+    let (nonce, sealed) = bytes.split_at(NONCE_LEN);
+    cipher
+        .decrypt(Nonce::from_slice(nonce), sealed)
+        .map_err(|_| anyhow!("Decryption failed; wrong ENCRYPTION_KEY?"))
+}
+
+/// An HMAC of `data`, URL safe. `salt` keeps signatures made for one
+/// purpose from being accepted for another.
+pub fn sign(salt: &str, data: &str) -> String {
+    // This is enforced at server startup, so it's safe to do here.
+    let secret_key = env::var("SECRET_KEY").expect("Unable to pull SECRET_KEY for signing");
+    let key = format!("{}{}", salt, secret_key);
+    let mut mac = HmacSha256::new_from_slice(key.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(data.as_bytes());
+    base64::encode_config(mac.finalize().into_bytes(), base64::URL_SAFE_NO_PAD)
+}
+
+/// Whether `signature` came from `sign(salt, data)`.
+pub fn verify(salt: &str, data: &str, signature: &str) -> bool {
+    constant_time_eq(sign(salt, data).as_bytes(), signature.as_bytes())
+}
+
+/// An ES256 key tokens are signed with, and the id (`kid`) it's published
+/// under: a hash of its public half, so it's stable across restarts.
+pub struct SigningKey {
+    pub kid: String,
+    key: EcdsaKey,
+}
+
+impl SigningKey {
+    /// A key from its base64 encoded 32 byte private scalar.
+    pub fn from_base64(encoded: &str) -> Result<Self> {
+        let bytes = base64::decode(encoded.trim())?;
+        let key = EcdsaKey::from_bytes(&bytes).map_err(|_| anyhow!("Not a P-256 private key"))?;
+        let public = VerifyingKey::from(&key).to_encoded_point(false);
+        let kid = base64::encode_config(&Sha256::digest(public.as_bytes())[..8], base64::URL_SAFE_NO_PAD);
+        Ok(SigningKey { kid, key })
+    }
+
+    /// The signature of `data`, as the 64 bytes (`r` then `s`) JWS uses.
+    pub fn sign(&self, data: &[u8]) -> Vec<u8> {
+        let signature: Signature = self.key.sign(data);
+        signature.as_ref().to_vec()
+    }
+
+    /// Whether `signature` is this key's signature of `data`.
+    pub fn verify(&self, data: &[u8], signature: &[u8]) -> bool {
+        match Signature::try_from(signature) {
+            Ok(signature) => VerifyingKey::from(&self.key).verify(data, &signature).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    /// The public half, as a JSON Web Key.
+    pub fn jwk(&self) -> Value {
+        let public = VerifyingKey::from(&self.key).to_encoded_point(false);
+        let coordinate = |c: Option<&[u8]>| base64::encode_config(c.unwrap_or_default(), base64::URL_SAFE_NO_PAD);
+        json!({
+            "kty": "EC",
+            "crv": "P-256",
+            "alg": "ES256",
+            "use": "sig",
+            "kid": self.kid,
+            "x": coordinate(public.x().map(|x| x.as_slice())),
+            "y": coordinate(public.y().map(|y| y.as_slice())),
+        })
+    }
+}
+
+lazy_static! {
+    static ref SIGNING_KEYS: Vec<SigningKey> = env::var("JWT_SIGNING_KEYS")
+        .map(|keys| {
+            keys.split(',')
+                .filter(|key| !key.trim().is_empty())
+                .map(|key| SigningKey::from_base64(key).expect("JWT_SIGNING_KEYS has an invalid key!"))
+                .collect()
+        })
+        .unwrap_or_default();
+}
+
+/// The keys from `JWT_SIGNING_KEYS`, the current one first; empty if unset.
+pub fn signing_keys() -> &'static [SigningKey] {
+    &SIGNING_KEYS
+}
+
+/// A value that's stored encrypted, if `ENCRYPTION_KEY` is set. It's
+/// only written out through `sealed` (the ciphertext, for storing) or
+/// read through `plaintext`; serialized any other way (e.g into a template
+/// context or an API response, as part of something bigger), it's `null`.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Encrypted<T>(pub T);
+
+impl<T> Encrypted<T> {
+    pub fn plaintext(&self) -> &T {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+
+    /// Serializes as what's stored: the ciphertext, or the plaintext if
+    /// encryption's off.
+    pub fn sealed(&self) -> Sealed<'_, T> {
+        Sealed(&self.0)
+    }
+}
+
+impl<T> From<T> for Encrypted<T> {
+    fn from(value: T) -> Self {
+        Encrypted(value)
+    }
+}
+
+/// Keeps sensitive values out of logs.
+impl<T> fmt::Debug for Encrypted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Encrypted(..)")
+    }
+}
+
+/// Keeps both the plaintext and the ciphertext out of anything an
+/// `Encrypted` is serialized along with.
+impl<T> Serialize for Encrypted<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_none()
+    }
+}
+
+/// An `Encrypted` value as it's stored; see `Encrypted::sealed`.
+pub struct Sealed<'a, T>(&'a T);
+
+impl<T: Serialize> Serialize for Sealed<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if !is_enabled() {
+            return self.0.serialize(serializer);
+        }
+
+        let json = serde_json::to_vec(self.0).map_err(S::Error::custom)?;
+        encrypt(&json)
+            .map_err(S::Error::custom)?
+            .serialize(serializer)
+    }
+}
+
+impl<'de, T: DeserializeOwned> Deserialize<'de> for Encrypted<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        match Value::deserialize(deserializer)? {
+            Value::String(value) if is_encrypted(&value) => {
+                let json = decrypt(&value).map_err(D::Error::custom)?;
+                serde_json::from_slice(&json).map(Encrypted).map_err(D::Error::custom)
+            }
+            plaintext => serde_json::from_value(plaintext).map(Encrypted).map_err(D::Error::custom),
+        }
+    }
+}
@@ -1,10 +1,13 @@
 pub(crate) use common::Configurable;
-pub use common::Email;
+pub use common::{Email, EmailCategory};
 pub use tera::Context;
 
 use anyhow::anyhow;
+use std::env::var;
 
 pub(crate) mod common;
+#[cfg(feature = "email-inline-css")]
+pub mod inline;
 #[cfg(feature = "email-mock")]
 pub mod mock;
 #[cfg(feature = "email-postmark")]
@@ -14,6 +17,24 @@ pub mod sendgrid;
 #[cfg(feature = "email-smtp")]
 pub mod smtp;
 
+pub mod jobs;
+pub use jobs::{SendBulkEmail, SendTransactionalEmail};
+
+/// The providers compiled in, in the order they're tried.
+pub fn providers() -> Vec<&'static str> {
+    #[allow(unused_mut)]
+    let mut providers = Vec::new();
+    #[cfg(feature = "email-postmark")]
+    providers.push("postmark");
+    #[cfg(feature = "email-sendgrid")]
+    providers.push("sendgrid");
+    #[cfg(feature = "email-smtp")]
+    providers.push("smtp");
+    #[cfg(feature = "email-mock")]
+    providers.push("mock");
+    providers
+}
+
 impl Configurable for Email {
     fn check_conf() {
         #[cfg(feature = "email-postmark")]
@@ -24,29 +45,68 @@ impl Configurable for Email {
         sendgrid::check_conf();
         #[cfg(feature = "email-mock")]
         mock::check_conf();
+
+        common::check_verified_senders();
+
+        #[cfg(feature = "production")]
+        if redirect_all_to().is_some() {
+            panic!("EMAIL_REDIRECT_ALL_TO is set, but it's only for staging, not production!");
+        }
+
+        for category in [EmailCategory::Transactional, EmailCategory::Bulk] {
+            if let Ok(provider) = var(category.provider_env()) {
+                if !providers().contains(&provider.as_str()) {
+                    panic!("{} is {}, which isn't enabled!", category.provider_env(), provider);
+                }
+            }
+        }
     }
 }
 
+/// The inbox `EMAIL_REDIRECT_ALL_TO` sends everything to, if it's set.
+pub fn redirect_all_to() -> Option<String> {
+    var("EMAIL_REDIRECT_ALL_TO").ok().filter(|inbox| !inbox.is_empty())
+}
+
 impl Email {
+    /// Sends via the provider configured for this email's category, or,
+    /// if there isn't one, each enabled provider in turn until one succeeds.
     pub fn send(self) -> Result<(), anyhow::Error> {
-        #[allow(unused_mut)]
-        let mut res = Result::Err(anyhow!("No email provider configured"));
-        #[cfg(feature = "email-postmark")]
-        if res.is_err() {
-            res = Email::send_via_postmark(&self, "https://api.postmarkapp.com");
+        if let Ok(provider) = var(self.category.provider_env()) {
+            return self.send_via(&provider);
         }
-        #[cfg(feature = "email-sendgrid")]
-        if res.is_err() {
-            res = Email::send_via_sendgrid(&self, "https://api.sendgrid.com");
+
+        let mut res = Result::Err(anyhow!("No email provider configured"));
+        for provider in providers() {
+            res = self.send_via(provider);
+            if res.is_ok() {
+                break;
+            }
         }
-        #[cfg(feature = "email-smtp")]
-        if res.is_err() {
-            res = Email::send_via_smtp(&self);
+        res
+    }
+
+    /// Sends via a specific provider. If `EMAIL_REDIRECT_ALL_TO` is set, the
+    /// message goes there instead (see `Email::redirect_to`); it's ignored
+    /// in production, which won't start with it set.
+    pub fn send_via(&self, provider: &str) -> Result<(), anyhow::Error> {
+        match redirect_all_to() {
+            Some(inbox) if cfg!(not(feature = "production")) => self.redirect_to(&inbox).dispatch(provider),
+            _ => self.dispatch(provider),
         }
-        #[cfg(feature = "email-mock")]
-        if res.is_err() {
-            res = Email::send_via_mock(&self);
+    }
+
+    fn dispatch(&self, provider: &str) -> Result<(), anyhow::Error> {
+        match provider {
+            #[cfg(feature = "email-postmark")]
+            "postmark" => self.send_via_postmark("https://api.postmarkapp.com"),
+            #[cfg(feature = "email-sendgrid")]
+            "sendgrid" => self.send_via_sendgrid("https://api.sendgrid.com"),
+            #[cfg(feature = "email-smtp")]
+            "smtp" => self.send_via_smtp(),
+            #[cfg(feature = "email-mock")]
+            "mock" => self.send_via_mock(),
+            _ => Err(anyhow!("Email provider {} is not enabled", provider)),
         }
-        res
     }
 }
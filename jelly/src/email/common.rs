@@ -5,7 +5,10 @@ use tera::{Context, Tera};
 
 use anyhow::{anyhow, Error, Result};
 use chrono::{Datelike, Utc};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+
+/// Line width for plain-text parts generated from HTML templates.
+const TEXT_WIDTH: usize = 78;
 
 pub trait Configurable {
     /// Check that configuration is complete.
@@ -21,12 +24,102 @@ pub fn env_exists_and_not_empty(env: &str) {
     }
 }
 
-#[derive(Debug, Default, Serialize)]
+/// The env var suffix for a template's own sender settings, e.g
+/// `email/reset-password` -> `RESET_PASSWORD`.
+fn sender_key(template_name: &str) -> String {
+    let name = template_name.rsplit('/').next().unwrap_or(template_name);
+    name.replace('-', "_").to_uppercase()
+}
+
+/// The From and Reply-To addresses for a template: `EMAIL_FROM_<NAME>` and
+/// `EMAIL_REPLY_TO_<NAME>` when set (e.g `EMAIL_FROM_RESET_PASSWORD`),
+/// otherwise `EMAIL_DEFAULT_FROM` and `EMAIL_DEFAULT_REPLY_TO`.
+pub fn senders(template_name: &str) -> (String, Option<String>) {
+    let key = sender_key(template_name);
+    let setting = |prefix: &str, default: &str| {
+        var(format!("{}{}", prefix, key))
+            .or_else(|_| var(default))
+            .ok()
+            .filter(|v| !v.is_empty())
+    };
+
+    let from = setting("EMAIL_FROM_", "EMAIL_DEFAULT_FROM").expect("EMAIL_DEFAULT_FROM not set!");
+    let reply_to = setting("EMAIL_REPLY_TO_", "EMAIL_DEFAULT_REPLY_TO");
+    (from, reply_to)
+}
+
+/// The bare address of a mailbox, e.g `Billing <billing@example.com>`.
+fn address(mailbox: &str) -> &str {
+    match (mailbox.find('<'), mailbox.rfind('>')) {
+        (Some(start), Some(end)) if start < end => mailbox[start + 1..end].trim(),
+        _ => mailbox.trim(),
+    }
+}
+
+/// Checks every configured From address (`EMAIL_DEFAULT_FROM` and any
+/// `EMAIL_FROM_*`) against `EMAIL_VERIFIED_SENDERS`, a comma-separated
+/// list of the addresses and domains verified with your provider. Does
+/// nothing if that isn't set; panics on an unverified sender.
+pub fn check_verified_senders() {
+    let verified: Vec<String> = match var("EMAIL_VERIFIED_SENDERS") {
+        Ok(list) => list
+            .split(',')
+            .map(|s| s.trim().trim_start_matches('@').to_lowercase())
+            .filter(|s| !s.is_empty())
+            .collect(),
+        Err(_) => return,
+    };
+
+    for (key, value) in env::vars() {
+        if key != "EMAIL_DEFAULT_FROM" && !key.starts_with("EMAIL_FROM_") {
+            continue;
+        }
+
+        let from = address(&value).to_lowercase();
+        let domain = from.rsplit('@').next().unwrap_or_default();
+        if !verified.iter().any(|v| *v == from || v == domain) {
+            panic!("{} ({}) is not in EMAIL_VERIFIED_SENDERS!", key, value);
+        }
+    }
+}
+
+/// What kind of email this is. Transactional mail (verification, password
+/// resets) is queued at high priority; bulk mail (newsletters, digests) goes
+/// to a throttled low priority queue. Each can also use its own provider.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailCategory {
+    Transactional,
+    Bulk,
+}
+
+impl Default for EmailCategory {
+    fn default() -> Self {
+        EmailCategory::Transactional
+    }
+}
+
+impl EmailCategory {
+    /// The environment variable naming the provider for this category,
+    /// e.g `EMAIL_PROVIDER_BULK="sendgrid"`.
+    pub fn provider_env(&self) -> &'static str {
+        match self {
+            EmailCategory::Transactional => "EMAIL_PROVIDER_TRANSACTIONAL",
+            EmailCategory::Bulk => "EMAIL_PROVIDER_BULK",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct Email {
     /// Who's sending this.
     #[serde(rename = "From")]
     pub from: String,
 
+    /// Where replies should go, if not to the sender.
+    #[serde(rename = "ReplyTo", skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<String>,
+
     /// Who to send to. Comma-delimited.
     #[serde(rename = "To")]
     pub to: String,
@@ -46,6 +139,10 @@ pub struct Email {
     /// Postmark stream to use
     #[serde(rename = "MessageStream")]
     pub postmark_message_stream: String,
+
+    /// Used for routing; not part of any provider payload.
+    #[serde(skip)]
+    pub category: EmailCategory,
 }
 
 impl Email {
@@ -83,13 +180,25 @@ impl Email {
         let body_html = engine
             .render(&(template_name.to_string() + ".html"), &context)
             .map_err(Error::msg)?;
-        let body = engine
-            .render(&(template_name.to_string() + ".txt"), &context)
-            .map_err(Error::msg)?;
+
+        // An explicit `.txt` template wins; otherwise derive the plain-text
+        // part from the HTML so every message goes out multipart/alternative.
+        let text_template = template_name.to_string() + ".txt";
+        let body = if engine.get_template_names().any(|name| name == text_template) {
+            engine.render(&text_template, &context).map_err(Error::msg)?
+        } else {
+            html2text::from_read(body_html.as_bytes(), TEXT_WIDTH)
+        };
+
+        #[cfg(feature = "email-inline-css")]
+        let body_html = super::inline::inline_css(template_name, &body_html)?;
+
+        let (from, reply_to) = senders(template_name);
 
         Ok(Email {
             to: to.join(","),
-            from: var("EMAIL_DEFAULT_FROM").expect("EMAIL_DEFAULT_FROM not set!"),
+            from,
+            reply_to,
             body_html,
             body,
             subject: subject.to_string(),
@@ -98,6 +207,24 @@ impl Email {
             #[cfg(feature = "email-postmark")]
             postmark_message_stream: var("POSTMARK_MESSAGE_STREAM")
                 .expect("POSTMARK_MESSAGE_STREAM not set!"),
+            category: EmailCategory::default(),
         })
     }
+
+    /// A copy of this email addressed to `inbox` instead, with the original
+    /// recipients noted in the subject. Used to keep staging from mailing
+    /// real users.
+    pub fn redirect_to(&self, inbox: &str) -> Self {
+        Email {
+            to: inbox.to_string(),
+            subject: format!("[To: {}] {}", self.to, self.subject),
+            ..self.clone()
+        }
+    }
+
+    /// Sets the category, e.g `Email::new(...)?.with_category(EmailCategory::Bulk)`.
+    pub fn with_category(mut self, category: EmailCategory) -> Self {
+        self.category = category;
+        self
+    }
 }
@@ -0,0 +1,64 @@
+//! Post-render CSS inlining for HTML emails. Outlook (and a few webmail
+//! clients) ignore `<style>` blocks, so rules are copied onto each element's
+//! `style` attribute before the mail goes out.
+//!
+//! Results are cached per template, keyed on a SHA-256 of the rendered
+//! HTML, so mail that renders identically (bulk sends, digests) is only
+//! inlined once, and one recipient's mail is never served for another's.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Result};
+use css_inline::{CSSInliner, InlineOptions};
+use lazy_static::lazy_static;
+use sha2::{Digest, Sha256};
+
+/// Cached renders kept per template before the oldest entries are dropped.
+const CACHE_SIZE: usize = 32;
+
+type Key = [u8; 32];
+
+lazy_static! {
+    static ref CACHE: Mutex<HashMap<String, Vec<(Key, String)>>> = Mutex::new(HashMap::new());
+}
+
+/// Inlines the `<style>` rules in `html`, rendered from `template_name`.
+/// Remote stylesheets are never fetched.
+pub fn inline_css(template_name: &str, html: &str) -> Result<String> {
+    let key: Key = Sha256::digest(html.as_bytes()).into();
+
+    if let Some(hit) = CACHE
+        .lock()
+        .ok()
+        .and_then(|cache| cached(&cache, template_name, key))
+    {
+        return Ok(hit);
+    }
+
+    let options = InlineOptions {
+        load_remote_stylesheets: false,
+        ..InlineOptions::default()
+    };
+    let inlined = CSSInliner::new(options)
+        .inline(html)
+        .map_err(|e| anyhow!("Error inlining CSS for {}: {}", template_name, e))?;
+
+    if let Ok(mut cache) = CACHE.lock() {
+        let entries = cache.entry(template_name.to_string()).or_default();
+        if entries.len() >= CACHE_SIZE {
+            entries.remove(0);
+        }
+        entries.push((key, inlined.clone()));
+    }
+
+    Ok(inlined)
+}
+
+fn cached(cache: &HashMap<String, Vec<(Key, String)>>, template_name: &str, key: Key) -> Option<String> {
+    cache
+        .get(template_name)?
+        .iter()
+        .find(|(k, _)| *k == key)
+        .map(|(_, html)| html.clone())
+}
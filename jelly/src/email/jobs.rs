@@ -0,0 +1,70 @@
+//! Jobs for sending an already-rendered `Email` through the queues.
+//!
+//! Transactional mail goes to `TRANSACTIONAL_QUEUE`, which has plenty of
+//! workers; bulk mail goes to `BULK_QUEUE`, with a single worker that pauses
+//! `EMAIL_BULK_DELAY_MS` (default 1000) after each message, so a newsletter
+//! can't starve password resets or trip provider rate limits.
+
+use std::env::var;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+use anyhow::Error;
+use background_jobs::Job;
+use serde::{Deserialize, Serialize};
+
+use super::{Email, EmailCategory};
+use crate::jobs::{JobState, BULK_QUEUE, TRANSACTIONAL_QUEUE};
+
+const DEFAULT_BULK_DELAY_MS: u64 = 1000;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SendTransactionalEmail {
+    pub email: Email,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SendBulkEmail {
+    pub email: Email,
+}
+
+impl Job for SendTransactionalEmail {
+    type State = JobState;
+    type Future = Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
+
+    const NAME: &'static str = "SendTransactionalEmailJob";
+    const QUEUE: &'static str = TRANSACTIONAL_QUEUE;
+
+    fn run(self, _state: JobState) -> Self::Future {
+        Box::pin(async move {
+            let mut email = self.email;
+            email.category = EmailCategory::Transactional;
+            email.send()
+        })
+    }
+}
+
+impl Job for SendBulkEmail {
+    type State = JobState;
+    type Future = Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
+
+    const NAME: &'static str = "SendBulkEmailJob";
+    const QUEUE: &'static str = BULK_QUEUE;
+
+    fn run(self, _state: JobState) -> Self::Future {
+        Box::pin(async move {
+            let mut email = self.email;
+            email.category = EmailCategory::Bulk;
+            let res = email.send();
+
+            let delay = var("EMAIL_BULK_DELAY_MS")
+                .ok()
+                .and_then(|ms| ms.parse().ok())
+                .unwrap_or(DEFAULT_BULK_DELAY_MS);
+            actix_rt::time::sleep(Duration::from_millis(delay)).await;
+
+            res
+        })
+    }
+}
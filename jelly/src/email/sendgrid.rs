@@ -24,6 +24,8 @@ struct Content<'a> {
 struct SendgridV3Data<'a> {
     personalizations: Vec<Personalization<'a>>,
     from: EmailAddress<'a>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply_to: Option<EmailAddress<'a>>,
     subject: &'a String,
     content: Vec<Content<'a>>,
 }
@@ -45,6 +47,7 @@ impl Email {
                 to: vec![EmailAddress { email: &self.to }],
             }],
             from: EmailAddress { email: &self.from },
+            reply_to: self.reply_to.as_ref().map(|email| EmailAddress { email }),
             subject: &self.subject,
             content: vec![
                 Content {
@@ -2,7 +2,9 @@
 //! returning responses. This module handles converting several differing
 //! error formats into the one we use for responding.
 
+use actix_web::http::StatusCode;
 use actix_web::{HttpResponse, ResponseError};
+use serde_json::json;
 use std::{error, fmt};
 
 #[cfg(feature = "oauth")]
@@ -27,6 +29,18 @@ pub enum OAuthError {
     FetchProfileError(#[source] reqwest::HttpClientError),
     #[error("decode profile error: #{0}")]
     DecodeProfileError(#[source] serde_json::error::Error),
+    #[error("client secret error: #{0}")]
+    ClientSecretError(String),
+    #[error("id token error: #{0}")]
+    IdTokenError(String),
+    #[error("provider doesn't support the device flow")]
+    DeviceFlowUnsupported,
+    #[error("device flow request error: #{0}")]
+    DeviceRequestError(#[source] reqwest::HttpClientError),
+    #[error("device flow error: #{0}")]
+    DeviceFlowError(String),
+    #[error("token revocation error: #{0}")]
+    RevokeTokenError(String),
 }
 
 #[cfg(not(feature = "oauth"))]
@@ -52,7 +66,12 @@ pub enum Error {
     NoPasswordForAccount,
     InvalidPassword,
     InvalidAccountToken,
+    AccountInactive,
     OAuth(OAuthError),
+    PreconditionFailed,
+    PreconditionRequired,
+    QuotaExceeded(String),
+    PermissionDenied(String),
 }
 
 impl fmt::Display for Error {
@@ -75,7 +94,12 @@ impl error::Error for Error {
             | Error::NoPasswordForAccount
             | Error::InvalidPassword
             | Error::InvalidAccountToken
-            | Error::OAuth(_) => None,
+            | Error::AccountInactive
+            | Error::OAuth(_)
+            | Error::PreconditionFailed
+            | Error::PreconditionRequired
+            | Error::QuotaExceeded(_)
+            | Error::PermissionDenied(_) => None,
         }
     }
 }
@@ -129,8 +153,37 @@ impl From<OAuthError> for Error {
 }
 
 impl ResponseError for Error {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Error::PreconditionFailed => StatusCode::PRECONDITION_FAILED,
+            Error::PreconditionRequired => StatusCode::PRECONDITION_REQUIRED,
+            Error::QuotaExceeded(_) => StatusCode::TOO_MANY_REQUESTS,
+            Error::PermissionDenied(_) => StatusCode::FORBIDDEN,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
     fn error_response(&self) -> HttpResponse {
-        HttpResponse::InternalServerError()
+        // These only come from the JSON API's `If-Match` checks, so answer
+        // in kind.
+        let message = match self {
+            Error::PreconditionFailed => Some((
+                "precondition_failed",
+                "The resource has changed; fetch it again.",
+            )),
+            Error::PreconditionRequired => Some((
+                "precondition_required",
+                "Send the resource's ETag in If-Match.",
+            )),
+            _ => None,
+        };
+
+        if let Some((code, message)) = message {
+            return HttpResponse::build(self.status_code())
+                .json(json!({ "error": code, "message": message }));
+        }
+
+        HttpResponse::build(self.status_code())
             .content_type("text/html; charset=utf-8")
             .body(render(self))
     }
@@ -0,0 +1,168 @@
+//! A/B experiments. Experiments and their variants are listed in
+//! `EXPERIMENTS`, e.g `"hero=control,new;pricing=monthly,annual"`, and
+//! everyone is put in one variant of each, by a random id kept in their
+//! session. It stays the same when they sign in, and is tied to their
+//! account then (`Recorder::identify`), so results can be counted by
+//! account without anyone's variant changing partway through. Bucketing
+//! is a hash of the experiment and that id, so it's stable without storing
+//! anything.
+//!
+//! Templates check the bucket with the `in_variant` test, against the
+//! `experiment_unit` every page gets:
+//!
+//! ```html
+//! {% if experiment_unit is in_variant("hero", "new") %}
+//! ```
+//!
+//! Handlers use `request.variant("hero")` and, when the goal is reached,
+//! `request.convert("hero")`. Both exposures and conversions go to the
+//! `Recorder` set with `set_recorder`; by default they're only logged.
+
+use std::env;
+use std::sync::RwLock;
+
+use lazy_static::lazy_static;
+use sha2::{Digest, Sha256};
+use tera::{Tera, Value};
+
+/// An experiment, and the variants people are split between.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Experiment {
+    pub name: String,
+    pub variants: Vec<String>,
+}
+
+impl Experiment {
+    /// The variant `unit` is in. The same unit always lands in the same
+    /// variant, for as long as the variants don't change.
+    pub fn variant_for(&self, unit: &str) -> &str {
+        let hash = Sha256::digest(format!("{}:{}", self.name, unit).as_bytes());
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&hash[..8]);
+        let bucket = u64::from_be_bytes(bytes) % self.variants.len() as u64;
+        &self.variants[bucket as usize]
+    }
+}
+
+/// Parses `EXPERIMENTS`-style configuration. Experiments without at least
+/// two variants are skipped.
+pub fn parse(config: &str) -> Vec<Experiment> {
+    config
+        .split(';')
+        .filter_map(|experiment| {
+            let (name, variants) = experiment.split_once('=')?;
+            let variants: Vec<String> = variants
+                .split(',')
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(String::from)
+                .collect();
+            if name.trim().is_empty() || variants.len() < 2 {
+                return None;
+            }
+            Some(Experiment {
+                name: name.trim().to_string(),
+                variants,
+            })
+        })
+        .collect()
+}
+
+/// Where exposures and conversions are sent.
+pub trait Recorder: Send + Sync {
+    /// `unit` was shown `variant` of `experiment`. Called every time the
+    /// variant is checked, so expect repeats.
+    fn exposure(&self, experiment: &str, variant: &str, unit: &str);
+
+    /// `unit` reached `experiment`'s goal.
+    fn conversion(&self, experiment: &str, unit: &str);
+
+    /// `unit` signed in as `account_id`. Called at every sign in, so
+    /// expect repeats.
+    fn identify(&self, _unit: &str, _account_id: i32) {}
+}
+
+/// The default `Recorder`, which just logs.
+pub struct LogRecorder;
+
+impl Recorder for LogRecorder {
+    fn exposure(&self, experiment: &str, variant: &str, unit: &str) {
+        debug!("Experiment {}: {} saw {}", experiment, unit, variant);
+    }
+
+    fn conversion(&self, experiment: &str, unit: &str) {
+        debug!("Experiment {}: {} converted", experiment, unit);
+    }
+
+    fn identify(&self, unit: &str, account_id: i32) {
+        debug!("Experiments: {} is account {}", unit, account_id);
+    }
+}
+
+lazy_static! {
+    static ref EXPERIMENTS: Vec<Experiment> = parse(&env::var("EXPERIMENTS").unwrap_or_default());
+    static ref RECORDER: RwLock<Box<dyn Recorder>> = RwLock::new(Box::new(LogRecorder));
+}
+
+/// Every configured experiment.
+pub fn all() -> &'static [Experiment] {
+    &EXPERIMENTS
+}
+
+pub fn get(name: &str) -> Option<&'static Experiment> {
+    EXPERIMENTS.iter().find(|experiment| experiment.name == name)
+}
+
+/// Replaces the `Recorder`; call once at startup.
+pub fn set_recorder<R: Recorder + 'static>(recorder: R) {
+    match RECORDER.write() {
+        Ok(mut current) => *current = Box::new(recorder),
+        Err(e) => error!("Unable to set experiment recorder: {:?}", e),
+    }
+}
+
+/// The variant `unit` is in, recording the exposure. `None` if there's no
+/// such experiment.
+pub fn expose(experiment: &str, unit: &str) -> Option<&'static str> {
+    let variant = get(experiment)?.variant_for(unit);
+    if let Ok(recorder) = RECORDER.read() {
+        recorder.exposure(experiment, variant, unit);
+    }
+    Some(variant)
+}
+
+/// Records that `unit` reached `experiment`'s goal.
+pub fn convert(experiment: &str, unit: &str) {
+    if get(experiment).is_none() {
+        return;
+    }
+    if let Ok(recorder) = RECORDER.read() {
+        recorder.conversion(experiment, unit);
+    }
+}
+
+/// Records that `unit` is `account_id`'s.
+pub fn identify(unit: &str, account_id: i32) {
+    if let Ok(recorder) = RECORDER.read() {
+        recorder.identify(unit, account_id);
+    }
+}
+
+/// Registers the `in_variant` test on a Tera instance.
+pub fn register(tera: &mut Tera) {
+    tera.register_tester("in_variant", |value: Option<&Value>, args: &[Value]| {
+        let unit = value
+            .and_then(Value::as_str)
+            .ok_or_else(|| tera::Error::msg("in_variant: expected the `experiment_unit`"))?;
+        let (experiment, variant) = match args {
+            [experiment, variant] => (experiment.as_str(), variant.as_str()),
+            _ => return Err(tera::Error::msg("in_variant: expected an experiment and a variant")),
+        };
+        let experiment = experiment
+            .ok_or_else(|| tera::Error::msg("in_variant: experiment must be a string"))?;
+        let variant =
+            variant.ok_or_else(|| tera::Error::msg("in_variant: variant must be a string"))?;
+
+        Ok(expose(experiment, unit) == Some(variant))
+    });
+}
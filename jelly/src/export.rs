@@ -0,0 +1,180 @@
+//! CSV (and, with the `xlsx` feature, Excel) downloads of model listings.
+//!
+//! Rows are fetched a page at a time, by keyset rather than offset, and
+//! CSV is streamed out as each page arrives, so exporting a big table
+//! doesn't mean loading it into memory:
+//!
+//! ```ignore
+//! let pool = request.db_pool()?.clone();
+//! Ok(export::csv("accounts.csv", move |after, limit| {
+//!     let (filter, pool) = (filter.clone(), pool.clone());
+//!     async move { Account::list(&filter, after, limit, &pool).await }
+//! }))
+//! ```
+//!
+//! `fetch(after, limit)` returns up to `limit` rows, in the listing's order,
+//! starting after the row whose `cursor()` is `after` (from the top if
+//! `None`).
+
+use std::future::Future;
+
+use actix_web::web::Bytes;
+use actix_web::HttpResponse;
+use futures::stream::{self, StreamExt, TryStreamExt};
+
+use crate::error::Error;
+use crate::request::render::stream_response;
+
+/// Rows fetched per page.
+pub const PAGE_SIZE: i64 = 500;
+
+/// Whether `xlsx` does anything, e.g to decide whether to offer it.
+pub const XLSX: bool = cfg!(feature = "xlsx");
+
+/// A row of an export.
+pub trait Row {
+    /// Column headings.
+    fn headers() -> &'static [&'static str];
+
+    /// The row's values, one per heading.
+    fn cells(&self) -> Vec<String>;
+
+    /// Where the next page starts, if this is the last row of a page;
+    /// usually the id the listing is ordered by.
+    fn cursor(&self) -> i64;
+}
+
+/// Quotes a CSV field. Anything a spreadsheet would treat as a formula is
+/// defused with a leading `'`, since exported values are often user supplied.
+pub fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@']) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+fn csv_line<I: IntoIterator<Item = String>>(cells: I) -> String {
+    let mut line = cells
+        .into_iter()
+        .map(|cell| csv_field(&cell))
+        .collect::<Vec<_>>()
+        .join(",");
+    line.push_str("\r\n");
+    line
+}
+
+/// `rows` as a whole CSV file, headings first; for writing out, rather
+/// than sending.
+pub fn csv_string<T: Row>(rows: &[T]) -> String {
+    let header = csv_line(T::headers().iter().map(|h| h.to_string()));
+    rows.iter().fold(header, |mut csv, row| {
+        csv.push_str(&csv_line(row.cells()));
+        csv
+    })
+}
+
+/// Every row `fetch` returns, page by page, as a stream.
+fn pages<T, F, Fut>(fetch: F) -> impl stream::Stream<Item = Result<Vec<T>, Error>>
+where
+    T: Row,
+    F: Fn(Option<i64>, i64) -> Fut,
+    Fut: Future<Output = Result<Vec<T>, Error>>,
+{
+    // `None` once the last page is in; `Some(after)` otherwise.
+    stream::try_unfold((fetch, Some(None)), |(fetch, next)| async move {
+        let after = match next {
+            Some(after) => after,
+            None => return Ok(None),
+        };
+
+        let rows = fetch(after, PAGE_SIZE).await?;
+        let next = match rows.last() {
+            Some(last) if rows.len() as i64 == PAGE_SIZE => Some(Some(last.cursor())),
+            _ => None,
+        };
+        Ok(Some((rows, (fetch, next))))
+    })
+}
+
+/// A streamed CSV download of every row `fetch` returns.
+pub fn csv<T, F, Fut>(filename: &str, fetch: F) -> HttpResponse
+where
+    T: Row + 'static,
+    F: Fn(Option<i64>, i64) -> Fut + 'static,
+    Fut: Future<Output = Result<Vec<T>, Error>> + 'static,
+{
+    let header = csv_line(T::headers().iter().map(|h| h.to_string()));
+    let rows = pages(fetch).map_ok(|rows| rows.iter().map(|row| csv_line(row.cells())).collect::<String>());
+
+    let body = stream::once(async move { Ok(header) })
+        .chain(rows)
+        .map_ok(Bytes::from);
+
+    stream_response("text/csv; charset=utf-8", Some(filename), body)
+}
+
+/// An Excel download of every row `fetch` returns. XLSX files are zip
+/// archives, so this one is built in memory before it's sent; prefer `csv`
+/// for anything big.
+#[cfg(feature = "xlsx")]
+pub async fn xlsx<T, F, Fut>(filename: &str, fetch: F) -> Result<HttpResponse, Error>
+where
+    T: Row,
+    F: Fn(Option<i64>, i64) -> Fut,
+    Fut: Future<Output = Result<Vec<T>, Error>>,
+{
+    use actix_web::http::header::CONTENT_DISPOSITION;
+    use simple_excel_writer::{Row as SheetRow, Workbook};
+
+    let rows: Vec<Vec<T>> = pages(fetch).try_collect().await?;
+
+    let mut workbook = Workbook::create_in_memory();
+    let mut sheet = workbook.create_sheet("Export");
+    workbook
+        .write_sheet(&mut sheet, |writer| {
+            let mut header = SheetRow::new();
+            for heading in T::headers() {
+                header.add_cell(*heading);
+            }
+            writer.append_row(header)?;
+
+            for row in rows.iter().flatten() {
+                let mut cells = SheetRow::new();
+                for cell in row.cells() {
+                    cells.add_cell(cell);
+                }
+                writer.append_row(cells)?;
+            }
+            Ok(())
+        })
+        .map_err(|e| Error::Generic(format!("Error writing XLSX: {:?}", e)))?;
+
+    let bytes = workbook
+        .close()
+        .map_err(|e| Error::Generic(format!("Error writing XLSX: {:?}", e)))?
+        .unwrap_or_default();
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/vnd.openxmlformats-officedocument.spreadsheetml.sheet")
+        .insert_header((CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)))
+        .body(bytes))
+}
+
+/// Without the `xlsx` feature there's no Excel export; callers can offer
+/// CSV instead.
+#[cfg(not(feature = "xlsx"))]
+pub async fn xlsx<T, F, Fut>(_filename: &str, _fetch: F) -> Result<HttpResponse, Error>
+where
+    T: Row,
+    F: Fn(Option<i64>, i64) -> Fut,
+    Fut: Future<Output = Result<Vec<T>, Error>>,
+{
+    Ok(HttpResponse::NotFound().finish())
+}
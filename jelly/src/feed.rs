@@ -0,0 +1,162 @@
+//! A small Atom (RFC 4287) feed builder:
+//!
+//! ```ignore
+//! let mut feed = Feed::new("https://example.com/feed.xml", "Example", updated);
+//! feed.push(Entry::new("https://example.com/pages/hello", "Hello", published).content(html));
+//! Ok(HttpResponse::Ok().content_type(feed::CONTENT_TYPE).body(feed.to_string()))
+//! ```
+
+use std::fmt;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+
+pub const CONTENT_TYPE: &str = "application/atom+xml; charset=utf-8";
+
+/// An `<entry>`. `id` should be a permanent URL (or other IRI) for it.
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub id: String,
+    pub title: String,
+    pub link: Option<String>,
+    pub published: DateTime<Utc>,
+    pub updated: DateTime<Utc>,
+    pub author: Option<String>,
+    pub summary: Option<String>,
+    /// HTML.
+    pub content: Option<String>,
+}
+
+impl Entry {
+    pub fn new(id: impl Into<String>, title: impl Into<String>, published: DateTime<Utc>) -> Self {
+        let id = id.into();
+        Entry {
+            link: Some(id.clone()),
+            id,
+            title: title.into(),
+            published,
+            updated: published,
+            author: None,
+            summary: None,
+            content: None,
+        }
+    }
+
+    pub fn link(mut self, link: impl Into<String>) -> Self {
+        self.link = Some(link.into());
+        self
+    }
+
+    pub fn updated(mut self, updated: DateTime<Utc>) -> Self {
+        self.updated = updated;
+        self
+    }
+
+    pub fn author(mut self, author: impl Into<String>) -> Self {
+        self.author = Some(author.into());
+        self
+    }
+
+    pub fn summary(mut self, summary: impl Into<String>) -> Self {
+        self.summary = Some(summary.into());
+        self
+    }
+
+    pub fn content(mut self, html: impl Into<String>) -> Self {
+        self.content = Some(html.into());
+        self
+    }
+}
+
+/// A `<feed>`. `Display` renders the finished document.
+#[derive(Clone, Debug)]
+pub struct Feed {
+    /// The feed's own URL, used as its id and `rel="self"` link.
+    pub id: String,
+    pub title: String,
+    pub link: Option<String>,
+    pub author: Option<String>,
+    pub updated: DateTime<Utc>,
+    pub entries: Vec<Entry>,
+}
+
+impl Feed {
+    pub fn new(id: impl Into<String>, title: impl Into<String>, updated: DateTime<Utc>) -> Self {
+        Feed {
+            id: id.into(),
+            title: title.into(),
+            link: None,
+            author: None,
+            updated,
+            entries: Vec::new(),
+        }
+    }
+
+    /// The site the feed is for.
+    pub fn link(mut self, link: impl Into<String>) -> Self {
+        self.link = Some(link.into());
+        self
+    }
+
+    /// The default author, for entries without one.
+    pub fn author(mut self, author: impl Into<String>) -> Self {
+        self.author = Some(author.into());
+        self
+    }
+
+    pub fn push(&mut self, entry: Entry) -> &mut Self {
+        self.entries.push(entry);
+        self
+    }
+}
+
+impl fmt::Display for Feed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, r#"<?xml version="1.0" encoding="utf-8"?>"#)?;
+        writeln!(f, r#"<feed xmlns="http://www.w3.org/2005/Atom">"#)?;
+        writeln!(f, "  <id>{}</id>", escape(&self.id))?;
+        writeln!(f, "  <title>{}</title>", escape(&self.title))?;
+        writeln!(f, "  <updated>{}</updated>", timestamp(&self.updated))?;
+        writeln!(f, r#"  <link rel="self" href="{}"/>"#, escape(&self.id))?;
+        if let Some(link) = &self.link {
+            writeln!(f, r#"  <link rel="alternate" href="{}"/>"#, escape(link))?;
+        }
+        if let Some(author) = &self.author {
+            writeln!(f, "  <author><name>{}</name></author>", escape(author))?;
+        }
+
+        for entry in self.entries.iter() {
+            writeln!(f, "  <entry>")?;
+            writeln!(f, "    <id>{}</id>", escape(&entry.id))?;
+            writeln!(f, "    <title>{}</title>", escape(&entry.title))?;
+            writeln!(f, "    <published>{}</published>", timestamp(&entry.published))?;
+            writeln!(f, "    <updated>{}</updated>", timestamp(&entry.updated))?;
+            if let Some(link) = &entry.link {
+                writeln!(f, r#"    <link rel="alternate" href="{}"/>"#, escape(link))?;
+            }
+            if let Some(author) = &entry.author {
+                writeln!(f, "    <author><name>{}</name></author>", escape(author))?;
+            }
+            if let Some(summary) = &entry.summary {
+                writeln!(f, "    <summary>{}</summary>", escape(summary))?;
+            }
+            if let Some(content) = &entry.content {
+                writeln!(f, r#"    <content type="html">{}</content>"#, escape(content))?;
+            }
+            writeln!(f, "  </entry>")?;
+        }
+
+        writeln!(f, "</feed>")
+    }
+}
+
+fn timestamp(at: &DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -33,10 +33,10 @@ mod date;
 pub use date::DateField;
 
 mod email;
-pub use email::EmailField;
+pub use email::{EmailDomains, EmailField};
 
 mod password;
-pub use password::{split_inputs, PasswordPolicy, PasswordField};
+pub use password::{split_inputs, PasswordPolicy, PasswordField, PasswordScore};
 
 mod slug;
 pub use slug::SlugField;
@@ -44,6 +44,9 @@ pub use slug::SlugField;
 mod text;
 pub use text::TextField;
 
+#[cfg(feature = "test-support")]
+pub mod testing;
+
 pub use form_validation as validation;
 
 mod validators;
@@ -71,7 +71,7 @@ impl Validatable<String> for DateField {
                     Ok(_date) => Ok(()),
                     Err(_) => {
                         Err(ValidationError::new(key.clone(), "INVALID_DATE")
-                        .with_message(|_| "not a valid date: {}".to_owned())
+                        .with_message(|_| "not a valid date".to_owned())
                         .into())
                     },
                 }
@@ -27,6 +27,52 @@ impl EmailField {
         self.key = key.into();
         self
     }
+
+    /// The part after the `@`, lowercased; empty if there isn't one.
+    pub fn domain(&self) -> String {
+        self.value
+            .rsplit_once('@')
+            .map_or(String::new(), |(_, domain)| domain.trim().to_lowercase())
+    }
+
+    /// `validate`, then that the address's domain is one `domains` lets
+    /// sign up.
+    pub fn validate_with(&self, domains: &EmailDomains) -> Result<(), ValidationErrors<String>> {
+        self.validate()?;
+
+        if domains.permits(&self.domain()) {
+            Ok(())
+        } else {
+            Err(ValidationError::new(self.key.clone(), "EMAIL_DOMAIN_NOT_ALLOWED")
+                .with_message(|_| "addresses at this domain can't sign up".to_owned())
+                .into())
+        }
+    }
+}
+
+/// Which email domains can sign up: with `allowed` set, only those (and
+/// their subdomains); never any in `denied` (nor theirs). Both empty lets
+/// everyone in.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct EmailDomains {
+    #[serde(default)]
+    pub allowed: Vec<String>,
+    #[serde(default)]
+    pub denied: Vec<String>,
+}
+
+impl EmailDomains {
+    pub fn permits(&self, domain: &str) -> bool {
+        let domain = domain.trim().to_lowercase();
+        let covers = |listed: &String| {
+            let listed = listed.trim().trim_start_matches('@').to_lowercase();
+            !listed.is_empty()
+                && (domain == listed
+                    || domain.strip_suffix(&listed).map_or(false, |sub| sub.ends_with('.')))
+        };
+
+        (self.allowed.is_empty() || self.allowed.iter().any(covers)) && !self.denied.iter().any(covers)
+    }
 }
 
 impl From<String> for EmailField {
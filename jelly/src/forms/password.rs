@@ -68,8 +68,9 @@ impl PasswordField {
         }
     }
 
+    /// Lengths are in characters, not bytes, as the messages say.
     pub fn validate_length(&self, min_length: usize, max_length: usize) -> Result<(), ValidationErrors<String>> {
-        match self.value.len() {
+        match self.value.chars().count() {
             0 => Ok(()), // already validated
             x if x < min_length =>
                 Err(ValidationError::new(self.key.clone(), "PASSWORD_MIN_LENGTH")
@@ -83,8 +84,10 @@ impl PasswordField {
         }
     }
 
+    /// A password the regex gives up on (it hits the backtracking limit)
+    /// doesn't match.
     pub fn validate_regex(&self, regex: &Regex, message: String) -> Result<(), ValidationErrors<String>> {
-        if regex.is_match(&self.value).unwrap() {
+        if regex.is_match(&self.value).unwrap_or(false) {
             Ok(())
         } else {
             Err(ValidationError::new(self.key.clone(), "PASSWORD_FORMAT")
@@ -94,16 +97,18 @@ impl PasswordField {
     }
 
     pub fn validate_strength(&self, strength: PasswordScore, user_inputs: &[&str]) -> Result<(), ValidationErrors<String>> {
-        // The unwrap is safe, as it only errors if the
-        // password is blank, which we already
-        // handle above.
+        // zxcvbn only errors if the password is blank, which
+        // `validate` reports.
         let words = split_inputs(user_inputs);
-        let estimate = zxcvbn(&self.value,
+        let estimate = match zxcvbn(&self.value,
             words
                 .iter()
                 .map(|s| s.as_ref())
                 .collect::<Vec<&str>>()
-                .as_slice()).unwrap();
+                .as_slice()) {
+            Ok(estimate) => estimate,
+            Err(_) => return Ok(()),
+        };
         if estimate.score() >= strength as u8 {
             Ok(())
         } else {
@@ -280,7 +285,7 @@ pub fn split_inputs(inputs: &[&str]) -> Vec<String> {
         let words: Vec<String> = splitter
             .replace_all(*input, " ")
             .split(' ')
-            .filter(|w| w.len() > 3)
+            .filter(|w| w.chars().count() > 3)
             .map(|w| w.to_lowercase())
             .collect();
         for word in words {
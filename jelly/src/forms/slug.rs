@@ -26,6 +26,21 @@ impl SlugField {
         self.key = key.into();
         self
     }
+
+    /// Makes a valid slug out of `value`, e.g a name or a third party's
+    /// username: anything but letters, numbers, dashes and underscores
+    /// becomes a dash. May be empty, if there's nothing usable.
+    pub fn slugify(value: &str) -> String {
+        let mut slug = String::with_capacity(value.len());
+        for c in value.trim().chars() {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                slug.push(c);
+            } else if !slug.is_empty() && !slug.ends_with('-') {
+                slug.push('-');
+            }
+        }
+        slug.trim_end_matches('-').to_string()
+    }
 }
 
 impl From<String> for SlugField {
@@ -73,3 +88,19 @@ impl Validatable<String> for SlugField {
         v.validate_value(&self.value, &self.key)
     }
 }
+
+impl SlugField {
+    /// `validate`, then that it's only letters, numbers, dashes and
+    /// underscores, as a username has to be (and `slugify` makes).
+    pub fn validate_username(&self) -> Result<(), ValidationErrors<String>> {
+        self.validate()?;
+
+        if self.value.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            Ok(())
+        } else {
+            Err(ValidationError::new(self.key.clone(), "INVALID_USERNAME")
+                .with_message(|_| "usernames can only contain letters, numbers, dashes and underscores".to_owned())
+                .into())
+        }
+    }
+}
@@ -0,0 +1,46 @@
+//! Helpers for property testing forms, with the `test-support` feature:
+//!
+//! ```ignore
+//! use jelly::forms::testing::{adversarial_text, codes};
+//! use proptest::prelude::*;
+//!
+//! proptest! {
+//!     #[test]
+//!     fn never_panic(name in adversarial_text(64)) {
+//!         let result = TextField::new(name).with_key("name").validate();
+//!         prop_assert!(codes(&result).iter().all(|code| *code == "REQUIRED_VALUE"));
+//!     }
+//! }
+//! ```
+
+use proptest::prelude::*;
+
+use super::validation::ValidationErrors;
+
+/// Characters that tend to trip up string handling: multi-byte and
+/// combining characters, zero-width and direction marks, control
+/// characters, and lookalikes of the ASCII that forms care about.
+const TRICKY: &[char] = &[
+    '\0', '\t', '\n', '\r', '\u{7f}', ' ', '\u{a0}', '\u{200b}', '\u{200d}', '\u{202e}', '\u{feff}',
+    '\u{301}', '\u{fe0f}', 'é', 'ß', 'İ', 'ﬀ', 'Ω', '中', '𝔘', '😀', '＠', '．', '／',
+    '@', '.', '/', '-', '_', '%', '\\', '"', '<', '>',
+];
+
+/// Any text of up to `max_len` characters, skewed towards the tricky ones.
+pub fn adversarial_text(max_len: usize) -> impl Strategy<Value = String> {
+    let tricky = prop::sample::select(TRICKY.to_vec()).prop_map(String::from);
+    let piece = prop_oneof![
+        3 => tricky,
+        2 => "[a-zA-Z0-9]{1,4}",
+        1 => any::<char>().prop_map(String::from),
+    ];
+    prop::collection::vec(piece, 0..=max_len).prop_map(|pieces| pieces.concat())
+}
+
+/// The error codes in a validation result, in order; empty when it passed.
+pub fn codes(result: &Result<(), ValidationErrors<String>>) -> Vec<&'static str> {
+    match result {
+        Ok(()) => Vec::new(),
+        Err(errors) => errors.errors.iter().map(|error| error.type_id).collect(),
+    }
+}
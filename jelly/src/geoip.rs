@@ -0,0 +1,100 @@
+//! Optional IP geolocation, for annotating login events. Needs the `geoip`
+//! feature and a MaxMind GeoLite2 (or GeoIP2) City database at the path in
+//! `GEOIP_DATABASE`; without either, `lookup` always returns `None`.
+
+#[cfg(feature = "geoip")]
+use std::net::{IpAddr, SocketAddr};
+
+use serde::{Deserialize, Serialize};
+
+/// Where an IP address appears to be.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Location {
+    /// ISO 3166 country code, e.g `NZ`.
+    pub country: Option<String>,
+    pub country_name: Option<String>,
+    pub city: Option<String>,
+    #[serde(default)]
+    pub latitude: Option<f64>,
+    #[serde(default)]
+    pub longitude: Option<f64>,
+}
+
+impl Location {
+    /// e.g `"Wellington, New Zealand"`.
+    pub fn describe(&self) -> String {
+        let country = self.country_name.as_deref().or(self.country.as_deref());
+        match (self.city.as_deref(), country) {
+            (Some(city), Some(country)) => format!("{}, {}", city, country),
+            (Some(place), None) | (None, Some(place)) => place.to_string(),
+            (None, None) => String::new(),
+        }
+    }
+
+    /// Great-circle distance to `other` in kilometres, if both have
+    /// coordinates.
+    pub fn distance_km(&self, other: &Location) -> Option<f64> {
+        const EARTH_RADIUS_KM: f64 = 6371.0;
+
+        let (lat1, lon1) = (self.latitude?.to_radians(), self.longitude?.to_radians());
+        let (lat2, lon2) = (other.latitude?.to_radians(), other.longitude?.to_radians());
+        let a = ((lat2 - lat1) / 2.0).sin().powi(2)
+            + lat1.cos() * lat2.cos() * ((lon2 - lon1) / 2.0).sin().powi(2);
+        Some(2.0 * EARTH_RADIUS_KM * a.sqrt().asin())
+    }
+}
+
+/// Parses an address as reported by actix's `ConnectionInfo`, which may
+/// or may not have a port attached.
+#[cfg(feature = "geoip")]
+fn parse(ip: &str) -> Option<IpAddr> {
+    ip.parse::<IpAddr>()
+        .ok()
+        .or_else(|| ip.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+}
+
+#[cfg(feature = "geoip")]
+lazy_static::lazy_static! {
+    static ref READER: Option<maxminddb::Reader<Vec<u8>>> = {
+        let path = std::env::var("GEOIP_DATABASE").ok()?;
+        match maxminddb::Reader::open_readfile(&path) {
+            Ok(reader) => Some(reader),
+            Err(e) => {
+                error!("Unable to open GEOIP_DATABASE {}: {:?}", path, e);
+                None
+            }
+        }
+    };
+}
+
+/// Looks up `ip`. Private and unknown addresses give `None`.
+#[cfg(feature = "geoip")]
+pub fn lookup(ip: &str) -> Option<Location> {
+    use maxminddb::geoip2::City;
+
+    let reader = READER.as_ref()?;
+    let city: City = reader.lookup(parse(ip)?).ok()?;
+    let english = |names: &Option<std::collections::BTreeMap<&str, &str>>| {
+        names.as_ref().and_then(|n| n.get("en")).map(|n| n.to_string())
+    };
+
+    let location = Location {
+        country: city.country.as_ref().and_then(|c| c.iso_code).map(String::from),
+        country_name: city.country.as_ref().and_then(|c| english(&c.names)),
+        city: city.city.as_ref().and_then(|c| english(&c.names)),
+        latitude: city.location.as_ref().and_then(|l| l.latitude),
+        longitude: city.location.as_ref().and_then(|l| l.longitude),
+    };
+
+    if location == Location::default() {
+        None
+    } else {
+        Some(location)
+    }
+}
+
+/// Looks up `ip`. Always `None`, as the `geoip` feature isn't enabled.
+#[cfg(not(feature = "geoip"))]
+pub fn lookup(_ip: &str) -> Option<Location> {
+    None
+}
@@ -2,9 +2,33 @@
 
 use actix_web::guard::{Guard, Header};
 
+pub mod admin;
+pub use admin::{Admin, AdminMiddleware};
+
 pub mod auth;
 pub use auth::{Auth, AuthMiddleware};
 
+pub mod csrf;
+pub use csrf::{Csrf, CsrfMiddleware};
+
+pub mod csp;
+pub use csp::{ContentSecurityPolicy, ContentSecurityPolicyMiddleware, CspNonce};
+
+pub mod permission;
+pub use permission::{Permission, PermissionMiddleware};
+
+pub mod idempotency;
+pub use idempotency::{Idempotency, IdempotencyMiddleware};
+
+pub mod ratelimit;
+pub use ratelimit::{RateLimit, RateLimitMiddleware};
+
+pub mod read_only;
+pub use read_only::{ReadOnly, ReadOnlyMiddleware};
+
+pub mod request_id;
+pub use request_id::{AssignRequestId, AssignRequestIdMiddleware, RequestId};
+
 pub fn accepts_json() -> impl Guard {
     Header("content-type", "application/json")
 }
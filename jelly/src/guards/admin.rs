@@ -0,0 +1,75 @@
+use std::task::{Context, Poll};
+
+use actix_service::{Service, Transform};
+use actix_web::body::BoxBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::{Error, HttpResponse};
+use futures::future::{ok, Either, Ready};
+
+use crate::error::render;
+use crate::request::Authentication;
+
+/// A guard that only lets admins through. Anyone else gets a 404, so it
+/// doesn't give away what's there; wrap it in `Auth` first if signed out
+/// users should be sent to log in instead.
+#[derive(Debug)]
+pub struct Admin;
+
+impl<S> Transform<S, ServiceRequest> for Admin
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<BoxBody>, Error = Error>,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = AdminMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(AdminMiddleware { service })
+    }
+}
+
+/// Middleware for checking that the user is an admin. You generally don't
+/// need this type, but it needs to be exported for compiler reasons.
+pub struct AdminMiddleware<S> {
+    /// The service provided.
+    service: S,
+}
+
+impl<S> Service<ServiceRequest> for AdminMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<BoxBody>, Error = Error>,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = Either<S::Future, Ready<Result<Self::Response, Self::Error>>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let (request, payload) = req.into_parts();
+
+        match request.user() {
+            Ok(user) if !user.is_anonymous && user.is_admin => {
+                let req = ServiceRequest::from_parts(request, payload);
+                Either::Left(self.service.call(req))
+            }
+
+            Ok(_) => Either::Right(ok(ServiceResponse::new(
+                request,
+                HttpResponse::NotFound().finish(),
+            ))),
+
+            Err(e) => Either::Right(ok(ServiceResponse::new(
+                request,
+                HttpResponse::InternalServerError()
+                    .body(render(e))
+            ))),
+        }
+    }
+}
@@ -1,16 +1,32 @@
+use std::rc::Rc;
 use std::task::{Context, Poll};
 
 use actix_service::{Service, Transform};
+use actix_session::SessionExt;
 use actix_web::body::BoxBody;
 use actix_web::dev::{ServiceRequest, ServiceResponse};
 use actix_web::http::header::LOCATION;
 use actix_web::{Error, HttpResponse};
-use futures::future::{ok, Either, Ready};
+use futures::future::{ok, LocalBoxFuture, Ready};
 
+use crate::accounts::{jwt, password, sessions};
 use crate::error::render;
 use crate::request::Authentication;
 
 /// A guard that enables route and scope authentication gating.
+///
+/// Sessions are checked against their server-side record (see
+/// `accounts::sessions`), so one that's been revoked, or whose account has
+/// been deactivated or deleted, is signed out - not just in the browser
+/// that did it.
+///
+/// Users whose password has expired (see `accounts::password::max_age`),
+/// or whose account has been flagged to reset it, are redirected to change
+/// it instead.
+///
+/// Requests with a valid bearer token (when the server accepts them; see
+/// `accounts::jwt`) are let through as they are, since there's no session
+/// to check.
 #[derive(Debug)]
 pub struct Auth {
     /// Where to redirect the user to if they fail an
@@ -20,7 +36,7 @@ pub struct Auth {
 
 impl<S> Transform<S, ServiceRequest> for Auth
 where
-    S: Service<ServiceRequest, Response = ServiceResponse<BoxBody>, Error = Error>,
+    S: Service<ServiceRequest, Response = ServiceResponse<BoxBody>, Error = Error> + 'static,
     S::Future: 'static,
 {
     type Response = ServiceResponse<BoxBody>;
@@ -31,7 +47,7 @@ where
 
     fn new_transform(&self, service: S) -> Self::Future {
         ok(AuthMiddleware {
-            service,
+            service: Rc::new(service),
             redirect_to: self.redirect_to,
         })
     }
@@ -45,45 +61,71 @@ pub struct AuthMiddleware<S> {
     redirect_to: &'static str,
 
     /// The service provided.
-    service: S,
+    service: Rc<S>,
 }
 
 impl<S> Service<ServiceRequest> for AuthMiddleware<S>
 where
-    S: Service<ServiceRequest, Response = ServiceResponse<BoxBody>, Error = Error>,
+    S: Service<ServiceRequest, Response = ServiceResponse<BoxBody>, Error = Error> + 'static,
     S::Future: 'static,
 {
     type Response = ServiceResponse<BoxBody>;
     type Error = Error;
-    type Future = Either<S::Future, Ready<Result<Self::Response, Self::Error>>>;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
 
     fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
         self.service.poll_ready(cx)
     }
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
-        let (request, payload) = req.into_parts();
+        let service = self.service.clone();
+        let redirect_to = self.redirect_to;
 
-        let status = request.is_authenticated();
+        Box::pin(async move {
+            let (request, payload) = req.into_parts();
 
-        match status {
-            Ok(v) if v => {
+            if jwt::bearer_user(&request).is_some() {
                 let req = ServiceRequest::from_parts(request, payload);
-                Either::Left(self.service.call(req))
+                return service.call(req).await;
             }
 
-            Ok(_) => Either::Right(ok(ServiceResponse::new(
-                request,
-                HttpResponse::Found()
-                    .append_header((LOCATION, self.redirect_to))
-                    .finish()
-            ))),
-
-            Err(e) => Either::Right(ok(ServiceResponse::new(
-                request,
-                HttpResponse::InternalServerError()
-                    .body(render(e))
-            ))),
-        }
+            let status = match request.user() {
+                Ok(user) if !user.is_anonymous => sessions::check(&request, &user).await,
+                Ok(_) => Ok(false),
+                Err(e) => Err(e),
+            };
+
+            match status {
+                Ok(true) if request.password_expired().unwrap_or(false) && request.path() != password::CHANGE_PATH => {
+                    Ok(ServiceResponse::new(
+                        request,
+                        HttpResponse::Found()
+                            .append_header((LOCATION, password::CHANGE_PATH))
+                            .finish()
+                    ))
+                }
+
+                Ok(true) => {
+                    let req = ServiceRequest::from_parts(request, payload);
+                    service.call(req).await
+                }
+
+                Ok(false) => {
+                    request.get_session().purge();
+                    Ok(ServiceResponse::new(
+                        request,
+                        HttpResponse::Found()
+                            .append_header((LOCATION, redirect_to))
+                            .finish()
+                    ))
+                }
+
+                Err(e) => Ok(ServiceResponse::new(
+                    request,
+                    HttpResponse::InternalServerError()
+                        .body(render(e))
+                )),
+            }
+        })
     }
 }
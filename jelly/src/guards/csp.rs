@@ -0,0 +1,106 @@
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+use actix_service::{Service, Transform};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderValue, CONTENT_SECURITY_POLICY};
+use actix_web::{Error, HttpMessage};
+use futures::future::{ok, LocalBoxFuture, Ready};
+use rand::{thread_rng, Rng};
+
+/// The policy used when `CONTENT_SECURITY_POLICY` isn't set. `{nonce}` is
+/// replaced with the per-request nonce. Images load from any https host,
+/// since avatar URLs users set on their profiles can point anywhere.
+pub const DEFAULT_POLICY: &str = "default-src 'self'; \
+    script-src 'self' 'nonce-{nonce}'; \
+    style-src 'self' 'unsafe-inline'; \
+    img-src 'self' data: https:; \
+    object-src 'none'; \
+    base-uri 'self'";
+
+/// The nonce generated for the current request. Templates get it as
+/// `csp_nonce`, e.g `<script nonce="{{ csp_nonce }}">`.
+#[derive(Clone, Debug)]
+pub struct CspNonce(pub String);
+
+impl CspNonce {
+    fn generate() -> Self {
+        let bytes: [u8; 16] = thread_rng().gen();
+        CspNonce(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+    }
+}
+
+/// Middleware that generates a nonce for every request, and sends a
+/// `Content-Security-Policy` header allowing scripts tagged with it.
+#[derive(Clone, Debug)]
+pub struct ContentSecurityPolicy {
+    /// The policy to send; any `{nonce}` in it is replaced with the nonce.
+    pub policy: String,
+}
+
+impl Default for ContentSecurityPolicy {
+    fn default() -> Self {
+        ContentSecurityPolicy {
+            policy: DEFAULT_POLICY.to_string(),
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for ContentSecurityPolicy
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = ContentSecurityPolicyMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(ContentSecurityPolicyMiddleware {
+            service: Rc::new(service),
+            policy: Rc::new(self.policy.clone()),
+        })
+    }
+}
+
+/// The middleware for `ContentSecurityPolicy`. You generally don't need this
+/// type, but it needs to be exported for compiler reasons.
+pub struct ContentSecurityPolicyMiddleware<S> {
+    service: Rc<S>,
+    policy: Rc<String>,
+}
+
+impl<S, B> Service<ServiceRequest> for ContentSecurityPolicyMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let nonce = CspNonce::generate();
+        let header = self.policy.replace("{nonce}", &nonce.0);
+        req.extensions_mut().insert(nonce);
+
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let mut res = fut.await?;
+            if let Ok(value) = HeaderValue::from_str(&header) {
+                res.headers_mut().insert(CONTENT_SECURITY_POLICY, value);
+            } else {
+                error!("Invalid Content-Security-Policy: {}", header);
+            }
+            Ok(res)
+        })
+    }
+}
@@ -0,0 +1,213 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+use actix_service::{Service, Transform};
+use actix_session::SessionExt;
+use actix_web::body::BoxBody;
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::error::PayloadError;
+use actix_web::http::header::CONTENT_TYPE;
+use actix_web::http::Method;
+use actix_web::web::Bytes;
+use actix_web::{Error, HttpRequest, HttpResponse};
+use constant_time_eq::constant_time_eq;
+use futures::future::{ok, LocalBoxFuture, Ready};
+use rand::{thread_rng, Rng};
+use tera::{Tera, Value};
+
+use crate::accounts::jwt;
+use crate::SESSION_CSRF;
+
+/// The form field the token is read from.
+pub const FIELD: &str = "csrf_token";
+
+/// The header the token is read from, for requests that aren't forms,
+/// e.g `hx-headers='{"X-CSRF-Token": "{{ csrf_token() }}"}'`.
+pub const HEADER: &str = "x-csrf-token";
+
+/// The session's token, generated the first time it's asked for.
+pub fn token(request: &HttpRequest) -> Result<String, crate::error::Error> {
+    let session = request.get_session();
+    if let Some(token) = session.get::<String>(SESSION_CSRF)? {
+        return Ok(token);
+    }
+
+    let bytes: [u8; 32] = thread_rng().gen();
+    let token: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    session.insert(SESSION_CSRF, &token)?;
+    Ok(token)
+}
+
+/// Whether `submitted` is the session's token.
+pub fn verify(request: &HttpRequest, submitted: &str) -> bool {
+    match request.get_session().get::<String>(SESSION_CSRF) {
+        Ok(Some(token)) => constant_time_eq(token.as_bytes(), submitted.as_bytes()),
+        _ => false,
+    }
+}
+
+/// The `csrf_token` field of a urlencoded form body. Tokens are hex, so
+/// there's nothing to decode.
+pub fn form_token(body: &[u8]) -> Option<&str> {
+    std::str::from_utf8(body)
+        .ok()?
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(name, _)| *name == FIELD)
+        .map(|(_, value)| value)
+}
+
+/// Whether a request of `content_type` could be sent cross-site by a plain
+/// HTML form, without a CORS preflight. Only those need a token.
+fn is_simple(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or("").trim().to_lowercase();
+    matches!(
+        mime.as_str(),
+        "" | "application/x-www-form-urlencoded" | "multipart/form-data" | "text/plain"
+    )
+}
+
+#[cfg(feature = "oauth")]
+fn is_oauth_callback(path: &str) -> bool {
+    path == crate::oauth::client::CALLBACK_PATH
+}
+
+#[cfg(not(feature = "oauth"))]
+fn is_oauth_callback(_path: &str) -> bool {
+    false
+}
+
+thread_local! {
+    /// The request whose page is being rendered, for `csrf_token()`.
+    static RENDERING: RefCell<Option<HttpRequest>> = RefCell::new(None);
+}
+
+/// Runs `render` with `request` available to the `csrf_token()` function.
+pub(crate) fn rendering<T>(request: &HttpRequest, render: impl FnOnce() -> T) -> T {
+    let previous = RENDERING.with(|current| current.replace(Some(request.clone())));
+    let result = render();
+    RENDERING.with(|current| *current.borrow_mut() = previous);
+    result
+}
+
+/// Registers the `csrf_token` function on a Tera instance. It only works in
+/// pages rendered with `request.render`:
+///
+/// ```html
+/// <input type="hidden" name="csrf_token" value="{{ csrf_token() }}">
+/// ```
+pub fn register(tera: &mut Tera) {
+    tera.register_function("csrf_token", |_: &HashMap<String, Value>| {
+        RENDERING.with(|current| match &*current.borrow() {
+            Some(request) => token(request)
+                .map(Value::String)
+                .map_err(|e| tera::Error::msg(format!("csrf_token: {:?}", e))),
+            None => Err(tera::Error::msg("csrf_token: only available when rendering a request")),
+        })
+    });
+}
+
+/// Middleware that turns away form submissions without the session's CSRF
+/// token, either as a `csrf_token` field or an `X-CSRF-Token` header.
+///
+/// Safe methods (`GET`, `HEAD`, `OPTIONS`) aren't checked, and neither are
+/// requests other sites can't forge: ones with a bearer token (see
+/// `accounts::jwt`), or a body a plain form can't send, like JSON. Multipart
+/// forms have to use the header, as their bodies aren't read. With `oauth`,
+/// providers posting back to `oauth::client::CALLBACK_PATH` are let through
+/// too, as the callback's `state` is checked instead.
+///
+/// It needs the session, so it has to sit inside `SessionMiddleware`.
+#[derive(Clone, Debug, Default)]
+pub struct Csrf;
+
+impl<S> Transform<S, ServiceRequest> for Csrf
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<BoxBody>, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = CsrfMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(CsrfMiddleware {
+            service: Rc::new(service),
+        })
+    }
+}
+
+/// The middleware for `Csrf`. You generally don't need this type, but it
+/// needs to be exported for compiler reasons.
+pub struct CsrfMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S> Service<ServiceRequest> for CsrfMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<BoxBody>, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            let content_type = req
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or("")
+                .to_string();
+
+            let exempt = matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS)
+                || !is_simple(&content_type)
+                || is_oauth_callback(req.path())
+                || jwt::bearer_user(req.request()).is_some();
+            if exempt {
+                return service.call(req).await;
+            }
+
+            let header = req
+                .headers()
+                .get(HEADER)
+                .and_then(|value| value.to_str().ok())
+                .map(String::from);
+
+            let valid = match header {
+                Some(token) => verify(req.request(), &token),
+                None if content_type.starts_with("application/x-www-form-urlencoded") => {
+                    // The form's read here, so put it back for the handler.
+                    let body = req.extract::<Bytes>().await?;
+                    let valid = form_token(&body).map_or(false, |token| verify(req.request(), token));
+                    let stream = futures::stream::once(async move { Ok::<_, PayloadError>(body) });
+                    req.set_payload(Payload::Stream {
+                        payload: Box::pin(stream),
+                    });
+                    valid
+                }
+                None => false,
+            };
+
+            if !valid {
+                warn!("Rejected {} {} without a valid CSRF token", req.method(), req.path());
+                return Ok(req.into_response(
+                    HttpResponse::Forbidden().body("Invalid or missing CSRF token; please go back, reload the page and try again."),
+                ));
+            }
+
+            service.call(req).await
+        })
+    }
+}
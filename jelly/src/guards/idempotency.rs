@@ -0,0 +1,188 @@
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+use actix_service::{Service, Transform};
+use actix_web::body::{to_bytes, BoxBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::error::{ErrorInternalServerError, PayloadError};
+use actix_web::http::header::CONTENT_TYPE;
+use actix_web::http::{Method, StatusCode};
+use actix_web::web::Bytes;
+use actix_web::{Error, HttpResponse};
+use futures::future::{ok, LocalBoxFuture, Ready};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::error::render;
+use crate::idempotency::{self, Begin, Saved};
+use crate::request::Authentication;
+
+/// The header clients send keys in.
+pub const HEADER: &str = "idempotency-key";
+
+/// Sent (as `true`) on replayed responses.
+pub const REPLAYED_HEADER: &str = "idempotent-replayed";
+
+/// The longest key accepted.
+const MAX_KEY_LENGTH: usize = 255;
+
+/// A guard that honours `Idempotency-Key` headers on mutations (`POST`,
+/// `PUT`, `PATCH` and `DELETE`) by signed in users: the first response to
+/// each key is saved (see `idempotency`), and retries get it again, with
+/// `Idempotent-Replayed: true`, rather than being run twice.
+///
+/// Keys belong to the user, and to the request they were first sent with;
+/// reusing one for a different request is a 422, and retrying before the
+/// first request has finished is a 409. Server errors (and 429s) aren't
+/// saved, so those can be retried with the same key.
+#[derive(Clone, Debug, Default)]
+pub struct Idempotency;
+
+impl<S> Transform<S, ServiceRequest> for Idempotency
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<BoxBody>, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = IdempotencyMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(IdempotencyMiddleware {
+            service: Rc::new(service),
+        })
+    }
+}
+
+/// The middleware for `Idempotency`. You generally don't need this type, but
+/// it needs to be exported for compiler reasons.
+pub struct IdempotencyMiddleware<S> {
+    service: Rc<S>,
+}
+
+/// A JSON error, like the rest of the API's.
+fn error(status: StatusCode, code: &str, message: &str) -> HttpResponse {
+    HttpResponse::build(status).json(json!({ "error": code, "message": message }))
+}
+
+fn replay(saved: Saved) -> HttpResponse {
+    let mut response = HttpResponse::build(StatusCode::from_u16(saved.status).unwrap_or(StatusCode::OK));
+    response.insert_header((REPLAYED_HEADER, "true"));
+    if let Some(content_type) = saved.content_type {
+        response.insert_header((CONTENT_TYPE, content_type));
+    }
+    response.body(saved.body)
+}
+
+/// Identifies a request, so a key can't be replayed for a different one.
+fn fingerprint(method: &Method, uri: &str, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(method.as_str().as_bytes());
+    hasher.update(b"\n");
+    hasher.update(uri.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(body);
+    format!("{:x}", hasher.finalize())
+}
+
+impl<S> Service<ServiceRequest> for IdempotencyMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<BoxBody>, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            let is_mutation = matches!(*req.method(), Method::POST | Method::PUT | Method::PATCH | Method::DELETE);
+            let header = req
+                .headers()
+                .get(HEADER)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.trim().to_string());
+            let user = req.request().user().ok().filter(|user| !user.is_anonymous);
+
+            let (header, user) = match (header, user) {
+                (Some(header), Some(user)) if is_mutation => (header, user),
+                _ => return service.call(req).await,
+            };
+            if header.is_empty() || header.len() > MAX_KEY_LENGTH {
+                let message = format!("Idempotency-Key must be 1 to {} characters.", MAX_KEY_LENGTH);
+                return Ok(req.into_response(error(StatusCode::BAD_REQUEST, "invalid_idempotency_key", &message)));
+            }
+            let key = format!("{}:{}", user.id, header);
+
+            // The body's read to fingerprint it, so put it back for the handler.
+            let body = req.extract::<Bytes>().await?;
+            let fingerprint = fingerprint(req.method(), &req.uri().to_string(), &body);
+            let stream = futures::stream::once(async move { Ok::<_, PayloadError>(body) });
+            req.set_payload(Payload::Stream {
+                payload: Box::pin(stream),
+            });
+
+            match idempotency::begin(&key, &fingerprint).await {
+                Ok(Begin::Started) => {}
+                Ok(Begin::Replay(saved)) => return Ok(req.into_response(replay(saved))),
+                Ok(Begin::InProgress) => {
+                    return Ok(req.into_response(error(
+                        StatusCode::CONFLICT,
+                        "idempotency_key_in_use",
+                        "A request with this Idempotency-Key is still being processed.",
+                    )))
+                }
+                Ok(Begin::Mismatch) => {
+                    return Ok(req.into_response(error(
+                        StatusCode::UNPROCESSABLE_ENTITY,
+                        "idempotency_key_reused",
+                        "This Idempotency-Key was already used for a different request.",
+                    )))
+                }
+                Err(e) => {
+                    return Ok(req.into_response(HttpResponse::InternalServerError().body(render(e))));
+                }
+            }
+
+            let response = match service.call(req).await {
+                Ok(response) => response,
+                Err(e) => {
+                    idempotency::abandon(&key).await?;
+                    return Err(e);
+                }
+            };
+
+            let status = response.status();
+            if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+                idempotency::abandon(&key).await?;
+                return Ok(response);
+            }
+
+            let (request, response) = response.into_parts();
+            let content_type = response
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .map(String::from);
+            let (response, body) = response.into_parts();
+            let body = to_bytes(body).await.map_err(ErrorInternalServerError)?;
+
+            let saved = Saved {
+                status: status.as_u16(),
+                content_type,
+                body: body.to_vec(),
+            };
+            idempotency::complete(&key, &saved).await?;
+
+            Ok(ServiceResponse::new(request, response.set_body(BoxBody::new(body))))
+        })
+    }
+}
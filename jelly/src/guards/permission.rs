@@ -0,0 +1,91 @@
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+use actix_service::{Service, Transform};
+use actix_web::body::BoxBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::{Error, HttpResponse};
+use futures::future::{ok, LocalBoxFuture, Ready};
+
+use crate::error::render;
+use crate::request::Permissions;
+
+/// A guard that only lets through users with some permission in `group`
+/// (see `accounts::permissions`), e.g `accounts.` for the account admin;
+/// the views behind it check the particular ones they need. Anyone else
+/// gets a 404, like with `Admin`, and it needs `Auth` wrapped around it
+/// just the same.
+#[derive(Debug)]
+pub struct Permission {
+    pub group: &'static str,
+}
+
+impl<S> Transform<S, ServiceRequest> for Permission
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<BoxBody>, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = PermissionMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(PermissionMiddleware {
+            service: Rc::new(service),
+            group: self.group,
+        })
+    }
+}
+
+/// Middleware for checking the user's permissions. You generally don't
+/// need this type, but it needs to be exported for compiler reasons.
+pub struct PermissionMiddleware<S> {
+    /// The group of permissions to look for.
+    group: &'static str,
+
+    /// The service provided.
+    service: Rc<S>,
+}
+
+impl<S> Service<ServiceRequest> for PermissionMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<BoxBody>, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let group = self.group;
+
+        Box::pin(async move {
+            let (request, payload) = req.into_parts();
+
+            match request.has_any_permission(group).await {
+                Ok(true) => {
+                    let req = ServiceRequest::from_parts(request, payload);
+                    service.call(req).await
+                }
+
+                Ok(false) => Ok(ServiceResponse::new(
+                    request,
+                    HttpResponse::NotFound().finish(),
+                )),
+
+                Err(e) => Ok(ServiceResponse::new(
+                    request,
+                    HttpResponse::InternalServerError()
+                        .body(render(e))
+                )),
+            }
+        })
+    }
+}
@@ -0,0 +1,144 @@
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+use actix_service::{Service, Transform};
+use actix_web::body::BoxBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue, RETRY_AFTER};
+use actix_web::{Error, HttpRequest, HttpResponse};
+use futures::future::{ok, LocalBoxFuture, Ready};
+use serde_json::json;
+
+use crate::error::render;
+use crate::ratelimit::{self, Limit, Status};
+use crate::request::Authentication;
+
+/// A guard that rate limits a scope, for APIs. Each call counts against
+/// the signed in user (session or bearer token), or the client's IP for
+/// anonymous ones, and every response says where the caller stands:
+///
+/// ```text
+/// RateLimit-Limit: 600
+/// RateLimit-Remaining: 598
+/// RateLimit-Reset: 2712
+/// ```
+///
+/// `RateLimit-Reset` is in seconds. Calls over the limit are turned away
+/// with a 429, a `Retry-After` header and a JSON body clients can back off
+/// with:
+///
+/// ```json
+/// {"error": "rate_limited", "message": "...", "retry_after": 2712}
+/// ```
+#[derive(Debug)]
+pub struct RateLimit {
+    pub limit: Limit,
+
+    /// Prefixes the rate limit keys, so scopes can be limited separately.
+    pub prefix: &'static str,
+}
+
+impl<S> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<BoxBody>, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RateLimitMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RateLimitMiddleware {
+            service: Rc::new(service),
+            limit: self.limit,
+            prefix: self.prefix,
+        })
+    }
+}
+
+/// Middleware for counting calls and adding the `RateLimit-*` headers. You
+/// generally don't need this type, but it needs to be exported for compiler
+/// reasons.
+pub struct RateLimitMiddleware<S> {
+    limit: Limit,
+    prefix: &'static str,
+
+    /// The service provided.
+    service: Rc<S>,
+}
+
+/// Who a call counts against.
+fn key(request: &HttpRequest, prefix: &str) -> String {
+    match request.user() {
+        Ok(user) if !user.is_anonymous => format!("{}:user:{}", prefix, user.id),
+        _ => format!("{}:ip:{}", prefix, ratelimit::client_ip(request)),
+    }
+}
+
+fn set_headers(headers: &mut HeaderMap, status: &Status) {
+    let mut set = |name: &'static str, value: u64| {
+        headers.insert(HeaderName::from_static(name), HeaderValue::from(value));
+    };
+    set("ratelimit-limit", u64::from(status.limit));
+    set("ratelimit-remaining", u64::from(status.remaining));
+    set("ratelimit-reset", status.reset_secs());
+}
+
+impl<S> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<BoxBody>, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let limit = self.limit;
+        let prefix = self.prefix;
+
+        Box::pin(async move {
+            let (request, payload) = req.into_parts();
+            let key = key(&request, prefix);
+
+            let status = match ratelimit::check(&key, &limit).await {
+                Ok(Some(status)) => status,
+                Ok(None) => {
+                    let req = ServiceRequest::from_parts(request, payload);
+                    return service.call(req).await;
+                }
+                Err(e) => {
+                    return Ok(ServiceResponse::new(
+                        request,
+                        HttpResponse::InternalServerError().body(render(e)),
+                    ))
+                }
+            };
+
+            if status.exceeded {
+                let retry_after = status.reset_secs();
+                let mut response = HttpResponse::TooManyRequests()
+                    .insert_header((RETRY_AFTER, retry_after))
+                    .json(json!({
+                        "error": "rate_limited",
+                        "message": "Too many requests; please try again later.",
+                        "retry_after": retry_after,
+                    }));
+                set_headers(response.headers_mut(), &status);
+                return Ok(ServiceResponse::new(request, response));
+            }
+
+            let req = ServiceRequest::from_parts(request, payload);
+            let mut response = service.call(req).await?;
+            set_headers(response.headers_mut(), &status);
+            Ok(response)
+        })
+    }
+}
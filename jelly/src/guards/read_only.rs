@@ -0,0 +1,118 @@
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+use actix_service::{Service, Transform};
+use actix_web::body::BoxBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{ACCEPT, CONTENT_TYPE, RETRY_AFTER};
+use actix_web::{Error, HttpRequest, HttpResponse};
+use futures::future::{ok, LocalBoxFuture, Ready};
+use serde_json::json;
+use sqlx::postgres::PgPool;
+
+use crate::error::render;
+use crate::read_only;
+use crate::request::Render;
+
+/// How long clients are told to wait before trying again, in seconds.
+const RETRY_AFTER_SECONDS: u32 = 60;
+
+const MESSAGE: &str = "We're in read-only mode for maintenance; please try again shortly.";
+
+/// A guard that turns away mutations (`POST`, `PUT`, `PATCH` and `DELETE`)
+/// with a 503 while read-only mode is on (see `read_only`), rendering
+/// `read_only.html` for browsers and JSON for API clients:
+///
+/// ```json
+/// {"error": "read_only", "message": "...", "retry_after": 60}
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ReadOnly;
+
+impl<S> Transform<S, ServiceRequest> for ReadOnly
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<BoxBody>, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = ReadOnlyMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(ReadOnlyMiddleware {
+            service: Rc::new(service),
+        })
+    }
+}
+
+/// The middleware for `ReadOnly`. You generally don't need this type, but
+/// it needs to be exported for compiler reasons.
+pub struct ReadOnlyMiddleware<S> {
+    service: Rc<S>,
+}
+
+fn wants_json(request: &HttpRequest) -> bool {
+    [ACCEPT, CONTENT_TYPE].iter().any(|header| {
+        request
+            .headers()
+            .get(header)
+            .and_then(|value| value.to_str().ok())
+            .map_or(false, |value| value.contains("json"))
+    })
+}
+
+fn rejection(request: &HttpRequest) -> HttpResponse {
+    let mut response = if wants_json(request) {
+        HttpResponse::ServiceUnavailable().json(json!({
+            "error": "read_only",
+            "message": MESSAGE,
+            "retry_after": RETRY_AFTER_SECONDS,
+        }))
+    } else {
+        let mut context = tera::Context::new();
+        context.insert("message", MESSAGE);
+        request
+            .render(503, "read_only.html", context)
+            .unwrap_or_else(|e| HttpResponse::ServiceUnavailable().body(render(e)))
+    };
+    if let Ok(value) = RETRY_AFTER_SECONDS.to_string().parse() {
+        response.headers_mut().insert(RETRY_AFTER, value);
+    }
+    response
+}
+
+impl<S> Service<ServiceRequest> for ReadOnlyMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<BoxBody>, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            // Read for every request (it's cached), so pages' banners
+            // (see `read_only::is_enabled`) keep up too.
+            let enabled = match req.app_data::<PgPool>() {
+                Some(pool) => read_only::enabled(pool).await,
+                None => read_only::is_enabled(),
+            };
+            if !enabled || !read_only::blocks(req.method(), req.path()) {
+                return service.call(req).await;
+            }
+
+            let (request, _) = req.into_parts();
+            let response = rejection(&request);
+            Ok(ServiceResponse::new(request, response))
+        })
+    }
+}
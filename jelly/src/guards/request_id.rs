@@ -0,0 +1,101 @@
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+use actix_service::{Service, Transform};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::{Error, HttpMessage};
+use futures::future::{ok, LocalBoxFuture, Ready};
+use rand::{thread_rng, Rng};
+
+/// The header a request's id is read from, when a proxy in front has
+/// already given it one, and sent back in.
+pub const HEADER: &str = "x-request-id";
+
+/// The id of the current request. It's logged with the request, and goes
+/// along with the jobs it queues (see `jobs::Traced`), so what they log
+/// can be traced back to it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    fn generate() -> Self {
+        let bytes: [u8; 16] = thread_rng().gen();
+        RequestId(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+    }
+
+    /// `id` from an incoming `X-Request-Id`, if it's short and plain
+    /// enough to put in logs as it is.
+    pub fn parse(id: &str) -> Option<Self> {
+        let is_plain = !id.is_empty()
+            && id.len() <= 64
+            && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.');
+        is_plain.then(|| RequestId(id.to_string()))
+    }
+}
+
+/// Middleware that gives every request a `RequestId`, keeping the one in
+/// `X-Request-Id` if it's usable and generating one otherwise, and sends
+/// it back in the response's `X-Request-Id`.
+#[derive(Clone, Debug, Default)]
+pub struct AssignRequestId;
+
+impl<S, B> Transform<S, ServiceRequest> for AssignRequestId
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = AssignRequestIdMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(AssignRequestIdMiddleware {
+            service: Rc::new(service),
+        })
+    }
+}
+
+/// The middleware for `AssignRequestId`. You generally don't need this
+/// type, but it needs to be exported for compiler reasons.
+pub struct AssignRequestIdMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for AssignRequestIdMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let id = req
+            .headers()
+            .get(HEADER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(RequestId::parse)
+            .unwrap_or_else(RequestId::generate);
+        let header = HeaderValue::from_str(&id.0).ok();
+        req.extensions_mut().insert(id);
+
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let mut res = fut.await?;
+            if let Some(value) = header {
+                res.headers_mut().insert(HeaderName::from_static(HEADER), value);
+            }
+            Ok(res)
+        })
+    }
+}
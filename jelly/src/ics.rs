@@ -0,0 +1,166 @@
+//! A small iCalendar (RFC 5545) builder, for calendar feeds people can
+//! subscribe to:
+//!
+//! ```ignore
+//! let mut calendar = Calendar::new("Example");
+//! calendar.push(Event::new("renewal-42@example.com", "Subscription renews", starts));
+//! Ok(HttpResponse::Ok().content_type(ics::CONTENT_TYPE).body(calendar.to_string()))
+//! ```
+
+use std::fmt;
+
+use chrono::{DateTime, NaiveDate, Utc};
+
+pub const CONTENT_TYPE: &str = "text/calendar; charset=utf-8";
+
+/// When an event happens: at a time, or all day.
+#[derive(Clone, Debug, PartialEq)]
+pub enum When {
+    At(DateTime<Utc>),
+    AllDay(NaiveDate),
+}
+
+impl When {
+    fn property(&self, name: &str) -> String {
+        match self {
+            When::At(at) => format!("{}:{}", name, at.format("%Y%m%dT%H%M%SZ")),
+            When::AllDay(day) => format!("{};VALUE=DATE:{}", name, day.format("%Y%m%d")),
+        }
+    }
+}
+
+/// A `VEVENT`. `uid` must be globally unique and stay the same across
+/// updates, so calendars replace the event rather than duplicating it.
+#[derive(Clone, Debug)]
+pub struct Event {
+    pub uid: String,
+    pub summary: String,
+    pub description: Option<String>,
+    pub url: Option<String>,
+    pub start: When,
+    pub end: Option<When>,
+    pub updated: DateTime<Utc>,
+}
+
+impl Event {
+    pub fn new(uid: impl Into<String>, summary: impl Into<String>, start: When) -> Self {
+        Event {
+            uid: uid.into(),
+            summary: summary.into(),
+            description: None,
+            url: None,
+            start,
+            end: None,
+            updated: Utc::now(),
+        }
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    pub fn url(mut self, url: impl Into<String>) -> Self {
+        self.url = Some(url.into());
+        self
+    }
+
+    pub fn end(mut self, end: When) -> Self {
+        self.end = Some(end);
+        self
+    }
+
+    /// When the event last changed; calendars use it to pick up edits.
+    pub fn updated(mut self, updated: DateTime<Utc>) -> Self {
+        self.updated = updated;
+        self
+    }
+
+    fn lines(&self) -> Vec<String> {
+        let mut lines = vec![
+            "BEGIN:VEVENT".to_string(),
+            format!("UID:{}", escape(&self.uid)),
+            format!("DTSTAMP:{}", self.updated.format("%Y%m%dT%H%M%SZ")),
+            self.start.property("DTSTART"),
+        ];
+        if let Some(end) = &self.end {
+            lines.push(end.property("DTEND"));
+        }
+        lines.push(format!("SUMMARY:{}", escape(&self.summary)));
+        if let Some(description) = &self.description {
+            lines.push(format!("DESCRIPTION:{}", escape(description)));
+        }
+        if let Some(url) = &self.url {
+            lines.push(format!("URL:{}", url));
+        }
+        lines.push("END:VEVENT".to_string());
+        lines
+    }
+}
+
+/// A `VCALENDAR` of events. `Display` renders the finished feed.
+#[derive(Clone, Debug)]
+pub struct Calendar {
+    pub name: String,
+    pub events: Vec<Event>,
+}
+
+impl Calendar {
+    pub fn new(name: impl Into<String>) -> Self {
+        Calendar {
+            name: name.into(),
+            events: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, event: Event) -> &mut Self {
+        self.events.push(event);
+        self
+    }
+}
+
+impl fmt::Display for Calendar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut lines = vec![
+            "BEGIN:VCALENDAR".to_string(),
+            "VERSION:2.0".to_string(),
+            format!("PRODID:-//{}//EN", escape(&self.name)),
+            "CALSCALE:GREGORIAN".to_string(),
+            format!("X-WR-CALNAME:{}", escape(&self.name)),
+        ];
+        for event in self.events.iter() {
+            lines.extend(event.lines());
+        }
+        lines.push("END:VCALENDAR".to_string());
+
+        for line in lines {
+            write!(f, "{}\r\n", fold(&line))?;
+        }
+        Ok(())
+    }
+}
+
+/// Escapes a TEXT value.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+/// Folds a content line to 75 octets, without splitting characters.
+fn fold(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len());
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            width = 1;
+        }
+        folded.push(c);
+        width += c.len_utf8();
+    }
+    folded
+}
@@ -0,0 +1,223 @@
+//! Idempotency keys, so clients can safely retry mutations: the first
+//! response to a request with an `Idempotency-Key` header is saved, and
+//! retries with the same key get it again instead of being run twice. See
+//! `guards::Idempotency`, which does this for a whole scope.
+//!
+//! Keys are remembered for `IDEMPOTENCY_KEY_HOURS` (24 by default). They
+//! live in memory unless `set_store(PgStore { .. })` is called at startup,
+//! which keeps them in the `idempotency_keys` table, shared between servers.
+
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgPool;
+
+use crate::error::Error;
+
+/// A response saved for replaying.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Saved {
+    pub status: u16,
+    pub content_type: Option<String>,
+    pub body: Vec<u8>,
+}
+
+/// What to do with a request, going by its key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Begin {
+    /// The key's new; run the request, then `complete` (or `abandon`) it.
+    Started,
+    /// The first request with the key hasn't finished yet.
+    InProgress,
+    /// The key was used for a different request.
+    Mismatch,
+    /// The key's been used for this request already; send this again.
+    Replay(Saved),
+}
+
+/// Where keys and their responses are kept.
+#[async_trait]
+pub trait Store: Send + Sync {
+    /// Claims `key` for a request with `fingerprint`, unless it's been
+    /// claimed in the last `ttl`.
+    async fn begin(&self, key: &str, fingerprint: &str, ttl: Duration) -> Result<Begin, Error>;
+
+    /// Saves the response to `key`'s request.
+    async fn complete(&self, key: &str, response: &Saved) -> Result<(), Error>;
+
+    /// Forgets `key`, so the request can be tried again.
+    async fn abandon(&self, key: &str) -> Result<(), Error>;
+}
+
+struct Entry {
+    fingerprint: String,
+    started: Instant,
+    response: Option<Saved>,
+}
+
+/// Keeps keys in this process.
+#[derive(Default)]
+pub struct MemoryStore {
+    keys: Mutex<HashMap<String, Entry>>,
+}
+
+#[async_trait]
+impl Store for MemoryStore {
+    async fn begin(&self, key: &str, fingerprint: &str, ttl: Duration) -> Result<Begin, Error> {
+        let mut keys = self.keys.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        keys.retain(|_, entry| now.duration_since(entry.started) < ttl);
+
+        let entry = match keys.get(key) {
+            Some(entry) => entry,
+            None => {
+                keys.insert(
+                    key.to_string(),
+                    Entry {
+                        fingerprint: fingerprint.to_string(),
+                        started: now,
+                        response: None,
+                    },
+                );
+                return Ok(Begin::Started);
+            }
+        };
+
+        Ok(if entry.fingerprint != fingerprint {
+            Begin::Mismatch
+        } else {
+            match &entry.response {
+                Some(response) => Begin::Replay(response.clone()),
+                None => Begin::InProgress,
+            }
+        })
+    }
+
+    async fn complete(&self, key: &str, response: &Saved) -> Result<(), Error> {
+        if let Some(entry) = self.keys.lock().unwrap_or_else(|e| e.into_inner()).get_mut(key) {
+            entry.response = Some(response.clone());
+        }
+        Ok(())
+    }
+
+    async fn abandon(&self, key: &str) -> Result<(), Error> {
+        self.keys.lock().unwrap_or_else(|e| e.into_inner()).remove(key);
+        Ok(())
+    }
+}
+
+/// Keeps keys in the `idempotency_keys` table, shared between servers.
+#[derive(Clone, Debug)]
+pub struct PgStore {
+    pub pool: PgPool,
+}
+
+#[async_trait]
+impl Store for PgStore {
+    async fn begin(&self, key: &str, fingerprint: &str, ttl: Duration) -> Result<Begin, Error> {
+        sqlx::query("DELETE FROM idempotency_keys WHERE key = $1 AND created <= now() - make_interval(secs => $2)")
+            .bind(key)
+            .bind(ttl.as_secs_f64())
+            .execute(&self.pool)
+            .await?;
+
+        let claimed: Option<String> = sqlx::query_scalar(
+            "
+            INSERT INTO idempotency_keys (key, fingerprint) VALUES ($1, $2)
+            ON CONFLICT (key) DO NOTHING
+            RETURNING key
+        ",
+        )
+        .bind(key)
+        .bind(fingerprint)
+        .fetch_optional(&self.pool)
+        .await?;
+        if claimed.is_some() {
+            return Ok(Begin::Started);
+        }
+
+        let existing: Option<(String, Option<i16>, Option<String>, Option<Vec<u8>>)> = sqlx::query_as(
+            "SELECT fingerprint, status, content_type, body FROM idempotency_keys WHERE key = $1",
+        )
+        .bind(key)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(match existing {
+            // Finished and cleared in between; let the caller retry.
+            None => Begin::InProgress,
+            Some((existing, _, _, _)) if existing != fingerprint => Begin::Mismatch,
+            Some((_, Some(status), content_type, body)) => Begin::Replay(Saved {
+                status: status as u16,
+                content_type,
+                body: body.unwrap_or_default(),
+            }),
+            Some(_) => Begin::InProgress,
+        })
+    }
+
+    async fn complete(&self, key: &str, response: &Saved) -> Result<(), Error> {
+        sqlx::query("UPDATE idempotency_keys SET status = $2, content_type = $3, body = $4 WHERE key = $1")
+            .bind(key)
+            .bind(response.status as i16)
+            .bind(&response.content_type)
+            .bind(&response.body)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn abandon(&self, key: &str) -> Result<(), Error> {
+        sqlx::query("DELETE FROM idempotency_keys WHERE key = $1")
+            .bind(key)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
+
+lazy_static! {
+    static ref STORE: RwLock<Arc<dyn Store>> = RwLock::new(Arc::new(MemoryStore::default()));
+}
+
+/// Replaces the `Store`; call once at startup.
+pub fn set_store<S: Store + 'static>(store: S) {
+    match STORE.write() {
+        Ok(mut current) => *current = Arc::new(store),
+        Err(e) => error!("Unable to set idempotency key store: {:?}", e),
+    }
+}
+
+fn store() -> Arc<dyn Store> {
+    STORE.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// How long keys are remembered.
+pub fn ttl() -> Duration {
+    let hours = env::var("IDEMPOTENCY_KEY_HOURS")
+        .ok()
+        .and_then(|hours| hours.parse().ok())
+        .filter(|hours| *hours > 0)
+        .unwrap_or(24);
+    Duration::from_secs(hours * 60 * 60)
+}
+
+/// Claims `key` for a request with `fingerprint`; see `Begin`.
+pub async fn begin(key: &str, fingerprint: &str) -> Result<Begin, Error> {
+    store().begin(key, fingerprint, ttl()).await
+}
+
+/// Saves the response to `key`'s request, for replaying.
+pub async fn complete(key: &str, response: &Saved) -> Result<(), Error> {
+    store().complete(key, response).await
+}
+
+/// Forgets `key`, e.g when its request failed and should be tried again.
+pub async fn abandon(key: &str) -> Result<(), Error> {
+    store().abandon(key).await
+}
@@ -4,11 +4,21 @@ use sqlx::postgres::PgPool;
 use std::sync::{Arc, RwLock};
 use tera::Tera;
 
+pub mod cron;
+pub mod traced;
+
 pub use background_jobs_actix::Unmanaged;
-pub use background_jobs::{Job, WorkerConfig};
+pub use background_jobs::{Job, QueueHandle, WorkerConfig};
+pub use traced::{Correlation, Traced};
 
 pub const DEFAULT_QUEUE: &str = "default";
 
+/// High priority queue, for emails users are waiting on.
+pub const TRANSACTIONAL_QUEUE: &str = "transactional";
+
+/// Low priority, throttled queue for newsletters and the like.
+pub const BULK_QUEUE: &str = "bulk";
+
 /// This type can be used to indicate what environment a job is running in,
 /// as well as gaining access to a database connection and to template engine.
 #[derive(Clone)]
@@ -0,0 +1,130 @@
+//! Jobs run on a schedule. A job that implements `Cron` says when it runs,
+//! and is registered with `Server::register_cron`:
+//!
+//! ```ignore
+//! #[derive(Debug, Default, Serialize, Deserialize)]
+//! pub struct ExpireOAuthFlows;
+//!
+//! impl Cron for ExpireOAuthFlows {
+//!     const SCHEDULE: &'static str = "0 * * * * *";
+//! }
+//!
+//! Server::new().register_cron::<ExpireOAuthFlows>()
+//! ```
+//!
+//! When it's due, the job is queued like any other, so it gets the queue's
+//! retries and logging. Every worker (and every server) keeps the schedule,
+//! but each run is claimed in the `cron_runs` table first, so only one of
+//! them queues it; the table doubles as a record of what ran when. Runs
+//! due while read-only mode is on (see `read_only`) are skipped.
+
+use std::str::FromStr;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use cron::Schedule;
+use futures::future::LocalBoxFuture;
+use sqlx::postgres::PgPool;
+
+use super::{Job, JobState, QueueHandle};
+use crate::error::Error;
+use crate::read_only;
+
+/// A job that runs on a schedule.
+pub trait Cron: Job<State = JobState> + Default + Send {
+    /// When it runs, as a cron expression with seconds (and optionally
+    /// years): `"0 0 * * * *"` is hourly, on the hour.
+    const SCHEDULE: &'static str;
+}
+
+/// How a registered job is queued.
+type Enqueue = Arc<dyn Fn(QueueHandle) -> LocalBoxFuture<'static, Result<(), Error>> + Send + Sync>;
+
+/// A registered `Cron` job.
+#[derive(Clone)]
+pub struct Entry {
+    pub name: &'static str,
+    pub schedule: Schedule,
+    enqueue: Enqueue,
+}
+
+impl Entry {
+    /// Panics if `J::SCHEDULE` isn't a valid cron expression, so mistakes
+    /// show up at startup.
+    pub fn new<J: Cron>() -> Self {
+        let schedule = Schedule::from_str(J::SCHEDULE)
+            .unwrap_or_else(|e| panic!("Invalid schedule for {}: {:?}", J::NAME, e));
+        Entry {
+            name: J::NAME,
+            schedule,
+            enqueue: Arc::new(|queue: QueueHandle| {
+                Box::pin(async move {
+                    queue
+                        .queue(J::default())
+                        .await
+                        .map_err(|e| Error::Generic(format!("Error queueing {}: {:?}", J::NAME, e)))
+                })
+            }),
+        }
+    }
+
+    /// When it's next due after `after`.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.schedule.after(&after).next()
+    }
+}
+
+/// Claims the run of `name` due at `due`. Only the first to ask gets it.
+pub async fn claim(pool: &PgPool, name: &str, due: DateTime<Utc>) -> Result<bool, Error> {
+    let result = sqlx::query(
+        "
+        INSERT INTO cron_runs (name, due) VALUES ($1, to_timestamp($2))
+        ON CONFLICT (name, due) DO NOTHING
+    ",
+    )
+    .bind(name)
+    .bind(due.timestamp() as f64)
+    .execute(pool)
+    .await?;
+
+    // Keep a week, for looking back on.
+    sqlx::query("DELETE FROM cron_runs WHERE name = $1 AND due < now() - interval '7 days'")
+        .bind(name)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Keeps `entries`' schedules, queueing each on `queue` when it's due and
+/// the run's claimed. Call from within the actix runtime.
+pub fn spawn(entries: &[Entry], pool: PgPool, queue: QueueHandle) {
+    for entry in entries.iter().cloned() {
+        let pool = pool.clone();
+        let queue = queue.clone();
+        actix_rt::spawn(async move {
+            let mut after = Utc::now();
+            while let Some(due) = entry.next_after(after) {
+                if let Ok(wait) = (due - Utc::now()).to_std() {
+                    actix_rt::time::sleep(wait).await;
+                }
+                after = due;
+
+                if read_only::enabled(&pool).await {
+                    info!("Skipping {} due {}: read-only mode is on", entry.name, due);
+                    continue;
+                }
+
+                match claim(&pool, entry.name, due).await {
+                    Ok(true) => {
+                        if let Err(e) = (entry.enqueue)(queue.clone()).await {
+                            error!("{:?}", e);
+                        }
+                    }
+                    Ok(false) => {}
+                    Err(e) => error!("Error claiming {} due {}: {:?}", entry.name, due, e),
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,142 @@
+//! Jobs that remember the request that queued them. A view queues a job
+//! with `request.queue_job(job)` (see `request::JobQueue`), which sends it
+//! along with the request's `Correlation`; registering the job as
+//! `Traced<J>` has its failures logged with it:
+//!
+//! ```ignore
+//! request.queue_job(SendVerifyAccountEmail { to: uid }).await?;
+//!
+//! config.register::<Traced<SendVerifyAccountEmail>>()
+//! ```
+//!
+//! The correlation rides in the job's own payload, next to its fields, and
+//! `Traced<J>` is queued under `J`'s name, so jobs queued the plain way
+//! (say, from another job) still run, just without one.
+//!
+//! Every job is registered this way, Jelly's own included, as `Traced` is
+//! also where read-only mode (see `read_only`) is checked: while it's on,
+//! jobs are queued again `PUT_OFF_SECONDS` later instead of running, so
+//! they don't use up their retries in a long restore.
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::RwLock;
+use std::time::{Duration, SystemTime};
+
+use background_jobs::{Backoff, MaxRetries};
+use lazy_static::lazy_static;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use super::{Job, JobState, QueueHandle};
+use crate::read_only;
+
+/// How long jobs are put off by while read-only mode is on.
+pub const PUT_OFF_SECONDS: u64 = 60;
+
+lazy_static! {
+    static ref QUEUE: RwLock<Option<QueueHandle>> = RwLock::new(None);
+}
+
+/// Where jobs put off by read-only mode are queued again; set by
+/// `Server::run` once the workers have started.
+pub fn set_queue(queue: QueueHandle) {
+    *QUEUE.write().unwrap_or_else(|e| e.into_inner()) = Some(queue);
+}
+
+fn queue() -> Option<QueueHandle> {
+    QUEUE.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Where a job came from: the request that queued it, and who made it.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Correlation {
+    /// See `guards::RequestId`.
+    pub request_id: Option<String>,
+    /// The signed in account, which is also its tenant (see `tenancy`).
+    pub account_id: Option<i32>,
+}
+
+impl Correlation {
+    pub fn is_empty(&self) -> bool {
+        self.request_id.is_none() && self.account_id.is_none()
+    }
+}
+
+impl fmt::Display for Correlation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.request_id, self.account_id) {
+            (Some(request_id), Some(account_id)) => write!(f, "request {}, account {}", request_id, account_id),
+            (Some(request_id), None) => write!(f, "request {}", request_id),
+            (None, Some(account_id)) => write!(f, "account {}", account_id),
+            (None, None) => write!(f, "no request"),
+        }
+    }
+}
+
+/// `job`, with the `Correlation` of the request that queued it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Traced<J> {
+    #[serde(default, rename = "_correlation")]
+    pub correlation: Correlation,
+    #[serde(flatten)]
+    pub job: J,
+}
+
+impl<J> Traced<J> {
+    pub fn new(correlation: Correlation, job: J) -> Self {
+        Traced { correlation, job }
+    }
+}
+
+impl<J> Job for Traced<J>
+where
+    J: Job<State = JobState> + Serialize + DeserializeOwned + Send,
+    J::Future: 'static,
+{
+    type State = J::State;
+    type Future = Pin<Box<dyn Future<Output = Result<(), anyhow::Error>> + Send>>;
+
+    const NAME: &'static str = J::NAME;
+    const QUEUE: &'static str = J::QUEUE;
+    const MAX_RETRIES: MaxRetries = J::MAX_RETRIES;
+    const BACKOFF: Backoff = J::BACKOFF;
+    const TIMEOUT: i64 = J::TIMEOUT;
+
+    fn run(self, state: Self::State) -> Self::Future {
+        Box::pin(async move {
+            if read_only::enabled(&state.pool).await {
+                return put_off(self).await;
+            }
+
+            let Traced { correlation, job } = self;
+            debug!("Running {} ({})", J::NAME, correlation);
+            let result = job.run(state).await;
+            if let Err(e) = &result {
+                error!("{} failed ({}): {:?}", J::NAME, correlation, e);
+            }
+            result
+        })
+    }
+}
+
+/// Queues `traced` to run again once read-only mode may be off. Without a
+/// queue to do it on, it fails, to be retried with its backoff.
+async fn put_off<J>(traced: Traced<J>) -> Result<(), anyhow::Error>
+where
+    J: Job<State = JobState> + Serialize + DeserializeOwned + Send,
+    J::Future: 'static,
+{
+    let queue = match queue() {
+        Some(queue) => queue,
+        None => return Err(anyhow::anyhow!("{} put off ({}): read-only mode is on", J::NAME, traced.correlation)),
+    };
+
+    info!("{} put off ({}): read-only mode is on", J::NAME, traced.correlation);
+    let after = SystemTime::now() + Duration::from_secs(PUT_OFF_SECONDS);
+    queue
+        .schedule(traced, after)
+        .await
+        .map_err(|e| anyhow::anyhow!("Error putting off {}: {:?}", J::NAME, e))
+}
@@ -13,8 +13,11 @@ pub use actix_web;
 pub use anyhow;
 pub use async_trait;
 pub use chrono;
+pub use constant_time_eq;
 pub use djangohashers;
 pub use futures;
+pub use minreq;
+pub use rand;
 pub use serde;
 pub use serde_json;
 pub use sqlx;
@@ -27,14 +30,45 @@ pub use oauth2;
 pub extern crate log;
 
 pub mod accounts;
+pub mod assets;
+pub mod avatars;
+pub mod challenge;
+pub mod config;
+pub mod crypto;
 pub mod email;
 pub mod error;
+pub mod experiments;
+pub mod export;
+pub mod feed;
 pub mod forms;
+pub mod geoip;
 pub mod guards;
+pub mod ics;
+pub mod idempotency;
 pub mod jobs;
+pub mod logging;
+pub mod migrations;
 pub mod prelude;
+pub mod prerender;
+pub mod progress;
+pub mod push;
+pub mod qr;
+pub mod ratelimit;
+pub mod read_only;
+pub mod recorder;
 pub mod request;
+pub mod retention;
+pub mod routes;
+pub mod scan;
+pub mod seo;
+pub mod settings;
+pub mod sitemap;
+pub mod storage;
+pub mod tenancy;
+pub mod thumbnails;
+pub mod timezones;
 pub mod utils;
+pub mod webhooks;
 
 mod server;
 mod templates;
@@ -43,12 +77,22 @@ pub use server::{Server, ServerConfig};
 #[cfg(feature = "oauth")]
 pub mod oauth;
 
+#[cfg(feature = "pdf")]
+pub mod pdf;
+
 pub type Result<T> = std::result::Result<T, crate::error::Error>;
 
 pub const NO_PASSWORD: Option<String> = None;
 pub const SESSION_FLASH: &str = "flsh";
 pub const SESSION_USER: &str = "sku";
+pub const SESSION_ID: &str = "sid";
+pub const SESSION_REMEMBER: &str = "rmb";
+pub const SESSION_PASSWORD_EXPIRED: &str = "pwx";
+pub const SESSION_TIMEZONE: &str = "tz";
+pub const SESSION_CSRF: &str = "csrf";
+pub const SESSION_EXPERIMENT_UNIT: &str = "xpu";
+pub const SESSION_QR_PENDING: &str = "qrp";
 
-#[cfg(feature = "oauth")]
-pub const SESSION_OAUTH_FLOW: &str = "oflw";
 pub const SESSION_OAUTH_TOKEN: &str = "rfsh";
+pub const SESSION_OAUTH_SCOPES: &str = "scps";
+pub const SESSION_OAUTH_STATE: &str = "osta";
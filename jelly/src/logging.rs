@@ -0,0 +1,139 @@
+//! The log filter (as in `RUST_LOG`, e.g `info,jelly=debug,sqlx=warn`),
+//! changeable while the app's running, so problems in production can be
+//! looked into without a restart. Admins can set it from the app, and
+//! `kill -USR1` flips between `RUST_LOG` and `RUST_LOG_DEBUG` (`debug` by
+//! default). A change only applies to the process it's made in, and lasts
+//! until it restarts.
+
+use std::env;
+use std::sync::{RwLock, RwLockReadGuard};
+
+use lazy_static::lazy_static;
+use log::{LevelFilter, Log, Metadata, Record};
+
+use crate::error::Error;
+
+/// A filter, and the logger that applies it.
+struct Filtered {
+    spec: String,
+    logger: env_logger::Logger,
+}
+
+impl Filtered {
+    fn new(spec: &str) -> Self {
+        Filtered {
+            spec: spec.to_string(),
+            logger: pretty_env_logger::formatted_builder().parse_filters(spec).build(),
+        }
+    }
+}
+
+lazy_static! {
+    static ref STARTUP: String = env::var("RUST_LOG").unwrap_or_default();
+    static ref CURRENT: RwLock<Filtered> = RwLock::new(Filtered::new(&STARTUP));
+}
+
+fn current_logger() -> RwLockReadGuard<'static, Filtered> {
+    CURRENT.read().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Hands records to whichever logger the filter's currently set to.
+struct Reloadable;
+
+impl Log for Reloadable {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        current_logger().logger.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        current_logger().logger.log(record)
+    }
+
+    fn flush(&self) {
+        current_logger().logger.flush()
+    }
+}
+
+static LOGGER: Reloadable = Reloadable;
+
+/// Logs through the reloadable filter, starting with `RUST_LOG`; in place
+/// of `pretty_env_logger::init`.
+pub fn init() {
+    let max_level = current_logger().logger.filter();
+    match log::set_logger(&LOGGER) {
+        Ok(()) => log::set_max_level(max_level),
+        Err(e) => eprintln!("Logging was already set up: {}", e),
+    }
+}
+
+/// Checks `spec`'s levels, as the logger ignores directives it can't
+/// parse rather than saying so.
+pub fn validate(spec: &str) -> Result<(), String> {
+    let directives = spec.split('/').next().unwrap_or_default();
+    for directive in directives.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+        if let Some((module, level)) = directive.split_once('=') {
+            if module.trim().is_empty() {
+                return Err(format!("{:?} has no module", directive));
+            }
+            if level.trim().parse::<LevelFilter>().is_err() {
+                return Err(format!("{:?} isn't a log level", level.trim()));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The filter in effect.
+pub fn current() -> String {
+    current_logger().spec.clone()
+}
+
+/// The filter the process started with, from `RUST_LOG`.
+pub fn startup() -> &'static str {
+    &STARTUP
+}
+
+/// Filters logs with `spec` from now on.
+pub fn set(spec: &str) -> Result<(), Error> {
+    let spec = spec.trim();
+    validate(spec).map_err(Error::Generic)?;
+
+    let filtered = Filtered::new(spec);
+    let max_level = filtered.logger.filter();
+    *CURRENT.write().unwrap_or_else(|e| e.into_inner()) = filtered;
+    log::set_max_level(max_level);
+    warn!("Log filter changed to {:?}", spec);
+    Ok(())
+}
+
+/// Goes back to `RUST_LOG`.
+pub fn reset() -> Result<(), Error> {
+    set(startup())
+}
+
+/// What `SIGUSR1` does: `RUST_LOG_DEBUG` if the filter's `RUST_LOG`, and
+/// `RUST_LOG` otherwise.
+pub fn toggle_debug() {
+    let debug = env::var("RUST_LOG_DEBUG").unwrap_or_else(|_| "debug".to_string());
+    let spec = if current() == startup() { debug } else { startup().to_string() };
+    if let Err(e) = set(&spec) {
+        error!("Can't switch the log filter: {:?}", e);
+    }
+}
+
+/// Calls `toggle_debug` on every `SIGUSR1`; spawned by `Server::run`.
+#[cfg(unix)]
+pub async fn listen_for_signal() {
+    use actix_rt::signal::unix::{signal, SignalKind};
+
+    let mut signals = match signal(SignalKind::user_defined1()) {
+        Ok(signals) => signals,
+        Err(e) => {
+            warn!("Can't listen for SIGUSR1: {:?}", e);
+            return;
+        }
+    };
+    while signals.recv().await.is_some() {
+        toggle_debug();
+    }
+}
@@ -0,0 +1,207 @@
+//! A startup check that the database has the migrations the build embeds
+//! (`sqlx::migrate!()`), and only those, so a partial deploy shows up as
+//! one message saying what to run, rather than as sqlx errors about
+//! missing columns once requests come in.
+//!
+//! ```ignore
+//! static MIGRATOR: Migrator = sqlx::migrate!();
+//!
+//! migrations::check_on_startup(&config.pool, &MIGRATOR).await?;
+//! ```
+//!
+//! `MIGRATION_CHECK` says what's done about drift: `warn` (the default),
+//! `refuse` to start (the default in production builds), or `off`.
+
+use std::env;
+use std::fmt;
+
+use sqlx::migrate::{Migration, Migrator};
+use sqlx::postgres::PgPool;
+
+use crate::error::Error;
+
+/// Where `sqlx migrate run` records what it's applied.
+pub const TABLE: &str = "_sqlx_migrations";
+
+/// What's done about drift at startup.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Off,
+    Warn,
+    Refuse,
+}
+
+/// `MIGRATION_CHECK`, or the build's default.
+pub fn mode() -> Mode {
+    match env::var("MIGRATION_CHECK").unwrap_or_default().as_str() {
+        "off" => Mode::Off,
+        "warn" => Mode::Warn,
+        "refuse" => Mode::Refuse,
+        _ if cfg!(feature = "production") => Mode::Refuse,
+        _ => Mode::Warn,
+    }
+}
+
+/// A migration as the database recorded it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Applied {
+    pub version: i64,
+    pub description: String,
+    pub success: bool,
+    pub checksum: Vec<u8>,
+}
+
+/// How the database differs from the build.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Drift {
+    /// In the build, but not applied.
+    Missing { version: i64, description: String },
+    /// Applied, but it failed partway through.
+    Failed { version: i64, description: String },
+    /// Applied, but its file has changed since.
+    Modified { version: i64, description: String },
+    /// Applied, but not in the build: the database is ahead of it, e.g
+    /// after a newer deploy was rolled back.
+    Unknown { version: i64, description: String },
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Drift::Missing { version, description } => write!(f, "{} {}: not applied", version, description),
+            Drift::Failed { version, description } => write!(f, "{} {}: failed partway", version, description),
+            Drift::Modified { version, description } => {
+                write!(f, "{} {}: changed since it was applied", version, description)
+            }
+            Drift::Unknown { version, description } => {
+                write!(f, "{} {}: applied, but not in this build", version, description)
+            }
+        }
+    }
+}
+
+/// How `applied` differs from `expected`, by version.
+pub fn diff(expected: &[Migration], applied: &[Applied]) -> Vec<Drift> {
+    let expected: Vec<&Migration> = expected
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+        .collect();
+
+    let mut drift = Vec::new();
+    for migration in expected.iter() {
+        let version = migration.version;
+        let description = migration.description.to_string();
+        match applied.iter().find(|applied| applied.version == version) {
+            None => drift.push(Drift::Missing { version, description }),
+            Some(applied) if !applied.success => drift.push(Drift::Failed { version, description }),
+            Some(applied) if applied.checksum != *migration.checksum => {
+                drift.push(Drift::Modified { version, description })
+            }
+            Some(_) => {}
+        }
+    }
+    for applied in applied {
+        if !expected.iter().any(|migration| migration.version == applied.version) {
+            drift.push(Drift::Unknown {
+                version: applied.version,
+                description: applied.description.clone(),
+            });
+        }
+    }
+    drift.sort_by_key(|drift| match drift {
+        Drift::Missing { version, .. }
+        | Drift::Failed { version, .. }
+        | Drift::Modified { version, .. }
+        | Drift::Unknown { version, .. } => *version,
+    });
+    drift
+}
+
+/// What to do about `drift`, for the log.
+pub fn remediation(drift: &[Drift]) -> String {
+    let mut message = format!("The database's migrations don't match this build's ({} differ):\n", drift.len());
+    for drift in drift {
+        message.push_str(&format!("  {}\n", drift));
+    }
+
+    let has = |f: fn(&Drift) -> bool| drift.iter().any(f);
+    if has(|d| matches!(d, Drift::Missing { .. })) {
+        message.push_str("Run `sqlx migrate run` against DATABASE_URL, then restart.\n");
+    }
+    if has(|d| matches!(d, Drift::Failed { .. })) {
+        message.push_str(&format!(
+            "Fix what a failed migration left half done, delete its row from {}, and run it again.\n",
+            TABLE
+        ));
+    }
+    if has(|d| matches!(d, Drift::Modified { .. })) {
+        message.push_str("Restore the changed migrations as they were applied; changes go in a new migration.\n");
+    }
+    if has(|d| matches!(d, Drift::Unknown { .. })) {
+        message.push_str("Deploy the build those migrations came from, or revert them.\n");
+    }
+    message.push_str("MIGRATION_CHECK=warn starts anyway.");
+    message
+}
+
+/// The migrations the database has recorded, by version; none if it's
+/// never been migrated.
+pub async fn applied(pool: &PgPool) -> Result<Vec<Applied>, Error> {
+    let exists: bool = sqlx::query_scalar("SELECT to_regclass($1) IS NOT NULL")
+        .bind(TABLE)
+        .fetch_one(pool)
+        .await?;
+    if !exists {
+        return Ok(Vec::new());
+    }
+
+    let sql = format!("SELECT version, description, success, checksum FROM {} ORDER BY version", TABLE);
+    let rows: Vec<(i64, String, bool, Vec<u8>)> = sqlx::query_as(&sql).fetch_all(pool).await?;
+    Ok(rows
+        .into_iter()
+        .map(|(version, description, success, checksum)| Applied {
+            version,
+            description,
+            success,
+            checksum,
+        })
+        .collect())
+}
+
+/// How the database differs from `migrator`'s migrations.
+pub async fn check(pool: &PgPool, migrator: &Migrator) -> Result<Vec<Drift>, Error> {
+    Ok(diff(&migrator.migrations, &applied(pool).await?))
+}
+
+/// Checks for drift as `mode()` says: logging it, or with `Mode::Refuse`,
+/// returning it as an error too, so the server doesn't start. If the
+/// check itself fails, that's logged and startup carries on.
+pub async fn check_on_startup(pool: &PgPool, migrator: &Migrator) -> Result<(), Error> {
+    let mode = mode();
+    if mode == Mode::Off {
+        return Ok(());
+    }
+
+    let drift = match check(pool, migrator).await {
+        Ok(drift) => drift,
+        Err(e) => {
+            warn!("Unable to check the database's migrations: {:?}", e);
+            return Ok(());
+        }
+    };
+    if drift.is_empty() {
+        return Ok(());
+    }
+
+    let message = remediation(&drift);
+    match mode {
+        Mode::Refuse => {
+            error!("{}", message);
+            Err(Error::Generic(message))
+        }
+        _ => {
+            warn!("{}", message);
+            Ok(())
+        }
+    }
+}
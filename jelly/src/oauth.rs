@@ -1,24 +1,40 @@
 //! URL dispatcher for oauth related API endpoints.
 
+use std::future::Future;
+use std::pin::Pin;
 use std::{result, str};
 
-use oauth2::basic::{BasicClient, BasicTokenResponse};
+use oauth2::basic::{
+    BasicErrorResponse, BasicRevocationErrorResponse, BasicTokenIntrospectionResponse,
+    BasicTokenType,
+};
 use oauth2::http::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
 use oauth2::http::method::Method;
-use oauth2::reqwest::http_client;
+use oauth2::reqwest::{async_http_client, HttpClientError};
 use oauth2::{
-    url, AccessToken, AuthorizationCode, AuthorizationRequest, CsrfToken, PkceCodeChallenge,
-    PkceCodeVerifier, Scope, TokenResponse,
+    url, AccessToken, AuthorizationCode, AuthorizationRequest, ConfigurationError, CsrfToken,
+    ExtraTokenFields, PkceCodeChallenge, PkceCodeVerifier, RefreshToken, Scope,
+    StandardRevocableToken, StandardTokenResponse, TokenResponse,
 };
+use constant_time_eq::constant_time_eq;
 use serde::{Deserialize, Serialize};
 use serde_json;
 
 use crate::error::{Error, OAuthError};
-use crate::SESSION_OAUTH_TOKEN;
+use crate::{SESSION_OAUTH_SCOPES, SESSION_OAUTH_TOKEN};
 use actix_session::Session;
 
 pub mod client;
+pub mod config;
+pub mod device;
+pub mod oidc;
 
+/// An authorization in progress, from the redirect to the provider until
+/// its callback. It holds the PKCE verifier, so keep it server-side, keyed
+/// by `csrf_token_secret` (the `state` the provider sends back), rather
+/// than in the session cookie, and hand it to `verify_callback` once. Only
+/// the state goes in the session (`SESSION_OAUTH_STATE`), so the callback
+/// can tell the flow was started by the same browser.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct OAuthFlow {
     pub provider: String,
@@ -26,6 +42,10 @@ pub struct OAuthFlow {
     pub authorization_code: String,
     pub csrf_token_secret: String,
     pub pkce_verifier_secret: String,
+    /// The scopes asked for (see `requested_scopes`), for when the
+    /// provider doesn't say which it granted.
+    #[serde(default)]
+    pub scopes: Vec<String>,
 }
 
 impl OAuthFlow {
@@ -47,22 +67,104 @@ pub struct UserInfo {
 
 // Accepts the json body to be deserialized and the email the user began
 // the authorization with.
-type UserInfoDeserializer = fn(&str, &str) -> serde_json::Result<UserInfo>;
+pub type UserInfoDeserializer = fn(&str, &str) -> serde_json::Result<UserInfo>;
 
-#[derive(Clone)]
-pub struct UserInfoRequest {
+/// One endpoint the profile is fetched from.
+#[derive(Clone, Debug)]
+pub struct UserInfoEndpoint {
     pub uri: String,
     pub params: Vec<(String, String)>,
+}
+
+#[derive(Clone)]
+pub struct UserInfoRequest {
+    /// Fetched in turn, with the JSON objects they send merged for the
+    /// deserializer; most providers need just the one, but e.g LinkedIn
+    /// keeps email addresses apart from the profile.
+    pub endpoints: Vec<UserInfoEndpoint>,
     pub headers: Vec<(Vec<u8>, String)>,
     pub deserializer: UserInfoDeserializer,
+    /// Where the user is in the profile, for providers from the providers
+    /// file (see `config`); used instead of `deserializer` when set.
+    pub fields: Option<config::UserInfoFields>,
+    /// Read the profile from the token response's `id_token` instead of
+    /// asking `endpoints` for it; for providers (Apple) with no user info
+    /// endpoint.
+    pub from_id_token: bool,
+}
+
+/// The OpenID Connect `id_token` some providers send with the access
+/// token.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct IdTokenFields {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id_token: Option<String>,
+}
+
+impl ExtraTokenFields for IdTokenFields {}
+
+pub type IdTokenResponse = StandardTokenResponse<IdTokenFields, BasicTokenType>;
+
+/// `oauth2`'s `BasicClient`, but keeping the `id_token`.
+pub type OAuthClient = oauth2::Client<
+    BasicErrorResponse,
+    IdTokenResponse,
+    BasicTokenType,
+    BasicTokenIntrospectionResponse,
+    StandardRevocableToken,
+    BasicRevocationErrorResponse,
+>;
+
+/// Makes the client secret for each token request, for providers (Apple)
+/// whose secret is a short-lived signed JWT rather than a fixed string.
+pub type ClientSecretGenerator = fn() -> result::Result<String, OAuthError>;
+
+/// A request to the provider, on its way.
+pub type TransportFuture = Pin<Box<dyn Future<Output = result::Result<oauth2::HttpResponse, HttpClientError>>>>;
+
+/// Sends requests to the provider. It's `async_transport` unless swapped
+/// out with `ScopedClient::with_transport`, e.g for tests.
+pub type Transport = fn(oauth2::HttpRequest) -> TransportFuture;
+
+/// `oauth2`'s async reqwest client, so waiting on the provider doesn't
+/// hold up the worker.
+pub fn async_transport(request: oauth2::HttpRequest) -> TransportFuture {
+    Box::pin(async_http_client(request))
+}
+
+impl UserInfoRequest {
+    /// Reads the user from the profile's JSON, for the login `email`.
+    pub fn parse(&self, json_body: &str, email: &str) -> serde_json::Result<UserInfo> {
+        match &self.fields {
+            Some(fields) => fields.parse(json_body, email),
+            None => (self.deserializer)(json_body, email),
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct ScopedClient {
-    pub inner: BasicClient,
+    pub inner: OAuthClient,
     pub scopes: Vec<String>,
     pub login_hint_key: Option<String>,
+    /// Extra parameters for the authorization URL, e.g Apple's
+    /// `response_mode=form_post`.
+    pub auth_params: Vec<(String, String)>,
+    pub client_secret: Option<ClientSecretGenerator>,
     pub user_info_request: UserInfoRequest,
+    pub transport: Transport,
+    /// Where the provider's device flow is, if it has one; see `device`.
+    pub device: Option<device::DeviceEndpoint>,
+    /// Who signs the provider's `id_token`s, for those that send them, so
+    /// they're verified; see `oidc`.
+    pub id_token_issuer: Option<oidc::Issuer>,
+}
+
+impl ScopedClient {
+    pub fn with_transport(mut self, transport: Transport) -> Self {
+        self.transport = transport;
+        self
+    }
 }
 
 pub struct ClientFlow {
@@ -73,51 +175,141 @@ pub struct ClientFlow {
 pub struct TokenInfo {
     pub provider: String,
     pub email: String,
-    pub response: BasicTokenResponse,
+    pub response: IdTokenResponse,
     pub user_info_request: UserInfoRequest,
+    pub transport: Transport,
+    /// The scopes asked for.
+    pub scopes: Vec<String>,
+    /// The `id_token`'s claims, as JSON, if it sent one and it's been
+    /// verified (see `oidc`).
+    pub id_token_claims: Option<String>,
 }
 
 impl TokenInfo {
+    /// The scopes the provider granted: the token response's `scope`, or
+    /// if it hasn't one, those asked for (RFC 6749 5.1).
+    pub fn granted_scopes(&self) -> Vec<String> {
+        match self.response.scopes() {
+            Some(scopes) => scopes.iter().map(|scope| scope.as_str().to_string()).collect(),
+            None => self.scopes.clone(),
+        }
+    }
+
     pub fn parse_user_info_response(
         &self,
         response: &oauth2::HttpResponse,
     ) -> Result<UserInfo, OAuthError> {
-        let body = str::from_utf8(response.body.as_slice()).unwrap();
-        // info!("got user_info body: {}", body);
+        self.parse_user_info_responses(std::slice::from_ref(response))
+    }
 
-        let deser = self.user_info_request.deserializer;
-        deser(body, &self.email).map_err(OAuthError::DecodeProfileError)
+    /// Reads the profile from each endpoint's response, merged into one
+    /// JSON object (later responses' fields win).
+    pub fn parse_user_info_responses(
+        &self,
+        responses: &[oauth2::HttpResponse],
+    ) -> Result<UserInfo, OAuthError> {
+        let request = &self.user_info_request;
+        if let [response] = responses {
+            let body = str::from_utf8(response.body.as_slice()).unwrap();
+            // info!("got user_info body: {}", body);
+            return request.parse(body, &self.email).map_err(OAuthError::DecodeProfileError);
+        }
+
+        let mut merged = serde_json::Map::new();
+        for response in responses {
+            let value: serde_json::Value =
+                serde_json::from_slice(&response.body).map_err(OAuthError::DecodeProfileError)?;
+            if let serde_json::Value::Object(fields) = value {
+                merged.extend(fields);
+            }
+        }
+        let body = serde_json::Value::Object(merged).to_string();
+        request.parse(&body, &self.email).map_err(OAuthError::DecodeProfileError)
     }
 }
 
+/// The client's scopes, and `extra_scopes` besides, e.g for asking a
+/// signed in user for more access than signing in takes (incremental
+/// authorization). Store them with the flow.
+pub fn requested_scopes(client: &ScopedClient, extra_scopes: &[&str]) -> Vec<String> {
+    let mut scopes = client.scopes.clone();
+    for scope in extra_scopes {
+        if !scopes.iter().any(|s| s == scope) {
+            scopes.push(scope.to_string());
+        }
+    }
+    scopes
+}
+
+/// The provider's authorization URL for the client's scopes and
+/// `extra_scopes`, with a PKCE challenge, and the verifier for it. For
+/// providers whose `id_token`s are verified, it has a `nonce` too.
 pub fn pkce_authorization_request<'a>(
     client: &'a ScopedClient,
     login_hint: Option<&'a str>,
+    extra_scopes: &[&str],
 ) -> (AuthorizationRequest<'a>, PkceCodeVerifier) {
     // Google and Twitter support Proof Key for Code Exchange (PKCE - https://oauth.net/2/pkce/).
     // Create a PKCE code verifier and SHA-256 encode it as a code challenge.
     let (pkce_code_challenge, pkce_code_verifier) = PkceCodeChallenge::new_random_sha256();
 
     // Generate the authorization URL to which we'll redirect the user.
+    let state = CsrfToken::new_random();
+    let nonce = oidc::nonce(state.secret());
     let mut authorization_request = client
         .inner
-        .authorize_url(CsrfToken::new_random)
+        .authorize_url(move || state)
         .set_pkce_challenge(pkce_code_challenge);
 
+    if client.id_token_issuer.is_some() {
+        authorization_request = authorization_request.add_extra_param("nonce", nonce);
+    }
+
     // Add "login_hint=email"
     if let (Some(key), Some(email)) = (&client.login_hint_key, login_hint) {
         authorization_request = authorization_request.add_extra_param(key, email);
     }
 
-    for scope in client.scopes.as_slice() {
-        authorization_request = authorization_request.add_scope(Scope::new(scope.to_string()));
+    for scope in requested_scopes(client, extra_scopes) {
+        authorization_request = authorization_request.add_scope(Scope::new(scope));
+    }
+
+    for (key, value) in client.auth_params.iter() {
+        authorization_request = authorization_request.add_extra_param(key, value);
     }
 
     (authorization_request, pkce_code_verifier)
 }
 
-pub fn request_token(client_flow: ClientFlow) -> result::Result<TokenInfo, OAuthError> {
-    let client = client_flow
+/// Checks the provider's callback against the `flow` stored for its
+/// `state` (`None` if there wasn't one, or it expired), returning what's
+/// needed to exchange the code for a token.
+pub fn verify_callback(
+    flow: Option<OAuthFlow>,
+    state: &str,
+    code: Option<&str>,
+    error: Option<&str>,
+) -> result::Result<ClientFlow, OAuthError> {
+    let flow = match flow {
+        Some(flow) if constant_time_eq(flow.csrf_token_secret.as_bytes(), state.as_bytes()) => flow,
+        _ => return Err(OAuthError::VerifyStateError),
+    };
+
+    match (error, code) {
+        (Some(e), _) => Err(OAuthError::GrantAuthorizationError(e.to_string())),
+        (None, Some(code)) => match client::client_for(&flow.provider) {
+            Some(client) => Ok(ClientFlow {
+                client,
+                flow: flow.set_authorization_code(code),
+            }),
+            _ => Err(OAuthError::ParseSessionError),
+        },
+        (None, None) => Err(OAuthError::ParseRequestError),
+    }
+}
+
+pub async fn request_token(client_flow: ClientFlow) -> result::Result<TokenInfo, OAuthError> {
+    let mut client = client_flow
         .client
         .inner
         .exchange_code(AuthorizationCode::new(
@@ -127,18 +319,42 @@ pub fn request_token(client_flow: ClientFlow) -> result::Result<TokenInfo, OAuth
             client_flow.flow.pkce_verifier_secret.clone(),
         ));
 
-    client
-        .request(http_client)
-        .map(move |response| TokenInfo {
-            response,
-            provider: client_flow.flow.provider,
-            email: client_flow.flow.email,
-            user_info_request: client_flow.client.user_info_request,
-        })
-        .map_err(OAuthError::GrantTokenError)
+    // Without a fixed secret the client id goes in the body, so the
+    // generated secret joins it there.
+    if let Some(generate) = client_flow.client.client_secret {
+        client = client.add_extra_param("client_secret", generate()?);
+    }
+
+    let response = client
+        .request_async(client_flow.client.transport)
+        .await
+        .map_err(OAuthError::GrantTokenError)?;
+
+    let id_token_claims = match (&client_flow.client.id_token_issuer, &response.extra_fields().id_token) {
+        (Some(issuer), Some(id_token)) => {
+            let nonce = oidc::nonce(&client_flow.flow.csrf_token_secret);
+            let transport = client_flow.client.transport;
+            Some(oidc::verify_id_token(id_token, issuer, Some(&nonce), transport).await?)
+        }
+        _ => None,
+    };
+
+    Ok(TokenInfo {
+        response,
+        provider: client_flow.flow.provider,
+        email: client_flow.flow.email,
+        user_info_request: client_flow.client.user_info_request,
+        transport: client_flow.client.transport,
+        // Flows from before scopes were kept asked for the client's.
+        scopes: match client_flow.flow.scopes.is_empty() {
+            true => client_flow.client.scopes,
+            false => client_flow.flow.scopes,
+        },
+        id_token_claims,
+    })
 }
 
-pub fn fetch_user_info(
+pub async fn fetch_user_info(
     session: &Session,
     token_info: TokenInfo,
 ) -> result::Result<UserInfo, Error> {
@@ -146,17 +362,81 @@ pub fn fetch_user_info(
     if let Some(refresh_token) = token_info.response.refresh_token() {
         session.insert(SESSION_OAUTH_TOKEN, refresh_token)?;
     }
+    session.insert(SESSION_OAUTH_SCOPES, token_info.granted_scopes())?;
+
+    // Verified claims are used as they are; otherwise the token's read
+    // without its signature checked, having come straight from the provider.
+    if token_info.user_info_request.from_id_token {
+        let claims = match &token_info.id_token_claims {
+            Some(claims) => claims.clone(),
+            None => {
+                let id_token = token_info
+                    .response
+                    .extra_fields()
+                    .id_token
+                    .as_deref()
+                    .ok_or_else(|| OAuthError::IdTokenError("missing from the token response".to_string()))?;
+                id_token_claims(id_token)?
+            }
+        };
+        return token_info
+            .user_info_request
+            .parse(&claims, &token_info.email)
+            .map_err(|e| Error::OAuth(OAuthError::DecodeProfileError(e)));
+    }
+
+    let fetcher = &token_info.user_info_request;
+    let mut responses = Vec::with_capacity(fetcher.endpoints.len());
+    for endpoint in fetcher.endpoints.iter() {
+        let request = get_user_info_request(access_token, endpoint, &fetcher.headers);
+        responses.push(
+            (token_info.transport)(request)
+                .await
+                .map_err(OAuthError::FetchProfileError)?,
+        );
+    }
+    token_info.parse_user_info_responses(&responses).map_err(Error::OAuth)
+}
+
+/// Revokes `refresh_token` at the provider (RFC 7009), which for most
+/// also revokes the access tokens it was used for. Returns false, without
+/// asking, if the provider has no revocation endpoint.
+pub async fn revoke(client: &ScopedClient, refresh_token: &str) -> result::Result<bool, OAuthError> {
+    let token = StandardRevocableToken::RefreshToken(RefreshToken::new(refresh_token.to_string()));
+    let mut request = match client.inner.revoke_token(token) {
+        Ok(request) => request,
+        Err(ConfigurationError::MissingUrl(_)) => return Ok(false),
+        Err(e) => return Err(OAuthError::RevokeTokenError(e.to_string())),
+    };
+    if let Some(generate) = client.client_secret {
+        request = request.add_extra_param("client_secret", generate()?);
+    }
+
+    request
+        .request_async(client.transport)
+        .await
+        .map(|_| true)
+        .map_err(|e| OAuthError::RevokeTokenError(format!("{:?}", e)))
+}
 
-    let user_info_request = get_user_info_request(access_token, &token_info.user_info_request);
-    http_client(user_info_request)
-        .map_err(OAuthError::FetchProfileError)
-        .and_then(|response| token_info.parse_user_info_response(&response))
-        .map_err(Error::OAuth)
+/// The claims of an `id_token`, as JSON. Its signature isn't checked: it's
+/// only read from the token response, which came straight from the
+/// provider over TLS (OpenID Connect Core 3.1.3.7). `oidc::verify` checks
+/// it as well, for providers whose keys are known.
+pub fn id_token_claims(id_token: &str) -> result::Result<String, OAuthError> {
+    let payload = id_token
+        .split('.')
+        .nth(1)
+        .ok_or_else(|| OAuthError::IdTokenError("not a JWT".to_string()))?;
+    let json = base64::decode_config(payload, base64::URL_SAFE_NO_PAD)
+        .map_err(|e| OAuthError::IdTokenError(e.to_string()))?;
+    String::from_utf8(json).map_err(|e| OAuthError::IdTokenError(e.to_string()))
 }
 
-fn get_user_info_request<'a>(
-    access_token: &'a AccessToken,
-    fetcher: &'a UserInfoRequest,
+fn get_user_info_request(
+    access_token: &AccessToken,
+    endpoint: &UserInfoEndpoint,
+    extra_headers: &[(Vec<u8>, String)],
 ) -> oauth2::HttpRequest {
     let token_value = access_token.secret();
 
@@ -169,7 +449,7 @@ fn get_user_info_request<'a>(
         AUTHORIZATION,
         HeaderValue::from_str(&format!("Bearer {}", token_value)).unwrap(),
     );
-    for (key, value) in fetcher.headers.iter() {
+    for (key, value) in extra_headers.iter() {
         headers.append(
             HeaderName::from_bytes(key).unwrap(),
             HeaderValue::from_str(value).unwrap(),
@@ -177,7 +457,7 @@ fn get_user_info_request<'a>(
     }
 
     let body: Vec<u8> = vec![];
-    let url = url::Url::parse_with_params(&fetcher.uri, fetcher.params.iter()).unwrap();
+    let url = url::Url::parse_with_params(&endpoint.uri, endpoint.params.iter()).unwrap();
 
     oauth2::HttpRequest {
         method: Method::GET,
@@ -1,16 +1,36 @@
+use chrono::Utc;
 use lazy_static::lazy_static;
-use oauth2::basic::BasicClient;
-use oauth2::{url, AuthUrl, ClientId, ClientSecret, RedirectUrl, RevocationUrl, TokenUrl};
+use oauth2::{url, AuthType, AuthUrl, ClientId, ClientSecret, RedirectUrl, RevocationUrl, TokenUrl};
+use p256::ecdsa::signature::Signer;
+use p256::ecdsa::{Signature, SigningKey};
+use p256::pkcs8::DecodePrivateKey;
+use p256::SecretKey;
 use serde::{Deserialize, Serialize};
-use serde_json;
+use serde_json::{self, json, Value};
 use std::collections::HashMap;
 use std::env;
-use std::sync::{Arc, Mutex};
+use std::sync::RwLock;
 
-use crate::oauth::{ScopedClient, UserInfo, UserInfoDeserializer, UserInfoRequest};
+use crate::error::OAuthError;
+use crate::oauth::config::{ProviderConfig, ProvidersFile, UserInfoFields};
+use crate::oauth::{
+    async_transport, ClientSecretGenerator, OAuthClient, ScopedClient, UserInfo, UserInfoDeserializer,
+    UserInfoEndpoint, UserInfoRequest,
+};
+use crate::oauth::device::DeviceEndpoint;
+use crate::oauth::oidc::Issuer;
 
 pub const DEFAULT_PROVIDER: &str = "google";
 
+/// Where providers send users back to, under `JELLY_DOMAIN`. Apple posts
+/// its callback here rather than redirecting, so `Csrf` lets posts here
+/// through; the callback's `state` does the same job.
+pub const CALLBACK_PATH: &str = "/oauth/callback";
+
+/// How long generated Apple client secrets last. Apple allows up to six
+/// months, but they're made fresh for every token request.
+const APPLE_SECRET_SECONDS: i64 = 5 * 60;
+
 #[derive(Clone, Copy, Debug, Deserialize, Serialize)]
 pub struct ProviderHints {
     pub uses_email_hint: bool,
@@ -20,10 +40,107 @@ type HintMap = HashMap<&'static str, ProviderHints>;
 
 type ClientMap = HashMap<String, Option<ScopedClient>>;
 
-// TODO 105: use once_cell get_or_init and/or once_cell:sync::Lazy
+type RegisteredMap = HashMap<&'static str, ScopedClientConfig>;
+
+// The hints never change once built, so they need no lock; clients are
+// built on first use, then only read. Registered providers are written
+// at startup.
 lazy_static! {
-    static ref LOGIN_HINTS: Arc<Mutex<HintMap>> = Arc::new(Mutex::new(build_hints()));
-    static ref CLIENTS: Arc<Mutex<ClientMap>> = Arc::new(Mutex::new(HashMap::new()));
+    static ref PROVIDERS_FILE: Result<ProvidersFile, String> = {
+        let builtin: Vec<&str> = build_hints().keys().copied().collect();
+        ProvidersFile::load(&builtin).map_err(|e| e.to_string())
+    };
+    static ref NO_PROVIDERS_FILE: ProvidersFile = ProvidersFile::default();
+    static ref LOGIN_HINTS: HintMap = with_file_hints(build_hints());
+    static ref CLIENTS: RwLock<ClientMap> = RwLock::new(HashMap::new());
+    static ref REGISTERED: RwLock<RegisteredMap> = RwLock::new(HashMap::new());
+}
+
+/// Reads the providers file, if there is one; `Server::run` calls this
+/// before it starts, so a bad file stops it there.
+pub fn load_providers_file() -> Result<(), String> {
+    PROVIDERS_FILE.as_ref().map(|_| ()).map_err(String::clone)
+}
+
+/// The providers file, or an empty one if it couldn't be read (which
+/// `load_providers_file` has already said).
+fn providers_file() -> &'static ProvidersFile {
+    PROVIDERS_FILE.as_ref().unwrap_or(&NO_PROVIDERS_FILE)
+}
+
+/// A provider the app defines itself (e.g an internal identity provider),
+/// added with `Server::register_oauth_provider`. The client id and secret
+/// are still read from the environment, by the variables named here. The
+/// profile's read with `fields` if they're set, or else `deserializer`.
+#[derive(Clone, Default)]
+pub struct ScopedClientConfig {
+    pub client_id_env: String,
+    pub client_secret_env: Option<String>,
+    pub auth_url: String,
+    pub token_url: String,
+    pub revoke_url: Option<String>,
+    /// The device authorization endpoint, if the provider has a device
+    /// flow; see `oauth::device`.
+    pub device_auth_url: Option<String>,
+    /// Sends the client id and secret in the token request's body, for
+    /// providers that don't take HTTP Basic auth.
+    pub secret_in_body: bool,
+    pub scopes: Vec<String>,
+    pub login_hint_key: Option<String>,
+    pub uses_email_hint: bool,
+    pub auth_params: Vec<(String, String)>,
+    pub user_info_endpoints: Vec<UserInfoEndpoint>,
+    pub user_info_headers: Vec<(Vec<u8>, String)>,
+    pub fields: Option<UserInfoFields>,
+    pub deserializer: Option<UserInfoDeserializer>,
+    /// The `iss` of the provider's `id_token`s, and where the keys they're
+    /// signed with are; both are needed to verify them (see `oidc`).
+    pub issuer: Option<String>,
+    pub jwks_url: Option<String>,
+    /// Reads the user from the `id_token`'s claims (with `fields`), rather
+    /// than from `user_info_endpoints`.
+    pub user_info_from_id_token: bool,
+}
+
+impl ScopedClientConfig {
+    fn build(&self, provider: &'static str, redirect_uri: &str) -> ScopedClient {
+        let cfg = ClientConfig {
+            redirect_uri,
+            client_id_env: &self.client_id_env,
+            client_secret_env: self.client_secret_env.as_deref(),
+            client_secret_generator: None,
+            tenant_env: None,
+            base_url_env: None,
+            auth_url: &self.auth_url,
+            token_url: &self.token_url,
+            revoke_url: self.revoke_url.as_deref(),
+            device_auth_url: self.device_auth_url.as_deref(),
+            secret_in_body: self.secret_in_body,
+            scopes: &[],
+            login_hint_key: self.login_hint_key.as_deref(),
+            auth_params: &[],
+            user_info_endpoints: &[],
+            user_info_headers: &[],
+            user_info_deserializer: self.deserializer.unwrap_or(deserialize_unconfigured),
+            user_info_from_id_token: self.user_info_from_id_token,
+            id_token_issuer: self.issuer.as_deref().zip(self.jwks_url.as_deref()),
+        };
+
+        let mut client: ScopedClient = cfg.into();
+        client.scopes = self.scopes.clone();
+        client.auth_params = self.auth_params.clone();
+        client.user_info_request.endpoints = self.user_info_endpoints.clone();
+        client.user_info_request.headers = self.user_info_headers.clone();
+        client.user_info_request.fields = self.fields.clone().map(|fields| UserInfoFields { provider, ..fields });
+        client
+    }
+}
+
+/// Adds `provider`, or replaces the built-in (or file's) provider of that
+/// name. Registrations are checked before either.
+pub fn register(provider: &'static str, config: ScopedClientConfig) {
+    REGISTERED.write().unwrap().insert(provider, config);
+    CLIENTS.write().unwrap().remove(provider);
 }
 
 fn build_hints() -> HintMap {
@@ -52,91 +169,247 @@ fn build_hints() -> HintMap {
             uses_email_hint: false,
         },
     );
+    hints.insert(
+        "apple",
+        ProviderHints {
+            uses_email_hint: false,
+        },
+    );
+    hints.insert(
+        "microsoft",
+        ProviderHints {
+            uses_email_hint: true,
+        },
+    );
+    hints.insert(
+        "gitlab",
+        ProviderHints {
+            uses_email_hint: false,
+        },
+    );
+    hints.insert(
+        "linkedin",
+        ProviderHints {
+            uses_email_hint: false,
+        },
+    );
+    hints.insert(
+        "slack",
+        ProviderHints {
+            uses_email_hint: false,
+        },
+    );
+    hints.insert(
+        "twitch",
+        ProviderHints {
+            uses_email_hint: false,
+        },
+    );
+    hints
+}
+
+/// Adds the providers file's providers to the built-in ones, and its
+/// `uses_email_hint`s.
+fn with_file_hints(mut hints: HintMap) -> HintMap {
+    for (name, provider) in providers_file().providers.iter() {
+        let provider_hints = hints.entry(name.as_str()).or_insert(ProviderHints {
+            uses_email_hint: false,
+        });
+        if let Some(uses_email_hint) = provider.uses_email_hint {
+            provider_hints.uses_email_hint = uses_email_hint;
+        }
+    }
     hints
 }
 
 pub fn valid_provider(provider: &str) -> bool {
-    LOGIN_HINTS.lock().unwrap().contains_key(provider)
+    REGISTERED.read().unwrap().contains_key(provider) || LOGIN_HINTS.contains_key(provider)
 }
 
 pub fn provider_hints(provider: &str) -> Option<ProviderHints> {
-    LOGIN_HINTS.lock().unwrap().get(provider).copied()
+    if let Some(config) = REGISTERED.read().unwrap().get(provider) {
+        return Some(ProviderHints {
+            uses_email_hint: config.uses_email_hint,
+        });
+    }
+    LOGIN_HINTS.get(provider).copied()
+}
+
+/// Every provider there's support for, by name, whether or not it's
+/// configured.
+pub fn providers() -> Vec<&'static str> {
+    let mut providers: Vec<&'static str> = LOGIN_HINTS.keys().copied().collect();
+    providers.extend(REGISTERED.read().unwrap().keys().copied());
+    providers.sort_unstable();
+    providers.dedup();
+    providers
 }
 
 pub fn client_for(provider: &str) -> Option<ScopedClient> {
-    if valid_provider(provider) {
-        let mut provider_map = CLIENTS.lock().unwrap();
-        if !provider_map.contains_key(provider) {
-            // Important: the root domain host cannot have a numeric IP address.
-            let root_domain = env::var("JELLY_DOMAIN").expect("JELLY_DOMAIN not set!");
-            // Important: the redirect_uri must have the trailing slash,
-            // and it must be registered with the OAuth provider.
-            let redirect_uri = format!("{}/oauth/callback", root_domain);
-            let client = build_client(provider, &redirect_uri);
-            provider_map.insert(provider.to_string(), client);
-        }
-        match provider_map.get(provider) {
-            // TODO 104: can we avoid client.clone() ?
-            Some(Some(client)) => Some(client.clone()),
-            _ => None,
-        }
-    } else {
-        None
+    if !valid_provider(provider) {
+        return None;
+    }
+
+    // TODO 104: can we avoid client.clone() ?
+    if let Some(client) = CLIENTS.read().unwrap().get(provider) {
+        return client.clone();
     }
+
+    let mut provider_map = CLIENTS.write().unwrap();
+    let client = provider_map.entry(provider.to_string()).or_insert_with(|| {
+        // Important: the root domain host cannot have a numeric IP address.
+        let root_domain = env::var("JELLY_DOMAIN").expect("JELLY_DOMAIN not set!");
+        // Important: the redirect_uri must have the trailing slash,
+        // and it must be registered with the OAuth provider.
+        let redirect_uri = format!("{}{}", root_domain, CALLBACK_PATH);
+        match REGISTERED.read().unwrap().get_key_value(provider) {
+            Some((&name, config)) => Some(config.build(name, &redirect_uri)),
+            None => build_client(provider, &redirect_uri),
+        }
+    });
+    client.clone()
+}
+
+/// Registers `client` as `provider`'s, in place of the one built from the
+/// environment; for pointing the login flow at a stand-in provider.
+pub fn set_client(provider: &str, client: ScopedClient) {
+    CLIENTS.write().unwrap().insert(provider.to_string(), Some(client));
 }
 
+/// Where a provider's endpoints are, when not where they usually are.
+/// There's a user info URI for each of the provider's user info endpoints.
+#[derive(Clone, Debug)]
+pub struct Endpoints {
+    pub auth_url: String,
+    pub token_url: String,
+    pub user_info_uris: Vec<String>,
+}
+
+type UserInfoEndpoints<'a> = &'a [(&'a str, &'a [(&'a str, &'a str)])];
+
 struct ClientConfig<'a> {
     redirect_uri: &'a str,
     client_id_env: &'a str,
     client_secret_env: Option<&'a str>,
+    /// For secrets made per request, in place of `client_secret_env`.
+    client_secret_generator: Option<ClientSecretGenerator>,
+    /// Fills `{tenant}` in `auth_url` and `token_url` from this environment
+    /// variable, or `common` if it isn't set.
+    tenant_env: Option<&'a str>,
+    /// Fills `{base}` in the endpoint URLs from this environment variable,
+    /// or the default given, for providers that can be self-hosted.
+    base_url_env: Option<(&'a str, &'a str)>,
     auth_url: &'a str,
     token_url: &'a str,
     revoke_url: Option<&'a str>,
+    /// The device authorization endpoint, for providers with a device flow.
+    device_auth_url: Option<&'a str>,
+    /// Sends the client id and secret in the token request's body, for
+    /// providers that don't take HTTP Basic auth.
+    secret_in_body: bool,
     scopes: &'a [&'a str],
     login_hint_key: Option<&'a str>,
-    user_info_uri: &'a str,
-    user_info_params: &'a [(&'a str, &'a str)],
+    auth_params: &'a [(&'a str, &'a str)],
+    /// Each endpoint's URI and query parameters.
+    user_info_endpoints: UserInfoEndpoints<'a>,
+    /// `{client_id}` in a value is filled with the client id, for APIs
+    /// that want it alongside the access token.
     user_info_headers: &'a [(&'a [u8], &'a str)],
     user_info_deserializer: UserInfoDeserializer,
+    user_info_from_id_token: bool,
+    /// Who issues the provider's `id_token`s and where its keys are, so
+    /// they're verified; `{tenant}` is filled as in the endpoints.
+    id_token_issuer: Option<(&'a str, &'a str)>,
 }
 
 impl<'a> From<ClientConfig<'a>> for ScopedClient {
     fn from(cfg: ClientConfig<'a>) -> Self {
-        let client_id = ClientId::new(
-            env::var(cfg.client_id_env)
-                .unwrap_or_else(|_| panic!("Missing the {} environment variable.", cfg.client_id_env)),
-        );
+        let client_id = env::var(cfg.client_id_env)
+            .unwrap_or_else(|_| panic!("Missing the {} environment variable.", cfg.client_id_env));
         let client_secret = cfg.client_secret_env.map(|secret_env| {
-            ClientSecret::new(
-                env::var(secret_env)
-                    .unwrap_or_else(|_| panic!("Missing the {} environment variable.", secret_env)),
-            )
+            env::var(secret_env).unwrap_or_else(|_| panic!("Missing the {} environment variable.", secret_env))
+        });
+        let tenant = cfg
+            .tenant_env
+            .map(|tenant_env| env::var(tenant_env).unwrap_or_else(|_| "common".to_string()));
+        let base_url = cfg.base_url_env.map(|(base_url_env, default)| {
+            env::var(base_url_env)
+                .unwrap_or_else(|_| default.to_string())
+                .trim_end_matches('/')
+                .to_string()
         });
+        let with_tenant = |url: &str| {
+            let url = match &tenant {
+                Some(tenant) => url.replace("{tenant}", tenant),
+                None => url.to_string(),
+            };
+            match &base_url {
+                Some(base_url) => url.replace("{base}", base_url),
+                None => url,
+            }
+        };
         let auth_url =
-            AuthUrl::new(cfg.auth_url.to_string()).expect("Invalid authorization endpoint URL");
-        let token_url = TokenUrl::new(cfg.token_url.to_string()).expect("Invalid token endpoint URL");
+            AuthUrl::new(with_tenant(cfg.auth_url)).expect("Invalid authorization endpoint URL");
+        let token_url = TokenUrl::new(with_tenant(cfg.token_url)).expect("Invalid token endpoint URL");
 
-        let mut inner = BasicClient::new(client_id, client_secret, auth_url, Some(token_url))
+        // The device flow sends the credentials itself; see `oauth::device`.
+        let device = cfg.device_auth_url.map(|device_auth_url| DeviceEndpoint {
+            authorization_url: with_tenant(device_auth_url),
+            token_url: with_tenant(cfg.token_url),
+            client_id: client_id.clone(),
+            client_secret: client_secret.clone(),
+        });
+
+        let id_token_issuer = cfg.id_token_issuer.map(|(issuer, jwks_url)| Issuer {
+            issuer: issuer.to_string(),
+            jwks_url: with_tenant(jwks_url),
+            client_id: client_id.clone(),
+        });
+        let user_info_headers = cfg
+            .user_info_headers
+            .iter()
+            .map(|&(key, value)| (key.to_vec(), value.replace("{client_id}", &client_id)))
+            .collect();
+
+        let client_id = ClientId::new(client_id);
+        let client_secret = client_secret.map(ClientSecret::new);
+        let mut inner = OAuthClient::new(client_id, client_secret, auth_url, Some(token_url))
             .set_redirect_uri(
                 RedirectUrl::new(cfg.redirect_uri.to_string()).expect("Invalid redirect URL"),
             );
 
         if let Some(revoke_url) = cfg.revoke_url {
             let revocation_url =
-                RevocationUrl::new(revoke_url.to_string()).expect("Invalid revocation endpoint URL");
+                RevocationUrl::new(with_tenant(revoke_url)).expect("Invalid revocation endpoint URL");
             inner = inner.set_revocation_uri(revocation_url);
         }
+        if cfg.secret_in_body {
+            inner = inner.set_auth_type(AuthType::RequestBody);
+        }
 
         Self {
             inner,
             scopes: array_str_to_vec(cfg.scopes),
             login_hint_key: cfg.login_hint_key.map(|key| key.to_string()),
+            auth_params: array_tuple_str_to_vec(cfg.auth_params),
+            client_secret: cfg.client_secret_generator,
             user_info_request: UserInfoRequest {
-                uri: cfg.user_info_uri.to_string(),
-                params: array_tuple_str_to_vec(cfg.user_info_params),
-                headers: array_tuple_u8_to_vec(cfg.user_info_headers),
+                endpoints: cfg
+                    .user_info_endpoints
+                    .iter()
+                    .map(|&(uri, params)| UserInfoEndpoint {
+                        uri: with_tenant(uri),
+                        params: array_tuple_str_to_vec(params),
+                    })
+                    .collect(),
+                headers: user_info_headers,
                 deserializer: cfg.user_info_deserializer,
+                fields: None,
+                from_id_token: cfg.user_info_from_id_token,
             },
+            transport: async_transport,
+            device,
+            id_token_issuer,
         }
     }
 }
@@ -149,81 +422,404 @@ fn array_tuple_str_to_vec(a: &[(&str, &str)]) -> Vec<(String, String)> {
     a.iter().map(|&(k, v)| (k.into(), v.into())).collect()
 }
 
-fn array_tuple_u8_to_vec(a: &[(&[u8], &str)]) -> Vec<(Vec<u8>, String)> {
-    a.iter().map(|&(k, v)| (k.into(), v.into())).collect()
+/// Redirect URI must match exactly with registered.
+fn build_client(provider: &str, redirect_uri: &str) -> Option<ScopedClient> {
+    client_config(provider, redirect_uri).map(|cfg| with_file_lists(provider, cfg.into()))
 }
 
-/// Redirect URI must match exactly with registered.
-fn build_client<'a>(provider: &'a str, redirect_uri: &'a str) -> Option<ScopedClient> {
+/// `provider`'s client, but talking to `endpoints` (and with nowhere to
+/// revoke tokens, nor keys to verify `id_token`s with).
+pub fn build_client_at(provider: &str, redirect_uri: &str, endpoints: &Endpoints) -> Option<ScopedClient> {
+    client_config(provider, redirect_uri).map(|cfg| {
+        let cfg = ClientConfig {
+            auth_url: &endpoints.auth_url,
+            token_url: &endpoints.token_url,
+            revoke_url: None,
+            device_auth_url: None,
+            id_token_issuer: None,
+            ..cfg
+        };
+        let mut client = with_file_lists(provider, cfg.into());
+
+        // Each endpoint keeps its parameters at its new URI.
+        let user_info_endpoints = &mut client.user_info_request.endpoints;
+        user_info_endpoints.truncate(endpoints.user_info_uris.len());
+        for (endpoint, uri) in user_info_endpoints.iter_mut().zip(endpoints.user_info_uris.iter()) {
+            endpoint.uri = uri.clone();
+        }
+        client
+    })
+}
+
+/// `provider`'s settings: the built-in ones with what the providers file
+/// changes, or the file's own for providers that aren't built in.
+fn client_config<'a>(provider: &str, redirect_uri: &'a str) -> Option<ClientConfig<'a>> {
+    let file = providers_file().providers.get(provider);
+    let cfg = match (builtin_config(provider, redirect_uri), file) {
+        (Some(cfg), _) => cfg,
+        // Everything else a new provider needs is checked to be in the file.
+        (None, Some(_)) => ClientConfig {
+            redirect_uri,
+            client_id_env: "",
+            client_secret_env: None,
+            client_secret_generator: None,
+            tenant_env: None,
+            base_url_env: None,
+            auth_url: "",
+            token_url: "",
+            revoke_url: None,
+            device_auth_url: None,
+            secret_in_body: false,
+            scopes: &[],
+            login_hint_key: None,
+            auth_params: &[],
+            user_info_endpoints: &[],
+            user_info_headers: &[],
+            user_info_deserializer: deserialize_unconfigured,
+            user_info_from_id_token: false,
+            id_token_issuer: None,
+        },
+        (None, None) => return None,
+    };
+
+    Some(match file {
+        Some(file) => ClientConfig {
+            client_id_env: file.client_id_env.as_deref().unwrap_or(cfg.client_id_env),
+            client_secret_env: file.client_secret_env.as_deref().or(cfg.client_secret_env),
+            auth_url: file.auth_url.as_deref().unwrap_or(cfg.auth_url),
+            token_url: file.token_url.as_deref().unwrap_or(cfg.token_url),
+            revoke_url: file.revoke_url.as_deref().or(cfg.revoke_url),
+            device_auth_url: file.device_auth_url.as_deref().or(cfg.device_auth_url),
+            secret_in_body: file.secret_in_body.unwrap_or(cfg.secret_in_body),
+            login_hint_key: file.login_hint_key.as_deref().or(cfg.login_hint_key),
+            ..cfg
+        },
+        None => cfg,
+    })
+}
+
+/// Sets the lists the providers file has for `provider` on its client;
+/// they're set after it's built, as `ClientConfig` only borrows them.
+fn with_file_lists(provider: &str, mut client: ScopedClient) -> ScopedClient {
+    let (file, name): (&ProviderConfig, &'static str) =
+        match (providers_file().providers.get(provider), LOGIN_HINTS.get_key_value(provider)) {
+            (Some(file), Some((&name, _))) => (file, name),
+            _ => return client,
+        };
+
+    if let Some(scopes) = &file.scopes {
+        client.scopes = scopes.clone();
+    }
+    if let Some(auth_params) = &file.auth_params {
+        client.auth_params = auth_params.clone().into_iter().collect();
+    }
+    if let Some(endpoints) = &file.user_info {
+        client.user_info_request.endpoints = endpoints
+            .iter()
+            .map(|endpoint| UserInfoEndpoint {
+                uri: endpoint.url.clone(),
+                params: endpoint.params.clone().into_iter().collect(),
+            })
+            .collect();
+    }
+    if let Some(headers) = &file.headers {
+        client.user_info_request.headers = headers
+            .iter()
+            .map(|(key, value)| (key.as_bytes().to_vec(), value.clone()))
+            .collect();
+    }
+    if let Some(fields) = &file.fields {
+        client.user_info_request.fields = Some(UserInfoFields {
+            provider: name,
+            ..fields.clone()
+        });
+    }
+    client
+}
+
+fn builtin_config<'a>(provider: &str, redirect_uri: &'a str) -> Option<ClientConfig<'a>> {
     match provider {
         "google" => Some(ClientConfig {
             redirect_uri,
             client_id_env: "GOOGLE_CLIENT_ID",
             client_secret_env: Some("GOOGLE_CLIENT_SECRET"),
+            client_secret_generator: None,
+            tenant_env: None,
+            base_url_env: None,
             auth_url: "https://accounts.google.com/o/oauth2/v2/auth",
             token_url: "https://oauth2.googleapis.com/token",
             revoke_url: Some("https://oauth2.googleapis.com/revoke"),
+            device_auth_url: Some("https://oauth2.googleapis.com/device/code"),
+            secret_in_body: false,
             scopes: &[
                 "https://www.googleapis.com/auth/userinfo.email",
                 "https://www.googleapis.com/auth/userinfo.profile",
             ],
             login_hint_key: Some("login_hint"),
-            user_info_uri: "https://www.googleapis.com/oauth2/v3/userinfo",
-            user_info_params: &[],
+            // Keeps what's already granted when asking for more.
+            auth_params: &[("include_granted_scopes", "true")],
+            user_info_endpoints: &[("https://www.googleapis.com/oauth2/v3/userinfo", &[])],
             user_info_headers: &[(b"Accept", "application/json")],
             user_info_deserializer: deserialize_google,
+            user_info_from_id_token: false,
+            id_token_issuer: Some(("https://accounts.google.com", "https://www.googleapis.com/oauth2/v3/certs")),
         }),
         "twitter" => Some(ClientConfig {
             redirect_uri,
             client_id_env: "TWITTER_CLIENT_ID",
             client_secret_env: None,
+            client_secret_generator: None,
+            tenant_env: None,
+            base_url_env: None,
             auth_url: "https://twitter.com/i/oauth2/authorize",
             token_url: "https://api.twitter.com/2/oauth2/token",
             revoke_url: Some("https://api.twitter.com/2/oauth2/revoke"),
+            device_auth_url: None,
+            secret_in_body: false,
             scopes: &["tweet.read", "users.read"],
             login_hint_key: None,
-            user_info_uri: "https://api.twitter.com/2/users/me",
-            user_info_params: &[(
-                "user.fields",
-                "id,name,username,verified,url,profile_image_url",
+            auth_params: &[],
+            user_info_endpoints: &[(
+                "https://api.twitter.com/2/users/me",
+                &[("user.fields", "id,name,username,verified,url,profile_image_url")],
             )],
             user_info_headers: &[(b"Accept", "application/json")],
             user_info_deserializer: deserialize_twitter,
+            user_info_from_id_token: false,
+            id_token_issuer: None,
         }),
         "github" => Some(ClientConfig {
             redirect_uri,
             client_id_env: "GITHUB_CLIENT_ID",
             client_secret_env: Some("GITHUB_CLIENT_SECRET"),
+            client_secret_generator: None,
+            tenant_env: None,
+            base_url_env: None,
             auth_url: "https://github.com/login/oauth/authorize",
             token_url: "https://github.com/login/oauth/access_token",
             revoke_url: None,
+            device_auth_url: Some("https://github.com/login/device/code"),
+            secret_in_body: false,
             scopes: &["read:user"],
             login_hint_key: Some("login"),
-            user_info_uri: "https://api.github.com/user",
-            user_info_params: &[],
+            auth_params: &[],
+            user_info_endpoints: &[("https://api.github.com/user", &[])],
             user_info_headers: &[
                 (b"Accept", "application/vnd.github.v3+json"),
                 (b"User-Agent", "Zingg-Starter-App"),
             ],
             user_info_deserializer: deserialize_github,
+            user_info_from_id_token: false,
+            id_token_issuer: None,
         }),
         "facebook" => Some(ClientConfig {
             redirect_uri,
             client_id_env: "FACEBOOK_CLIENT_ID",
             client_secret_env: Some("FACEBOOK_CLIENT_SECRET"),
+            client_secret_generator: None,
+            tenant_env: None,
+            base_url_env: None,
             auth_url: "https://www.facebook.com/v13.0/dialog/oauth",
             token_url: "https://graph.facebook.com/v13.0/oauth/access_token",
             revoke_url: None,
+            device_auth_url: None,
+            secret_in_body: false,
             scopes: &["public_profile", "email"],
             login_hint_key: None,
-            user_info_uri: "https://graph.facebook.com/v13.0/me",
-            user_info_params: &[],
+            auth_params: &[],
+            user_info_endpoints: &[("https://graph.facebook.com/v13.0/me", &[])],
             user_info_headers: &[(b"Accept", "application/json")],
             user_info_deserializer: deserialize_facebook,
+            user_info_from_id_token: false,
+            id_token_issuer: None,
+        }),
+        "apple" => Some(ClientConfig {
+            redirect_uri,
+            // The Services ID, not the app's bundle id.
+            client_id_env: "APPLE_CLIENT_ID",
+            client_secret_env: None,
+            client_secret_generator: Some(apple_client_secret),
+            tenant_env: None,
+            base_url_env: None,
+            auth_url: "https://appleid.apple.com/auth/authorize",
+            token_url: "https://appleid.apple.com/auth/token",
+            revoke_url: Some("https://appleid.apple.com/auth/revoke"),
+            device_auth_url: None,
+            secret_in_body: false,
+            scopes: &["name", "email"],
+            login_hint_key: None,
+            // Required when asking for the name or email.
+            auth_params: &[("response_mode", "form_post")],
+            user_info_endpoints: &[],
+            user_info_headers: &[],
+            user_info_deserializer: deserialize_apple,
+            user_info_from_id_token: true,
+            id_token_issuer: Some(("https://appleid.apple.com", "https://appleid.apple.com/auth/keys")),
+        }),
+        "microsoft" => Some(ClientConfig {
+            redirect_uri,
+            client_id_env: "MICROSOFT_CLIENT_ID",
+            client_secret_env: Some("MICROSOFT_CLIENT_SECRET"),
+            client_secret_generator: None,
+            // A directory (tenant) id or domain to only let in your
+            // organization; `organizations` for any work or school account,
+            // or `common` (the default) for personal accounts too.
+            tenant_env: Some("MICROSOFT_TENANT"),
+            base_url_env: None,
+            auth_url: "https://login.microsoftonline.com/{tenant}/oauth2/v2.0/authorize",
+            token_url: "https://login.microsoftonline.com/{tenant}/oauth2/v2.0/token",
+            revoke_url: None,
+            device_auth_url: Some("https://login.microsoftonline.com/{tenant}/oauth2/v2.0/devicecode"),
+            secret_in_body: false,
+            scopes: &["openid", "profile", "email", "User.Read"],
+            login_hint_key: Some("login_hint"),
+            auth_params: &[],
+            user_info_endpoints: &[("https://graph.microsoft.com/v1.0/me", &[])],
+            user_info_headers: &[(b"Accept", "application/json")],
+            user_info_deserializer: deserialize_microsoft,
+            user_info_from_id_token: false,
+            id_token_issuer: Some((
+                "https://login.microsoftonline.com/{tenantid}/v2.0",
+                "https://login.microsoftonline.com/{tenant}/discovery/v2.0/keys",
+            )),
+        }),
+        "gitlab" => Some(ClientConfig {
+            redirect_uri,
+            client_id_env: "GITLAB_CLIENT_ID",
+            client_secret_env: Some("GITLAB_CLIENT_SECRET"),
+            client_secret_generator: None,
+            tenant_env: None,
+            // Your own instance's URL, if it's self-hosted.
+            base_url_env: Some(("GITLAB_BASE_URL", "https://gitlab.com")),
+            auth_url: "{base}/oauth/authorize",
+            token_url: "{base}/oauth/token",
+            revoke_url: Some("{base}/oauth/revoke"),
+            device_auth_url: None,
+            secret_in_body: false,
+            scopes: &["read_user"],
+            login_hint_key: None,
+            auth_params: &[],
+            user_info_endpoints: &[("{base}/api/v4/user", &[])],
+            user_info_headers: &[(b"Accept", "application/json")],
+            user_info_deserializer: deserialize_gitlab,
+            user_info_from_id_token: false,
+            id_token_issuer: None,
+        }),
+        "linkedin" => Some(ClientConfig {
+            redirect_uri,
+            client_id_env: "LINKEDIN_CLIENT_ID",
+            client_secret_env: Some("LINKEDIN_CLIENT_SECRET"),
+            client_secret_generator: None,
+            tenant_env: None,
+            base_url_env: None,
+            auth_url: "https://www.linkedin.com/oauth/v2/authorization",
+            token_url: "https://www.linkedin.com/oauth/v2/accessToken",
+            revoke_url: None,
+            device_auth_url: None,
+            secret_in_body: true,
+            scopes: &["r_liteprofile", "r_emailaddress"],
+            login_hint_key: None,
+            auth_params: &[],
+            // The profile, then the email address, which it doesn't include.
+            user_info_endpoints: &[
+                (
+                    "https://api.linkedin.com/v2/me",
+                    &[("projection", "(id,localizedFirstName,localizedLastName)")],
+                ),
+                (
+                    "https://api.linkedin.com/v2/emailAddress",
+                    &[("q", "members"), ("projection", "(elements*(handle~))")],
+                ),
+            ],
+            user_info_headers: &[(b"Accept", "application/json")],
+            user_info_deserializer: deserialize_linkedin,
+            user_info_from_id_token: false,
+            id_token_issuer: None,
+        }),
+        // Sign in with Slack, which is OpenID Connect.
+        "slack" => Some(ClientConfig {
+            redirect_uri,
+            client_id_env: "SLACK_CLIENT_ID",
+            client_secret_env: Some("SLACK_CLIENT_SECRET"),
+            client_secret_generator: None,
+            tenant_env: None,
+            base_url_env: None,
+            auth_url: "https://slack.com/openid/connect/authorize",
+            token_url: "https://slack.com/api/openid.connect.token",
+            revoke_url: None,
+            device_auth_url: None,
+            secret_in_body: true,
+            scopes: &["openid", "profile", "email"],
+            login_hint_key: None,
+            auth_params: &[],
+            user_info_endpoints: &[("https://slack.com/api/openid.connect.userInfo", &[])],
+            user_info_headers: &[(b"Accept", "application/json")],
+            user_info_deserializer: deserialize_slack,
+            user_info_from_id_token: false,
+            id_token_issuer: Some(("https://slack.com", "https://slack.com/openid/connect/keys")),
+        }),
+        "twitch" => Some(ClientConfig {
+            redirect_uri,
+            client_id_env: "TWITCH_CLIENT_ID",
+            client_secret_env: Some("TWITCH_CLIENT_SECRET"),
+            client_secret_generator: None,
+            tenant_env: None,
+            base_url_env: None,
+            auth_url: "https://id.twitch.tv/oauth2/authorize",
+            token_url: "https://id.twitch.tv/oauth2/token",
+            revoke_url: Some("https://id.twitch.tv/oauth2/revoke"),
+            device_auth_url: None,
+            secret_in_body: true,
+            scopes: &["user:read:email"],
+            login_hint_key: None,
+            auth_params: &[],
+            // Without an id, it's the token's user.
+            user_info_endpoints: &[("https://api.twitch.tv/helix/users", &[])],
+            user_info_headers: &[(b"Accept", "application/json"), (b"Client-Id", "{client_id}")],
+            user_info_deserializer: deserialize_twitch,
+            user_info_from_id_token: false,
+            id_token_issuer: None,
         }),
         _ => None,
     }
-    .map(|cfg| cfg.into())
+}
+
+fn env_var(name: &str) -> Result<String, OAuthError> {
+    env::var(name).map_err(|_| OAuthError::ClientSecretError(format!("{} not set!", name)))
+}
+
+/// Apple's client secret: a JWT signed with the `.p8` key at
+/// `APPLE_KEY_PATH`, with its `APPLE_KEY_ID` and your `APPLE_TEAM_ID`.
+fn apple_client_secret() -> Result<String, OAuthError> {
+    let pem = std::fs::read_to_string(env_var("APPLE_KEY_PATH")?)
+        .map_err(|e| OAuthError::ClientSecretError(format!("Error reading APPLE_KEY_PATH: {:?}", e)))?;
+    let key = SecretKey::from_pkcs8_pem(&pem)
+        .map_err(|e| OAuthError::ClientSecretError(format!("APPLE_KEY_PATH is not a P-256 key: {:?}", e)))?;
+
+    let now = Utc::now().timestamp();
+    let encode = |value: &Value| base64::encode_config(value.to_string(), base64::URL_SAFE_NO_PAD);
+    let signing_input = format!(
+        "{}.{}",
+        encode(&json!({ "alg": "ES256", "kid": env_var("APPLE_KEY_ID")? })),
+        encode(&json!({
+            "iss": env_var("APPLE_TEAM_ID")?,
+            "iat": now,
+            "exp": now + APPLE_SECRET_SECONDS,
+            "aud": "https://appleid.apple.com",
+            "sub": env_var("APPLE_CLIENT_ID")?,
+        }))
+    );
+    let signature: Signature = SigningKey::from(key).sign(signing_input.as_bytes());
+    Ok(format!(
+        "{}.{}",
+        signing_input,
+        base64::encode_config(signature.as_ref(), base64::URL_SAFE_NO_PAD)
+    ))
+}
+
+/// Providers from the file read the profile with their `fields` instead.
+fn deserialize_unconfigured(_json_body: &str, _email: &str) -> serde_json::Result<UserInfo> {
+    Err(serde::de::Error::custom("no fields configured for the profile"))
 }
 
 fn deserialize_google(json_body: &str, email: &str) -> serde_json::Result<UserInfo> {
@@ -242,6 +838,40 @@ fn deserialize_facebook(json_body: &str, email: &str) -> serde_json::Result<User
     parse_user_info::<FacebookUserInfo>(json_body, email)
 }
 
+fn deserialize_microsoft(json_body: &str, email: &str) -> serde_json::Result<UserInfo> {
+    parse_user_info::<MicrosoftUserInfo>(json_body, email)
+}
+
+fn deserialize_gitlab(json_body: &str, email: &str) -> serde_json::Result<UserInfo> {
+    parse_user_info::<GitlabUserInfo>(json_body, email)
+}
+
+fn deserialize_linkedin(json_body: &str, email: &str) -> serde_json::Result<UserInfo> {
+    parse_user_info::<LinkedinUserInfo>(json_body, email)
+}
+
+fn deserialize_slack(json_body: &str, email: &str) -> serde_json::Result<UserInfo> {
+    parse_user_info::<SlackUserInfo>(json_body, email)
+}
+
+/// Helix wraps its results in a `data` array; here it's just them.
+fn deserialize_twitch(json_body: &str, email: &str) -> serde_json::Result<UserInfo> {
+    let users: TwitchUsers = serde_json::from_str(json_body)?;
+    let user = users
+        .data
+        .into_iter()
+        .next()
+        .ok_or_else(|| serde::de::Error::custom("no user in the response"))?;
+    Ok(UserInfo {
+        login_email: email.to_string(),
+        ..user.into()
+    })
+}
+
+fn deserialize_apple(json_body: &str, email: &str) -> serde_json::Result<UserInfo> {
+    parse_user_info::<AppleIdToken>(json_body, email)
+}
+
 fn parse_user_info<'de, T: Deserialize<'de> + Into<UserInfo>>(
     json_body: &'de str,
     email: &str,
@@ -354,3 +984,206 @@ impl From<FacebookUserInfo> for UserInfo {
         }
     }
 }
+
+/// Microsoft Graph `me` endpoint. `mail` is often unset, e.g for personal
+/// accounts; `userPrincipalName` is what they sign in with, usually an email
+/// address.
+/// See https://docs.microsoft.com/en-us/graph/api/user-get
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MicrosoftUserInfo {
+    id: String,
+    display_name: Option<String>,
+    user_principal_name: String,
+    mail: Option<String>,
+    given_name: Option<String>,
+    surname: Option<String>,
+}
+
+impl From<MicrosoftUserInfo> for UserInfo {
+    fn from(microsoft: MicrosoftUserInfo) -> Self {
+        let name = microsoft.display_name.unwrap_or_else(|| {
+            [microsoft.given_name, microsoft.surname]
+                .iter()
+                .flatten()
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join(" ")
+        });
+        UserInfo {
+            provider: "microsoft",
+            id: microsoft.id,
+            name,
+            username: Some(microsoft.user_principal_name),
+            provider_email: microsoft.mail,
+            ..Default::default()
+        }
+    }
+}
+
+/// GitLab `user` endpoint. `email` is their primary address, which needs
+/// the `read_user` scope; `public_email` is empty unless they've set one.
+/// See https://docs.gitlab.com/ee/api/users.html#for-normal-users-1
+#[derive(Debug, Deserialize, Serialize)]
+struct GitlabUserInfo {
+    id: i64,
+    username: String,
+    name: String,
+    email: Option<String>,
+    public_email: Option<String>,
+    web_url: Option<url::Url>,
+}
+
+impl From<GitlabUserInfo> for UserInfo {
+    fn from(gitlab: GitlabUserInfo) -> Self {
+        let email = [gitlab.email, gitlab.public_email]
+            .into_iter()
+            .flatten()
+            .find(|email| !email.is_empty());
+        UserInfo {
+            provider: "gitlab",
+            id: gitlab.id.to_string(),
+            name: gitlab.name,
+            username: Some(gitlab.username),
+            provider_email: email,
+            ..Default::default()
+        }
+    }
+}
+
+/// LinkedIn's `me` and `emailAddress` endpoints, merged.
+/// See https://docs.microsoft.com/en-us/linkedin/consumer/integrations/self-serve/sign-in-with-linkedin
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LinkedinUserInfo {
+    id: String,
+    localized_first_name: Option<String>,
+    localized_last_name: Option<String>,
+    #[serde(default)]
+    elements: Vec<LinkedinEmailElement>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct LinkedinEmailElement {
+    #[serde(rename = "handle~")]
+    handle: Option<LinkedinEmailHandle>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LinkedinEmailHandle {
+    email_address: String,
+}
+
+impl From<LinkedinUserInfo> for UserInfo {
+    fn from(linkedin: LinkedinUserInfo) -> Self {
+        let name = [linkedin.localized_first_name, linkedin.localized_last_name]
+            .iter()
+            .flatten()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(" ");
+        let email = linkedin
+            .elements
+            .into_iter()
+            .find_map(|element| element.handle)
+            .map(|handle| handle.email_address);
+        UserInfo {
+            provider: "linkedin",
+            id: linkedin.id,
+            name,
+            username: None,
+            provider_email: email,
+            ..Default::default()
+        }
+    }
+}
+
+/// Slack `openid.connect.userInfo` endpoint. `sub` is their user id,
+/// which is unique across workspaces; failures come back as `200 OK`
+/// with `"ok": false` and no `sub`, so don't parse.
+/// See https://api.slack.com/methods/openid.connect.userInfo
+#[derive(Debug, Deserialize, Serialize)]
+struct SlackUserInfo {
+    sub: String,
+    name: Option<String>,
+    given_name: Option<String>,
+    family_name: Option<String>,
+    email: Option<String>,
+    email_verified: Option<bool>,
+}
+
+impl From<SlackUserInfo> for UserInfo {
+    fn from(slack: SlackUserInfo) -> Self {
+        let name = slack.name.unwrap_or_else(|| {
+            [slack.given_name, slack.family_name]
+                .iter()
+                .flatten()
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join(" ")
+        });
+        UserInfo {
+            provider: "slack",
+            id: slack.sub,
+            name,
+            username: None,
+            provider_email: slack.email,
+            ..Default::default()
+        }
+    }
+}
+
+/// Twitch Helix `users` endpoint. `email` needs the `user:read:email`
+/// scope.
+/// See https://dev.twitch.tv/docs/api/reference#get-users
+#[derive(Debug, Deserialize, Serialize)]
+struct TwitchUsers {
+    data: Vec<TwitchUserInfo>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct TwitchUserInfo {
+    id: String,
+    login: String,
+    display_name: String,
+    email: Option<String>,
+}
+
+impl From<TwitchUserInfo> for UserInfo {
+    fn from(twitch: TwitchUserInfo) -> Self {
+        UserInfo {
+            provider: "twitch",
+            id: twitch.id,
+            name: twitch.display_name,
+            username: Some(twitch.login),
+            provider_email: twitch.email,
+            ..Default::default()
+        }
+    }
+}
+
+/// The claims of Apple's `id_token`. There's no name: Apple only sends
+/// that with the first callback, in its `user` field.
+/// See https://developer.apple.com/documentation/sign_in_with_apple/sign_in_with_apple_rest_api/authenticating_users_with_sign_in_with_apple
+#[derive(Debug, Deserialize, Serialize)]
+struct AppleIdToken {
+    sub: String,
+    email: Option<String>,
+    // Apple sends these as booleans or strings.
+    email_verified: Option<Value>,
+    is_private_email: Option<Value>,
+}
+
+impl From<AppleIdToken> for UserInfo {
+    fn from(apple: AppleIdToken) -> Self {
+        UserInfo {
+            provider: "apple",
+            id: apple.sub,
+            name: String::new(),
+            username: None,
+            provider_email: apple.email,
+            ..Default::default()
+        }
+    }
+}
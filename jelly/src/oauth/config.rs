@@ -0,0 +1,165 @@
+//! Providers from a TOML file at `OAUTH_PROVIDERS_FILE`, so scopes can
+//! change and providers come and go without rebuilding. A table with a
+//! built-in provider's name changes just the keys it sets; any other name
+//! adds a provider, which needs its client id variable, endpoints, and
+//! `fields` saying where in the profile (as JSON pointers) the user is:
+//!
+//! ```toml
+//! [providers.google]
+//! scopes = ["openid", "email", "profile"]
+//!
+//! [providers.discord]
+//! client_id_env = "DISCORD_CLIENT_ID"
+//! client_secret_env = "DISCORD_CLIENT_SECRET"
+//! auth_url = "https://discord.com/oauth2/authorize"
+//! token_url = "https://discord.com/api/oauth2/token"
+//! scopes = ["identify", "email"]
+//! headers = { Accept = "application/json" }
+//! user_info = [{ url = "https://discord.com/api/users/@me" }]
+//! fields = { id = "/id", name = "/global_name", username = "/username", email = "/email" }
+//! ```
+//!
+//! The file's read once, when `Server::run` starts. One that's missing or
+//! invalid stops it starting, rather than leaving a provider quietly
+//! unconfigured.
+
+use std::collections::BTreeMap;
+use std::env;
+
+use serde::{de, Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::OAuthError;
+use crate::oauth::UserInfo;
+
+/// The providers file, by provider name.
+#[derive(Debug, Default, Deserialize)]
+pub struct ProvidersFile {
+    #[serde(default)]
+    pub providers: BTreeMap<String, ProviderConfig>,
+}
+
+/// A provider's settings. Each is optional, as for built-in providers they
+/// only change what's there; see `ProvidersFile::parse` for what a new one
+/// needs.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProviderConfig {
+    pub client_id_env: Option<String>,
+    pub client_secret_env: Option<String>,
+    pub auth_url: Option<String>,
+    pub token_url: Option<String>,
+    pub revoke_url: Option<String>,
+    /// The device authorization endpoint, for the device flow.
+    pub device_auth_url: Option<String>,
+    /// Sends the client id and secret in the token request's body, rather
+    /// than with HTTP Basic auth.
+    pub secret_in_body: Option<bool>,
+    pub scopes: Option<Vec<String>>,
+    /// The authorization URL parameter the email address being signed in
+    /// goes in, e.g `login_hint`.
+    pub login_hint_key: Option<String>,
+    /// Whether to ask for an email address to pass as the hint.
+    pub uses_email_hint: Option<bool>,
+    pub auth_params: Option<BTreeMap<String, String>>,
+    /// The endpoints the profile's fetched from, in turn.
+    pub user_info: Option<Vec<EndpointConfig>>,
+    /// Headers for the user info requests.
+    pub headers: Option<BTreeMap<String, String>>,
+    pub fields: Option<UserInfoFields>,
+}
+
+/// A user info endpoint, and its query parameters.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EndpointConfig {
+    pub url: String,
+    #[serde(default)]
+    pub params: BTreeMap<String, String>,
+}
+
+/// Where in the profile (merged, if there's more than one endpoint) each
+/// part of the user is, as JSON pointers (e.g `/data/id`). Only the id has
+/// to be there.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct UserInfoFields {
+    /// The provider's name; set when the client's built.
+    #[serde(skip)]
+    pub provider: &'static str,
+    pub id: String,
+    pub name: Option<String>,
+    pub username: Option<String>,
+    pub email: Option<String>,
+}
+
+impl ProvidersFile {
+    /// Reads the providers file's contents. New providers are checked for
+    /// what they need here, so a typo shows up at startup rather than at
+    /// someone's first sign in.
+    pub fn parse(contents: &str, builtin: &[&str]) -> Result<Self, OAuthError> {
+        let file: ProvidersFile =
+            toml::from_str(contents).map_err(|e| OAuthError::RegisterProviderError(e.to_string()))?;
+
+        for (name, provider) in file.providers.iter() {
+            if builtin.contains(&name.as_str()) {
+                continue;
+            }
+            let missing = [
+                ("client_id_env", provider.client_id_env.is_none()),
+                ("auth_url", provider.auth_url.is_none()),
+                ("token_url", provider.token_url.is_none()),
+                ("user_info", provider.user_info.as_ref().map_or(true, Vec::is_empty)),
+                ("fields", provider.fields.is_none()),
+            ];
+            if let Some((key, _)) = missing.iter().find(|(_, missing)| *missing) {
+                return Err(OAuthError::RegisterProviderError(format!("{} needs {}", name, key)));
+            }
+        }
+        Ok(file)
+    }
+
+    /// The file at `OAUTH_PROVIDERS_FILE`, or none if it isn't set.
+    pub fn load(builtin: &[&str]) -> Result<Self, OAuthError> {
+        let path = match env::var("OAUTH_PROVIDERS_FILE") {
+            Ok(path) if !path.is_empty() => path,
+            _ => return Ok(ProvidersFile::default()),
+        };
+        let contents = std::fs::read_to_string(&path).map_err(|e| {
+            OAuthError::RegisterProviderError(format!("unable to read OAUTH_PROVIDERS_FILE {}: {:?}", path, e))
+        })?;
+        ProvidersFile::parse(&contents, builtin)
+            .map_err(|e| OAuthError::RegisterProviderError(format!("invalid OAUTH_PROVIDERS_FILE {}: {}", path, e)))
+    }
+}
+
+impl UserInfoFields {
+    /// Reads the user from a profile's JSON.
+    pub fn parse(&self, json_body: &str, email: &str) -> serde_json::Result<UserInfo> {
+        let profile: Value = serde_json::from_str(json_body)?;
+        let field = |pointer: Option<&str>| {
+            pointer
+                .and_then(|pointer| as_string(profile.pointer(pointer)?))
+                .filter(|value| !value.is_empty())
+        };
+
+        let id = field(Some(&self.id)).ok_or_else(|| de::Error::custom(format!("no {} in the profile", self.id)))?;
+        Ok(UserInfo {
+            provider: self.provider,
+            id,
+            name: field(self.name.as_deref()).unwrap_or_default(),
+            username: field(self.username.as_deref()),
+            provider_email: field(self.email.as_deref()),
+            login_email: email.to_string(),
+        })
+    }
+}
+
+/// Strings as they are, and numbers (ids, often) written out.
+fn as_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value.clone()),
+        Value::Number(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
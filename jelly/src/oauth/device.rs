@@ -0,0 +1,200 @@
+//! The device authorization grant (RFC 8628), for clients with no browser
+//! of their own, like CLIs and TVs. `start` asks the provider for a code
+//! the user enters on another device, at its verification URI; the client
+//! then `poll`s, no faster than the interval it was given, until the user
+//! has (or hasn't) approved it. Only providers with a device authorization
+//! endpoint support it: Google, GitHub and Microsoft among the built-in
+//! ones, or any with `device_auth_url` in the providers file.
+//!
+//! Polls are made one at a time rather than in a loop, so the client can
+//! come back for each one, and nothing waits on the user in the meantime.
+
+use std::result;
+
+use oauth2::http::header::{HeaderMap, HeaderValue, ACCEPT, CONTENT_TYPE};
+use oauth2::http::method::Method;
+use oauth2::url::{form_urlencoded, Url};
+use serde::{Deserialize, Serialize};
+
+use crate::error::OAuthError;
+use crate::oauth::{IdTokenResponse, ScopedClient, TokenInfo};
+
+/// The token request's `grant_type` for device codes.
+pub const GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// How long to wait between polls when the provider doesn't say.
+pub const DEFAULT_INTERVAL: u64 = 5;
+
+/// What `slow_down` adds to the interval (RFC 8628 3.5).
+pub const SLOW_DOWN_SECONDS: u64 = 5;
+
+/// Where a provider's device flow is, and the credentials it's asked with.
+/// They're sent in the request body, as public clients (which most device
+/// clients are) have no other way to.
+#[derive(Clone, Debug)]
+pub struct DeviceEndpoint {
+    pub authorization_url: String,
+    pub token_url: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+}
+
+/// The provider's answer to `start`. `device_code` is the client's secret;
+/// the rest is for showing the user.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DeviceAuthorization {
+    pub device_code: String,
+    pub user_code: String,
+    /// Google calls it `verification_url`.
+    #[serde(alias = "verification_url")]
+    pub verification_uri: String,
+    /// The verification URI with the user code filled in, if the provider
+    /// has one, e.g for a QR code.
+    #[serde(default)]
+    pub verification_uri_complete: Option<String>,
+    pub expires_in: u64,
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+}
+
+fn default_interval() -> u64 {
+    DEFAULT_INTERVAL
+}
+
+/// What a poll came to.
+#[derive(Debug)]
+pub enum DevicePoll {
+    /// The user hasn't finished yet; poll again after the interval.
+    Pending,
+    /// Polling too often; add `SLOW_DOWN_SECONDS` to the interval.
+    SlowDown,
+    /// The user approved, and here's the token.
+    Granted(Box<IdTokenResponse>),
+    /// The user said no.
+    Denied,
+    /// The device code's expired; the client has to start over.
+    Expired,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: String,
+    error_description: Option<String>,
+}
+
+/// Whether `client`'s provider supports the device flow.
+pub fn supported(client: &ScopedClient) -> bool {
+    client.device.is_some()
+}
+
+fn endpoint(client: &ScopedClient) -> result::Result<&DeviceEndpoint, OAuthError> {
+    client.device.as_ref().ok_or(OAuthError::DeviceFlowUnsupported)
+}
+
+/// A form post of `params`, with the client's credentials, to `url`.
+/// `Accept` is set as GitHub answers with a form otherwise.
+fn form_request(
+    url: &str,
+    endpoint: &DeviceEndpoint,
+    params: &[(&str, &str)],
+) -> result::Result<oauth2::HttpRequest, OAuthError> {
+    let url = Url::parse(url).map_err(|e| OAuthError::DeviceFlowError(e.to_string()))?;
+
+    let mut body = form_urlencoded::Serializer::new(String::new());
+    body.append_pair("client_id", &endpoint.client_id);
+    if let Some(secret) = &endpoint.client_secret {
+        body.append_pair("client_secret", secret);
+    }
+    body.extend_pairs(params.iter());
+
+    let mut headers = HeaderMap::new();
+    headers.append(CONTENT_TYPE, HeaderValue::from_static("application/x-www-form-urlencoded"));
+    headers.append(ACCEPT, HeaderValue::from_static("application/json"));
+
+    Ok(oauth2::HttpRequest {
+        method: Method::POST,
+        url,
+        headers,
+        body: body.finish().into_bytes(),
+    })
+}
+
+/// Asks the provider for a device code and user code, with the client's
+/// scopes.
+pub async fn start(client: &ScopedClient) -> result::Result<DeviceAuthorization, OAuthError> {
+    let endpoint = endpoint(client)?;
+    let scope = client.scopes.join(" ");
+    let request = form_request(&endpoint.authorization_url, endpoint, &[("scope", &scope)])?;
+
+    let response = (client.transport)(request).await.map_err(OAuthError::DeviceRequestError)?;
+    parse_authorization_response(&response)
+}
+
+/// Reads the provider's answer to `start`.
+pub fn parse_authorization_response(
+    response: &oauth2::HttpResponse,
+) -> result::Result<DeviceAuthorization, OAuthError> {
+    if !response.status_code.is_success() {
+        return Err(OAuthError::DeviceFlowError(describe_error(&response.body)));
+    }
+    serde_json::from_slice(&response.body).map_err(|e| OAuthError::DeviceFlowError(e.to_string()))
+}
+
+/// Asks the provider, once, whether the user's approved `device_code`.
+pub async fn poll(client: &ScopedClient, device_code: &str) -> result::Result<DevicePoll, OAuthError> {
+    let endpoint = endpoint(client)?;
+    let request = form_request(
+        &endpoint.token_url,
+        endpoint,
+        &[("grant_type", GRANT_TYPE), ("device_code", device_code)],
+    )?;
+
+    let response = (client.transport)(request).await.map_err(OAuthError::DeviceRequestError)?;
+    parse_poll_response(&response)
+}
+
+/// Reads the provider's answer to a poll. GitHub sends its errors with a
+/// 200, so the body's checked for one whatever the status.
+pub fn parse_poll_response(response: &oauth2::HttpResponse) -> result::Result<DevicePoll, OAuthError> {
+    if let Ok(error) = serde_json::from_slice::<ErrorResponse>(&response.body) {
+        return match error.error.as_str() {
+            "authorization_pending" => Ok(DevicePoll::Pending),
+            "slow_down" => Ok(DevicePoll::SlowDown),
+            "access_denied" => Ok(DevicePoll::Denied),
+            "expired_token" => Ok(DevicePoll::Expired),
+            _ => Err(OAuthError::DeviceFlowError(match error.error_description {
+                Some(description) => format!("{}: {}", error.error, description),
+                None => error.error,
+            })),
+        };
+    }
+    if !response.status_code.is_success() {
+        return Err(OAuthError::DeviceFlowError(describe_error(&response.body)));
+    }
+
+    serde_json::from_slice(&response.body)
+        .map(|token| DevicePoll::Granted(Box::new(token)))
+        .map_err(|e| OAuthError::DeviceFlowError(e.to_string()))
+}
+
+/// What's needed to fetch the profile of the user who approved, with
+/// `fetch_user_info`. There's no login email, as they signed in elsewhere.
+pub fn token_info(client: &ScopedClient, provider: &str, response: IdTokenResponse) -> TokenInfo {
+    TokenInfo {
+        provider: provider.to_string(),
+        email: String::new(),
+        response,
+        user_info_request: client.user_info_request.clone(),
+        transport: client.transport,
+        scopes: client.scopes.clone(),
+        // There's no nonce to check it with; see `fetch_user_info`.
+        id_token_claims: None,
+    }
+}
+
+fn describe_error(body: &[u8]) -> String {
+    match serde_json::from_slice::<ErrorResponse>(body) {
+        Ok(error) => error.error,
+        Err(_) => String::from_utf8_lossy(body).chars().take(200).collect(),
+    }
+}
@@ -0,0 +1,269 @@
+//! OpenID Connect `id_token`s, checked against the keys the provider
+//! publishes (its JWKS) rather than taken on trust, so their claims can
+//! stand in for a second request to its user info endpoint. RS256 and
+//! ES256 signatures are supported, which covers Google, Microsoft, Apple
+//! and Slack.
+//!
+//! Authorization requests carry a `nonce` made from their `state`, so a
+//! token can't be replayed into another sign in; `request_token` checks
+//! it, with the issuer, audience and expiry, and puts the claims on
+//! `TokenInfo::id_token_claims`.
+
+use std::collections::HashMap;
+use std::result;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use constant_time_eq::constant_time_eq;
+use lazy_static::lazy_static;
+use oauth2::http::header::{HeaderMap, HeaderValue, ACCEPT};
+use oauth2::http::method::Method;
+use oauth2::url;
+use p256::ecdsa::signature::Verifier;
+use rsa::{BigUint, Hash, PaddingScheme, PublicKey, RsaPublicKey};
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::error::OAuthError;
+use crate::oauth::Transport;
+
+/// How far past its expiry a token is still taken, for clock skew.
+pub const LEEWAY_SECONDS: i64 = 60;
+
+/// How long fetched keys are used before they're fetched again. Keys
+/// the cache doesn't have are fetched straight away, so rotations don't
+/// wait on this.
+const KEYS_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Who issues a provider's `id_token`s, where its keys are, and the
+/// audience (our client id) they're for.
+#[derive(Clone, Debug)]
+pub struct Issuer {
+    /// The `iss` claim; `{tenantid}` stands for any one path segment, for
+    /// Microsoft's multi-tenant endpoints.
+    pub issuer: String,
+    pub jwks_url: String,
+    pub client_id: String,
+}
+
+impl Issuer {
+    /// Whether `iss` is this issuer.
+    pub fn matches(&self, iss: &str) -> bool {
+        match self.issuer.split_once("{tenantid}") {
+            Some((prefix, suffix)) => {
+                iss.len() > prefix.len() + suffix.len()
+                    && iss.starts_with(prefix)
+                    && iss.ends_with(suffix)
+                    && !iss[prefix.len()..iss.len() - suffix.len()].contains('/')
+            }
+            None => iss == self.issuer,
+        }
+    }
+}
+
+/// A JSON Web Key Set.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Jwks {
+    pub keys: Vec<Jwk>,
+}
+
+/// One public key; RSA keys have `n` and `e`, EC keys `crv`, `x` and `y`.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Jwk {
+    pub kty: String,
+    pub kid: Option<String>,
+    pub alg: Option<String>,
+    pub n: Option<String>,
+    pub e: Option<String>,
+    pub crv: Option<String>,
+    pub x: Option<String>,
+    pub y: Option<String>,
+}
+
+impl Jwks {
+    /// The key with `kid`, or without one, the only key there is.
+    pub fn find(&self, kid: Option<&str>) -> Option<&Jwk> {
+        match kid {
+            Some(kid) => self.keys.iter().find(|key| key.kid.as_deref() == Some(kid)),
+            None if self.keys.len() == 1 => self.keys.first(),
+            None => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Header {
+    alg: String,
+    kid: Option<String>,
+}
+
+fn error(message: &str) -> OAuthError {
+    OAuthError::IdTokenError(message.to_string())
+}
+
+fn decode(part: &str) -> result::Result<Vec<u8>, OAuthError> {
+    base64::decode_config(part, base64::URL_SAFE_NO_PAD).map_err(|e| OAuthError::IdTokenError(e.to_string()))
+}
+
+/// The `nonce` sent with an authorization request whose `state` is
+/// `state`; it's kept by keeping the state.
+pub fn nonce(state: &str) -> String {
+    base64::encode_config(Sha256::digest(state.as_bytes()), base64::URL_SAFE_NO_PAD)
+}
+
+fn verify_signature(key: &Jwk, alg: &str, signing_input: &[u8], signature: &[u8]) -> result::Result<(), OAuthError> {
+    if key.alg.as_deref().map_or(false, |key_alg| key_alg != alg) {
+        return Err(error("signed with a different algorithm than its key"));
+    }
+
+    let field = |value: &Option<String>| decode(value.as_deref().ok_or_else(|| error("key is incomplete"))?);
+    let valid = match (alg, key.kty.as_str()) {
+        ("RS256", "RSA") => {
+            let (n, e) = (BigUint::from_bytes_be(&field(&key.n)?), BigUint::from_bytes_be(&field(&key.e)?));
+            let public = RsaPublicKey::new(n, e).map_err(|e| OAuthError::IdTokenError(e.to_string()))?;
+            let hashed = Sha256::digest(signing_input);
+            public
+                .verify(PaddingScheme::new_pkcs1v15_sign(Some(Hash::SHA2_256)), &hashed, signature)
+                .is_ok()
+        }
+        ("ES256", "EC") if key.crv.as_deref() == Some("P-256") => {
+            let mut point = vec![0x04];
+            point.extend(field(&key.x)?);
+            point.extend(field(&key.y)?);
+            let public = p256::ecdsa::VerifyingKey::from_sec1_bytes(&point).map_err(|_| error("key is not on P-256"))?;
+            match p256::ecdsa::Signature::try_from(signature) {
+                Ok(signature) => public.verify(signing_input, &signature).is_ok(),
+                Err(_) => false,
+            }
+        }
+        _ => return Err(OAuthError::IdTokenError(format!("{} isn't supported for {} keys", alg, key.kty))),
+    };
+
+    match valid {
+        true => Ok(()),
+        false => Err(error("signature doesn't match")),
+    }
+}
+
+fn check_claims(claims: &Value, issuer: &Issuer, nonce: Option<&str>, now: i64) -> result::Result<(), OAuthError> {
+    match claims["iss"].as_str() {
+        Some(iss) if issuer.matches(iss) => {}
+        _ => return Err(error("issued by someone else")),
+    }
+
+    let for_us = match &claims["aud"] {
+        Value::String(aud) => *aud == issuer.client_id,
+        Value::Array(auds) => auds.iter().any(|aud| aud.as_str() == Some(issuer.client_id.as_str())),
+        _ => false,
+    };
+    if !for_us {
+        return Err(error("meant for another client"));
+    }
+
+    match claims["exp"].as_i64() {
+        Some(exp) if exp + LEEWAY_SECONDS > now => {}
+        _ => return Err(error("expired")),
+    }
+
+    if let Some(nonce) = nonce {
+        match claims["nonce"].as_str() {
+            Some(claim) if constant_time_eq(claim.as_bytes(), nonce.as_bytes()) => {}
+            _ => return Err(error("nonce doesn't match")),
+        }
+    }
+    Ok(())
+}
+
+/// The `kid` in `id_token`'s header, if it has one.
+pub fn key_id(id_token: &str) -> result::Result<Option<String>, OAuthError> {
+    let header = id_token.split('.').next().ok_or_else(|| error("not a JWT"))?;
+    let header: Header = serde_json::from_slice(&decode(header)?).map_err(|e| OAuthError::IdTokenError(e.to_string()))?;
+    Ok(header.kid)
+}
+
+/// `id_token`'s claims, as JSON, once its signature's checked with `keys`
+/// and it's checked to be from `issuer`, for us, unexpired at `now` (a
+/// Unix timestamp), and with `nonce` if there's one to expect.
+pub fn verify(
+    id_token: &str,
+    keys: &Jwks,
+    issuer: &Issuer,
+    nonce: Option<&str>,
+    now: i64,
+) -> result::Result<String, OAuthError> {
+    let parts: Vec<&str> = id_token.split('.').collect();
+    let (header, payload, signature) = match parts.as_slice() {
+        [header, payload, signature] => (*header, *payload, *signature),
+        _ => return Err(error("not a JWT")),
+    };
+
+    let parsed: Header = serde_json::from_slice(&decode(header)?).map_err(|e| OAuthError::IdTokenError(e.to_string()))?;
+    let key = keys.find(parsed.kid.as_deref()).ok_or_else(|| error("signed with an unknown key"))?;
+    let signing_input = format!("{}.{}", header, payload);
+    verify_signature(key, &parsed.alg, signing_input.as_bytes(), &decode(signature)?)?;
+
+    let json = String::from_utf8(decode(payload)?).map_err(|e| OAuthError::IdTokenError(e.to_string()))?;
+    let claims: Value = serde_json::from_str(&json).map_err(|e| OAuthError::IdTokenError(e.to_string()))?;
+    check_claims(&claims, issuer, nonce, now)?;
+    Ok(json)
+}
+
+lazy_static! {
+    static ref KEYS: RwLock<HashMap<String, (Instant, Jwks)>> = RwLock::new(HashMap::new());
+}
+
+/// Fetches the keys at `jwks_url`, and caches them.
+pub async fn fetch_keys(jwks_url: &str, transport: Transport) -> result::Result<Jwks, OAuthError> {
+    let url = url::Url::parse(jwks_url).map_err(|e| OAuthError::IdTokenError(e.to_string()))?;
+    let mut headers = HeaderMap::new();
+    headers.append(ACCEPT, HeaderValue::from_static("application/json"));
+    let request = oauth2::HttpRequest {
+        method: Method::GET,
+        url,
+        headers,
+        body: Vec::new(),
+    };
+
+    let response = transport(request).await.map_err(|e| OAuthError::IdTokenError(format!("{:?}", e)))?;
+    if !response.status_code.is_success() {
+        return Err(OAuthError::IdTokenError(format!(
+            "fetching keys failed with {}",
+            response.status_code
+        )));
+    }
+    let keys: Jwks = serde_json::from_slice(&response.body).map_err(|e| OAuthError::IdTokenError(e.to_string()))?;
+
+    KEYS.write()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(jwks_url.to_string(), (Instant::now(), keys.clone()));
+    Ok(keys)
+}
+
+/// `issuer`'s keys, cached unless they're old or haven't the one `kid`
+/// names.
+async fn keys_for(issuer: &Issuer, kid: Option<&str>, transport: Transport) -> result::Result<Jwks, OAuthError> {
+    let cached = KEYS
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(&issuer.jwks_url)
+        .filter(|(fetched, keys)| fetched.elapsed() < KEYS_MAX_AGE && keys.find(kid).is_some())
+        .map(|(_, keys)| keys.clone());
+    match cached {
+        Some(keys) => Ok(keys),
+        None => fetch_keys(&issuer.jwks_url, transport).await,
+    }
+}
+
+/// `verify`, with `issuer`'s keys (fetched through `transport` if they
+/// aren't cached) and the time now.
+pub async fn verify_id_token(
+    id_token: &str,
+    issuer: &Issuer,
+    nonce: Option<&str>,
+    transport: Transport,
+) -> result::Result<String, OAuthError> {
+    let kid = key_id(id_token)?;
+    let keys = keys_for(issuer, kid.as_deref(), transport).await?;
+    verify(id_token, &keys, issuer, nonce, chrono::Utc::now().timestamp())
+}
@@ -0,0 +1,245 @@
+//! PDFs rendered from Tera templates, e.g for receipts. The HTML is handed
+//! to an external tool to lay out, picked with `PDF_RENDERER`: `weasyprint`
+//! (the default) or `chromium`, run headless. `PDF_RENDERER_BIN` overrides
+//! where the binary is; anything else can be plugged in with `set_renderer`.
+//!
+//! Rendering is slow, so it's done by the `RenderPdf` job, which stores the
+//! result under the tenant's storage directory (see `storage`) and can email
+//! a signed link to download it:
+//!
+//! ```ignore
+//! request.job_queue()?.queue(RenderPdf {
+//!     template: "receipts/usage.html".into(),
+//!     context: context.into_json(),
+//!     tenant,
+//!     name: "receipts/2022-04.pdf".into(),
+//!     notify: Some(Notify { to: email, subject: "Your receipt".into(), template: "email/receipt-ready".into() }),
+//!     progress: None,
+//! }).await?;
+//! ```
+
+use std::env;
+use std::future::Future;
+use std::io::Write;
+use std::pin::Pin;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, RwLock};
+
+use anyhow::anyhow;
+use background_jobs::Job;
+use chrono::{Duration, Utc};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use tera::{Context, Tera};
+
+use crate::email::Email;
+use crate::error::Error;
+use crate::jobs::{JobState, DEFAULT_QUEUE};
+use crate::progress;
+use crate::storage;
+use crate::tenancy::TenantId;
+
+/// Turns a page of HTML into a PDF.
+pub trait Renderer: Send + Sync {
+    fn render(&self, html: &str) -> Result<Vec<u8>, Error>;
+}
+
+/// WeasyPrint, reading the HTML from stdin and writing the PDF to stdout.
+pub struct WeasyPrint {
+    pub binary: String,
+}
+
+impl Renderer for WeasyPrint {
+    fn render(&self, html: &str) -> Result<Vec<u8>, Error> {
+        let mut child = Command::new(&self.binary)
+            .args(["--quiet", "-", "-"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| Error::Generic(format!("Error starting {}: {:?}", self.binary, e)))?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(html.as_bytes())
+                .map_err(|e| Error::Generic(format!("Error writing to {}: {:?}", self.binary, e)))?;
+        }
+
+        output(&self.binary, child.wait_with_output())
+    }
+}
+
+/// Headless Chromium (or Chrome). It only prints pages it can load, so the
+/// HTML goes through a temporary file.
+pub struct Chromium {
+    pub binary: String,
+}
+
+impl Renderer for Chromium {
+    fn render(&self, html: &str) -> Result<Vec<u8>, Error> {
+        let id = uuid::Uuid::new_v4();
+        let input = env::temp_dir().join(format!("jelly-pdf-{}.html", id));
+        let pdf = env::temp_dir().join(format!("jelly-pdf-{}.pdf", id));
+        std::fs::write(&input, html).map_err(|e| Error::Generic(format!("Error writing PDF input: {:?}", e)))?;
+
+        let result = output(
+            &self.binary,
+            Command::new(&self.binary)
+                .args(["--headless", "--disable-gpu", "--no-sandbox", "--no-pdf-header-footer"])
+                .arg(format!("--print-to-pdf={}", pdf.display()))
+                .arg(format!("file://{}", input.display()))
+                .output(),
+        )
+        .and_then(|_| std::fs::read(&pdf).map_err(|e| Error::Generic(format!("Error reading PDF: {:?}", e))));
+
+        let _ = std::fs::remove_file(&input);
+        let _ = std::fs::remove_file(&pdf);
+        result
+    }
+}
+
+/// A finished renderer process' stdout, or an error with its stderr.
+fn output(binary: &str, output: std::io::Result<std::process::Output>) -> Result<Vec<u8>, Error> {
+    let output = output.map_err(|e| Error::Generic(format!("Error running {}: {:?}", binary, e)))?;
+    if !output.status.success() {
+        return Err(Error::Generic(format!(
+            "{} failed ({}): {}",
+            binary,
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        )));
+    }
+    Ok(output.stdout)
+}
+
+/// The renderer `PDF_RENDERER` asks for.
+fn from_env() -> Arc<dyn Renderer> {
+    let binary = env::var("PDF_RENDERER_BIN").ok();
+    match env::var("PDF_RENDERER").as_deref() {
+        Ok("chromium") => Arc::new(Chromium {
+            binary: binary.unwrap_or_else(|| "chromium".to_string()),
+        }),
+        _ => Arc::new(WeasyPrint {
+            binary: binary.unwrap_or_else(|| "weasyprint".to_string()),
+        }),
+    }
+}
+
+lazy_static! {
+    static ref RENDERER: RwLock<Arc<dyn Renderer>> = RwLock::new(from_env());
+}
+
+/// Replaces the `Renderer`; call once at startup.
+pub fn set_renderer<R: Renderer + 'static>(renderer: R) {
+    match RENDERER.write() {
+        Ok(mut current) => *current = Arc::new(renderer),
+        Err(e) => error!("Unable to set PDF renderer: {:?}", e),
+    }
+}
+
+fn renderer() -> Arc<dyn Renderer> {
+    RENDERER.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Renders `template` with `context` to a PDF. This blocks while the
+/// renderer runs, so call it from a job (or `web::block`).
+pub fn render(templates: &RwLock<Tera>, template: &str, context: &Context) -> Result<Vec<u8>, Error> {
+    let html = templates
+        .read()
+        .map_err(|e| Error::Generic(format!("Error acquiring template read lock: {:?}", e)))?
+        .render(template, context)?;
+    renderer().render(&html)
+}
+
+/// How long emailed download links work for: `PDF_LINK_DAYS`, 7 by default.
+fn link_days() -> i64 {
+    env::var("PDF_LINK_DAYS")
+        .ok()
+        .and_then(|days| days.parse().ok())
+        .unwrap_or(7)
+}
+
+/// Who to email a download link to once a PDF is ready. `template` gets
+/// `file_name`, `download_url` and `expires` in its context.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Notify {
+    pub to: String,
+    pub subject: String,
+    pub template: String,
+}
+
+/// Renders `template` to a PDF, and stores it as `tenant`'s file `name`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RenderPdf {
+    pub template: String,
+    /// A JSON object; see `tera::Context::into_json`.
+    pub context: serde_json::Value,
+    pub tenant: TenantId,
+    pub name: String,
+    pub notify: Option<Notify>,
+    /// A `progress` id to report to, if the account's watching.
+    #[serde(default)]
+    pub progress: Option<i32>,
+}
+
+impl Job for RenderPdf {
+    type State = JobState;
+    type Future = Pin<Box<dyn Future<Output = Result<(), anyhow::Error>> + Send>>;
+
+    const NAME: &'static str = "RenderPdfJob";
+    const QUEUE: &'static str = DEFAULT_QUEUE;
+
+    fn run(self, state: JobState) -> Self::Future {
+        Box::pin(async move {
+            let path = storage::path_for(self.tenant, &self.name)
+                .map_err(|e| anyhow!("Error finding PDF path: {:?}", e))?
+                .ok_or_else(|| anyhow!("Invalid PDF name {}", self.name))?;
+            let context = Context::from_value(self.context).map_err(|e| anyhow!("Invalid PDF context: {:?}", e))?;
+
+            if let Some(id) = self.progress {
+                progress::report(&state.pool, id, 10, "Rendering")
+                    .await
+                    .map_err(|e| anyhow!("Error reporting progress: {:?}", e))?;
+            }
+
+            let templates = state.templates.clone();
+            let template = self.template;
+            let rendered = actix_rt::task::spawn_blocking(move || -> Result<(), Error> {
+                let pdf = render(&templates, &template, &context)?;
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent).map_err(|e| Error::Generic(format!("Error storing PDF: {:?}", e)))?;
+                }
+                std::fs::write(&path, pdf).map_err(|e| Error::Generic(format!("Error storing PDF: {:?}", e)))
+            })
+            .await
+            .map_err(|e| anyhow!("Error rendering PDF: {:?}", e))
+            .and_then(|rendered| rendered.map_err(|e| anyhow!("Error rendering PDF: {:?}", e)));
+            if let Some(id) = self.progress {
+                let reported = match &rendered {
+                    Ok(()) => progress::finish(&state.pool, id, "Ready").await,
+                    Err(_) => progress::fail(&state.pool, id, "Rendering failed").await,
+                };
+                reported.map_err(|e| anyhow!("Error reporting progress: {:?}", e))?;
+            }
+            rendered?;
+
+            let notify = match self.notify {
+                Some(notify) => notify,
+                None => return Ok(()),
+            };
+            let domain = env::var("JELLY_DOMAIN").expect("No JELLY_DOMAIN value set!");
+            let expires = Utc::now() + Duration::days(link_days());
+
+            let mut context = Context::new();
+            context.insert("file_name", &self.name);
+            context.insert(
+                "download_url",
+                &format!("{}{}", domain, storage::signed_url(self.tenant, &self.name, expires)),
+            );
+            context.insert("expires", &expires.format("%Y-%m-%d").to_string());
+
+            let email = Email::new(&notify.template, &[notify.to], &notify.subject, context, state.templates);
+            email?.send()
+        })
+    }
+}
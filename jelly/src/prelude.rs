@@ -16,7 +16,7 @@ pub use super::{
     //i18n::{I18nString},
 
     // Enables various helpers for actix_web's `HttpRequest` type.
-    request::{Authentication, DatabasePool, FlashMessages, JobQueue, Render},
+    request::{Authentication, Conditional, DatabasePool, Experiments, FlashMessages, Htmx, JobQueue, Permissions, Render, Tenant, Timezone, Turbo},
 
     tera::Context,
 };
@@ -0,0 +1,48 @@
+//! Pre-rendering public pages to files, for a CDN to serve in front of
+//! the app; see `Server::export_static`. The app still serves everything,
+//! these included, so a page missing from the export just falls through
+//! to it.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The file `path` is written to, relative to the export directory: paths
+/// whose last segment has an extension (`/feed.xml`) as they are, and the
+/// rest as an `index.html` in a directory of their own, so they keep the
+/// same URL on the CDN. `None` for anything that isn't a plain absolute
+/// path, e.g with a query or `..`.
+pub fn file_for(path: &str) -> Option<PathBuf> {
+    let rest = path.strip_prefix('/')?;
+    if rest.contains(|c: char| c == '?' || c == '#' || c == '\\') {
+        return None;
+    }
+
+    let segments: Vec<&str> = rest.split('/').collect();
+    let (last, dirs) = segments.split_last()?;
+    if dirs.iter().any(|segment| segment.is_empty() || segment.starts_with('.')) || last.starts_with('.') {
+        return None;
+    }
+
+    let mut file: PathBuf = dirs.iter().collect();
+    if !last.is_empty() {
+        file.push(last);
+    }
+    if !last.contains('.') {
+        file.push("index.html");
+    }
+    Some(file)
+}
+
+/// Writes `body` for `path` under `dir`, making directories as needed, and
+/// returns where it went.
+pub fn write(dir: &Path, path: &str, body: &[u8]) -> io::Result<PathBuf> {
+    let file = file_for(path)
+        .map(|file| dir.join(file))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("Can't export {}", path)))?;
+    if let Some(parent) = file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&file, body)?;
+    Ok(file)
+}
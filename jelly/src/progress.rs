@@ -0,0 +1,166 @@
+//! Progress reporting for long background jobs, so the account that asked
+//! for one can watch it run. The request `start`s a row in the
+//! `job_progress` table and hands its id to the job, which `report`s as it
+//! goes and `finish`es (or `fail`s) at the end:
+//!
+//! ```ignore
+//! let progress = progress::start(pool, account_id, "Usage receipt").await?;
+//! queue.queue(RenderPdf { progress: Some(progress), .. }).await?;
+//!
+//! // ...and in the job:
+//! progress::report(&state.pool, id, 50, "Rendering").await?;
+//! ```
+//!
+//! `events` streams an account's progress as server-sent events, for a
+//! dashboard widget to show live.
+
+use std::time::Duration;
+
+use actix_web::web::Bytes;
+use futures::stream::{self, Stream};
+use serde::Serialize;
+use sqlx::postgres::PgPool;
+
+use crate::error::Error;
+
+pub const RUNNING: &str = "running";
+pub const DONE: &str = "done";
+pub const FAILED: &str = "failed";
+
+/// How often `events` checks for changes.
+const POLL: Duration = Duration::from_secs(1);
+
+/// Where a job's got to.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Progress {
+    pub id: i32,
+    pub label: String,
+    pub percent: i16,
+    pub message: String,
+    pub status: String,
+}
+
+impl Progress {
+    pub fn is_running(&self) -> bool {
+        self.status == RUNNING
+    }
+}
+
+/// Starts tracking a job of `account_id`'s, described by `label`. Returns
+/// the id to report progress with. Old finished rows are tidied up here.
+pub async fn start(pool: &PgPool, account_id: i32, label: &str) -> Result<i32, Error> {
+    sqlx::query("DELETE FROM job_progress WHERE status <> $1 AND updated < now() - interval '1 day'")
+        .bind(RUNNING)
+        .execute(pool)
+        .await?;
+
+    let id: i32 = sqlx::query_scalar("INSERT INTO job_progress (account_id, label) VALUES ($1, $2) RETURNING id")
+        .bind(account_id)
+        .bind(label)
+        .fetch_one(pool)
+        .await?;
+    Ok(id)
+}
+
+async fn update(pool: &PgPool, id: i32, percent: i16, message: &str, status: &str) -> Result<(), Error> {
+    sqlx::query(
+        "
+        UPDATE job_progress SET percent = $2, message = $3, status = $4, updated = now()
+        WHERE id = $1
+    ",
+    )
+    .bind(id)
+    .bind(percent.clamp(0, 100))
+    .bind(message)
+    .bind(status)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Records that the job's `percent` done, and what it's doing.
+pub async fn report(pool: &PgPool, id: i32, percent: i16, message: &str) -> Result<(), Error> {
+    update(pool, id, percent, message, RUNNING).await
+}
+
+pub async fn finish(pool: &PgPool, id: i32, message: &str) -> Result<(), Error> {
+    update(pool, id, 100, message, DONE).await
+}
+
+/// Records that the job gave up, keeping how far it got.
+pub async fn fail(pool: &PgPool, id: i32, message: &str) -> Result<(), Error> {
+    sqlx::query("UPDATE job_progress SET message = $2, status = $3, updated = now() WHERE id = $1")
+        .bind(id)
+        .bind(message)
+        .bind(FAILED)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// `account_id`'s running jobs, and those that finished in the last hour.
+pub async fn for_account(pool: &PgPool, account_id: i32) -> Result<Vec<Progress>, Error> {
+    let rows: Vec<(i32, String, i16, String, String)> = sqlx::query_as(
+        "
+        SELECT id, label, percent, message, status FROM job_progress
+        WHERE account_id = $1 AND (status = $2 OR updated > now() - interval '1 hour')
+        ORDER BY created, id
+    ",
+    )
+    .bind(account_id)
+    .bind(RUNNING)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(id, label, percent, message, status)| Progress {
+            id,
+            label,
+            percent,
+            message,
+            status,
+        })
+        .collect())
+}
+
+/// A server-sent event.
+fn event(name: Option<&str>, data: &str) -> Bytes {
+    match name {
+        Some(name) => Bytes::from(format!("event: {}\ndata: {}\n\n", name, data)),
+        None => Bytes::from(format!("data: {}\n\n", data)),
+    }
+}
+
+/// `account_id`'s progress as server-sent events: the whole list as JSON
+/// whenever it changes, then an `idle` event once nothing's running, at
+/// which point the stream ends (and the client should stop listening).
+pub fn events(pool: PgPool, account_id: i32) -> impl Stream<Item = Result<Bytes, actix_web::Error>> {
+    stream::unfold(Some(None), move |state: Option<Option<Vec<Progress>>>| {
+        let pool = pool.clone();
+        async move {
+            let last = state?;
+            loop {
+                if last.is_some() {
+                    actix_rt::time::sleep(POLL).await;
+                }
+                let progress = match for_account(&pool, account_id).await {
+                    Ok(progress) => progress,
+                    Err(e) => {
+                        error!("Error loading job progress: {:?}", e);
+                        return None;
+                    }
+                };
+
+                if !progress.iter().any(Progress::is_running) {
+                    let data = serde_json::to_string(&progress).unwrap_or_default();
+                    return Some((Ok(event(Some("idle"), &data)), None));
+                }
+                if last.as_ref() != Some(&progress) {
+                    let data = serde_json::to_string(&progress).unwrap_or_default();
+                    return Some((Ok(event(None, &data)), Some(Some(progress))));
+                }
+            }
+        }
+    })
+}
@@ -0,0 +1,164 @@
+//! Push notifications to mobile apps, through Firebase Cloud Messaging
+//! (`push-fcm` feature) and the Apple Push Notification service
+//! (`push-apns`). Apps register their device tokens (kept in the
+//! `device_tokens` table), and `notify` sends a message to every device an
+//! account has:
+//!
+//! ```ignore
+//! push::notify(&pool, account_id, &Message::new("Upload quarantined", "...")).await?;
+//! ```
+//!
+//! From a request, queue `SendPush` instead, so it doesn't wait on the
+//! providers. Tokens a provider says are no longer valid (the app was
+//! uninstalled, say) are removed.
+
+use std::future::Future;
+use std::pin::Pin;
+
+use anyhow::anyhow;
+use background_jobs::Job;
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgPool;
+
+use crate::error::Error;
+use crate::jobs::{JobState, DEFAULT_QUEUE};
+
+#[cfg(feature = "push-apns")]
+pub mod apns;
+#[cfg(feature = "push-fcm")]
+pub mod fcm;
+
+/// Firebase Cloud Messaging, for Android (and anything else using it).
+pub const FCM: &str = "fcm";
+
+/// The Apple Push Notification service, for iOS.
+pub const APNS: &str = "apns";
+
+/// The providers compiled in.
+pub fn providers() -> Vec<&'static str> {
+    #[allow(unused_mut)]
+    let mut providers = Vec::new();
+    #[cfg(feature = "push-fcm")]
+    providers.push(FCM);
+    #[cfg(feature = "push-apns")]
+    providers.push(APNS);
+    providers
+}
+
+/// A notification, shown as an alert with `title` and `body`. `data` is
+/// handed to the app as is, e.g for where to go when it's tapped.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Message {
+    pub title: String,
+    pub body: String,
+    #[serde(default)]
+    pub data: Option<serde_json::Value>,
+}
+
+impl Message {
+    pub fn new(title: &str, body: &str) -> Self {
+        Message {
+            title: title.to_string(),
+            body: body.to_string(),
+            data: None,
+        }
+    }
+}
+
+/// How sending to a device went.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Delivery {
+    Sent,
+    /// The token's no longer valid, and should be forgotten.
+    Unregistered,
+}
+
+/// Sends `message` to the device with `token`, through `provider`.
+pub async fn send(provider: &str, token: &str, message: &Message) -> Result<Delivery, Error> {
+    match provider {
+        #[cfg(feature = "push-fcm")]
+        FCM => fcm::send(token, message).await,
+        #[cfg(feature = "push-apns")]
+        APNS => apns::send(token, message).await,
+        _ => Err(Error::Generic(format!("Push provider `{}` isn't enabled", provider))),
+    }
+}
+
+/// Registers a device of `account_id`'s. Tokens are unique, so a device
+/// that changes hands moves to its new account.
+pub async fn register(pool: &PgPool, account_id: i32, provider: &str, token: &str) -> Result<(), Error> {
+    sqlx::query(
+        "
+        INSERT INTO device_tokens (account_id, provider, token) VALUES ($1, $2, $3)
+        ON CONFLICT (token) DO UPDATE SET account_id = $1, provider = $2, updated = now()
+    ",
+    )
+    .bind(account_id)
+    .bind(provider)
+    .bind(token)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Forgets one of `account_id`'s devices, e.g when the user signs out of
+/// the app. Returns whether it was registered.
+pub async fn unregister(pool: &PgPool, account_id: i32, token: &str) -> Result<bool, Error> {
+    let result = sqlx::query("DELETE FROM device_tokens WHERE account_id = $1 AND token = $2")
+        .bind(account_id)
+        .bind(token)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Sends `message` to all of `account_id`'s devices, returning how many it
+/// reached. Failures for one device are logged rather than stopping the
+/// rest.
+pub async fn notify(pool: &PgPool, account_id: i32, message: &Message) -> Result<usize, Error> {
+    let devices: Vec<(i32, String, String)> =
+        sqlx::query_as("SELECT id, provider, token FROM device_tokens WHERE account_id = $1")
+            .bind(account_id)
+            .fetch_all(pool)
+            .await?;
+
+    let mut sent = 0;
+    for (id, provider, token) in devices {
+        match send(&provider, &token, message).await {
+            Ok(Delivery::Sent) => sent += 1,
+            Ok(Delivery::Unregistered) => {
+                debug!("Removing unregistered {} device {}", provider, id);
+                sqlx::query("DELETE FROM device_tokens WHERE id = $1")
+                    .bind(id)
+                    .execute(pool)
+                    .await?;
+            }
+            Err(e) => error!("Error sending push to {} device {}: {:?}", provider, id, e),
+        }
+    }
+    Ok(sent)
+}
+
+/// Sends a push notification to all of an account's devices.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SendPush {
+    pub account_id: i32,
+    pub message: Message,
+}
+
+impl Job for SendPush {
+    type State = JobState;
+    type Future = Pin<Box<dyn Future<Output = Result<(), anyhow::Error>> + Send>>;
+
+    const NAME: &'static str = "SendPushJob";
+    const QUEUE: &'static str = DEFAULT_QUEUE;
+
+    fn run(self, state: JobState) -> Self::Future {
+        Box::pin(async move {
+            notify(&state.pool, self.account_id, &self.message)
+                .await
+                .map_err(|e| anyhow!("Error sending push notification: {:?}", e))?;
+            Ok(())
+        })
+    }
+}
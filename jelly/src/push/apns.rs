@@ -0,0 +1,111 @@
+//! The Apple Push Notification service, with token-based auth: the `.p8`
+//! key at `APNS_KEY_PATH`, its `APNS_KEY_ID`, your `APNS_TEAM_ID`, and the
+//! app's bundle id as `APNS_TOPIC`. Set `APNS_SANDBOX` for development
+//! builds of the app.
+//!
+//! APNs only speaks HTTP/2, which minreq doesn't, hence reqwest here.
+
+use std::env;
+use std::sync::Mutex;
+
+use chrono::Utc;
+use lazy_static::lazy_static;
+use p256::ecdsa::signature::Signer;
+use p256::ecdsa::{Signature, SigningKey};
+use p256::pkcs8::DecodePrivateKey;
+use p256::SecretKey;
+use serde_json::{json, Value};
+
+use super::{Delivery, Message};
+use crate::error::Error;
+
+/// Apple rejects provider tokens more than an hour old, and throttles
+/// making new ones more often than every 20 minutes.
+const TOKEN_SECONDS: i64 = 50 * 60;
+
+lazy_static! {
+    static ref CLIENT: reqwest::Client = reqwest::Client::new();
+    static ref TOKEN: Mutex<Option<(String, i64)>> = Mutex::new(None);
+}
+
+fn var(name: &str) -> Result<String, Error> {
+    env::var(name).map_err(|_| Error::Generic(format!("{} not set!", name)))
+}
+
+/// The JWT APNs requests are authorized with, reused while it's fresh.
+fn provider_token() -> Result<String, Error> {
+    let now = Utc::now().timestamp();
+    let mut token = TOKEN.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((token, issued)) = &*token {
+        if now - issued < TOKEN_SECONDS {
+            return Ok(token.clone());
+        }
+    }
+
+    let pem = std::fs::read_to_string(var("APNS_KEY_PATH")?)
+        .map_err(|e| Error::Generic(format!("Error reading APNS_KEY_PATH: {:?}", e)))?;
+    let key = SecretKey::from_pkcs8_pem(&pem)
+        .map_err(|e| Error::Generic(format!("APNS_KEY_PATH is not a P-256 key: {:?}", e)))?;
+
+    let encode = |value: &Value| base64::encode_config(value.to_string(), base64::URL_SAFE_NO_PAD);
+    let signing_input = format!(
+        "{}.{}",
+        encode(&json!({ "alg": "ES256", "kid": var("APNS_KEY_ID")? })),
+        encode(&json!({ "iss": var("APNS_TEAM_ID")?, "iat": now }))
+    );
+    let signature: Signature = SigningKey::from(key).sign(signing_input.as_bytes());
+    let jwt = format!(
+        "{}.{}",
+        signing_input,
+        base64::encode_config(signature.as_ref(), base64::URL_SAFE_NO_PAD)
+    );
+
+    *token = Some((jwt.clone(), now));
+    Ok(jwt)
+}
+
+fn host() -> &'static str {
+    match env::var("APNS_SANDBOX") {
+        Ok(sandbox) if !sandbox.is_empty() && sandbox != "0" => "api.sandbox.push.apple.com",
+        _ => "api.push.apple.com",
+    }
+}
+
+/// Whether APNs' error `reason` means the token is no good.
+pub fn is_unregistered(status: u16, reason: &str) -> bool {
+    status == 410 || reason == "BadDeviceToken" || reason == "Unregistered"
+}
+
+pub async fn send(token: &str, message: &Message) -> Result<Delivery, Error> {
+    let mut body = json!({
+        "aps": {
+            "alert": { "title": message.title, "body": message.body },
+            "sound": "default",
+        }
+    });
+    if let Some(data) = &message.data {
+        body["data"] = data.clone();
+    }
+
+    let response = CLIENT
+        .post(format!("https://{}/3/device/{}", host(), token))
+        .bearer_auth(provider_token()?)
+        .header("apns-topic", var("APNS_TOPIC")?)
+        .header("apns-push-type", "alert")
+        .json(&body)
+        .send()
+        .await
+        .map_err(|e| Error::Generic(format!("Error sending to APNs: {:?}", e)))?;
+
+    let status = response.status().as_u16();
+    if status == 200 {
+        return Ok(Delivery::Sent);
+    }
+
+    let reply: Value = response.json().await.unwrap_or_default();
+    let reason = reply.get("reason").and_then(Value::as_str).unwrap_or("");
+    if is_unregistered(status, reason) {
+        return Ok(Delivery::Unregistered);
+    }
+    Err(Error::Generic(format!("APNs returned {}: {}", status, reason)))
+}
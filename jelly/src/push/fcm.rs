@@ -0,0 +1,60 @@
+//! Firebase Cloud Messaging, with the HTTP API and the server key in
+//! `FCM_SERVER_KEY`.
+
+use std::env;
+
+use serde_json::{json, Value};
+
+use super::{Delivery, Message};
+use crate::error::Error;
+
+const URL: &str = "https://fcm.googleapis.com/fcm/send";
+
+/// Reads FCM's reply for one token: it answers 200 either way, with any
+/// problem as the result's `error`.
+pub fn parse_reply(reply: &Value) -> Result<Delivery, Error> {
+    let error = reply
+        .get("results")
+        .and_then(|results| results.get(0))
+        .and_then(|result| result.get("error"))
+        .and_then(Value::as_str);
+
+    match error {
+        None => Ok(Delivery::Sent),
+        Some("NotRegistered") | Some("InvalidRegistration") => Ok(Delivery::Unregistered),
+        Some(error) => Err(Error::Generic(format!("FCM error: {}", error))),
+    }
+}
+
+pub async fn send(token: &str, message: &Message) -> Result<Delivery, Error> {
+    let key = env::var("FCM_SERVER_KEY").map_err(|_| Error::Generic("FCM_SERVER_KEY not set!".to_string()))?;
+    let body = json!({
+        "to": token,
+        "notification": { "title": message.title, "body": message.body },
+        "data": message.data,
+    });
+
+    // minreq blocks, so it's run off the runtime.
+    let response = actix_rt::task::spawn_blocking(move || {
+        minreq::post(URL)
+            .with_header("Authorization", format!("key={}", key))
+            .with_json(&body)?
+            .send()
+    })
+    .await
+    .map_err(|e| Error::Generic(format!("Error sending to FCM: {:?}", e)))?
+    .map_err(|e| Error::Generic(format!("Error sending to FCM: {:?}", e)))?;
+
+    if response.status_code != 200 {
+        return Err(Error::Generic(format!(
+            "FCM returned {}: {}",
+            response.status_code,
+            response.as_str().unwrap_or_default()
+        )));
+    }
+
+    let reply: Value = response
+        .json()
+        .map_err(|e| Error::Generic(format!("Unexpected reply from FCM: {:?}", e)))?;
+    parse_reply(&reply)
+}
@@ -0,0 +1,224 @@
+//! QR codes, rendered server-side at `/qr` as SVG or PNG (needs the `qr`
+//! feature; without it, `/qr` is a 404). Only payloads signed with
+//! `SECRET_KEY` are rendered, so the endpoint can't be used to generate
+//! arbitrary codes; the `qr_url` Tera function signs them for you:
+//!
+//! ```html
+//! <img src="{{ qr_url(data=invite_url, format="png") }}">
+//! ```
+//!
+//! Signed URLs always render the same image, so responses are cached hard
+//! by browsers, and recently rendered codes are kept in memory.
+//!
+//! The payload's in the URL, though, so secrets (e.g a TOTP provisioning
+//! URI) go through `pending_url` instead: it keeps the payload in the
+//! session, and `/qr/pending` renders it from there, uncached.
+//!
+//! ```rust,ignore
+//! let qr = qr::pending_url(&request.get_session(), &provisioning_uri, Format::Svg)?;
+//! request.render(200, "accounts/totp.html", { context.insert("qr", &qr); context })
+//! ```
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use actix_session::{Session, SessionExt};
+use actix_web::http::header::{CacheControl, CacheDirective};
+use actix_web::web::{self, ServiceConfig};
+use actix_web::{HttpRequest, HttpResponse};
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use tera::{Tera, Value};
+
+use crate::crypto;
+use crate::error::Error;
+use crate::routes;
+use crate::SESSION_QR_PENDING;
+
+const KEY_SALT: &str = "com.jelly.qr";
+
+/// How many rendered codes are kept in memory.
+const CACHE_SIZE: usize = 256;
+
+/// The image formats `/qr` renders.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Svg,
+    Png,
+}
+
+impl Default for Format {
+    fn default() -> Self {
+        Format::Svg
+    }
+}
+
+impl Format {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Format::Svg => "svg",
+            Format::Png => "png",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Format::Svg => "image/svg+xml",
+            Format::Png => "image/png",
+        }
+    }
+}
+
+lazy_static! {
+    static ref CACHE: Mutex<HashMap<(String, Format), Vec<u8>>> = Mutex::new(HashMap::new());
+}
+
+/// Signs `data` for `/qr`.
+pub fn sign(data: &str) -> String {
+    crypto::sign(KEY_SALT, data)
+}
+
+/// Whether `signature` came from `sign(data)`.
+pub fn verify(data: &str, signature: &str) -> bool {
+    crypto::verify(KEY_SALT, data, signature)
+}
+
+/// The `/qr` URL for `data`.
+pub fn url(data: &str, format: Format) -> String {
+    let encoded = base64::encode_config(data, base64::URL_SAFE_NO_PAD);
+    format!("/qr?d={}&s={}&f={}", encoded, sign(data), format.as_str())
+}
+
+/// Keeps `data` in the session for `/qr/pending`, and returns its URL;
+/// for payloads that shouldn't be in a URL or cached.
+pub fn pending_url(session: &Session, data: &str, format: Format) -> Result<String, Error> {
+    session.insert(SESSION_QR_PENDING, data)?;
+    Ok(format!("/qr/pending?f={}", format.as_str()))
+}
+
+/// Forgets the session's pending payload, once it's been used (e.g the
+/// TOTP code's been confirmed).
+pub fn clear_pending(session: &Session) {
+    session.remove(SESSION_QR_PENDING);
+}
+
+/// Renders `data` as a QR code image, or `None` if it can't be (e.g too
+/// long, or the `qr` feature is off).
+#[cfg(feature = "qr")]
+pub fn render(data: &str, format: Format) -> Option<Vec<u8>> {
+    use image::{DynamicImage, ImageOutputFormat, Luma};
+    use qrcode::render::svg;
+    use qrcode::QrCode;
+
+    let code = QrCode::new(data.as_bytes()).ok()?;
+    match format {
+        Format::Svg => Some(
+            code.render::<svg::Color<'_>>()
+                .min_dimensions(200, 200)
+                .build()
+                .into_bytes(),
+        ),
+        Format::Png => {
+            let image = code.render::<Luma<u8>>().min_dimensions(200, 200).build();
+            let mut png = Vec::new();
+            DynamicImage::ImageLuma8(image)
+                .write_to(&mut png, ImageOutputFormat::Png)
+                .ok()?;
+            Some(png)
+        }
+    }
+}
+
+#[cfg(not(feature = "qr"))]
+pub fn render(_data: &str, _format: Format) -> Option<Vec<u8>> {
+    None
+}
+
+/// `render`, through the in-memory cache.
+fn cached_render(data: &str, format: Format) -> Option<Vec<u8>> {
+    let key = (data.to_string(), format);
+    if let Some(image) = CACHE.lock().ok()?.get(&key) {
+        return Some(image.clone());
+    }
+
+    let image = render(data, format)?;
+    if let Ok(mut cache) = CACHE.lock() {
+        // Crude, but these are cheap to re-render.
+        if cache.len() >= CACHE_SIZE {
+            cache.clear();
+        }
+        cache.insert(key, image.clone());
+    }
+    Some(image)
+}
+
+#[derive(Deserialize)]
+pub struct QrQuery {
+    d: String,
+    s: String,
+    #[serde(default)]
+    f: Format,
+}
+
+/// Renders a signed payload. Anything unsigned, tampered with or
+/// unrenderable is a 404.
+pub async fn handler(query: web::Query<QrQuery>) -> HttpResponse {
+    let data = base64::decode_config(&query.d, base64::URL_SAFE_NO_PAD)
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .filter(|data| verify(data, &query.s));
+
+    match data.and_then(|data| cached_render(&data, query.f)) {
+        Some(image) => HttpResponse::Ok()
+            .content_type(query.f.content_type())
+            .insert_header(CacheControl(vec![
+                CacheDirective::Private,
+                CacheDirective::MaxAge(86400),
+            ]))
+            .body(image),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PendingQuery {
+    #[serde(default)]
+    f: Format,
+}
+
+/// Renders the session's pending payload, if it has one, and nothing's
+/// kept: neither here nor by the browser.
+pub async fn pending_handler(request: HttpRequest, query: web::Query<PendingQuery>) -> HttpResponse {
+    let data = request.get_session().get::<String>(SESSION_QR_PENDING).ok().flatten();
+
+    match data.and_then(|data| render(&data, query.f)) {
+        Some(image) => HttpResponse::Ok()
+            .content_type(query.f.content_type())
+            .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+            .body(image),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+pub fn configure(config: &mut ServiceConfig) {
+    routes::resource("/qr").get(handler).mount(config);
+    routes::resource("/qr/pending").get(pending_handler).mount(config);
+}
+
+/// Registers the `qr_url` function on a Tera instance.
+pub fn register(tera: &mut Tera) {
+    tera.register_function("qr_url", |args: &HashMap<String, Value>| {
+        let data = args
+            .get("data")
+            .and_then(Value::as_str)
+            .ok_or_else(|| tera::Error::msg("qr_url: missing `data` argument"))?;
+        let format = match args.get("format").and_then(Value::as_str) {
+            None | Some("svg") => Format::Svg,
+            Some("png") => Format::Png,
+            Some(other) => return Err(tera::Error::msg(format!("qr_url: unknown format `{}`", other))),
+        };
+
+        Ok(Value::String(url(data, format)))
+    });
+}
@@ -0,0 +1,299 @@
+//! Fixed window rate limiting, e.g for login attempts:
+//!
+//! ```ignore
+//! let limit = Limit::from_env("LOGIN_MAX_ATTEMPTS_PER_IP", 20, "LOGIN_ATTEMPT_WINDOW_MINUTES", 15);
+//! let key = format!("login:ip:{}", ratelimit::client_ip(&request));
+//! if !ratelimit::attempt(&key, &limit).await? {
+//!     return request.render(429, "accounts/login.html", ctx);
+//! }
+//! ```
+//!
+//! `attempt` counts the hit and checks it in one go; checking with
+//! `exceeded` and counting with `hit` afterwards lets a burst of concurrent
+//! requests all through before any of them is counted.
+//!
+//! Keys by address should use `client_ip`, which only believes
+//! `X-Forwarded-For` from the proxies listed in `TRUSTED_PROXIES`: anyone
+//! can send the header, and get a fresh limit with every made up address.
+//!
+//! APIs can wrap a scope in `guards::RateLimit` instead, which counts every
+//! call and tells clients where they stand with `RateLimit-*` headers.
+//!
+//! Counts live in memory by default, which is fine for a single server;
+//! with several, call `set_store(PgStore { .. })` at startup so they share
+//! the `rate_limits` table.
+
+use std::collections::HashMap;
+use std::env;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use actix_web::HttpRequest;
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use sqlx::postgres::PgPool;
+
+use crate::error::Error;
+
+/// At most `max` hits per `window`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limit {
+    pub max: u32,
+    pub window: Duration,
+}
+
+impl Limit {
+    /// A limit of `max` (read from `max_var`) per `minutes` (read from
+    /// `window_var`). A max of 0 turns the limit off.
+    pub fn from_env(max_var: &str, max: u32, window_var: &str, minutes: u64) -> Self {
+        let max = env::var(max_var).ok().and_then(|v| v.parse().ok()).unwrap_or(max);
+        let minutes = env::var(window_var).ok().and_then(|v| v.parse().ok()).unwrap_or(minutes);
+        Limit {
+            max,
+            window: Duration::from_secs(minutes * 60),
+        }
+    }
+}
+
+/// Where hit counts are kept.
+#[async_trait]
+pub trait Store: Send + Sync {
+    /// Hits on `key` in the current window.
+    async fn count(&self, key: &str, window: Duration) -> Result<u32, Error>;
+
+    /// Counts a hit on `key`, returning the new total for the window and
+    /// how long until the window resets.
+    async fn hit(&self, key: &str, window: Duration) -> Result<(u32, Duration), Error>;
+
+    /// Forgets `key`'s hits.
+    async fn clear(&self, key: &str) -> Result<(), Error>;
+}
+
+/// Keeps counts in this process.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    // key => (window start, hits)
+    windows: Mutex<HashMap<String, (Instant, u32)>>,
+}
+
+impl MemoryStore {
+    fn with_window<T>(&self, key: &str, window: Duration, f: impl FnOnce(&mut u32, Duration) -> T) -> T {
+        let mut windows = self.windows.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+
+        // Drop expired windows now and then, so keys don't pile up.
+        if windows.len() > 10_000 {
+            windows.retain(|_, (start, _)| now.duration_since(*start) < window);
+        }
+
+        let entry = windows.entry(key.to_string()).or_insert((now, 0));
+        if now.duration_since(entry.0) >= window {
+            *entry = (now, 0);
+        }
+        let resets_in = window.saturating_sub(now.duration_since(entry.0));
+        f(&mut entry.1, resets_in)
+    }
+}
+
+#[async_trait]
+impl Store for MemoryStore {
+    async fn count(&self, key: &str, window: Duration) -> Result<u32, Error> {
+        Ok(self.with_window(key, window, |hits, _| *hits))
+    }
+
+    async fn hit(&self, key: &str, window: Duration) -> Result<(u32, Duration), Error> {
+        Ok(self.with_window(key, window, |hits, resets_in| {
+            *hits += 1;
+            (*hits, resets_in)
+        }))
+    }
+
+    async fn clear(&self, key: &str) -> Result<(), Error> {
+        self.windows.lock().unwrap_or_else(|e| e.into_inner()).remove(key);
+        Ok(())
+    }
+}
+
+/// Keeps counts in the `rate_limits` table, shared between servers.
+#[derive(Clone, Debug)]
+pub struct PgStore {
+    pub pool: PgPool,
+}
+
+#[async_trait]
+impl Store for PgStore {
+    async fn count(&self, key: &str, window: Duration) -> Result<u32, Error> {
+        let hits: Option<i32> = sqlx::query_scalar(
+            "
+            SELECT hits FROM rate_limits
+            WHERE key = $1 AND window_start > now() - make_interval(secs => $2)
+        ",
+        )
+        .bind(key)
+        .bind(window.as_secs_f64())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(hits.unwrap_or(0) as u32)
+    }
+
+    async fn hit(&self, key: &str, window: Duration) -> Result<(u32, Duration), Error> {
+        let (hits, resets_in): (i32, f64) = sqlx::query_as(
+            "
+            INSERT INTO rate_limits (key, hits, window_start)
+            VALUES ($1, 1, now())
+            ON CONFLICT (key) DO UPDATE SET
+                hits = CASE
+                    WHEN rate_limits.window_start <= now() - make_interval(secs => $2) THEN 1
+                    ELSE rate_limits.hits + 1
+                END,
+                window_start = CASE
+                    WHEN rate_limits.window_start <= now() - make_interval(secs => $2) THEN now()
+                    ELSE rate_limits.window_start
+                END
+            RETURNING hits, GREATEST(
+                EXTRACT(EPOCH FROM window_start + make_interval(secs => $2) - now()), 0
+            )::float8
+        ",
+        )
+        .bind(key)
+        .bind(window.as_secs_f64())
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok((hits as u32, Duration::from_secs_f64(resets_in)))
+    }
+
+    async fn clear(&self, key: &str) -> Result<(), Error> {
+        sqlx::query("DELETE FROM rate_limits WHERE key = $1")
+            .bind(key)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
+
+lazy_static! {
+    static ref STORE: RwLock<Arc<dyn Store>> = RwLock::new(Arc::new(MemoryStore::default()));
+    static ref TRUSTED_PROXIES: Vec<IpAddr> = parse_proxies(&env::var("TRUSTED_PROXIES").unwrap_or_default());
+}
+
+/// The addresses in a `TRUSTED_PROXIES` value, e.g `127.0.0.1, 10.0.0.2`.
+/// Anything that isn't one is logged and skipped.
+pub fn parse_proxies(value: &str) -> Vec<IpAddr> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|proxy| !proxy.is_empty())
+        .filter_map(|proxy| match proxy.parse() {
+            Ok(ip) => Some(ip),
+            Err(_) => {
+                warn!("TRUSTED_PROXIES: {:?} isn't an IP address", proxy);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Who a request's from, given its `peer` and `X-Forwarded-For` header:
+/// the peer, unless it's one of the `trusted` proxies, in which case the
+/// nearest address in the header that isn't one (proxies append who they
+/// heard from, so anything further left is the client's say-so).
+pub fn client_ip_from(peer: Option<IpAddr>, forwarded_for: Option<&str>, trusted: &[IpAddr]) -> Option<IpAddr> {
+    let peer = peer?;
+    if !trusted.contains(&peer) {
+        return Some(peer);
+    }
+
+    let forwarded: Vec<IpAddr> = forwarded_for
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|hop| hop.trim().parse().ok())
+        .collect();
+    Some(forwarded.into_iter().rev().find(|hop| !trusted.contains(hop)).unwrap_or(peer))
+}
+
+/// The address to count `request` against; see `client_ip_from`.
+pub fn client_ip(request: &HttpRequest) -> String {
+    let forwarded_for = request
+        .headers()
+        .get("x-forwarded-for")
+        .and_then(|value| value.to_str().ok());
+    client_ip_from(request.peer_addr().map(|peer| peer.ip()), forwarded_for, &TRUSTED_PROXIES)
+        .map_or_else(|| "unknown".to_string(), |ip| ip.to_string())
+}
+
+/// Replaces the `Store`; call once at startup.
+pub fn set_store<S: Store + 'static>(store: S) {
+    match STORE.write() {
+        Ok(mut current) => *current = Arc::new(store),
+        Err(e) => error!("Unable to set rate limit store: {:?}", e),
+    }
+}
+
+fn store() -> Arc<dyn Store> {
+    STORE.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Whether `key` has used up `limit` for now.
+pub async fn exceeded(key: &str, limit: &Limit) -> Result<bool, Error> {
+    if limit.max == 0 {
+        return Ok(false);
+    }
+    Ok(store().count(key, limit.window).await? >= limit.max)
+}
+
+/// Counts an attempt on `key`, and says whether it's within `limit`.
+pub async fn attempt(key: &str, limit: &Limit) -> Result<bool, Error> {
+    Ok(!check(key, limit).await?.map_or(false, |status| status.exceeded))
+}
+
+/// Counts a hit on `key`.
+pub async fn hit(key: &str, limit: &Limit) -> Result<(), Error> {
+    if limit.max > 0 {
+        store().hit(key, limit.window).await?;
+    }
+    Ok(())
+}
+
+/// Where `key` stands against a limit, after a hit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Status {
+    pub limit: u32,
+    /// Hits left in the window; 0 once it's used up.
+    pub remaining: u32,
+    /// How long until the window resets.
+    pub reset: Duration,
+    /// Whether this hit went over the limit.
+    pub exceeded: bool,
+}
+
+impl Status {
+    /// Seconds until the window resets, rounded up, as the `RateLimit-Reset`
+    /// and `Retry-After` headers want them.
+    pub fn reset_secs(&self) -> u64 {
+        self.reset.as_secs() + u64::from(self.reset.subsec_nanos() > 0)
+    }
+}
+
+/// Counts a hit on `key` and says whether it's within `limit`; `None` if
+/// the limit is turned off.
+pub async fn check(key: &str, limit: &Limit) -> Result<Option<Status>, Error> {
+    if limit.max == 0 {
+        return Ok(None);
+    }
+
+    let (hits, reset) = store().hit(key, limit.window).await?;
+    Ok(Some(Status {
+        limit: limit.max,
+        remaining: limit.max.saturating_sub(hits),
+        reset,
+        exceeded: hits > limit.max,
+    }))
+}
+
+/// Forgets `key`'s hits, e.g after a successful login.
+pub async fn clear(key: &str) -> Result<(), Error> {
+    store().clear(key).await
+}
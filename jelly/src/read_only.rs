@@ -0,0 +1,69 @@
+//! Read-only mode, for database failovers and restores. While it's on,
+//! mutating requests are turned away with a 503 (see `guards::ReadOnly`),
+//! jobs are put off (see `jobs::Traced`) and scheduled ones skipped, and
+//! pages can show a banner with the `read_only` template variable. Reads
+//! carry on as usual.
+//!
+//! It's on with `READ_ONLY=true` in the environment, which takes a restart,
+//! or with the `app.read_only` setting, which doesn't. Settings can still
+//! be saved while it's on, so it can be turned off the same way, and the
+//! log filter changed, to look into whatever it's on for.
+
+use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use actix_web::http::Method;
+use sqlx::postgres::PgPool;
+
+use crate::settings;
+
+/// The setting that turns read-only mode on without a restart.
+pub const SETTING: &str = "app.read_only";
+
+/// Paths that still take mutations, so the mode can be turned off.
+pub const EXEMPT_PATHS: &[&str] = &["/admin/settings", "/admin/logging"];
+
+/// The setting, as of the last time it was read, for what can't wait on
+/// the database.
+static LAST_KNOWN: AtomicBool = AtomicBool::new(false);
+
+/// Whether `READ_ONLY` is set (to `true` or `1`).
+pub fn from_env() -> bool {
+    matches!(env::var("READ_ONLY").unwrap_or_default().as_str(), "true" | "1")
+}
+
+/// Whether read-only mode is on, by the environment or the setting. If the
+/// setting can't be read, as during a failover, the last known value is
+/// kept.
+pub async fn enabled(pool: &PgPool) -> bool {
+    if from_env() {
+        return true;
+    }
+
+    let enabled = match settings::get_or(pool, SETTING, false).await {
+        Ok(enabled) => enabled,
+        Err(e) => {
+            warn!("Error reading {}, keeping the last known value: {:?}", SETTING, e);
+            LAST_KNOWN.load(Ordering::Relaxed)
+        }
+    };
+    LAST_KNOWN.store(enabled, Ordering::Relaxed);
+    enabled
+}
+
+/// Whether read-only mode is on, without asking the database: the
+/// environment, or the setting as `enabled` last read it.
+pub fn is_enabled() -> bool {
+    from_env() || LAST_KNOWN.load(Ordering::Relaxed)
+}
+
+/// Whether requests with `method` change anything.
+pub fn is_mutation(method: &Method) -> bool {
+    matches!(*method, Method::POST | Method::PUT | Method::PATCH | Method::DELETE)
+}
+
+/// Whether a request to `path` with `method` is turned away while
+/// read-only mode is on.
+pub fn blocks(method: &Method, path: &str) -> bool {
+    is_mutation(method) && !EXEMPT_PATHS.iter().any(|exempt| path.starts_with(exempt))
+}
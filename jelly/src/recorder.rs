@@ -0,0 +1,395 @@
+//! A tape recorder for debugging, in development builds only: the last
+//! `DEV_RECORDER_REQUESTS` (50 by default in debug builds, "0" to turn it
+//! off) requests, with their headers, query and form parameters, the
+//! template rendered and what the session held after, are kept in memory
+//! and listed at `/dev/requests/`, for admins and requests from this
+//! machine. Handy for following multi-step flows like OAuth sign ins and
+//! password resets.
+//!
+//! Cookies, `Authorization` headers, form fields, query parameters and
+//! session values that look like passwords or tokens are redacted, as are
+//! long path segments, like the tokens in reset and verification links.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::env;
+use std::rc::Rc;
+use std::sync::Mutex;
+use std::task::{Context as TaskContext, Poll};
+
+use actix_service::{Service, Transform};
+use actix_session::SessionExt;
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::error::PayloadError;
+use actix_web::http::header::{AUTHORIZATION, CONTENT_TYPE, COOKIE, LOCATION};
+use actix_web::web::{Bytes, Query, ServiceConfig};
+use actix_web::{Error, HttpMessage, HttpRequest};
+use chrono::{DateTime, Utc};
+use futures::future::{ok, LocalBoxFuture, Ready};
+use lazy_static::lazy_static;
+use serde::Serialize;
+
+use crate::{SESSION_CSRF, SESSION_ID, SESSION_OAUTH_STATE, SESSION_OAUTH_TOKEN};
+
+/// Where the recordings are listed.
+pub const PATH: &str = "/dev/requests";
+
+/// Form bodies longer than this aren't recorded.
+const MAX_FORM_BYTES: usize = 16 * 1024;
+
+const REDACTED: &str = "[redacted]";
+
+/// Path segments this long are taken for tokens.
+const MAX_PATH_SEGMENT: usize = 20;
+
+/// Session values that let whoever has them act for the user.
+const SECRET_SESSION_KEYS: [&str; 4] = [SESSION_CSRF, SESSION_ID, SESSION_OAUTH_STATE, SESSION_OAUTH_TOKEN];
+
+/// The template a response was rendered with, left in the request's
+/// extensions by `Render`.
+#[derive(Clone, Debug)]
+pub struct RenderedTemplate(pub String);
+
+/// One request, and what came of it.
+#[derive(Clone, Debug, Serialize)]
+pub struct Recording {
+    pub id: u64,
+    pub at: DateTime<Utc>,
+    pub method: String,
+    pub path: String,
+    pub query: Vec<(String, String)>,
+    pub headers: Vec<(String, String)>,
+    pub form: Vec<(String, String)>,
+    pub status: u16,
+    /// Where it redirected to, if it did.
+    pub location: Option<String>,
+    pub template: Option<String>,
+    pub session: BTreeMap<String, String>,
+}
+
+/// The last `capacity` recordings, oldest first.
+#[derive(Debug)]
+pub struct Tape {
+    capacity: usize,
+    next_id: u64,
+    recordings: VecDeque<Recording>,
+}
+
+impl Tape {
+    pub fn new(capacity: usize) -> Self {
+        Tape {
+            capacity,
+            next_id: 1,
+            recordings: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Keeps `recording`, numbering it and dropping the oldest if the tape's
+    /// full.
+    pub fn push(&mut self, mut recording: Recording) {
+        if self.capacity == 0 {
+            return;
+        }
+        recording.id = self.next_id;
+        self.next_id += 1;
+        if self.recordings.len() == self.capacity {
+            self.recordings.pop_front();
+        }
+        self.recordings.push_back(recording);
+    }
+
+    /// The recordings, newest first.
+    pub fn list(&self) -> Vec<Recording> {
+        self.recordings.iter().rev().cloned().collect()
+    }
+
+    pub fn get(&self, id: u64) -> Option<Recording> {
+        self.recordings.iter().find(|recording| recording.id == id).cloned()
+    }
+
+    pub fn clear(&mut self) {
+        self.recordings.clear();
+    }
+}
+
+fn capacity() -> usize {
+    env::var("DEV_RECORDER_REQUESTS")
+        .ok()
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(if cfg!(debug_assertions) { 50 } else { 0 })
+}
+
+lazy_static! {
+    static ref TAPE: Mutex<Tape> = Mutex::new(Tape::new(capacity()));
+}
+
+/// Whether requests are being recorded: by default in debug builds, and
+/// never in production ones.
+pub fn enabled() -> bool {
+    cfg!(not(feature = "production")) && capacity() > 0
+}
+
+/// Whether a form field's value should be kept off the tape.
+pub fn is_secret(name: &str) -> bool {
+    let name = name.to_lowercase();
+    ["password", "token", "secret", "code_verifier", "signature"]
+        .iter()
+        .any(|word| name.contains(word))
+        || ["code", "state", "sig", "preview"].contains(&name.as_str())
+}
+
+/// Whether a session value should be kept off the tape.
+pub fn is_secret_session_key(key: &str) -> bool {
+    SECRET_SESSION_KEYS.contains(&key) || is_secret(key)
+}
+
+/// `path`, with segments long enough to be tokens redacted.
+pub fn redact_path(path: &str) -> String {
+    path.split('/')
+        .map(|segment| match segment.len() >= MAX_PATH_SEGMENT {
+            true => REDACTED,
+            false => segment,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn redact_pairs(pairs: Vec<(String, String)>, is_secret: fn(&str) -> bool) -> Vec<(String, String)> {
+    pairs
+        .into_iter()
+        .map(|(name, value)| {
+            let value = if is_secret(&name) { REDACTED.to_string() } else { value };
+            (name, value)
+        })
+        .collect()
+}
+
+fn parse_pairs(query: &str) -> Vec<(String, String)> {
+    Query::<Vec<(String, String)>>::from_query(query)
+        .map(|pairs| pairs.into_inner())
+        .unwrap_or_default()
+}
+
+/// Form fields, with the secret ones redacted.
+pub fn form_fields(body: &[u8]) -> Vec<(String, String)> {
+    redact_pairs(parse_pairs(std::str::from_utf8(body).unwrap_or("")), is_secret)
+}
+
+/// Query parameters, with the secret ones redacted.
+pub fn query_params(query: &str) -> Vec<(String, String)> {
+    redact_pairs(parse_pairs(query), is_secret)
+}
+
+fn should_record(path: &str) -> bool {
+    !path.starts_with(PATH) && !path.starts_with("/static/")
+}
+
+/// Middleware recording requests on the tape, when `enabled`. It needs
+/// the session, so it goes inside the session middleware.
+#[derive(Clone, Debug, Default)]
+pub struct Recorder;
+
+impl<S, B> Transform<S, ServiceRequest> for Recorder
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RecorderMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RecorderMiddleware {
+            service: Rc::new(service),
+            enabled: enabled(),
+        })
+    }
+}
+
+/// The middleware for `Recorder`. You generally don't need this type, but
+/// it needs to be exported for compiler reasons.
+pub struct RecorderMiddleware<S> {
+    service: Rc<S>,
+    enabled: bool,
+}
+
+impl<S, B> Service<ServiceRequest> for RecorderMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        if !self.enabled || !should_record(req.path()) {
+            return Box::pin(async move { service.call(req).await });
+        }
+
+        Box::pin(async move {
+            let headers = req
+                .headers()
+                .iter()
+                .map(|(name, value)| {
+                    let value = if name == COOKIE || name == AUTHORIZATION {
+                        REDACTED.to_string()
+                    } else {
+                        value.to_str().unwrap_or("[binary]").to_string()
+                    };
+                    (name.to_string(), value)
+                })
+                .collect();
+
+            // Forms are read to record them, so put them back for the handler.
+            let is_form = req
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .map_or(false, |value| value.starts_with("application/x-www-form-urlencoded"));
+            let mut form = Vec::new();
+            if is_form {
+                let body = req.extract::<Bytes>().await?;
+                if body.len() <= MAX_FORM_BYTES {
+                    form = form_fields(&body);
+                }
+                let stream = futures::stream::once(async move { Ok::<_, PayloadError>(body) });
+                req.set_payload(Payload::Stream {
+                    payload: Box::pin(stream),
+                });
+            }
+
+            let mut recording = Recording {
+                id: 0,
+                at: Utc::now(),
+                method: req.method().to_string(),
+                path: redact_path(req.path()),
+                query: query_params(req.query_string()),
+                headers,
+                form,
+                status: 0,
+                location: None,
+                template: None,
+                session: BTreeMap::new(),
+            };
+
+            let res = service.call(req).await?;
+            recording.status = res.status().as_u16();
+            recording.location = res
+                .headers()
+                .get(LOCATION)
+                .and_then(|value| value.to_str().ok())
+                .map(|location| match location.split_once('?') {
+                    Some((path, _)) => format!("{}?{}", redact_path(path), REDACTED),
+                    None => redact_path(location),
+                });
+            recording.template = res
+                .request()
+                .extensions()
+                .get::<RenderedTemplate>()
+                .map(|template| template.0.clone());
+            recording.session = res
+                .request()
+                .get_session()
+                .entries()
+                .iter()
+                .map(|(key, value)| {
+                    let value = if is_secret_session_key(key) { REDACTED.to_string() } else { value.clone() };
+                    (key.clone(), value)
+                })
+                .collect();
+
+            TAPE.lock().unwrap().push(recording);
+            Ok(res)
+        })
+    }
+}
+
+#[cfg(not(feature = "production"))]
+mod views {
+    use actix_web::web::Path;
+    use actix_web::{HttpRequest, HttpResponse};
+    use tera::Context;
+
+    use super::{PATH, TAPE};
+    use crate::error::Error;
+    use crate::request::{Authentication, Render};
+
+    /// Whether `request` can see the tape: it's from an admin, or from this
+    /// machine. Anyone else gets a 404, as if it weren't there.
+    fn allowed(request: &HttpRequest) -> bool {
+        let is_local = request.peer_addr().map_or(false, |peer| peer.ip().is_loopback());
+        is_local || request.user().map_or(false, |user| user.is_admin)
+    }
+
+    fn not_found(request: &HttpRequest) -> Result<HttpResponse, Error> {
+        request.render(404, "404.html", Context::new())
+    }
+
+    /// The recordings, newest first.
+    pub async fn list(request: HttpRequest) -> Result<HttpResponse, Error> {
+        if !allowed(&request) {
+            return not_found(&request);
+        }
+        let recordings = TAPE.lock().unwrap().list();
+        request.render(200, "dev/requests.html", {
+            let mut ctx = Context::new();
+            ctx.insert("recordings", &recordings);
+            ctx
+        })
+    }
+
+    pub async fn detail(request: HttpRequest, id: Path<u64>) -> Result<HttpResponse, Error> {
+        if !allowed(&request) {
+            return not_found(&request);
+        }
+        let recording = match TAPE.lock().unwrap().get(id.into_inner()) {
+            Some(recording) => recording,
+            None => return not_found(&request),
+        };
+        request.render(200, "dev/request.html", {
+            let mut ctx = Context::new();
+            ctx.insert("recording", &recording);
+            ctx
+        })
+    }
+
+    pub async fn clear(request: HttpRequest) -> Result<HttpResponse, Error> {
+        if !allowed(&request) {
+            return not_found(&request);
+        }
+        TAPE.lock().unwrap().clear();
+        request.redirect(&format!("{}/", PATH))
+    }
+}
+
+/// Mounts the recordings at `/dev/requests/`, when they're being kept.
+#[cfg(not(feature = "production"))]
+pub fn configure(config: &mut ServiceConfig) {
+    use crate::routes::resource;
+
+    if enabled() {
+        resource(&format!("{}/", PATH)).get(views::list).mount(config);
+        resource(&format!("{}/clear", PATH)).post(views::clear).mount(config);
+        resource(&format!("{}/{{id}}", PATH)).get(views::detail).mount(config);
+    }
+}
+
+/// A noop in production, where nothing's recorded.
+#[cfg(feature = "production")]
+pub fn configure(_config: &mut ServiceConfig) {}
+
+/// The template rendered for `request`, for the tape.
+pub(crate) fn rendered(request: &HttpRequest, template: &str) {
+    if cfg!(not(feature = "production")) {
+        request.extensions_mut().insert(RenderedTemplate(template.to_string()));
+    }
+}
@@ -6,14 +6,35 @@
 pub mod auth;
 pub use auth::Authentication;
 
+pub mod conditional;
+pub use conditional::Conditional;
+
 pub mod database;
 pub use database::DatabasePool;
 
+pub mod experiments;
+pub use experiments::Experiments;
+
 pub mod flash;
 pub use flash::FlashMessages;
 
+pub mod htmx;
+pub use htmx::Htmx;
+
 pub mod jobs;
 pub use jobs::JobQueue;
 
+pub mod permissions;
+pub use permissions::Permissions;
+
 pub mod render;
 pub use render::Render;
+
+pub mod tenant;
+pub use tenant::Tenant;
+
+pub mod timezone;
+pub use timezone::Timezone;
+
+pub mod turbo;
+pub use turbo::{Turbo, TurboStream};
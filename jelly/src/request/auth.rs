@@ -1,44 +1,85 @@
 use actix_session::SessionExt;
 use actix_web::HttpRequest;
 
-use crate::SESSION_USER;
-use crate::accounts::User;
+use crate::{SESSION_PASSWORD_EXPIRED, SESSION_REMEMBER, SESSION_USER};
+use crate::accounts::{jwt, AuthMode, User};
 use crate::error::Error;
+use crate::experiments;
+use crate::request::Experiments;
 
 /// `Authentication` is kind of a request guard - it returns a Future which will resolve
 /// with either the current authenticated user, or "error" out if the user has no session data
 /// that'd tie them to a user profile, or if the session cache can't be read, or if the database
 /// has issues, or... pick your poison I guess.
 ///
+/// Depending on the `AuthMode` the server runs in, the user comes from the
+/// session, a bearer token (see `accounts::jwt`), or either.
 pub trait Authentication {
     /// Returns whether a user session exists and is valid.
     fn is_authenticated(&self) -> Result<bool, Error>;
 
-    /// Sets a serializable user instance.
+    /// Sets a serializable user instance, and ties the session's
+    /// experiment unit to them (see `experiments::identify`).
     fn set_user(&self, account: User) -> Result<(), Error>;
 
     /// Returns a User, if it can be extracted properly.
     fn user(&self) -> Result<User, Error>;
+
+    /// Keeps the user signed in after the browser closes; see
+    /// `accounts::remember`. Call after `set_user`.
+    fn remember(&self) -> Result<(), Error>;
+
+    /// Flags (or clears) the signed in user's password as expired; while
+    /// it's flagged, the `Auth` guard sends them to change it.
+    fn set_password_expired(&self, expired: bool) -> Result<(), Error>;
+
+    /// Whether the signed in user's password has expired.
+    fn password_expired(&self) -> Result<bool, Error>;
 }
 
 impl Authentication for HttpRequest {
     #[inline(always)]
     fn is_authenticated(&self) -> Result<bool, Error> {
-        Ok(self
-            .get_session()
-            .get::<serde_json::Value>(SESSION_USER)?
-            .is_some())
+        if AuthMode::of(self).sessions() && self.get_session().get::<serde_json::Value>(SESSION_USER)?.is_some() {
+            return Ok(true);
+        }
+        Ok(jwt::bearer_user(self).is_some())
     }
 
     fn set_user(&self, account: User) -> Result<(), Error> {
+        if !account.is_anonymous {
+            experiments::identify(&self.experiment_unit()?, account.id);
+        }
         self.get_session().insert(SESSION_USER, account)?;
         Ok(())
     }
 
     fn user(&self) -> Result<User, Error> {
-        match self.get_session().get(SESSION_USER)? {
-            Some(user) => Ok(user),
-            None => Ok(User::default()),
+        if AuthMode::of(self).sessions() {
+            if let Some(user) = self.get_session().get(SESSION_USER)? {
+                return Ok(user);
+            }
+        }
+        Ok(jwt::bearer_user(self).unwrap_or_default())
+    }
+
+    fn remember(&self) -> Result<(), Error> {
+        // Zero means not issued yet, so `RememberMe` issues it now.
+        self.get_session().insert(SESSION_REMEMBER, 0)?;
+        Ok(())
+    }
+
+    fn set_password_expired(&self, expired: bool) -> Result<(), Error> {
+        let session = self.get_session();
+        if expired {
+            session.insert(SESSION_PASSWORD_EXPIRED, true)?;
+        } else {
+            session.remove(SESSION_PASSWORD_EXPIRED);
         }
+        Ok(())
+    }
+
+    fn password_expired(&self) -> Result<bool, Error> {
+        Ok(self.get_session().get::<bool>(SESSION_PASSWORD_EXPIRED)?.unwrap_or(false))
     }
 }
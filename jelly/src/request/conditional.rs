@@ -0,0 +1,86 @@
+use std::time::SystemTime;
+
+use actix_web::http::header::{EntityTag, Header, HttpDate, IfMatch, IfModifiedSince, IfNoneMatch, ETAG};
+use actix_web::{HttpRequest, HttpResponse};
+use serde::Serialize;
+
+use super::Render;
+use crate::error::Error;
+
+/// Builds the (strong) entity tag for a given row `version`.
+pub fn version_tag(version: i32) -> EntityTag {
+    EntityTag::new_strong(format!("v{}", version))
+}
+
+/// Helpers for optimistic concurrency on versioned resources. A resource's
+/// `version` column is handed out as an `ETag`, and clients must echo it
+/// back in `If-Match` when modifying the resource.
+///
+/// For reads, `is_fresh` checks `If-None-Match` / `If-Modified-Since`, so
+/// a 304 can be sent instead of the body.
+pub trait Conditional {
+    /// Checks the `If-Match` header against the current `version` of a
+    /// resource. A missing header is `Error::PreconditionRequired` (428),
+    /// a stale one is `Error::PreconditionFailed` (412).
+    fn check_if_match(&self, version: i32) -> Result<(), Error>;
+
+    /// Whether the client's copy is current: its `If-None-Match` has `tag`
+    /// or, without that header, `If-Modified-Since` isn't before
+    /// `last_modified`.
+    fn is_fresh(&self, tag: &EntityTag, last_modified: SystemTime) -> bool;
+
+    /// Shorthand for returning a JSON payload tagged with an `ETag`
+    /// for the given `version`.
+    fn versioned_json<S: Serialize>(
+        &self,
+        code: usize,
+        version: i32,
+        payload: S,
+    ) -> Result<HttpResponse, Error>;
+}
+
+impl Conditional for HttpRequest {
+    fn check_if_match(&self, version: i32) -> Result<(), Error> {
+        if !self.headers().contains_key(IfMatch::name()) {
+            return Err(Error::PreconditionRequired);
+        }
+
+        let current = version_tag(version);
+        match IfMatch::parse(self).map_err(|_| Error::PreconditionFailed)? {
+            IfMatch::Any => Ok(()),
+            IfMatch::Items(tags) if tags.iter().any(|tag| tag.strong_eq(&current)) => Ok(()),
+            IfMatch::Items(_) => Err(Error::PreconditionFailed),
+        }
+    }
+
+    fn is_fresh(&self, tag: &EntityTag, last_modified: SystemTime) -> bool {
+        if self.headers().contains_key(IfNoneMatch::name()) {
+            return match IfNoneMatch::parse(self) {
+                Ok(IfNoneMatch::Any) => true,
+                Ok(IfNoneMatch::Items(tags)) => tags.iter().any(|t| t.weak_eq(tag)),
+                Err(_) => false,
+            };
+        }
+
+        match IfModifiedSince::parse(self) {
+            // HTTP dates are to the second, so compare at that precision.
+            Ok(IfModifiedSince(since)) => since >= HttpDate::from(last_modified),
+            Err(_) => false,
+        }
+    }
+
+    fn versioned_json<S: Serialize>(
+        &self,
+        code: usize,
+        version: i32,
+        payload: S,
+    ) -> Result<HttpResponse, Error> {
+        let mut response = self.json(code, payload)?;
+        let tag = version_tag(version).to_string();
+        response.headers_mut().insert(
+            ETAG,
+            tag.parse().map_err(|e| Error::Generic(format!("Invalid ETag: {:?}", e)))?,
+        );
+        Ok(response)
+    }
+}
@@ -0,0 +1,48 @@
+use actix_session::SessionExt;
+use actix_web::HttpRequest;
+use rand::{thread_rng, Rng};
+
+use crate::error::Error;
+use crate::experiments;
+use crate::SESSION_EXPERIMENT_UNIT;
+
+/// A trait for putting requests into A/B experiments.
+pub trait Experiments {
+    /// Who's being bucketed: `session:<random id>`, created on first use.
+    /// It's the same before and after signing in, so nobody switches
+    /// variants partway through; `set_user` ties it to the account.
+    fn experiment_unit(&self) -> Result<String, Error>;
+
+    /// The variant of `experiment` to show, recording the exposure. `None`
+    /// if the experiment isn't configured.
+    fn variant(&self, experiment: &str) -> Result<Option<&'static str>, Error>;
+
+    /// Records that the goal of `experiment` was reached.
+    fn convert(&self, experiment: &str) -> Result<(), Error>;
+}
+
+impl Experiments for HttpRequest {
+    fn experiment_unit(&self) -> Result<String, Error> {
+        let session = self.get_session();
+        let id = match session.get::<String>(SESSION_EXPERIMENT_UNIT)? {
+            Some(id) => id,
+            None => {
+                let bytes: [u8; 12] = thread_rng().gen();
+                let id: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+                session.insert(SESSION_EXPERIMENT_UNIT, &id)?;
+                id
+            }
+        };
+
+        Ok(format!("session:{}", id))
+    }
+
+    fn variant(&self, experiment: &str) -> Result<Option<&'static str>, Error> {
+        Ok(experiments::expose(experiment, &self.experiment_unit()?))
+    }
+
+    fn convert(&self, experiment: &str) -> Result<(), Error> {
+        experiments::convert(experiment, &self.experiment_unit()?);
+        Ok(())
+    }
+}
@@ -0,0 +1,67 @@
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::{HttpRequest, HttpResponse};
+
+use crate::error::Error;
+
+/// `HX-Request` is sent by htmx on every request it makes.
+pub const HX_REQUEST: &str = "hx-request";
+
+/// `HX-Boosted` is sent for `hx-boost`ed links/forms, which expect a full page.
+pub const HX_BOOSTED: &str = "hx-boosted";
+
+/// Tells htmx to do a full client-side redirect.
+pub const HX_REDIRECT: &str = "hx-redirect";
+
+/// Triggers client-side events once the response is swapped in.
+pub const HX_TRIGGER: &str = "hx-trigger";
+
+/// Helpers for working with [htmx](https://htmx.org) requests.
+///
+/// `Render::render` uses `is_htmx()` to render only the `content` block of a
+/// template (skipping the base layout) for htmx requests.
+pub trait Htmx {
+    /// Whether this request was made by htmx.
+    fn is_htmx(&self) -> bool;
+
+    /// Whether this request was made by htmx for a boosted link or form.
+    fn is_htmx_boosted(&self) -> bool;
+
+    /// A redirect that htmx will follow with a full page load. A plain
+    /// 302 would be followed by the XHR and swapped into the page instead.
+    fn hx_redirect(&self, location: &str) -> Result<HttpResponse, Error>;
+}
+
+impl Htmx for HttpRequest {
+    fn is_htmx(&self) -> bool {
+        header_is_true(self, HX_REQUEST)
+    }
+
+    fn is_htmx_boosted(&self) -> bool {
+        header_is_true(self, HX_BOOSTED)
+    }
+
+    fn hx_redirect(&self, location: &str) -> Result<HttpResponse, Error> {
+        Ok(HttpResponse::Ok()
+            .append_header((HX_REDIRECT, location))
+            .finish())
+    }
+}
+
+/// Adds an `HX-Trigger` header to a response, e.g `hx_trigger(&mut response,
+/// "accountUpdated")`. `events` can also be a JSON object, as htmx allows.
+pub fn hx_trigger(response: &mut HttpResponse, events: &str) -> Result<(), Error> {
+    let value = HeaderValue::from_str(events)
+        .map_err(|e| Error::Generic(format!("Invalid HX-Trigger value: {:?}", e)))?;
+    response
+        .headers_mut()
+        .insert(HeaderName::from_static(HX_TRIGGER), value);
+    Ok(())
+}
+
+fn header_is_true(request: &HttpRequest, name: &str) -> bool {
+    request
+        .headers()
+        .get(name)
+        .map(|v| v.as_bytes() == b"true")
+        .unwrap_or(false)
+}
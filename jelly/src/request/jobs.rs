@@ -1,12 +1,29 @@
-use actix_web::{web, HttpRequest};
+use actix_web::{web, HttpMessage, HttpRequest};
 use background_jobs::QueueHandle;
+use futures::future::LocalBoxFuture;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 
 use crate::error::Error;
+use crate::guards::RequestId;
+use crate::jobs::{Correlation, Job, Traced};
+use crate::request::Authentication;
 
 /// A trait for adding jobs to a background queue.
 pub trait JobQueue {
     /// Grabs the QueueHandle
     fn job_queue(&self) -> Result<&QueueHandle, Error>;
+
+    /// This request's id, and the signed in account's, for the jobs it
+    /// queues.
+    fn correlation(&self) -> Correlation;
+
+    /// Queues `job` with this request's `correlation`. It has to be
+    /// registered as `Traced<J>` for that to be logged; see `jobs::traced`.
+    fn queue_job<J>(&self, job: J) -> LocalBoxFuture<'_, Result<(), Error>>
+    where
+        J: Job + Serialize + DeserializeOwned,
+        J::Future: 'static;
 }
 
 impl JobQueue for HttpRequest {
@@ -16,4 +33,23 @@ impl JobQueue for HttpRequest {
             .map(|data| data.get_ref())
             .ok_or_else(|| Error::Generic("QueueHandle unavailable.".to_string()))
     }
+
+    fn correlation(&self) -> Correlation {
+        Correlation {
+            request_id: self.extensions().get::<RequestId>().map(|id| id.0.clone()),
+            account_id: self.user().ok().filter(|user| !user.is_anonymous).map(|user| user.id),
+        }
+    }
+
+    fn queue_job<J>(&self, job: J) -> LocalBoxFuture<'_, Result<(), Error>>
+    where
+        J: Job + Serialize + DeserializeOwned,
+        J::Future: 'static,
+    {
+        let job = Traced::new(self.correlation(), job);
+        Box::pin(async move {
+            self.job_queue()?.queue(job).await?;
+            Ok(())
+        })
+    }
 }
@@ -0,0 +1,53 @@
+use actix_web::{HttpMessage, HttpRequest};
+use async_trait::async_trait;
+
+use super::{Authentication, DatabasePool};
+use crate::accounts::permissions::{self, Granted};
+use crate::error::Error;
+
+/// A trait for checking the signed in user's permissions (see
+/// `accounts::permissions`), to gate individual actions rather than whole
+/// scopes. They're loaded once per request, however many are checked.
+#[async_trait(?Send)]
+pub trait Permissions {
+    /// Whether the user has `name`.
+    async fn has_permission(&self, name: &str) -> Result<bool, Error>;
+
+    /// Whether the user has anything in `group`, e.g `accounts.`.
+    async fn has_any_permission(&self, group: &str) -> Result<bool, Error>;
+
+    /// Fails with `Error::PermissionDenied` (a 403) unless the user has
+    /// `name`.
+    async fn require_permission(&self, name: &str) -> Result<(), Error>;
+}
+
+#[async_trait(?Send)]
+impl Permissions for HttpRequest {
+    async fn has_permission(&self, name: &str) -> Result<bool, Error> {
+        Ok(granted(self).await?.allows(name))
+    }
+
+    async fn has_any_permission(&self, group: &str) -> Result<bool, Error> {
+        Ok(granted(self).await?.allows_any(group))
+    }
+
+    async fn require_permission(&self, name: &str) -> Result<(), Error> {
+        if self.has_permission(name).await? {
+            Ok(())
+        } else {
+            Err(Error::PermissionDenied(name.to_string()))
+        }
+    }
+}
+
+/// The user's permissions, loaded on first use and kept for the rest of the
+/// request.
+async fn granted(request: &HttpRequest) -> Result<Granted, Error> {
+    if let Some(granted) = request.extensions().get::<Granted>() {
+        return Ok(granted.clone());
+    }
+
+    let granted = permissions::load(request.db_pool()?, &request.user()?).await?;
+    request.extensions_mut().insert(granted.clone());
+    Ok(granted)
+}
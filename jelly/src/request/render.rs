@@ -1,24 +1,60 @@
 use std::env;
 use std::sync::{Arc, RwLock};
 
-use actix_web::http::header::LOCATION;
-use actix_web::{HttpRequest, HttpResponse};
+use actix_web::http::header::{HeaderValue, CACHE_CONTROL, CONTENT_DISPOSITION, LOCATION, VARY};
+use actix_web::http::StatusCode;
+use actix_web::web::Bytes;
+use actix_web::{HttpMessage, HttpRequest, HttpResponse};
+use futures::stream::{Stream, StreamExt};
 use serde::Serialize;
 use tera::{Context, Tera};
 
-use super::{Authentication, FlashMessages};
+use super::{Authentication, Experiments, FlashMessages, Htmx, Timezone};
 use crate::error::Error;
+use crate::experiments;
+use crate::guards::{csrf, CspNonce};
+use crate::read_only;
+use crate::recorder;
+use crate::templates::block_template;
+
+/// Maps a numeric response code to a `StatusCode`, falling back to
+/// 200 OK for anything that isn't a valid HTTP status.
+pub(crate) fn status_code(code: usize) -> StatusCode {
+    u16::try_from(code)
+        .ok()
+        .and_then(|c| StatusCode::from_u16(c).ok())
+        .unwrap_or(StatusCode::OK)
+}
 
 /// A trait for making certain types of response handling easier.
 pub trait Render {
     /// Shorthand for rendering a template, with a specific HTTP response code.
+    ///
+    /// For htmx requests (that aren't boosted), only the `content` block of
+    /// the template is rendered, skipping the base layout.
     fn render(&self, code: usize, template: &str, context: Context) -> Result<HttpResponse, Error>;
 
+    /// Renders a single block of a template, e.g for swapping into a page
+    /// with htmx.
+    fn render_partial(
+        &self,
+        code: usize,
+        template: &str,
+        block: &str,
+        context: Context,
+    ) -> Result<HttpResponse, Error>;
+
     /// Shorthand for returning a JSON payload.
     fn json<S: Serialize>(&self, code: usize, payload: S) -> Result<HttpResponse, Error>;
 
     /// Handy redirects helper.
     fn redirect(&self, location: &str) -> Result<HttpResponse, Error>;
+
+    /// Streams `body` out in chunks as it's produced, for downloads too big
+    /// to hold in memory. With a `filename`, it's sent as an attachment.
+    fn stream<S>(&self, content_type: &str, filename: Option<&str>, body: S) -> Result<HttpResponse, Error>
+    where
+        S: Stream<Item = Result<Bytes, Error>> + 'static;
 }
 
 impl Render for HttpRequest {
@@ -26,53 +62,48 @@ impl Render for HttpRequest {
         &self,
         code: usize,
         template: &str,
-        mut context: Context,
+        context: Context,
     ) -> Result<HttpResponse, Error> {
-        let data: Option<&Arc<RwLock<Tera>>> = self.app_data();
+        let templates = self.templates()?;
+        let has_htmx_block = has_block(templates, template, HTMX_BLOCK)?;
 
-        // We pull the user and flash messages for all requests;
-        // it's blank if a User is anonymous (not authenticated).
-        let user = self.user()?;
-        let messages = self.get_flash_messages()?;
-        context.insert("user", &user);
-        context.insert("flash_messages", &messages);
-        for (k, v) in env::vars() {
-            if k.starts_with("JELLY_") {
-                context.insert(k, &v);
-            }
-        }
+        let mut response = if self.is_htmx() && !self.is_htmx_boosted() && has_htmx_block {
+            self.render_partial(code, template, HTMX_BLOCK, context)?
+        } else {
+            let context = self.template_context(context)?;
+            recorder::rendered(self, template);
+            csrf::rendering(self, || render_html(code, templates, template, &context))?
+        };
 
-        if let Some(eng) = data {
-            let engine = eng.read().map_err(|e| {
-                Error::Generic(format!("Error acquiring template read lock: {:?}", e))
-            })?;
+        // The same URL renders differently for htmx, so caches have to
+        // tell the two apart.
+        if has_htmx_block {
+            response
+                .headers_mut()
+                .append(VARY, HeaderValue::from_static("HX-Request, HX-Boosted"));
+        }
+        Ok(response)
+    }
 
-            let body = engine.render(template, &context).map_err(Error::from)?;
+    fn render_partial(
+        &self,
+        code: usize,
+        template: &str,
+        block: &str,
+        context: Context,
+    ) -> Result<HttpResponse, Error> {
+        let templates = self.templates()?;
+        let name = block_template(templates, template, block)?;
 
-            Ok(match code {
-                200 => HttpResponse::Ok(),
-                400 => HttpResponse::BadRequest(),
-                404 => HttpResponse::NotFound(),
-                _ => HttpResponse::Ok(),
-            }
-            .content_type("text/html; charset=utf-8")
-            .body(body))
-        } else {
-            Err(Error::Generic(
-                "Unable to locate Templates cache".to_string(),
-            ))
-        }
+        let context = self.template_context(context)?;
+        recorder::rendered(self, &format!("{} ({})", template, block));
+        csrf::rendering(self, || render_html(code, templates, &name, &context))
     }
 
     fn json<S: Serialize>(&self, code: usize, payload: S) -> Result<HttpResponse, Error> {
         let o = serde_json::to_string(&payload)?;
 
-        Ok(match code {
-            200 => HttpResponse::Ok(),
-            400 => HttpResponse::BadRequest(),
-            404 => HttpResponse::NotFound(),
-            _ => HttpResponse::Ok(),
-        }
+        Ok(HttpResponse::build(status_code(code))
         .content_type("application/json")
         .body(o))
     }
@@ -83,4 +114,117 @@ impl Render for HttpRequest {
             .finish()
         )
     }
+
+    fn stream<S>(&self, content_type: &str, filename: Option<&str>, body: S) -> Result<HttpResponse, Error>
+    where
+        S: Stream<Item = Result<Bytes, Error>> + 'static,
+    {
+        Ok(stream_response(content_type, filename, body))
+    }
+}
+
+/// A chunked response for `body`. There's no `Content-Length`, and proxies
+/// are asked not to buffer it. An error part way through can only cut the
+/// response short, as the status has already gone; it's logged.
+pub(crate) fn stream_response<S>(content_type: &str, filename: Option<&str>, body: S) -> HttpResponse
+where
+    S: Stream<Item = Result<Bytes, Error>> + 'static,
+{
+    let mut response = HttpResponse::Ok();
+    response
+        .content_type(content_type)
+        .insert_header((CACHE_CONTROL, "no-store"))
+        .insert_header(("X-Accel-Buffering", "no"));
+    if let Some(filename) = filename {
+        response.insert_header((
+            CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", filename.replace('"', "")),
+        ));
+    }
+
+    response.streaming(body.map(|chunk| {
+        chunk.map_err(|e| {
+            error!("Error streaming response: {:?}", e);
+            std::io::Error::new(std::io::ErrorKind::Other, "stream failed")
+        })
+    }))
+}
+
+/// The block rendered in place of the full page for htmx requests.
+const HTMX_BLOCK: &str = "content";
+
+/// Internal helpers shared by the various rendering traits.
+pub(crate) trait TemplateData {
+    fn templates(&self) -> Result<&RwLock<Tera>, Error>;
+
+    fn template_context(&self, context: Context) -> Result<Context, Error>;
+}
+
+impl TemplateData for HttpRequest {
+    fn templates(&self) -> Result<&RwLock<Tera>, Error> {
+        let data: Option<&Arc<RwLock<Tera>>> = self.app_data();
+        data.map(|eng| eng.as_ref()).ok_or_else(|| {
+            Error::Generic("Unable to locate Templates cache".to_string())
+        })
+    }
+
+    fn template_context(&self, mut context: Context) -> Result<Context, Error> {
+        // We pull the user and flash messages for all requests;
+        // it's blank if a User is anonymous (not authenticated).
+        let user = self.user()?;
+        let messages = self.get_flash_messages()?;
+        context.insert("user", &user);
+        context.insert("flash_messages", &messages);
+        context.insert("hx_request", &self.is_htmx());
+        context.insert("timezone", &self.timezone()?);
+        context.insert("read_only", &read_only::is_enabled());
+        // Only when there's something to bucket, so anonymous visitors
+        // don't all get a session otherwise.
+        if !experiments::all().is_empty() {
+            context.insert("experiment_unit", &self.experiment_unit()?);
+        }
+        if let Some(nonce) = self.extensions().get::<CspNonce>() {
+            context.insert("csp_nonce", &nonce.0);
+        }
+        for (k, v) in env::vars() {
+            if k.starts_with("JELLY_") {
+                context.insert(k, &v);
+            }
+        }
+
+        Ok(context)
+    }
+}
+
+fn has_block(templates: &RwLock<Tera>, template: &str, block: &str) -> Result<bool, Error> {
+    let engine = templates.read().map_err(|e| {
+        Error::Generic(format!("Error acquiring template read lock: {:?}", e))
+    })?;
+
+    Ok(engine.get_template(template)?.blocks.contains_key(block))
+}
+
+fn render_html(
+    code: usize,
+    templates: &RwLock<Tera>,
+    template: &str,
+    context: &Context,
+) -> Result<HttpResponse, Error> {
+    let body = render_string(templates, template, context)?;
+
+    Ok(HttpResponse::build(status_code(code))
+    .content_type("text/html; charset=utf-8")
+    .body(body))
+}
+
+pub(crate) fn render_string(
+    templates: &RwLock<Tera>,
+    template: &str,
+    context: &Context,
+) -> Result<String, Error> {
+    let engine = templates.read().map_err(|e| {
+        Error::Generic(format!("Error acquiring template read lock: {:?}", e))
+    })?;
+
+    engine.render(template, context).map_err(Error::from)
 }
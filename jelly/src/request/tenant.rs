@@ -0,0 +1,23 @@
+use actix_web::HttpRequest;
+
+use crate::error::Error;
+use crate::request::{Authentication, DatabasePool};
+use crate::tenancy::{TenantId, TenantPool};
+
+/// A trait for getting a database pool scoped to the signed in account.
+pub trait Tenant {
+    /// Returns a `TenantPool` for the current user. Errors for anonymous
+    /// users, who have no data of their own.
+    fn tenant_pool(&self) -> Result<TenantPool<'_>, Error>;
+}
+
+impl Tenant for HttpRequest {
+    fn tenant_pool(&self) -> Result<TenantPool<'_>, Error> {
+        let user = self.user()?;
+        if user.is_anonymous {
+            return Err(Error::Generic("No tenant for anonymous users.".to_string()));
+        }
+
+        Ok(TenantPool::new(self.db_pool()?, TenantId(user.id)))
+    }
+}
@@ -0,0 +1,35 @@
+use actix_session::SessionExt;
+use actix_web::HttpRequest;
+
+use crate::error::Error;
+use crate::timezones;
+use crate::SESSION_TIMEZONE;
+
+/// A trait for the time zone the signed in user sees times in. It's kept
+/// in the session, so set it at sign in and when the user changes it.
+pub trait Timezone {
+    /// The user's time zone name, or `timezones::DEFAULT`.
+    fn timezone(&self) -> Result<String, Error>;
+
+    /// Sets the user's time zone; unknown names are ignored.
+    fn set_timezone(&self, tz: &str) -> Result<(), Error>;
+}
+
+impl Timezone for HttpRequest {
+    fn timezone(&self) -> Result<String, Error> {
+        Ok(self
+            .get_session()
+            .get::<String>(SESSION_TIMEZONE)?
+            .unwrap_or_else(|| timezones::DEFAULT.to_string()))
+    }
+
+    fn set_timezone(&self, tz: &str) -> Result<(), Error> {
+        let session = self.get_session();
+        if timezones::is_valid(tz) {
+            session.insert(SESSION_TIMEZONE, tz)?;
+        } else {
+            session.remove(SESSION_TIMEZONE);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,223 @@
+use actix_web::http::header::ACCEPT;
+use actix_web::{HttpRequest, HttpResponse};
+use tera::Context;
+
+use super::render::{render_string, status_code, TemplateData};
+use super::Render;
+use crate::error::Error;
+use crate::guards::csrf;
+use crate::templates::block_template;
+
+/// The content type for Turbo Stream responses.
+pub const TURBO_STREAM: &str = "text/vnd.turbo-stream.html";
+
+/// The status Turbo expects for a form submission that failed validation.
+pub const UNPROCESSABLE_ENTITY: usize = 422;
+
+/// A Turbo Stream action, e.g `replace`.
+#[derive(Clone, Copy, Debug)]
+pub enum Action {
+    Append,
+    Prepend,
+    Replace,
+    Update,
+    Remove,
+    Before,
+    After,
+}
+
+impl Action {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Action::Append => "append",
+            Action::Prepend => "prepend",
+            Action::Replace => "replace",
+            Action::Update => "update",
+            Action::Remove => "remove",
+            Action::Before => "before",
+            Action::After => "after",
+        }
+    }
+}
+
+struct Step {
+    action: Action,
+    target: String,
+    partial: Option<(String, Option<String>, Context)>,
+}
+
+/// Builds a `text/vnd.turbo-stream.html` payload out of Tera templates (or
+/// single blocks of them), e.g:
+///
+/// ```rust,ignore
+/// let stream = TurboStream::new()
+///     .append_block("messages", "messages/index.html", "message", ctx)
+///     .remove("empty-state");
+/// request.turbo_stream(200, stream)
+/// ```
+///
+/// Templates are rendered with the same context additions as `render()`.
+#[derive(Default)]
+pub struct TurboStream {
+    steps: Vec<Step>,
+}
+
+impl TurboStream {
+    pub fn new() -> Self {
+        TurboStream::default()
+    }
+
+    /// Adds an action rendering a whole template.
+    pub fn action(mut self, action: Action, target: &str, template: &str, context: Context) -> Self {
+        self.steps.push(Step {
+            action,
+            target: target.to_string(),
+            partial: Some((template.to_string(), None, context)),
+        });
+        self
+    }
+
+    /// Adds an action rendering a single block of a template.
+    pub fn action_block(
+        mut self,
+        action: Action,
+        target: &str,
+        template: &str,
+        block: &str,
+        context: Context,
+    ) -> Self {
+        self.steps.push(Step {
+            action,
+            target: target.to_string(),
+            partial: Some((template.to_string(), Some(block.to_string()), context)),
+        });
+        self
+    }
+
+    pub fn append(self, target: &str, template: &str, context: Context) -> Self {
+        self.action(Action::Append, target, template, context)
+    }
+
+    pub fn append_block(self, target: &str, template: &str, block: &str, context: Context) -> Self {
+        self.action_block(Action::Append, target, template, block, context)
+    }
+
+    pub fn prepend(self, target: &str, template: &str, context: Context) -> Self {
+        self.action(Action::Prepend, target, template, context)
+    }
+
+    pub fn replace(self, target: &str, template: &str, context: Context) -> Self {
+        self.action(Action::Replace, target, template, context)
+    }
+
+    pub fn replace_block(self, target: &str, template: &str, block: &str, context: Context) -> Self {
+        self.action_block(Action::Replace, target, template, block, context)
+    }
+
+    pub fn update(self, target: &str, template: &str, context: Context) -> Self {
+        self.action(Action::Update, target, template, context)
+    }
+
+    /// Removes the target element; no template needed.
+    pub fn remove(mut self, target: &str) -> Self {
+        self.steps.push(Step {
+            action: Action::Remove,
+            target: target.to_string(),
+            partial: None,
+        });
+        self
+    }
+}
+
+/// Helpers for [Turbo](https://turbo.hotwired.dev) frontends.
+///
+/// The existing form flows already work with Turbo Drive, since they either
+/// redirect or respond with a 4xx. `render_form_errors` additionally lets a
+/// form be swapped in place, with 422 as Turbo recommends.
+pub trait Turbo {
+    /// Whether the client accepts Turbo Stream responses (Turbo sets this
+    /// for form submissions).
+    fn accepts_turbo_stream(&self) -> bool;
+
+    /// The id of the `<turbo-frame>` making this request, if any.
+    fn turbo_frame(&self) -> Option<String>;
+
+    /// Renders a `TurboStream` into a response.
+    fn turbo_stream(&self, code: usize, stream: TurboStream) -> Result<HttpResponse, Error>;
+
+    /// Renders a form that failed validation. Turbo Stream clients get a
+    /// `replace` of `target`, rendered from the block of the same name (so
+    /// keep the form in `{% block <target> %}` with `id="<target>"`); everyone
+    /// else gets the full template. Either way the status is 422.
+    fn render_form_errors(
+        &self,
+        template: &str,
+        target: &str,
+        context: Context,
+    ) -> Result<HttpResponse, Error>;
+}
+
+impl Turbo for HttpRequest {
+    fn accepts_turbo_stream(&self) -> bool {
+        self.headers()
+            .get(ACCEPT)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.contains(TURBO_STREAM))
+            .unwrap_or(false)
+    }
+
+    fn turbo_frame(&self) -> Option<String> {
+        self.headers()
+            .get("turbo-frame")
+            .and_then(|v| v.to_str().ok())
+            .map(String::from)
+    }
+
+    fn turbo_stream(&self, code: usize, stream: TurboStream) -> Result<HttpResponse, Error> {
+        let templates = self.templates()?;
+        let mut body = String::new();
+
+        for step in stream.steps {
+            let target = tera::escape_html(&step.target);
+            match step.partial {
+                Some((template, block, context)) => {
+                    let name = match block {
+                        Some(block) => block_template(templates, &template, &block)?,
+                        None => template,
+                    };
+                    let context = self.template_context(context)?;
+                    let html = csrf::rendering(self, || render_string(templates, &name, &context))?;
+                    body.push_str(&format!(
+                        r#"<turbo-stream action="{}" target="{}"><template>{}</template></turbo-stream>"#,
+                        step.action.as_str(),
+                        target,
+                        html
+                    ));
+                }
+                None => body.push_str(&format!(
+                    r#"<turbo-stream action="{}" target="{}"></turbo-stream>"#,
+                    step.action.as_str(),
+                    target
+                )),
+            }
+        }
+
+        Ok(HttpResponse::build(status_code(code))
+            .content_type(TURBO_STREAM)
+            .body(body))
+    }
+
+    fn render_form_errors(
+        &self,
+        template: &str,
+        target: &str,
+        context: Context,
+    ) -> Result<HttpResponse, Error> {
+        if self.accepts_turbo_stream() {
+            let stream = TurboStream::new().replace_block(target, template, target, context);
+            return self.turbo_stream(UNPROCESSABLE_ENTITY, stream);
+        }
+
+        self.render(UNPROCESSABLE_ENTITY, template, context)
+    }
+}
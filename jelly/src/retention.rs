@@ -0,0 +1,199 @@
+//! Retention policies: how long rows are kept, declared per table and
+//! applied by a job on a schedule. Admins can change a policy's days
+//! without a deploy in the `retention.policies` setting, by name:
+//!
+//! ```json
+//! {"audit_events": 730, "sessions": 14}
+//! ```
+//!
+//! Zero days keeps rows forever. With the `retention.dry_run` setting on,
+//! policies only count what they'd remove, for a report, and nothing's
+//! deleted.
+//!
+//! ```ignore
+//! const AUDIT_EVENTS: Policy = Policy::new("audit_events", "audit_events", "created", 365);
+//!
+//! let policies = retention::resolve(&pool, &[AUDIT_EVENTS]).await?;
+//! for outcome in retention::apply(&pool, &policies, retention::dry_run(&pool).await?).await? {
+//!     info!("{}", outcome);
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+
+use futures::future::BoxFuture;
+use serde::Serialize;
+use sqlx::postgres::PgPool;
+
+use crate::error::Error;
+use crate::settings;
+
+/// The setting overriding policies' days, by policy name.
+pub const POLICIES_SETTING: &str = "retention.policies";
+
+/// The setting that has policies report rather than delete.
+pub const DRY_RUN_SETTING: &str = "retention.dry_run";
+
+/// Removes what a policy covers, for `days`, when deleting rows from its
+/// table isn't enough (e.g accounts, whose identities go with them).
+/// Returns how many were removed.
+pub type Purge = for<'a> fn(&'a PgPool, i32) -> BoxFuture<'a, Result<u64, Error>>;
+
+/// How long rows of `table` are kept, by the age of their `column`.
+#[derive(Clone, Copy)]
+pub struct Policy {
+    pub name: &'static str,
+    pub table: &'static str,
+    /// The timestamp rows age from.
+    pub column: &'static str,
+    /// Only rows that also match this are covered, e.g
+    /// `deleted_at IS NOT NULL`.
+    pub filter: Option<&'static str>,
+    pub days: i32,
+    /// Used in place of deleting from `table`, if set.
+    pub purge: Option<Purge>,
+}
+
+impl fmt::Debug for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Policy")
+            .field("name", &self.name)
+            .field("table", &self.table)
+            .field("column", &self.column)
+            .field("filter", &self.filter)
+            .field("days", &self.days)
+            .finish()
+    }
+}
+
+impl Policy {
+    pub const fn new(name: &'static str, table: &'static str, column: &'static str, days: i32) -> Self {
+        Policy {
+            name,
+            table,
+            column,
+            filter: None,
+            days,
+            purge: None,
+        }
+    }
+
+    pub const fn filter(mut self, filter: &'static str) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    pub const fn purge(mut self, purge: Purge) -> Self {
+        self.purge = Some(purge);
+        self
+    }
+
+    pub fn keeps_forever(&self) -> bool {
+        self.days <= 0
+    }
+
+    /// The `WHERE` clause for the rows this covers, with the days as `$1`.
+    /// Names and filters are the policy's own, never user input.
+    pub fn condition(&self) -> String {
+        let aged = format!("{} <= now() - make_interval(days => $1)", self.column);
+        match self.filter {
+            Some(filter) => format!("{} AND ({})", aged, filter),
+            None => aged,
+        }
+    }
+}
+
+/// What applying a policy came to.
+#[derive(Clone, Debug, Serialize)]
+pub struct Outcome {
+    pub policy: &'static str,
+    pub table: &'static str,
+    pub days: i32,
+    /// Rows removed, or with `dry_run`, that would have been.
+    pub rows: u64,
+    pub dry_run: bool,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.days <= 0 {
+            return write!(f, "{}: kept forever", self.policy);
+        }
+        let verb = if self.dry_run { "would remove" } else { "removed" };
+        write!(
+            f,
+            "{}: {} {} rows from {} older than {} days",
+            self.policy, verb, self.rows, self.table, self.days
+        )
+    }
+}
+
+/// `policies`, with the days in `overrides` for those it names. Negative
+/// days are taken as zero, keeping rows forever.
+pub fn with_overrides(policies: &[Policy], overrides: &HashMap<String, i32>) -> Vec<Policy> {
+    policies
+        .iter()
+        .map(|policy| match overrides.get(policy.name) {
+            Some(&days) => Policy {
+                days: days.max(0),
+                ..*policy
+            },
+            None => *policy,
+        })
+        .collect()
+}
+
+/// `policies`, with the overrides from the `retention.policies` setting.
+pub async fn resolve(pool: &PgPool, policies: &[Policy]) -> Result<Vec<Policy>, Error> {
+    let overrides: HashMap<String, i32> = settings::get_or(pool, POLICIES_SETTING, HashMap::new()).await?;
+    Ok(with_overrides(policies, &overrides))
+}
+
+/// Whether the `retention.dry_run` setting's on.
+pub async fn dry_run(pool: &PgPool) -> Result<bool, Error> {
+    settings::get_or(pool, DRY_RUN_SETTING, false).await
+}
+
+/// How many rows `policy` would remove now.
+pub async fn count(pool: &PgPool, policy: &Policy) -> Result<u64, Error> {
+    if policy.keeps_forever() {
+        return Ok(0);
+    }
+    let sql = format!("SELECT count(*) FROM {} WHERE {}", policy.table, policy.condition());
+    let count: i64 = sqlx::query_scalar(&sql).bind(policy.days).fetch_one(pool).await?;
+    Ok(count as u64)
+}
+
+/// Removes the rows `policy` covers, returning how many.
+pub async fn remove(pool: &PgPool, policy: &Policy) -> Result<u64, Error> {
+    if policy.keeps_forever() {
+        return Ok(0);
+    }
+    if let Some(purge) = policy.purge {
+        return purge(pool, policy.days).await;
+    }
+    let sql = format!("DELETE FROM {} WHERE {}", policy.table, policy.condition());
+    Ok(sqlx::query(&sql).bind(policy.days).execute(pool).await?.rows_affected())
+}
+
+/// Applies each policy in turn, or with `dry_run` counts what each would
+/// remove.
+pub async fn apply(pool: &PgPool, policies: &[Policy], dry_run: bool) -> Result<Vec<Outcome>, Error> {
+    let mut outcomes = Vec::with_capacity(policies.len());
+    for policy in policies {
+        let rows = if dry_run {
+            count(pool, policy).await?
+        } else {
+            remove(pool, policy).await?
+        };
+        outcomes.push(Outcome {
+            policy: policy.name,
+            table: policy.table,
+            days: policy.days,
+            rows,
+            dry_run,
+        });
+    }
+    Ok(outcomes)
+}
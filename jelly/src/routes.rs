@@ -0,0 +1,416 @@
+//! Mounting routes, and a registry of them for `cargo run -- routes`. Each
+//! `configure()` mounts its routes with `routes::scope` or
+//! `routes::resource`, which wrap the guards and rate limits in front of
+//! them and record what they mount, so what's reachable without signing in
+//! can be audited in one place rather than module by module:
+//!
+//! ```ignore
+//! routes::scope("/dashboard")
+//!     .auth()
+//!     .resource(routes::resource("").get(views::dashboard))
+//!     .resource(routes::resource("/preferences").get(views::form).post(views::save))
+//!     .mount(config);
+//! ```
+//!
+//! As with actix-web's `wrap`, what's added last runs first. Views that
+//! limit themselves (e.g logins, by IP) say so with `Resource::limited`.
+
+use std::fmt;
+use std::future::Future;
+use std::sync::RwLock;
+
+use actix_service::{ServiceFactory, Transform};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::web::{self, ServiceConfig};
+use actix_web::{Error, FromRequest, Handler, Responder};
+use lazy_static::lazy_static;
+use serde::Serialize;
+
+use crate::guards::{Admin, Auth, Permission, RateLimit};
+use crate::ratelimit::Limit;
+
+/// Where `Auth` sends anyone who isn't signed in.
+pub const LOGIN_PATH: &str = "/accounts/login";
+
+/// What a route needs from whoever calls it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub enum Access {
+    /// Signed in (`guards::Auth`, or checked by the view).
+    Auth,
+    /// An admin (`guards::Admin`).
+    Admin,
+    /// Signed in with a permission in the group (`guards::Permission`),
+    /// e.g `"accounts."`; admins have them all.
+    Permission(&'static str),
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Access::Auth => write!(f, "auth"),
+            Access::Admin => write!(f, "admin"),
+            Access::Permission("") => write!(f, "permission:*"),
+            Access::Permission(group) => write!(f, "permission:{}*", group),
+        }
+    }
+}
+
+/// A rate limit in front of a route, and what it counts against, e.g
+/// `"ip"` or `"user"`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RouteLimit {
+    pub max: u32,
+    pub window_minutes: u64,
+    pub per: &'static str,
+}
+
+impl RouteLimit {
+    pub fn new(limit: Limit, per: &'static str) -> Self {
+        RouteLimit {
+            max: limit.max,
+            window_minutes: limit.window.as_secs() / 60,
+            per,
+        }
+    }
+}
+
+impl fmt::Display for RouteLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.max == 0 {
+            return write!(f, "off per {}", self.per);
+        }
+        write!(f, "{}/{}m per {}", self.max, self.window_minutes, self.per)
+    }
+}
+
+/// A route as recorded: its methods (e.g `"GET POST"`), full path, and
+/// what's in front of it.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Route {
+    pub methods: String,
+    pub path: String,
+    pub access: Vec<Access>,
+    pub rate_limits: Vec<RouteLimit>,
+}
+
+impl Route {
+    /// Whether anyone can reach it, signed in or not.
+    pub fn is_public(&self) -> bool {
+        self.access.is_empty()
+    }
+}
+
+/// What actix-web can mount: a scope's or a resource's services, with
+/// whatever's been wrapped around them.
+pub trait Mountable:
+    ServiceFactory<ServiceRequest, Config = (), Response = ServiceResponse, Error = Error, InitError = ()> + 'static
+{
+}
+
+impl<T> Mountable for T where
+    T: ServiceFactory<ServiceRequest, Config = (), Response = ServiceResponse, Error = Error, InitError = ()> + 'static
+{
+}
+
+/// Routes under a common prefix, with the same guards in front.
+pub struct Scope<T> {
+    prefix: &'static str,
+    inner: actix_web::Scope<T>,
+    access: Vec<Access>,
+    rate_limits: Vec<RouteLimit>,
+    routes: Vec<Route>,
+}
+
+/// Starts the routes under `prefix`.
+pub fn scope(prefix: &'static str) -> Scope<impl Mountable> {
+    Scope {
+        prefix,
+        inner: web::scope(prefix),
+        access: Vec::new(),
+        rate_limits: Vec::new(),
+        routes: Vec::new(),
+    }
+}
+
+impl<T: Mountable> Scope<T> {
+    /// Wraps middleware that doesn't guard anything, e.g `Idempotency`.
+    pub fn wrap<M>(self, middleware: M) -> Scope<impl Mountable>
+    where
+        M: Transform<T::Service, ServiceRequest, Response = ServiceResponse, Error = Error, InitError = ()> + 'static,
+    {
+        Scope {
+            prefix: self.prefix,
+            inner: self.inner.wrap(middleware),
+            access: self.access,
+            rate_limits: self.rate_limits,
+            routes: self.routes,
+        }
+    }
+
+    /// Wraps a function as middleware; see actix-web's `Scope::wrap_fn`.
+    pub fn wrap_fn<F, R>(self, middleware: F) -> Scope<impl Mountable>
+    where
+        F: Fn(ServiceRequest, &T::Service) -> R + Clone + 'static,
+        R: Future<Output = Result<ServiceResponse, Error>>,
+    {
+        Scope {
+            prefix: self.prefix,
+            inner: self.inner.wrap_fn(middleware),
+            access: self.access,
+            rate_limits: self.rate_limits,
+            routes: self.routes,
+        }
+    }
+
+    /// Only lets signed in users through (`guards::Auth`).
+    pub fn auth(mut self) -> Scope<impl Mountable> {
+        self.access.push(Access::Auth);
+        self.wrap(Auth {
+            redirect_to: LOGIN_PATH,
+        })
+    }
+
+    /// Only lets admins through (`guards::Admin`), once they're signed in.
+    pub fn admin(mut self) -> Scope<impl Mountable> {
+        self.access.extend([Access::Auth, Access::Admin]);
+        self.wrap(Admin).wrap(Auth {
+            redirect_to: LOGIN_PATH,
+        })
+    }
+
+    /// Only lets through users with a permission in `group`
+    /// (`guards::Permission`), once they're signed in.
+    pub fn permission(mut self, group: &'static str) -> Scope<impl Mountable> {
+        self.access.extend([Access::Auth, Access::Permission(group)]);
+        self.wrap(Permission { group }).wrap(Auth {
+            redirect_to: LOGIN_PATH,
+        })
+    }
+
+    /// Limits how fast each caller can come in (`guards::RateLimit`); `per`
+    /// says what it counts against, e.g `"user"`.
+    pub fn rate_limit(mut self, rate_limit: RateLimit, per: &'static str) -> Scope<impl Mountable> {
+        self.rate_limits.push(RouteLimit::new(rate_limit.limit, per));
+        self.wrap(rate_limit)
+    }
+
+    /// Adds `resource`, its path under the scope's prefix.
+    pub fn resource<R: Mountable>(mut self, resource: Resource<R>) -> Self {
+        self.routes.push(resource.route(self.prefix));
+        self.inner = self.inner.service(resource.inner);
+        self
+    }
+
+    /// Mounts the scope, and records its routes with what's in front of
+    /// them.
+    pub fn mount(self, config: &mut ServiceConfig) {
+        for mut route in self.routes {
+            route.access.splice(0..0, self.access.iter().cloned());
+            route.rate_limits.splice(0..0, self.rate_limits.iter().cloned());
+            record(route);
+        }
+        config.service(self.inner);
+    }
+}
+
+/// A path's routes, by method.
+pub struct Resource<T> {
+    path: String,
+    inner: actix_web::Resource<T>,
+    methods: Vec<&'static str>,
+    access: Vec<Access>,
+    rate_limits: Vec<RouteLimit>,
+}
+
+/// Starts the routes for `path`.
+pub fn resource(path: &str) -> Resource<impl Mountable> {
+    Resource {
+        path: path.to_string(),
+        inner: web::resource(path),
+        methods: Vec::new(),
+        access: Vec::new(),
+        rate_limits: Vec::new(),
+    }
+}
+
+impl<T: Mountable> Resource<T> {
+    fn method(mut self, method: &'static str, route: actix_web::Route) -> Self {
+        self.methods.push(method);
+        self.inner = self.inner.route(route);
+        self
+    }
+
+    pub fn get<F, Args>(self, handler: F) -> Self
+    where
+        F: Handler<Args>,
+        Args: FromRequest + 'static,
+        F::Output: Responder + 'static,
+    {
+        self.method("GET", web::get().to(handler))
+    }
+
+    pub fn post<F, Args>(self, handler: F) -> Self
+    where
+        F: Handler<Args>,
+        Args: FromRequest + 'static,
+        F::Output: Responder + 'static,
+    {
+        self.method("POST", web::post().to(handler))
+    }
+
+    pub fn put<F, Args>(self, handler: F) -> Self
+    where
+        F: Handler<Args>,
+        Args: FromRequest + 'static,
+        F::Output: Responder + 'static,
+    {
+        self.method("PUT", web::put().to(handler))
+    }
+
+    pub fn patch<F, Args>(self, handler: F) -> Self
+    where
+        F: Handler<Args>,
+        Args: FromRequest + 'static,
+        F::Output: Responder + 'static,
+    {
+        self.method("PATCH", web::patch().to(handler))
+    }
+
+    pub fn delete<F, Args>(self, handler: F) -> Self
+    where
+        F: Handler<Args>,
+        Args: FromRequest + 'static,
+        F::Output: Responder + 'static,
+    {
+        self.method("DELETE", web::delete().to(handler))
+    }
+
+    /// Handles every method.
+    pub fn to<F, Args>(mut self, handler: F) -> Self
+    where
+        F: Handler<Args>,
+        Args: FromRequest + 'static,
+        F::Output: Responder + 'static,
+    {
+        self.methods.push("ANY");
+        self.inner = self.inner.to(handler);
+        self
+    }
+
+    /// Names the resource, for `HttpRequest::url_for`.
+    pub fn name(mut self, name: &str) -> Self {
+        self.inner = self.inner.name(name);
+        self
+    }
+
+    /// Notes a rate limit the view applies itself.
+    pub fn limited(mut self, limit: Limit, per: &'static str) -> Self {
+        self.rate_limits.push(RouteLimit::new(limit, per));
+        self
+    }
+
+    /// Only lets signed in users through (`guards::Auth`).
+    pub fn auth(mut self) -> Resource<impl Mountable> {
+        self.access.push(Access::Auth);
+        Resource {
+            path: self.path,
+            inner: self.inner.wrap(Auth {
+                redirect_to: LOGIN_PATH,
+            }),
+            methods: self.methods,
+            access: self.access,
+            rate_limits: self.rate_limits,
+        }
+    }
+
+    /// Only lets through users with a permission in `group`
+    /// (`guards::Permission`), once they're signed in.
+    pub fn permission(mut self, group: &'static str) -> Resource<impl Mountable> {
+        self.access.extend([Access::Auth, Access::Permission(group)]);
+        Resource {
+            path: self.path,
+            inner: self.inner.wrap(Permission { group }).wrap(Auth {
+                redirect_to: LOGIN_PATH,
+            }),
+            methods: self.methods,
+            access: self.access,
+            rate_limits: self.rate_limits,
+        }
+    }
+
+    /// The route as recorded, under `prefix`.
+    fn route(&self, prefix: &str) -> Route {
+        Route {
+            methods: self.methods.join(" "),
+            path: format!("{}{}", prefix, self.path),
+            access: self.access.clone(),
+            rate_limits: self.rate_limits.clone(),
+        }
+    }
+
+    /// Mounts the resource on its own, outside any scope, and records it.
+    pub fn mount(self, config: &mut ServiceConfig) {
+        record(self.route(""));
+        config.service(self.inner);
+    }
+}
+
+lazy_static! {
+    static ref ROUTES: RwLock<Vec<Route>> = RwLock::new(Vec::new());
+}
+
+/// Adds `route` to the registry, unless it's there already: `configure()`
+/// runs once per worker.
+fn record(route: Route) {
+    let mut routes = ROUTES.write().unwrap_or_else(|e| e.into_inner());
+    if !routes.iter().any(|r| r.methods == route.methods && r.path == route.path) {
+        routes.push(route);
+    }
+}
+
+/// Every recorded route, by path.
+pub fn all() -> Vec<Route> {
+    let mut routes = ROUTES.read().unwrap_or_else(|e| e.into_inner()).clone();
+    routes.sort_by(|a, b| a.path.cmp(&b.path).then(a.methods.cmp(&b.methods)));
+    routes
+}
+
+/// `routes` as a table, one per line, with `public` for the ones that
+/// need nothing.
+pub fn table(routes: &[Route]) -> String {
+    let rows: Vec<[String; 4]> = routes
+        .iter()
+        .map(|route| {
+            let access = match route.is_public() {
+                true => "public".to_string(),
+                false => route.access.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(", "),
+            };
+            let limits = route.rate_limits.iter().map(|l| l.to_string()).collect::<Vec<_>>().join(", ");
+            [route.methods.clone(), route.path.clone(), access, limits]
+        })
+        .collect();
+
+    let mut widths = [0; 3];
+    for row in rows.iter() {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let mut out = String::new();
+    for [methods, path, access, limits] in rows {
+        let line = format!(
+            "{:m$}  {:p$}  {:a$}  {}",
+            methods,
+            path,
+            access,
+            limits,
+            m = widths[0],
+            p = widths[1],
+            a = widths[2]
+        );
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
@@ -0,0 +1,115 @@
+//! Virus scanning with ClamAV, by streaming files to `clamd` with its
+//! `INSTREAM` command. It's optional: set `CLAMD_ADDRESS` to `host:port`
+//! or `unix:/path/to/clamd.sock`, and without it `scan` returns `None`.
+//!
+//! ```ignore
+//! match scan::scan(path).await? {
+//!     Some(Verdict::Infected(threat)) => quarantine(...),
+//!     Some(Verdict::Clean) => ...,
+//!     None => ..., // no scanner configured
+//! }
+//! ```
+
+use std::env;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::error::Error;
+
+/// Size of the chunks files are streamed to `clamd` in.
+const CHUNK_SIZE: usize = 8192;
+
+/// How long to wait on `clamd` before giving up; big files take a while.
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+/// What `clamd` made of a file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Clean,
+    /// With the name of what was found, e.g. `Eicar-Test-Signature`.
+    Infected(String),
+}
+
+/// Where `clamd` listens, if scanning is set up.
+pub fn address() -> Option<String> {
+    env::var("CLAMD_ADDRESS").ok().filter(|address| !address.is_empty())
+}
+
+/// Reads `clamd`'s reply to a stream scan: `stream: OK`, or
+/// `stream: {threat} FOUND`. Anything else (e.g. `... ERROR`, when the
+/// file is over clamd's `StreamMaxLength`) is an error.
+pub fn parse_reply(reply: &str) -> Result<Verdict, Error> {
+    let reply = reply.trim_end_matches('\0').trim();
+    let result = reply.strip_prefix("stream:").map(str::trim);
+
+    match result {
+        Some("OK") => Ok(Verdict::Clean),
+        Some(result) if result.ends_with(" FOUND") => {
+            Ok(Verdict::Infected(result.trim_end_matches(" FOUND").to_string()))
+        }
+        _ => Err(Error::Generic(format!("Unexpected reply from clamd: {}", reply))),
+    }
+}
+
+/// Scans the file at `path`, or returns `None` if there's no `clamd` to
+/// scan it with. The socket I/O is blocking, so it's done off the runtime.
+pub async fn scan(path: PathBuf) -> Result<Option<Verdict>, Error> {
+    let address = match address() {
+        Some(address) => address,
+        None => return Ok(None),
+    };
+
+    actix_rt::task::spawn_blocking(move || scan_with(&address, &path))
+        .await
+        .map_err(|e| Error::Generic(format!("Error running virus scan: {:?}", e)))?
+        .map(Some)
+}
+
+fn scan_with(address: &str, path: &Path) -> Result<Verdict, Error> {
+    let io_error = |e: std::io::Error| Error::Generic(format!("Error scanning {}: {:?}", path.display(), e));
+    let mut file = File::open(path).map_err(io_error)?;
+
+    let reply = match address.strip_prefix("unix:") {
+        #[cfg(unix)]
+        Some(socket) => {
+            let stream = std::os::unix::net::UnixStream::connect(socket).map_err(io_error)?;
+            stream.set_read_timeout(Some(TIMEOUT)).map_err(io_error)?;
+            instream(stream, &mut file).map_err(io_error)?
+        }
+        #[cfg(not(unix))]
+        Some(_) => return Err(Error::Generic("clamd unix sockets need a unix".to_string())),
+        None => {
+            let stream = TcpStream::connect(address).map_err(io_error)?;
+            stream.set_read_timeout(Some(TIMEOUT)).map_err(io_error)?;
+            instream(stream, &mut file).map_err(io_error)?
+        }
+    };
+
+    parse_reply(&reply)
+}
+
+/// Streams `file` to `clamd` as length-prefixed chunks, ending with an
+/// empty one, and reads back the (null terminated) reply.
+fn instream<S: Read + Write>(mut stream: S, file: &mut impl Read) -> std::io::Result<String> {
+    stream.write_all(b"zINSTREAM\0")?;
+
+    let mut buffer = [0; CHUNK_SIZE];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        stream.write_all(&(read as u32).to_be_bytes())?;
+        stream.write_all(&buffer[..read])?;
+    }
+    stream.write_all(&0u32.to_be_bytes())?;
+    stream.flush()?;
+
+    // clamd closes the connection once it's replied.
+    let mut reply = Vec::new();
+    stream.read_to_end(&mut reply)?;
+    Ok(String::from_utf8_lossy(&reply).into_owned())
+}
@@ -0,0 +1,70 @@
+//! Page metadata for search engines and link previews (OpenGraph).
+//!
+//! Views build a `Seo` and insert it into their template context; the base
+//! layout renders it as `<meta>` tags, unless a template overrides the
+//! `og_*` blocks itself.
+//!
+//! ```rust,ignore
+//! let mut ctx = Context::new();
+//! Seo::new("Jane's profile")
+//!     .description("Jane writes about Rust.")
+//!     .kind("profile")
+//!     .insert(&mut ctx);
+//! ```
+
+use std::env;
+
+use serde::Serialize;
+use tera::Context;
+
+#[derive(Debug, Default, Serialize)]
+pub struct Seo {
+    pub title: String,
+    pub description: String,
+    pub image: Option<String>,
+    pub url: Option<String>,
+    pub kind: String,
+}
+
+impl Seo {
+    pub fn new<S>(title: S) -> Self where S: Into<String> {
+        Seo {
+            title: title.into(),
+            kind: "website".to_string(),
+            ..Seo::default()
+        }
+    }
+
+    pub fn description<S>(mut self, description: S) -> Self where S: Into<String> {
+        self.description = description.into();
+        self
+    }
+
+    pub fn image<S>(mut self, image: S) -> Self where S: Into<String> {
+        self.image = Some(image.into());
+        self
+    }
+
+    /// A path (`/u/jane/`) is made absolute using `JELLY_DOMAIN`.
+    pub fn url<S>(mut self, url: S) -> Self where S: Into<String> {
+        let url = url.into();
+        self.url = Some(if url.starts_with('/') {
+            let domain = env::var("JELLY_DOMAIN").unwrap_or_default();
+            format!("{}{}", domain.trim_end_matches('/'), url)
+        } else {
+            url
+        });
+        self
+    }
+
+    /// The OpenGraph type, e.g `website`, `article` or `profile`.
+    pub fn kind<S>(mut self, kind: S) -> Self where S: Into<String> {
+        self.kind = kind.into();
+        self
+    }
+
+    /// Inserts this as `seo` into a template context.
+    pub fn insert(self, context: &mut Context) {
+        context.insert("seo", &self);
+    }
+}
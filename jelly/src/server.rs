@@ -1,18 +1,27 @@
 use std::env;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use actix_session::{SessionMiddleware, storage::CookieSessionStore};
+use actix_session::{SessionMiddleware, storage::{CookieSessionStore, SessionStore}};
 use actix_web::cookie::Key;
+use actix_web::http::StatusCode;
 use actix_web::{dev, middleware, web, App, HttpServer};
 use actix_web::web::ServiceConfig;
 use background_jobs::memory_storage::Storage;
 use background_jobs::WorkerConfig;
 use sqlx::postgres::{PgPool, PgPoolOptions};
 
+use crate::accounts::AuthMode;
 use crate::email::{Configurable, Email};
-use crate::jobs::{JobConfig, JobState, DEFAULT_QUEUE};
+use crate::guards::{AssignRequestId, ContentSecurityPolicy, Csrf, ReadOnly};
+use crate::jobs::cron::{self, Cron};
+use crate::jobs::{traced, JobConfig, JobState, Traced, BULK_QUEUE, DEFAULT_QUEUE, TRANSACTIONAL_QUEUE};
 use crate::templates::TemplateStore;
 
+/// actix-web's default access log line, with the request's id (see
+/// `guards::RequestId`) on the end.
+const LOG_FORMAT: &str = r#"%a "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T %{x-request-id}o"#;
+
 /// We package the startup as a separate struct,
 /// so it can be used outside the server, for
 /// other Actors who need access to logging, email, database,
@@ -25,8 +34,9 @@ pub struct ServerConfig {
 impl ServerConfig {
     /// Initialize the configuration.
     pub async fn load() -> Self {
-        dotenv::dotenv().ok();
-        pretty_env_logger::init();
+        let profile = crate::config::load();
+        crate::logging::init();
+        info!("Using the {} configuration profile.", profile);
         Email::check_conf();
 
         let template_store = crate::templates::load();
@@ -43,10 +53,27 @@ impl ServerConfig {
 
 /// This struct provides a slightly simpler way to write `main.rs` in
 /// the root project, and forces more coupling to app-specific modules.
-#[derive(Default)]
-pub struct Server {
+///
+/// Sessions are kept in a signed cookie unless another `SessionStore` is
+/// given with `with_session_store`.
+pub struct Server<S = CookieSessionStore> {
     apps: Vec<Box<dyn Fn(&mut ServiceConfig) + Send + Sync + 'static>>,
     jobs: Vec<Box<dyn Fn(JobConfig) -> JobConfig + Send + Sync + 'static>>,
+    cron: Vec<cron::Entry>,
+    auth_mode: AuthMode,
+    session_store: Arc<dyn Fn() -> S + Send + Sync + 'static>,
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Server {
+            apps: Vec::new(),
+            jobs: Vec::new(),
+            cron: Vec::new(),
+            auth_mode: AuthMode::default(),
+            session_store: Arc::new(CookieSessionStore::default),
+        }
+    }
 }
 
 impl Server {
@@ -55,6 +82,12 @@ impl Server {
     pub fn new() -> Self {
         Server::default()
     }
+}
+
+impl<S> Server<S>
+where
+    S: SessionStore + 'static,
+{
 
     /// Registers a service.
     pub fn register_service<F>(mut self, handler: F) -> Self
@@ -65,7 +98,8 @@ impl Server {
         self
     }
 
-    /// Registers jobs.
+    /// Registers jobs. Register them as `jobs::Traced<J>`, so they're put
+    /// off in read-only mode.
     pub fn register_jobs<F>(mut self, handler: F) -> Self
     where
         F: Fn(JobConfig) -> JobConfig + Send + Sync + 'static,
@@ -74,6 +108,103 @@ impl Server {
         self
     }
 
+    /// Registers a job that runs on its schedule; see `jobs::cron`.
+    pub fn register_cron<J>(mut self) -> Self
+    where
+        J: Cron,
+        J::Future: 'static,
+    {
+        self.jobs.push(Box::new(|config| config.register::<Traced<J>>()));
+        self.cron.push(cron::Entry::new::<J>());
+        self
+    }
+
+    /// Adds an OAuth provider of the app's own, e.g an internal identity
+    /// provider, or replaces a built-in one of the same name; see
+    /// `oauth::client::ScopedClientConfig`.
+    #[cfg(feature = "oauth")]
+    pub fn register_oauth_provider(self, name: &'static str, config: crate::oauth::client::ScopedClientConfig) -> Self {
+        crate::oauth::client::register(name, config);
+        self
+    }
+
+    /// Picks whether users are signed in with cookie sessions (the
+    /// default), bearer tokens, or either; see `accounts::jwt`.
+    pub fn auth_mode(mut self, mode: AuthMode) -> Self {
+        self.auth_mode = mode;
+        self
+    }
+
+    /// Keeps sessions in another `SessionStore`, e.g Redis, instead of a
+    /// cookie. Each worker gets its own store, made by calling `store`:
+    ///
+    /// ```ignore
+    /// let redis = env::var("REDIS_URL").expect("REDIS_URL not set!");
+    /// Server::new().with_session_store(move || RedisActorSessionStore::new(redis.clone()))
+    /// ```
+    pub fn with_session_store<T, F>(self, store: F) -> Server<T>
+    where
+        T: SessionStore + 'static,
+        F: Fn() -> T + Send + Sync + 'static,
+    {
+        Server {
+            apps: self.apps,
+            jobs: self.jobs,
+            cron: self.cron,
+            auth_mode: self.auth_mode,
+            session_store: Arc::new(store),
+        }
+    }
+
+    /// Every route the server would mount, as recorded by the services'
+    /// `configure()`s (see `routes`), rather than serving.
+    pub fn routes(&self) -> Vec<crate::routes::Route> {
+        // Configuring is what records them; the app itself isn't needed.
+        let app = App::new()
+            .configure(crate::accounts::jwt::configure)
+            .configure(crate::avatars::configure)
+            .configure(crate::qr::configure)
+            .configure(crate::recorder::configure)
+            .configure(crate::storage::configure)
+            .configure(crate::thumbnails::configure);
+        let _app = self.apps.iter().fold(app, |app, handler| app.configure(handler));
+        crate::routes::all()
+    }
+
+    /// Renders `paths` as an anonymous visitor sees them and writes them
+    /// under `dir` (see `prerender`), rather than serving. Only the
+    /// registered services are mounted, and no jobs run. A path that
+    /// doesn't come back 200 stops the export, so a broken page never
+    /// goes out.
+    pub async fn export_static(self, config: ServerConfig, paths: &[String], dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+        let secret_key = Key::from(env::var("SECRET_KEY").expect("SECRET_KEY not set!").as_bytes());
+
+        let mut app = App::new()
+            .app_data(config.pool.clone())
+            .app_data(config.template_store.templates.clone())
+            .app_data(self.auth_mode)
+            .wrap(SessionMiddleware::new((self.session_store)(), secret_key));
+        for handler in self.apps.iter() {
+            app = app.configure(handler);
+        }
+        let app = actix_web::test::init_service(app).await;
+
+        let mut written = Vec::with_capacity(paths.len());
+        for path in paths {
+            let request = actix_web::test::TestRequest::get().uri(path).to_request();
+            let response = actix_web::test::call_service(&app, request).await;
+            if response.status() != StatusCode::OK {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    format!("{} returned {}", path, response.status()),
+                ));
+            }
+            let body = actix_web::test::read_body(response).await;
+            written.push(crate::prerender::write(dir, path, &body)?);
+        }
+        Ok(written)
+    }
+
     /// Consumes and then runs the server, with default settings that we
     /// generally want.
     pub async fn run(self, config: ServerConfig) -> std::io::Result<dev::Server> {
@@ -81,24 +212,42 @@ impl Server {
         let secret_key = Key::from(env::var("SECRET_KEY").expect("SECRET_KEY not set!").as_bytes());
         let _root_domain = env::var("JELLY_DOMAIN").expect("JELLY_DOMAIN not set!");
 
+        #[cfg(feature = "oauth")]
+        crate::oauth::client::load_providers_file()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+
         #[cfg(feature = "production")]
         let cookie_domain = env::var("SESSIONID_DOMAIN").expect("SESSIONID_DOMAIN not set!");
 
+        let csp = env::var("CONTENT_SECURITY_POLICY")
+            .map(|policy| ContentSecurityPolicy { policy })
+            .unwrap_or_default();
+
+        // It runs shell commands, so never in production, whatever else is on.
+        #[cfg(all(feature = "asset_watcher", not(feature = "production")))]
+        let assets = crate::assets::supervisor::AssetSupervisor::start();
+
+        #[cfg(unix)]
+        actix_rt::spawn(crate::logging::listen_for_signal());
+
+        let auth_mode = self.auth_mode;
+        let session_store = self.session_store;
         let apps = Arc::new(self.apps);
         let jobs = Arc::new(self.jobs);
+        let schedules = Arc::new(self.cron);
 
         let server = HttpServer::new(move || {
             // !production needs no domain set, because browsers.
             #[cfg(not(feature = "production"))]
             let session_storage = SessionMiddleware::builder(
-                CookieSessionStore::default(), secret_key.clone())
+                session_store(), secret_key.clone())
                 .cookie_path("/".to_string())
                 .cookie_name("sessionid".to_string())
                 .cookie_secure(false);
 
             #[cfg(feature = "production")]
             let session_storage = SessionMiddleware::builder(
-                CookieSessionStore::default(), secret_key.clone())
+                session_store(), secret_key.clone())
                 .cookie_path("/".to_string())
                 .cookie_name("sessionid".to_string())
                 .cookie_secure(true)
@@ -108,9 +257,23 @@ impl Server {
             let mut app = App::new()
                 .app_data(config.pool.clone())
                 .app_data(config.template_store.templates.clone())
-                .wrap(middleware::Logger::default())
+                .app_data(auth_mode)
+                .wrap(ReadOnly)
+                .wrap(Csrf)
+                .wrap(csp.clone())
+                // Dev builds only; see `recorder`.
+                .wrap(crate::recorder::Recorder)
+                .wrap(AssignRequestId)
+                .wrap(middleware::Logger::new(LOG_FORMAT))
+                .wrap(crate::accounts::RememberMe)
                 .wrap(session_storage.build())
                 .configure(crate::utils::static_handler)
+                .configure(crate::accounts::jwt::configure)
+                .configure(crate::avatars::configure)
+                .configure(crate::qr::configure)
+                .configure(crate::recorder::configure)
+                .configure(crate::storage::configure)
+                .configure(crate::thumbnails::configure)
                 // Depending on your CORS needs, you may opt to change the
                 // default service. Up to you.
                 .default_service(web::to(crate::utils::default_handler));
@@ -124,7 +287,15 @@ impl Server {
             // TODO 104: can we avoid clone() ?
             let storage = Storage::new();
             let state = JobState::new("JobState", config.pool.clone(), config.template_store.templates.clone());
-            let mut worker_config = WorkerConfig::new(storage, move |_| state.clone());
+            let mut worker_config = WorkerConfig::new(storage, move |_| state.clone())
+                .register::<Traced<crate::email::SendTransactionalEmail>>()
+                .register::<Traced<crate::email::SendBulkEmail>>()
+                .register::<Traced<crate::push::SendPush>>();
+
+            #[cfg(feature = "pdf")]
+            {
+                worker_config = worker_config.register::<Traced<crate::pdf::RenderPdf>>();
+            }
 
             for handler in jobs.iter() {
                 worker_config = (*handler)(worker_config);
@@ -132,7 +303,15 @@ impl Server {
 
             let queue_handle = worker_config
                 .set_worker_count(DEFAULT_QUEUE, 16)
+                .set_worker_count(TRANSACTIONAL_QUEUE, 8)
+                .set_worker_count(BULK_QUEUE, 1)
                 .start();
+            traced::set_queue(queue_handle.clone());
+            cron::spawn(&schedules, config.pool.clone(), queue_handle.clone());
+
+            // Hold requests until the initial asset build is done.
+            #[cfg(all(feature = "asset_watcher", not(feature = "production")))]
+            let app = app.wrap(assets.guard());
 
             app.app_data(web::Data::new(queue_handle))
         })
@@ -0,0 +1,189 @@
+//! Settings admins can change while the app's running, kept as JSON in the
+//! `settings` table by key (e.g `oauth.disabled_providers`). Reads go
+//! through a cache, so hot paths like logging in don't query for them every
+//! time; `set` and `remove` clear the key on this server straight away, and
+//! other servers pick the change up within `SETTINGS_CACHE_SECONDS` (60 by
+//! default).
+//!
+//! ```ignore
+//! let disabled: Vec<String> = settings::get_or(pool, "oauth.disabled_providers", Vec::new()).await?;
+//! ```
+//!
+//! Keys `register`ed with the type they're read as are checked by
+//! `validate` before admins can save them; a value that's still the wrong
+//! type (set some other way) is logged and read as the default.
+
+use std::collections::HashMap;
+use std::env;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use sqlx::postgres::PgPool;
+
+use crate::error::Error;
+use crate::{read_only, retention};
+
+/// Values by key, each for up to `ttl`. A key that isn't set is cached as
+/// `None`, so missing settings are no dearer than ones that are there.
+#[derive(Debug)]
+pub struct Cache {
+    ttl: Duration,
+    entries: RwLock<HashMap<String, (Option<Value>, Instant)>>,
+}
+
+impl Cache {
+    pub fn new(ttl: Duration) -> Self {
+        Cache {
+            ttl,
+            entries: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// The cached value of `key` (itself `None` if it isn't set), or `None`
+    /// if it needs loading.
+    pub fn get(&self, key: &str) -> Option<Option<Value>> {
+        let entries = self.entries.read().unwrap_or_else(|e| e.into_inner());
+        match entries.get(key) {
+            Some((value, loaded)) if loaded.elapsed() < self.ttl => Some(value.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn insert(&self, key: &str, value: Option<Value>) {
+        let mut entries = self.entries.write().unwrap_or_else(|e| e.into_inner());
+        entries.insert(key.to_string(), (value, Instant::now()));
+    }
+
+    pub fn invalidate(&self, key: &str) {
+        self.entries.write().unwrap_or_else(|e| e.into_inner()).remove(key);
+    }
+
+    pub fn clear(&self) {
+        self.entries.write().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+}
+
+fn ttl() -> Duration {
+    let seconds = env::var("SETTINGS_CACHE_SECONDS")
+        .ok()
+        .and_then(|seconds| seconds.parse().ok())
+        .unwrap_or(60);
+    Duration::from_secs(seconds)
+}
+
+/// Whether a value can be read as the type its key is.
+type Check = fn(&Value) -> Result<(), String>;
+
+fn check<T: DeserializeOwned>(value: &Value) -> Result<(), String> {
+    serde_json::from_value::<T>(value.clone())
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+lazy_static! {
+    static ref CACHE: Cache = Cache::new(ttl());
+    static ref KNOWN: RwLock<HashMap<&'static str, Check>> = {
+        let mut known: HashMap<&'static str, Check> = HashMap::new();
+        known.insert(read_only::SETTING, check::<bool>);
+        known.insert(retention::POLICIES_SETTING, check::<HashMap<String, i32>>);
+        known.insert(retention::DRY_RUN_SETTING, check::<bool>);
+        RwLock::new(known)
+    };
+}
+
+/// Notes that `key` is read as a `T`, so values that aren't one can't be
+/// saved. Jelly's own settings are already known.
+pub fn register<T: DeserializeOwned>(key: &'static str) {
+    KNOWN.write().unwrap_or_else(|e| e.into_inner()).insert(key, check::<T>);
+}
+
+/// Checks `value` can be read as the type `key` is registered with. Keys
+/// nobody's registered can hold anything.
+pub fn validate(key: &str, value: &Value) -> Result<(), String> {
+    let known = KNOWN.read().unwrap_or_else(|e| e.into_inner());
+    match known.get(key) {
+        Some(check) => check(value),
+        None => Ok(()),
+    }
+}
+
+/// The raw value of `key`, if it's set.
+pub async fn get_value(pool: &PgPool, key: &str) -> Result<Option<Value>, Error> {
+    if let Some(value) = CACHE.get(key) {
+        return Ok(value);
+    }
+
+    let value: Option<Value> = sqlx::query_scalar("SELECT value FROM settings WHERE key = $1")
+        .bind(key)
+        .fetch_optional(pool)
+        .await?;
+    CACHE.insert(key, value.clone());
+    Ok(value)
+}
+
+/// The value of `key`, if it's set. A value that isn't a `T` is an error.
+pub async fn get<T: DeserializeOwned>(pool: &PgPool, key: &str) -> Result<Option<T>, Error> {
+    match get_value(pool, key).await? {
+        Some(value) => Ok(Some(serde_json::from_value(value)?)),
+        None => Ok(None),
+    }
+}
+
+/// The value of `key`, or `default` if it isn't set or isn't a `T`.
+pub async fn get_or<T: DeserializeOwned>(pool: &PgPool, key: &str, default: T) -> Result<T, Error> {
+    match get_value(pool, key).await? {
+        Some(value) => match serde_json::from_value(value) {
+            Ok(value) => Ok(value),
+            Err(e) => {
+                error!("Setting {} is invalid, using the default: {:?}", key, e);
+                Ok(default)
+            }
+        },
+        None => Ok(default),
+    }
+}
+
+/// Every setting, by key, for admins to see. Not cached.
+pub async fn all(pool: &PgPool) -> Result<Vec<(String, Value)>, Error> {
+    Ok(sqlx::query_as("SELECT key, value FROM settings ORDER BY key")
+        .fetch_all(pool)
+        .await?)
+}
+
+pub async fn set(pool: &PgPool, key: &str, value: &Value) -> Result<(), Error> {
+    sqlx::query(
+        "
+        INSERT INTO settings (key, value) VALUES ($1, $2)
+        ON CONFLICT (key) DO UPDATE SET value = $2, updated = now()
+    ",
+    )
+    .bind(key)
+    .bind(value)
+    .execute(pool)
+    .await?;
+
+    invalidate(key);
+    Ok(())
+}
+
+pub async fn remove(pool: &PgPool, key: &str) -> Result<(), Error> {
+    sqlx::query("DELETE FROM settings WHERE key = $1")
+        .bind(key)
+        .execute(pool)
+        .await?;
+
+    invalidate(key);
+    Ok(())
+}
+
+/// Drops `key` from this server's cache, so it's loaded afresh next time.
+pub fn invalidate(key: &str) {
+    CACHE.invalidate(key);
+}
+
+pub fn invalidate_all() {
+    CACHE.clear();
+}
@@ -0,0 +1,65 @@
+//! A sitemaps.org `<urlset>`, for search engines:
+//!
+//! ```ignore
+//! let mut sitemap = Sitemap::new();
+//! sitemap.push("https://example.com/", None);
+//! sitemap.push("https://example.com/pages/hello", Some(updated));
+//! Ok(HttpResponse::Ok().content_type(sitemap::CONTENT_TYPE).body(sitemap.to_string()))
+//! ```
+
+use std::fmt;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+
+pub const CONTENT_TYPE: &str = "application/xml; charset=utf-8";
+
+/// A page, and when it last changed, if that's known.
+#[derive(Clone, Debug)]
+pub struct Url {
+    pub loc: String,
+    pub lastmod: Option<DateTime<Utc>>,
+}
+
+/// The pages to list. `Display` renders the finished document.
+#[derive(Clone, Debug, Default)]
+pub struct Sitemap {
+    pub urls: Vec<Url>,
+}
+
+impl Sitemap {
+    pub fn new() -> Self {
+        Sitemap::default()
+    }
+
+    pub fn push(&mut self, loc: impl Into<String>, lastmod: Option<DateTime<Utc>>) -> &mut Self {
+        self.urls.push(Url {
+            loc: loc.into(),
+            lastmod,
+        });
+        self
+    }
+}
+
+impl fmt::Display for Sitemap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, r#"<?xml version="1.0" encoding="utf-8"?>"#)?;
+        writeln!(f, r#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">"#)?;
+        for url in self.urls.iter() {
+            write!(f, "  <url><loc>{}</loc>", escape(&url.loc))?;
+            if let Some(lastmod) = &url.lastmod {
+                write!(f, "<lastmod>{}</lastmod>", lastmod.to_rfc3339_opts(SecondsFormat::Secs, true))?;
+            }
+            writeln!(f, "</url>")?;
+        }
+        writeln!(f, "</urlset>")
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
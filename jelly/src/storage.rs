@@ -0,0 +1,138 @@
+//! Locally stored files, kept under `STORAGE_ROOT` (default `storage`)
+//! with a directory per tenant, and served through the app rather than
+//! exposed directly - so views decide who sees what:
+//!
+//! ```ignore
+//! let path = storage::path_for(request.tenant_pool()?.tenant(), &name)?;
+//! storage::serve(&request, path).await
+//! ```
+//!
+//! `serve` (with the `storage` feature) handles `Range` requests, answering
+//! with 206 Partial Content, and conditional ones (`If-None-Match`,
+//! `If-Modified-Since`, `If-Range`), so audio and video can be streamed
+//! and seeked in browsers.
+//!
+//! Files can also be handed out without signing in, with `signed_url`;
+//! those links are served at `/download/...` until they expire.
+
+use std::env;
+use std::path::{Component, Path, PathBuf};
+
+use actix_web::web::{self, ServiceConfig};
+use actix_web::{HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+
+use crate::crypto;
+use crate::error::Error;
+use crate::routes;
+use crate::tenancy::TenantId;
+
+const KEY_SALT: &str = "com.jelly.storage";
+
+/// Where files are kept.
+pub fn root() -> PathBuf {
+    env::var("STORAGE_ROOT").unwrap_or_else(|_| "storage".to_string()).into()
+}
+
+/// Where `tenant`'s file `name` lives. `name` may have subdirectories,
+/// but nothing that would climb out of the tenant's directory.
+pub fn path_for(tenant: TenantId, name: &str) -> Result<Option<PathBuf>, Error> {
+    let name = Path::new(name);
+    if !is_relative(name) {
+        return Ok(None);
+    }
+
+    Ok(Some(root().join(tenant.to_string()).join(name)))
+}
+
+/// Moves `tenant`'s file `name` out of reach, under `.quarantine` in the
+/// storage root, e.g. after it fails a virus scan (see `scan`). Returns
+/// whether there was a file to move.
+pub fn quarantine(tenant: TenantId, name: &str) -> Result<bool, Error> {
+    let from = match path_for(tenant, name)? {
+        Some(path) if path.is_file() => path,
+        _ => return Ok(false),
+    };
+    let to = root().join(".quarantine").join(tenant.to_string()).join(name);
+
+    let io_error = |e: std::io::Error| Error::Generic(format!("Error quarantining {}: {:?}", from.display(), e));
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent).map_err(io_error)?;
+    }
+    std::fs::rename(&from, &to).map_err(io_error)?;
+    Ok(true)
+}
+
+/// Whether `name` is a non-empty path that stays below wherever it's joined.
+pub(crate) fn is_relative(name: &Path) -> bool {
+    !name.as_os_str().is_empty()
+        && name
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+}
+
+/// Serves the file at `path`, honouring `Range` and conditional request
+/// headers. It's marked private, so shared caches don't keep a copy.
+#[cfg(feature = "storage")]
+pub async fn serve(request: &HttpRequest, path: Option<PathBuf>) -> Result<HttpResponse, Error> {
+    use actix_files::NamedFile;
+    use actix_web::http::header::{HeaderValue, CACHE_CONTROL};
+
+    let path = match path {
+        Some(path) if path.is_file() => path,
+        _ => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    let file = NamedFile::open_async(path)
+        .await
+        .map_err(|e| Error::Generic(format!("Error opening stored file: {:?}", e)))?
+        .use_etag(true)
+        .use_last_modified(true);
+
+    let mut response = file.into_response(request);
+    response
+        .headers_mut()
+        .insert(CACHE_CONTROL, HeaderValue::from_static("private, no-cache"));
+    Ok(response)
+}
+
+/// Without the `storage` feature there's nothing to serve files with.
+#[cfg(not(feature = "storage"))]
+pub async fn serve(_request: &HttpRequest, _path: Option<PathBuf>) -> Result<HttpResponse, Error> {
+    Ok(HttpResponse::NotFound().finish())
+}
+
+/// A link to `tenant`'s file `name` that works, for anyone who has it,
+/// until `expires`.
+pub fn signed_url(tenant: TenantId, name: &str, expires: DateTime<Utc>) -> String {
+    let expires = expires.timestamp();
+    let signature = crypto::sign(KEY_SALT, &format!("{}/{}/{}", tenant, expires, name));
+    format!("/download/{}/{}/{}/{}", tenant, expires, signature, name)
+}
+
+/// Whether `signature` came from `signed_url`, and hasn't expired.
+pub fn verify(tenant: TenantId, name: &str, expires: i64, signature: &str) -> bool {
+    expires > Utc::now().timestamp()
+        && crypto::verify(KEY_SALT, &format!("{}/{}/{}", tenant, expires, name), signature)
+}
+
+/// Serves a file from a `signed_url`. Anything unsigned, tampered with or
+/// expired is a 404.
+pub async fn download(
+    request: HttpRequest,
+    path: web::Path<(i32, i64, String, String)>,
+) -> Result<HttpResponse, Error> {
+    let (tenant, expires, signature, name) = path.into_inner();
+    let tenant = TenantId(tenant);
+    if !verify(tenant, &name, expires, &signature) {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+
+    serve(&request, path_for(tenant, &name)?).await
+}
+
+pub fn configure(config: &mut ServiceConfig) {
+    routes::resource("/download/{tenant}/{expires}/{signature}/{name:.+}")
+        .get(download)
+        .mount(config);
+}
@@ -10,9 +10,14 @@
 use std::sync::{Arc, RwLock};
 use std::{env, thread};
 
+use fancy_regex::Regex;
+use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
 use tera::Tera;
 
+use crate::assets::AssetManifest;
+use crate::error::Error;
+
 #[cfg(feature = "template_watcher")]
 use std::{fs::read_dir, path::Path, sync::mpsc::channel, time::Duration};
 
@@ -45,9 +50,15 @@ pub struct TemplateStore {
 /// they're updated.
 pub fn load() -> TemplateStore {
     let templates_glob = env::var("TEMPLATES_GLOB").expect("TEMPLATES_GLOB not set!");
-    let templates = Arc::new(RwLock::new(
-        Tera::new(&templates_glob).expect("Unable to compile templates!"),
-    ));
+    let mut tera = Tera::new(&templates_glob).expect("Unable to compile templates!");
+    Arc::new(AssetManifest::load()).register(&mut tera);
+    crate::avatars::register(&mut tera);
+    crate::guards::csrf::register(&mut tera);
+    crate::experiments::register(&mut tera);
+    crate::qr::register(&mut tera);
+    crate::thumbnails::register(&mut tera);
+    crate::timezones::register(&mut tera);
+    let templates = Arc::new(RwLock::new(tera));
 
     #[cfg(feature = "template_watcher")]
     let store = templates.clone();
@@ -157,3 +168,76 @@ fn is_folder_empty(dir: &Path) -> bool {
         .expect("Failed to read a directory to see if it was empty")
         .count() == 0
 }
+
+lazy_static! {
+    static ref BLOCK_TAG: Regex =
+        Regex::new(r"\{%-?\s*(block\s+(\w+)|endblock(\s+\w+)?)\s*-?%\}").unwrap();
+    static ref IMPORT_TAG: Regex = Regex::new(r"\{%-?\s*import\s[^%]*-?%\}").unwrap();
+}
+
+/// Returns the name of a standalone template containing just `block` of
+/// `template`, registering it as `"{template}#{block}"` on first use. This
+/// is what allows rendering e.g only the `content` block for HTMX requests.
+///
+/// The block is cut out of the template source, along with any macro
+/// imports, so `{{ super() }}` isn't available inside it.
+pub(crate) fn block_template(
+    templates: &RwLock<Tera>,
+    template: &str,
+    block: &str,
+) -> Result<String, Error> {
+    let name = format!("{}#{}", template, block);
+
+    let path = {
+        let engine = templates.read().map_err(|e| {
+            Error::Generic(format!("Error acquiring template read lock: {:?}", e))
+        })?;
+        if engine.get_template_names().any(|n| n == name) {
+            return Ok(name);
+        }
+
+        engine.get_template(template)?.path.clone().ok_or_else(|| {
+            Error::Generic(format!("Template {} has no source file", template))
+        })?
+    };
+
+    let source = std::fs::read_to_string(&path)
+        .map_err(|e| Error::Generic(format!("Unable to read {}: {:?}", path, e)))?;
+    let body = extract_block(&source, block)
+        .ok_or_else(|| Error::Generic(format!("No block {} in {}", block, template)))?;
+
+    let mut partial = String::new();
+    for import in IMPORT_TAG.find_iter(&source).flatten() {
+        partial.push_str(import.as_str());
+        partial.push('\n');
+    }
+    partial.push_str(body);
+
+    let mut engine = templates.write().map_err(|e| {
+        Error::Generic(format!("Error acquiring template write lock: {:?}", e))
+    })?;
+    engine.add_raw_template(&name, &partial)?;
+
+    Ok(name)
+}
+
+/// Finds the source between `{% block name %}` and its matching `{% endblock %}`.
+fn extract_block<'a>(source: &'a str, block: &str) -> Option<&'a str> {
+    let mut start = None;
+    let mut depth = 0;
+
+    for caps in BLOCK_TAG.captures_iter(source).flatten() {
+        let tag = caps.get(0)?;
+        match (start, caps.get(2)) {
+            (None, Some(name)) if name.as_str() == block => {
+                start = Some(tag.end());
+            }
+            (None, _) => {}
+            (Some(_), Some(_)) => depth += 1,
+            (Some(begin), None) if depth == 0 => return Some(&source[begin..tag.start()]),
+            (Some(_), None) => depth -= 1,
+        }
+    }
+
+    None
+}
@@ -0,0 +1,141 @@
+//! Helpers for keeping tenant-scoped data apart. Until there are
+//! organizations, the tenant is the account that owns the rows.
+//!
+//! Scoped queries go through `tenant_query!` / `tenant_query_as!`, which
+//! bind the tenant id as `$1` and, in debug builds, panic if the SQL doesn't
+//! actually filter on it - so a forgotten `WHERE account_id = $1` shows up
+//! the first time the query runs in development, rather than as another
+//! customer's data in production.
+//!
+//! ```ignore
+//! let db = request.tenant_pool()?;
+//! let events = jelly::tenant_query_as!(
+//!     db,
+//!     AuditEvent,
+//!     "SELECT * FROM audit_events WHERE account_id = $1 LIMIT $2",
+//!     limit
+//! )
+//! .fetch_all(db.pool())
+//! .await?;
+//! ```
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgPool;
+
+/// The column tenant-scoped tables use, unless told otherwise.
+pub const DEFAULT_COLUMN: &str = "account_id";
+
+/// Identifies a tenant.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TenantId(pub i32);
+
+impl TenantId {
+    pub fn get(self) -> i32 {
+        self.0
+    }
+}
+
+impl fmt::Display for TenantId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// A database pool bound to a single tenant.
+#[derive(Clone, Copy, Debug)]
+pub struct TenantPool<'a> {
+    pool: &'a PgPool,
+    tenant: TenantId,
+    column: &'static str,
+}
+
+impl<'a> TenantPool<'a> {
+    pub fn new(pool: &'a PgPool, tenant: TenantId) -> Self {
+        TenantPool {
+            pool,
+            tenant,
+            column: DEFAULT_COLUMN,
+        }
+    }
+
+    /// Scopes on `column` instead of `DEFAULT_COLUMN`.
+    pub fn with_column(self, column: &'static str) -> Self {
+        TenantPool { column, ..self }
+    }
+
+    pub fn tenant(&self) -> TenantId {
+        self.tenant
+    }
+
+    pub fn column(&self) -> &'static str {
+        self.column
+    }
+
+    /// The underlying pool, to run scoped queries against.
+    pub fn pool(&self) -> &'a PgPool {
+        self.pool
+    }
+
+    /// Panics, in debug builds, if `sql` isn't scoped to the tenant. Called
+    /// by the query macros; there's no need to call it directly.
+    pub fn check(&self, sql: &str) {
+        if cfg!(debug_assertions) && !is_scoped(sql, self.column) {
+            panic!(
+                "Query isn't scoped to the tenant; expected `{} = $1`:\n{}",
+                self.column, sql
+            );
+        }
+    }
+}
+
+/// Whether `sql` is limited to the tenant bound as `$1`: inserts must set
+/// `column` (listed first) to `$1`, and anything else must filter on
+/// `column = $1` (optionally qualified, e.g `e.account_id = $1`).
+pub fn is_scoped(sql: &str, column: &str) -> bool {
+    let sql = sql.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+    let column = column.to_lowercase();
+
+    if sql.starts_with("insert") {
+        let (columns, rest) = match sql.find('(').zip(sql.find(')')) {
+            Some((start, end)) if start < end => (&sql[start + 1..end], sql[end + 1..].trim_start()),
+            _ => return false,
+        };
+        let first_value = rest
+            .strip_prefix("values (")
+            .or_else(|| rest.strip_prefix("select "))
+            .and_then(|values| values.split([',', ')', ' ', ':']).next());
+        return columns.split(',').next().map(str::trim) == Some(column.as_str())
+            && first_value == Some("$1");
+    }
+
+    let filter = format!("{} = $1", column);
+    sql.match_indices(&filter).any(|(i, _)| {
+        let before = sql[..i].chars().last();
+        let after = sql[i + filter.len()..].chars().next();
+        matches!(before, None | Some(' ' | '.' | '('))
+            && !matches!(after, Some(c) if c.is_ascii_digit())
+    })
+}
+
+/// `sqlx::query!`, with the tenant id bound as `$1` ahead of `args`.
+#[macro_export]
+macro_rules! tenant_query {
+    ($db:expr, $sql:literal $(, $args:expr)* $(,)?) => {{
+        let db: &$crate::tenancy::TenantPool = &$db;
+        db.check($sql);
+        ::sqlx::query!($sql, db.tenant().get() $(, $args)*)
+    }};
+}
+
+/// `sqlx::query_as_unchecked!`, with the tenant id bound as `$1` ahead of
+/// `args`.
+#[macro_export]
+macro_rules! tenant_query_as {
+    ($db:expr, $out:path, $sql:literal $(, $args:expr)* $(,)?) => {{
+        let db: &$crate::tenancy::TenantPool = &$db;
+        db.check($sql);
+        ::sqlx::query_as_unchecked!($out, $sql, db.tenant().get() $(, $args)*)
+    }};
+}
@@ -0,0 +1,319 @@
+//! Resized and cropped copies of stored images, rendered on demand at
+//! `/img/{signature}/{size}/{key}` (needs the `thumbnails` feature; without
+//! it, `/img` is a 404). `key` is the image's tenant and name in storage,
+//! `{tenant}/{name}` (see `storage::path_for`), and `size` is
+//! `{width}x{height}` to fit within those bounds, or `{width}x{height}c` to
+//! fill them exactly, cropping the overflow.
+//!
+//! Only signed URLs are rendered, so nobody can make the server resize
+//! images to whatever sizes they like; the `thumbnail_url` Tera function
+//! signs them for you:
+//!
+//! ```html
+//! <img src="{{ thumbnail_url(key=photo.key, size="300x200") }}">
+//! <img src="{{ thumbnail_url(key=photo.key, size="64x64c") }}" alt="">
+//! ```
+//!
+//! Rendered images are cached on disk under `THUMBNAIL_CACHE` (default
+//! `{STORAGE_ROOT}/.thumbnails`), and re-rendered if the original changes.
+//!
+//! Even signed, a key's only rendered if the app's `Sources` says it can
+//! be (call `set_sources` at startup; e.g. only uploads that have been
+//! virus scanned and found clean), and never from hidden directories like
+//! `.quarantine`. Originals bigger than `MAX_SOURCE_PIXELS` aren't decoded.
+
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+use actix_web::web::{self, ServiceConfig};
+use actix_web::{HttpRequest, HttpResponse};
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use sha2::{Digest, Sha256};
+use tera::{Tera, Value};
+
+use crate::crypto;
+use crate::error::Error;
+use crate::routes;
+use crate::storage;
+use crate::tenancy::TenantId;
+
+const KEY_SALT: &str = "com.jelly.thumbnails";
+
+/// The largest width or height a thumbnail can be rendered at.
+pub const MAX_DIMENSION: u32 = 2048;
+
+/// The most pixels an original can have to be decoded, so a small file
+/// claiming to be a huge image can't take all the memory.
+pub const MAX_SOURCE_PIXELS: u64 = 40_000_000;
+
+/// The image types thumbnails are rendered for.
+const EXTENSIONS: [&str; 4] = ["png", "jpg", "jpeg", "gif"];
+
+/// How a thumbnail is sized.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Size {
+    pub width: u32,
+    pub height: u32,
+    /// Fill `width` x `height` exactly, cropping, rather than fitting within it.
+    pub crop: bool,
+}
+
+impl FromStr for Size {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (value, crop) = match value.strip_suffix('c') {
+            Some(value) => (value, true),
+            None => (value, false),
+        };
+        let (width, height) = value.split_once('x').ok_or(())?;
+        let width: u32 = width.parse().map_err(|_| ())?;
+        let height: u32 = height.parse().map_err(|_| ())?;
+        if width == 0 || height == 0 || width > MAX_DIMENSION || height > MAX_DIMENSION {
+            return Err(());
+        }
+
+        Ok(Size { width, height, crop })
+    }
+}
+
+impl fmt::Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}{}", self.width, self.height, if self.crop { "c" } else { "" })
+    }
+}
+
+/// Signs `size` of `key` for `/img`.
+pub fn sign(size: &Size, key: &str) -> String {
+    crypto::sign(KEY_SALT, &format!("{}/{}", size, key))
+}
+
+/// Whether `signature` came from `sign(size, key)`.
+pub fn verify(size: &Size, key: &str, signature: &str) -> bool {
+    crypto::verify(KEY_SALT, &format!("{}/{}", size, key), signature)
+}
+
+/// The `/img` URL for `size` of `key`.
+pub fn url(size: &Size, key: &str) -> String {
+    format!("/img/{}/{}/{}", sign(size, key), size, key)
+}
+
+/// Where rendered thumbnails are kept.
+pub fn cache_root() -> PathBuf {
+    env::var("THUMBNAIL_CACHE")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| storage::root().join(".thumbnails"))
+}
+
+/// Where `size` of `key` is cached. Names are hashed, so keys of any
+/// length or depth end up as one flat file.
+fn cache_path(size: &Size, key: &str, extension: &str) -> PathBuf {
+    let hash = Sha256::digest(format!("{}/{}", size, key).as_bytes());
+    cache_root().join(format!("{:x}.{}", hash, extension))
+}
+
+/// Which stored files thumbnails can be rendered of.
+#[async_trait]
+pub trait Sources: Send + Sync {
+    async fn allows(&self, tenant: TenantId, name: &str) -> Result<bool, Error>;
+}
+
+/// Allows any stored image; the default, for apps without uploads to check.
+#[derive(Debug, Default)]
+pub struct AnySource;
+
+#[async_trait]
+impl Sources for AnySource {
+    async fn allows(&self, _tenant: TenantId, _name: &str) -> Result<bool, Error> {
+        Ok(true)
+    }
+}
+
+lazy_static! {
+    static ref SOURCES: RwLock<Arc<dyn Sources>> = RwLock::new(Arc::new(AnySource));
+}
+
+/// Replaces the `Sources`; call once at startup.
+pub fn set_sources<S: Sources + 'static>(sources: S) {
+    match SOURCES.write() {
+        Ok(mut current) => *current = Arc::new(sources),
+        Err(e) => error!("Unable to set thumbnail sources: {:?}", e),
+    }
+}
+
+fn sources() -> Arc<dyn Sources> {
+    SOURCES.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// The tenant and name `key` is for, if it's one of ours: nothing hidden
+/// (like `.quarantine` or `.thumbnails`), and an image type we render.
+pub fn parse_key(key: &str) -> Option<(TenantId, &str)> {
+    let (tenant, name) = key.split_once('/')?;
+    let tenant = TenantId(tenant.parse().ok()?);
+    let path = Path::new(name);
+    if !storage::is_relative(path) {
+        return None;
+    }
+    let hidden = path.components().any(|component| match component {
+        Component::Normal(part) => part.to_string_lossy().starts_with('.'),
+        _ => true,
+    });
+    if hidden {
+        return None;
+    }
+
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    if !EXTENSIONS.contains(&extension.as_str()) {
+        return None;
+    }
+    Some((tenant, name))
+}
+
+/// The original image for `key`, and its (lowercased) extension, if it's
+/// a stored image we render thumbnails of.
+async fn source_for(key: &str) -> Result<Option<(PathBuf, String)>, Error> {
+    let (tenant, name) = match parse_key(key) {
+        Some(parsed) => parsed,
+        None => return Ok(None),
+    };
+    if !sources().allows(tenant, name).await? {
+        return Ok(None);
+    }
+
+    let path = match storage::path_for(tenant, name)? {
+        Some(path) if path.is_file() => path,
+        _ => return Ok(None),
+    };
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default().to_lowercase();
+    Ok(Some((path, extension)))
+}
+
+/// Whether the cached thumbnail at `cached` is at least as new as `source`.
+fn is_current(cached: &Path, source: &Path) -> bool {
+    let modified = |path: &Path| path.metadata().and_then(|metadata| metadata.modified());
+    match (modified(cached), modified(source)) {
+        (Ok(cached), Ok(source)) => cached >= source,
+        _ => false,
+    }
+}
+
+/// Renders `size` of the image at `source` to `target`. It's written to a
+/// temporary file first, so concurrent requests never see half an image.
+#[cfg(feature = "thumbnails")]
+pub fn render(source: &Path, target: &Path, size: &Size) -> Result<(), Error> {
+    use image::imageops::FilterType;
+    use image::ImageFormat;
+
+    let format = ImageFormat::from_path(source)
+        .map_err(|e| Error::Generic(format!("Unknown image format: {:?}", e)))?;
+    // Only the header's read for this, before anything's decoded.
+    let (width, height) = image::image_dimensions(source)
+        .map_err(|e| Error::Generic(format!("Error reading image size: {:?}", e)))?;
+    if u64::from(width) * u64::from(height) > MAX_SOURCE_PIXELS {
+        return Err(Error::Generic(format!("Image too big for a thumbnail: {}x{}", width, height)));
+    }
+    let image = image::open(source).map_err(|e| Error::Generic(format!("Error opening image: {:?}", e)))?;
+    let image = if size.crop {
+        image.resize_to_fill(size.width, size.height, FilterType::Lanczos3)
+    } else {
+        image.resize(size.width, size.height, FilterType::Lanczos3)
+    };
+
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| Error::Generic(format!("Error creating thumbnail cache: {:?}", e)))?;
+    }
+    let temporary = target.with_extension(format!("{}.tmp", uuid::Uuid::new_v4()));
+    image
+        .save_with_format(&temporary, format)
+        .map_err(|e| Error::Generic(format!("Error saving thumbnail: {:?}", e)))?;
+    std::fs::rename(&temporary, target)
+        .map_err(|e| Error::Generic(format!("Error saving thumbnail: {:?}", e)))?;
+    Ok(())
+}
+
+#[cfg(not(feature = "thumbnails"))]
+pub fn render(_source: &Path, _target: &Path, _size: &Size) -> Result<(), Error> {
+    Err(Error::Generic("Thumbnails need the `thumbnails` feature".to_string()))
+}
+
+/// Serves a signed thumbnail, rendering it first if it isn't cached (or
+/// the original has changed since). Anything unsigned, tampered with,
+/// missing or not allowed by the `Sources` is a 404.
+pub async fn handler(
+    request: HttpRequest,
+    path: web::Path<(String, String, String)>,
+) -> Result<HttpResponse, Error> {
+    let (signature, size, key) = path.into_inner();
+    let size = match size.parse::<Size>() {
+        Ok(size) if verify(&size, &key, &signature) => size,
+        _ => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let (source, extension) = match source_for(&key).await? {
+        Some(source) => source,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    let cached = cache_path(&size, &key, &extension);
+    if !is_current(&cached, &source) {
+        let target = cached.clone();
+        web::block(move || render(&source, &target, &size))
+            .await
+            .map_err(|e| Error::Generic(format!("Error rendering thumbnail: {:?}", e)))??;
+    }
+
+    serve(&request, cached).await
+}
+
+/// Serves a rendered thumbnail. Signed URLs always render the same image,
+/// so browsers can hang on to it for a day.
+#[cfg(feature = "thumbnails")]
+async fn serve(request: &HttpRequest, path: PathBuf) -> Result<HttpResponse, Error> {
+    use actix_files::NamedFile;
+    use actix_web::http::header::{HeaderValue, CACHE_CONTROL};
+
+    let file = NamedFile::open_async(path)
+        .await
+        .map_err(|e| Error::Generic(format!("Error opening thumbnail: {:?}", e)))?
+        .use_etag(true)
+        .use_last_modified(true);
+
+    let mut response = file.into_response(request);
+    response
+        .headers_mut()
+        .insert(CACHE_CONTROL, HeaderValue::from_static("private, max-age=86400"));
+    Ok(response)
+}
+
+#[cfg(not(feature = "thumbnails"))]
+async fn serve(_request: &HttpRequest, _path: PathBuf) -> Result<HttpResponse, Error> {
+    Ok(HttpResponse::NotFound().finish())
+}
+
+pub fn configure(config: &mut ServiceConfig) {
+    routes::resource("/img/{signature}/{size}/{key:.+}").get(handler).mount(config);
+}
+
+/// Registers the `thumbnail_url` function on a Tera instance.
+pub fn register(tera: &mut Tera) {
+    tera.register_function("thumbnail_url", |args: &HashMap<String, Value>| {
+        let key = args
+            .get("key")
+            .and_then(Value::as_str)
+            .ok_or_else(|| tera::Error::msg("thumbnail_url: missing `key` argument"))?;
+        let size = args
+            .get("size")
+            .and_then(Value::as_str)
+            .ok_or_else(|| tera::Error::msg("thumbnail_url: missing `size` argument"))?;
+        let size = size
+            .parse::<Size>()
+            .map_err(|_| tera::Error::msg(format!("thumbnail_url: invalid size `{}`", size)))?;
+
+        Ok(Value::String(url(&size, key)))
+    });
+}
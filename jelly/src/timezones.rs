@@ -0,0 +1,99 @@
+//! Showing stored UTC timestamps in the viewer's time zone, with two Tera
+//! filters:
+//!
+//! ```html
+//! {{ event.created | localtime(tz=timezone) }}           {# 2022-04-18 10:30 BST #}
+//! {{ event.created | localtime(tz=timezone, format="%d %b %Y") }}
+//! {{ session.last_seen | humanize }}                     {# 3 hours ago #}
+//! ```
+//!
+//! Pages rendered with `request.render` have the signed in user's time zone
+//! as `timezone` (see `request::Timezone`); emails have to add it to their
+//! context themselves. Unknown zones fall back to UTC.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::{Tz, TZ_VARIANTS};
+use tera::{Tera, Value};
+
+/// The zone used when none (or an unknown one) is given.
+pub const DEFAULT: &str = "UTC";
+
+/// How `localtime` formats times, unless given a `format`.
+pub const DEFAULT_FORMAT: &str = "%Y-%m-%d %H:%M %Z";
+
+/// Whether `name` is an IANA time zone name, e.g `Europe/London`.
+pub fn is_valid(name: &str) -> bool {
+    name.parse::<Tz>().is_ok()
+}
+
+/// Every zone name, for picking one from.
+pub fn names() -> impl Iterator<Item = &'static str> {
+    TZ_VARIANTS.iter().map(|tz| tz.name())
+}
+
+/// `at`, in the zone `tz`, formatted with `format`.
+pub fn localtime(at: DateTime<Utc>, tz: &str, format: &str) -> String {
+    let tz: Tz = tz.parse().unwrap_or(Tz::UTC);
+    at.with_timezone(&tz).format(format).to_string()
+}
+
+/// How long before or after `now` it was at `at`, roughly, e.g "just now",
+/// "5 minutes ago" or "in 2 days".
+pub fn humanize(at: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let seconds = (now - at).num_seconds();
+    let elapsed = seconds.abs();
+    let plural = |n: i64, unit: &str| format!("{} {}s", n, unit);
+
+    let span = match elapsed {
+        0..=44 => return "just now".to_string(),
+        45..=89 => "a minute".to_string(),
+        90..=2_699 => plural((elapsed + 30) / 60, "minute"),
+        2_700..=5_399 => "an hour".to_string(),
+        5_400..=79_199 => plural((elapsed + 1_800) / 3_600, "hour"),
+        79_200..=129_599 => "a day".to_string(),
+        129_600..=2_246_399 => plural((elapsed + 43_200) / 86_400, "day"),
+        2_246_400..=3_887_999 => "a month".to_string(),
+        3_888_000..=27_647_999 => plural((elapsed + 1_296_000) / 2_592_000, "month"),
+        27_648_000..=47_347_199 => "a year".to_string(),
+        _ => plural((elapsed + 15_778_800) / 31_557_600, "year"),
+    };
+
+    if seconds > 0 {
+        format!("{} ago", span)
+    } else {
+        format!("in {}", span)
+    }
+}
+
+/// Reads a timestamp as serialized into a template context: an RFC 3339
+/// string (how `DateTime<Utc>` serializes), a naive one taken as UTC, or
+/// seconds since the epoch.
+fn timestamp(value: &Value) -> Option<DateTime<Utc>> {
+    match value {
+        Value::String(value) => DateTime::parse_from_rfc3339(value)
+            .map(|at| at.with_timezone(&Utc))
+            .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f").map(|at| Utc.from_utc_datetime(&at)))
+            .ok(),
+        Value::Number(value) => value.as_i64().map(|seconds| Utc.timestamp(seconds, 0)),
+        _ => None,
+    }
+}
+
+/// Registers the `localtime` and `humanize` filters on a Tera instance.
+pub fn register(tera: &mut Tera) {
+    tera.register_filter("localtime", |value: &Value, args: &HashMap<String, Value>| {
+        let at = timestamp(value).ok_or_else(|| tera::Error::msg(format!("localtime: not a timestamp: {}", value)))?;
+        let tz = args.get("tz").and_then(Value::as_str).unwrap_or(DEFAULT);
+        let format = args.get("format").and_then(Value::as_str).unwrap_or(DEFAULT_FORMAT);
+
+        Ok(Value::String(localtime(at, tz, format)))
+    });
+
+    tera.register_filter("humanize", |value: &Value, _: &HashMap<String, Value>| {
+        let at = timestamp(value).ok_or_else(|| tera::Error::msg(format!("humanize: not a timestamp: {}", value)))?;
+
+        Ok(Value::String(humanize(at, Utc::now())))
+    });
+}
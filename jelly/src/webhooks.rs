@@ -0,0 +1,284 @@
+//! Outgoing webhooks: events POSTed as JSON to URLs accounts register,
+//! signed so the receiver can tell they came from us.
+//!
+//! Each request carries a `webhook-signature` header of the form
+//! `t=<unix time>,v1=<signature>`, where the signature is a base64 HMAC-SHA256
+//! of `<unix time>.<body>` keyed on the endpoint's secret. While a secret is
+//! being rotated, the request is signed with both the new and the old one
+//! (`t=...,v1=<new>,v1=<old>`), so receivers can switch over at their
+//! leisure; any one matching is enough. `verify` does that check, for
+//! receivers written in Rust.
+//!
+//! Endpoints are given by accounts, so requests only go to public
+//! addresses: a URL whose host resolves to a loopback, private, link-local
+//! or otherwise internal address is refused (see `check_url`), and
+//! redirects aren't followed. The host's resolved again for every
+//! delivery, not just when the endpoint's added, and the request goes to
+//! the address that was checked; it isn't looked up a second time, so the
+//! name can't be pointed somewhere internal in between.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+use constant_time_eq::constant_time_eq;
+use hmac::{Hmac, Mac, NewMac};
+use rand::{thread_rng, Rng};
+use sha2::Sha256;
+
+use crate::error::Error;
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const SIGNATURE_HEADER: &str = "webhook-signature";
+pub const EVENT_HEADER: &str = "webhook-event";
+
+/// Sent by the "send test event" button, to check an endpoint's set up.
+pub const TEST_EVENT: &str = "webhook.test";
+
+/// How long receivers are given to answer.
+const TIMEOUT_SECONDS: u64 = 10;
+
+/// A new endpoint secret.
+pub fn new_secret() -> String {
+    let bytes: [u8; 32] = thread_rng().gen();
+    format!("whsec_{}", base64::encode(bytes))
+}
+
+/// The signature of `body`, sent at `timestamp`, with `secret`.
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+    base64::encode(mac.finalize().into_bytes())
+}
+
+/// The `webhook-signature` header value, signed with each of `secrets`.
+pub fn signature_header(secrets: &[&str], timestamp: i64, body: &str) -> String {
+    let mut header = format!("t={}", timestamp);
+    for secret in secrets {
+        header.push_str(",v1=");
+        header.push_str(&sign(secret, timestamp, body));
+    }
+    header
+}
+
+/// Whether `header` is a signature of `body` with `secret`, made within
+/// `tolerance` seconds of `now` (so old requests can't be replayed).
+pub fn verify(secret: &str, header: &str, body: &str, now: i64, tolerance: i64) -> bool {
+    let mut timestamp = None;
+    let mut signatures = Vec::new();
+    for part in header.split(',') {
+        match part.trim().split_once('=') {
+            Some(("t", value)) => timestamp = value.parse::<i64>().ok(),
+            Some(("v1", value)) => signatures.push(value),
+            _ => {}
+        }
+    }
+
+    let timestamp = match timestamp {
+        Some(timestamp) if (now - timestamp).abs() <= tolerance => timestamp,
+        _ => return false,
+    };
+    let expected = sign(secret, timestamp, body);
+    signatures
+        .iter()
+        .any(|signature| constant_time_eq(signature.as_bytes(), expected.as_bytes()))
+}
+
+/// How a delivery went: the status code the receiver answered with, or why
+/// there wasn't one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Attempt {
+    pub status: Option<u16>,
+    pub error: Option<String>,
+    pub duration: Duration,
+}
+
+impl Attempt {
+    /// Whether the receiver took it (any 2xx).
+    pub fn succeeded(&self) -> bool {
+        matches!(self.status, Some(status) if (200..300).contains(&status))
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        || a == 0
+        // Carrier-grade NAT, 100.64.0.0/10.
+        || (a == 100 && (64..128).contains(&b)))
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    if ip.is_loopback() || ip.is_unspecified() || ip.is_multicast() {
+        return false;
+    }
+    // Unique local (fc00::/7) and link-local (fe80::/10).
+    if first & 0xfe00 == 0xfc00 || first & 0xffc0 == 0xfe80 {
+        return false;
+    }
+    // NAT64 (64:ff9b::/96) reaches the IPv4 address in its last 32 bits,
+    // so it's checked as that; the local-use prefix (64:ff9b:1::/48) is
+    // only ever internal.
+    match ip.segments() {
+        [0x64, 0xff9b, 0, 0, 0, 0, high, low] => {
+            return is_public_v4(Ipv4Addr::from((u32::from(high) << 16) | u32::from(low)))
+        }
+        [0x64, 0xff9b, 1, ..] => return false,
+        _ => {}
+    }
+    // IPv4 addresses written as IPv6 ones are checked as what they are.
+    match ip.to_ipv4() {
+        Some(v4) => is_public_v4(v4),
+        None => true,
+    }
+}
+
+/// Whether requests can be sent to `ip`: it's not loopback, private,
+/// link-local, or otherwise on this network.
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => is_public_v6(ip),
+    }
+}
+
+/// The host and port an `https://` URL points at, or `None` if it isn't
+/// one (or has credentials in it).
+pub fn host_and_port(url: &str) -> Option<(String, u16)> {
+    let rest = url.trim().strip_prefix("https://")?;
+    let authority = rest.split(|c| c == '/' || c == '?' || c == '#').next()?;
+    if authority.contains('@') {
+        return None;
+    }
+
+    let (host, port) = match authority.strip_prefix('[') {
+        Some(v6) => {
+            let (host, rest) = v6.split_once(']')?;
+            (host, rest.strip_prefix(':'))
+        }
+        None => match authority.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        },
+    };
+    let port = match port {
+        Some(port) => port.parse().ok()?,
+        None => 443,
+    };
+    Some((host.to_lowercase(), port).filter(|(host, _)| !host.is_empty()))
+}
+
+/// Why requests can't be sent to `url`, if they can't: it's not https, or
+/// its host is (or resolves to) an address that isn't public. Resolving
+/// blocks; see `check_url`.
+pub fn check_destination(url: &str) -> Result<(), String> {
+    resolve_destination(url).map(|_| ())
+}
+
+/// Like `check_destination`, but returns an address requests to `url` can
+/// be sent to: one the host resolved to, all of which are public.
+fn resolve_destination(url: &str) -> Result<SocketAddr, String> {
+    let (host, port) = host_and_port(url).ok_or_else(|| "url must start with https://".to_string())?;
+    if host == "localhost" || host.ends_with(".localhost") {
+        return Err("url must be a public address".to_string());
+    }
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return match is_public(ip) {
+            true => Ok(SocketAddr::new(ip, port)),
+            false => Err("url must be a public address".to_string()),
+        };
+    }
+
+    let addrs: Vec<_> = (host.as_str(), port)
+        .to_socket_addrs()
+        .map_err(|_| format!("{} can't be found", host))?
+        .collect();
+    if addrs.is_empty() {
+        return Err(format!("{} can't be found", host));
+    }
+    if !addrs.iter().all(|addr| is_public(addr.ip())) {
+        return Err("url must be a public address".to_string());
+    }
+    Ok(addrs[0])
+}
+
+/// `check_destination`, off the runtime.
+pub async fn check_url(url: &str) -> Result<Result<(), String>, Error> {
+    let url = url.to_string();
+    actix_rt::task::spawn_blocking(move || check_destination(&url))
+        .await
+        .map_err(|e| Error::Generic(format!("Error checking webhook url: {:?}", e)))
+}
+
+/// What's logged for a request that failed: only roughly why, so endpoints
+/// can't be used to probe what's behind them.
+fn failure(e: &reqwest::Error) -> &'static str {
+    if e.is_timeout() {
+        "timed out"
+    } else {
+        "couldn't connect"
+    }
+}
+
+/// POSTs `body` to `url` as `event`, signed with `secrets` at `timestamp`.
+/// Failing to get a response, or the URL not being public, is an `Attempt`
+/// with an error rather than an `Err`, so it can be logged like any other.
+/// Only the status code is kept, not what the receiver answered with.
+pub async fn post(url: &str, event: &str, secrets: &[&str], timestamp: i64, body: String) -> Result<Attempt, Error> {
+    let started = Instant::now();
+    let failed = |error: String| Attempt {
+        status: None,
+        error: Some(error),
+        duration: started.elapsed(),
+    };
+
+    // Resolving blocks, so it's run off the runtime.
+    let destination = url.to_string();
+    let resolved = actix_rt::task::spawn_blocking(move || resolve_destination(&destination))
+        .await
+        .map_err(|e| Error::Generic(format!("Error sending webhook: {:?}", e)))?;
+    let addr = match resolved {
+        Ok(addr) => addr,
+        Err(error) => return Ok(failed(error)),
+    };
+
+    // minreq can't be told which address to connect to, hence reqwest here;
+    // the client's built per delivery, pinned to the address just checked.
+    let host = host_and_port(url).map(|(host, _)| host).unwrap_or_default();
+    let client = reqwest::Client::builder()
+        .resolve(&host, addr)
+        .redirect(reqwest::redirect::Policy::none())
+        .timeout(Duration::from_secs(TIMEOUT_SECONDS))
+        .build()
+        .map_err(|e| Error::Generic(format!("Error building webhook client: {:?}", e)))?;
+
+    let response = client
+        .post(url)
+        .header("Content-Type", "application/json")
+        .header(EVENT_HEADER, event)
+        .header(SIGNATURE_HEADER, signature_header(secrets, timestamp, &body))
+        .body(body)
+        .send()
+        .await;
+
+    Ok(match response {
+        Ok(response) => {
+            let status = response.status();
+            Attempt {
+                status: Some(status.as_u16()),
+                error: status
+                    .is_redirection()
+                    .then(|| "redirected; redirects aren't followed".to_string()),
+                duration: started.elapsed(),
+            }
+        }
+        Err(e) => failed(failure(&e).to_string()),
+    })
+}
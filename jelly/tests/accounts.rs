@@ -0,0 +1,41 @@
+use jelly::accounts::User;
+
+#[cfg(test)]
+mod user_should {
+    use super::*;
+
+    #[test]
+    fn read_sessions_from_before_the_later_fields() {
+        let user: User =
+            serde_json::from_str(r#"{"id":7,"name":"Erby Doe","is_admin":false,"is_anonymous":false}"#).unwrap();
+
+        assert_eq!(user.id, 7);
+        assert_eq!(user.name, "Erby Doe");
+        assert!(user.email.is_empty());
+        assert!(!user.has_verified_email);
+        assert!(user.roles.is_empty());
+        // No current account is at version 0, so it's reloaded on next use.
+        assert_eq!(user.session_version, 0);
+    }
+
+    #[test]
+    fn round_trip_through_the_session() {
+        let user = User {
+            id: 7,
+            name: "Erby Doe".to_string(),
+            is_admin: false,
+            is_anonymous: false,
+            email: "erby@example.com".to_string(),
+            has_verified_email: true,
+            roles: vec!["editor".to_string()],
+            session_version: 3,
+        };
+
+        let user: User = serde_json::from_str(&serde_json::to_string(&user).unwrap()).unwrap();
+        assert_eq!(user.email, "erby@example.com");
+        assert!(user.has_verified_email);
+        assert!(user.has_role("editor"));
+        assert!(!user.has_role("admin"));
+        assert_eq!(user.session_version, 3);
+    }
+}
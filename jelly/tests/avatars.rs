@@ -0,0 +1,31 @@
+use jelly::avatars;
+
+const HASH: &str = "b58996c504c5638798eb6b511e6f49af0ad9ebb84a7ae32a2b0e1d8e1a2b3c4d";
+
+#[cfg(test)]
+mod avatars_should {
+    use super::*;
+
+    #[test]
+    fn key_identicons_by_normalized_email() {
+        assert_eq!(avatars::identicon_url(" Erby@Example.com "), avatars::identicon_url("erby@example.com"));
+        assert!(avatars::identicon_url("erby@example.com").starts_with("/avatars/"));
+    }
+
+    #[test]
+    fn draw_the_same_identicon_for_the_same_hash() {
+        assert_eq!(avatars::identicon_svg(HASH), avatars::identicon_svg(HASH));
+        assert_ne!(avatars::identicon_svg(HASH), avatars::identicon_svg(&HASH.replace('b', 'c')));
+    }
+
+    #[test]
+    fn mirror_identicons() {
+        let svg = avatars::identicon_svg(HASH);
+        for row in 0..5 {
+            for col in 0..2 {
+                let cell = |x: usize| format!(r#"x="{}" y="{}""#, x, row);
+                assert_eq!(svg.contains(&cell(col)), svg.contains(&cell(4 - col)));
+            }
+        }
+    }
+}
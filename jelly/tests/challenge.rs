@@ -0,0 +1,71 @@
+use jelly::challenge::{self, Challenge, Issued, Response};
+
+/// Finds a solution the slow way, as the page's script does.
+fn solve(token: &str, bits: u32) -> String {
+    (0u64..).map(|n| n.to_string()).find(|n| challenge::solves(token, n, bits)).unwrap()
+}
+
+fn issue(salt: &str, bits: u32) -> String {
+    std::env::set_var("SECRET_KEY", "test-secret");
+    match (Challenge::ProofOfWork { bits }).issue(salt) {
+        Some(Issued::ProofOfWork { token, .. }) => token,
+        other => panic!("expected a proof of work, got {:?}", other),
+    }
+}
+
+#[cfg(test)]
+mod challenge_should {
+    use super::*;
+
+    #[actix_rt::test]
+    async fn pass_a_solved_proof_of_work_once() {
+        let pow = Challenge::ProofOfWork { bits: 8 };
+        let token = issue("signup", 8);
+        let response = Response {
+            solution: solve(&token, 8),
+            token,
+            ..Response::default()
+        };
+
+        assert!(pow.verify("signup", &response, None).await.unwrap());
+        assert!(!pow.verify("signup", &response, None).await.unwrap());
+    }
+
+    #[actix_rt::test]
+    async fn fail_wrong_answers() {
+        let pow = Challenge::ProofOfWork { bits: 8 };
+        let token = issue("signup", 8);
+        let solution = solve(&token, 8);
+
+        let unsolved = Response {
+            token: token.clone(),
+            solution: "not-it".to_string(),
+            ..Response::default()
+        };
+        assert!(!pow.verify("signup", &unsolved, None).await.unwrap());
+
+        let other_form = Response {
+            token,
+            solution,
+            ..Response::default()
+        };
+        assert!(!pow.verify("login", &other_form, None).await.unwrap());
+        assert!(!pow.verify("signup", &Response::default(), None).await.unwrap());
+    }
+
+    #[test]
+    fn expire_old_tokens() {
+        let token = issue("signup", 8);
+        let issued: i64 = token.split('.').next().unwrap().parse().unwrap();
+
+        assert!(challenge::verify_token("signup", &token, issued).is_some());
+        let late = issued + challenge::MAX_AGE.as_secs() as i64 + 1;
+        assert!(challenge::verify_token("signup", &token, late).is_none());
+    }
+
+    #[actix_rt::test]
+    async fn pass_everything_when_off() {
+        assert!(Challenge::Off.issue("signup").is_none());
+        assert!(Challenge::Off.verify("signup", &Response::default(), None).await.unwrap());
+    }
+}
@@ -0,0 +1,74 @@
+use actix_web::test::{call_service, init_service, TestRequest};
+use actix_web::{web, App, HttpMessage, HttpRequest, HttpResponse};
+use jelly::guards::{AssignRequestId, RequestId};
+use jelly::jobs::{Correlation, Traced};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct SendEmail {
+    to: i32,
+}
+
+async fn echo(request: HttpRequest) -> HttpResponse {
+    let id = request.extensions().get::<RequestId>().map(|id| id.0.clone()).unwrap_or_default();
+    HttpResponse::Ok().body(id)
+}
+
+#[cfg(test)]
+mod request_id_should {
+    use super::*;
+
+    #[actix_rt::test]
+    async fn be_generated_and_sent_back() {
+        let app = init_service(App::new().wrap(AssignRequestId).route("/", web::get().to(echo))).await;
+        let response = call_service(&app, TestRequest::get().uri("/").to_request()).await;
+
+        let header = response.headers().get("x-request-id").unwrap().to_str().unwrap().to_string();
+        assert_eq!(header.len(), 32);
+        assert_eq!(actix_web::test::read_body(response).await, header.as_bytes());
+    }
+
+    #[actix_rt::test]
+    async fn keep_a_proxys_id() {
+        let app = init_service(App::new().wrap(AssignRequestId).route("/", web::get().to(echo))).await;
+        let request = TestRequest::get().uri("/").insert_header(("x-request-id", "lb-1234.5")).to_request();
+        let response = call_service(&app, request).await;
+
+        assert_eq!(response.headers().get("x-request-id").unwrap(), "lb-1234.5");
+    }
+
+    #[test]
+    fn refuse_ids_unfit_for_logs() {
+        assert!(RequestId::parse("").is_none());
+        assert!(RequestId::parse("a b").is_none());
+        assert!(RequestId::parse("id\nFAKE LOG LINE").is_none());
+        assert!(RequestId::parse(&"a".repeat(65)).is_none());
+    }
+}
+
+#[cfg(test)]
+mod traced_should {
+    use super::*;
+
+    #[test]
+    fn carry_the_correlation_next_to_the_job() {
+        let correlation = Correlation {
+            request_id: Some("abc".to_string()),
+            account_id: Some(7),
+        };
+        let json = serde_json::to_value(Traced::new(correlation.clone(), SendEmail { to: 3 })).unwrap();
+        assert_eq!(json, serde_json::json!({ "to": 3, "_correlation": { "request_id": "abc", "account_id": 7 } }));
+
+        let traced: Traced<SendEmail> = serde_json::from_value(json).unwrap();
+        assert_eq!(traced.correlation, correlation);
+        assert_eq!(traced.job, SendEmail { to: 3 });
+    }
+
+    #[test]
+    fn run_jobs_queued_without_one() {
+        let traced: Traced<SendEmail> = serde_json::from_value(serde_json::json!({ "to": 3 })).unwrap();
+        assert!(traced.correlation.is_empty());
+        assert_eq!(traced.correlation.to_string(), "no request");
+        assert_eq!(traced.job, SendEmail { to: 3 });
+    }
+}
@@ -0,0 +1,69 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use jelly::chrono::{TimeZone, Utc};
+use jelly::jobs::cron::{Cron, Entry};
+use jelly::jobs::{Job, JobState, DEFAULT_QUEUE};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Hourly;
+
+impl Job for Hourly {
+    type State = JobState;
+    type Future = Pin<Box<dyn Future<Output = Result<(), anyhow::Error>> + Send>>;
+
+    const NAME: &'static str = "HourlyJob";
+    const QUEUE: &'static str = DEFAULT_QUEUE;
+
+    fn run(self, _state: JobState) -> Self::Future {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+impl Cron for Hourly {
+    const SCHEDULE: &'static str = "0 0 * * * *";
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Broken;
+
+impl Job for Broken {
+    type State = JobState;
+    type Future = Pin<Box<dyn Future<Output = Result<(), anyhow::Error>> + Send>>;
+
+    const NAME: &'static str = "BrokenJob";
+    const QUEUE: &'static str = DEFAULT_QUEUE;
+
+    fn run(self, _state: JobState) -> Self::Future {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+impl Cron for Broken {
+    const SCHEDULE: &'static str = "every hour";
+}
+
+#[cfg(test)]
+mod entry_should {
+    use super::*;
+
+    #[test]
+    fn find_the_next_run() {
+        let entry = Entry::new::<Hourly>();
+        assert_eq!(entry.name, "HourlyJob");
+
+        let now = Utc.ymd(2022, 4, 26).and_hms(9, 15, 0);
+        assert_eq!(entry.next_after(now), Some(Utc.ymd(2022, 4, 26).and_hms(10, 0, 0)));
+
+        // Exactly on the hour, the next run is the following one.
+        let on_the_hour = Utc.ymd(2022, 4, 26).and_hms(10, 0, 0);
+        assert_eq!(entry.next_after(on_the_hour), Some(Utc.ymd(2022, 4, 26).and_hms(11, 0, 0)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid schedule for BrokenJob")]
+    fn reject_bad_schedules() {
+        Entry::new::<Broken>();
+    }
+}
@@ -0,0 +1,92 @@
+use anyhow::Result;
+use jelly::crypto::{self, Encrypted};
+use serde_json::json;
+
+// 32 zero bytes; the key is read once, so every test sets the same one.
+const KEY: &str = "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=";
+
+#[cfg(test)]
+mod encrypted_should {
+    use super::*;
+
+    #[test]
+    fn round_trip() -> Result<()> {
+        std::env::set_var("ENCRYPTION_KEY", KEY);
+        let stored = serde_json::to_value(Encrypted("Wellington".to_string()).sealed())?;
+
+        let ciphertext = stored.as_str().unwrap();
+        assert!(crypto::is_encrypted(ciphertext));
+        assert!(!ciphertext.contains("Wellington"));
+
+        let loaded: Encrypted<String> = serde_json::from_value(stored)?;
+        assert_eq!(loaded.plaintext(), "Wellington");
+        Ok(())
+    }
+
+    #[test]
+    fn read_plaintext() -> Result<()> {
+        std::env::set_var("ENCRYPTION_KEY", KEY);
+        let loaded: Encrypted<String> = serde_json::from_value(json!("Wellington"))?;
+        assert_eq!(loaded.plaintext(), "Wellington");
+        Ok(())
+    }
+
+    #[test]
+    fn serialize_as_null_unless_sealed() -> Result<()> {
+        std::env::set_var("ENCRYPTION_KEY", KEY);
+        let value = json!({ "location": Encrypted("Wellington".to_string()) });
+        assert_eq!(value, json!({ "location": null }));
+        Ok(())
+    }
+
+    #[test]
+    fn reject_tampering() -> Result<()> {
+        std::env::set_var("ENCRYPTION_KEY", KEY);
+        let ciphertext = crypto::encrypt(b"Wellington")?;
+        let mut bytes = base64::decode(ciphertext.trim_start_matches("enc:v1:"))?;
+        bytes[20] ^= 1;
+        let tampered = format!("enc:v1:{}", base64::encode(bytes));
+        assert!(crypto::decrypt(&tampered).is_err());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod signing_key_should {
+    use super::*;
+    use jelly::crypto::SigningKey;
+
+    // Any 32 bytes (short of the curve order) make a P-256 key.
+    const SIGNING_KEY: &str = "AQIDBAUGBwgJCgsMDQ4PEBESExQVFhcYGRobHB0eHyA=";
+
+    #[test]
+    fn verify_own_signatures() -> Result<()> {
+        let key = SigningKey::from_base64(SIGNING_KEY)?;
+        let signature = key.sign(b"header.payload");
+        assert_eq!(signature.len(), 64);
+        assert!(key.verify(b"header.payload", &signature));
+        assert!(!key.verify(b"header.tampered", &signature));
+        assert!(!key.verify(b"header.payload", &signature[1..]));
+        Ok(())
+    }
+
+    #[test]
+    fn publish_the_public_key() -> Result<()> {
+        let key = SigningKey::from_base64(SIGNING_KEY)?;
+        let jwk = key.jwk();
+        assert_eq!(jwk["kty"], json!("EC"));
+        assert_eq!(jwk["crv"], json!("P-256"));
+        assert_eq!(jwk["kid"], json!(key.kid));
+        assert!(jwk.get("d").is_none());
+
+        let x = base64::decode_config(jwk["x"].as_str().unwrap(), base64::URL_SAFE_NO_PAD)?;
+        assert_eq!(x.len(), 32);
+        Ok(())
+    }
+
+    #[test]
+    fn refuse_invalid_keys() {
+        assert!(SigningKey::from_base64("not base64!").is_err());
+        assert!(SigningKey::from_base64("AAAA").is_err());
+    }
+}
@@ -0,0 +1,20 @@
+use jelly::guards::csrf;
+
+#[cfg(test)]
+mod csrf_should {
+    use super::*;
+
+    #[test]
+    fn find_the_token_in_a_form() {
+        assert_eq!(csrf::form_token(b"email=a%40b.c&csrf_token=abc123&x=1"), Some("abc123"));
+        assert_eq!(csrf::form_token(b"csrf_token=abc123"), Some("abc123"));
+    }
+
+    #[test]
+    fn not_find_a_missing_token() {
+        assert_eq!(csrf::form_token(b"email=a%40b.c&password=x"), None);
+        assert_eq!(csrf::form_token(b"xcsrf_token=abc123"), None);
+        assert_eq!(csrf::form_token(b""), None);
+        assert_eq!(csrf::form_token(&[0xff, 0xfe]), None);
+    }
+}
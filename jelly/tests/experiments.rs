@@ -0,0 +1,39 @@
+use jelly::experiments::{self, Experiment};
+
+#[cfg(test)]
+mod experiments_should {
+    use super::*;
+
+    fn hero() -> Experiment {
+        Experiment {
+            name: "hero".to_string(),
+            variants: vec!["control".to_string(), "new".to_string()],
+        }
+    }
+
+    #[test]
+    fn parse_config() {
+        let parsed = experiments::parse("hero=control, new;pricing=monthly,annual,;broken=only;=a,b");
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0], hero());
+        assert_eq!(parsed[1].variants, vec!["monthly", "annual"]);
+    }
+
+    #[test]
+    fn bucket_deterministically() {
+        let experiment = hero();
+        for i in 0..20 {
+            let unit = format!("account:{}", i);
+            assert_eq!(experiment.variant_for(&unit), experiment.variant_for(&unit));
+        }
+    }
+
+    #[test]
+    fn use_every_variant() {
+        let experiment = hero();
+        let new = (0..1000)
+            .filter(|i| experiment.variant_for(&format!("account:{}", i)) == "new")
+            .count();
+        assert!((400..600).contains(&new), "{} of 1000 in `new`", new);
+    }
+}
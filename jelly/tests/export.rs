@@ -0,0 +1,55 @@
+use jelly::export::{csv_field, csv_string, Row};
+
+struct Person(i64, &'static str);
+
+impl Row for Person {
+    fn headers() -> &'static [&'static str] {
+        &["id", "name"]
+    }
+
+    fn cells(&self) -> Vec<String> {
+        vec![self.0.to_string(), self.1.to_string()]
+    }
+
+    fn cursor(&self) -> i64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod csv_field_should {
+    use super::*;
+
+    #[test]
+    fn leave_plain_values() {
+        assert_eq!(csv_field("jane@example.com"), "jane@example.com");
+    }
+
+    #[test]
+    fn quote_separators() {
+        assert_eq!(csv_field("Doe, Jane"), "\"Doe, Jane\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn defuse_formulas() {
+        assert_eq!(csv_field("=HYPERLINK(\"x\")"), "\"'=HYPERLINK(\"\"x\"\")\"");
+        assert_eq!(csv_field("@SUM(A1)"), "'@SUM(A1)");
+    }
+}
+
+#[cfg(test)]
+mod csv_string_should {
+    use super::*;
+
+    #[test]
+    fn put_the_headings_first() {
+        let csv = csv_string(&[Person(1, "Jane"), Person(2, "Doe, John")]);
+        assert_eq!(csv, "id,name\r\n1,Jane\r\n2,\"Doe, John\"\r\n");
+    }
+
+    #[test]
+    fn keep_the_headings_when_empty() {
+        assert_eq!(csv_string::<Person>(&[]), "id,name\r\n");
+    }
+}
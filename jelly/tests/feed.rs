@@ -0,0 +1,33 @@
+use chrono::{TimeZone, Utc};
+use jelly::feed::{Entry, Feed};
+
+#[cfg(test)]
+mod feed_should {
+    use super::*;
+
+    fn feed() -> Feed {
+        let at = Utc.ymd(2022, 4, 15).and_hms(9, 0, 0);
+        let mut feed = Feed::new("https://example.com/feed.xml", "Example", at).author("Jane");
+        feed.push(Entry::new("https://example.com/pages/hello", "Hello & welcome", at).content("<p>Hi</p>"));
+        feed
+    }
+
+    #[test]
+    fn render_entries() {
+        let xml = feed().to_string();
+
+        assert!(xml.starts_with("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n"));
+        assert!(xml.contains("<updated>2022-04-15T09:00:00Z</updated>"));
+        assert!(xml.contains("<author><name>Jane</name></author>"));
+        assert!(xml.contains("<link rel=\"alternate\" href=\"https://example.com/pages/hello\"/>"));
+        assert!(xml.trim_end().ends_with("</feed>"));
+    }
+
+    #[test]
+    fn escape_text_and_content() {
+        let xml = feed().to_string();
+
+        assert!(xml.contains("<title>Hello &amp; welcome</title>"));
+        assert!(xml.contains("<content type=\"html\">&lt;p&gt;Hi&lt;/p&gt;</content>"));
+    }
+}
@@ -0,0 +1,176 @@
+#![cfg(feature = "test-support")]
+
+use jelly::forms::testing::{adversarial_text, codes};
+use jelly::forms::validation::Validatable;
+use jelly::forms::{split_inputs, DateField, EmailDomains, EmailField, PasswordField, PasswordPolicy, PasswordScore};
+use proptest::prelude::*;
+
+#[cfg(test)]
+mod password_field_should {
+    use super::*;
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn never_panic_on_adversarial_input(
+            password in adversarial_text(40),
+            name in adversarial_text(20),
+            email in adversarial_text(20),
+        ) {
+            let field = PasswordField::new(password).with_key("password");
+            let result = field.validate_with(&[&name, &email], &PasswordPolicy::default());
+            for code in codes(&result) {
+                prop_assert!(
+                    [
+                        "REQUIRED_VALUE",
+                        "PASSWORD_MIN_LENGTH",
+                        "PASSWORD_MAX_LENGTH",
+                        "PASSWORD_FORMAT",
+                        "PASSWORD_STRENGTH",
+                        "PASSWORD_HINTS",
+                    ]
+                    .contains(&code),
+                    "unexpected code {}",
+                    code
+                );
+            }
+        }
+
+        #[test]
+        fn count_characters_not_bytes(password in "[éß中😀]{8,16}") {
+            let field = PasswordField::new(password).with_key("password");
+            prop_assert!(field.validate_length(8, 16).is_ok());
+        }
+    }
+
+    #[test]
+    fn keep_stable_codes() {
+        let policy = PasswordPolicy::default();
+        let blank = PasswordField::new("").with_key("password");
+        assert_eq!(codes(&blank.validate_with(&[], &policy)), ["REQUIRED_VALUE"]);
+
+        let short = PasswordField::new("abc").with_key("password");
+        assert_eq!(codes(&short.validate_length(8, 255)), ["PASSWORD_MIN_LENGTH"]);
+
+        let symbols = PasswordField::new("pass word!").with_key("password");
+        assert_eq!(codes(&symbols.validate_with(&[], &policy))[0], "PASSWORD_FORMAT");
+
+        let other = PasswordField::new("other").with_key("password");
+        assert_eq!(codes(&other.validate_confirmation("another")), ["PASSWORD_CONFIRMATION"]);
+    }
+
+    #[test]
+    fn not_panic_checking_the_strength_of_nothing() {
+        let blank = PasswordField::new("").with_key("password");
+        assert!(blank.validate_strength(PasswordScore::SafelyUnguessable, &[]).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod email_field_should {
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn never_panic_on_adversarial_input(email in adversarial_text(64)) {
+            let result = EmailField::new(email).with_key("email").validate();
+            for code in codes(&result) {
+                prop_assert!(["REQUIRED_VALUE", "INVALID_EMAIL"].contains(&code), "unexpected code {}", code);
+            }
+        }
+
+        #[test]
+        fn accept_plain_addresses(user in "[a-z0-9]{1,20}", domain in "[a-z]{1,20}") {
+            let email = format!("{}@{}.com", user, domain);
+            prop_assert!(EmailField::new(email).with_key("email").validate().is_ok());
+        }
+    }
+
+    #[test]
+    fn keep_stable_codes() {
+        assert_eq!(codes(&EmailField::new("").with_key("email").validate()), ["REQUIRED_VALUE"]);
+        assert_eq!(codes(&EmailField::new("nope").with_key("email").validate()), ["INVALID_EMAIL"]);
+        assert_eq!(codes(&EmailField::new("a@example.com").validate()), ["REQUIRED_KEY"]);
+    }
+
+    #[test]
+    fn let_in_only_allowed_domains_and_their_subdomains() {
+        let domains = EmailDomains {
+            allowed: vec!["Example.com".to_string()],
+            denied: vec!["contractors.example.com".to_string()],
+        };
+        let check = |email: &str| codes(&EmailField::new(email).with_key("email").validate_with(&domains));
+
+        assert!(check("jane@example.com").is_empty());
+        assert!(check("jane@EU.example.com").is_empty());
+        assert_eq!(check("jane@notexample.com"), ["EMAIL_DOMAIN_NOT_ALLOWED"]);
+        assert_eq!(check("jane@contractors.example.com"), ["EMAIL_DOMAIN_NOT_ALLOWED"]);
+        assert_eq!(check("nope"), ["INVALID_EMAIL"]);
+    }
+
+    #[test]
+    fn let_everyone_in_without_an_allowlist() {
+        let domains = EmailDomains {
+            allowed: Vec::new(),
+            denied: vec!["@mailinator.com".to_string()],
+        };
+
+        assert!(domains.permits("gmail.com"));
+        assert!(!domains.permits("mailinator.com"));
+        assert!(EmailDomains::default().permits("anything.test"));
+    }
+}
+
+#[cfg(test)]
+mod date_field_should {
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn never_panic_on_adversarial_input(date in adversarial_text(32)) {
+            let field = DateField::new(date).with_key("date").with_date();
+            let result = field.validate();
+            prop_assert_eq!(result.is_ok(), field.date.is_some());
+            for code in codes(&result) {
+                prop_assert_eq!(code, "INVALID_DATE");
+            }
+        }
+
+        #[test]
+        fn accept_real_dates(month in 1u32..=12, day in 1u32..=28, year in 1900i32..=2100) {
+            let field = DateField::new(format!("{:02}/{:02}/{}", month, day, year)).with_key("date").with_date();
+            prop_assert!(field.validate().is_ok());
+            prop_assert!(field.date.is_some());
+        }
+    }
+
+    #[test]
+    fn keep_stable_codes() {
+        assert_eq!(codes(&DateField::new("02/30/2022").with_key("date").validate()), ["INVALID_DATE"]);
+        assert_eq!(codes(&DateField::new("").with_key("date").validate()), ["INVALID_DATE"]);
+    }
+}
+
+#[cfg(test)]
+mod split_inputs_should {
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn return_unique_lowercase_words(inputs in prop::collection::vec(adversarial_text(32), 0..4)) {
+            let inputs: Vec<&str> = inputs.iter().map(String::as_str).collect();
+            let words = split_inputs(&inputs);
+            for (i, word) in words.iter().enumerate() {
+                prop_assert!(word.chars().count() > 3, "short word {:?}", word);
+                prop_assert!(!word.contains(' '));
+                prop_assert!(!words[..i].contains(word), "duplicate word {:?}", word);
+            }
+        }
+    }
+
+    #[test]
+    fn count_characters_not_bytes() {
+        assert_eq!(split_inputs(&["José Ñú"]), vec!["josé"]);
+    }
+}
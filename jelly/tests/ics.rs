@@ -0,0 +1,55 @@
+use chrono::{NaiveDate, TimeZone, Utc};
+use jelly::ics::{Calendar, Event, When};
+
+#[cfg(test)]
+mod calendar_should {
+    use super::*;
+
+    fn renewal() -> Event {
+        let at = Utc.ymd(2022, 5, 1).and_hms(9, 30, 0);
+        Event::new("renewal-42@example.com", "Subscription renews", When::At(at)).updated(at)
+    }
+
+    #[test]
+    fn render_events() {
+        let mut calendar = Calendar::new("Example");
+        calendar.push(renewal());
+        let ics = calendar.to_string();
+
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+        assert!(ics.contains("\r\nDTSTART:20220501T093000Z\r\n"));
+        assert!(ics.contains("\r\nSUMMARY:Subscription renews\r\n"));
+        assert!(ics.ends_with("END:VEVENT\r\nEND:VCALENDAR\r\n"));
+    }
+
+    #[test]
+    fn render_all_day_events() {
+        let day = NaiveDate::from_ymd(2022, 5, 1);
+        let mut calendar = Calendar::new("Example");
+        calendar.push(Event::new("maintenance@example.com", "Maintenance", When::AllDay(day)));
+
+        assert!(calendar.to_string().contains("\r\nDTSTART;VALUE=DATE:20220501\r\n"));
+    }
+
+    #[test]
+    fn escape_text() {
+        let mut calendar = Calendar::new("Example");
+        calendar.push(renewal().description("Plan: Pro, billed yearly; see\nthe dashboard"));
+
+        assert!(calendar
+            .to_string()
+            .contains("DESCRIPTION:Plan: Pro\\, billed yearly\\; see\\nthe dashboard"));
+    }
+
+    #[test]
+    fn fold_long_lines() {
+        let mut calendar = Calendar::new("Example");
+        calendar.push(renewal().description("é".repeat(100)));
+        let ics = calendar.to_string();
+
+        for line in ics.split("\r\n") {
+            assert!(line.len() <= 75, "{} octets: {}", line.len(), line);
+        }
+        assert!(ics.contains("\r\n é"));
+    }
+}
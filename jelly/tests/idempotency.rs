@@ -0,0 +1,48 @@
+use jelly::idempotency::{self, Begin, Saved};
+
+#[cfg(test)]
+mod idempotency_should {
+    use super::*;
+
+    fn saved() -> Saved {
+        Saved {
+            status: 201,
+            content_type: Some("application/json".to_string()),
+            body: br#"{"id":1}"#.to_vec(),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn replay_completed_requests() {
+        let key = "1:replay";
+        assert_eq!(idempotency::begin(key, "a").await.unwrap(), Begin::Started);
+        idempotency::complete(key, &saved()).await.unwrap();
+
+        assert_eq!(idempotency::begin(key, "a").await.unwrap(), Begin::Replay(saved()));
+    }
+
+    #[actix_rt::test]
+    async fn hold_retries_until_the_first_finishes() {
+        let key = "1:in-progress";
+        assert_eq!(idempotency::begin(key, "a").await.unwrap(), Begin::Started);
+        assert_eq!(idempotency::begin(key, "a").await.unwrap(), Begin::InProgress);
+    }
+
+    #[actix_rt::test]
+    async fn refuse_keys_reused_for_other_requests() {
+        let key = "1:mismatch";
+        assert_eq!(idempotency::begin(key, "a").await.unwrap(), Begin::Started);
+        idempotency::complete(key, &saved()).await.unwrap();
+
+        assert_eq!(idempotency::begin(key, "b").await.unwrap(), Begin::Mismatch);
+    }
+
+    #[actix_rt::test]
+    async fn allow_abandoned_keys_again() {
+        let key = "1:abandon";
+        assert_eq!(idempotency::begin(key, "a").await.unwrap(), Begin::Started);
+        idempotency::abandon(key).await.unwrap();
+
+        assert_eq!(idempotency::begin(key, "a").await.unwrap(), Begin::Started);
+    }
+}
@@ -0,0 +1,138 @@
+use jelly::accounts::jwt::{self, AuthMode};
+use jelly::accounts::User;
+use jelly::actix_web::http::header::AUTHORIZATION;
+use jelly::actix_web::test::TestRequest;
+
+const SECRET_KEY: &str = "test-secret-key-that-is-long-enough-for-actix-session-0.6-cookies";
+
+fn user() -> User {
+    User {
+        id: 7,
+        name: "Erby Doe".to_string(),
+        is_admin: false,
+        is_anonymous: false,
+        ..User::default()
+    }
+}
+
+fn encode(json: &str) -> String {
+    base64::encode_config(json, base64::URL_SAFE_NO_PAD)
+}
+
+#[cfg(test)]
+mod jwt_should {
+    use super::*;
+
+    #[test]
+    fn verify_own_tokens() {
+        std::env::set_var("SECRET_KEY", SECRET_KEY);
+        let (token, expires) = jwt::issue(&user()).unwrap();
+        let claims = jwt::verify(&token).unwrap();
+        assert_eq!(claims.sub, 7);
+        assert_eq!(claims.name, "Erby Doe");
+        assert_eq!(claims.exp, expires);
+    }
+
+    #[test]
+    fn reject_tampered_tokens() {
+        std::env::set_var("SECRET_KEY", SECRET_KEY);
+        let (token, _) = jwt::issue(&user()).unwrap();
+        let parts: Vec<&str> = token.split('.').collect();
+        let forged = encode(r#"{"sub":1,"name":"Admin","adm":true,"iat":0,"exp":99999999999}"#);
+        assert!(jwt::verify(&format!("{}.{}.{}", parts[0], forged, parts[2])).is_none());
+        assert!(jwt::verify(&format!("{}.{}.", encode(r#"{"alg":"none"}"#), forged)).is_none());
+        assert!(jwt::verify("not-a-token").is_none());
+    }
+
+    #[test]
+    fn only_read_bearer_tokens_when_accepted() {
+        std::env::set_var("SECRET_KEY", SECRET_KEY);
+        let (token, _) = jwt::issue(&user()).unwrap();
+        let header = (AUTHORIZATION, format!("Bearer {}", token));
+
+        let request = TestRequest::default()
+            .insert_header(header.clone())
+            .app_data(AuthMode::Both)
+            .to_http_request();
+        assert_eq!(jwt::bearer_user(&request).map(|user| user.id), Some(7));
+
+        let request = TestRequest::default().insert_header(header).to_http_request();
+        assert!(jwt::bearer_user(&request).is_none());
+    }
+
+    #[test]
+    fn read_bearer_claims() {
+        std::env::set_var("SECRET_KEY", SECRET_KEY);
+        let (token, _) = jwt::issue(&user()).unwrap();
+        let request = TestRequest::default()
+            .insert_header((AUTHORIZATION, format!("Bearer {}", token)))
+            .app_data(AuthMode::Both)
+            .to_http_request();
+
+        let claims = jwt::bearer_claims(&request).unwrap();
+        assert_eq!(claims.sub, 7);
+        // Not granted under an API token, so there's none to count against.
+        assert_eq!(claims.tid, None);
+    }
+
+    #[test]
+    fn read_claims_from_before_api_tokens() {
+        let claims: jwt::Claims =
+            serde_json::from_str(r#"{"sub":7,"name":"Erby Doe","iat":0,"exp":99999999999}"#).unwrap();
+        assert_eq!(claims.tid, None);
+
+        let claims: jwt::Claims =
+            serde_json::from_str(r#"{"sub":7,"name":"Erby Doe","iat":0,"exp":99999999999,"tid":3}"#).unwrap();
+        assert_eq!(claims.tid, Some(3));
+    }
+
+    #[test]
+    fn only_accept_service_tokens_under_the_api() {
+        std::env::set_var("SECRET_KEY", SECRET_KEY);
+        let (token, _) = jwt::issue_service(7, 2, "Importer", &["account.read".to_string()]).unwrap();
+        let header = (AUTHORIZATION, format!("Bearer {}", token));
+
+        let request = TestRequest::with_uri("/api/account")
+            .insert_header(header.clone())
+            .app_data(AuthMode::Both)
+            .to_http_request();
+        let claims = jwt::bearer_claims(&request).unwrap();
+        assert_eq!((claims.sub, claims.svc), (7, Some(2)));
+
+        let request = TestRequest::with_uri("/dashboard")
+            .insert_header(header)
+            .app_data(AuthMode::Both)
+            .to_http_request();
+        assert!(jwt::bearer_claims(&request).is_none());
+    }
+
+    #[test]
+    fn limit_service_tokens_to_their_scopes() {
+        std::env::set_var("SECRET_KEY", SECRET_KEY);
+        let (token, _) = jwt::issue_service(7, 2, "Importer", &["account.read".to_string()]).unwrap();
+        let request = TestRequest::with_uri("/api/account")
+            .insert_header((AUTHORIZATION, format!("Bearer {}", token)))
+            .app_data(AuthMode::Both)
+            .to_http_request();
+        assert!(jwt::require_scope(&request, "account.read").is_ok());
+        assert!(jwt::require_scope(&request, "account.write").is_err());
+
+        // People's tokens aren't limited.
+        let (token, _) = jwt::issue(&user()).unwrap();
+        let request = TestRequest::with_uri("/api/account")
+            .insert_header((AUTHORIZATION, format!("Bearer {}", token)))
+            .app_data(AuthMode::Both)
+            .to_http_request();
+        assert!(jwt::require_scope(&request, "account.write").is_ok());
+    }
+
+    #[test]
+    fn allow_wildcard_scopes() {
+        let claims: jwt::Claims = serde_json::from_str(
+            r#"{"sub":7,"name":"Importer","iat":0,"exp":99999999999,"svc":2,"scp":["devices.*"]}"#,
+        )
+        .unwrap();
+        assert!(claims.allows("devices.write"));
+        assert!(!claims.allows("account.read"));
+    }
+}
@@ -0,0 +1,31 @@
+use jelly::logging;
+
+#[cfg(test)]
+mod logging_should {
+    use super::*;
+
+    #[test]
+    fn accept_filters_as_in_rust_log() {
+        assert!(logging::validate("").is_ok());
+        assert!(logging::validate("info").is_ok());
+        assert!(logging::validate("info,jelly=debug, sqlx=warn").is_ok());
+        assert!(logging::validate("jelly::oauth=trace/token").is_ok());
+    }
+
+    #[test]
+    fn reject_levels_it_would_ignore() {
+        assert!(logging::validate("jelly=dbug").is_err());
+        assert!(logging::validate("=debug").is_err());
+    }
+
+    #[test]
+    fn switch_filters_and_back() {
+        logging::set("warn,jelly=debug").unwrap();
+        assert_eq!(logging::current(), "warn,jelly=debug");
+        assert!(logging::set("jelly=loud").is_err());
+        assert_eq!(logging::current(), "warn,jelly=debug");
+
+        logging::reset().unwrap();
+        assert_eq!(logging::current(), logging::startup());
+    }
+}
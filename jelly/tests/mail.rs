@@ -227,3 +227,97 @@ mod send_via_postmark_should {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod email_new_should {
+    use super::*;
+    use jelly::email::Email;
+    use test_log::test; // Automatically log tests
+
+    fn set_env() {
+        std::env::set_var("POSTMARK_MESSAGE_STREAM", "default");
+        std::env::set_var("EMAIL_DEFAULT_FROM", "owner@example.com");
+    }
+
+    #[test]
+    fn generate_text_body_from_html() -> Result<()> {
+        set_env();
+        let mut templates = Tera::default();
+        templates.add_raw_template("t.html", "<p>test {{ name }}</p>")?;
+
+        let mut context = Context::new();
+        context.insert("name", "surname name");
+        let email = Email::new(
+            "t",
+            &vec!["a@exemple.com".to_string()],
+            "subject line",
+            context,
+            Arc::new(RwLock::new(templates)),
+        )?;
+
+        assert_eq!(email.body.trim(), "test surname name");
+        Ok(())
+    }
+
+    #[test]
+    fn prefer_text_template() -> Result<()> {
+        set_env();
+        let mut templates = Tera::default();
+        templates.add_raw_template("t.html", "<p>test {{ name }}</p>")?;
+        templates.add_raw_template("t.txt", "plain {{ name }}")?;
+
+        let mut context = Context::new();
+        context.insert("name", "surname name");
+        let email = Email::new(
+            "t",
+            &vec!["a@exemple.com".to_string()],
+            "subject line",
+            context,
+            Arc::new(RwLock::new(templates)),
+        )?;
+
+        assert_eq!(email.body, "plain surname name");
+        Ok(())
+    }
+
+    #[test]
+    fn redirect_to_inbox() -> Result<()> {
+        set_env();
+        let mut templates = Tera::default();
+        templates.add_raw_template("t.html", "<p>test</p>")?;
+
+        let email = Email::new(
+            "t",
+            &vec!["a@exemple.com".to_string(), "b@example.com".to_string()],
+            "subject line",
+            Context::new(),
+            Arc::new(RwLock::new(templates)),
+        )?
+        .redirect_to("staging@example.com");
+
+        assert_eq!(email.to, "staging@example.com");
+        assert_eq!(email.subject, "[To: a@exemple.com,b@example.com] subject line");
+        Ok(())
+    }
+
+    #[test]
+    fn use_template_sender() -> Result<()> {
+        set_env();
+        std::env::set_var("EMAIL_FROM_INVOICE", "Billing <billing@example.com>");
+        std::env::set_var("EMAIL_REPLY_TO_INVOICE", "accounts@example.com");
+        let mut templates = Tera::default();
+        templates.add_raw_template("billing/invoice.html", "<p>test</p>")?;
+
+        let email = Email::new(
+            "billing/invoice",
+            &vec!["a@exemple.com".to_string()],
+            "subject line",
+            Context::new(),
+            Arc::new(RwLock::new(templates)),
+        )?;
+
+        assert_eq!(email.from, "Billing <billing@example.com>");
+        assert_eq!(email.reply_to.as_deref(), Some("accounts@example.com"));
+        Ok(())
+    }
+}
@@ -0,0 +1,90 @@
+use jelly::migrations::{self, Applied, Drift};
+use jelly::sqlx::migrate::{Migration, MigrationType};
+
+fn migration(version: i64, description: &'static str, sql: &'static str) -> Migration {
+    Migration::new(version, description.into(), MigrationType::Simple, sql.into())
+}
+
+fn applied(migration: &Migration) -> Applied {
+    Applied {
+        version: migration.version,
+        description: migration.description.to_string(),
+        success: true,
+        checksum: migration.checksum.to_vec(),
+    }
+}
+
+#[cfg(test)]
+mod migrations_should {
+    use super::*;
+
+    #[test]
+    fn find_nothing_when_every_migration_is_applied() {
+        let expected = [migration(1, "accounts", "create table accounts ();")];
+        let applied: Vec<Applied> = expected.iter().map(applied).collect();
+
+        assert!(migrations::diff(&expected, &applied).is_empty());
+    }
+
+    #[test]
+    fn find_migrations_a_partial_deploy_left_out() {
+        let expected = [
+            migration(1, "accounts", "create table accounts ();"),
+            migration(2, "identities", "create table identities ();"),
+        ];
+        let applied = [applied(&expected[0])];
+
+        assert_eq!(
+            migrations::diff(&expected, &applied),
+            vec![Drift::Missing {
+                version: 2,
+                description: "identities".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn find_failed_changed_and_unknown_migrations() {
+        let expected = [
+            migration(1, "accounts", "create table accounts ();"),
+            migration(2, "identities", "create table identities ();"),
+        ];
+        let mut failed = applied(&expected[0]);
+        failed.success = false;
+        let mut changed = applied(&expected[1]);
+        changed.checksum = migration(2, "identities", "create table identities (id serial);").checksum.to_vec();
+        let unknown = applied(&migration(3, "scopes", "alter table identities add scopes text[];"));
+
+        let drift = migrations::diff(&expected, &[unknown, changed, failed]);
+        assert_eq!(
+            drift,
+            vec![
+                Drift::Failed {
+                    version: 1,
+                    description: "accounts".to_string()
+                },
+                Drift::Modified {
+                    version: 2,
+                    description: "identities".to_string()
+                },
+                Drift::Unknown {
+                    version: 3,
+                    description: "scopes".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn say_what_to_run() {
+        let drift = [Drift::Missing {
+            version: 2,
+            description: "identities".to_string(),
+        }];
+        let message = migrations::remediation(&drift);
+
+        assert!(message.contains("2 identities: not applied"), "{}", message);
+        assert!(message.contains("sqlx migrate run"), "{}", message);
+        assert!(!message.contains("Deploy the build"), "{}", message);
+    }
+}
@@ -0,0 +1,1246 @@
+#![cfg(feature = "oauth")]
+
+use actix_session::SessionExt;
+use actix_web::test::TestRequest;
+use httpmock::prelude::*;
+use jelly::crypto::SigningKey;
+use jelly::error::OAuthError;
+use jelly::oauth::client::{self, Endpoints};
+use jelly::oauth::oidc::{self, Issuer, Jwks};
+use jelly::oauth::{self, ClientFlow, OAuthFlow, ScopedClient};
+use jelly::oauth2::basic::BasicErrorResponseType;
+use jelly::oauth2::{reqwest, RequestTokenError};
+use jelly::{SESSION_OAUTH_SCOPES, SESSION_OAUTH_TOKEN};
+use std::collections::HashMap;
+
+const EMAIL: &str = "jane@example.com";
+const REDIRECT_URI: &str = "https://example.com/oauth/callback";
+
+/// A Google client that talks to `server` instead.
+fn client_at(server: &MockServer) -> ScopedClient {
+    std::env::set_var("GOOGLE_CLIENT_ID", "client-id");
+    std::env::set_var("GOOGLE_CLIENT_SECRET", "client-secret");
+    let endpoints = Endpoints {
+        auth_url: server.url("/authorize"),
+        token_url: server.url("/token"),
+        user_info_uris: vec![server.url("/userinfo")],
+    };
+    client::build_client_at("google", REDIRECT_URI, &endpoints).unwrap()
+}
+
+/// An Apple client that talks to `server` instead, with a fixed secret in
+/// place of the signed one.
+fn apple_client_at(server: &MockServer) -> ScopedClient {
+    std::env::set_var("APPLE_CLIENT_ID", "com.example.signin");
+    let endpoints = Endpoints {
+        auth_url: server.url("/authorize"),
+        token_url: server.url("/token"),
+        user_info_uris: Vec::new(),
+    };
+    let mut client = client::build_client_at("apple", REDIRECT_URI, &endpoints).unwrap();
+    client.client_secret = Some(fixed_secret);
+    client
+}
+
+fn fixed_secret() -> Result<String, OAuthError> {
+    Ok("generated-secret".to_string())
+}
+
+/// An unsigned JWT carrying `claims`, as read from a token response.
+fn id_token(claims: serde_json::Value) -> String {
+    let encode = |json: String| base64::encode_config(json, base64::URL_SAFE_NO_PAD);
+    format!(
+        "{}.{}.signature",
+        encode(r#"{"alg":"RS256"}"#.to_string()),
+        encode(claims.to_string())
+    )
+}
+
+/// What's stored at login, for the callback to find.
+fn login(client: &ScopedClient) -> (String, OAuthFlow) {
+    let (request, verifier) = oauth::pkce_authorization_request(client, Some(EMAIL), &[]);
+    let (url, csrf_token) = request.url();
+    let flow = OAuthFlow {
+        provider: "google".to_string(),
+        email: EMAIL.to_string(),
+        authorization_code: String::new(),
+        csrf_token_secret: csrf_token.secret().to_string(),
+        pkce_verifier_secret: verifier.secret().to_string(),
+        scopes: oauth::requested_scopes(client, &[]),
+    };
+    (url.to_string(), flow)
+}
+
+fn mock_token(server: &MockServer, code: &str, status: u16, body: serde_json::Value) -> httpmock::Mock {
+    let code = format!("code={}", code);
+    server.mock(|expect, resp_with| {
+        expect.method(POST).path("/token").body_contains(&code).body_contains("code_verifier=");
+        resp_with
+            .status(status)
+            .header("content-type", "application/json")
+            .json_body(body);
+    })
+}
+
+#[cfg(test)]
+mod login_should {
+    use super::*;
+
+    #[test]
+    fn redirect_to_the_provider_with_state_and_pkce() {
+        let server = MockServer::start();
+        let (url, flow) = login(&client_at(&server));
+
+        assert!(url.starts_with(&server.url("/authorize")));
+        let query: HashMap<_, _> = jelly::oauth2::url::Url::parse(&url)
+            .unwrap()
+            .query_pairs()
+            .into_owned()
+            .collect();
+        assert_eq!(query["state"], flow.csrf_token_secret);
+        assert_eq!(query["redirect_uri"], REDIRECT_URI);
+        assert_eq!(query["login_hint"], EMAIL);
+        assert_eq!(query["code_challenge_method"], "S256");
+        assert!(!query["code_challenge"].is_empty());
+    }
+
+    #[test]
+    fn ask_for_extra_scopes_on_top_of_the_clients() {
+        let server = MockServer::start();
+        let client = client_at(&server);
+        let calendar = "https://www.googleapis.com/auth/calendar.readonly";
+
+        let scopes = oauth::requested_scopes(&client, &[calendar, &client.scopes[0]]);
+        assert_eq!(scopes.len(), client.scopes.len() + 1);
+        assert_eq!(scopes.last().unwrap(), calendar);
+
+        let (request, _) = oauth::pkce_authorization_request(&client, Some(EMAIL), &[calendar]);
+        let (url, _) = request.url();
+        let query: HashMap<_, _> = url.query_pairs().into_owned().collect();
+        assert_eq!(query["scope"], scopes.join(" "));
+        assert_eq!(query["include_granted_scopes"], "true");
+    }
+
+    #[test]
+    fn list_every_provider_in_order() {
+        let providers = client::providers();
+        assert_eq!(
+            providers,
+            ["apple", "facebook", "github", "gitlab", "google", "linkedin", "microsoft", "slack", "twitch", "twitter"]
+        );
+        assert!(providers.iter().all(|provider| client::valid_provider(provider)));
+    }
+}
+
+#[cfg(test)]
+mod apple_should {
+    use super::*;
+
+    #[test]
+    fn ask_for_a_form_post() {
+        let server = MockServer::start();
+        let (url, _) = login(&apple_client_at(&server));
+
+        let query: HashMap<_, _> = jelly::oauth2::url::Url::parse(&url)
+            .unwrap()
+            .query_pairs()
+            .into_owned()
+            .collect();
+        assert_eq!(query["response_mode"], "form_post");
+        assert_eq!(query["scope"], "name email");
+        assert_eq!(query["client_id"], "com.example.signin");
+        assert!(!query.contains_key("login_hint"));
+    }
+
+    #[actix_rt::test]
+    async fn read_the_profile_from_the_id_token() {
+        let server = MockServer::start_async().await;
+        let client = apple_client_at(&server);
+        let (_, flow) = login(&client);
+
+        let token = server.mock(|expect, resp_with| {
+            expect
+                .method(POST)
+                .path("/token")
+                .body_contains("client_id=com.example.signin")
+                .body_contains("client_secret=generated-secret");
+            resp_with
+                .status(200)
+                .header("content-type", "application/json")
+                .json_body(serde_json::json!({
+                    "access_token": "access-token",
+                    "token_type": "bearer",
+                    "expires_in": 3600,
+                    "id_token": id_token(serde_json::json!({
+                        "sub": "001234.abcd",
+                        "email": "jane@privaterelay.appleid.com",
+                        "email_verified": "true",
+                    })),
+                }));
+        });
+
+        let client_flow = ClientFlow {
+            client,
+            flow: flow.set_authorization_code("good-code"),
+        };
+        let token_info = oauth::request_token(client_flow).await.unwrap();
+        let request = TestRequest::default().to_http_request();
+        let info = oauth::fetch_user_info(&request.get_session(), token_info).await.unwrap();
+
+        token.assert();
+        assert_eq!(info.provider, "apple");
+        assert_eq!(info.id, "001234.abcd");
+        assert!(info.name.is_empty());
+        assert_eq!(info.provider_email.as_deref(), Some("jane@privaterelay.appleid.com"));
+        assert_eq!(info.login_email, EMAIL);
+    }
+
+    #[actix_rt::test]
+    async fn fail_without_an_id_token() {
+        let server = MockServer::start_async().await;
+        let client = apple_client_at(&server);
+        let (_, flow) = login(&client);
+
+        mock_token(
+            &server,
+            "good-code",
+            200,
+            serde_json::json!({ "access_token": "access-token", "token_type": "bearer" }),
+        );
+
+        let client_flow = ClientFlow {
+            client,
+            flow: flow.set_authorization_code("good-code"),
+        };
+        let token_info = oauth::request_token(client_flow).await.unwrap();
+        let request = TestRequest::default().to_http_request();
+        assert!(matches!(
+            oauth::fetch_user_info(&request.get_session(), token_info).await,
+            Err(jelly::error::Error::OAuth(OAuthError::IdTokenError(_)))
+        ));
+    }
+
+    #[test]
+    fn reject_malformed_id_tokens() {
+        assert!(matches!(oauth::id_token_claims("nope"), Err(OAuthError::IdTokenError(_))));
+        assert!(matches!(oauth::id_token_claims("a.!!!.c"), Err(OAuthError::IdTokenError(_))));
+    }
+}
+
+#[cfg(test)]
+mod microsoft_should {
+    use super::*;
+
+    #[test]
+    fn sign_in_through_the_configured_tenant() {
+        std::env::set_var("JELLY_DOMAIN", "https://example.com");
+        std::env::set_var("MICROSOFT_CLIENT_ID", "client-id");
+        std::env::set_var("MICROSOFT_CLIENT_SECRET", "client-secret");
+        std::env::set_var("MICROSOFT_TENANT", "contoso.onmicrosoft.com");
+
+        let client = client::client_for("microsoft").unwrap();
+        let (url, _) = login(&client);
+        assert!(url.starts_with("https://login.microsoftonline.com/contoso.onmicrosoft.com/oauth2/v2.0/authorize?"));
+        assert_eq!(
+            client.inner.token_url().unwrap().as_str(),
+            "https://login.microsoftonline.com/contoso.onmicrosoft.com/oauth2/v2.0/token"
+        );
+    }
+
+    #[actix_rt::test]
+    async fn read_the_graph_profile() {
+        std::env::set_var("MICROSOFT_CLIENT_ID", "client-id");
+        std::env::set_var("MICROSOFT_CLIENT_SECRET", "client-secret");
+        let server = MockServer::start_async().await;
+        let endpoints = Endpoints {
+            auth_url: server.url("/authorize"),
+            token_url: server.url("/token"),
+            user_info_uris: vec![server.url("/me")],
+        };
+        let client = client::build_client_at("microsoft", REDIRECT_URI, &endpoints).unwrap();
+        let (_, flow) = login(&client);
+
+        mock_token(
+            &server,
+            "good-code",
+            200,
+            serde_json::json!({ "access_token": "access-token", "token_type": "bearer" }),
+        );
+        let me = server.mock(|expect, resp_with| {
+            expect.method(GET).path("/me").header("Authorization", "Bearer access-token");
+            resp_with.status(200).header("content-type", "application/json").json_body(serde_json::json!({
+                "id": "87d349ed-44d7-43e1-9a83-5f2406dee5bd",
+                "displayName": null,
+                "givenName": "Jane",
+                "surname": "Doe",
+                "mail": null,
+                "userPrincipalName": "jane@contoso.com",
+            }));
+        });
+
+        let client_flow = ClientFlow {
+            client,
+            flow: flow.set_authorization_code("good-code"),
+        };
+        let token_info = oauth::request_token(client_flow).await.unwrap();
+        let request = TestRequest::default().to_http_request();
+        let info = oauth::fetch_user_info(&request.get_session(), token_info).await.unwrap();
+
+        me.assert();
+        assert_eq!(info.provider, "microsoft");
+        assert_eq!(info.id, "87d349ed-44d7-43e1-9a83-5f2406dee5bd");
+        assert_eq!(info.name, "Jane Doe");
+        assert_eq!(info.username.as_deref(), Some("jane@contoso.com"));
+        assert_eq!(info.provider_email, None);
+    }
+}
+
+#[cfg(test)]
+mod gitlab_should {
+    use super::*;
+
+    #[test]
+    fn sign_in_through_a_self_hosted_instance() {
+        std::env::set_var("JELLY_DOMAIN", "https://example.com");
+        std::env::set_var("GITLAB_CLIENT_ID", "client-id");
+        std::env::set_var("GITLAB_CLIENT_SECRET", "client-secret");
+        std::env::set_var("GITLAB_BASE_URL", "https://gitlab.example.com/");
+
+        let client = client::client_for("gitlab").unwrap();
+        let (url, _) = login(&client);
+        assert!(url.starts_with("https://gitlab.example.com/oauth/authorize?"));
+        assert_eq!(client.inner.token_url().unwrap().as_str(), "https://gitlab.example.com/oauth/token");
+        assert_eq!(client.user_info_request.endpoints[0].uri, "https://gitlab.example.com/api/v4/user");
+    }
+
+    #[actix_rt::test]
+    async fn read_the_user() {
+        std::env::set_var("GITLAB_CLIENT_ID", "client-id");
+        std::env::set_var("GITLAB_CLIENT_SECRET", "client-secret");
+        let server = MockServer::start_async().await;
+        let endpoints = Endpoints {
+            auth_url: server.url("/authorize"),
+            token_url: server.url("/token"),
+            user_info_uris: vec![server.url("/api/v4/user")],
+        };
+        let client = client::build_client_at("gitlab", REDIRECT_URI, &endpoints).unwrap();
+        let (_, flow) = login(&client);
+
+        mock_token(
+            &server,
+            "good-code",
+            200,
+            serde_json::json!({ "access_token": "access-token", "token_type": "bearer" }),
+        );
+        let user = server.mock(|expect, resp_with| {
+            expect.method(GET).path("/api/v4/user").header("Authorization", "Bearer access-token");
+            resp_with.status(200).header("content-type", "application/json").json_body(serde_json::json!({
+                "id": 1234567,
+                "username": "janedoe",
+                "name": "Jane Doe",
+                "email": "",
+                "public_email": "jane@example.com",
+                "web_url": "https://gitlab.com/janedoe",
+            }));
+        });
+
+        let client_flow = ClientFlow {
+            client,
+            flow: flow.set_authorization_code("good-code"),
+        };
+        let token_info = oauth::request_token(client_flow).await.unwrap();
+        let request = TestRequest::default().to_http_request();
+        let info = oauth::fetch_user_info(&request.get_session(), token_info).await.unwrap();
+
+        user.assert();
+        assert_eq!(info.provider, "gitlab");
+        assert_eq!(info.id, "1234567");
+        assert_eq!(info.name, "Jane Doe");
+        assert_eq!(info.username.as_deref(), Some("janedoe"));
+        assert_eq!(info.provider_email.as_deref(), Some("jane@example.com"));
+    }
+}
+
+#[cfg(test)]
+mod linkedin_should {
+    use super::*;
+
+    #[actix_rt::test]
+    async fn merge_the_profile_and_email_address() {
+        std::env::set_var("LINKEDIN_CLIENT_ID", "client-id");
+        std::env::set_var("LINKEDIN_CLIENT_SECRET", "client-secret");
+        let server = MockServer::start_async().await;
+        let endpoints = Endpoints {
+            auth_url: server.url("/authorize"),
+            token_url: server.url("/token"),
+            user_info_uris: vec![server.url("/me"), server.url("/emailAddress")],
+        };
+        let client = client::build_client_at("linkedin", REDIRECT_URI, &endpoints).unwrap();
+        let (_, flow) = login(&client);
+
+        let token = server.mock(|expect, resp_with| {
+            expect
+                .method(POST)
+                .path("/token")
+                .body_contains("code=good-code")
+                .body_contains("client_secret=client-secret");
+            resp_with
+                .status(200)
+                .header("content-type", "application/json")
+                .json_body(serde_json::json!({ "access_token": "access-token", "token_type": "bearer" }));
+        });
+        let me = server.mock(|expect, resp_with| {
+            expect
+                .method(GET)
+                .path("/me")
+                .query_param("projection", "(id,localizedFirstName,localizedLastName)");
+            resp_with.status(200).header("content-type", "application/json").json_body(serde_json::json!({
+                "id": "yrZCpj2Z12",
+                "localizedFirstName": "Jane",
+                "localizedLastName": "Doe",
+            }));
+        });
+        let email = server.mock(|expect, resp_with| {
+            expect
+                .method(GET)
+                .path("/emailAddress")
+                .query_param("q", "members")
+                .header("Authorization", "Bearer access-token");
+            resp_with.status(200).header("content-type", "application/json").json_body(serde_json::json!({
+                "elements": [{ "handle": "urn:li:emailAddress:3775708763", "handle~": { "emailAddress": "jane@example.com" } }],
+            }));
+        });
+
+        let client_flow = ClientFlow {
+            client,
+            flow: flow.set_authorization_code("good-code"),
+        };
+        let token_info = oauth::request_token(client_flow).await.unwrap();
+        let request = TestRequest::default().to_http_request();
+        let info = oauth::fetch_user_info(&request.get_session(), token_info).await.unwrap();
+
+        token.assert();
+        me.assert();
+        email.assert();
+        assert_eq!(info.provider, "linkedin");
+        assert_eq!(info.id, "yrZCpj2Z12");
+        assert_eq!(info.name, "Jane Doe");
+        assert_eq!(info.provider_email.as_deref(), Some("jane@example.com"));
+    }
+}
+
+#[cfg(test)]
+mod slack_should {
+    use super::*;
+
+    fn slack_client_at(server: &MockServer) -> ScopedClient {
+        std::env::set_var("SLACK_CLIENT_ID", "client-id");
+        std::env::set_var("SLACK_CLIENT_SECRET", "client-secret");
+        let endpoints = Endpoints {
+            auth_url: server.url("/authorize"),
+            token_url: server.url("/token"),
+            user_info_uris: vec![server.url("/api/openid.connect.userInfo")],
+        };
+        client::build_client_at("slack", REDIRECT_URI, &endpoints).unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn read_the_profile_from_user_info() {
+        let server = MockServer::start_async().await;
+        let client = slack_client_at(&server);
+        let (url, flow) = login(&client);
+        let query: HashMap<String, String> = jelly::oauth2::url::Url::parse(&url)
+            .unwrap()
+            .query_pairs()
+            .into_owned()
+            .collect();
+        assert_eq!(query["scope"], "openid profile email");
+
+        let token = server.mock(|expect, resp_with| {
+            expect
+                .method(POST)
+                .path("/token")
+                .body_contains("code=good-code")
+                .body_contains("client_secret=client-secret");
+            resp_with
+                .status(200)
+                .header("content-type", "application/json")
+                .json_body(serde_json::json!({ "ok": true, "access_token": "xoxp-1234", "token_type": "Bearer" }));
+        });
+        let user_info = server.mock(|expect, resp_with| {
+            expect
+                .method(GET)
+                .path("/api/openid.connect.userInfo")
+                .header("Authorization", "Bearer xoxp-1234");
+            resp_with.status(200).header("content-type", "application/json").json_body(serde_json::json!({
+                "ok": true,
+                "sub": "U0R7JM",
+                "https://slack.com/team_id": "T0R7GR",
+                "email": "jane@example.com",
+                "email_verified": true,
+                "name": "Jane Doe",
+                "given_name": "Jane",
+                "family_name": "Doe",
+            }));
+        });
+
+        let client_flow = ClientFlow {
+            client,
+            flow: flow.set_authorization_code("good-code"),
+        };
+        let token_info = oauth::request_token(client_flow).await.unwrap();
+        let request = TestRequest::default().to_http_request();
+        let info = oauth::fetch_user_info(&request.get_session(), token_info).await.unwrap();
+
+        token.assert();
+        user_info.assert();
+        assert_eq!(info.provider, "slack");
+        assert_eq!(info.id, "U0R7JM");
+        assert_eq!(info.name, "Jane Doe");
+        assert_eq!(info.provider_email.as_deref(), Some("jane@example.com"));
+    }
+
+    #[actix_rt::test]
+    async fn fail_when_slack_says_not_ok() {
+        let server = MockServer::start_async().await;
+        let client = slack_client_at(&server);
+        let (_, flow) = login(&client);
+
+        mock_token(
+            &server,
+            "good-code",
+            200,
+            serde_json::json!({ "access_token": "xoxp-1234", "token_type": "Bearer" }),
+        );
+        server.mock(|expect, resp_with| {
+            expect.method(GET).path("/api/openid.connect.userInfo");
+            resp_with
+                .status(200)
+                .header("content-type", "application/json")
+                .json_body(serde_json::json!({ "ok": false, "error": "invalid_auth" }));
+        });
+
+        let client_flow = ClientFlow {
+            client,
+            flow: flow.set_authorization_code("good-code"),
+        };
+        let token_info = oauth::request_token(client_flow).await.unwrap();
+        let request = TestRequest::default().to_http_request();
+        assert!(oauth::fetch_user_info(&request.get_session(), token_info).await.is_err());
+    }
+}
+
+#[cfg(test)]
+mod twitch_should {
+    use super::*;
+
+    #[actix_rt::test]
+    async fn read_the_user_from_the_data_array() {
+        std::env::set_var("TWITCH_CLIENT_ID", "client-id");
+        std::env::set_var("TWITCH_CLIENT_SECRET", "client-secret");
+        let server = MockServer::start_async().await;
+        let endpoints = Endpoints {
+            auth_url: server.url("/authorize"),
+            token_url: server.url("/token"),
+            user_info_uris: vec![server.url("/helix/users")],
+        };
+        let client = client::build_client_at("twitch", REDIRECT_URI, &endpoints).unwrap();
+        let (_, flow) = login(&client);
+
+        let token = server.mock(|expect, resp_with| {
+            expect
+                .method(POST)
+                .path("/token")
+                .body_contains("client_id=client-id")
+                .body_contains("client_secret=client-secret");
+            resp_with
+                .status(200)
+                .header("content-type", "application/json")
+                .json_body(serde_json::json!({ "access_token": "access-token", "token_type": "bearer" }));
+        });
+        let users = server.mock(|expect, resp_with| {
+            expect
+                .method(GET)
+                .path("/helix/users")
+                .header("Authorization", "Bearer access-token")
+                .header("Client-Id", "client-id");
+            resp_with.status(200).header("content-type", "application/json").json_body(serde_json::json!({
+                "data": [{
+                    "id": "141981764",
+                    "login": "twitchdev",
+                    "display_name": "TwitchDev",
+                    "email": "jane@example.com",
+                }],
+            }));
+        });
+
+        let client_flow = ClientFlow {
+            client,
+            flow: flow.set_authorization_code("good-code"),
+        };
+        let token_info = oauth::request_token(client_flow).await.unwrap();
+        let request = TestRequest::default().to_http_request();
+        let info = oauth::fetch_user_info(&request.get_session(), token_info).await.unwrap();
+
+        token.assert();
+        users.assert();
+        assert_eq!(info.provider, "twitch");
+        assert_eq!(info.id, "141981764");
+        assert_eq!(info.name, "TwitchDev");
+        assert_eq!(info.username.as_deref(), Some("twitchdev"));
+        assert_eq!(info.provider_email.as_deref(), Some("jane@example.com"));
+        assert_eq!(info.login_email, EMAIL);
+    }
+}
+
+#[cfg(test)]
+mod callback_should {
+    use super::*;
+
+    #[actix_rt::test]
+    async fn sign_in_with_the_providers_profile() {
+        let server = MockServer::start_async().await;
+        let client = client_at(&server);
+        let (_, flow) = login(&client);
+        let state = flow.csrf_token_secret.clone();
+        let scopes = client.scopes.clone();
+        client::set_client("google", client);
+
+        let token = mock_token(
+            &server,
+            "good-code",
+            200,
+            serde_json::json!({
+                "access_token": "access-token",
+                "token_type": "bearer",
+                "expires_in": 3600,
+                "refresh_token": "refresh-token",
+            }),
+        );
+        let user_info = server.mock(|expect, resp_with| {
+            expect.method(GET).path("/userinfo").header("Authorization", "Bearer access-token");
+            resp_with.status(200).header("content-type", "application/json").json_body(serde_json::json!({
+                "sub": "1234",
+                "name": "Jane Doe",
+                "email": "jane@gmail.com",
+            }));
+        });
+
+        let client_flow = oauth::verify_callback(Some(flow), &state, Some("good-code"), None).unwrap();
+        let token_info = oauth::request_token(client_flow).await.unwrap();
+        let request = TestRequest::default().to_http_request();
+        let session = request.get_session();
+        let info = oauth::fetch_user_info(&session, token_info).await.unwrap();
+
+        token.assert();
+        user_info.assert();
+        assert_eq!(info.provider, "google");
+        assert_eq!(info.id, "1234");
+        assert_eq!(info.name, "Jane Doe");
+        assert_eq!(info.provider_email.as_deref(), Some("jane@gmail.com"));
+        assert_eq!(info.login_email, EMAIL);
+        assert_eq!(
+            session.get::<String>(SESSION_OAUTH_TOKEN).unwrap().as_deref(),
+            Some("refresh-token")
+        );
+        // The response doesn't say, so it's what was asked for.
+        assert_eq!(session.get::<Vec<String>>(SESSION_OAUTH_SCOPES).unwrap(), Some(scopes));
+    }
+
+    #[actix_rt::test]
+    async fn keep_the_scopes_the_provider_granted() {
+        let server = MockServer::start_async().await;
+        let client = client_at(&server);
+        let (_, flow) = login(&client);
+
+        mock_token(
+            &server,
+            "good-code",
+            200,
+            serde_json::json!({
+                "access_token": "access-token",
+                "token_type": "bearer",
+                "scope": "openid email",
+            }),
+        );
+
+        let client_flow = ClientFlow {
+            client,
+            flow: flow.set_authorization_code("good-code"),
+        };
+        let token_info = oauth::request_token(client_flow).await.unwrap();
+        assert_eq!(token_info.granted_scopes(), ["openid", "email"]);
+    }
+
+    #[test]
+    fn reject_a_mismatched_state() {
+        let server = MockServer::start();
+        let (_, flow) = login(&client_at(&server));
+
+        let result = oauth::verify_callback(Some(flow), "forged-state", Some("good-code"), None);
+        assert!(matches!(result, Err(OAuthError::VerifyStateError)));
+
+        // An unknown or expired state finds no flow at all.
+        let result = oauth::verify_callback(None, "forged-state", Some("good-code"), None);
+        assert!(matches!(result, Err(OAuthError::VerifyStateError)));
+    }
+
+    #[test]
+    fn stop_when_consent_is_denied() {
+        let server = MockServer::start();
+        let (_, flow) = login(&client_at(&server));
+        let state = flow.csrf_token_secret.clone();
+
+        match oauth::verify_callback(Some(flow), &state, None, Some("access_denied")) {
+            Err(OAuthError::GrantAuthorizationError(e)) => assert_eq!(e, "access_denied"),
+            other => panic!("expected GrantAuthorizationError, got {:?}", other.err()),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn fail_with_an_expired_code() {
+        let server = MockServer::start_async().await;
+        let client = client_at(&server);
+        let (_, flow) = login(&client);
+
+        let token = mock_token(
+            &server,
+            "expired-code",
+            400,
+            serde_json::json!({ "error": "invalid_grant", "error_description": "Code expired" }),
+        );
+
+        let client_flow = ClientFlow {
+            client,
+            flow: flow.set_authorization_code("expired-code"),
+        };
+        match oauth::request_token(client_flow).await {
+            Err(OAuthError::GrantTokenError(RequestTokenError::ServerResponse(response))) => {
+                assert_eq!(*response.error(), BasicErrorResponseType::InvalidGrant)
+            }
+            Err(e) => panic!("expected invalid_grant, got {:?}", e),
+            Ok(_) => panic!("expected invalid_grant"),
+        }
+        token.assert();
+    }
+
+    #[actix_rt::test]
+    async fn send_requests_through_the_transport() {
+        fn offline(_: jelly::oauth2::HttpRequest) -> oauth::TransportFuture {
+            Box::pin(async { Err(reqwest::Error::Other("offline".to_string())) })
+        }
+
+        let server = MockServer::start_async().await;
+        let client = client_at(&server).with_transport(offline);
+        let (_, flow) = login(&client);
+
+        let client_flow = ClientFlow {
+            client,
+            flow: flow.set_authorization_code("good-code"),
+        };
+        assert!(matches!(
+            oauth::request_token(client_flow).await,
+            Err(OAuthError::GrantTokenError(RequestTokenError::Request(_)))
+        ));
+    }
+}
+
+#[cfg(test)]
+mod providers_file_should {
+    use super::*;
+    use jelly::oauth::config::{ProvidersFile, UserInfoFields};
+
+    const DISCORD: &str = r#"
+        [providers.google]
+        scopes = ["openid", "email", "profile"]
+
+        [providers.discord]
+        client_id_env = "DISCORD_CLIENT_ID"
+        auth_url = "https://discord.com/oauth2/authorize"
+        token_url = "https://discord.com/api/oauth2/token"
+        user_info = [{ url = "https://discord.com/api/users/@me" }]
+        fields = { id = "/id", name = "/global_name", username = "/username", email = "/email" }
+    "#;
+
+    #[test]
+    fn change_built_in_providers_and_add_others() {
+        let file = ProvidersFile::parse(DISCORD, &client::providers()).unwrap();
+        let google = &file.providers["google"];
+        assert_eq!(google.scopes.clone().unwrap(), ["openid", "email", "profile"]);
+        assert!(google.auth_url.is_none());
+        assert_eq!(file.providers["discord"].user_info.as_ref().unwrap()[0].url, "https://discord.com/api/users/@me");
+    }
+
+    #[test]
+    fn reject_new_providers_missing_what_they_need() {
+        let contents = DISCORD.replace("token_url", "token_uri");
+        assert!(ProvidersFile::parse(&contents, &client::providers()).is_err());
+
+        let contents = r#"
+            [providers.discord]
+            client_id_env = "DISCORD_CLIENT_ID"
+            auth_url = "https://discord.com/oauth2/authorize"
+            token_url = "https://discord.com/api/oauth2/token"
+            user_info = [{ url = "https://discord.com/api/users/@me" }]
+        "#;
+        match ProvidersFile::parse(contents, &client::providers()) {
+            Err(OAuthError::RegisterProviderError(message)) => assert_eq!(message, "discord needs fields"),
+            _ => panic!("expected a missing fields error"),
+        }
+    }
+
+    #[test]
+    fn read_the_user_with_its_fields() {
+        let file = ProvidersFile::parse(DISCORD, &client::providers()).unwrap();
+        let fields = UserInfoFields {
+            provider: "discord",
+            ..file.providers["discord"].fields.clone().unwrap()
+        };
+
+        let profile = r#"{"id":"80351110224678912","username":"nelly","global_name":"Nelly","email":"nelly@example.com"}"#;
+        let info = fields.parse(profile, EMAIL).unwrap();
+        assert_eq!(info.provider, "discord");
+        assert_eq!(info.id, "80351110224678912");
+        assert_eq!(info.name, "Nelly");
+        assert_eq!(info.username.as_deref(), Some("nelly"));
+        assert_eq!(info.provider_email.as_deref(), Some("nelly@example.com"));
+        assert_eq!(info.login_email, EMAIL);
+
+        // Numeric ids are fine; a missing one isn't.
+        assert_eq!(fields.parse(r#"{"id":42}"#, EMAIL).unwrap().id, "42");
+        assert!(fields.parse(r#"{"username":"nelly"}"#, EMAIL).is_err());
+    }
+}
+
+#[cfg(test)]
+mod device_should {
+    use super::*;
+    use jelly::oauth::device::{self, DevicePoll};
+    use jelly::oauth2::http::{HeaderMap, StatusCode};
+
+    /// A Google client whose device flow talks to `server` too.
+    fn device_client_at(server: &MockServer) -> ScopedClient {
+        let mut client = client_at(server);
+        client.device.as_mut().unwrap().authorization_url = server.url("/device/code");
+        client
+    }
+
+    fn mock_poll(server: &MockServer, device_code: &str, status: u16, body: serde_json::Value) -> httpmock::Mock {
+        let device_code = format!("device_code={}", device_code);
+        server.mock(|expect, resp_with| {
+            expect
+                .method(POST)
+                .path("/token")
+                .body_contains(&device_code)
+                .body_contains("grant_type=urn%3Aietf%3Aparams%3Aoauth%3Agrant-type%3Adevice_code");
+            resp_with
+                .status(status)
+                .header("content-type", "application/json")
+                .json_body(body);
+        })
+    }
+
+    #[test]
+    fn be_supported_by_providers_with_a_device_endpoint() {
+        let server = MockServer::start();
+        assert!(device::supported(&client_at(&server)));
+
+        std::env::set_var("TWITTER_CLIENT_ID", "client-id");
+        let endpoints = Endpoints {
+            auth_url: server.url("/authorize"),
+            token_url: server.url("/token"),
+            user_info_uris: vec![server.url("/me")],
+        };
+        let twitter = client::build_client_at("twitter", REDIRECT_URI, &endpoints).unwrap();
+        assert!(!device::supported(&twitter));
+    }
+
+    #[actix_rt::test]
+    async fn refuse_to_start_without_a_device_endpoint() {
+        let server = MockServer::start_async().await;
+        let mut client = client_at(&server);
+        client.device = None;
+        assert!(matches!(device::start(&client).await, Err(OAuthError::DeviceFlowUnsupported)));
+    }
+
+    #[actix_rt::test]
+    async fn start_with_the_client_and_its_scopes() {
+        let server = MockServer::start_async().await;
+        let client = device_client_at(&server);
+
+        let code = server.mock(|expect, resp_with| {
+            expect
+                .method(POST)
+                .path("/device/code")
+                .header("Accept", "application/json")
+                .body_contains("client_id=client-id")
+                .body_contains("client_secret=client-secret")
+                .body_contains("scope=https%3A%2F%2Fwww.googleapis.com%2Fauth%2Fuserinfo.email");
+            // Google's names the verification URI a URL, and sends no
+            // `verification_uri_complete`.
+            resp_with.status(200).header("content-type", "application/json").json_body(serde_json::json!({
+                "device_code": "device-code",
+                "user_code": "GQVQ-JKEC",
+                "verification_url": "https://www.google.com/device",
+                "expires_in": 1800,
+            }));
+        });
+
+        let authorization = device::start(&client).await.unwrap();
+        code.assert();
+        assert_eq!(authorization.device_code, "device-code");
+        assert_eq!(authorization.user_code, "GQVQ-JKEC");
+        assert_eq!(authorization.verification_uri, "https://www.google.com/device");
+        assert_eq!(authorization.verification_uri_complete, None);
+        assert_eq!(authorization.expires_in, 1800);
+        assert_eq!(authorization.interval, device::DEFAULT_INTERVAL);
+    }
+
+    #[actix_rt::test]
+    async fn fail_to_start_when_the_provider_refuses() {
+        let server = MockServer::start_async().await;
+        let client = device_client_at(&server);
+        server.mock(|expect, resp_with| {
+            expect.method(POST).path("/device/code");
+            resp_with
+                .status(400)
+                .header("content-type", "application/json")
+                .json_body(serde_json::json!({ "error": "invalid_client" }));
+        });
+
+        match device::start(&client).await {
+            Err(OAuthError::DeviceFlowError(message)) => assert_eq!(message, "invalid_client"),
+            other => panic!("expected invalid_client, got {:?}", other),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn tell_pending_slow_denied_and_expired_polls_apart() {
+        let server = MockServer::start_async().await;
+        let client = device_client_at(&server);
+        mock_poll(&server, "pending", 428, serde_json::json!({ "error": "authorization_pending" }));
+        mock_poll(&server, "slow", 400, serde_json::json!({ "error": "slow_down" }));
+        mock_poll(&server, "denied", 400, serde_json::json!({ "error": "access_denied" }));
+        mock_poll(&server, "expired", 400, serde_json::json!({ "error": "expired_token" }));
+        mock_poll(&server, "bad", 400, serde_json::json!({ "error": "invalid_grant", "error_description": "Bad code" }));
+
+        assert!(matches!(device::poll(&client, "pending").await, Ok(DevicePoll::Pending)));
+        assert!(matches!(device::poll(&client, "slow").await, Ok(DevicePoll::SlowDown)));
+        assert!(matches!(device::poll(&client, "denied").await, Ok(DevicePoll::Denied)));
+        assert!(matches!(device::poll(&client, "expired").await, Ok(DevicePoll::Expired)));
+        match device::poll(&client, "bad").await {
+            Err(OAuthError::DeviceFlowError(message)) => assert_eq!(message, "invalid_grant: Bad code"),
+            other => panic!("expected invalid_grant, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn read_errors_sent_with_a_200() {
+        // As GitHub does.
+        let response = jelly::oauth2::HttpResponse {
+            status_code: StatusCode::OK,
+            headers: HeaderMap::new(),
+            body: br#"{"error":"authorization_pending","error_description":"Not yet"}"#.to_vec(),
+        };
+        assert!(matches!(device::parse_poll_response(&response), Ok(DevicePoll::Pending)));
+    }
+
+    #[actix_rt::test]
+    async fn fetch_the_profile_once_granted() {
+        let server = MockServer::start_async().await;
+        let client = device_client_at(&server);
+        mock_poll(
+            &server,
+            "approved",
+            200,
+            serde_json::json!({ "access_token": "access-token", "token_type": "bearer" }),
+        );
+        let userinfo = server.mock(|expect, resp_with| {
+            expect.method(GET).path("/userinfo").header("Authorization", "Bearer access-token");
+            resp_with.status(200).header("content-type", "application/json").json_body(serde_json::json!({
+                "sub": "1234567890",
+                "name": "Jane Doe",
+                "email": "jane@example.com",
+            }));
+        });
+
+        let response = match device::poll(&client, "approved").await {
+            Ok(DevicePoll::Granted(response)) => *response,
+            other => panic!("expected a token, got {:?}", other),
+        };
+        let token_info = device::token_info(&client, "google", response);
+        let request = TestRequest::default().to_http_request();
+        let info = oauth::fetch_user_info(&request.get_session(), token_info).await.unwrap();
+
+        userinfo.assert();
+        assert_eq!(info.id, "1234567890");
+        assert_eq!(info.provider_email.as_deref(), Some("jane@example.com"));
+        assert_eq!(info.login_email, "");
+    }
+}
+
+#[cfg(test)]
+mod revoke_should {
+    use super::*;
+    use jelly::oauth2::RevocationUrl;
+
+    /// A Google client whose revocation endpoint is on `server` too.
+    fn revoking_client_at(server: &MockServer) -> ScopedClient {
+        let mut client = client_at(server);
+        client.inner = client
+            .inner
+            .set_revocation_uri(RevocationUrl::new(server.url("/revoke")).unwrap());
+        client
+    }
+
+    #[actix_rt::test]
+    async fn revoke_the_refresh_token() {
+        let server = MockServer::start_async().await;
+        let client = revoking_client_at(&server);
+
+        let revoke = server.mock(|expect, resp_with| {
+            expect
+                .method(POST)
+                .path("/revoke")
+                .body_contains("token=refresh-token")
+                .body_contains("token_type_hint=refresh_token");
+            resp_with.status(200);
+        });
+
+        assert!(oauth::revoke(&client, "refresh-token").await.unwrap());
+        revoke.assert();
+    }
+
+    #[actix_rt::test]
+    async fn skip_providers_without_a_revocation_endpoint() {
+        let server = MockServer::start_async().await;
+        std::env::set_var("GITHUB_CLIENT_ID", "client-id");
+        std::env::set_var("GITHUB_CLIENT_SECRET", "client-secret");
+        let endpoints = Endpoints {
+            auth_url: server.url("/authorize"),
+            token_url: server.url("/token"),
+            user_info_uris: vec![server.url("/user")],
+        };
+        let github = client::build_client_at("github", REDIRECT_URI, &endpoints).unwrap();
+
+        assert!(!oauth::revoke(&github, "refresh-token").await.unwrap());
+    }
+
+    #[actix_rt::test]
+    async fn fail_when_the_provider_refuses() {
+        let server = MockServer::start_async().await;
+        let client = revoking_client_at(&server);
+
+        server.mock(|expect, resp_with| {
+            expect.method(POST).path("/revoke");
+            resp_with
+                .status(400)
+                .header("content-type", "application/json")
+                .json_body(serde_json::json!({ "error": "invalid_request" }));
+        });
+
+        assert!(matches!(
+            oauth::revoke(&client, "refresh-token").await,
+            Err(OAuthError::RevokeTokenError(_))
+        ));
+    }
+}
+
+const SIGNING_KEY: &str = "AQIDBAUGBwgJCgsMDQ4PEBESExQVFhcYGRobHB0eHyA=";
+
+fn issuer_at(server: &MockServer) -> Issuer {
+    Issuer {
+        issuer: "https://accounts.example.com".to_string(),
+        jwks_url: server.url("/certs"),
+        client_id: "client-id".to_string(),
+    }
+}
+
+/// A JWKS holding `key`'s public half as `kid`.
+fn jwks(key: &SigningKey, kid: &str) -> serde_json::Value {
+    let mut jwk = key.jwk();
+    jwk["kid"] = serde_json::json!(kid);
+    serde_json::json!({ "keys": [jwk] })
+}
+
+/// An `id_token` signed with `key`, as `kid`.
+fn signed_id_token(key: &SigningKey, kid: &str, claims: serde_json::Value) -> String {
+    let encode = |json: String| base64::encode_config(json, base64::URL_SAFE_NO_PAD);
+    let signing_input = format!(
+        "{}.{}",
+        encode(serde_json::json!({ "alg": "ES256", "kid": kid }).to_string()),
+        encode(claims.to_string())
+    );
+    let signature = base64::encode_config(key.sign(signing_input.as_bytes()), base64::URL_SAFE_NO_PAD);
+    format!("{}.{}", signing_input, signature)
+}
+
+fn claims(nonce: &str) -> serde_json::Value {
+    serde_json::json!({
+        "iss": "https://accounts.example.com",
+        "aud": "client-id",
+        "exp": chrono::Utc::now().timestamp() + 600,
+        "nonce": nonce,
+        "sub": "1234",
+        "email": EMAIL,
+    })
+}
+
+#[cfg(test)]
+mod verify_id_tokens_should {
+    use super::*;
+
+    fn keys(key: &SigningKey) -> Jwks {
+        serde_json::from_value(jwks(key, "key-1")).unwrap()
+    }
+
+    fn verify(id_token: &str, nonce: &str) -> Result<String, OAuthError> {
+        let key = SigningKey::from_base64(SIGNING_KEY).unwrap();
+        let issuer = Issuer {
+            issuer: "https://accounts.example.com".to_string(),
+            jwks_url: "https://accounts.example.com/certs".to_string(),
+            client_id: "client-id".to_string(),
+        };
+        oidc::verify(id_token, &keys(&key), &issuer, Some(nonce), chrono::Utc::now().timestamp())
+    }
+
+    #[test]
+    fn accept_a_token_signed_by_the_provider() {
+        let key = SigningKey::from_base64(SIGNING_KEY).unwrap();
+        let token = signed_id_token(&key, "key-1", claims("the-nonce"));
+
+        let json = verify(&token, "the-nonce").unwrap();
+        let claims: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(claims["sub"], "1234");
+    }
+
+    #[test]
+    fn reject_tokens_that_dont_check_out() {
+        let key = SigningKey::from_base64(SIGNING_KEY).unwrap();
+        let with = |field: &str, value: serde_json::Value| {
+            let mut claims = claims("the-nonce");
+            claims[field] = value;
+            signed_id_token(&key, "key-1", claims)
+        };
+
+        for token in [
+            with("nonce", serde_json::json!("another-nonce")),
+            with("aud", serde_json::json!("another-client")),
+            with("iss", serde_json::json!("https://evil.example.com")),
+            with("exp", serde_json::json!(chrono::Utc::now().timestamp() - 600)),
+            signed_id_token(&key, "key-2", claims("the-nonce")),
+            id_token(claims("the-nonce")),
+        ] {
+            assert!(matches!(verify(&token, "the-nonce"), Err(OAuthError::IdTokenError(_))));
+        }
+    }
+
+    #[test]
+    fn reject_a_tampered_token() {
+        let key = SigningKey::from_base64(SIGNING_KEY).unwrap();
+        let token = signed_id_token(&key, "key-1", claims("the-nonce"));
+        let forged = base64::encode_config(
+            claims("the-nonce").to_string().replace("1234", "5678"),
+            base64::URL_SAFE_NO_PAD,
+        );
+        let parts: Vec<&str> = token.split('.').collect();
+        let tampered = format!("{}.{}.{}", parts[0], forged, parts[2]);
+
+        assert!(matches!(verify(&tampered, "the-nonce"), Err(OAuthError::IdTokenError(_))));
+    }
+
+    #[test]
+    fn match_any_tenant_for_multi_tenant_issuers() {
+        let issuer = Issuer {
+            issuer: "https://login.microsoftonline.com/{tenantid}/v2.0".to_string(),
+            jwks_url: String::new(),
+            client_id: String::new(),
+        };
+
+        assert!(issuer.matches("https://login.microsoftonline.com/9188040d-6c67/v2.0"));
+        assert!(!issuer.matches("https://login.microsoftonline.com//v2.0"));
+        assert!(!issuer.matches("https://login.microsoftonline.com/a/b/v2.0"));
+        assert!(!issuer.matches("https://evil.example.com/9188040d-6c67/v2.0"));
+    }
+
+    #[actix_rt::test]
+    async fn verify_the_token_response_and_ask_for_a_nonce() {
+        let server = MockServer::start_async().await;
+        let mut client = client_at(&server);
+        client.id_token_issuer = Some(issuer_at(&server));
+        let (url, flow) = login(&client);
+        let key = SigningKey::from_base64(SIGNING_KEY).unwrap();
+        let nonce = oidc::nonce(&flow.csrf_token_secret);
+
+        let query: HashMap<String, String> = jelly::oauth2::url::Url::parse(&url)
+            .unwrap()
+            .query_pairs()
+            .into_owned()
+            .collect();
+        assert_eq!(query["nonce"], nonce);
+
+        let certs = server.mock(|expect, resp_with| {
+            expect.method(GET).path("/certs");
+            resp_with
+                .status(200)
+                .header("content-type", "application/json")
+                .json_body(jwks(&key, "key-1"));
+        });
+        mock_token(
+            &server,
+            "good-code",
+            200,
+            serde_json::json!({
+                "access_token": "access-token",
+                "token_type": "bearer",
+                "id_token": signed_id_token(&key, "key-1", claims(&nonce)),
+            }),
+        );
+
+        let client_flow = ClientFlow {
+            client,
+            flow: flow.set_authorization_code("good-code"),
+        };
+        let token_info = oauth::request_token(client_flow).await.unwrap();
+
+        certs.assert();
+        let claims: serde_json::Value = serde_json::from_str(token_info.id_token_claims.as_deref().unwrap()).unwrap();
+        assert_eq!(claims["email"], EMAIL);
+    }
+
+    #[actix_rt::test]
+    async fn fail_the_token_request_on_a_replayed_token() {
+        let server = MockServer::start_async().await;
+        let mut client = client_at(&server);
+        client.id_token_issuer = Some(issuer_at(&server));
+        let (_, flow) = login(&client);
+        let key = SigningKey::from_base64(SIGNING_KEY).unwrap();
+
+        server.mock(|expect, resp_with| {
+            expect.method(GET).path("/certs");
+            resp_with
+                .status(200)
+                .header("content-type", "application/json")
+                .json_body(jwks(&key, "key-1"));
+        });
+        mock_token(
+            &server,
+            "good-code",
+            200,
+            serde_json::json!({
+                "access_token": "access-token",
+                "token_type": "bearer",
+                "id_token": signed_id_token(&key, "key-1", claims("another-sign-in")),
+            }),
+        );
+
+        let client_flow = ClientFlow {
+            client,
+            flow: flow.set_authorization_code("good-code"),
+        };
+        assert!(matches!(
+            oauth::request_token(client_flow).await,
+            Err(OAuthError::IdTokenError(_))
+        ));
+    }
+}
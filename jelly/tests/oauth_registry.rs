@@ -0,0 +1,66 @@
+#![cfg(feature = "oauth")]
+
+//! Registering providers changes what every test in the process sees, so
+//! these are kept apart from `oauth.rs`.
+
+use jelly::oauth::client::{self, ScopedClientConfig};
+use jelly::oauth::config::UserInfoFields;
+use jelly::oauth::UserInfoEndpoint;
+
+fn intranet() -> ScopedClientConfig {
+    ScopedClientConfig {
+        client_id_env: "INTRANET_CLIENT_ID".to_string(),
+        auth_url: "https://id.example.com/authorize".to_string(),
+        token_url: "https://id.example.com/token".to_string(),
+        scopes: vec!["openid".to_string(), "email".to_string()],
+        login_hint_key: Some("login_hint".to_string()),
+        uses_email_hint: true,
+        user_info_endpoints: vec![UserInfoEndpoint {
+            uri: "https://id.example.com/userinfo".to_string(),
+            params: Vec::new(),
+        }],
+        fields: Some(UserInfoFields {
+            id: "/sub".to_string(),
+            name: Some("/name".to_string()),
+            email: Some("/email".to_string()),
+            ..UserInfoFields::default()
+        }),
+        ..ScopedClientConfig::default()
+    }
+}
+
+#[cfg(test)]
+mod registry_should {
+    use super::*;
+
+    #[test]
+    fn add_and_replace_providers() {
+        std::env::set_var("JELLY_DOMAIN", "https://example.com");
+        std::env::set_var("INTRANET_CLIENT_ID", "intranet-id");
+        client::register("intranet", intranet());
+        client::register(
+            "github",
+            ScopedClientConfig {
+                scopes: vec!["read:user".to_string(), "user:email".to_string()],
+                ..intranet()
+            },
+        );
+
+        assert!(client::valid_provider("intranet"));
+        assert!(client::provider_hints("intranet").unwrap().uses_email_hint);
+        let providers = client::providers();
+        assert!(providers.contains(&"intranet"));
+        assert_eq!(providers.iter().filter(|provider| **provider == "github").count(), 1);
+
+        let client = client::client_for("intranet").unwrap();
+        assert_eq!(client.scopes, ["openid", "email"]);
+        let info = client
+            .user_info_request
+            .parse(r#"{"sub":"u-1","name":"Jane","email":"jane@example.com"}"#, "jane@example.com")
+            .unwrap();
+        assert_eq!((info.provider, info.id.as_str()), ("intranet", "u-1"));
+
+        // Registrations come first, even over built-in providers.
+        assert_eq!(client::client_for("github").unwrap().scopes, ["read:user", "user:email"]);
+    }
+}
@@ -0,0 +1,34 @@
+use jelly::accounts::password;
+use jelly::chrono::{Duration, Utc};
+use jelly::error::Error;
+
+#[cfg(test)]
+mod password_should {
+    use super::*;
+
+    // One test, since both halves set the same environment variable.
+    #[test]
+    fn expire_only_when_a_max_age_is_set() {
+        let long_ago = Utc::now() - Duration::days(120);
+
+        std::env::remove_var("PASSWORD_MAX_AGE_DAYS");
+        assert_eq!(password::max_age(), None);
+        assert!(!password::is_expired(long_ago));
+
+        std::env::set_var("PASSWORD_MAX_AGE_DAYS", "90");
+        assert_eq!(password::max_age(), Some(Duration::days(90)));
+        assert!(password::is_expired(long_ago));
+        assert!(!password::is_expired(Utc::now() - Duration::days(30)));
+
+        std::env::set_var("PASSWORD_MAX_AGE_DAYS", "0");
+        assert!(!password::is_expired(long_ago));
+    }
+
+    #[test]
+    fn reject_a_wrong_password() {
+        let encoded = password::hash("correct horse");
+        assert!(password::verify("correct horse", Some(&encoded)).is_ok());
+        assert!(matches!(password::verify("battery staple", Some(&encoded)), Err(Error::InvalidPassword)));
+        assert!(matches!(password::verify("correct horse", None), Err(Error::NoPasswordForAccount)));
+    }
+}
@@ -0,0 +1,81 @@
+use jelly::accounts::permissions::{self, Granted};
+
+fn granted(allowed: &[&str], denied: &[&str]) -> Granted {
+    Granted {
+        all: false,
+        allowed: allowed.iter().map(|p| p.to_string()).collect(),
+        denied: denied.iter().map(|p| p.to_string()).collect(),
+    }
+}
+
+#[cfg(test)]
+mod permissions_should {
+    use super::*;
+
+    #[test]
+    fn match_names_and_groups() {
+        assert!(permissions::matches("accounts.edit", "accounts.edit"));
+        assert!(!permissions::matches("accounts.edit", "accounts.delete"));
+        assert!(permissions::matches("accounts.*", "accounts.delete"));
+        assert!(!permissions::matches("accounts.*", "audit.view"));
+        assert!(permissions::matches("*", "audit.view"));
+    }
+
+    #[test]
+    fn allow_only_what_is_granted() {
+        let granted = granted(&["accounts.view", "audit.*"], &[]);
+        assert!(granted.allows("accounts.view"));
+        assert!(granted.allows("audit.export"));
+        assert!(!granted.allows("accounts.edit"));
+        assert!(!Granted::default().allows("accounts.view"));
+    }
+
+    #[test]
+    fn let_overrides_take_permissions_away() {
+        let granted = granted(&["accounts.*"], &["accounts.delete"]);
+        assert!(granted.allows("accounts.edit"));
+        assert!(!granted.allows("accounts.delete"));
+    }
+
+    #[test]
+    fn allow_everything_for_admins() {
+        let granted = Granted {
+            all: true,
+            ..granted(&[], &["accounts.delete"])
+        };
+        assert!(granted.allows("accounts.delete"));
+    }
+
+    #[test]
+    fn allow_into_groups_with_anything_granted() {
+        let some = granted(&["accounts.view", "audit.*"], &[]);
+        assert!(some.allows_any("accounts."));
+        assert!(some.allows_any("audit."));
+        assert!(!some.allows_any("settings."));
+        assert!(granted(&["*"], &[]).allows_any("settings."));
+        assert!(!Granted::default().allows_any("accounts."));
+    }
+
+    #[test]
+    fn keep_out_of_groups_taken_away() {
+        let all_but_accounts = granted(&["*"], &["accounts.*"]);
+        assert!(!all_but_accounts.allows_any("accounts."));
+        assert!(all_but_accounts.allows_any("audit."));
+        assert!(granted(&["accounts.*"], &["accounts.edit"]).allows_any("accounts."));
+    }
+
+    #[test]
+    fn only_include_what_is_all_granted() {
+        let managers = granted(&["accounts.*", "invitations.*"], &[]);
+        assert!(managers.includes(&granted(&["accounts.view", "invitations.*"], &[])));
+        assert!(managers.includes(&Granted::default()));
+        assert!(!managers.includes(&granted(&["accounts.edit", "billing.view"], &[])));
+        assert!(!managers.includes(&granted(&["*"], &[])));
+        assert!(!granted(&["accounts.*"], &["accounts.delete"]).includes(&granted(&["accounts.*"], &[])));
+        assert!(!granted(&["accounts.edit"], &[]).includes(&granted(&["accounts.*"], &[])));
+        assert!(!managers.includes(&Granted {
+            all: true,
+            ..Granted::default()
+        }));
+    }
+}
@@ -0,0 +1,31 @@
+use std::path::PathBuf;
+
+use jelly::prerender::file_for;
+
+#[cfg(test)]
+mod prerender_should {
+    use super::*;
+
+    #[test]
+    fn give_pages_a_directory_of_their_own() {
+        assert_eq!(file_for("/"), Some(PathBuf::from("index.html")));
+        assert_eq!(file_for("/pages"), Some(PathBuf::from("pages/index.html")));
+        assert_eq!(file_for("/pages/"), Some(PathBuf::from("pages/index.html")));
+        assert_eq!(file_for("/pages/hello"), Some(PathBuf::from("pages/hello/index.html")));
+    }
+
+    #[test]
+    fn keep_files_with_an_extension() {
+        assert_eq!(file_for("/feed.xml"), Some(PathBuf::from("feed.xml")));
+        assert_eq!(file_for("/a/sitemap.xml"), Some(PathBuf::from("a/sitemap.xml")));
+    }
+
+    #[test]
+    fn refuse_paths_outside_the_export() {
+        assert_eq!(file_for("pages"), None);
+        assert_eq!(file_for("/../etc/passwd"), None);
+        assert_eq!(file_for("/pages/.."), None);
+        assert_eq!(file_for("//pages"), None);
+        assert_eq!(file_for("/pages?page=2"), None);
+    }
+}
@@ -0,0 +1,30 @@
+use jelly::progress::{Progress, DONE, RUNNING};
+
+#[cfg(test)]
+mod progress_should {
+    use super::*;
+
+    fn progress(status: &str) -> Progress {
+        Progress {
+            id: 1,
+            label: "Usage receipt".to_string(),
+            percent: 10,
+            message: "Rendering".to_string(),
+            status: status.to_string(),
+        }
+    }
+
+    #[test]
+    fn know_when_running() {
+        assert!(progress(RUNNING).is_running());
+        assert!(!progress(DONE).is_running());
+    }
+
+    #[test]
+    fn serialize_for_the_widget() {
+        let json = serde_json::to_value(progress(RUNNING)).unwrap();
+        assert_eq!(json["percent"], 10);
+        assert_eq!(json["status"], "running");
+        assert_eq!(json["label"], "Usage receipt");
+    }
+}
@@ -0,0 +1,43 @@
+use jelly::push::Message;
+
+#[cfg(test)]
+mod message_should {
+    use super::*;
+
+    #[test]
+    fn default_to_no_data() {
+        let message: Message = serde_json::from_str(r#"{"title":"Hi","body":"There"}"#).unwrap();
+        assert_eq!(message.title, "Hi");
+        assert!(message.data.is_none());
+    }
+}
+
+#[cfg(all(test, feature = "push-fcm"))]
+mod fcm_should {
+    use jelly::push::{fcm, Delivery};
+    use serde_json::json;
+
+    #[test]
+    fn read_replies() {
+        let sent = json!({ "success": 1, "results": [{ "message_id": "0:1" }] });
+        assert_eq!(fcm::parse_reply(&sent).unwrap(), Delivery::Sent);
+
+        let gone = json!({ "failure": 1, "results": [{ "error": "NotRegistered" }] });
+        assert_eq!(fcm::parse_reply(&gone).unwrap(), Delivery::Unregistered);
+
+        let failed = json!({ "failure": 1, "results": [{ "error": "Unavailable" }] });
+        assert!(fcm::parse_reply(&failed).is_err());
+    }
+}
+
+#[cfg(all(test, feature = "push-apns"))]
+mod apns_should {
+    use jelly::push::apns;
+
+    #[test]
+    fn spot_dead_tokens() {
+        assert!(apns::is_unregistered(410, "Unregistered"));
+        assert!(apns::is_unregistered(400, "BadDeviceToken"));
+        assert!(!apns::is_unregistered(400, "PayloadTooLarge"));
+    }
+}
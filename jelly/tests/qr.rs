@@ -0,0 +1,65 @@
+use jelly::actix_session::storage::CookieSessionStore;
+use jelly::actix_session::{SessionExt, SessionMiddleware};
+use jelly::actix_web::cookie::Key;
+use jelly::actix_web::http::{header, StatusCode};
+use jelly::actix_web::{test, web, App, HttpRequest, HttpResponse};
+use jelly::qr::{self, Format};
+
+const SECRET_KEY: &str = "test-secret-key-that-is-long-enough-for-actix-session-0.6-cookies";
+
+const PROVISIONING_URI: &str = "otpauth://totp/Example:erby?secret=JBSWY3DPEHPK3PXP";
+
+/// Starts TOTP setup the way an app would, putting the code in the session.
+async fn setup(request: HttpRequest) -> HttpResponse {
+    let url = qr::pending_url(&request.get_session(), PROVISIONING_URI, Format::Svg).unwrap();
+    HttpResponse::Ok().body(url)
+}
+
+#[cfg(test)]
+mod qr_should {
+    use super::*;
+
+    #[test]
+    fn verify_own_signature() {
+        std::env::set_var("SECRET_KEY", SECRET_KEY);
+        let signature = qr::sign("otpauth://totp/Example:erby?secret=JBSWY3DPEHPK3PXP");
+        assert!(qr::verify("otpauth://totp/Example:erby?secret=JBSWY3DPEHPK3PXP", &signature));
+        assert!(!qr::verify("otpauth://totp/Example:mallory?secret=JBSWY3DPEHPK3PXP", &signature));
+    }
+
+    #[test]
+    fn build_url_safe_urls() {
+        std::env::set_var("SECRET_KEY", SECRET_KEY);
+        let url = qr::url("https://example.com/accounts/register?invite=a+b/c", Format::Png);
+        assert!(url.starts_with("/qr?d="));
+        assert!(url.ends_with("&f=png"));
+        assert!(!url.contains('+') && !url.contains("//"));
+    }
+
+    #[actix_rt::test]
+    async fn render_pending_codes_from_the_session_only() {
+        let app = test::init_service(
+            App::new()
+                .wrap(SessionMiddleware::new(CookieSessionStore::default(), Key::from(SECRET_KEY.as_bytes())))
+                .route("/setup", web::get().to(setup))
+                .configure(qr::configure),
+        )
+        .await;
+
+        let response = test::call_service(&app, test::TestRequest::get().uri("/setup").to_request()).await;
+        let cookie = response.response().cookies().next().unwrap().into_owned();
+        let url = String::from_utf8(test::read_body(response).await.to_vec()).unwrap();
+        assert_eq!(url, "/qr/pending?f=svg");
+        assert!(!url.contains("secret"));
+
+        let without_session = test::call_service(&app, test::TestRequest::get().uri(&url).to_request()).await;
+        assert_eq!(without_session.status(), StatusCode::NOT_FOUND);
+
+        if cfg!(feature = "qr") {
+            let request = test::TestRequest::get().uri(&url).cookie(cookie).to_request();
+            let response = test::call_service(&app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.headers().get(header::CACHE_CONTROL).unwrap(), "no-store");
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use std::time::Duration;
+
+use jelly::ratelimit::{self, Limit};
+
+#[cfg(test)]
+mod ratelimit_should {
+    use super::*;
+
+    fn limit() -> Limit {
+        Limit {
+            max: 3,
+            window: Duration::from_secs(60),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn allow_hits_up_to_the_limit() {
+        let key = "test:allow";
+        for _ in 0..2 {
+            ratelimit::hit(key, &limit()).await.unwrap();
+        }
+        assert!(!ratelimit::exceeded(key, &limit()).await.unwrap());
+
+        ratelimit::hit(key, &limit()).await.unwrap();
+        assert!(ratelimit::exceeded(key, &limit()).await.unwrap());
+    }
+
+    #[actix_rt::test]
+    async fn forget_cleared_keys() {
+        let key = "test:clear";
+        for _ in 0..3 {
+            ratelimit::hit(key, &limit()).await.unwrap();
+        }
+        ratelimit::clear(key).await.unwrap();
+        assert!(!ratelimit::exceeded(key, &limit()).await.unwrap());
+    }
+
+    #[actix_rt::test]
+    async fn start_a_new_window() {
+        let key = "test:window";
+        let limit = Limit {
+            max: 1,
+            window: Duration::from_millis(10),
+        };
+        ratelimit::hit(key, &limit).await.unwrap();
+        assert!(ratelimit::exceeded(key, &limit).await.unwrap());
+
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(!ratelimit::exceeded(key, &limit).await.unwrap());
+    }
+
+    #[actix_rt::test]
+    async fn ignore_disabled_limits() {
+        let key = "test:disabled";
+        let limit = Limit { max: 0, ..limit() };
+        ratelimit::hit(key, &limit).await.unwrap();
+        assert!(!ratelimit::exceeded(key, &limit).await.unwrap());
+    }
+
+    #[actix_rt::test]
+    async fn report_remaining_hits() {
+        let key = "test:check";
+        let status = ratelimit::check(key, &limit()).await.unwrap().unwrap();
+        assert_eq!(status.limit, 3);
+        assert_eq!(status.remaining, 2);
+        assert!(!status.exceeded);
+        assert!(status.reset <= Duration::from_secs(60));
+        assert!(status.reset_secs() > 0);
+
+        ratelimit::check(key, &limit()).await.unwrap();
+        ratelimit::check(key, &limit()).await.unwrap();
+        let status = ratelimit::check(key, &limit()).await.unwrap().unwrap();
+        assert_eq!(status.remaining, 0);
+        assert!(status.exceeded);
+    }
+
+    #[actix_rt::test]
+    async fn skip_checks_on_disabled_limits() {
+        let limit = Limit { max: 0, ..limit() };
+        assert!(ratelimit::check("test:check-disabled", &limit).await.unwrap().is_none());
+    }
+
+    #[actix_rt::test]
+    async fn count_attempts_as_they_are_made() {
+        let key = "test:attempt";
+        for _ in 0..3 {
+            assert!(ratelimit::attempt(key, &limit()).await.unwrap());
+        }
+        assert!(!ratelimit::attempt(key, &limit()).await.unwrap());
+    }
+}
+
+#[cfg(test)]
+mod client_ip_should {
+    use std::net::IpAddr;
+
+    use super::*;
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn ignore_forwarded_for_from_anyone_else() {
+        let client = ratelimit::client_ip_from(Some(ip("203.0.113.9")), Some("198.51.100.1"), &[ip("10.0.0.2")]);
+        assert_eq!(client, Some(ip("203.0.113.9")));
+    }
+
+    #[test]
+    fn believe_trusted_proxies() {
+        let trusted = ratelimit::parse_proxies("10.0.0.2, 10.0.0.3, nonsense");
+        assert_eq!(trusted, vec![ip("10.0.0.2"), ip("10.0.0.3")]);
+
+        // The client made up the first address; the proxies added the rest.
+        let forwarded = "1.2.3.4, 198.51.100.1, 10.0.0.3";
+        let client = ratelimit::client_ip_from(Some(ip("10.0.0.2")), Some(forwarded), &trusted);
+        assert_eq!(client, Some(ip("198.51.100.1")));
+
+        let client = ratelimit::client_ip_from(Some(ip("10.0.0.2")), None, &trusted);
+        assert_eq!(client, Some(ip("10.0.0.2")));
+    }
+}
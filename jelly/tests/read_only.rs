@@ -0,0 +1,33 @@
+use jelly::actix_web::http::Method;
+use jelly::read_only;
+
+#[cfg(test)]
+mod read_only_should {
+    use super::*;
+
+    #[test]
+    fn block_mutations() {
+        for method in [Method::POST, Method::PUT, Method::PATCH, Method::DELETE] {
+            assert!(read_only::blocks(&method, "/dashboard/profile"), "{}", method);
+        }
+    }
+
+    #[test]
+    fn allow_reads() {
+        for method in [Method::GET, Method::HEAD, Method::OPTIONS] {
+            assert!(!read_only::blocks(&method, "/dashboard/profile"), "{}", method);
+        }
+    }
+
+    #[test]
+    fn allow_saving_settings_so_it_can_be_turned_off() {
+        assert!(!read_only::blocks(&Method::POST, "/admin/settings"));
+        assert!(read_only::blocks(&Method::POST, "/admin/accounts"));
+    }
+
+    #[test]
+    fn allow_changing_the_log_filter() {
+        assert!(!read_only::blocks(&Method::POST, "/admin/logging"));
+        assert!(!read_only::blocks(&Method::POST, "/admin/logging/reset"));
+    }
+}
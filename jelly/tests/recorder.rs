@@ -0,0 +1,103 @@
+use std::collections::BTreeMap;
+
+use jelly::chrono::Utc;
+use jelly::recorder::{self, Recording, Tape};
+
+fn recording(path: &str) -> Recording {
+    Recording {
+        id: 0,
+        at: Utc::now(),
+        method: "GET".to_string(),
+        path: path.to_string(),
+        query: Vec::new(),
+        headers: Vec::new(),
+        form: Vec::new(),
+        status: 200,
+        location: None,
+        template: None,
+        session: BTreeMap::new(),
+    }
+}
+
+#[cfg(test)]
+mod tape_should {
+    use super::*;
+
+    #[test]
+    fn keep_the_last_requests_newest_first() {
+        let mut tape = Tape::new(2);
+        tape.push(recording("/one"));
+        tape.push(recording("/two"));
+        tape.push(recording("/three"));
+
+        let paths: Vec<String> = tape.list().into_iter().map(|recording| recording.path).collect();
+        assert_eq!(paths, ["/three", "/two"]);
+        assert!(tape.get(1).is_none());
+        assert_eq!(tape.get(3).unwrap().path, "/three");
+
+        tape.clear();
+        assert!(tape.list().is_empty());
+    }
+
+    #[test]
+    fn keep_nothing_without_room() {
+        let mut tape = Tape::new(0);
+        tape.push(recording("/one"));
+        assert!(tape.list().is_empty());
+    }
+}
+
+#[cfg(test)]
+mod form_fields_should {
+    use super::*;
+
+    #[test]
+    fn redact_secrets() {
+        let fields = recorder::form_fields(b"email=jane%40example.com&password=hunter2&csrf_token=abc&new_password_confirm=x");
+        assert_eq!(
+            fields,
+            vec![
+                ("email".to_string(), "jane@example.com".to_string()),
+                ("password".to_string(), "[redacted]".to_string()),
+                ("csrf_token".to_string(), "[redacted]".to_string()),
+                ("new_password_confirm".to_string(), "[redacted]".to_string()),
+            ]
+        );
+    }
+}
+
+#[cfg(test)]
+mod redact_should {
+    use super::*;
+
+    #[test]
+    fn redact_tokens_in_paths() {
+        assert_eq!(
+            recorder::redact_path("/accounts/reset/MQ-5xk2ab-3c9d1e0f2a4b6c8d0e1f"),
+            "/accounts/reset/[redacted]"
+        );
+        assert_eq!(recorder::redact_path("/dashboard/profile"), "/dashboard/profile");
+    }
+
+    #[test]
+    fn redact_secret_query_parameters() {
+        let params = recorder::query_params("code=abc&state=xyz&page=2&preview=tok");
+        assert_eq!(
+            params,
+            vec![
+                ("code".to_string(), "[redacted]".to_string()),
+                ("state".to_string(), "[redacted]".to_string()),
+                ("page".to_string(), "2".to_string()),
+                ("preview".to_string(), "[redacted]".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn redact_secret_session_values() {
+        assert!(recorder::is_secret_session_key(jelly::SESSION_OAUTH_TOKEN));
+        assert!(recorder::is_secret_session_key(jelly::SESSION_ID));
+        assert!(recorder::is_secret_session_key("invite_token"));
+        assert!(!recorder::is_secret_session_key(jelly::SESSION_TIMEZONE));
+    }
+}
@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+
+use jelly::retention::{self, Outcome, Policy};
+
+const AUDIT_EVENTS: Policy = Policy::new("audit_events", "audit_events", "created", 365);
+const DELETED_ACCOUNTS: Policy = Policy::new("deleted_accounts", "accounts", "deleted_at", 30).filter("deleted_at IS NOT NULL");
+
+#[cfg(test)]
+mod policy_should {
+    use super::*;
+
+    #[test]
+    fn cover_rows_older_than_its_days() {
+        assert_eq!(AUDIT_EVENTS.condition(), "created <= now() - make_interval(days => $1)");
+        assert_eq!(
+            DELETED_ACCOUNTS.condition(),
+            "deleted_at <= now() - make_interval(days => $1) AND (deleted_at IS NOT NULL)"
+        );
+    }
+
+    #[test]
+    fn take_the_days_admins_set() {
+        let overrides: HashMap<String, i32> =
+            [("audit_events".to_string(), 730), ("unknown".to_string(), 1)].into_iter().collect();
+        let policies = retention::with_overrides(&[AUDIT_EVENTS, DELETED_ACCOUNTS], &overrides);
+
+        assert_eq!(policies[0].days, 730);
+        assert_eq!(policies[1].days, 30);
+        assert_eq!(policies.len(), 2);
+    }
+
+    #[test]
+    fn keep_rows_forever_with_no_days() {
+        let overrides: HashMap<String, i32> = [("audit_events".to_string(), -1)].into_iter().collect();
+        let policies = retention::with_overrides(&[AUDIT_EVENTS], &overrides);
+
+        assert_eq!(policies[0].days, 0);
+        assert!(policies[0].keeps_forever());
+        assert!(!AUDIT_EVENTS.keeps_forever());
+    }
+}
+
+#[cfg(test)]
+mod outcome_should {
+    use super::*;
+
+    fn outcome(days: i32, dry_run: bool) -> Outcome {
+        Outcome {
+            policy: "audit_events",
+            table: "audit_events",
+            days,
+            rows: 12,
+            dry_run,
+        }
+    }
+
+    #[test]
+    fn say_what_was_or_would_be_removed() {
+        assert_eq!(
+            outcome(365, false).to_string(),
+            "audit_events: removed 12 rows from audit_events older than 365 days"
+        );
+        assert_eq!(
+            outcome(365, true).to_string(),
+            "audit_events: would remove 12 rows from audit_events older than 365 days"
+        );
+        assert_eq!(outcome(0, true).to_string(), "audit_events: kept forever");
+    }
+}
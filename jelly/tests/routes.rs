@@ -0,0 +1,99 @@
+use std::time::Duration;
+
+use jelly::actix_web::web::ServiceConfig;
+use jelly::actix_web::{App, HttpResponse};
+use jelly::guards::RateLimit;
+use jelly::ratelimit::Limit;
+use jelly::routes::{self, resource, scope, Access};
+
+const LIMIT: Limit = Limit {
+    max: 20,
+    window: Duration::from_secs(15 * 60),
+};
+
+async fn ok() -> HttpResponse {
+    HttpResponse::Ok().finish()
+}
+
+/// Runs `configure` the way the server does, which is what records.
+fn mount(configure: impl FnOnce(&mut ServiceConfig)) {
+    let _app = App::new().configure(configure);
+}
+
+#[cfg(test)]
+mod routes_should {
+    use super::*;
+
+    #[test]
+    fn record_routes_under_their_scope_with_its_guards() {
+        mount(|config| {
+            scope("/scoped")
+                .permission("accounts.")
+                .resource(resource("").get(ok))
+                .resource(resource("/{id}").get(ok).post(ok))
+                .mount(config)
+        });
+
+        let scoped: Vec<_> = routes::all().into_iter().filter(|r| r.path.starts_with("/scoped")).collect();
+        assert_eq!(scoped.len(), 2);
+        assert_eq!(scoped[0].path, "/scoped");
+        assert_eq!(scoped[1].path, "/scoped/{id}");
+        assert_eq!(scoped[1].methods, "GET POST");
+        assert_eq!(scoped[1].access, vec![Access::Auth, Access::Permission("accounts.")]);
+        assert!(!scoped[0].is_public());
+    }
+
+    #[test]
+    fn record_resources_own_guards_after_the_scopes() {
+        mount(|config| {
+            scope("/mixed")
+                .resource(resource("/open").get(ok))
+                .resource(resource("/closed").post(ok).auth())
+                .mount(config)
+        });
+
+        let open = routes::all().into_iter().find(|r| r.path == "/mixed/open").unwrap();
+        let closed = routes::all().into_iter().find(|r| r.path == "/mixed/closed").unwrap();
+        assert!(open.is_public());
+        assert_eq!(closed.access, vec![Access::Auth]);
+    }
+
+    #[test]
+    fn record_each_route_once() {
+        for _ in 0..3 {
+            mount(|config| resource("/once").get(ok).mount(config));
+        }
+        assert_eq!(routes::all().iter().filter(|r| r.path == "/once").count(), 1);
+    }
+
+    #[test]
+    fn add_views_own_limits_to_the_scopes() {
+        mount(|config| {
+            scope("/limited")
+                .rate_limit(RateLimit { limit: LIMIT, prefix: "test" }, "user")
+                .resource(resource("/login").post(ok).limited(LIMIT, "ip"))
+                .mount(config)
+        });
+
+        let route = routes::all().into_iter().find(|r| r.path == "/limited/login").unwrap();
+        let limits: Vec<_> = route.rate_limits.iter().map(|l| l.to_string()).collect();
+        assert_eq!(limits, vec!["20/15m per user", "20/15m per ip"]);
+        assert!(route.is_public());
+    }
+
+    #[test]
+    fn show_public_routes_as_public() {
+        mount(|config| {
+            scope("/tabled")
+                .resource(resource("/open").get(ok))
+                .resource(resource("/closed").post(ok).auth())
+                .mount(config)
+        });
+
+        let tabled: Vec<_> = routes::all().into_iter().filter(|r| r.path.starts_with("/tabled")).collect();
+        assert_eq!(
+            routes::table(&tabled),
+            "POST  /tabled/closed  auth\nGET   /tabled/open    public\n"
+        );
+    }
+}
@@ -0,0 +1,47 @@
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::thread;
+
+use jelly::scan::{self, Verdict};
+
+#[cfg(test)]
+mod scan_should {
+    use super::*;
+
+    #[test]
+    fn read_clamd_replies() {
+        assert_eq!(scan::parse_reply("stream: OK\0").unwrap(), Verdict::Clean);
+        assert_eq!(
+            scan::parse_reply("stream: Eicar-Test-Signature FOUND\0").unwrap(),
+            Verdict::Infected("Eicar-Test-Signature".to_string())
+        );
+        assert!(scan::parse_reply("INSTREAM size limit exceeded. ERROR\0").is_err());
+    }
+
+    // One test, since it's the only one that sets `CLAMD_ADDRESS`.
+    #[actix_rt::test]
+    async fn stream_files_to_clamd() {
+        std::env::remove_var("CLAMD_ADDRESS");
+        let path = std::env::temp_dir().join("jelly-scan-test.txt");
+        std::fs::write(&path, b"hello").unwrap();
+        assert_eq!(scan::scan(path.clone()).await.unwrap(), None);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        std::env::set_var("CLAMD_ADDRESS", listener.local_addr().unwrap().to_string());
+        let clamd = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            // "zINSTREAM\0", one 5 byte chunk, then the empty one.
+            let mut request = vec![0; 10 + 4 + 5 + 4];
+            stream.read_exact(&mut request).unwrap();
+            stream.write_all(b"stream: OK\0").unwrap();
+            request
+        });
+
+        assert_eq!(scan::scan(path).await.unwrap(), Some(Verdict::Clean));
+        let request = clamd.join().unwrap();
+        assert_eq!(&request[..10], b"zINSTREAM\0");
+        assert_eq!(&request[10..19], b"\0\0\0\x05hello");
+        assert_eq!(&request[19..], b"\0\0\0\0");
+        std::env::remove_var("CLAMD_ADDRESS");
+    }
+}
@@ -0,0 +1,49 @@
+use jelly::accounts::{jwt, ServiceAccount};
+use jelly::chrono::Utc;
+
+const SECRET_KEY: &str = "test-secret-key-that-is-long-enough-for-actix-session-0.6-cookies";
+
+fn service_account() -> ServiceAccount {
+    ServiceAccount {
+        id: 2,
+        account_id: 7,
+        name: "Importer".to_string(),
+        client_id: "svc_abc".to_string(),
+        scopes: vec!["account.read".to_string(), "devices.write".to_string()],
+        last_used: None,
+        created: Utc::now(),
+        rotated_at: None,
+    }
+}
+
+#[cfg(test)]
+mod service_should {
+    use super::*;
+
+    #[test]
+    fn grant_all_scopes_unless_asked() {
+        let service_account = service_account();
+        assert_eq!(service_account.scopes_for(None), service_account.scopes);
+        assert_eq!(service_account.scopes_for(Some(" ")), service_account.scopes);
+    }
+
+    #[test]
+    fn only_grant_scopes_it_has() {
+        let scopes = service_account().scopes_for(Some("devices.write account.write"));
+        assert_eq!(scopes, vec!["devices.write".to_string()]);
+    }
+
+    #[test]
+    fn issue_scoped_tokens() {
+        std::env::set_var("SECRET_KEY", SECRET_KEY);
+        let service_account = service_account();
+        let grant = service_account.grant(service_account.scopes_for(Some("account.read"))).unwrap();
+        assert_eq!(grant.token_type, "Bearer");
+        assert_eq!(grant.scope, "account.read");
+
+        let claims = jwt::verify(&grant.access_token).unwrap();
+        assert_eq!((claims.sub, claims.svc), (7, Some(2)));
+        assert!(claims.allows("account.read"));
+        assert!(!claims.allows("devices.write"));
+    }
+}
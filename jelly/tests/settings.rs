@@ -0,0 +1,68 @@
+use std::thread;
+use std::time::Duration;
+
+use jelly::settings::{self, Cache};
+use serde_json::json;
+
+#[cfg(test)]
+mod cache_should {
+    use super::*;
+
+    #[test]
+    fn remember_values_and_missing_keys() {
+        let cache = Cache::new(Duration::from_secs(60));
+        assert_eq!(cache.get("oauth.disabled_providers"), None);
+
+        cache.insert("oauth.disabled_providers", Some(json!(["twitter"])));
+        cache.insert("unset", None);
+        assert_eq!(cache.get("oauth.disabled_providers"), Some(Some(json!(["twitter"]))));
+        assert_eq!(cache.get("unset"), Some(None));
+    }
+
+    #[test]
+    fn forget_invalidated_keys() {
+        let cache = Cache::new(Duration::from_secs(60));
+        cache.insert("a", Some(json!(1)));
+        cache.insert("b", Some(json!(2)));
+
+        cache.invalidate("a");
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.get("b"), Some(Some(json!(2))));
+
+        cache.clear();
+        assert_eq!(cache.get("b"), None);
+    }
+
+    #[test]
+    fn expire_values() {
+        let cache = Cache::new(Duration::from_millis(10));
+        cache.insert("a", Some(json!(1)));
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(cache.get("a"), None);
+    }
+}
+
+#[cfg(test)]
+mod validate_should {
+    use super::*;
+
+    #[test]
+    fn check_known_keys_against_their_type() {
+        assert!(settings::validate("app.read_only", &json!(true)).is_ok());
+        assert!(settings::validate("app.read_only", &json!("yes")).is_err());
+        assert!(settings::validate("retention.policies", &json!({ "audit_events": 90 })).is_ok());
+        assert!(settings::validate("retention.policies", &json!(["audit_events"])).is_err());
+    }
+
+    #[test]
+    fn check_registered_keys() {
+        settings::register::<Vec<String>>("test.providers");
+        assert!(settings::validate("test.providers", &json!(["github"])).is_ok());
+        assert!(settings::validate("test.providers", &json!("github")).is_err());
+    }
+
+    #[test]
+    fn let_unknown_keys_hold_anything() {
+        assert!(settings::validate("test.unknown", &json!({ "any": ["thing"] })).is_ok());
+    }
+}
@@ -0,0 +1,22 @@
+use chrono::{TimeZone, Utc};
+use jelly::sitemap::Sitemap;
+
+#[cfg(test)]
+mod sitemap_should {
+    use super::*;
+
+    #[test]
+    fn list_urls_with_when_they_changed() {
+        let mut sitemap = Sitemap::new();
+        sitemap
+            .push("https://example.com/", None)
+            .push("https://example.com/pages/a&b", Some(Utc.ymd(2022, 4, 15).and_hms(9, 0, 0)));
+        let xml = sitemap.to_string();
+
+        assert!(xml.contains("<url><loc>https://example.com/</loc></url>"));
+        assert!(xml.contains(
+            "<url><loc>https://example.com/pages/a&amp;b</loc><lastmod>2022-04-15T09:00:00Z</lastmod></url>"
+        ));
+        assert!(xml.trim_end().ends_with("</urlset>"));
+    }
+}
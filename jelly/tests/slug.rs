@@ -0,0 +1,46 @@
+use jelly::forms::SlugField;
+use jelly::forms::validation::Validatable;
+
+#[cfg(test)]
+mod slugify_should {
+    use super::*;
+
+    #[test]
+    fn keep_valid_slugs() {
+        assert_eq!(SlugField::slugify("pzingg"), "pzingg");
+        assert_eq!(SlugField::slugify("peter_z-99"), "peter_z-99");
+    }
+
+    #[test]
+    fn dash_everything_else() {
+        assert_eq!(SlugField::slugify("Peter Zingg"), "Peter-Zingg");
+        assert_eq!(SlugField::slugify("peter.zingg"), "peter-zingg");
+        assert_eq!(SlugField::slugify("  José  Núñez! "), "Jos-N-ez");
+        assert_eq!(SlugField::slugify("..."), "");
+    }
+
+    #[test]
+    fn make_valid_slugs() {
+        let slug = SlugField::new(SlugField::slugify("a.b c/d")).with_key("username");
+        assert!(slug.validate().is_ok());
+        assert!(slug.validate_username().is_ok());
+    }
+}
+
+#[cfg(test)]
+mod slug_field_should {
+    use super::*;
+
+    #[test]
+    fn only_reject_spaces_in_slugs() {
+        assert!(SlugField::new("2022/04/hello.html").with_key("slug").validate().is_ok());
+        assert!(SlugField::new("hello world").with_key("slug").validate().is_err());
+    }
+
+    #[test]
+    fn keep_usernames_to_slug_characters() {
+        assert!(SlugField::new("peter_z-99").with_key("username").validate_username().is_ok());
+        assert!(SlugField::new("peter.zingg").with_key("username").validate_username().is_err());
+        assert!(SlugField::new("peter zingg").with_key("username").validate_username().is_err());
+    }
+}
@@ -0,0 +1,56 @@
+use jelly::storage::{path_for, root};
+use jelly::tenancy::TenantId;
+
+#[cfg(test)]
+mod path_for_should {
+    use super::*;
+
+    #[test]
+    fn keep_files_in_the_tenant_directory() {
+        let path = path_for(TenantId(7), "videos/intro.mp4").unwrap();
+        assert_eq!(path, Some(root().join("7").join("videos/intro.mp4")));
+    }
+
+    #[test]
+    fn refuse_to_climb_out() {
+        assert_eq!(path_for(TenantId(7), "../8/secret.pdf").unwrap(), None);
+        assert_eq!(path_for(TenantId(7), "/etc/passwd").unwrap(), None);
+        assert_eq!(path_for(TenantId(7), "").unwrap(), None);
+    }
+}
+
+#[cfg(test)]
+mod signed_url_should {
+    use jelly::chrono::{Duration, Utc};
+    use jelly::storage::{signed_url, verify};
+    use jelly::tenancy::TenantId;
+
+    const SECRET_KEY: &str = "test-secret-key-that-is-long-enough-for-actix-session-0.6-cookies";
+
+    fn parts(url: &str) -> (i64, String) {
+        let parts: Vec<&str> = url.splitn(6, '/').collect();
+        (parts[3].parse().unwrap(), parts[4].to_string())
+    }
+
+    #[test]
+    fn verify_until_expiry() {
+        std::env::set_var("SECRET_KEY", SECRET_KEY);
+        let url = signed_url(TenantId(7), "receipts/april.pdf", Utc::now() + Duration::days(1));
+        assert!(url.starts_with("/download/7/"));
+        assert!(url.ends_with("/receipts/april.pdf"));
+
+        let (expires, signature) = parts(&url);
+        assert!(verify(TenantId(7), "receipts/april.pdf", expires, &signature));
+        assert!(!verify(TenantId(8), "receipts/april.pdf", expires, &signature));
+        assert!(!verify(TenantId(7), "receipts/may.pdf", expires, &signature));
+        assert!(!verify(TenantId(7), "receipts/april.pdf", expires + 86400, &signature));
+    }
+
+    #[test]
+    fn reject_expired_links() {
+        std::env::set_var("SECRET_KEY", SECRET_KEY);
+        let url = signed_url(TenantId(7), "receipts/april.pdf", Utc::now() - Duration::seconds(1));
+        let (expires, signature) = parts(&url);
+        assert!(!verify(TenantId(7), "receipts/april.pdf", expires, &signature));
+    }
+}
@@ -0,0 +1,43 @@
+use jelly::tenancy::is_scoped;
+
+#[cfg(test)]
+mod is_scoped_should {
+    use super::*;
+
+    #[test]
+    fn accept_tenant_filter() {
+        assert!(is_scoped(
+            "SELECT * FROM audit_events WHERE account_id = $1 LIMIT $2",
+            "account_id"
+        ));
+        assert!(is_scoped(
+            "SELECT e.id FROM audit_events e\n  WHERE e.account_id = $1",
+            "account_id"
+        ));
+        assert!(is_scoped(
+            "INSERT INTO audit_events (account_id, kind) VALUES ($1, $2)",
+            "account_id"
+        ));
+        assert!(is_scoped(
+            "INSERT INTO usage_counters (account_id, amount) SELECT $1::int, $2 WHERE $2 < 10",
+            "account_id"
+        ));
+    }
+
+    #[test]
+    fn reject_missing_filter() {
+        assert!(!is_scoped("SELECT * FROM audit_events WHERE id = $1", "account_id"));
+        assert!(!is_scoped(
+            "SELECT * FROM audit_events WHERE account_id = $12",
+            "account_id"
+        ));
+        assert!(!is_scoped(
+            "SELECT * FROM audit_events WHERE other_account_id = $1",
+            "account_id"
+        ));
+        assert!(!is_scoped(
+            "INSERT INTO audit_events (kind, account_id) VALUES ($1, $2)",
+            "account_id"
+        ));
+    }
+}
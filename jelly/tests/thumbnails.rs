@@ -0,0 +1,57 @@
+use jelly::tenancy::TenantId;
+use jelly::thumbnails::{self, Size};
+
+const SECRET_KEY: &str = "test-secret-key-that-is-long-enough-for-actix-session-0.6-cookies";
+
+#[cfg(test)]
+mod thumbnails_should {
+    use super::*;
+
+    #[test]
+    fn parse_sizes() {
+        let size: Size = "300x200".parse().unwrap();
+        assert_eq!(size, Size { width: 300, height: 200, crop: false });
+        let size: Size = "64x64c".parse().unwrap();
+        assert_eq!(size, Size { width: 64, height: 64, crop: true });
+        assert_eq!(size.to_string(), "64x64c");
+    }
+
+    #[test]
+    fn reject_unreasonable_sizes() {
+        assert!("0x200".parse::<Size>().is_err());
+        assert!("300x".parse::<Size>().is_err());
+        assert!("99999x99999".parse::<Size>().is_err());
+        assert!("300x200x".parse::<Size>().is_err());
+    }
+
+    #[test]
+    fn sign_size_and_key_together() {
+        std::env::set_var("SECRET_KEY", SECRET_KEY);
+        let small = Size { width: 64, height: 64, crop: true };
+        let large = Size { width: 2048, height: 2048, crop: false };
+        let signature = thumbnails::sign(&small, "7/photos/cat.jpg");
+        assert!(thumbnails::verify(&small, "7/photos/cat.jpg", &signature));
+        assert!(!thumbnails::verify(&large, "7/photos/cat.jpg", &signature));
+        assert!(!thumbnails::verify(&small, "8/photos/cat.jpg", &signature));
+    }
+
+    #[test]
+    fn only_take_keys_for_visible_images() {
+        assert_eq!(thumbnails::parse_key("7/photos/cat.jpg"), Some((TenantId(7), "photos/cat.jpg")));
+        assert_eq!(thumbnails::parse_key("7/photos/CAT.PNG"), Some((TenantId(7), "photos/CAT.PNG")));
+        assert!(thumbnails::parse_key(".quarantine/7/cat.jpg").is_none());
+        assert!(thumbnails::parse_key("7/.hidden/cat.jpg").is_none());
+        assert!(thumbnails::parse_key("7/../8/cat.jpg").is_none());
+        assert!(thumbnails::parse_key("7/notes.txt").is_none());
+        assert!(thumbnails::parse_key("cat.jpg").is_none());
+    }
+
+    #[test]
+    fn build_urls() {
+        std::env::set_var("SECRET_KEY", SECRET_KEY);
+        let size = Size { width: 300, height: 200, crop: false };
+        let url = thumbnails::url(&size, "7/photos/cat.jpg");
+        assert!(url.starts_with("/img/"));
+        assert!(url.ends_with("/300x200/7/photos/cat.jpg"));
+    }
+}
@@ -0,0 +1,49 @@
+use jelly::chrono::{Duration, TimeZone, Utc};
+use jelly::tera::{Context, Tera};
+use jelly::timezones;
+
+#[cfg(test)]
+mod timezones_should {
+    use super::*;
+
+    #[test]
+    fn convert_to_local_time() {
+        let at = Utc.ymd(2022, 4, 18).and_hms(9, 30, 0);
+        assert_eq!(timezones::localtime(at, "Europe/London", timezones::DEFAULT_FORMAT), "2022-04-18 10:30 BST");
+        assert_eq!(timezones::localtime(at, "America/New_York", "%H:%M"), "05:30");
+        assert_eq!(timezones::localtime(at, "Not/AZone", "%H:%M %Z"), "09:30 UTC");
+    }
+
+    #[test]
+    fn know_zone_names() {
+        assert!(timezones::is_valid("Asia/Tokyo"));
+        assert!(!timezones::is_valid("Asia/Atlantis"));
+        assert!(timezones::names().any(|name| name == "Europe/Paris"));
+    }
+
+    #[test]
+    fn humanize_relative_times() {
+        let now = Utc.ymd(2022, 4, 18).and_hms(12, 0, 0);
+        assert_eq!(timezones::humanize(now - Duration::seconds(10), now), "just now");
+        assert_eq!(timezones::humanize(now - Duration::seconds(60), now), "a minute ago");
+        assert_eq!(timezones::humanize(now - Duration::minutes(5), now), "5 minutes ago");
+        assert_eq!(timezones::humanize(now - Duration::hours(3), now), "3 hours ago");
+        assert_eq!(timezones::humanize(now - Duration::days(1), now), "a day ago");
+        assert_eq!(timezones::humanize(now + Duration::days(4), now), "in 4 days");
+        assert_eq!(timezones::humanize(now - Duration::days(90), now), "3 months ago");
+        assert_eq!(timezones::humanize(now - Duration::days(800), now), "2 years ago");
+    }
+
+    #[test]
+    fn register_filters() {
+        let mut tera = Tera::default();
+        timezones::register(&mut tera);
+        tera.add_raw_template("when", "{{ at | localtime(tz=timezone, format=\"%H:%M\") }}")
+            .unwrap();
+
+        let mut context = Context::new();
+        context.insert("at", &Utc.ymd(2022, 1, 1).and_hms(12, 0, 0));
+        context.insert("timezone", "Australia/Sydney");
+        assert_eq!(tera.render("when", &context).unwrap(), "23:00");
+    }
+}
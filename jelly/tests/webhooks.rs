@@ -0,0 +1,95 @@
+use jelly::webhooks::{self, Attempt};
+use std::time::Duration;
+
+const BODY: &str = r#"{"event":"webhook.test","data":{}}"#;
+const NOW: i64 = 1_650_000_000;
+
+#[cfg(test)]
+mod signature_should {
+    use super::*;
+
+    #[test]
+    fn verify_with_the_secret() {
+        let secret = webhooks::new_secret();
+        let header = webhooks::signature_header(&[&secret], NOW, BODY);
+        assert!(webhooks::verify(&secret, &header, BODY, NOW + 10, 300));
+        assert!(!webhooks::verify(&webhooks::new_secret(), &header, BODY, NOW + 10, 300));
+    }
+
+    #[test]
+    fn verify_with_either_secret_while_rotating() {
+        let (new, old) = (webhooks::new_secret(), webhooks::new_secret());
+        let header = webhooks::signature_header(&[&new, &old], NOW, BODY);
+        assert_eq!(header.matches("v1=").count(), 2);
+        assert!(webhooks::verify(&new, &header, BODY, NOW, 300));
+        assert!(webhooks::verify(&old, &header, BODY, NOW, 300));
+    }
+
+    #[test]
+    fn reject_tampering_and_replays() {
+        let secret = webhooks::new_secret();
+        let header = webhooks::signature_header(&[&secret], NOW, BODY);
+        assert!(!webhooks::verify(&secret, &header, "{}", NOW, 300));
+        assert!(!webhooks::verify(&secret, &header, BODY, NOW + 301, 300));
+
+        let moved = header.replace(&format!("t={}", NOW), &format!("t={}", NOW + 1));
+        assert!(!webhooks::verify(&secret, &moved, BODY, NOW, 300));
+    }
+}
+
+#[cfg(test)]
+mod attempt_should {
+    use super::*;
+
+    #[test]
+    fn succeed_only_on_2xx() {
+        let attempt = |status| Attempt {
+            status,
+            error: None,
+            duration: Duration::from_millis(5),
+        };
+        assert!(attempt(Some(204)).succeeded());
+        assert!(!attempt(Some(301)).succeeded());
+        assert!(!attempt(Some(500)).succeeded());
+        assert!(!attempt(None).succeeded());
+    }
+}
+
+#[cfg(test)]
+mod destination_should {
+    use super::*;
+
+    #[test]
+    fn find_the_host_and_port() {
+        assert_eq!(webhooks::host_and_port("https://Example.com/hooks"), Some(("example.com".to_string(), 443)));
+        assert_eq!(webhooks::host_and_port("https://example.com:8443?x=1"), Some(("example.com".to_string(), 8443)));
+        assert_eq!(webhooks::host_and_port("https://[::1]:9000/"), Some(("::1".to_string(), 9000)));
+        assert_eq!(webhooks::host_and_port("http://example.com/"), None);
+        assert_eq!(webhooks::host_and_port("https://user@example.com/"), None);
+    }
+
+    #[test]
+    fn refuse_addresses_that_arent_public() {
+        for ip in ["127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "100.64.0.1", "0.0.0.0", "::1", "fd00::1", "fe80::1", "::ffff:127.0.0.1"] {
+            assert!(!webhooks::is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        assert!(webhooks::is_public("93.184.216.34".parse().unwrap()));
+        assert!(webhooks::is_public("2606:2800:220:1::".parse().unwrap()));
+    }
+
+    #[test]
+    fn check_nat64_addresses_as_the_ipv4_they_reach() {
+        for ip in ["64:ff9b::7f00:1", "64:ff9b::a9fe:a9fe", "64:ff9b::c0a8:101", "64:ff9b:1::5db8:d822"] {
+            assert!(!webhooks::is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        assert!(webhooks::is_public("64:ff9b::5db8:d822".parse().unwrap()));
+    }
+
+    #[test]
+    fn refuse_internal_urls_without_resolving() {
+        assert!(webhooks::check_destination("https://169.254.169.254/latest/meta-data").is_err());
+        assert!(webhooks::check_destination("https://localhost:8000/").is_err());
+        assert!(webhooks::check_destination("https://[::1]/").is_err());
+        assert!(webhooks::check_destination("http://example.com/").is_err());
+    }
+}
@@ -0,0 +1,3 @@
+-- Adds a row version to accounts, used for optimistic concurrency (ETag/If-Match).
+
+alter table accounts add column if not exists version integer not null default 1;
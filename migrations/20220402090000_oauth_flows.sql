@@ -0,0 +1,13 @@
+-- Creates an oauth_flows table, holding in-progress OAuth authorizations
+-- keyed by their state (CSRF token) value.
+
+create table if not exists oauth_flows (
+    state text primary key,
+    provider text not null,
+    email text not null,
+    pkce_verifier_secret text not null,
+    created timestamp with time zone not null default now(),
+    expires timestamp with time zone not null default now() + interval '10 minutes'
+);
+
+create index oauth_flows_expires_idx on oauth_flows (expires);
@@ -0,0 +1,5 @@
+-- Adds an optional, unique public username to accounts.
+
+alter table accounts add column if not exists username text;
+
+create unique index accounts_unique_lower_username_idx on accounts (lower(username));
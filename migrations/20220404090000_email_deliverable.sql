@@ -0,0 +1,4 @@
+-- Cleared when a provider reports a hard bounce for the account's address;
+-- no more mail is sent to it until the address is fixed.
+
+alter table accounts add column if not exists email_deliverable boolean not null default true;
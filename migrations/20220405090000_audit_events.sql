@@ -0,0 +1,18 @@
+-- An append-only log of security-relevant account events (logins, purges,
+-- etc). Rows outlive the account they're about, hence the nullable key.
+
+create table if not exists audit_events (
+    id serial primary key,
+    account_id int,
+    kind text not null,
+    ip text,
+    user_agent text,
+    data jsonb not null default '{}',
+    created timestamp with time zone not null default now(),
+    foreign key(account_id) references accounts(id) on delete set null
+);
+
+create index audit_events_account_id_idx on audit_events (account_id, created);
+
+-- When the "verify your email" reminder went out to an unverified account.
+alter table accounts add column if not exists verification_reminder_sent timestamp with time zone;
@@ -0,0 +1,12 @@
+-- Per-account usage, for quotas. Monthly metrics get a row per month
+-- (`period` is the first day of it); running totals like storage use a
+-- single row dated 1970-01-01.
+
+create table if not exists usage_counters (
+    account_id int not null,
+    metric text not null,
+    period date not null,
+    amount bigint not null default 0,
+    primary key(account_id, metric, period),
+    foreign key(account_id) references accounts(id) on delete cascade
+);
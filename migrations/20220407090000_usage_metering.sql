@@ -0,0 +1,27 @@
+-- Metered usage, for usage-based billing. Events are recorded as they
+-- happen and rolled up into `usage_daily` once their day is over.
+
+create table if not exists usage_events (
+    id serial primary key,
+    account_id int not null,
+    metric text not null,
+    quantity bigint not null,
+    created timestamp with time zone not null default now(),
+    foreign key(account_id) references accounts(id) on delete cascade
+);
+
+create index if not exists usage_events_created_idx on usage_events(created);
+
+create table if not exists usage_daily (
+    account_id int not null,
+    metric text not null,
+    day date not null,
+    quantity bigint not null default 0,
+    reported timestamp with time zone,
+    primary key(account_id, metric, day),
+    foreign key(account_id) references accounts(id) on delete cascade
+);
+
+-- Set by whatever creates the customer in Stripe; usage for accounts
+-- without one is only exported as CSV.
+alter table accounts add column if not exists stripe_customer_id text;
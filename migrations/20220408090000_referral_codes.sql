@@ -0,0 +1,15 @@
+-- One shareable referral code per account, and who referred each account.
+-- `referral_credited` is set once the referrer has been credited, so it
+-- only happens once.
+
+create table if not exists referral_codes (
+    account_id int primary key,
+    code text not null unique,
+    created timestamp with time zone not null default now(),
+    foreign key(account_id) references accounts(id) on delete cascade
+);
+
+alter table accounts add column if not exists referred_by int references accounts(id) on delete set null;
+alter table accounts add column if not exists referral_credited timestamp with time zone;
+
+create index if not exists accounts_referred_by_idx on accounts(referred_by);
@@ -0,0 +1,14 @@
+-- People waiting to be let in while registration is closed. Approving an
+-- entry gives it an invite token, which lets that person register.
+
+create table if not exists waitlist (
+    id serial primary key,
+    email text not null unique,
+    survey jsonb not null default '{}',
+    created timestamp with time zone not null default now(),
+    approved timestamp with time zone,
+    invite_token text unique,
+    registered timestamp with time zone
+);
+
+create index if not exists waitlist_pending_idx on waitlist(created) where approved is null;
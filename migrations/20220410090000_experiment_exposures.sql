@@ -0,0 +1,11 @@
+-- Who has seen which variant of each A/B experiment, and whether they went
+-- on to convert. `unit` is `account:<id>` or `session:<id>`.
+
+create table if not exists experiment_exposures (
+    experiment text not null,
+    unit text not null,
+    variant text not null,
+    created timestamp with time zone not null default now(),
+    converted timestamp with time zone,
+    primary key(experiment, unit)
+);
@@ -0,0 +1,19 @@
+-- Short, tracked links (`/l/{code}`), e.g for campaign emails. UTM
+-- parameters are added to `url` when redirecting; the same url and
+-- parameters share one link.
+
+create table if not exists links (
+    id serial primary key,
+    code text not null unique,
+    url text not null,
+    utm_source text,
+    utm_medium text,
+    utm_campaign text,
+    clicks bigint not null default 0,
+    last_clicked timestamp with time zone,
+    created timestamp with time zone not null default now()
+);
+
+create unique index if not exists links_target_idx on links (
+    url, coalesce(utm_source, ''), coalesce(utm_medium, ''), coalesce(utm_campaign, '')
+);
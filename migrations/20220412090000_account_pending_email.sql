@@ -0,0 +1,3 @@
+-- A new email address waiting to be confirmed from its own inbox.
+
+alter table accounts add column if not exists pending_email text;
@@ -0,0 +1,15 @@
+-- Scheduled items (renewals, maintenance windows, ...) for the
+-- `/calendar.ics` feed. Rows without an account are shown to everyone.
+
+create table if not exists scheduled_events (
+    id serial primary key,
+    account_id integer references accounts (id) on delete cascade,
+    title text not null,
+    description text,
+    starts timestamp with time zone not null,
+    ends timestamp with time zone,
+    created timestamp with time zone not null default now(),
+    updated timestamp with time zone not null default now()
+);
+
+create index if not exists scheduled_events_account_idx on scheduled_events (account_id, starts);
@@ -0,0 +1,6 @@
+-- Accounts their owners have deleted. They're treated as gone straight
+-- away, and removed for good after `DELETED_ACCOUNT_RETENTION_DAYS`.
+
+alter table accounts add column if not exists deleted_at timestamp with time zone;
+
+create index if not exists accounts_deleted_at_idx on accounts (deleted_at) where deleted_at is not null;
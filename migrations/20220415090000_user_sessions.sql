@@ -0,0 +1,14 @@
+-- Server-side records of signed in sessions; the cookie carries the id.
+-- Revoked sessions are signed out the next time they're used.
+
+create table if not exists user_sessions (
+    id text primary key,
+    account_id integer not null references accounts (id) on delete cascade,
+    user_agent text,
+    ip text,
+    created timestamp with time zone not null default now(),
+    last_seen timestamp with time zone not null default now(),
+    revoked_at timestamp with time zone
+);
+
+create index if not exists user_sessions_account_idx on user_sessions (account_id, last_seen desc);
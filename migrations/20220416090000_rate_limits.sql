@@ -0,0 +1,8 @@
+-- Rate limit counters, for `jelly::ratelimit::PgStore` (set
+-- RATE_LIMIT_STORE="postgres"). One fixed window per key.
+
+create table if not exists rate_limits (
+    key text primary key,
+    hits integer not null default 0,
+    window_start timestamp with time zone not null default now()
+);
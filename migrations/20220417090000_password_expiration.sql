@@ -0,0 +1,4 @@
+-- When each account's password was last set, so passwords can be made to
+-- expire after `PASSWORD_MAX_AGE_DAYS`. Existing passwords count from now.
+
+alter table accounts add column if not exists password_changed_at timestamp with time zone not null default now();
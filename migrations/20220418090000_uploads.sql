@@ -0,0 +1,17 @@
+-- Files accounts have uploaded to storage (see `STORAGE_ROOT`), and how
+-- their virus scan went: `pending` until it's run, then `clean`,
+-- `infected` (the file is moved to quarantine, and `threat` says what was
+-- found) or `unscanned` if there's no scanner configured. Only clean
+-- files should ever be shared with other accounts.
+
+create table if not exists uploads (
+    id serial primary key,
+    account_id integer not null references accounts (id) on delete cascade,
+    name text not null,
+    size bigint not null,
+    status text not null default 'pending',
+    threat text,
+    created timestamp with time zone not null default now(),
+    scanned_at timestamp with time zone,
+    unique (account_id, name)
+);
@@ -0,0 +1,13 @@
+-- Responses saved for API requests sent with an `Idempotency-Key`, for
+-- `jelly::idempotency::PgStore` (set IDEMPOTENCY_STORE="postgres"). Keys
+-- are prefixed with the account id; `status` is null until the first
+-- request has finished.
+
+create table if not exists idempotency_keys (
+    key text primary key,
+    fingerprint text not null,
+    status smallint,
+    content_type text,
+    body bytea,
+    created timestamp with time zone not null default now()
+);
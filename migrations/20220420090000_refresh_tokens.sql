@@ -0,0 +1,14 @@
+-- Refresh tokens for API clients (see `jelly::accounts::jwt`), stored as
+-- SHA-256 hashes. Each is deleted when it's used, and revoked when the
+-- account's password changes or it signs out everywhere.
+
+create table if not exists refresh_tokens (
+    id serial primary key,
+    account_id integer not null references accounts (id) on delete cascade,
+    token_hash text not null unique,
+    expires timestamp with time zone not null,
+    created timestamp with time zone not null default now(),
+    revoked_at timestamp with time zone
+);
+
+create index if not exists refresh_tokens_account_id on refresh_tokens (account_id);
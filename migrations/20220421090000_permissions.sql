@@ -0,0 +1,30 @@
+-- Fine-grained permissions (see `jelly::accounts::permissions`), named like
+-- `accounts.edit`; a trailing `*` grants a whole group. Accounts get them
+-- from their roles, and per-account overrides grant extra ones
+-- (`granted = true`) or take away ones a role gave (`granted = false`).
+-- Admins have every permission regardless.
+
+create table if not exists roles (
+    id serial primary key,
+    name text not null unique,
+    created timestamp with time zone not null default now()
+);
+
+create table if not exists role_permissions (
+    role_id integer not null references roles (id) on delete cascade,
+    permission text not null,
+    primary key (role_id, permission)
+);
+
+create table if not exists account_roles (
+    account_id integer not null references accounts (id) on delete cascade,
+    role_id integer not null references roles (id) on delete cascade,
+    primary key (account_id, role_id)
+);
+
+create table if not exists account_permissions (
+    account_id integer not null references accounts (id) on delete cascade,
+    permission text not null,
+    granted boolean not null default true,
+    primary key (account_id, permission)
+);
@@ -0,0 +1,14 @@
+-- Mobile devices registered for push notifications (see `jelly::push`).
+-- `provider` is `fcm` or `apns`; tokens are unique, so a device that's
+-- signed into another account moves over to it.
+
+create table if not exists device_tokens (
+    id serial primary key,
+    account_id integer not null references accounts (id) on delete cascade,
+    provider text not null,
+    token text not null unique,
+    created timestamp with time zone not null default now(),
+    updated timestamp with time zone not null default now()
+);
+
+create index if not exists device_tokens_account_id on device_tokens (account_id);
@@ -0,0 +1,14 @@
+-- Invitations for invite-only registration (INVITE_ONLY). Each token lets
+-- one person register, and is used up by doing so.
+
+create table if not exists invitations (
+    id serial primary key,
+    token text not null unique,
+    created_by integer references accounts(id) on delete set null,
+    created timestamp with time zone not null default now(),
+    expires timestamp with time zone not null,
+    used timestamp with time zone,
+    used_by integer references accounts(id) on delete set null
+);
+
+create index if not exists invitations_unused_idx on invitations(created) where used is null;
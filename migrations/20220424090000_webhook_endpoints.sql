@@ -0,0 +1,27 @@
+-- Outgoing webhooks: URLs accounts have events POSTed to, and a log of
+-- each attempt. `previous_secret` still signs requests for a while after
+-- the secret is rotated, so receivers can switch over.
+
+create table if not exists webhook_endpoints (
+    id serial primary key,
+    account_id integer not null references accounts(id) on delete cascade,
+    url text not null,
+    secret text not null,
+    previous_secret text,
+    rotated timestamp with time zone,
+    created timestamp with time zone not null default now()
+);
+
+create index if not exists webhook_endpoints_account_idx on webhook_endpoints(account_id);
+
+create table if not exists webhook_deliveries (
+    id serial primary key,
+    endpoint_id integer not null references webhook_endpoints(id) on delete cascade,
+    event text not null,
+    status integer,
+    error text,
+    duration_ms integer not null,
+    created timestamp with time zone not null default now()
+);
+
+create index if not exists webhook_deliveries_endpoint_idx on webhook_deliveries(endpoint_id, created);
@@ -0,0 +1,16 @@
+-- How far along long background jobs are, for showing to the account that
+-- asked for them (see jelly::progress). Finished rows are tidied up after
+-- a day.
+
+create table if not exists job_progress (
+    id serial primary key,
+    account_id integer not null references accounts(id) on delete cascade,
+    label text not null,
+    percent smallint not null default 0,
+    message text not null default '',
+    status text not null default 'running',
+    created timestamp with time zone not null default now(),
+    updated timestamp with time zone not null default now()
+);
+
+create index if not exists job_progress_account_idx on job_progress(account_id, created);
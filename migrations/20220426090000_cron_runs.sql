@@ -0,0 +1,10 @@
+-- Scheduled job runs (see jelly::jobs::cron). Each run is claimed here by
+-- whichever worker gets to it first, so it's only queued once; rows are
+-- kept for a week.
+
+create table if not exists cron_runs (
+    name text not null,
+    due timestamp with time zone not null,
+    claimed timestamp with time zone not null default now(),
+    primary key (name, due)
+);
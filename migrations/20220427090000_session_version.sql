@@ -0,0 +1,4 @@
+-- Bumped whenever something a signed in session keeps about the account
+-- changes (see jelly::accounts::sessions::refresh), so sessions reload it.
+
+alter table accounts add column if not exists session_version integer not null default 1;
@@ -0,0 +1,5 @@
+-- Set by admins, or on a suspicious login, when an account looks
+-- compromised; until the password is changed, the Auth guard sends the user
+-- to do that before anything else.
+
+alter table accounts add column if not exists must_reset_password boolean not null default false;
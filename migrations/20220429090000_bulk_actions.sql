@@ -0,0 +1,16 @@
+-- Bulk actions taken from the admin account list, run by a background job.
+-- `affected` is what the action actually changed, so reversible ones can be
+-- undone for a little while; `result` is the file an export wrote.
+
+create table if not exists bulk_actions (
+    id serial primary key,
+    action text not null,
+    account_ids integer[] not null,
+    affected integer[] not null default '{}',
+    requested_by integer not null references accounts (id) on delete cascade,
+    status text not null default 'queued',
+    result text,
+    created timestamp with time zone not null default now(),
+    finished timestamp with time zone,
+    undone_at timestamp with time zone
+);
@@ -0,0 +1,8 @@
+-- Settings admins can change without a deploy, as JSON by key. Read
+-- through `jelly::settings`, which caches them.
+
+create table if not exists settings (
+    key text primary key,
+    value jsonb not null,
+    updated timestamptz not null default now()
+);
@@ -0,0 +1,21 @@
+-- API tokens: one per sign in from an API client, carried on through each
+-- refresh after it (see `jelly::accounts::jwt`). Requests made with them
+-- are counted by a background job, and owners are emailed before one
+-- expires unused.
+
+create table if not exists api_tokens (
+    id serial primary key,
+    account_id integer not null references accounts (id) on delete cascade,
+    name text,
+    request_count bigint not null default 0,
+    last_used timestamp with time zone,
+    expires timestamp with time zone not null,
+    expiry_warned_at timestamp with time zone,
+    created timestamp with time zone not null default now(),
+    revoked_at timestamp with time zone
+);
+
+create index if not exists api_tokens_account_id on api_tokens (account_id);
+
+alter table refresh_tokens
+    add column if not exists api_token_id integer references api_tokens (id) on delete cascade;
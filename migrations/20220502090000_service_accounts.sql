@@ -0,0 +1,21 @@
+-- Service accounts (see `jelly::accounts::service`): credentials for
+-- integrations, belonging to an account and limited to some scopes. Secrets
+-- are stored hashed; a rotated one keeps working until
+-- `previous_secret_expires`.
+
+create table if not exists service_accounts (
+    id serial primary key,
+    account_id integer not null references accounts (id) on delete cascade,
+    name text not null,
+    client_id text not null unique,
+    secret_hash text not null,
+    previous_secret_hash text,
+    previous_secret_expires timestamp with time zone,
+    scopes text[] not null default '{}',
+    last_used timestamp with time zone,
+    created timestamp with time zone not null default now(),
+    rotated_at timestamp with time zone,
+    revoked_at timestamp with time zone
+);
+
+create index if not exists service_accounts_account_id_idx on service_accounts (account_id);
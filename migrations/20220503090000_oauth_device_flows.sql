@@ -0,0 +1,20 @@
+-- Creates an oauth_device_flows table, holding device authorizations
+-- (RFC 8628) while their clients poll. Clients poll with a code of their
+-- own, stored hashed; the provider's device code never leaves the server.
+-- The id is public, for the page showing the user code.
+
+create table if not exists oauth_device_flows (
+    id text primary key,
+    code_hash text not null unique,
+    provider text not null,
+    device_code text not null,
+    user_code text not null,
+    verification_uri text not null,
+    verification_uri_complete text,
+    interval_seconds integer not null,
+    last_polled timestamp with time zone,
+    created timestamp with time zone not null default now(),
+    expires timestamp with time zone not null
+);
+
+create index oauth_device_flows_expires_idx on oauth_device_flows (expires);
@@ -0,0 +1,6 @@
+-- Keeps the scopes OAuth flows ask for, and those identities have been
+-- granted, so apps can ask for more access later (incremental
+-- authorization) and see what they already have.
+
+alter table oauth_flows add column if not exists scopes text[] not null default '{}';
+alter table identities add column if not exists scopes text[] not null default '{}';
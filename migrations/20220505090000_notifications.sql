@@ -0,0 +1,19 @@
+-- In-app notifications, listed at `/dashboard/notifications`. Unread ones
+-- are batched into digest emails for accounts that ask for them;
+-- `digested_at` is when one went out with them.
+
+create table if not exists notifications (
+    id serial primary key,
+    account_id integer not null references accounts (id) on delete cascade,
+    category text not null,
+    title text not null,
+    body text,
+    url text,
+    read_at timestamp with time zone,
+    digested_at timestamp with time zone,
+    created timestamp with time zone not null default now()
+);
+
+create index if not exists notifications_account_idx on notifications (account_id, created);
+create index if not exists notifications_undigested_idx on notifications (account_id)
+    where read_at is null and digested_at is null;
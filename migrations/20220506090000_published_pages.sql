@@ -0,0 +1,9 @@
+-- Pages (see `src/pages`) as they go live, so the scheduler can tell which
+-- have been published since it last looked and update the feed and
+-- sitemap for them.
+
+create table if not exists published_pages (
+    slug text primary key,
+    published_at timestamp with time zone not null,
+    seen timestamp with time zone not null default now()
+);
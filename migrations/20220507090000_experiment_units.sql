@@ -0,0 +1,10 @@
+-- Which account each experiment unit (`session:<id>`) signed in as, so
+-- exposures and conversions can be counted by account.
+
+create table if not exists experiment_units (
+    unit text primary key,
+    account_id integer not null references accounts (id) on delete cascade,
+    created timestamp with time zone not null default now()
+);
+
+create index if not exists experiment_units_account_id on experiment_units (account_id);
@@ -0,0 +1,15 @@
+-- Bumps an account's version on every update, since every update changes
+-- `updated` (and so the API representation). Statements that already bump
+-- it themselves, like the If-Match checked ones, aren't bumped twice.
+
+create or replace function bump_account_version() returns trigger as $$
+begin
+    if new.version = old.version then
+        new.version = old.version + 1;
+    end if;
+    return new;
+end;
+$$ language 'plpgsql';
+
+create trigger account_versioned before update on accounts
+for each row execute procedure bump_account_version();
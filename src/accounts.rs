@@ -1,14 +1,67 @@
 //! URL dispatcher for user account related API endpoints.
+//!
+//! With the `accounts.oauth_only` setting on, accounts sign in with OAuth
+//! identities alone: registering, signing in and changing passwords are
+//! gone, "forgot your password" emails a one-time sign in link instead,
+//! and deleting or deactivating an account is confirmed with its email.
+//!
+//! The `accounts.email_domains` setting limits who can sign up, by email
+//! domain, for private betas: `{"allowed": ["example.com"]}` lets in only
+//! addresses there (and at its subdomains), and `"denied"` turns domains
+//! away. Registering and signing up with OAuth both check it, the latter
+//! against the address the provider has too. Accounts that already exist
+//! are left be, but while only some domains are allowed, those signing in
+//! with a password have to have verified their email first.
 
-use jelly::actix_web::web::{get, post, resource, scope, ServiceConfig};
+use std::env;
+
+use jelly::actix_web::web::ServiceConfig;
+use jelly::error::Error;
+use jelly::forms::EmailDomains;
+use jelly::routes::{resource, scope};
 use jelly::serde::Deserialize;
+use jelly::settings;
+use sqlx::postgres::PgPool;
 
 pub mod forms;
 pub mod jobs;
 pub mod models;
 pub mod views;
 
-pub use models::Account;
+pub use models::{Account, UserSession};
+
+/// How long deleted accounts are kept before being removed for good:
+/// `DELETED_ACCOUNT_RETENTION_DAYS`, 30 by default. It's the default for
+/// the `deleted_accounts` retention policy, which admins can override; see
+/// `crate::retention`.
+pub fn deletion_grace_days() -> i32 {
+    env::var("DELETED_ACCOUNT_RETENTION_DAYS")
+        .ok()
+        .and_then(|days| days.parse::<i32>().ok())
+        .unwrap_or(30)
+        .max(0)
+}
+
+/// The setting that turns passwords off, leaving OAuth identities as the
+/// only way in.
+pub const OAUTH_ONLY: &str = "accounts.oauth_only";
+
+pub async fn oauth_only(pool: &PgPool) -> Result<bool, Error> {
+    settings::get_or(pool, OAUTH_ONLY, false).await
+}
+
+/// The setting limiting which email domains can sign up.
+pub const EMAIL_DOMAINS: &str = "accounts.email_domains";
+
+pub async fn email_domains(pool: &PgPool) -> Result<EmailDomains, Error> {
+    settings::get_or(pool, EMAIL_DOMAINS, EmailDomains::default()).await
+}
+
+/// Whether `account` confirms changes with its password: it has one, and
+/// passwords are still in use.
+pub async fn uses_password(account: &Account, pool: &PgPool) -> Result<bool, Error> {
+    Ok(account.password.is_some() && !oauth_only(pool).await?)
+}
 
 #[derive(Deserialize)]
 pub struct TokenInfo {
@@ -18,33 +71,86 @@ pub struct TokenInfo {
 }
 
 pub fn configure(config: &mut ServiceConfig) {
-    config.service(
-        scope("/accounts")
-            .service(
-                resource("/register")
-                    .route(get().to(views::register::form))
-                    .route(post().to(views::register::create_account)),
-            )
-            .service(
-                resource("/reset/{uidb64}-{ts}-{token}")
-                    .route(get().to(views::reset_password::with_token))
-                    .route(post().to(views::reset_password::reset)),
-            )
-            .service(
-                resource("/reset")
-                    .route(get().to(views::reset_password::form))
-                    .route(post().to(views::reset_password::request_reset)),
-            )
-            .service(
-                resource("/login")
-                    .route(get().to(views::login::form))
-                    .route(post().to(views::login::authenticate)),
-            )
-            .service(
-                resource("/verify/{uidb64}-{ts}-{token}")
-                    .route(get().to(views::verify::with_token)),
-            )
-            .service(resource("/verify").route(get().to(views::verify::verify)))
-            .service(resource("/logout").route(post().to(views::logout))),
-    );
+    let (per_account, per_ip) = views::login::limits();
+
+    scope("/accounts")
+        .resource(
+            resource("/register")
+                .get(views::register::form)
+                .post(views::register::create_account),
+        )
+        .resource(
+            resource("/reset/{uidb64}-{ts}-{token}")
+                .get(views::reset_password::with_token)
+                .post(views::reset_password::reset),
+        )
+        .resource(
+            resource("/reset")
+                .get(views::reset_password::form)
+                .post(views::reset_password::request_reset),
+        )
+        .resource(
+            resource("/recover/{uidb64}-{ts}-{token}")
+                .get(views::reset_password::recover_form)
+                .post(views::reset_password::recover),
+        )
+        .resource(resource("/email/confirm/{uidb64}-{ts}-{token}").get(views::change_email::confirm))
+        // Changing the account needs a session that's still good (see
+        // `jelly::accounts::sessions`), not just one the cookie says is
+        // signed in.
+        .resource(
+            resource("/email")
+                .get(views::change_email::form)
+                .post(views::change_email::request_change)
+                .auth(),
+        )
+        .resource(
+            resource("/password")
+                .get(views::change_password::form)
+                .post(views::change_password::change)
+                .auth(),
+        )
+        .resource(
+            resource("/deactivate")
+                .get(views::deactivate::form)
+                .post(views::deactivate::deactivate)
+                .auth(),
+        )
+        .resource(
+            resource("/delete")
+                .get(views::delete::form)
+                .post(views::delete::delete)
+                .auth(),
+        )
+        .resource(
+            resource("/login")
+                .get(views::login::form)
+                .post(views::login::authenticate)
+                .limited(per_account, "account")
+                .limited(per_ip, "ip"),
+        )
+        .resource(
+            resource("/token")
+                .post(views::login::token)
+                .limited(per_account, "account")
+                .limited(per_ip, "ip"),
+        )
+        .resource(resource("/token/session").post(views::login::session_token).auth())
+        .resource(resource("/token/refresh").post(views::login::refresh_token))
+        .resource(
+            resource("/token/client")
+                .post(views::login::client_token)
+                .limited(per_account, "client")
+                .limited(per_ip, "ip"),
+        )
+        .resource(
+            resource("/token/client/rotate")
+                .post(views::login::rotate_client_secret)
+                .limited(per_account, "client")
+                .limited(per_ip, "ip"),
+        )
+        .resource(resource("/verify/{uidb64}-{ts}-{token}").get(views::verify::with_token))
+        .resource(resource("/verify").get(views::verify::verify))
+        .resource(resource("/logout").post(views::logout))
+        .mount(config);
 }
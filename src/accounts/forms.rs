@@ -1,6 +1,14 @@
-use jelly::forms::{EmailField, PasswordPolicy, PasswordField, TextField};
+use jelly::forms::{BoolField, EmailDomains, EmailField, PasswordPolicy, PasswordField, SlugField, TextField};
 use jelly::forms::validation::{concat_results, Validatable, ValidationErrors};
 use serde::{Deserialize, Serialize};
+use std::env;
+
+/// Whether accounts pick a public username at registration. Set
+/// `JELLY_ACCOUNT_USERNAMES` to enable; being a `JELLY_` variable, templates
+/// can check it too.
+pub fn usernames_enabled() -> bool {
+    env::var("JELLY_ACCOUNT_USERNAMES").map_or(false, |v| !v.is_empty() && v != "0")
+}
 
 fn default_redirect_path() -> String {
     "/".into()
@@ -8,15 +16,17 @@ fn default_redirect_path() -> String {
 
 #[derive(Default, Debug, Deserialize, Serialize)]
 pub struct LoginForm {
-    pub email: EmailField,
+    pub login: TextField, // email or username
     pub password: TextField, // not checking strength, just presence
+    #[serde(default)]
+    pub remember: BoolField,
     #[serde(default = "default_redirect_path")]
     pub redirect: String,
 }
 
 impl LoginForm {
     pub fn set_keys(mut self) -> Self {
-        self.email = self.email.with_key("email");
+        self.login = self.login.with_key("login");
         self.password = self.password.with_key("password");
         self
     }
@@ -24,7 +34,7 @@ impl LoginForm {
 
 impl Validatable<String> for LoginForm {
     fn validate(&self) -> Result<(), ValidationErrors<String>> {
-        concat_results(vec![self.email.validate(), self.password.validate()])
+        concat_results(vec![self.login.validate(), self.password.validate()])
     }
 }
 
@@ -32,8 +42,13 @@ impl Validatable<String> for LoginForm {
 pub struct NewAccountForm {
     #[serde(skip)]
     pub policy: PasswordPolicy,
+    /// The domains that can sign up; see `accounts::email_domains`.
+    #[serde(skip)]
+    pub domains: EmailDomains,
     pub name: TextField,
     pub email: EmailField,
+    #[serde(default)]
+    pub username: SlugField,
     pub password: PasswordField,
 }
 
@@ -41,18 +56,32 @@ impl NewAccountForm {
     pub fn set_keys(mut self) -> Self {
         self.name = self.name.with_key("name");
         self.email = self.email.with_key("email");
+        self.username = self.username.with_key("username");
         self.password = self.password.with_key("password");
         self
     }
+
+    /// The chosen username, if usernames are enabled.
+    pub fn username(&self) -> Option<&str> {
+        if usernames_enabled() {
+            Some(&self.username.value)
+        } else {
+            None
+        }
+    }
 }
 
 impl Validatable<String> for NewAccountForm {
     fn validate(&self) -> Result<(), ValidationErrors<String>> {
-        concat_results(vec![
+        let mut results = vec![
             self.name.validate(),
-            self.email.validate(),
+            self.email.validate_with(&self.domains),
             self.password.validate_with(&[&self.name, &self.email], &self.policy)
-        ])
+        ];
+        if usernames_enabled() {
+            results.push(self.username.validate_username());
+        }
+        concat_results(results)
     }
 }
 
@@ -74,6 +103,48 @@ impl Validatable<String> for EmailForm {
     }
 }
 
+/// A new email address, plus the current password for accounts that have
+/// one.
+#[derive(Default, Debug, Deserialize, Serialize)]
+pub struct ChangeEmailForm {
+    pub email: EmailField,
+    #[serde(default)]
+    pub password: TextField,
+}
+
+impl ChangeEmailForm {
+    pub fn set_keys(mut self) -> Self {
+        self.email = self.email.with_key("email");
+        self.password = self.password.with_key("password");
+        self
+    }
+}
+
+impl Validatable<String> for ChangeEmailForm {
+    fn validate(&self) -> Result<(), ValidationErrors<String>> {
+        self.email.validate()
+    }
+}
+
+/// Confirms deleting (or deactivating) the signed in account. The password
+/// is only asked for (and checked by the view) if the account uses one;
+/// otherwise the account's email has to be typed out instead.
+#[derive(Default, Debug, Deserialize, Serialize)]
+pub struct DeleteAccountForm {
+    #[serde(default)]
+    pub password: TextField,
+    #[serde(default)]
+    pub email: TextField,
+}
+
+impl DeleteAccountForm {
+    pub fn set_keys(mut self) -> Self {
+        self.password = self.password.with_key("password");
+        self.email = self.email.with_key("email");
+        self
+    }
+}
+
 #[derive(Default, Debug, Deserialize, Serialize)]
 pub struct ChangePasswordForm {
     // Unused in rendering, but stored here to enable password
@@ -81,12 +152,17 @@ pub struct ChangePasswordForm {
     pub name: Option<String>,
     pub email: Option<String>,
 
+    /// Only asked for (and checked by the view) when a signed in account
+    /// changes its password, not when resetting a forgotten one.
+    #[serde(default)]
+    pub current_password: TextField,
     pub password: PasswordField,
     pub password_confirm: PasswordField,
 }
 
 impl ChangePasswordForm {
     pub fn set_keys(mut self) -> Self {
+        self.current_password = self.current_password.with_key("current_password");
         self.password = self.password.with_key("password");
         self.password_confirm = self.password_confirm.with_key("password_confirm");
         self
@@ -114,3 +190,20 @@ impl Validatable<String> for ChangePasswordForm {
         ])
     }
 }
+
+/// Swaps a refresh token for new tokens; see `jelly::accounts::jwt`.
+#[derive(Default, Debug, Deserialize, Serialize)]
+pub struct RefreshTokenForm {
+    pub refresh_token: String,
+}
+
+/// A service account's credentials, swapped for an access token (or a new
+/// secret); see `jelly::accounts::service`. `scope` narrows the token to
+/// some of the service account's scopes, space separated.
+#[derive(Default, Debug, Deserialize, Serialize)]
+pub struct ClientCredentialsForm {
+    pub client_id: String,
+    pub client_secret: String,
+    #[serde(default)]
+    pub scope: Option<String>,
+}
@@ -1,8 +1,8 @@
-use jelly::jobs::JobConfig;
+use jelly::jobs::{JobConfig, Traced};
 
 mod verify;
 pub use verify::build_context as build_verify_context;
-pub use verify::SendVerifyAccountEmail;
+pub use verify::{build_reminder_email, verify_url, SendVerifyAccountEmail};
 
 mod welcome;
 pub use welcome::build_context as build_welcome_context;
@@ -12,14 +12,25 @@ mod reset_password;
 pub use reset_password::build_context as build_reset_password_context;
 pub use reset_password::{SendPasswordWasResetEmail, SendResetPasswordEmail};
 
+mod recover;
+pub use recover::build_context as build_recover_account_context;
+pub use recover::SendAccountRecoveryEmail;
+
+mod change_email;
+pub use change_email::build_context as build_confirm_email_change_context;
+pub use change_email::{SendConfirmEmailChangeEmail, SendEmailWasChangedEmail};
+
 mod odd_registration_attempt;
 pub use odd_registration_attempt::build_context as build_odd_registration_attempt_context;
 pub use odd_registration_attempt::SendAccountOddRegisterAttemptEmail;
 
 pub fn configure(config: JobConfig) -> JobConfig {
-    let mut config = config.register::<SendResetPasswordEmail>();
-    config = config.register::<SendPasswordWasResetEmail>();
-    config = config.register::<SendWelcomeAccountEmail>();
-    config = config.register::<SendAccountOddRegisterAttemptEmail>();
-    config.register::<SendVerifyAccountEmail>()
+    let mut config = config.register::<Traced<SendResetPasswordEmail>>();
+    config = config.register::<Traced<SendPasswordWasResetEmail>>();
+    config = config.register::<Traced<SendAccountRecoveryEmail>>();
+    config = config.register::<Traced<SendWelcomeAccountEmail>>();
+    config = config.register::<Traced<SendAccountOddRegisterAttemptEmail>>();
+    config = config.register::<Traced<SendConfirmEmailChangeEmail>>();
+    config = config.register::<Traced<SendEmailWasChangedEmail>>();
+    config.register::<Traced<SendVerifyAccountEmail>>()
 }
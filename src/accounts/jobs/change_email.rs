@@ -0,0 +1,102 @@
+use std::env;
+use std::future::Future;
+use std::pin::Pin;
+
+use jelly::accounts::OneTimeUseTokenGenerator;
+use jelly::anyhow::{anyhow, Error};
+use jelly::email::Email;
+use jelly::jobs::{Job, JobState, TRANSACTIONAL_QUEUE};
+use jelly::serde::{Deserialize, Serialize};
+use jelly::tera::Context;
+
+use crate::accounts::Account;
+
+/// Sends the link confirming an account's pending email change to the new
+/// address.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SendConfirmEmailChangeEmail {
+    pub to: i32,
+}
+
+pub fn build_context(action_url: &str, new_email: &str) -> Context {
+    let mut context = Context::new();
+    context.insert("action_url", action_url);
+    context.insert("new_email", new_email);
+    context
+}
+
+impl Job for SendConfirmEmailChangeEmail {
+    type State = JobState;
+    type Future = Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
+
+    const NAME: &'static str = "SendConfirmEmailChangeEmailJob";
+    const QUEUE: &'static str = TRANSACTIONAL_QUEUE;
+
+    fn run(self, state: JobState) -> Self::Future {
+        Box::pin(async move {
+            let change = match Account::email_change(self.to, &state.pool)
+                .await
+                .map_err(|e| anyhow!("Error fetching email change: {:?}", e))?
+            {
+                Some(change) => change,
+                None => return Ok(()),
+            };
+
+            let domain = env::var("JELLY_DOMAIN").expect("No JELLY_DOMAIN value set!");
+            let confirm_url = format!(
+                "{}/accounts/email/confirm/{}-{}",
+                domain,
+                base64_url::encode(&format!("{}", change.account_id)),
+                change
+                    .create_reset_token()
+                    .map_err(|e| { anyhow!("Error creating email change token: {:?}", e) })?
+            );
+
+            let email = Email::new(
+                "email/confirm-email-change",
+                &[change.new_email.clone()],
+                "Confirm your new email address",
+                build_context(&confirm_url, &change.new_email),
+                state.templates,
+            );
+
+            email?.send()?;
+
+            Ok(())
+        })
+    }
+}
+
+/// Lets the old address know the account's email was changed.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SendEmailWasChangedEmail {
+    pub to: String,
+    pub new_email: String,
+}
+
+impl Job for SendEmailWasChangedEmail {
+    type State = JobState;
+    type Future = Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
+
+    const NAME: &'static str = "SendEmailWasChangedEmailJob";
+    const QUEUE: &'static str = TRANSACTIONAL_QUEUE;
+
+    fn run(self, state: JobState) -> Self::Future {
+        Box::pin(async move {
+            let mut context = Context::new();
+            context.insert("new_email", &self.new_email);
+
+            let email = Email::new(
+                "email/email-was-changed",
+                &[self.to],
+                "Your Email Address Was Changed",
+                context,
+                state.templates,
+            );
+
+            email?.send()?;
+
+            Ok(())
+        })
+    }
+}
@@ -4,7 +4,7 @@ use std::pin::Pin;
 
 use jelly::anyhow::{anyhow, Error};
 use jelly::email::Email;
-use jelly::jobs::{Job, JobState, DEFAULT_QUEUE};
+use jelly::jobs::{Job, JobState, TRANSACTIONAL_QUEUE};
 use jelly::serde::{Deserialize, Serialize};
 use jelly::tera::Context;
 
@@ -41,7 +41,7 @@ impl Job for SendAccountOddRegisterAttemptEmail {
     type Future = Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
 
     const NAME: &'static str = "SendAccountOddRegisterAttemptEmailJob";
-    const QUEUE: &'static str = DEFAULT_QUEUE;
+    const QUEUE: &'static str = TRANSACTIONAL_QUEUE;
 
     fn run(self, state: JobState) -> Self::Future {
         Box::pin(async move {
@@ -54,6 +54,14 @@ impl Job for SendAccountOddRegisterAttemptEmail {
                     )
                 })?;
 
+            let deliverable = Account::is_deliverable(&self.to, &state.pool)
+                .await
+                .map_err(|e| anyhow!("Error checking deliverability: {:?}", e))?;
+            if !deliverable {
+                warn!("Not sending odd registration notice to undeliverable {}", self.to);
+                return Ok(());
+            }
+
             let email = Email::new(
                 "email/odd-registration-attempt",
                 &[self.to],
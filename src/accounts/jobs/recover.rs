@@ -0,0 +1,81 @@
+use std::env;
+use std::future::Future;
+use std::pin::Pin;
+
+use jelly::accounts::OneTimeUseTokenGenerator;
+use jelly::anyhow::{anyhow, Error};
+use jelly::email::Email;
+use jelly::jobs::{Job, JobState, TRANSACTIONAL_QUEUE};
+use jelly::serde::{Deserialize, Serialize};
+use jelly::tera::Context;
+
+use crate::accounts::models::Identity;
+use crate::accounts::Account;
+
+/// Sent instead of a password reset when accounts sign in with OAuth only:
+/// a one-time link that signs the account in, so it can link a provider
+/// again, along with the providers it's already linked to.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SendAccountRecoveryEmail {
+    pub to: String,
+}
+
+pub fn build_context(sign_in_url: &str, providers: &[String]) -> Context {
+    let mut context = Context::new();
+    context.insert("action_url", sign_in_url);
+    context.insert("providers", providers);
+    context
+}
+
+impl Job for SendAccountRecoveryEmail {
+    type State = JobState;
+    type Future = Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
+
+    const NAME: &'static str = "SendAccountRecoveryEmailJob";
+    const QUEUE: &'static str = TRANSACTIONAL_QUEUE;
+
+    fn run(self, state: JobState) -> Self::Future {
+        Box::pin(async move {
+            let account = Account::get_by_email(&self.to, &state.pool)
+                .await
+                .map_err(|e| anyhow!("Error fetching account for recovery: {:?}", e))?;
+
+            if !account.email_deliverable {
+                warn!("Not sending account recovery to undeliverable {}", account.email);
+                return Ok(());
+            }
+
+            let mut providers: Vec<String> = Identity::linked_to_account_id(account.id, &state.pool)
+                .await
+                .map_err(|e| anyhow!("Error fetching identities for recovery: {:?}", e))?
+                .into_iter()
+                .map(|identity| identity.provider)
+                .collect();
+            providers.sort();
+            providers.dedup();
+
+            let domain = env::var("JELLY_DOMAIN").expect("No JELLY_DOMAIN value set!");
+
+            let sign_in_url = format!(
+                "{}/accounts/recover/{}-{}",
+                domain,
+                base64_url::encode(&format!("{}", account.id)),
+                account
+                    .create_reset_token()
+                    .map_err(|e| { anyhow!("Error creating recovery token: {:?}", e) })?
+            );
+
+            let email = Email::new(
+                "email/recover-account",
+                &[account.email],
+                "Sign in to your account",
+                build_context(&sign_in_url, &providers),
+                state.templates,
+            );
+
+            email?.send()?;
+
+            Ok(())
+        })
+    }
+}
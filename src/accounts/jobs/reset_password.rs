@@ -5,7 +5,7 @@ use std::pin::Pin;
 use jelly::accounts::OneTimeUseTokenGenerator;
 use jelly::anyhow::{anyhow, Error};
 use jelly::email::Email;
-use jelly::jobs::{Job, JobState, DEFAULT_QUEUE};
+use jelly::jobs::{Job, JobState, TRANSACTIONAL_QUEUE};
 use jelly::serde::{Deserialize, Serialize};
 use jelly::tera::Context;
 
@@ -27,7 +27,7 @@ impl Job for SendResetPasswordEmail {
     type Future = Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
 
     const NAME: &'static str = "SendResetPasswordEmailJob";
-    const QUEUE: &'static str = DEFAULT_QUEUE;
+    const QUEUE: &'static str = TRANSACTIONAL_QUEUE;
 
     fn run(self, state: JobState) -> Self::Future {
         Box::pin(async move {
@@ -35,6 +35,11 @@ impl Job for SendResetPasswordEmail {
                 .await
                 .map_err(|e| anyhow!("Error fetching account for password reset: {:?}", e))?;
 
+            if !account.email_deliverable {
+                warn!("Not sending password reset to undeliverable {}", account.email);
+                return Ok(());
+            }
+
             let domain = env::var("JELLY_DOMAIN").expect("No JELLY_DOMAIN value set!");
 
             let verify_url = format!(
@@ -71,11 +76,18 @@ impl Job for SendPasswordWasResetEmail {
     type Future = Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
 
     const NAME: &'static str = "SendPasswordWasResetEmailJob";
-    const QUEUE: &'static str = DEFAULT_QUEUE;
+    const QUEUE: &'static str = TRANSACTIONAL_QUEUE;
 
     fn run(self, state: JobState) -> Self::Future {
         Box::pin(async move {
-            let _ = &state;
+            let deliverable = Account::is_deliverable(&self.to, &state.pool)
+                .await
+                .map_err(|e| anyhow!("Error checking deliverability: {:?}", e))?;
+            if !deliverable {
+                warn!("Not sending password change notice to undeliverable {}", self.to);
+                return Ok(());
+            }
+
             let email = Email::new(
                 "email/password-was-reset",
                 &[self.to],
@@ -1,15 +1,18 @@
 use std::env;
 use std::future::Future;
 use std::pin::Pin;
+use std::sync::{Arc, RwLock};
 
 use jelly::accounts::OneTimeUseTokenGenerator;
 use jelly::anyhow::{anyhow, Error};
 use jelly::email::Email;
-use jelly::jobs::{Job, JobState, DEFAULT_QUEUE};
+use jelly::jobs::{Job, JobState, TRANSACTIONAL_QUEUE};
 use jelly::serde::{Deserialize, Serialize};
-use jelly::tera::Context;
+use jelly::tenancy::{TenantId, TenantPool};
+use jelly::tera::{Context, Tera};
 
 use crate::accounts::Account;
+use crate::quotas::{Metric, Usage};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SendVerifyAccountEmail {
@@ -22,12 +25,45 @@ pub fn build_context(verify_url: &str) -> Context {
     context
 }
 
+/// The link that verifies `account`'s email address.
+pub fn verify_url(account: &Account) -> Result<String, Error> {
+    let domain = env::var("JELLY_DOMAIN").expect("No JELLY_DOMAIN value set!");
+
+    Ok(format!(
+        "{}/accounts/verify/{}-{}",
+        domain,
+        base64_url::encode(&format!("{}", account.id)),
+        account
+            .create_reset_token()
+            .map_err(|e| { anyhow!("Error creating verification token: {:?}", e) })?
+    ))
+}
+
+/// The reminder sent halfway through the unverified account retention
+/// period (see the scheduler).
+pub fn build_reminder_email(
+    account: &Account,
+    retention_days: i32,
+    templates: Arc<RwLock<Tera>>,
+) -> Result<Email, Error> {
+    let mut context = build_context(&verify_url(account)?);
+    context.insert("retention_days", &retention_days);
+
+    Email::new(
+        "email/verify-reminder",
+        &[account.email.clone()],
+        "Please verify your account",
+        context,
+        templates,
+    )
+}
+
 impl Job for SendVerifyAccountEmail {
     type State = JobState;
     type Future = Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
 
     const NAME: &'static str = "SendVerifyAccountEmailJob";
-    const QUEUE: &'static str = DEFAULT_QUEUE;
+    const QUEUE: &'static str = TRANSACTIONAL_QUEUE;
 
     fn run(self, state: JobState) -> Self::Future {
         Box::pin(async move {
@@ -35,27 +71,27 @@ impl Job for SendVerifyAccountEmail {
                 .await
                 .map_err(|e| anyhow!("Error fetching account for verification: {:?}", e))?;
 
-            let domain = env::var("JELLY_DOMAIN").expect("No JELLY_DOMAIN value set!");
-
-            let verify_url = format!(
-                "{}/accounts/verify/{}-{}",
-                domain,
-                base64_url::encode(&format!("{}", account.id)),
-                account
-                    .create_reset_token()
-                    .map_err(|e| { anyhow!("Error creating verification token: {:?}", e) })?
-            );
+            if !account.email_deliverable {
+                warn!("Not sending verification to undeliverable {}", account.email);
+                return Ok(());
+            }
 
             let email = Email::new(
                 "email/verify-account",
-                &[account.email],
+                &[account.email.clone()],
                 "Verify your new account",
-                build_context(&verify_url),
+                build_context(&verify_url(&account)?),
                 state.templates,
             );
 
             email?.send()?;
 
+            // Counted, but never held back by the quota; the account can't
+            // be used without it.
+            Usage::record(&TenantPool::new(&state.pool, TenantId(account.id)), Metric::EmailsSent, 1)
+                .await
+                .map_err(|e| anyhow!("Error recording email usage: {:?}", e))?;
+
             Ok(())
         })
     }
@@ -4,11 +4,13 @@ use std::pin::Pin;
 
 use jelly::anyhow::{anyhow, Error};
 use jelly::email::Email;
-use jelly::jobs::{Job, JobState, DEFAULT_QUEUE};
+use jelly::jobs::{Job, JobState, TRANSACTIONAL_QUEUE};
 use jelly::serde::{Deserialize, Serialize};
+use jelly::tenancy::{TenantId, TenantPool};
 use jelly::tera::Context;
 
 use crate::accounts::Account;
+use crate::quotas::{Metric, Usage};
 
 /// A job for sending a Welcome email, generally dispatched after an account
 /// has been verified.
@@ -33,7 +35,7 @@ impl Job for SendWelcomeAccountEmail {
     type Future = Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
 
     const NAME: &'static str = "SendWelcomeAccountEmailJob";
-    const QUEUE: &'static str = DEFAULT_QUEUE;
+    const QUEUE: &'static str = TRANSACTIONAL_QUEUE;
 
     fn run(self, state: JobState) -> Self::Future {
         Box::pin(async move {
@@ -41,6 +43,23 @@ impl Job for SendWelcomeAccountEmail {
                 .await
                 .map_err(|e| anyhow!("Error fetching user name/email: {:?}", e))?;
 
+            let deliverable = Account::is_deliverable(&email, &state.pool)
+                .await
+                .map_err(|e| anyhow!("Error checking deliverability: {:?}", e))?;
+            if !deliverable {
+                warn!("Not sending welcome to undeliverable {}", email);
+                return Ok(());
+            }
+
+            let db = TenantPool::new(&state.pool, TenantId(self.to));
+            let within_quota = Usage::consume(&db, Metric::EmailsSent, 1)
+                .await
+                .map_err(|e| anyhow!("Error checking email quota: {:?}", e))?;
+            if !within_quota {
+                warn!("Not sending welcome to {}; account is over its email quota", email);
+                return Ok(());
+            }
+
             let email = Email::new(
                 "email/welcome",
                 &[email],
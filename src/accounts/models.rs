@@ -1,44 +1,198 @@
 // Implements a basic Account model, with support for creating/updating/deleting
 // users, along with welcome email and verification.
 
-use jelly::accounts::{OneTimeUseTokenGenerator, User};
+use jelly::accounts::{password, OneTimeUseTokenGenerator, User};
 use jelly::chrono::{DateTime, Utc};
+use jelly::crypto::Encrypted;
 use jelly::djangohashers as hasher;
 use jelly::error::Error;
+use jelly::forms::SlugField;
 use jelly::serde::{Deserialize, Serialize};
-use sqlx::{postgres::PgPool, types::Json, FromRow};
+use jelly::tenancy::TenantPool;
+use sqlx::{postgres::{PgDatabaseError, PgExecutor, PgPool}, types::Json, FromRow};
 
 use super::forms::{LoginForm, NewAccountForm};
 use crate::oauth::forms::LinkIdentityForm;
 
 /// Personalized profile data that is a pain to make a needless JOIN
 /// for; just shove it in a jsonb field.
-#[derive(Debug, Serialize, Deserialize, FromRow)]
-pub struct Profile {}
+///
+/// Sensitive fields are wrapped in `Encrypted`, so they're encrypted
+/// inside the jsonb when `ENCRYPTION_KEY` is set, and left out when a
+/// profile's serialized anywhere else.
+#[derive(Debug, Default, Serialize, Deserialize, FromRow)]
+#[serde(default)]
+pub struct Profile {
+    pub bio: String,
+    pub location: Encrypted<String>,
+    pub website: String,
+    /// A picture to show instead of their Gravatar.
+    pub avatar_url: String,
+    pub privacy: ProfilePrivacy,
+    /// Where times are shown for, e.g `Europe/London`; empty for UTC.
+    pub timezone: String,
+    /// Their language, e.g `en-GB`; empty for the site's default.
+    pub locale: String,
+    pub notifications: NotificationPreferences,
+}
+
+/// What an account shows on its public profile page. Everything is
+/// hidden until the user opts in from their preferences.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProfilePrivacy {
+    /// Whether `/u/{username}/` is visible at all.
+    pub public: bool,
+    pub show_location: bool,
+    pub show_website: bool,
+}
+
+/// How often an account is emailed a digest of its unread notifications.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Digest {
+    Off,
+    Daily,
+    Weekly,
+}
+
+impl Default for Digest {
+    fn default() -> Self {
+        Digest::Off
+    }
+}
+
+impl Digest {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "off" => Some(Digest::Off),
+            "daily" => Some(Digest::Daily),
+            "weekly" => Some(Digest::Weekly),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Digest::Off => "off",
+            Digest::Daily => "daily",
+            Digest::Weekly => "weekly",
+        }
+    }
+
+    /// Days between digests; zero when they're off.
+    pub fn days(&self) -> i32 {
+        match self {
+            Digest::Off => 0,
+            Digest::Daily => 1,
+            Digest::Weekly => 7,
+        }
+    }
+}
+
+/// How an account hears about its notifications, besides in the app.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationPreferences {
+    pub digest: Digest,
+}
+
+/// The subset of an Account that's safe to render on its public profile.
+#[derive(Debug, Serialize)]
+pub struct PublicProfile {
+    pub username: String,
+    pub name: String,
+    pub bio: String,
+    pub location: Option<String>,
+    pub website: Option<String>,
+    pub avatar_url: Option<String>,
+    pub joined: DateTime<Utc>,
+}
+
+impl PublicProfile {
+    /// Returns `None` if the account has no username, or hasn't made
+    /// their profile public.
+    pub fn for_account(account: &Account) -> Option<Self> {
+        let profile = &account.profile.0;
+        let privacy = &profile.privacy;
+        if !privacy.public || !account.is_active {
+            return None;
+        }
+
+        let shown = |show: bool, value: &str| {
+            if show && !value.is_empty() {
+                Some(value.to_string())
+            } else {
+                None
+            }
+        };
+
+        Some(PublicProfile {
+            username: account.username.clone()?,
+            name: account.name.clone(),
+            bio: profile.bio.clone(),
+            location: shown(privacy.show_location, profile.location.plaintext()),
+            website: shown(privacy.show_website, &profile.website),
+            avatar_url: shown(true, &profile.avatar_url),
+            joined: account.created,
+        })
+    }
+}
 
 /// A user Account.
-/// Note: `password` can be None if authenticating via OAuth.
+/// Note: `password` can be None if authenticating via OAuth. It's never
+/// serialized, so accounts can go in template contexts as they are.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Account {
     pub id: i32,
     pub name: String,
     pub email: String,
+    pub username: Option<String>,
+    #[serde(skip_serializing)]
     pub password: Option<String>,
     pub profile: Json<Profile>,
     pub plan: i32,
     pub is_active: bool,
     pub is_admin: bool,
     pub has_verified_email: bool,
+    pub email_deliverable: bool,
     pub last_login: Option<DateTime<Utc>>,
     pub created: DateTime<Utc>,
     pub updated: DateTime<Utc>,
+    pub version: i32,
+}
+
+/// An email change waiting for confirmation. Its token is only good while
+/// the account still has both addresses (and the same password), so it's
+/// spent once the change goes through, or if another is requested.
+#[derive(Debug)]
+pub struct EmailChange {
+    pub account_id: i32,
+    pub email: String,
+    pub new_email: String,
+    password: Option<String>,
+}
+
+impl OneTimeUseTokenGenerator for EmailChange {
+    fn hash_value(&self) -> String {
+        format!(
+            "{}{}{}{}",
+            self.account_id,
+            self.password.as_ref().unwrap_or(&"NoPassword".to_string()),
+            self.email,
+            self.new_email
+        )
+    }
 }
 
 struct UserPass {
     id: i32,
     name: String,
     password: Option<String>,
+    password_changed_at: DateTime<Utc>,
     is_admin: bool,
+    is_active: bool,
+    must_reset_password: bool,
 }
 
 impl UserPass {
@@ -70,9 +224,9 @@ impl Account {
             Account,
             "
             SELECT
-                id, name, email, password, profile, plan,
-                is_active, is_admin, has_verified_email,
-                last_login, created, updated
+                id, name, email, username, password, profile, plan,
+                is_active, is_admin, has_verified_email, email_deliverable,
+                last_login, created, updated, version
             FROM accounts WHERE id = $1
         ",
             id
@@ -81,15 +235,33 @@ impl Account {
         .await?)
     }
 
+    /// Like `get`, but only for accounts that can still sign in: active,
+    /// and not deleted.
+    pub async fn get_active(id: i32, pool: &PgPool) -> Result<Option<Self>, Error> {
+        Ok(sqlx::query_as_unchecked!(
+            Account,
+            "
+            SELECT
+                id, name, email, username, password, profile, plan,
+                is_active, is_admin, has_verified_email, email_deliverable,
+                last_login, created, updated, version
+            FROM accounts WHERE id = $1 AND is_active AND deleted_at IS NULL
+        ",
+            id
+        )
+        .fetch_optional(pool)
+        .await?)
+    }
+
     pub async fn get_by_email(email: &str, pool: &PgPool) -> Result<Self, Error> {
         Ok(sqlx::query_as_unchecked!(
             Account,
             "
             SELECT
-                id, name, email, password, profile, plan,
-                is_active, is_admin, has_verified_email,
-                last_login, created, updated
-            FROM accounts WHERE email = $1
+                id, name, email, username, password, profile, plan,
+                is_active, is_admin, has_verified_email, email_deliverable,
+                last_login, created, updated, version
+            FROM accounts WHERE email = $1 AND is_active AND deleted_at IS NULL
         ",
             email
         )
@@ -97,6 +269,77 @@ impl Account {
         .await?)
     }
 
+    pub async fn get_by_username(username: &str, pool: &PgPool) -> Result<Self, Error> {
+        Ok(sqlx::query_as_unchecked!(
+            Account,
+            "
+            SELECT
+                id, name, email, username, password, profile, plan,
+                is_active, is_admin, has_verified_email, email_deliverable,
+                last_login, created, updated, version
+            FROM accounts WHERE lower(username) = lower($1) AND is_active AND deleted_at IS NULL
+        ",
+            username
+        )
+        .fetch_one(pool)
+        .await?)
+    }
+
+    pub async fn username_taken(username: &str, pool: &PgPool) -> Result<bool, Error> {
+        Ok(sqlx::query!(
+            "
+            SELECT exists(SELECT 1 FROM accounts WHERE lower(username) = lower($1))
+        ",
+            username
+        )
+        .fetch_one(pool)
+        .await?
+        .exists
+        .unwrap_or(false))
+    }
+
+    /// Whether `e` is an insert or update failing because another account
+    /// has the username; checking first with `username_taken` can't rule
+    /// that out.
+    pub fn is_username_conflict(e: &Error) -> bool {
+        match e {
+            Error::Database(sqlx::Error::Database(e)) => e
+                .try_downcast_ref::<PgDatabaseError>()
+                .and_then(PgDatabaseError::constraint)
+                == Some("accounts_unique_lower_username_idx"),
+            _ => false,
+        }
+    }
+
+    /// A username that isn't taken, going by `name` (e.g the username an
+    /// OAuth provider has for them): its slug, or failing that, the slug with
+    /// a number added. `None` if `name` has nothing to make a slug of.
+    pub async fn suggest_username(name: &str, pool: &PgPool) -> Result<Option<String>, Error> {
+        // Email addresses make for poor usernames, and reveal the address.
+        let name = name.split('@').next().unwrap_or_default();
+        let slug = SlugField::slugify(name);
+        if slug.is_empty() {
+            return Ok(None);
+        }
+
+        let taken: Vec<String> = sqlx::query!(
+            "
+            SELECT lower(username) as \"username!\" FROM accounts
+            WHERE lower(username) LIKE lower($1) || '%'
+        ",
+            slug
+        )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| row.username)
+        .collect();
+
+        Ok((1..100)
+            .map(|n| if n == 1 { slug.clone() } else { format!("{}{}", slug, n) })
+            .find(|candidate| !taken.contains(&candidate.to_lowercase())))
+    }
+
     pub async fn id_by_email(email: &str, pool: &PgPool) -> Result<i32, Error> {
         Ok(sqlx::query!(
             "
@@ -110,27 +353,47 @@ impl Account {
         .id)
     }
 
-    pub async fn authenticate(form: &LoginForm, pool: &PgPool) -> Result<User, Error> {
+    /// The id of the account a login form refers to, by email or username.
+    pub async fn id_by_login(login: &str, pool: &PgPool) -> Result<i32, Error> {
+        Ok(sqlx::query!(
+            "
+            SELECT id
+            FROM accounts
+            WHERE (email = $1 OR lower(username) = lower($1)) AND deleted_at IS NULL
+        ",
+            login
+        )
+        .fetch_one(pool)
+        .await?
+        .id)
+    }
+
+    /// Checks the login and password, returning the signed in user and
+    /// whether they have to choose a new password: it's expired (see
+    /// `password::max_age`) or the account's been flagged to reset it.
+    /// Deactivated accounts are `Error::AccountInactive`, once the
+    /// password's been checked.
+    pub async fn authenticate(form: &LoginForm, pool: &PgPool) -> Result<(User, bool), Error> {
         let user = sqlx::query_as_unchecked!(
             UserPass,
             "
             SELECT
-                id, name, password, is_admin
-            FROM accounts WHERE email = $1
+                id, name, password, password_changed_at, is_admin, is_active, must_reset_password
+            FROM accounts
+            WHERE (email = $1 OR lower(username) = lower($1)) AND deleted_at IS NULL
         ",
-            form.email.value
+            form.login.value
         )
         .fetch_one(pool)
         .await?;
 
-        user.check_password(&form.password.value)?;
+        password::verify(&form.password.value, user.password.as_deref())?;
+        if !user.is_active {
+            return Err(Error::AccountInactive);
+        }
 
-        Ok(User {
-            id: user.id,
-            name: user.name,
-            is_admin: user.is_admin,
-            is_anonymous: false,
-        })
+        let expired = user.must_reset_password || password::is_expired(user.password_changed_at);
+        Ok((User::load(pool, user.id).await?, expired))
     }
 
     pub async fn fetch_email(id: i32, pool: &PgPool) -> Result<(String, String), Error> {
@@ -161,30 +424,408 @@ impl Account {
         Ok(data.name)
     }
 
-    pub async fn register(form: &NewAccountForm, pool: &PgPool) -> Result<i32, Error> {
+    /// Whether mail may be sent to `email`. Addresses that don't belong to
+    /// an account are always deliverable.
+    pub async fn is_deliverable(email: &str, pool: &PgPool) -> Result<bool, Error> {
+        Ok(sqlx::query!(
+            "
+            SELECT coalesce(bool_and(email_deliverable), true) as \"deliverable!\"
+            FROM accounts WHERE email = $1
+        ",
+            email
+        )
+        .fetch_one(pool)
+        .await?
+        .deliverable)
+    }
+
+    /// Flags the account using `email` after a hard bounce. Returns whether
+    /// there was one.
+    pub async fn mark_undeliverable(email: &str, pool: &PgPool) -> Result<bool, Error> {
+        let result = sqlx::query!(
+            "
+            UPDATE accounts
+            SET email_deliverable = false
+            WHERE email = $1
+        ",
+            email
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Unverified accounts created more than `days` ago that haven't been
+    /// sent a verification reminder yet. OAuth-only accounts never verify
+    /// an email, so they're left alone.
+    pub async fn needing_verification_reminder(
+        days: i32,
+        pool: &PgPool,
+    ) -> Result<Vec<Self>, Error> {
+        Ok(sqlx::query_as_unchecked!(
+            Account,
+            "
+            SELECT
+                id, name, email, username, password, profile, plan,
+                is_active, is_admin, has_verified_email, email_deliverable,
+                last_login, created, updated, version
+            FROM accounts
+            WHERE has_verified_email = false
+                AND verification_reminder_sent IS NULL
+                AND created <= now() - make_interval(days => $1)
+                AND NOT EXISTS (SELECT 1 FROM identities WHERE account_id = accounts.id)
+        ",
+            days
+        )
+        .fetch_all(pool)
+        .await?)
+    }
+
+    pub async fn mark_verification_reminder_sent(id: i32, pool: &PgPool) -> Result<(), Error> {
+        sqlx::query!(
+            "
+            UPDATE accounts
+            SET verification_reminder_sent = now()
+            WHERE id = $1
+        ",
+            id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Deletes (or, with `deactivate`, deactivates) accounts that still
+    /// haven't verified their email `days` after signing up, and were
+    /// reminded at least `notice_days` ago. Returns the ids and emails of
+    /// the accounts affected.
+    pub async fn purge_unverified(
+        days: i32,
+        notice_days: i32,
+        deactivate: bool,
+        pool: &PgPool,
+    ) -> Result<Vec<(i32, String)>, Error> {
+        let purged = if deactivate {
+            sqlx::query!(
+                "
+                UPDATE accounts
+                SET is_active = false
+                WHERE has_verified_email = false
+                    AND is_active = true
+                    AND created <= now() - make_interval(days => $1)
+                    AND verification_reminder_sent <= now() - make_interval(days => $2)
+                    AND NOT EXISTS (SELECT 1 FROM identities WHERE account_id = accounts.id)
+                RETURNING id, email
+            ",
+                days,
+                notice_days
+            )
+            .fetch_all(pool)
+            .await?
+            .into_iter()
+            .map(|row| (row.id, row.email))
+            .collect()
+        } else {
+            sqlx::query!(
+                "
+                DELETE FROM accounts
+                WHERE has_verified_email = false
+                    AND created <= now() - make_interval(days => $1)
+                    AND verification_reminder_sent <= now() - make_interval(days => $2)
+                    AND NOT EXISTS (SELECT 1 FROM identities WHERE account_id = accounts.id)
+                RETURNING id, email
+            ",
+                days,
+                notice_days
+            )
+            .fetch_all(pool)
+            .await?
+            .into_iter()
+            .map(|row| (row.id, row.email))
+            .collect()
+        };
+
+        Ok(purged)
+    }
+
+    /// Marks the account deleted. It's treated as gone from here on, and
+    /// `purge_deleted` removes it for good once the grace period is up.
+    pub async fn soft_delete(id: i32, pool: &PgPool) -> Result<(), Error> {
+        sqlx::query!(
+            "
+            UPDATE accounts
+            SET deleted_at = now(), updated = now()
+            WHERE id = $1 AND deleted_at IS NULL
+        ",
+            id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Deactivates the account: it can't sign in, and its sessions and
+    /// tokens stop working, until an admin reactivates it.
+    pub async fn deactivate(id: i32, pool: &PgPool) -> Result<(), Error> {
+        sqlx::query!(
+            "
+            UPDATE accounts
+            SET is_active = false, updated = now()
+            WHERE id = $1 AND deleted_at IS NULL
+        ",
+            id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn reactivate(id: i32, pool: &PgPool) -> Result<(), Error> {
+        sqlx::query!(
+            "
+            UPDATE accounts
+            SET is_active = true, updated = now()
+            WHERE id = $1 AND deleted_at IS NULL
+        ",
+            id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Deactivates those of `ids` that are active, other than `except` (the
+    /// admin doing it) and admins. Returns the ids it deactivated.
+    pub async fn deactivate_many(ids: &[i32], except: i32, pool: &PgPool) -> Result<Vec<i32>, Error> {
+        Ok(sqlx::query!(
+            "
+            UPDATE accounts
+            SET is_active = false, updated = now()
+            WHERE id = ANY($1) AND id <> $2 AND NOT is_admin AND is_active AND deleted_at IS NULL
+            RETURNING id
+        ",
+            ids,
+            except
+        )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| row.id)
+        .collect())
+    }
+
+    pub async fn reactivate_many(ids: &[i32], pool: &PgPool) -> Result<Vec<i32>, Error> {
+        Ok(sqlx::query!(
+            "
+            UPDATE accounts
+            SET is_active = true, updated = now()
+            WHERE id = ANY($1) AND NOT is_active AND deleted_at IS NULL
+            RETURNING id
+        ",
+            ids
+        )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| row.id)
+        .collect())
+    }
+
+    /// Those of `ids` still waiting to verify their email address.
+    pub async fn unverified_among(ids: &[i32], pool: &PgPool) -> Result<Vec<i32>, Error> {
+        Ok(sqlx::query!(
+            "
+            SELECT id FROM accounts
+            WHERE id = ANY($1) AND NOT has_verified_email AND is_active AND deleted_at IS NULL
+            ORDER BY id
+        ",
+            ids
+        )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| row.id)
+        .collect())
+    }
+
+    /// Flags the account (e.g as compromised) so it has to choose a new
+    /// password before doing anything else; changing or resetting the
+    /// password clears it. Returns whether it was flagged: accounts without
+    /// a password aren't.
+    pub async fn require_password_reset(id: i32, pool: &PgPool) -> Result<bool, Error> {
+        let result = sqlx::query!(
+            "
+            UPDATE accounts
+            SET must_reset_password = true, updated = now()
+            WHERE id = $1 AND password IS NOT NULL AND deleted_at IS NULL
+        ",
+            id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Drops the flag set by `require_password_reset`, for when there's no
+    /// password to reset after all (see `oauth_only`).
+    pub async fn clear_password_reset(id: i32, pool: &PgPool) -> Result<(), Error> {
+        sqlx::query!(
+            "
+            UPDATE accounts
+            SET must_reset_password = false, updated = now()
+            WHERE id = $1 AND must_reset_password
+        ",
+            id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Deletes accounts that were marked deleted more than `days` ago,
+    /// along with everything that cascades from them. Returns the ids and
+    /// emails of the accounts removed.
+    pub async fn purge_deleted(days: i32, pool: &PgPool) -> Result<Vec<(i32, String)>, Error> {
+        Ok(sqlx::query!(
+            "
+            WITH purged AS (
+                SELECT id FROM accounts
+                WHERE deleted_at <= now() - make_interval(days => $1)
+            ), unlinked AS (
+                DELETE FROM identities WHERE account_id IN (SELECT id FROM purged)
+            )
+            DELETE FROM accounts
+            WHERE id IN (SELECT id FROM purged)
+            RETURNING id, email
+        ",
+            days
+        )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| (row.id, row.email))
+        .collect())
+    }
+
+    /// Creates the account, returning its id. Takes a transaction as well
+    /// as a pool, for registering along with something else.
+    pub async fn register<'e, E: PgExecutor<'e>>(form: &NewAccountForm, executor: E) -> Result<i32, Error> {
         // TODO 101: return InvalidPassword if password is empty
-        let password = hasher::make_password(&form.password);
+        let password = password::hash(&form.password);
 
         Ok(sqlx::query!(
             "
-            INSERT INTO accounts (name, email, password)
-            VALUES ($1, $2, $3)
+            INSERT INTO accounts (name, email, username, password)
+            VALUES ($1, $2, $3, $4)
             RETURNING id
         ",
             form.name.value,
             form.email.value,
+            form.username(),
             password
         )
-        .fetch_one(pool)
+        .fetch_one(executor)
         .await?
         .id)
     }
 
+    /// Whether `password` is the account's password. Accounts without one
+    /// (OAuth only) never match.
+    pub async fn check_password(id: i32, password: &str, pool: &PgPool) -> Result<bool, Error> {
+        let user = sqlx::query_as_unchecked!(
+            UserPass,
+            "
+            SELECT
+                id, name, password, is_admin
+            FROM accounts WHERE id = $1
+        ",
+            id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        match user.check_password(password) {
+            Err(Error::NoPasswordForAccount) => Ok(false),
+            result => result,
+        }
+    }
+
+    /// Starts changing the account's email to `new_email`, replacing any
+    /// change already pending. Nothing changes until it's confirmed.
+    pub async fn request_email_change(id: i32, new_email: &str, pool: &PgPool) -> Result<(), Error> {
+        sqlx::query!(
+            "
+            UPDATE accounts
+            SET pending_email = $2
+            WHERE id = $1
+        ",
+            id,
+            new_email
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// The account's pending email change, if there is one.
+    pub async fn email_change(id: i32, pool: &PgPool) -> Result<Option<EmailChange>, Error> {
+        Ok(sqlx::query!(
+            "
+            SELECT id, email, password, pending_email as \"pending_email!\"
+            FROM accounts
+            WHERE id = $1 AND pending_email IS NOT NULL
+        ",
+            id
+        )
+        .fetch_optional(pool)
+        .await?
+        .map(|row| EmailChange {
+            account_id: row.id,
+            email: row.email,
+            new_email: row.pending_email,
+            password: row.password,
+        }))
+    }
+
+    /// Switches the account over to the address in `change`. The new
+    /// address has been proven, so it's marked verified (and deliverable).
+    /// Fails if another account has taken the address in the meantime.
+    pub async fn confirm_email_change(change: &EmailChange, pool: &PgPool) -> Result<(), Error> {
+        let result = sqlx::query!(
+            "
+            UPDATE accounts
+            SET email = pending_email, pending_email = NULL,
+                has_verified_email = true, email_deliverable = true,
+                version = version + 1, session_version = session_version + 1
+            WHERE id = $1 AND email = $2 AND pending_email = $3
+        ",
+            change.account_id,
+            change.email,
+            change.new_email
+        )
+        .execute(pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(Error::InvalidAccountToken);
+        }
+        Ok(())
+    }
+
     pub async fn mark_verified(id: i32, pool: &PgPool) -> Result<(), Error> {
         sqlx::query!(
             "
             UPDATE accounts
-            SET has_verified_email = true, last_login = now()
+            SET has_verified_email = true, last_login = now(),
+                session_version = session_version + 1
             WHERE id = $1
         ",
             id
@@ -216,12 +857,32 @@ impl Account {
         pool: &PgPool,
     ) -> Result<(), Error> {
         // TODO 101: return InvalidPassword if password is empty
-        let password = hasher::make_password(password);
+        let password = password::hash(password);
+
+        sqlx::query!(
+            "
+            UPDATE accounts
+            SET password = $2, password_changed_at = now(), last_login = now(),
+                must_reset_password = false
+            WHERE id = $1
+        ",
+            id,
+            password
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Sets a new password for a signed in account, restarting its expiry.
+    pub async fn update_password(id: i32, password: &str, pool: &PgPool) -> Result<(), Error> {
+        let password = password::hash(password);
 
         sqlx::query!(
             "
             UPDATE accounts
-            SET password = $2, last_login = now()
+            SET password = $2, password_changed_at = now(), must_reset_password = false
             WHERE id = $1
         ",
             id,
@@ -233,9 +894,85 @@ impl Account {
         Ok(())
     }
 
+    /// Updates the account name, but only if the row is still at
+    /// `expected_version`. Returns `None` if someone else got there first.
+    pub async fn update_name_versioned(
+        id: i32,
+        expected_version: i32,
+        name: &str,
+        pool: &PgPool,
+    ) -> Result<Option<Self>, Error> {
+        Ok(sqlx::query_as_unchecked!(
+            Account,
+            "
+            UPDATE accounts
+            SET name = $3, version = version + 1, session_version = session_version + 1
+            WHERE id = $1 AND version = $2
+            RETURNING
+                id, name, email, username, password, profile, plan,
+                is_active, is_admin, has_verified_email, email_deliverable,
+                last_login, created, updated, version
+        ",
+            id,
+            expected_version,
+            name
+        )
+        .fetch_optional(pool)
+        .await?)
+    }
+
+    /// Sets the profile fields in `changes` (a struct or map of some of
+    /// `Profile`'s fields), leaving the rest as they are. It's merged in the
+    /// database, so saving one form can't undo another saved meanwhile.
+    pub async fn update_profile<T: Serialize>(id: i32, changes: &T, pool: &PgPool) -> Result<(), Error> {
+        sqlx::query!(
+            "
+            UPDATE accounts
+            SET profile = profile || $2
+            WHERE id = $1
+        ",
+            id,
+            Json(changes) as _
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Updates an identity that's signed in again, or been authorized for
+    /// more: `scopes` are added to what it had, and a new refresh token
+    /// replaces the old (providers only send one on consent).
+    async fn update_grant<'e, E: PgExecutor<'e>>(
+        form: &LinkIdentityForm,
+        refresh_token: &Option<String>,
+        scopes: &[String],
+        executor: E,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            "
+            UPDATE identities
+            SET
+                scopes = ARRAY(SELECT DISTINCT unnest(scopes || $3::text[])),
+                refresh_token = COALESCE($4, refresh_token),
+                updated = now()
+            WHERE provider = $1 AND username = $2
+        ",
+            form.provider,
+            form.username,
+            scopes,
+            refresh_token.as_deref(),
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    }
+
     pub async fn merge_identity_and_login(
         form: &LinkIdentityForm,
         refresh_token: Option<String>,
+        scopes: Vec<String>,
         current_account_id: Option<i32>,
         pool: &PgPool,
     ) -> Result<User, Error> {
@@ -254,6 +991,17 @@ impl Account {
         .await?
         .map(|r| r.account_id);
 
+        // Deactivated accounts can't sign in this way either.
+        if let Some(account_id) = linked_account_id.or(current_account_id) {
+            let is_active = sqlx::query!("SELECT is_active FROM accounts WHERE id = $1", account_id)
+                .fetch_one(&mut tx)
+                .await?
+                .is_active;
+            if !is_active {
+                return Err(Error::AccountInactive);
+            }
+        }
+
         match (linked_account_id, current_account_id) {
             (Some(linked_id), None) => {
                 // The account is linked to a local account and
@@ -265,23 +1013,19 @@ impl Account {
                     SET last_login = now()
                     WHERE id = $1
                     RETURNING
-                        id, name, email, password, profile, plan,
-                        is_active, is_admin, has_verified_email,
-                        last_login, created, updated
+                        id, name, email, username, password, profile, plan,
+                        is_active, is_admin, has_verified_email, email_deliverable,
+                        last_login, created, updated, version
                 ",
                     linked_id
                 )
                 .fetch_one(&mut tx)
                 .await?;
 
+                Account::update_grant(form, &refresh_token, &scopes, &mut tx).await?;
                 tx.commit().await?;
 
-                Ok(User {
-                    id: user.id,
-                    name: user.name,
-                    is_admin: user.is_admin,
-                    is_anonymous: false,
-                })
+                User::load(pool, user.id).await
             }
             (None, None) => {
                 // The account is not linked to a local account and
@@ -289,15 +1033,16 @@ impl Account {
                 let user = sqlx::query_as_unchecked!(
                     Account,
                     "
-                    INSERT INTO accounts (name, email, password, last_login)
-                    VALUES ($1, $2, $3, now())
+                    INSERT INTO accounts (name, email, username, password, last_login)
+                    VALUES ($1, $2, $3, $4, now())
                     RETURNING
-                        id, name, email, password, profile, plan,
-                        is_active, is_admin, has_verified_email,
-                        last_login, created, updated
+                        id, name, email, username, password, profile, plan,
+                        is_active, is_admin, has_verified_email, email_deliverable,
+                        last_login, created, updated, version
                 ",
                     form.name.value,
                     form.email.value,
+                    form.account_username(),
                     jelly::NO_PASSWORD,
                 )
                 .fetch_one(&mut tx)
@@ -305,8 +1050,8 @@ impl Account {
 
                 let _identity_id = sqlx::query!(
                     "
-                    INSERT INTO identities (account_id, provider, username, name, refresh_token)
-                    VALUES ($1, $2, $3, $4, $5)
+                    INSERT INTO identities (account_id, provider, username, name, refresh_token, scopes)
+                    VALUES ($1, $2, $3, $4, $5, $6)
                     RETURNING id
                 ",
                     user.id,
@@ -314,6 +1059,7 @@ impl Account {
                     form.username,
                     form.name.value,
                     refresh_token,
+                    &scopes,
                 )
                 .fetch_one(&mut tx)
                 .await?
@@ -321,12 +1067,7 @@ impl Account {
 
                 tx.commit().await?;
 
-                Ok(User {
-                    id: user.id,
-                    name: user.name,
-                    is_admin: user.is_admin,
-                    is_anonymous: false,
-                })
+                User::load(pool, user.id).await
             }
             (Some(linked_id), Some(account_id)) => {
                 // The account is linked to a local account and
@@ -339,9 +1080,9 @@ impl Account {
                         SET name = $1, last_login = now()
                         WHERE id = $2
                         RETURNING
-                            id, name, email, password, profile, plan,
-                            is_active, is_admin, has_verified_email,
-                            last_login, created, updated
+                            id, name, email, username, password, profile, plan,
+                            is_active, is_admin, has_verified_email, email_deliverable,
+                            last_login, created, updated, version
                     ",
                         form.name.value,
                         account_id
@@ -349,14 +1090,10 @@ impl Account {
                     .fetch_one(&mut tx)
                     .await?;
 
+                    Account::update_grant(form, &refresh_token, &scopes, &mut tx).await?;
                     tx.commit().await?;
 
-                    Ok(User {
-                        id: user.id,
-                        name: user.name,
-                        is_admin: user.is_admin,
-                        is_anonymous: false,
-                    })
+                    User::load(pool, user.id).await
                 } else {
                     Err(Error::Generic(
                         "The provider account is linked to a different account".to_string(),
@@ -373,9 +1110,9 @@ impl Account {
                     SET last_login = now()
                     WHERE id = $1
                     RETURNING
-                        id, name, email, password, profile, plan,
-                        is_active, is_admin, has_verified_email,
-                        last_login, created, updated
+                        id, name, email, username, password, profile, plan,
+                        is_active, is_admin, has_verified_email, email_deliverable,
+                        last_login, created, updated, version
                 ",
                     account_id
                 )
@@ -384,8 +1121,8 @@ impl Account {
 
                 let _identity_id = sqlx::query!(
                     "
-                    INSERT INTO identities (account_id, provider, username, name, refresh_token)
-                    VALUES ($1, $2, $3, $4, $5)
+                    INSERT INTO identities (account_id, provider, username, name, refresh_token, scopes)
+                    VALUES ($1, $2, $3, $4, $5, $6)
                     RETURNING id
                 ",
                     account_id,
@@ -393,6 +1130,7 @@ impl Account {
                     form.username,
                     form.name.value,
                     refresh_token,
+                    &scopes,
                 )
                 .fetch_one(&mut tx)
                 .await?
@@ -400,12 +1138,7 @@ impl Account {
 
                 tx.commit().await?;
 
-                Ok(User {
-                    id: user.id,
-                    name: user.name,
-                    is_admin: user.is_admin,
-                    is_anonymous: false,
-                })
+                User::load(pool, user.id).await
             }
         }
     }
@@ -501,6 +1234,8 @@ pub struct Identity {
     pub username: String,
     pub name: Option<String>,
     pub refresh_token: Option<String>,
+    /// What the provider's granted access to, so far.
+    pub scopes: Vec<String>,
     pub created: DateTime<Utc>,
     pub updated: DateTime<Utc>,
 }
@@ -512,7 +1247,7 @@ impl Identity {
             "
             SELECT
                 id, account_id, provider, username, name,
-                refresh_token, created, updated
+                refresh_token, scopes, created, updated
             FROM identities WHERE id = $1
         ",
             id
@@ -531,7 +1266,7 @@ impl Identity {
             "
             SELECT
                 id, account_id, provider, username, name,
-                refresh_token, created, updated
+                refresh_token, scopes, created, updated
             FROM identities
             WHERE provider = $1 AND username = $2
         ",
@@ -548,7 +1283,7 @@ impl Identity {
             "
             SELECT
                 id, account_id, provider, username, name,
-                refresh_token, created, updated
+                refresh_token, scopes, created, updated
             FROM identities WHERE account_id = $1
         ",
             account_id
@@ -556,4 +1291,122 @@ impl Identity {
         .fetch_all(pool)
         .await?)
     }
+
+    /// Forgets the identity's refresh token, once it's been revoked.
+    pub async fn clear_refresh_token(id: i32, pool: &PgPool) -> Result<(), Error> {
+        sqlx::query!("UPDATE identities SET refresh_token = NULL, updated = now() WHERE id = $1", id)
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Unlinks the account's identity with `provider`, unless it's the
+    /// account's only way in: it has no password (or passwords are off),
+    /// and no identity with another provider. The account's row is locked
+    /// meanwhile, so two unlinks at once can't each leave the other's
+    /// identity as the last.
+    pub async fn delete_for_account(provider: &str, account_id: i32, pool: &PgPool) -> Result<Unlink, Error> {
+        let mut tx = pool.begin().await?;
+
+        let has_password = sqlx::query!("SELECT password FROM accounts WHERE id = $1 FOR UPDATE", account_id)
+            .fetch_one(&mut tx)
+            .await?
+            .password
+            .is_some();
+        let has_password = has_password && !crate::accounts::oauth_only(pool).await?;
+
+        let providers: Vec<String> = sqlx::query!("SELECT provider FROM identities WHERE account_id = $1", account_id)
+            .fetch_all(&mut tx)
+            .await?
+            .into_iter()
+            .map(|row| row.provider)
+            .collect();
+        if !providers.iter().any(|linked| linked == provider) {
+            return Ok(Unlink::NotLinked);
+        }
+        let others = providers.iter().filter(|linked| *linked != provider).count();
+        if !has_password && others == 0 {
+            return Ok(Unlink::LastLoginMethod);
+        }
+
+        sqlx::query!(
+            "DELETE FROM identities WHERE provider = $1 AND account_id = $2",
+            provider,
+            account_id
+        )
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(Unlink::Removed)
+    }
+}
+
+/// What came of unlinking an identity.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Unlink {
+    Removed,
+    NotLinked,
+    /// Refused, as the account would have no way to sign in.
+    LastLoginMethod,
+}
+
+/// A signed in session (see `jelly::accounts::sessions`).
+#[derive(Debug, Serialize)]
+pub struct UserSession {
+    pub id: String,
+    pub account_id: i32,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub created: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+}
+
+impl UserSession {
+    /// The tenant's sessions that haven't been revoked, most recent first.
+    pub async fn for_account(db: &TenantPool<'_>) -> Result<Vec<Self>, Error> {
+        Ok(jelly::tenant_query_as!(
+            db,
+            UserSession,
+            "
+            SELECT id, account_id, user_agent, ip, created, last_seen
+            FROM user_sessions
+            WHERE account_id = $1 AND revoked_at IS NULL
+            ORDER BY last_seen DESC
+        "
+        )
+        .fetch_all(db.pool())
+        .await?)
+    }
+
+    /// Revokes one of the tenant's sessions; it's signed out the next
+    /// time it's used.
+    pub async fn revoke(db: &TenantPool<'_>, id: &str) -> Result<(), Error> {
+        jelly::tenant_query!(
+            db,
+            "
+            UPDATE user_sessions SET revoked_at = now()
+            WHERE account_id = $1 AND id = $2 AND revoked_at IS NULL
+        ",
+            id
+        )
+        .execute(db.pool())
+        .await?;
+
+        Ok(())
+    }
+
+    /// Revokes all of the tenant's sessions, returning how many there were.
+    pub async fn revoke_all(db: &TenantPool<'_>) -> Result<u64, Error> {
+        Ok(jelly::tenant_query!(
+            db,
+            "
+            UPDATE user_sessions SET revoked_at = now()
+            WHERE account_id = $1 AND revoked_at IS NULL
+        "
+        )
+        .execute(db.pool())
+        .await?
+        .rows_affected())
+    }
 }
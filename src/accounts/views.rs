@@ -1,8 +1,16 @@
 //!  Views for user auth.
 
 use jelly::prelude::*;
-use jelly::Result;
+use jelly::{Result, SESSION_ID};
 
+use crate::accounts::models::Identity;
+use crate::accounts::UserSession;
+use crate::oauth;
+
+pub mod change_email;
+pub mod change_password;
+pub mod deactivate;
+pub mod delete;
 pub mod login;
 pub mod register;
 pub mod reset_password;
@@ -10,6 +18,17 @@ pub mod utils;
 pub mod verify;
 
 pub async fn logout(request: HttpRequest) -> Result<HttpResponse> {
+    if let Some(id) = request.get_session().get::<String>(SESSION_ID)? {
+        UserSession::revoke(&request.tenant_pool()?, &id).await?;
+    }
+
+    let user = request.user()?;
+    let db = request.db_pool()?;
+    if !user.is_anonymous && oauth::revoke_on_logout(db).await? {
+        for identity in Identity::linked_to_account_id(user.id, db).await? {
+            oauth::revoke(&identity, db).await?;
+        }
+    }
     request.get_session().clear();
     request.redirect("/")
 }
@@ -0,0 +1,116 @@
+use jelly::actix_web::{web, HttpRequest};
+use jelly::accounts::OneTimeUseTokenGenerator;
+use jelly::forms::validation::{Validatable, ValidationError, ValidationErrors};
+use jelly::prelude::*;
+use jelly::serde_json::json;
+use jelly::Result;
+
+use crate::accounts::forms::ChangeEmailForm;
+use crate::accounts::jobs::{SendConfirmEmailChangeEmail, SendEmailWasChangedEmail};
+use crate::accounts::{uses_password, Account, TokenInfo};
+use crate::audit::AuditEvent;
+
+fn render_form(
+    request: &HttpRequest,
+    status: usize,
+    form: &ChangeEmailForm,
+    account: &Account,
+    has_password: bool,
+    errors: Option<ValidationErrors<String>>,
+) -> Result<HttpResponse> {
+    request.render(status, "accounts/change_email/index.html", {
+        let mut context = Context::new();
+        if let Some(errors) = errors {
+            context.insert("errors", &errors);
+        }
+        context.insert("form", form);
+        context.insert("email", &account.email);
+        context.insert("has_password", &has_password);
+        context
+    })
+}
+
+/// The change email form, for the signed in account.
+pub async fn form(request: HttpRequest) -> Result<HttpResponse> {
+    if !request.is_authenticated()? {
+        return request.redirect("/accounts/login");
+    }
+
+    let db = request.db_pool()?;
+    let account = Account::get(request.user()?.id, db).await?;
+    let has_password = uses_password(&account, db).await?;
+    render_form(&request, 200, &ChangeEmailForm::default(), &account, has_password, None)
+}
+
+/// Records the new address as pending and sends it a confirmation link;
+/// nothing changes until that's followed. Accounts with a password have to
+/// give it first.
+pub async fn request_change(
+    request: HttpRequest,
+    form: web::Form<ChangeEmailForm>,
+) -> Result<HttpResponse> {
+    if !request.is_authenticated()? {
+        return request.redirect("/accounts/login");
+    }
+
+    let db = request.db_pool()?;
+    let account = Account::get(request.user()?.id, db).await?;
+    let has_password = uses_password(&account, db).await?;
+    let form = form.into_inner().set_keys();
+    if let Err(errors) = form.validate() {
+        return render_form(&request, 400, &form, &account, has_password, Some(errors));
+    }
+
+    if has_password && !Account::check_password(account.id, &form.password, db).await? {
+        let errors: ValidationErrors<String> = ValidationError::new("password".to_owned(), "INVALID_PASSWORD")
+            .with_message(move |_| "password is incorrect".to_owned())
+            .into();
+        return render_form(&request, 400, &form, &account, has_password, Some(errors));
+    }
+
+    // Changing to an address that's already taken fails at confirmation;
+    // saying so here would reveal who has an account.
+    Account::request_email_change(account.id, &form.email.value, db).await?;
+    request.queue_job(SendConfirmEmailChangeEmail { to: account.id }).await?;
+
+    request.render(200, "accounts/change_email/requested.html", {
+        let mut context = Context::new();
+        context.insert("new_email", &form.email.value);
+        context
+    })
+}
+
+/// Given a link (of form {uidb64}-{ts}-{token}) from the new address,
+/// switches the account over to it and lets the old address know.
+pub async fn confirm(request: HttpRequest, path: web::Path<TokenInfo>) -> Result<HttpResponse> {
+    let db = request.db_pool()?;
+    let account_id = base64_url::decode(&path.uidb64)
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .and_then(|id| id.parse::<i32>().ok());
+    let change = match account_id {
+        Some(id) => Account::email_change(id, db).await?,
+        None => None,
+    };
+
+    let token = format!("{}-{}", path.ts, path.token);
+    let change = match change {
+        Some(change) if change.is_token_valid(&token) => change,
+        _ => return request.render(200, "accounts/invalid_token.html", Context::new()),
+    };
+
+    if Account::confirm_email_change(&change, db).await.is_err() {
+        request.flash("Email Change", "That address can't be used. Please try another.")?;
+        return request.redirect("/accounts/email");
+    }
+
+    let data = json!({ "old_email": change.email, "new_email": change.new_email });
+    AuditEvent::record_request(&request, change.account_id, "email.changed", data).await?;
+    request.queue_job(SendEmailWasChangedEmail {
+        to: change.email.clone(),
+        new_email: change.new_email.clone(),
+    }).await?;
+
+    request.flash("Email Change", "Your email address was changed.")?;
+    request.redirect("/dashboard")
+}
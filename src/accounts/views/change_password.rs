@@ -0,0 +1,98 @@
+use jelly::accounts::jwt;
+use jelly::actix_web::{web, HttpRequest};
+use jelly::forms::validation::{Validatable, ValidationError, ValidationErrors};
+use jelly::prelude::*;
+use jelly::serde_json::json;
+use jelly::Result;
+
+use crate::accounts::forms::ChangePasswordForm;
+use crate::accounts::{oauth_only, Account};
+use crate::audit::AuditEvent;
+
+fn render_form(
+    request: &HttpRequest,
+    status: usize,
+    form: &ChangePasswordForm,
+    account: &Account,
+    errors: Option<ValidationErrors<String>>,
+) -> Result<HttpResponse> {
+    request.render(status, "accounts/change_password.html", {
+        let mut context = Context::new();
+        if let Some(errors) = errors {
+            context.insert("errors", &errors);
+        }
+        context.insert("form", form);
+        context.insert("has_password", &account.password.is_some());
+        context.insert("expired", &request.password_expired()?);
+        context
+    })
+}
+
+/// Without passwords there's nothing to change. Sessions flagged with an
+/// expired password from before they were turned off are let go, and so
+/// are accounts flagged to reset it, so they aren't sent back here forever.
+async fn not_found(request: &HttpRequest) -> Result<HttpResponse> {
+    request.set_password_expired(false)?;
+    Account::clear_password_reset(request.user()?.id, request.db_pool()?).await?;
+    request.render(404, "404.html", Context::new())
+}
+
+/// The change password form, for the signed in account. This is also
+/// where accounts with an expired password are sent.
+pub async fn form(request: HttpRequest) -> Result<HttpResponse> {
+    if !request.is_authenticated()? {
+        return request.redirect("/accounts/login");
+    }
+    if oauth_only(request.db_pool()?).await? {
+        return not_found(&request).await;
+    }
+
+    let account = Account::get(request.user()?.id, request.db_pool()?).await?;
+    render_form(&request, 200, &ChangePasswordForm::default(), &account, None)
+}
+
+/// Sets the new password, once the current one (if the account has one)
+/// checks out. The new one can't be the same as the old.
+pub async fn change(
+    request: HttpRequest,
+    form: web::Form<ChangePasswordForm>,
+) -> Result<HttpResponse> {
+    if !request.is_authenticated()? {
+        return request.redirect("/accounts/login");
+    }
+
+    let db = request.db_pool()?;
+    if oauth_only(db).await? {
+        return not_found(&request).await;
+    }
+    let account = Account::get(request.user()?.id, db).await?;
+    let form = form
+        .into_inner()
+        .set_keys()
+        .set_name_and_email(&account.name, &account.email);
+
+    if account.password.is_some() && !Account::check_password(account.id, &form.current_password, db).await? {
+        let errors: ValidationErrors<String> = ValidationError::new("current_password".to_owned(), "INVALID_PASSWORD")
+            .with_message(move |_| "password is incorrect".to_owned())
+            .into();
+        return render_form(&request, 400, &form, &account, Some(errors));
+    }
+    if let Err(errors) = form.validate() {
+        return render_form(&request, 400, &form, &account, Some(errors));
+    }
+    if form.password.value == form.current_password.value {
+        let errors: ValidationErrors<String> = ValidationError::new("password".to_owned(), "PASSWORD_UNCHANGED")
+            .with_message(move |_| "choose a password you haven't used here".to_owned())
+            .into();
+        return render_form(&request, 400, &form, &account, Some(errors));
+    }
+
+    Account::update_password(account.id, &form.password, db).await?;
+    jwt::revoke_all(db, account.id).await?;
+    AuditEvent::record_request(&request, account.id, "password.changed", json!({})).await?;
+
+    request.set_password_expired(false)?;
+    request.flash("Password Changed", "Your password was changed.")?;
+    request.redirect("/dashboard")
+}
+
@@ -0,0 +1,71 @@
+use jelly::actix_web::{web, HttpRequest};
+use jelly::forms::validation::ValidationErrors;
+use jelly::prelude::*;
+use jelly::serde_json::json;
+use jelly::Result;
+
+use crate::accounts::forms::DeleteAccountForm;
+use crate::accounts::views::utils::confirm_account;
+use crate::accounts::{uses_password, Account};
+use crate::audit::AuditEvent;
+use crate::retention::{self, DELETED_ACCOUNTS};
+
+async fn render_form(
+    request: &HttpRequest,
+    status: usize,
+    form: &DeleteAccountForm,
+    has_password: bool,
+    errors: Option<ValidationErrors<String>>,
+) -> Result<HttpResponse> {
+    // The retention policy can be changed by admins, so it's asked for.
+    let grace_days = retention::days(request.db_pool()?, DELETED_ACCOUNTS).await?;
+    request.render(status, "accounts/delete.html", {
+        let mut context = Context::new();
+        if let Some(errors) = errors {
+            context.insert("errors", &errors);
+        }
+        context.insert("form", form);
+        context.insert("has_password", &has_password);
+        context.insert("grace_days", &grace_days);
+        context
+    })
+}
+
+/// Asks the signed in account to confirm it wants to be deleted.
+pub async fn form(request: HttpRequest) -> Result<HttpResponse> {
+    if !request.is_authenticated()? {
+        return request.redirect("/accounts/login");
+    }
+
+    let db = request.db_pool()?;
+    let account = Account::get(request.user()?.id, db).await?;
+    let has_password = uses_password(&account, db).await?;
+    render_form(&request, 200, &DeleteAccountForm::default(), has_password, None).await
+}
+
+/// Marks the account deleted and signs it out. Accounts with a password
+/// have to give it first; others confirm with their email address.
+pub async fn delete(
+    request: HttpRequest,
+    form: web::Form<DeleteAccountForm>,
+) -> Result<HttpResponse> {
+    if !request.is_authenticated()? {
+        return request.redirect("/accounts/login");
+    }
+
+    let db = request.db_pool()?;
+    let account = Account::get(request.user()?.id, db).await?;
+    let form = form.into_inner().set_keys();
+
+    let has_password = uses_password(&account, db).await?;
+    if let Some(errors) = confirm_account(&request, &account, &form, has_password).await? {
+        return render_form(&request, 400, &form, has_password, Some(errors)).await;
+    }
+
+    Account::soft_delete(account.id, db).await?;
+    AuditEvent::record_request(&request, account.id, "account.deleted", json!({})).await?;
+
+    request.get_session().clear();
+    request.flash("Account Deleted", "Your account has been deleted.")?;
+    request.redirect("/")
+}
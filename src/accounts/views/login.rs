@@ -1,11 +1,101 @@
+use jelly::accounts::{jwt, password, service, sessions, AuthMode};
+use jelly::actix_web::http::header::USER_AGENT;
 use jelly::actix_web::{web, HttpRequest};
 use jelly::forms::validation::{Validatable, ValidationError, ValidationErrors};
 use jelly::prelude::*;
+use jelly::ratelimit::{self, Limit};
 use jelly::request::{Authentication, DatabasePool};
+use jelly::serde_json::json;
 use jelly::Result;
 
-use crate::accounts::forms::LoginForm;
-use crate::accounts::Account;
+use crate::accounts::forms::{ClientCredentialsForm, LoginForm, RefreshTokenForm};
+use crate::accounts::jobs::SendVerifyAccountEmail;
+use crate::accounts::{email_domains, oauth_only, Account};
+use crate::audit::jobs::AnalyzeLogin;
+use crate::audit::AuditEvent;
+use crate::oauth::enabled_providers;
+
+/// Login attempts allowed per account (by the email or username tried)
+/// and per IP, within `LOGIN_ATTEMPT_WINDOW_MINUTES`. Attempts are counted
+/// as they're made; signing in clears the account's count.
+pub(crate) fn limits() -> (Limit, Limit) {
+    (
+        Limit::from_env("LOGIN_MAX_ATTEMPTS_PER_ACCOUNT", 5, "LOGIN_ATTEMPT_WINDOW_MINUTES", 15),
+        Limit::from_env("LOGIN_MAX_ATTEMPTS_PER_IP", 20, "LOGIN_ATTEMPT_WINDOW_MINUTES", 15),
+    )
+}
+
+/// The rate limit keys for an attempt: attempts count against whatever
+/// was typed, so unknown logins are throttled just the same and don't give
+/// away who has an account.
+fn limit_keys(request: &HttpRequest, form: &LoginForm) -> (String, String) {
+    (
+        format!("login:account:{}", form.login.value.to_lowercase()),
+        format!("login:ip:{}", ratelimit::client_ip(request)),
+    )
+}
+
+/// Counts an attempt against both keys, and says whether it's within both
+/// limits.
+async fn attempt(keys: &(String, String), limits: &(Limit, Limit)) -> Result<bool> {
+    let account = ratelimit::attempt(&keys.0, &limits.0).await?;
+    let ip = ratelimit::attempt(&keys.1, &limits.1).await?;
+    Ok(account && ip)
+}
+
+/// The rate limit keys for a service account's attempt: by client id,
+/// under the same limits as logins, and by IP alongside them.
+fn client_limit_keys(request: &HttpRequest, form: &ClientCredentialsForm) -> (String, String) {
+    (
+        format!("login:client:{}", form.client_id),
+        format!("login:ip:{}", ratelimit::client_ip(request)),
+    )
+}
+
+/// What the client calls itself, to name its API token with.
+fn client_name(request: &HttpRequest) -> Option<&str> {
+    request.headers().get(USER_AGENT).and_then(|ua| ua.to_str().ok())
+}
+
+/// The login page's context: the form, unless accounts sign in with OAuth
+/// only, and the providers offered alongside it.
+async fn login_context(request: &HttpRequest, form: &LoginForm) -> Result<Context> {
+    let db = request.db_pool()?;
+    let mut context = Context::new();
+    context.insert("form", form);
+    context.insert("password_login", &!oauth_only(db).await?);
+    context.insert("oauth_providers", &enabled_providers(db).await?);
+    Ok(context)
+}
+
+async fn render_error(
+    request: &HttpRequest,
+    status: usize,
+    form: &LoginForm,
+    code: &'static str,
+    message: &'static str,
+) -> Result<HttpResponse> {
+    let errors: ValidationErrors<String> = ValidationError::new("form".to_owned(), code)
+        .with_message(move |_| message.to_owned())
+        .into();
+    let mut context = login_context(request, form).await?;
+    // ValidationErrors object is serialized into HashMap here
+    context.insert("errors", &errors);
+    request.render(status, "accounts/login.html", context)
+}
+
+/// Whether `account_id` has to verify its email before signing in: only
+/// while sign ups are limited to some domains, when the address is what
+/// gets someone in, so it has to be shown to be theirs. The verification
+/// email's sent again.
+async fn needs_verification(request: &HttpRequest, account_id: i32) -> Result<bool> {
+    let db = request.db_pool()?;
+    if email_domains(db).await?.allowed.is_empty() || Account::get(account_id, db).await?.has_verified_email {
+        return Ok(false);
+    }
+    request.queue_job(SendVerifyAccountEmail { to: account_id }).await?;
+    Ok(true)
+}
 
 /// The login form.
 pub async fn form(request: HttpRequest) -> Result<HttpResponse> {
@@ -13,11 +103,8 @@ pub async fn form(request: HttpRequest) -> Result<HttpResponse> {
         return request.redirect("/dashboard");
     }
 
-    request.render(200, "accounts/login.html", {
-        let mut ctx = Context::new();
-        ctx.insert("form", &LoginForm::default());
-        ctx
-    })
+    let context = login_context(&request, &LoginForm::default()).await?;
+    request.render(200, "accounts/login.html", context)
 }
 
 /// POST-handler for logging in.
@@ -28,35 +115,216 @@ pub async fn authenticate(
     if request.is_authenticated()? {
         return request.redirect("/dashboard");
     }
+    if oauth_only(request.db_pool()?).await? {
+        return request.render(404, "404.html", Context::new());
+    }
     let form = form.into_inner().set_keys();
     if let Err(errors) = form.validate() {
-        return request.render(400, "accounts/login.html", {
-            let mut context = Context::new();
-
-            // ValidationErrors object is serialized into HashMap here
-            context.insert("errors", &errors);
-            context.insert("form", &form);
-            context
-        });
+        let mut context = login_context(&request, &form).await?;
+        // ValidationErrors object is serialized into HashMap here
+        context.insert("errors", &errors);
+        return request.render(400, "accounts/login.html", context);
     }
 
+    let keys = limit_keys(&request, &form);
+
     let db = request.db_pool()?;
-    if let Ok(user) = Account::authenticate(&form, db).await {
+    if !attempt(&keys, &limits()).await? {
+        if let Ok(id) = Account::id_by_login(&form.login.value, db).await {
+            AuditEvent::record_request(&request, id, "login.throttled", json!({})).await?;
+        }
+        return render_error(
+            &request,
+            429,
+            &form,
+            "TOO_MANY_ATTEMPTS",
+            "Too many attempts; please try again later.",
+        )
+        .await;
+    }
+
+    let authenticated = Account::authenticate(&form, db).await;
+    if let Ok((user, password_expired)) = authenticated {
+        ratelimit::clear(&keys.0).await?;
+        if needs_verification(&request, user.id).await? {
+            return render_error(
+                &request,
+                403,
+                &form,
+                "EMAIL_NOT_VERIFIED",
+                "please verify your email address first; we've sent the link again",
+            )
+            .await;
+        }
         Account::update_last_login(user.id, db).await?;
-        request.set_user(user)?;
+        let event_id = AuditEvent::record_request(&request, user.id, "login", json!({})).await?;
+        request.queue_job(AnalyzeLogin { event_id }).await?;
+        let timezone = Account::get(user.id, db).await?.profile.timezone.clone();
+        sessions::sign_in(&request, user).await?;
+        request.set_timezone(&timezone)?;
+        if form.remember.value {
+            request.remember()?;
+        }
+        if password_expired {
+            request.set_password_expired(true)?;
+            request.flash("Password Expired", "Your password has expired; please choose a new one.")?;
+            return request.redirect(password::CHANGE_PATH);
+        }
         return request.redirect("/dashboard");
     }
+    if let Err(Error::AccountInactive) = authenticated {
+        ratelimit::clear(&keys.0).await?;
+        return render_error(&request, 403, &form, "ACCOUNT_INACTIVE", "this account has been deactivated").await;
+    }
 
-    // Create a ValidationErrors object
-    let errors: ValidationErrors<String> = ValidationError::new("form".to_owned(), "INVALID_CREDENTIALS")
-        .with_message(move |_| "password is incorrect".to_owned())
-        .into();
-    request.render(400, "accounts/login.html", {
-        let mut context = Context::new();
+    if let Ok(id) = Account::id_by_login(&form.login.value, db).await {
+        AuditEvent::record_request(&request, id, "login.failed", json!({})).await?;
+    }
 
-        // ValidationErrors object is serialized into HashMap here
-        context.insert("errors", &errors);
-        context.insert("form", &form);
-        context
-    })
+    render_error(&request, 400, &form, "INVALID_CREDENTIALS", "password is incorrect").await
+}
+
+/// POST-handler issuing a bearer token and refresh token (see
+/// `jelly::accounts::jwt`) for API clients, if the server accepts them.
+/// Takes the login form's fields as JSON, and is rate limited the same way.
+/// Without passwords, clients swap a web session for tokens instead.
+pub async fn token(request: HttpRequest, form: web::Json<LoginForm>) -> Result<HttpResponse> {
+    if !AuthMode::of(&request).tokens() || oauth_only(request.db_pool()?).await? {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    let form = form.into_inner().set_keys();
+    if let Err(errors) = form.validate() {
+        return request.json(400, &errors);
+    }
+
+    let keys = limit_keys(&request, &form);
+    if !attempt(&keys, &limits()).await? {
+        return request.json(429, json!({ "error": "Too many attempts; please try again later." }));
+    }
+
+    let db = request.db_pool()?;
+    let authenticated = Account::authenticate(&form, db).await;
+    if let Ok((user, password_expired)) = authenticated {
+        ratelimit::clear(&keys.0).await?;
+        if password_expired {
+            return request.json(403, json!({ "error": "Password expired; sign in on the web to change it." }));
+        }
+        if needs_verification(&request, user.id).await? {
+            return request.json(403, json!({ "error": "Email not verified; we've sent the link again." }));
+        }
+        Account::update_last_login(user.id, db).await?;
+        AuditEvent::record_request(&request, user.id, "login.token", json!({})).await?;
+
+        return request.json(200, jwt::grant(db, &user, client_name(&request)).await?);
+    }
+    if let Err(Error::AccountInactive) = authenticated {
+        ratelimit::clear(&keys.0).await?;
+        return request.json(403, json!({ "error": "Account deactivated." }));
+    }
+
+    if let Ok(id) = Account::id_by_login(&form.login.value, db).await {
+        AuditEvent::record_request(&request, id, "login.failed", json!({})).await?;
+    }
+
+    request.json(401, json!({ "error": "Invalid login or password." }))
+}
+
+/// Swaps the signed in session for API tokens, so a first-party app that
+/// signed in on the web (e.g in a web view) can carry on without cookies.
+/// Like any other post from a page, it needs the `X-CSRF-Token` header.
+pub async fn session_token(request: HttpRequest) -> Result<HttpResponse> {
+    if !AuthMode::of(&request).tokens() {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    let user = request.user()?;
+    if user.is_anonymous {
+        return request.json(401, json!({ "error": "Not signed in." }));
+    }
+    if request.password_expired()? {
+        return request.json(403, json!({ "error": "Password expired; sign in on the web to change it." }));
+    }
+
+    let db = request.db_pool()?;
+    AuditEvent::record_request(&request, user.id, "login.token", json!({ "grant": "session" })).await?;
+    request.json(200, jwt::grant(db, &user, client_name(&request)).await?)
+}
+
+/// Swaps a refresh token for new tokens. Refresh tokens only work once,
+/// so clients must keep the new one.
+pub async fn refresh_token(request: HttpRequest, form: web::Json<RefreshTokenForm>) -> Result<HttpResponse> {
+    if !AuthMode::of(&request).tokens() {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+
+    let db = request.db_pool()?;
+    match jwt::redeem(db, &form.refresh_token).await? {
+        Some(grant) => request.json(200, grant),
+        None => request.json(401, json!({ "error": "Invalid or expired refresh token." })),
+    }
+}
+
+/// Swaps a service account's credentials for an access token limited to
+/// its scopes (or those of them `scope` asks for), much like an OAuth2
+/// client credentials grant. There's no refresh token; it just asks again.
+pub async fn client_token(request: HttpRequest, form: web::Json<ClientCredentialsForm>) -> Result<HttpResponse> {
+    if !AuthMode::of(&request).tokens() {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    let service_account = match authenticate_client(&request, &form).await? {
+        Ok(service_account) => service_account,
+        Err(response) => return Ok(response),
+    };
+
+    let scopes = service_account.scopes_for(form.scope.as_deref());
+    request.json(200, service_account.grant(scopes)?)
+}
+
+/// Gives a service account a new secret, given its current one, so
+/// integrations can rotate their own. The old secret keeps working for a
+/// while (see `jelly::accounts::service`).
+pub async fn rotate_client_secret(request: HttpRequest, form: web::Json<ClientCredentialsForm>) -> Result<HttpResponse> {
+    if !AuthMode::of(&request).tokens() {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    let service_account = match authenticate_client(&request, &form).await? {
+        Ok(service_account) => service_account,
+        Err(response) => return Ok(response),
+    };
+
+    let db = request.db_pool()?;
+    match service::rotate(db, service_account.account_id, service_account.id).await? {
+        Some(credentials) => {
+            AuditEvent::record_request(
+                &request,
+                service_account.account_id,
+                "service_account.rotated",
+                json!({ "service_account_id": service_account.id, "by": "client" }),
+            )
+            .await?;
+            request.json(200, credentials)
+        }
+        None => request.json(401, json!({ "error": "Invalid client credentials." })),
+    }
+}
+
+/// The service account with the form's credentials, or the response to
+/// turn the request away with.
+async fn authenticate_client(
+    request: &HttpRequest,
+    form: &ClientCredentialsForm,
+) -> Result<std::result::Result<service::ServiceAccount, HttpResponse>> {
+    let keys = client_limit_keys(request, form);
+    if !attempt(&keys, &limits()).await? {
+        let response = request.json(429, json!({ "error": "Too many attempts; please try again later." }))?;
+        return Ok(Err(response));
+    }
+
+    let db = request.db_pool()?;
+    match service::authenticate(db, &form.client_id, &form.client_secret).await? {
+        Some(service_account) => {
+            ratelimit::clear(&keys.0).await?;
+            Ok(Ok(service_account))
+        }
+        None => Ok(Err(request.json(401, json!({ "error": "Invalid client credentials." }))?)),
+    }
 }
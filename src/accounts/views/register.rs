@@ -1,21 +1,65 @@
 use jelly::actix_web::{web, HttpRequest};
-use jelly::forms::validation::{Validatable};
+use jelly::forms::EmailField;
+use jelly::forms::validation::{Validatable, ValidationError, ValidationErrors};
 use jelly::prelude::*;
 use jelly::request::{Authentication, DatabasePool};
 use jelly::Result;
+use serde::Deserialize;
 
 use crate::accounts::forms::NewAccountForm;
 use crate::accounts::jobs::{SendAccountOddRegisterAttemptEmail, SendVerifyAccountEmail};
-use crate::accounts::Account;
+use crate::accounts::{email_domains, oauth_only, Account};
+use crate::invitations::{self, invite_only, Invitation, SESSION_INVITATION_TOKEN};
+use crate::referrals::{ReferralCode, SESSION_REFERRAL_CODE};
+use crate::waitlist::{self, waitlist_enabled, WaitlistEntry, SESSION_INVITE_TOKEN};
 
-pub async fn form(request: HttpRequest) -> Result<HttpResponse> {
+#[derive(Deserialize)]
+pub struct RegisterQuery {
+    #[serde(rename = "ref")]
+    pub referral_code: Option<String>,
+    pub invite: Option<String>,
+    pub invitation: Option<String>,
+}
+
+/// The sign up form. A `?ref=<code>` is kept in the session, so the new
+/// account can be attributed to its referrer however long signing up takes,
+/// and likewise a waitlist `?invite=<token>` and an `?invitation=<token>`.
+/// With the waitlist on, people without a valid invite get the waitlist form
+/// instead; when invite-only, those without an invitation are turned away.
+/// When accounts sign in with OAuth only, there's no form: signing in with
+/// a provider signs up.
+pub async fn form(request: HttpRequest, query: web::Query<RegisterQuery>) -> Result<HttpResponse> {
     if request.is_authenticated()? {
         return request.redirect("/dashboard");
     }
+    if oauth_only(request.db_pool()?).await? {
+        return request.render(404, "404.html", Context::new());
+    }
+
+    if let Some(code) = &query.referral_code {
+        request.get_session().insert(SESSION_REFERRAL_CODE, code)?;
+    }
+    if let Some(token) = &query.invite {
+        request.get_session().insert(SESSION_INVITE_TOKEN, token)?;
+    }
+    if let Some(token) = &query.invitation {
+        request.get_session().insert(SESSION_INVITATION_TOKEN, token)?;
+    }
+
+    let mut form = NewAccountForm::default();
+    if waitlist_enabled() {
+        match waitlist::views::session_invite(&request).await? {
+            Some(entry) => form.email = EmailField::new(entry.email),
+            None => return waitlist::views::form(&request),
+        }
+    }
+    if invite_only() && invitations::views::session_invitation(&request).await?.is_none() {
+        return invitations::views::required(&request);
+    }
 
     request.render(200, "accounts/register.html", {
         let mut ctx = Context::new();
-        ctx.insert("form", &NewAccountForm::default());
+        ctx.insert("form", &form);
         ctx
     })
 }
@@ -27,8 +71,29 @@ pub async fn create_account(
     if request.is_authenticated()? {
         return request.redirect("/dashboard");
     }
+    if oauth_only(request.db_pool()?).await? {
+        return request.render(404, "404.html", Context::new());
+    }
+    let invite = if waitlist_enabled() {
+        match waitlist::views::session_invite(&request).await? {
+            Some(entry) => entry.invite_token,
+            None => return waitlist::views::form(&request),
+        }
+    } else {
+        None
+    };
+    let invitation = if invite_only() {
+        match invitations::views::session_invitation(&request).await? {
+            Some(invitation) => Some(invitation.token),
+            None => return invitations::views::required(&request),
+        }
+    } else {
+        None
+    };
+
     // Will use default password policy
-    let form = form.into_inner().set_keys();
+    let mut form = form.into_inner().set_keys();
+    form.domains = email_domains(request.db_pool()?).await?;
     if let Err(errors) = form.validate() {
         return request.render(400, "accounts/register.html", {
             let mut context = Context::new();
@@ -40,21 +105,58 @@ pub async fn create_account(
         });
     }
 
+    let db = request.db_pool()?;
+
     // Catch this error
     // if duplicate:
     //  - send email to existing user asking if they were trying to sign in
     //  - pass requesting user through normal "fake" flow to avoid leaking if
     //      an account exists?
-    let queue = request.job_queue()?;
-    let db = request.db_pool()?;
-    match Account::register(&form, db).await {
+    let registered = match &invitation {
+        // The invitation's used up in the same transaction, so it can't
+        // let two people in.
+        Some(token) => match Invitation::register(&form, token, db).await {
+            Ok(Some(uid)) => Ok(uid),
+            Ok(None) => return invitations::views::required(&request),
+            Err(e) => Err(e),
+        },
+        None => Account::register(&form, db).await,
+    };
+
+    match registered {
         Ok(uid) => {
-            queue.queue(SendVerifyAccountEmail { to: uid }).await?;
+            let session = request.get_session();
+            if invitation.is_some() {
+                session.remove(SESSION_INVITATION_TOKEN);
+            }
+            if let Some(token) = invite {
+                WaitlistEntry::mark_registered(&token, db).await?;
+                session.remove(SESSION_INVITE_TOKEN);
+            }
+            if let Some(code) = session.get::<String>(SESSION_REFERRAL_CODE)? {
+                ReferralCode::attribute(uid, &code, db).await?;
+                session.remove(SESSION_REFERRAL_CODE);
+            }
+            request.queue_job(SendVerifyAccountEmail { to: uid }).await?;
+        }
+
+        // Usernames are public anyway, so a taken one can be said so; the
+        // unique index is what decides, as two sign ups can race for it.
+        Err(e) if Account::is_username_conflict(&e) => {
+            let errors: ValidationErrors<String> = ValidationError::new("username".to_owned(), "USERNAME_TAKEN")
+                .with_message(move |_| "username is already taken".to_owned())
+                .into();
+            return request.render(400, "accounts/register.html", {
+                let mut context = Context::new();
+                context.insert("errors", &errors);
+                context.insert("form", &form);
+                context
+            });
         }
 
         Err(e) => {
             error!("Error with registering: {:?}", e);
-            queue.queue(SendAccountOddRegisterAttemptEmail {
+            request.queue_job(SendAccountOddRegisterAttemptEmail {
                 to: form.email.value.clone(),
             }).await?;
         }
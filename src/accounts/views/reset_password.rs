@@ -1,20 +1,25 @@
-use jelly::accounts::User;
+use jelly::accounts::{jwt, sessions, User};
 use jelly::actix_web::{web, HttpRequest};
 use jelly::forms::validation::{Validatable};
 use jelly::prelude::*;
+use jelly::serde_json::json;
 use jelly::Result;
 
 use crate::accounts::forms::{ChangePasswordForm, EmailForm};
-use crate::accounts::jobs::{SendPasswordWasResetEmail, SendResetPasswordEmail};
+use crate::accounts::jobs::{SendAccountRecoveryEmail, SendPasswordWasResetEmail, SendResetPasswordEmail};
 use crate::accounts::views::utils::validate_token;
-use crate::accounts::{Account, TokenInfo};
+use crate::accounts::{oauth_only, Account, TokenInfo};
+use crate::audit::AuditEvent;
 
-/// Just renders a standard "Enter Your Email" password reset page.
+/// Just renders a standard "Enter Your Email" password reset page. When
+/// accounts sign in with OAuth only, it offers a sign in link instead.
 pub async fn form(request: HttpRequest) -> Result<HttpResponse> {
+    let oauth_only = oauth_only(request.db_pool()?).await?;
     request.render(200, "accounts/reset_password/index.html", {
         let mut context = Context::new();
         context.insert("form", &EmailForm::default());
         context.insert("sent", &false);
+        context.insert("oauth_only", &oauth_only);
         context
     })
 }
@@ -23,6 +28,7 @@ pub async fn form(request: HttpRequest) -> Result<HttpResponse> {
 /// it to a background worker to execute - we do this to avoid any timing
 /// attacks re: leaking user existence.
 pub async fn request_reset(request: HttpRequest, form: web::Form<EmailForm>) -> Result<HttpResponse> {
+    let oauth_only = oauth_only(request.db_pool()?).await?;
     let form = form.into_inner().set_keys();
     if let Err(errors) = form.validate() {
         return request.render(400, "accounts/reset_password/index.html", {
@@ -32,14 +38,17 @@ pub async fn request_reset(request: HttpRequest, form: web::Form<EmailForm>) ->
             context.insert("errors", &errors);
             context.insert("form", &form);
             context.insert("sent", &false);
+            context.insert("oauth_only", &oauth_only);
             context
         });
     }
 
-    let queue = request.job_queue()?;
-    queue.queue(SendResetPasswordEmail {
-        to: form.email.value.clone(),
-    }).await?;
+    let to = form.email.value.clone();
+    if oauth_only {
+        request.queue_job(SendAccountRecoveryEmail { to }).await?;
+    } else {
+        request.queue_job(SendResetPasswordEmail { to }).await?;
+    }
 
     request.render(200, "accounts/reset_password/requested.html", {
         let mut context = Context::new();
@@ -58,6 +67,10 @@ pub async fn with_token(
     request: HttpRequest,
     path: web::Path<TokenInfo>,
 ) -> Result<HttpResponse> {
+    if oauth_only(request.db_pool()?).await? {
+        return request.render(404, "404.html", Context::new());
+    }
+
     if let Ok(_account) = validate_token(&request, &path.uidb64, &path.ts, &path.token).await {
         request.render(200, "accounts/reset_password/change_password.html", {
             let mut context = Context::new();
@@ -79,6 +92,10 @@ pub async fn reset(
     path: web::Path<TokenInfo>,
     form: web::Form<ChangePasswordForm>,
 ) -> Result<HttpResponse> {
+    if oauth_only(request.db_pool()?).await? {
+        return request.render(404, "404.html", Context::new());
+    }
+
     match validate_token(&request, &path.uidb64, &path.ts, &path.token).await {
         Ok(account) => {
             // Note! This is a case where we need to fetch the user ahead of form validation.
@@ -101,18 +118,15 @@ pub async fn reset(
 
             let pool = request.db_pool()?;
             Account::update_password_and_last_login(account.id, &form.password, pool).await?;
+            jwt::revoke_all(pool, account.id).await?;
+            AuditEvent::record_request(&request, account.id, "password.reset", json!({})).await?;
 
-            let queue = request.job_queue()?;
-            queue.queue(SendPasswordWasResetEmail {
+            request.queue_job(SendPasswordWasResetEmail {
                 to: account.email.clone(),
             }).await?;
 
-            request.set_user(User {
-                id: account.id,
-                name: account.name,
-                is_admin: account.is_admin,
-                is_anonymous: false,
-            })?;
+            sessions::sign_in(&request, User::load(pool, account.id).await?).await?;
+            request.set_timezone(&account.profile.timezone)?;
 
             request.flash("Password Reset", "Your password was successfully reset.")?;
             request.redirect("/dashboard")
@@ -123,3 +137,50 @@ pub async fn reset(
         }
     }
 }
+
+/// Given a sign in link from the recovery email (of the same form as a
+/// reset link), asks them to confirm; signing in on a GET would let link
+/// scanners use it up.
+pub async fn recover_form(request: HttpRequest, path: web::Path<TokenInfo>) -> Result<HttpResponse> {
+    if !oauth_only(request.db_pool()?).await? {
+        return request.render(404, "404.html", Context::new());
+    }
+
+    if validate_token(&request, &path.uidb64, &path.ts, &path.token).await.is_err() {
+        return request.render(200, "accounts/invalid_token.html", Context::new());
+    }
+
+    request.render(200, "accounts/reset_password/recover.html", {
+        let mut context = Context::new();
+        context.insert("uidb64", &path.uidb64);
+        context.insert("ts", &path.ts);
+        context.insert("token", &path.token);
+        context
+    })
+}
+
+/// Signs the account in from a recovery link. Signing in updates the last
+/// login, which the token's made from, so the link only works once.
+pub async fn recover(request: HttpRequest, path: web::Path<TokenInfo>) -> Result<HttpResponse> {
+    let pool = request.db_pool()?;
+    if !oauth_only(pool).await? {
+        return request.render(404, "404.html", Context::new());
+    }
+
+    let account = match validate_token(&request, &path.uidb64, &path.ts, &path.token).await {
+        Ok(account) if account.is_active => account,
+        _ => {
+            request.flash("Sign In", "The link you used is invalid. Please request another one.")?;
+            return request.redirect("/");
+        }
+    };
+
+    Account::update_last_login(account.id, pool).await?;
+    AuditEvent::record_request(&request, account.id, "login.recovered", json!({})).await?;
+
+    sessions::sign_in(&request, User::load(pool, account.id).await?).await?;
+    request.set_timezone(&account.profile.timezone)?;
+
+    request.flash("Signed In", "Welcome back! You can sign in with a linked provider next time.")?;
+    request.redirect("/dashboard")
+}
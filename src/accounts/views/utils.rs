@@ -1,8 +1,10 @@
 use jelly::accounts::OneTimeUseTokenGenerator;
+use jelly::forms::validation::{ValidationError, ValidationErrors};
 use jelly::prelude::*;
 use jelly::request::DatabasePool;
 use jelly::Result;
 
+use crate::accounts::forms::DeleteAccountForm;
 use crate::accounts::Account;
 
 /// Decodes the pieces used in verify and reset-password URL structures,
@@ -37,3 +39,33 @@ pub async fn validate_token(
 
     Err(Error::InvalidAccountToken)
 }
+
+/// Checks the signed in account really means to delete or deactivate
+/// itself: with its password if it has one to use, otherwise by typing
+/// its email address.
+pub async fn confirm_account(
+    request: &HttpRequest,
+    account: &Account,
+    form: &DeleteAccountForm,
+    has_password: bool,
+) -> Result<Option<ValidationErrors<String>>> {
+    if has_password {
+        if Account::check_password(account.id, &form.password, request.db_pool()?).await? {
+            return Ok(None);
+        }
+        return Ok(Some(
+            ValidationError::new("password".to_owned(), "INVALID_PASSWORD")
+                .with_message(move |_| "password is incorrect".to_owned())
+                .into(),
+        ));
+    }
+
+    if form.email.value.trim().eq_ignore_ascii_case(&account.email) {
+        return Ok(None);
+    }
+    Ok(Some(
+        ValidationError::new("email".to_owned(), "EMAIL_MISMATCH")
+            .with_message(move |_| "email doesn't match your account".to_owned())
+            .into(),
+    ))
+}
@@ -1,11 +1,14 @@
-use jelly::accounts::User;
+use jelly::accounts::{sessions, User};
 use jelly::actix_web::{web::Path, HttpRequest};
 use jelly::prelude::*;
 use jelly::request::DatabasePool;
+use jelly::serde_json::json;
 use jelly::Result;
 
 use crate::accounts::views::utils::validate_token;
 use crate::accounts::{Account, TokenInfo};
+use crate::audit::AuditEvent;
+use crate::referrals::jobs::CreditReferrer;
 
 /// Just renders a standard "Check your email and verify" page.
 pub async fn verify(request: HttpRequest) -> Result<HttpResponse> {
@@ -24,13 +27,11 @@ pub async fn with_token(
     if let Ok(account) = validate_token(&request, &path.uidb64, &path.ts, &path.token).await {
         let db = request.db_pool()?;
         Account::mark_verified(account.id, db).await?;
+        AuditEvent::record_request(&request, account.id, "email.verified", json!({})).await?;
+        request.queue_job(CreditReferrer { account_id: account.id }).await?;
 
-        request.set_user(User {
-            id: account.id,
-            name: account.name,
-            is_admin: account.is_admin,
-            is_anonymous: false,
-        })?;
+        sessions::sign_in(&request, User::load(db, account.id).await?).await?;
+        request.set_timezone(&account.profile.timezone)?;
 
         request.redirect("/dashboard")
     } else {
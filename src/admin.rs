@@ -0,0 +1,68 @@
+//! Admin listings of accounts and the audit log, filterable and
+//! exportable as CSV (or XLSX, with `jelly/xlsx`), deactivating or
+//! reactivating accounts, and making them reset their password. Checked
+//! accounts can be acted on in bulk by a background job, and deactivating
+//! them undone for a few minutes after. Settings (see `jelly::settings`)
+//! can be changed from `/admin/settings`. Admins see everything; anyone
+//! else needs the `accounts.*`, `audit.*` or `settings.edit` permissions
+//! (see `jelly::accounts::permissions`), and only gets into the sections
+//! they have something in.
+//!
+//! `/admin/logging` changes the log filter on the server it's served by,
+//! for looking into problems without a restart (see `jelly::logging`); it
+//! needs `logging.edit`.
+//!
+//! Roles hand those permissions out: `/admin/roles` edits which of the
+//! `models::CAPABILITIES` each role has, and who has each role. It needs
+//! `roles.edit`, which no role can give.
+
+use jelly::actix_web::web::ServiceConfig;
+use jelly::routes::{resource, scope};
+
+pub mod forms;
+pub mod jobs;
+pub mod models;
+pub mod views;
+
+pub use models::{AccountFilter, AdminAccount, AuditFilter};
+
+pub fn configure(config: &mut ServiceConfig) {
+    scope("/admin/accounts")
+        .permission("accounts.")
+        .resource(resource("").get(views::accounts))
+        .resource(resource("/export.csv").get(views::accounts_csv))
+        .resource(resource("/export.xlsx").get(views::accounts_xlsx))
+        .resource(resource("/bulk").post(views::bulk_confirm))
+        .resource(resource("/bulk/run").post(views::bulk_run))
+        .resource(resource("/bulk/{id}/undo").post(views::bulk_undo))
+        .resource(resource("/{id}/deactivate").post(views::deactivate))
+        .resource(resource("/{id}/reactivate").post(views::reactivate))
+        .resource(resource("/{id}/require-password-reset").post(views::require_password_reset))
+        .mount(config);
+    scope("/admin/audit")
+        .permission("audit.")
+        .resource(resource("").get(views::audit))
+        .resource(resource("/export.csv").get(views::audit_csv))
+        .resource(resource("/export.xlsx").get(views::audit_xlsx))
+        .mount(config);
+    scope("/admin/settings")
+        .permission("settings.")
+        .resource(resource("").get(views::settings).post(views::save_setting))
+        .resource(resource("/{key}/remove").post(views::remove_setting))
+        .mount(config);
+    scope("/admin/logging")
+        .permission("logging.")
+        .resource(resource("").get(views::logging).post(views::save_log_filter))
+        .resource(resource("/reset").post(views::reset_log_filter))
+        .mount(config);
+    scope("/admin/roles")
+        .permission("roles.")
+        .resource(resource("").get(views::roles).post(views::save_roles))
+        .resource(resource("/new").post(views::create_role))
+        .resource(resource("/{id}/delete").post(views::delete_role))
+        .resource(resource("/{id}/members").post(views::add_role_member))
+        .resource(resource("/{id}/members/remove").post(views::remove_role_member))
+        .mount(config);
+
+    resource("/admin").get(views::index).permission("").mount(config);
+}
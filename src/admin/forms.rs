@@ -0,0 +1,118 @@
+use jelly::serde_json::{self, Value};
+use serde::{Deserialize, Serialize};
+
+use super::models::{capability, BulkAction, Capability};
+
+/// The account list's bulk action form: an `action`, and a checkbox per
+/// account, each sending its id as `account_ids`. `web::Form` can't read
+/// repeated fields, so it's parsed here; ids and action names have nothing
+/// to decode.
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct BulkForm {
+    pub action: String,
+    pub account_ids: Vec<i32>,
+}
+
+impl BulkForm {
+    /// Anything that isn't an id is skipped, and duplicates dropped.
+    pub fn parse(body: &[u8]) -> Self {
+        let mut form = BulkForm::default();
+        let body = std::str::from_utf8(body).unwrap_or("");
+
+        for (name, value) in body.split('&').filter_map(|pair| pair.split_once('=')) {
+            match name {
+                "action" => form.action = value.to_string(),
+                "account_ids" => {
+                    if let Ok(id) = value.parse() {
+                        if !form.account_ids.contains(&id) {
+                            form.account_ids.push(id);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        form
+    }
+
+    pub fn is_valid(&self) -> bool {
+        BulkAction::is_valid(&self.action) && !self.account_ids.is_empty()
+    }
+}
+
+/// A setting to save: its key, and its value as JSON text.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct SettingForm {
+    pub key: String,
+    pub value: String,
+}
+
+impl SettingForm {
+    /// The key and parsed value, if the key isn't blank and the value is
+    /// JSON.
+    pub fn parsed(&self) -> Option<(&str, Value)> {
+        let key = self.key.trim();
+        if key.is_empty() {
+            return None;
+        }
+        serde_json::from_str(&self.value).ok().map(|value| (key, value))
+    }
+}
+
+/// A log filter to switch to, as in `RUST_LOG`.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct LogFilterForm {
+    pub filter: String,
+}
+
+/// The roles editor's matrix: a checkbox per role and capability, each
+/// sending `<role id>.<capability>` as `grants`. Parsed by hand for the
+/// same reason as `BulkForm`; unknown capabilities are skipped.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct RolesForm {
+    pub grants: Vec<(i32, &'static str)>,
+}
+
+impl RolesForm {
+    pub fn parse(body: &[u8]) -> Self {
+        let mut form = RolesForm::default();
+        let body = std::str::from_utf8(body).unwrap_or("");
+
+        for (name, value) in body.split('&').filter_map(|pair| pair.split_once('=')) {
+            if name != "grants" {
+                continue;
+            }
+            let grant = value.split_once('.').and_then(|(id, name)| {
+                let capability = capability(name)?;
+                Some((id.parse().ok()?, capability.name))
+            });
+            if let Some(grant) = grant {
+                if !form.grants.contains(&grant) {
+                    form.grants.push(grant);
+                }
+            }
+        }
+        form
+    }
+
+    /// The capabilities checked for the role `id`.
+    pub fn for_role(&self, id: i32) -> Vec<&'static Capability> {
+        self.grants
+            .iter()
+            .filter(|(role_id, _)| *role_id == id)
+            .filter_map(|(_, name)| capability(name))
+            .collect()
+    }
+}
+
+/// A new role's name.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct RoleForm {
+    pub name: String,
+}
+
+/// Who to give a role to, or take it away from.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct RoleMemberForm {
+    pub email: String,
+}
@@ -0,0 +1,147 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use jelly::accounts::{permissions, User};
+use jelly::anyhow::{anyhow, Error};
+use jelly::export;
+use jelly::jobs::{Job, JobConfig, JobState, Traced, DEFAULT_QUEUE};
+use jelly::serde::{Deserialize, Serialize};
+use jelly::serde_json::json;
+use jelly::storage;
+use jelly::tenancy::TenantId;
+
+use super::models::{BulkAction, DEACTIVATE, EXPORT, RESEND_VERIFICATION};
+use super::AdminAccount;
+use crate::accounts::jobs::SendVerifyAccountEmail;
+use crate::accounts::Account;
+use crate::audit::AuditEvent;
+
+/// Runs a bulk action from the admin account list, or with `undo`, puts
+/// back what it changed. Each account changed gets its own audit event,
+/// noting the bulk action it was part of.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RunBulkAction {
+    pub id: i32,
+    pub undo: bool,
+}
+
+/// Where an export is written, under the requester's storage.
+pub fn export_name(id: i32) -> String {
+    format!("exports/accounts-{}.csv", id)
+}
+
+async fn run(bulk: &BulkAction, state: &JobState) -> Result<(Vec<i32>, Option<String>), Error> {
+    let data = json!({ "by": bulk.requested_by, "bulk_action_id": bulk.id });
+
+    match bulk.action.as_str() {
+        DEACTIVATE => {
+            // Whoever asked may have lost permissions since, so check as of now.
+            let requester = User::load(&state.pool, bulk.requested_by)
+                .await
+                .map_err(|e| anyhow!("Error loading requester: {:?}", e))?;
+            let mut allowed = Vec::new();
+            for id in bulk.account_ids.iter() {
+                match permissions::can_manage(&state.pool, &requester, *id).await {
+                    Ok(true) => allowed.push(*id),
+                    Ok(false) => warn!("Not deactivating account {} for {}", id, bulk.requested_by),
+                    Err(e) => return Err(anyhow!("Error checking permissions: {:?}", e)),
+                }
+            }
+
+            let affected = Account::deactivate_many(&allowed, bulk.requested_by, &state.pool)
+                .await
+                .map_err(|e| anyhow!("Error deactivating accounts: {:?}", e))?;
+            for id in affected.iter() {
+                AuditEvent::record(Some(*id), "account.deactivated", data.clone(), &state.pool)
+                    .await
+                    .map_err(|e| anyhow!("Error recording deactivation: {:?}", e))?;
+            }
+            Ok((affected, None))
+        }
+
+        RESEND_VERIFICATION => {
+            let unverified = Account::unverified_among(&bulk.account_ids, &state.pool)
+                .await
+                .map_err(|e| anyhow!("Error fetching unverified accounts: {:?}", e))?;
+
+            // One bad address shouldn't stop the rest.
+            let mut sent = Vec::new();
+            for id in unverified {
+                match (SendVerifyAccountEmail { to: id }).run(state.clone()).await {
+                    Ok(()) => sent.push(id),
+                    Err(e) => error!("Error resending verification to account {}: {:?}", id, e),
+                }
+            }
+            Ok((sent, None))
+        }
+
+        EXPORT => {
+            let accounts = AdminAccount::by_ids(&bulk.account_ids, &state.pool)
+                .await
+                .map_err(|e| anyhow!("Error fetching accounts to export: {:?}", e))?;
+            let name = export_name(bulk.id);
+            let path = storage::path_for(TenantId(bulk.requested_by), &name)
+                .map_err(|e| anyhow!("Error finding export path: {:?}", e))?
+                .ok_or_else(|| anyhow!("Invalid export path {}", name))?;
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            std::fs::write(&path, export::csv_string(&accounts))?;
+
+            let ids = accounts.iter().map(|account| account.id).collect();
+            Ok((ids, Some(name)))
+        }
+
+        action => Err(anyhow!("Unknown bulk action {}", action)),
+    }
+}
+
+async fn undo(bulk: &BulkAction, state: &JobState) -> Result<(), Error> {
+    let data = json!({ "by": bulk.requested_by, "bulk_action_id": bulk.id, "undo": true });
+
+    let reactivated = Account::reactivate_many(&bulk.affected, &state.pool)
+        .await
+        .map_err(|e| anyhow!("Error reactivating accounts: {:?}", e))?;
+    for id in reactivated {
+        AuditEvent::record(Some(id), "account.reactivated", data.clone(), &state.pool)
+            .await
+            .map_err(|e| anyhow!("Error recording reactivation: {:?}", e))?;
+    }
+    Ok(())
+}
+
+impl Job for RunBulkAction {
+    type State = JobState;
+    type Future = Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
+
+    const NAME: &'static str = "RunBulkActionJob";
+    const QUEUE: &'static str = DEFAULT_QUEUE;
+
+    fn run(self, state: JobState) -> Self::Future {
+        Box::pin(async move {
+            let bulk = BulkAction::get(self.id, &state.pool)
+                .await
+                .map_err(|e| anyhow!("Error fetching bulk action: {:?}", e))?;
+
+            if self.undo {
+                return undo(&bulk, &state).await;
+            }
+
+            match run(&bulk, &state).await {
+                Ok((affected, result)) => BulkAction::finish(bulk.id, &affected, result.as_deref(), &state.pool)
+                    .await
+                    .map_err(|e| anyhow!("Error finishing bulk action: {:?}", e)),
+                Err(e) => {
+                    BulkAction::fail(bulk.id, &state.pool)
+                        .await
+                        .map_err(|e| anyhow!("Error failing bulk action: {:?}", e))?;
+                    Err(e)
+                }
+            }
+        })
+    }
+}
+
+pub fn configure(config: JobConfig) -> JobConfig {
+    config.register::<Traced<RunBulkAction>>()
+}
@@ -0,0 +1,589 @@
+use jelly::accounts::permissions::Granted;
+use jelly::accounts::sessions;
+use jelly::chrono::{DateTime, Duration, Utc};
+use jelly::error::Error;
+use jelly::export::Row;
+use jelly::serde::{Deserialize, Serialize};
+use sqlx::postgres::PgPool;
+
+use crate::audit::AuditEvent;
+
+/// Treats a blank form field as unset.
+fn non_empty(value: Option<String>) -> Option<String> {
+    value
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// Filters for the account list: `q` matches name, email or username;
+/// `status` is one of `verified`, `unverified`, `admin`, `deactivated` or
+/// `deleted`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct AccountFilter {
+    pub q: Option<String>,
+    pub status: Option<String>,
+}
+
+impl AccountFilter {
+    pub fn normalized(self) -> Self {
+        AccountFilter {
+            q: non_empty(self.q),
+            status: non_empty(self.status),
+        }
+    }
+}
+
+/// Filters for the audit log: `kind` is an event kind, `account` an
+/// account's id or email.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct AuditFilter {
+    pub kind: Option<String>,
+    pub account: Option<String>,
+}
+
+impl AuditFilter {
+    pub fn normalized(self) -> Self {
+        AuditFilter {
+            kind: non_empty(self.kind),
+            account: non_empty(self.account),
+        }
+    }
+}
+
+/// An account, as listed for admins.
+#[derive(Debug, Serialize)]
+pub struct AdminAccount {
+    pub id: i32,
+    pub name: String,
+    pub email: String,
+    pub username: Option<String>,
+    pub is_admin: bool,
+    pub has_verified_email: bool,
+    pub is_active: bool,
+    pub must_reset_password: bool,
+    pub last_login: Option<DateTime<Utc>>,
+    pub created: DateTime<Utc>,
+    pub deleted_at: Option<DateTime<Utc>>,
+    pub avatar_url: Option<String>,
+}
+
+impl AdminAccount {
+    /// Up to `limit` accounts matching `filter`, newest first, after the
+    /// account with id `after`.
+    pub async fn list(
+        filter: &AccountFilter,
+        after: Option<i64>,
+        limit: i64,
+        pool: &PgPool,
+    ) -> Result<Vec<Self>, Error> {
+        Ok(sqlx::query_as_unchecked!(
+            AdminAccount,
+            "
+            SELECT
+                id, name, email, username, is_admin, has_verified_email,
+                is_active, must_reset_password, last_login, created, deleted_at,
+                profile->>'avatar_url' AS avatar_url
+            FROM accounts
+            WHERE ($1::text IS NULL
+                    OR email ILIKE '%' || $1 || '%'
+                    OR name ILIKE '%' || $1 || '%'
+                    OR username ILIKE '%' || $1 || '%')
+                AND CASE $2::text
+                    WHEN 'verified' THEN has_verified_email AND deleted_at IS NULL
+                    WHEN 'unverified' THEN NOT has_verified_email AND deleted_at IS NULL
+                    WHEN 'admin' THEN is_admin
+                    WHEN 'deactivated' THEN NOT is_active AND deleted_at IS NULL
+                    WHEN 'deleted' THEN deleted_at IS NOT NULL
+                    ELSE true
+                END
+                AND ($3::bigint IS NULL OR id < $3)
+            ORDER BY id DESC
+            LIMIT $4
+        ",
+            filter.q,
+            filter.status,
+            after,
+            limit
+        )
+        .fetch_all(pool)
+        .await?)
+    }
+
+    /// The accounts with these ids, newest first.
+    pub async fn by_ids(ids: &[i32], pool: &PgPool) -> Result<Vec<Self>, Error> {
+        Ok(sqlx::query_as_unchecked!(
+            AdminAccount,
+            "
+            SELECT
+                id, name, email, username, is_admin, has_verified_email,
+                is_active, must_reset_password, last_login, created, deleted_at,
+                profile->>'avatar_url' AS avatar_url
+            FROM accounts
+            WHERE id = ANY($1)
+            ORDER BY id DESC
+        ",
+            ids
+        )
+        .fetch_all(pool)
+        .await?)
+    }
+}
+
+pub const DEACTIVATE: &str = "deactivate";
+pub const RESEND_VERIFICATION: &str = "resend_verification";
+pub const EXPORT: &str = "export";
+
+pub const QUEUED: &str = "queued";
+pub const DONE: &str = "done";
+pub const FAILED: &str = "failed";
+
+/// How long after it's run a reversible bulk action can be undone.
+pub const UNDO_MINUTES: i64 = 10;
+
+/// An action taken on several accounts at once from the account list, run
+/// by `jobs::RunBulkAction`.
+#[derive(Debug, Serialize)]
+pub struct BulkAction {
+    pub id: i32,
+    pub action: String,
+    pub account_ids: Vec<i32>,
+    /// The accounts it changed, which undoing changes back.
+    pub affected: Vec<i32>,
+    pub requested_by: i32,
+    pub status: String,
+    /// The file an export wrote, under the requester's storage.
+    pub result: Option<String>,
+    pub created: DateTime<Utc>,
+    pub finished: Option<DateTime<Utc>>,
+    pub undone_at: Option<DateTime<Utc>>,
+}
+
+impl BulkAction {
+    pub fn is_valid(action: &str) -> bool {
+        [DEACTIVATE, RESEND_VERIFICATION, EXPORT].contains(&action)
+    }
+
+    /// Whether `action` can be undone. Emails can't be unsent, and an
+    /// export doesn't change anything.
+    pub fn is_reversible(action: &str) -> bool {
+        action == DEACTIVATE
+    }
+
+    pub fn describe(action: &str) -> &'static str {
+        match action {
+            DEACTIVATE => "Deactivate",
+            RESEND_VERIFICATION => "Resend verification email",
+            EXPORT => "Export",
+            _ => "Unknown action",
+        }
+    }
+
+    /// Whether it can still be undone.
+    pub fn can_undo(&self) -> bool {
+        Self::is_reversible(&self.action)
+            && self.undone_at.is_none()
+            && self
+                .finished
+                .map_or(false, |at| Utc::now() - at < Duration::minutes(UNDO_MINUTES))
+    }
+
+    pub async fn create(action: &str, account_ids: &[i32], requested_by: i32, pool: &PgPool) -> Result<i32, Error> {
+        Ok(sqlx::query!(
+            "
+            INSERT INTO bulk_actions (action, account_ids, requested_by)
+            VALUES ($1, $2, $3)
+            RETURNING id
+        ",
+            action,
+            account_ids,
+            requested_by
+        )
+        .fetch_one(pool)
+        .await?
+        .id)
+    }
+
+    pub async fn get(id: i32, pool: &PgPool) -> Result<Self, Error> {
+        Ok(sqlx::query_as_unchecked!(
+            BulkAction,
+            "
+            SELECT
+                id, action, account_ids, affected, requested_by, status, result,
+                created, finished, undone_at
+            FROM bulk_actions WHERE id = $1
+        ",
+            id
+        )
+        .fetch_one(pool)
+        .await?)
+    }
+
+    /// Those taken in the last day, newest first.
+    pub async fn recent(pool: &PgPool) -> Result<Vec<Self>, Error> {
+        Ok(sqlx::query_as_unchecked!(
+            BulkAction,
+            "
+            SELECT
+                id, action, account_ids, affected, requested_by, status, result,
+                created, finished, undone_at
+            FROM bulk_actions
+            WHERE created > now() - interval '1 day'
+            ORDER BY id DESC
+            LIMIT 10
+        "
+        )
+        .fetch_all(pool)
+        .await?)
+    }
+
+    pub async fn finish(id: i32, affected: &[i32], result: Option<&str>, pool: &PgPool) -> Result<(), Error> {
+        sqlx::query!(
+            "
+            UPDATE bulk_actions
+            SET status = $2, affected = $3, result = $4, finished = now()
+            WHERE id = $1
+        ",
+            id,
+            DONE,
+            affected,
+            result
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn fail(id: i32, pool: &PgPool) -> Result<(), Error> {
+        sqlx::query!(
+            "
+            UPDATE bulk_actions
+            SET status = $2, finished = now()
+            WHERE id = $1
+        ",
+            id,
+            FAILED
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Marks the action undone, if it still can be. Returns whether it was;
+    /// the job then puts things back.
+    pub async fn mark_undone(id: i32, pool: &PgPool) -> Result<bool, Error> {
+        let result = sqlx::query!(
+            "
+            UPDATE bulk_actions
+            SET undone_at = now()
+            WHERE id = $1 AND action = $2 AND status = $3 AND undone_at IS NULL
+                AND finished > now() - make_interval(mins => $4)
+        ",
+            id,
+            DEACTIVATE,
+            DONE,
+            UNDO_MINUTES as i32
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+/// A column of the roles editor: something a role can be trusted with,
+/// granting the permissions (see `jelly::accounts::permissions`) the views
+/// for it check.
+#[derive(Debug, Serialize)]
+pub struct Capability {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub permissions: &'static [&'static str],
+}
+
+/// What roles can be given. Editing roles themselves (`roles.edit`) isn't
+/// among them, and the editor only lets people grant capabilities, and
+/// hand out roles, they hold themselves; so nobody can grant themselves
+/// (or anyone else) more than admins gave them.
+pub const CAPABILITIES: &[Capability] = &[
+    Capability {
+        name: "manage_users",
+        description: "Manage users",
+        permissions: &["accounts.*", "invitations.*", "waitlist.*"],
+    },
+    Capability {
+        name: "manage_billing",
+        description: "Manage billing",
+        permissions: &["billing.*"],
+    },
+    Capability {
+        name: "view_audit",
+        description: "View the audit log",
+        permissions: &["audit.view", "audit.export"],
+    },
+    Capability {
+        name: "send_campaigns",
+        description: "Send campaigns",
+        permissions: &["campaigns.*"],
+    },
+];
+
+impl Capability {
+    /// What it grants, to check against what someone holds.
+    pub fn granted(&self) -> Granted {
+        Granted {
+            allowed: self.permissions.iter().map(|p| p.to_string()).collect(),
+            ..Granted::default()
+        }
+    }
+}
+
+pub fn capability(name: &str) -> Option<&'static Capability> {
+    CAPABILITIES.iter().find(|capability| capability.name == name)
+}
+
+/// A role, with its permissions and the email of everyone who has it.
+#[derive(Debug, Serialize)]
+pub struct Role {
+    pub id: i32,
+    pub name: String,
+    pub permissions: Vec<String>,
+    pub members: Vec<String>,
+}
+
+impl Role {
+    /// Whether the role has all of `capability`'s permissions.
+    pub fn has(&self, capability: &Capability) -> bool {
+        capability
+            .permissions
+            .iter()
+            .all(|permission| self.permissions.iter().any(|p| p == permission))
+    }
+
+    /// What its members get from it, including permissions given outside
+    /// the editor.
+    pub fn granted(&self) -> Granted {
+        Granted {
+            allowed: self.permissions.clone(),
+            ..Granted::default()
+        }
+    }
+
+    /// The names of the capabilities it has.
+    pub fn capabilities(&self) -> Vec<&'static str> {
+        CAPABILITIES
+            .iter()
+            .filter(|capability| self.has(capability))
+            .map(|capability| capability.name)
+            .collect()
+    }
+
+    pub async fn list(pool: &PgPool) -> Result<Vec<Self>, Error> {
+        Ok(sqlx::query_as_unchecked!(
+            Role,
+            "
+            SELECT
+                r.id, r.name,
+                ARRAY(
+                    SELECT permission FROM role_permissions
+                    WHERE role_id = r.id ORDER BY permission
+                ) as \"permissions!\",
+                ARRAY(
+                    SELECT a.email FROM account_roles ar
+                    JOIN accounts a ON a.id = ar.account_id
+                    WHERE ar.role_id = r.id ORDER BY a.email
+                ) as \"members!\"
+            FROM roles r
+            ORDER BY r.name
+        "
+        )
+        .fetch_all(pool)
+        .await?)
+    }
+
+    /// Adds a role with nothing granted. Returns `None` if the name's taken.
+    pub async fn create(name: &str, pool: &PgPool) -> Result<Option<i32>, Error> {
+        Ok(sqlx::query!(
+            "
+            INSERT INTO roles (name) VALUES ($1)
+            ON CONFLICT (name) DO NOTHING
+            RETURNING id
+        ",
+            name
+        )
+        .fetch_optional(pool)
+        .await?
+        .map(|row| row.id))
+    }
+
+    /// Removes the role, and takes it away from everyone who had it.
+    pub async fn delete(id: i32, pool: &PgPool) -> Result<(), Error> {
+        let mut tx = pool.begin().await?;
+        sqlx::query!(
+            "
+            UPDATE accounts SET session_version = session_version + 1
+            WHERE id IN (SELECT account_id FROM account_roles WHERE role_id = $1)
+        ",
+            id
+        )
+        .execute(&mut tx)
+        .await?;
+        sqlx::query!("DELETE FROM roles WHERE id = $1", id)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Grants the role the permissions of exactly `capabilities`. Any other
+    /// permissions it was given (outside the editor) are left alone.
+    pub async fn set_capabilities(id: i32, capabilities: &[&Capability], pool: &PgPool) -> Result<(), Error> {
+        let editable: Vec<String> = CAPABILITIES
+            .iter()
+            .flat_map(|capability| capability.permissions.iter().map(|p| p.to_string()))
+            .collect();
+        let granted: Vec<String> = capabilities
+            .iter()
+            .flat_map(|capability| capability.permissions.iter().map(|p| p.to_string()))
+            .collect();
+
+        let mut tx = pool.begin().await?;
+        sqlx::query!(
+            "DELETE FROM role_permissions WHERE role_id = $1 AND permission = ANY($2)",
+            id,
+            &editable
+        )
+        .execute(&mut tx)
+        .await?;
+        sqlx::query!(
+            "
+            INSERT INTO role_permissions (role_id, permission)
+            SELECT $1, unnest($2::text[])
+            ON CONFLICT DO NOTHING
+        ",
+            id,
+            &granted
+        )
+        .execute(&mut tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// The id of the (not deleted) account with `email`, who the role
+    /// would be given to.
+    pub async fn member_id(email: &str, pool: &PgPool) -> Result<Option<i32>, Error> {
+        Ok(sqlx::query!(
+            "SELECT id FROM accounts WHERE lower(email) = lower($1) AND deleted_at IS NULL",
+            email
+        )
+        .fetch_optional(pool)
+        .await?
+        .map(|row| row.id))
+    }
+
+    /// Gives the role to the account with `email`, and has its sessions
+    /// pick that up. Returns the account's id, or `None` if there isn't one.
+    pub async fn assign(id: i32, email: &str, pool: &PgPool) -> Result<Option<i32>, Error> {
+        let account_id = sqlx::query!(
+            "
+            INSERT INTO account_roles (account_id, role_id)
+            SELECT a.id, $1 FROM accounts a
+            WHERE lower(a.email) = lower($2) AND a.deleted_at IS NULL
+            ON CONFLICT (account_id, role_id) DO UPDATE SET role_id = EXCLUDED.role_id
+            RETURNING account_id
+        ",
+            id,
+            email
+        )
+        .fetch_optional(pool)
+        .await?
+        .map(|row| row.account_id);
+
+        if let Some(account_id) = account_id {
+            sessions::refresh(pool, account_id).await?;
+        }
+        Ok(account_id)
+    }
+
+    /// Takes the role away from the account with `email`, and has its
+    /// sessions pick that up. Returns the account's id, if it had the role.
+    pub async fn unassign(id: i32, email: &str, pool: &PgPool) -> Result<Option<i32>, Error> {
+        let account_id = sqlx::query!(
+            "
+            DELETE FROM account_roles
+            WHERE role_id = $1
+                AND account_id = (SELECT id FROM accounts WHERE lower(email) = lower($2))
+            RETURNING account_id
+        ",
+            id,
+            email
+        )
+        .fetch_optional(pool)
+        .await?
+        .map(|row| row.account_id);
+
+        if let Some(account_id) = account_id {
+            sessions::refresh(pool, account_id).await?;
+        }
+        Ok(account_id)
+    }
+}
+
+fn timestamp(at: &Option<DateTime<Utc>>) -> String {
+    at.map(|at| at.to_rfc3339()).unwrap_or_default()
+}
+
+impl Row for AdminAccount {
+    fn headers() -> &'static [&'static str] {
+        &[
+            "id", "name", "email", "username", "admin", "verified", "active",
+            "must_reset_password", "last_login", "created", "deleted",
+        ]
+    }
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            self.name.clone(),
+            self.email.clone(),
+            self.username.clone().unwrap_or_default(),
+            self.is_admin.to_string(),
+            self.has_verified_email.to_string(),
+            self.is_active.to_string(),
+            self.must_reset_password.to_string(),
+            timestamp(&self.last_login),
+            self.created.to_rfc3339(),
+            timestamp(&self.deleted_at),
+        ]
+    }
+
+    fn cursor(&self) -> i64 {
+        self.id.into()
+    }
+}
+
+impl Row for AuditEvent {
+    fn headers() -> &'static [&'static str] {
+        &["id", "time", "account_id", "event", "ip", "location", "device"]
+    }
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            self.created.to_rfc3339(),
+            self.account_id.map(|id| id.to_string()).unwrap_or_default(),
+            self.kind.clone(),
+            self.ip.clone().unwrap_or_default(),
+            self.location().map(|l| l.describe()).unwrap_or_default(),
+            self.user_agent.clone().unwrap_or_default(),
+        ]
+    }
+
+    fn cursor(&self) -> i64 {
+        self.id.into()
+    }
+}
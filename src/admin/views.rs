@@ -0,0 +1,536 @@
+use jelly::accounts::{jwt, permissions};
+use jelly::actix_web::web::Bytes;
+use jelly::actix_web::{web, HttpRequest};
+use jelly::chrono::{Duration, Utc};
+use jelly::export;
+use jelly::logging;
+use jelly::settings;
+use jelly::prelude::*;
+use jelly::serde_json::json;
+use jelly::storage;
+use jelly::tenancy::TenantId;
+use jelly::Result;
+use serde::{Deserialize, Serialize};
+
+use super::forms::{BulkForm, LogFilterForm, RoleForm, RoleMemberForm, RolesForm, SettingForm};
+use super::jobs::RunBulkAction;
+use super::models::{BulkAction, Role, CAPABILITIES, EXPORT, UNDO_MINUTES};
+use super::{AccountFilter, AdminAccount, AuditFilter};
+use crate::accounts::Account;
+use crate::audit::AuditEvent;
+
+const PER_PAGE: i64 = 50;
+
+/// The admin sections, and the permission each is listed for.
+const SECTIONS: &[(&str, &str, &str)] = &[
+    ("Accounts", "/admin/accounts", "accounts.view"),
+    ("Audit log", "/admin/audit", "audit.view"),
+    ("Invitations", "/admin/invitations", "invitations.view"),
+    ("Waitlist", "/admin/waitlist", "waitlist.view"),
+    ("Experiments", "/admin/experiments", "experiments.view"),
+    ("Settings", "/admin/settings", "settings.edit"),
+    ("Logging", "/admin/logging", "logging.edit"),
+    ("Roles", "/admin/roles", "roles.edit"),
+];
+
+/// Links to the admin sections the user can see.
+pub async fn index(request: HttpRequest) -> Result<HttpResponse> {
+    let mut sections = Vec::new();
+    for &(name, path, permission) in SECTIONS {
+        if request.has_permission(permission).await? {
+            sections.push((name, path));
+        }
+    }
+
+    request.render(200, "admin/index.html", {
+        let mut ctx = Context::new();
+        ctx.insert("sections", &sections);
+        ctx
+    })
+}
+
+#[derive(Deserialize)]
+pub struct PageQuery {
+    pub after: Option<i64>,
+}
+
+/// A recent bulk action, as listed under the accounts.
+#[derive(Serialize)]
+struct BulkActionRow<'a> {
+    #[serde(flatten)]
+    bulk: &'a BulkAction,
+    description: &'static str,
+    can_undo: bool,
+    /// Exports are only offered to whoever asked for them.
+    download_url: Option<String>,
+}
+
+/// Accounts matching the filter, newest first, and the last day's bulk
+/// actions.
+pub async fn accounts(
+    request: HttpRequest,
+    filter: web::Query<AccountFilter>,
+    page: web::Query<PageQuery>,
+) -> Result<HttpResponse> {
+    request.require_permission("accounts.view").await?;
+    let filter = filter.into_inner().normalized();
+    let db = request.db_pool()?;
+    let accounts = AdminAccount::list(&filter, page.after, PER_PAGE, db).await?;
+    let next = match accounts.last() {
+        Some(last) if accounts.len() as i64 == PER_PAGE => Some(last.id),
+        _ => None,
+    };
+
+    let user = request.user()?;
+    let bulk_actions = BulkAction::recent(db).await?;
+    let bulk_actions: Vec<_> = bulk_actions
+        .iter()
+        .map(|bulk| BulkActionRow {
+            bulk,
+            description: BulkAction::describe(&bulk.action),
+            can_undo: bulk.can_undo(),
+            download_url: match &bulk.result {
+                Some(name) if bulk.requested_by == user.id => Some(storage::signed_url(
+                    TenantId(user.id),
+                    name,
+                    Utc::now() + Duration::hours(1),
+                )),
+                _ => None,
+            },
+        })
+        .collect();
+
+    request.render(200, "admin/accounts.html", {
+        let mut ctx = Context::new();
+        ctx.insert("accounts", &accounts);
+        ctx.insert("filter", &filter);
+        ctx.insert("next", &next);
+        ctx.insert("xlsx", &export::XLSX);
+        ctx.insert("bulk_actions", &bulk_actions);
+        ctx.insert("undo_minutes", &UNDO_MINUTES);
+        ctx
+    })
+}
+
+pub async fn accounts_csv(request: HttpRequest, filter: web::Query<AccountFilter>) -> Result<HttpResponse> {
+    request.require_permission("accounts.export").await?;
+    let filter = filter.into_inner().normalized();
+    let pool = request.db_pool()?.clone();
+
+    Ok(export::csv("accounts.csv", move |after, limit| {
+        let (filter, pool) = (filter.clone(), pool.clone());
+        async move { AdminAccount::list(&filter, after, limit, &pool).await }
+    }))
+}
+
+pub async fn accounts_xlsx(request: HttpRequest, filter: web::Query<AccountFilter>) -> Result<HttpResponse> {
+    request.require_permission("accounts.export").await?;
+    let filter = filter.into_inner().normalized();
+    let db = request.db_pool()?;
+
+    export::xlsx("accounts.xlsx", |after, limit| AdminAccount::list(&filter, after, limit, db)).await
+}
+
+/// Deactivates an account, signing it out everywhere.
+pub async fn deactivate(request: HttpRequest, path: web::Path<i32>) -> Result<HttpResponse> {
+    request.require_permission("accounts.edit").await?;
+    let (id, user) = (path.into_inner(), request.user()?);
+    if id == user.id {
+        request.flash("Accounts", "You can't deactivate your own account from here.")?;
+        return request.redirect("/admin/accounts");
+    }
+
+    let db = request.db_pool()?;
+    if !permissions::can_manage(db, &user, id).await? {
+        request.flash("Accounts", "You can't deactivate that account.")?;
+        return request.redirect("/admin/accounts");
+    }
+    Account::deactivate(id, db).await?;
+    AuditEvent::record(Some(id), "account.deactivated", json!({ "by": user.id }), db).await?;
+    request.flash("Accounts", "The account has been deactivated.")?;
+    request.redirect("/admin/accounts?status=deactivated")
+}
+
+pub async fn reactivate(request: HttpRequest, path: web::Path<i32>) -> Result<HttpResponse> {
+    request.require_permission("accounts.edit").await?;
+    let (id, user) = (path.into_inner(), request.user()?);
+
+    let db = request.db_pool()?;
+    Account::reactivate(id, db).await?;
+    AuditEvent::record(Some(id), "account.reactivated", json!({ "by": user.id }), db).await?;
+    request.flash("Accounts", "The account has been reactivated.")?;
+    request.redirect("/admin/accounts")
+}
+
+/// Flags an account as compromised: it has to choose a new password
+/// before doing anything else, and its API refresh tokens stop working.
+pub async fn require_password_reset(request: HttpRequest, path: web::Path<i32>) -> Result<HttpResponse> {
+    request.require_permission("accounts.edit").await?;
+    let (id, user) = (path.into_inner(), request.user()?);
+
+    let db = request.db_pool()?;
+    if !permissions::can_manage(db, &user, id).await? {
+        request.flash("Accounts", "You can't make that account reset its password.")?;
+        return request.redirect("/admin/accounts");
+    }
+    if !Account::require_password_reset(id, db).await? {
+        request.flash("Accounts", "That account doesn't have a password to reset.")?;
+        return request.redirect("/admin/accounts");
+    }
+    jwt::revoke_all(db, id).await?;
+    AuditEvent::record(Some(id), "password.reset_required", json!({ "by": user.id }), db).await?;
+    request.flash("Accounts", "The account will have to choose a new password.")?;
+    request.redirect("/admin/accounts")
+}
+
+/// The permission a bulk `action` needs.
+fn bulk_permission(action: &str) -> &'static str {
+    if action == EXPORT {
+        "accounts.export"
+    } else {
+        "accounts.edit"
+    }
+}
+
+/// Asks to confirm a bulk action on the accounts checked in the list.
+pub async fn bulk_confirm(request: HttpRequest, body: Bytes) -> Result<HttpResponse> {
+    let form = BulkForm::parse(&body);
+    if !form.is_valid() {
+        request.flash("Accounts", "Choose an action and at least one account.")?;
+        return request.redirect("/admin/accounts");
+    }
+    request.require_permission(bulk_permission(&form.action)).await?;
+
+    let accounts = AdminAccount::by_ids(&form.account_ids, request.db_pool()?).await?;
+    request.render(200, "admin/bulk_confirm.html", {
+        let mut ctx = Context::new();
+        ctx.insert("form", &form);
+        ctx.insert("description", BulkAction::describe(&form.action));
+        ctx.insert("reversible", &BulkAction::is_reversible(&form.action));
+        ctx.insert("undo_minutes", &UNDO_MINUTES);
+        ctx.insert("accounts", &accounts);
+        ctx
+    })
+}
+
+/// Queues a confirmed bulk action.
+pub async fn bulk_run(request: HttpRequest, body: Bytes) -> Result<HttpResponse> {
+    let form = BulkForm::parse(&body);
+    if !form.is_valid() {
+        request.flash("Accounts", "Choose an action and at least one account.")?;
+        return request.redirect("/admin/accounts");
+    }
+    request.require_permission(bulk_permission(&form.action)).await?;
+
+    let user = request.user()?;
+    let db = request.db_pool()?;
+    let id = BulkAction::create(&form.action, &form.account_ids, user.id, db).await?;
+    request.queue_job(RunBulkAction { id, undo: false }).await?;
+    let data = json!({ "bulk_action_id": id, "action": form.action, "accounts": form.account_ids.len() });
+    AuditEvent::record_request(&request, user.id, "admin.bulk_action", data).await?;
+
+    request.flash(
+        "Accounts",
+        &format!(
+            "Queued \"{}\" for {} accounts; it's listed below once it's done.",
+            BulkAction::describe(&form.action),
+            form.account_ids.len()
+        ),
+    )?;
+    request.redirect("/admin/accounts")
+}
+
+/// Undoes a bulk action, if it's still recent enough.
+pub async fn bulk_undo(request: HttpRequest, path: web::Path<i32>) -> Result<HttpResponse> {
+    request.require_permission("accounts.edit").await?;
+    let (id, user) = (path.into_inner(), request.user()?);
+
+    let db = request.db_pool()?;
+    if !BulkAction::mark_undone(id, db).await? {
+        request.flash("Accounts", "That action can't be undone any more.")?;
+        return request.redirect("/admin/accounts");
+    }
+    request.queue_job(RunBulkAction { id, undo: true }).await?;
+    AuditEvent::record_request(&request, user.id, "admin.bulk_action_undone", json!({ "bulk_action_id": id })).await?;
+
+    request.flash("Accounts", "The action is being undone.")?;
+    request.redirect("/admin/accounts")
+}
+
+/// Audit events matching the filter, newest first.
+pub async fn audit(
+    request: HttpRequest,
+    filter: web::Query<AuditFilter>,
+    page: web::Query<PageQuery>,
+) -> Result<HttpResponse> {
+    request.require_permission("audit.view").await?;
+    let filter = filter.into_inner().normalized();
+    let events = AuditEvent::list(&filter, page.after, PER_PAGE, request.db_pool()?).await?;
+    let next = match events.last() {
+        Some(last) if events.len() as i64 == PER_PAGE => Some(last.id),
+        _ => None,
+    };
+
+    request.render(200, "admin/audit.html", {
+        let mut ctx = Context::new();
+        ctx.insert("events", &events);
+        ctx.insert("filter", &filter);
+        ctx.insert("next", &next);
+        ctx.insert("xlsx", &export::XLSX);
+        ctx
+    })
+}
+
+pub async fn audit_csv(request: HttpRequest, filter: web::Query<AuditFilter>) -> Result<HttpResponse> {
+    request.require_permission("audit.export").await?;
+    let filter = filter.into_inner().normalized();
+    let pool = request.db_pool()?.clone();
+
+    Ok(export::csv("audit-log.csv", move |after, limit| {
+        let (filter, pool) = (filter.clone(), pool.clone());
+        async move { AuditEvent::list(&filter, after, limit, &pool).await }
+    }))
+}
+
+pub async fn audit_xlsx(request: HttpRequest, filter: web::Query<AuditFilter>) -> Result<HttpResponse> {
+    request.require_permission("audit.export").await?;
+    let filter = filter.into_inner().normalized();
+    let db = request.db_pool()?;
+
+    export::xlsx("audit-log.xlsx", |after, limit| AuditEvent::list(&filter, after, limit, db)).await
+}
+
+/// Every setting, for viewing and changing.
+pub async fn settings(request: HttpRequest) -> Result<HttpResponse> {
+    request.require_permission("settings.edit").await?;
+    let settings: Vec<(String, String)> = settings::all(request.db_pool()?)
+        .await?
+        .into_iter()
+        .map(|(key, value)| (key, value.to_string()))
+        .collect();
+
+    request.render(200, "admin/settings.html", {
+        let mut ctx = Context::new();
+        ctx.insert("settings", &settings);
+        ctx.insert("form", &SettingForm::default());
+        ctx
+    })
+}
+
+/// Adds or changes a setting. It applies on this server straight away, and
+/// on others once their cached copy expires.
+pub async fn save_setting(request: HttpRequest, form: web::Form<SettingForm>) -> Result<HttpResponse> {
+    request.require_permission("settings.edit").await?;
+    let (key, value) = match form.parsed() {
+        Some(parsed) => parsed,
+        None => {
+            request.flash("Settings", "Settings need a key, and a value that's valid JSON.")?;
+            return request.redirect("/admin/settings");
+        }
+    };
+
+    if let Err(e) = settings::validate(key, &value) {
+        request.flash("Settings", &format!("That isn't a valid value for {}: {}", key, e))?;
+        return request.redirect("/admin/settings");
+    }
+
+    settings::set(request.db_pool()?, key, &value).await?;
+    let user = request.user()?;
+    AuditEvent::record_request(&request, user.id, "settings.changed", json!({ "key": key, "value": value })).await?;
+    request.flash("Settings", "The setting has been saved.")?;
+    request.redirect("/admin/settings")
+}
+
+pub async fn remove_setting(request: HttpRequest, path: web::Path<String>) -> Result<HttpResponse> {
+    request.require_permission("settings.edit").await?;
+    let key = path.into_inner();
+
+    settings::remove(request.db_pool()?, &key).await?;
+    let user = request.user()?;
+    AuditEvent::record_request(&request, user.id, "settings.changed", json!({ "key": key, "removed": true })).await?;
+    request.flash("Settings", "The setting has been removed.")?;
+    request.redirect("/admin/settings")
+}
+
+/// The log filter in effect on this server, for changing.
+pub async fn logging(request: HttpRequest) -> Result<HttpResponse> {
+    request.require_permission("logging.edit").await?;
+
+    request.render(200, "admin/logging.html", {
+        let mut ctx = Context::new();
+        ctx.insert("current", &logging::current());
+        ctx.insert("startup", logging::startup());
+        ctx
+    })
+}
+
+/// Changes the log filter; on this server only, until it restarts.
+pub async fn save_log_filter(request: HttpRequest, form: web::Form<LogFilterForm>) -> Result<HttpResponse> {
+    request.require_permission("logging.edit").await?;
+    if let Err(message) = logging::validate(&form.filter) {
+        request.flash("Logging", &format!("That filter won't work: {}", message))?;
+        return request.redirect("/admin/logging");
+    }
+
+    logging::set(&form.filter)?;
+    let user = request.user()?;
+    AuditEvent::record_request(&request, user.id, "logging.changed", json!({ "filter": form.filter.trim() })).await?;
+    request.flash("Logging", "The log filter has been changed on this server.")?;
+    request.redirect("/admin/logging")
+}
+
+/// Goes back to the filter the server started with.
+pub async fn reset_log_filter(request: HttpRequest) -> Result<HttpResponse> {
+    request.require_permission("logging.edit").await?;
+
+    logging::reset()?;
+    let user = request.user()?;
+    AuditEvent::record_request(&request, user.id, "logging.changed", json!({ "filter": logging::startup() })).await?;
+    request.flash("Logging", "The log filter is back to RUST_LOG.")?;
+    request.redirect("/admin/logging")
+}
+
+/// A role as shown in the editor: which capabilities are checked.
+#[derive(Serialize)]
+struct RoleRow<'a> {
+    #[serde(flatten)]
+    role: &'a Role,
+    capabilities: Vec<&'static str>,
+}
+
+/// The roles, as a matrix of the capabilities each has, with who has them.
+pub async fn roles(request: HttpRequest) -> Result<HttpResponse> {
+    request.require_permission("roles.edit").await?;
+    let roles = Role::list(request.db_pool()?).await?;
+    let rows: Vec<_> = roles
+        .iter()
+        .map(|role| RoleRow {
+            role,
+            capabilities: role.capabilities(),
+        })
+        .collect();
+
+    request.render(200, "admin/roles.html", {
+        let mut ctx = Context::new();
+        ctx.insert("roles", &rows);
+        ctx.insert("capabilities", CAPABILITIES);
+        ctx.insert("form", &RoleForm::default());
+        ctx
+    })
+}
+
+/// Saves the whole matrix: every role gets exactly what's checked for it,
+/// except capabilities the user doesn't hold themselves, which they can't
+/// give or take away; those are left as they were.
+pub async fn save_roles(request: HttpRequest, body: Bytes) -> Result<HttpResponse> {
+    request.require_permission("roles.edit").await?;
+    let form = RolesForm::parse(&body);
+
+    let db = request.db_pool()?;
+    let held = permissions::load(db, &request.user()?).await?;
+    let mut kept = false;
+    for role in Role::list(db).await? {
+        let checked = form.for_role(role.id);
+        let capabilities: Vec<_> = CAPABILITIES
+            .iter()
+            .filter(|capability| {
+                let wanted = checked.iter().any(|c| c.name == capability.name);
+                if held.includes(&capability.granted()) {
+                    return wanted;
+                }
+                kept |= wanted != role.has(capability);
+                role.has(capability)
+            })
+            .collect();
+        Role::set_capabilities(role.id, &capabilities, db).await?;
+    }
+    let grants: Vec<String> = form.grants.iter().map(|(id, name)| format!("{}.{}", id, name)).collect();
+    AuditEvent::record_request(&request, request.user()?.id, "roles.changed", json!({ "grants": grants })).await?;
+
+    if kept {
+        request.flash("Roles", "The roles have been saved, except for capabilities you don't have yourself.")?;
+    } else {
+        request.flash("Roles", "The roles have been saved.")?;
+    }
+    request.redirect("/admin/roles")
+}
+
+pub async fn create_role(request: HttpRequest, form: web::Form<RoleForm>) -> Result<HttpResponse> {
+    request.require_permission("roles.edit").await?;
+    let name = form.name.trim();
+    if name.is_empty() {
+        request.flash("Roles", "Roles need a name.")?;
+        return request.redirect("/admin/roles");
+    }
+
+    match Role::create(name, request.db_pool()?).await? {
+        Some(id) => {
+            let data = json!({ "role_id": id, "created": name });
+            AuditEvent::record_request(&request, request.user()?.id, "roles.changed", data).await?;
+            request.flash("Roles", "The role has been added; check what it can do below.")?;
+        }
+        None => request.flash("Roles", "There's already a role with that name.")?,
+    }
+    request.redirect("/admin/roles")
+}
+
+pub async fn delete_role(request: HttpRequest, path: web::Path<i32>) -> Result<HttpResponse> {
+    request.require_permission("roles.edit").await?;
+    let id = path.into_inner();
+
+    Role::delete(id, request.db_pool()?).await?;
+    AuditEvent::record_request(&request, request.user()?.id, "roles.changed", json!({ "role_id": id, "deleted": true })).await?;
+    request.flash("Roles", "The role has been removed.")?;
+    request.redirect("/admin/roles")
+}
+
+pub async fn add_role_member(
+    request: HttpRequest,
+    path: web::Path<i32>,
+    form: web::Form<RoleMemberForm>,
+) -> Result<HttpResponse> {
+    request.require_permission("roles.edit").await?;
+    let (id, user, email) = (path.into_inner(), request.user()?, form.email.trim());
+
+    // Nobody can give a role, to themselves or anyone else, that grants
+    // more than they have; or give one to someone they couldn't manage.
+    let db = request.db_pool()?;
+    let role = Role::list(db).await?.into_iter().find(|role| role.id == id);
+    let allowed = match (role, Role::member_id(email, db).await?) {
+        (Some(role), Some(account_id)) => {
+            permissions::load(db, &user).await?.includes(&role.granted())
+                && permissions::can_manage(db, &user, account_id).await?
+        }
+        _ => true,
+    };
+    if !allowed {
+        request.flash("Roles", "You can't give them that role.")?;
+        return request.redirect("/admin/roles");
+    }
+
+    match Role::assign(id, email, db).await? {
+        Some(account_id) => {
+            let data = json!({ "role_id": id, "by": user.id });
+            AuditEvent::record_request(&request, account_id, "role.assigned", data).await?;
+            request.flash("Roles", "The role has been given to them.")?;
+        }
+        None => request.flash("Roles", "There's no account with that email.")?,
+    }
+    request.redirect("/admin/roles")
+}
+
+pub async fn remove_role_member(
+    request: HttpRequest,
+    path: web::Path<i32>,
+    form: web::Form<RoleMemberForm>,
+) -> Result<HttpResponse> {
+    request.require_permission("roles.edit").await?;
+    let id = path.into_inner();
+
+    if let Some(account_id) = Role::unassign(id, &form.email, request.db_pool()?).await? {
+        let data = json!({ "role_id": id, "by": request.user()?.id });
+        AuditEvent::record_request(&request, account_id, "role.removed", data).await?;
+    }
+    request.flash("Roles", "The role has been taken away from them.")?;
+    request.redirect("/admin/roles")
+}
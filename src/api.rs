@@ -0,0 +1,93 @@
+//! JSON API endpoints.
+
+use jelly::accounts::jwt;
+use jelly::actix_service::Service;
+use jelly::chrono::Utc;
+use jelly::actix_web::web::ServiceConfig;
+use jelly::error::Error;
+use jelly::guards::{Idempotency, RateLimit};
+use jelly::ratelimit::Limit;
+use jelly::request::{JobQueue, Tenant};
+use jelly::routes::{resource, scope};
+use jelly::tenancy::TenantPool;
+
+use crate::metering;
+use crate::quotas::{Metric, Usage};
+
+pub mod forms;
+pub mod jobs;
+pub mod models;
+pub mod views;
+
+/// What a service account's tokens can be limited to, with what each
+/// allows, for the dashboard to offer. Handlers check them with
+/// `jwt::require_scope`.
+pub const SCOPES: &[(&str, &str)] = &[
+    ("account.read", "Read the account"),
+    ("account.write", "Change the account's name"),
+    ("devices.write", "Register and remove devices for push notifications"),
+];
+
+pub fn configure(config: &mut ServiceConfig) {
+    let rate_limit = RateLimit {
+        limit: Limit::from_env("API_RATE_LIMIT", 60, "API_RATE_LIMIT_WINDOW_MINUTES", 1),
+        prefix: "api",
+    };
+
+    scope("/api")
+        // Counts each call against the account's API quota, and turns
+        // it away with a 429 once that's used up. Calls that go through
+        // are metered for billing.
+        .wrap_fn(|mut req, srv| {
+            // The request can't still be shared once it's routed, so
+            // take what the check needs before handing it on.
+            let tenant = {
+                let request = req.parts_mut().0;
+                request.tenant_pool().map(|db| (db.pool().clone(), db.tenant()))
+            };
+            let response = srv.call(req);
+            async move {
+                let (pool, tenant) = tenant?;
+                let db = TenantPool::new(&pool, tenant);
+                if !Usage::consume(&db, Metric::ApiRequests, 1).await? {
+                    return Err(Error::QuotaExceeded(Metric::ApiRequests.as_str().to_string()).into());
+                }
+                metering::record(&db, Metric::ApiRequests.as_str(), 1).await?;
+                response.await
+            }
+        })
+        // Counts calls made with an API token against it, for the
+        // tokens page; the job does the write, after the response.
+        .wrap_fn(|req, srv| {
+            let token_id = jwt::bearer_claims(req.request()).and_then(|claims| claims.tid);
+            let queue = req.request().job_queue().ok().cloned();
+            let response = srv.call(req);
+            async move {
+                let response = response.await?;
+                if let (Some(token_id), Some(queue)) = (token_id, queue) {
+                    let job = jobs::RecordTokenUse { token_id, at: Utc::now() };
+                    if let Err(e) = queue.queue(job).await {
+                        error!("Error queueing API token use: {:?}", e);
+                    }
+                }
+                Ok(response)
+            }
+        })
+        // Retries of calls with an `Idempotency-Key` get the first
+        // response again, without running (or counting) the call twice.
+        .wrap(Idempotency)
+        // Per user limits on how fast calls can come in, with
+        // `RateLimit-*` headers on the way out so clients can pace
+        // themselves. Runs before the quota, so throttled calls don't
+        // use it up.
+        .rate_limit(rate_limit, "user")
+        .auth()
+        .resource(
+            resource("/account")
+                .get(views::account::show)
+                .patch(views::account::update),
+        )
+        .resource(resource("/devices").post(views::devices::register))
+        .resource(resource("/devices/{token}").delete(views::devices::unregister))
+        .mount(config);
+}
@@ -0,0 +1,29 @@
+use jelly::forms::TextField;
+use jelly::forms::validation::{Validatable, ValidationErrors};
+use serde::{Deserialize, Serialize};
+
+#[derive(Default, Debug, Deserialize, Serialize)]
+pub struct AccountPatch {
+    pub name: TextField,
+}
+
+impl AccountPatch {
+    pub fn set_keys(mut self) -> Self {
+        self.name = self.name.with_key("name");
+        self
+    }
+}
+
+impl Validatable<String> for AccountPatch {
+    fn validate(&self) -> Result<(), ValidationErrors<String>> {
+        self.name.validate()
+    }
+}
+
+/// A device registering for push notifications; see `jelly::push`.
+#[derive(Default, Debug, Deserialize, Serialize)]
+pub struct DeviceForm {
+    /// `fcm` or `apns`.
+    pub provider: String,
+    pub token: String,
+}
@@ -0,0 +1,59 @@
+use std::env;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+
+use jelly::anyhow::{anyhow, Error};
+use jelly::chrono::{DateTime, Utc};
+use jelly::email::Email;
+use jelly::jobs::{Job, JobConfig, JobState, Traced, DEFAULT_QUEUE};
+use jelly::serde::{Deserialize, Serialize};
+use jelly::tera::{Context, Tera};
+
+use super::models::{ApiToken, ExpiringToken};
+
+/// Counts a request made with an API token. Queued from the API scope, so
+/// the write never holds up the request itself.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecordTokenUse {
+    pub token_id: i32,
+    pub at: DateTime<Utc>,
+}
+
+impl Job for RecordTokenUse {
+    type State = JobState;
+    type Future = Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
+
+    const NAME: &'static str = "RecordTokenUseJob";
+    const QUEUE: &'static str = DEFAULT_QUEUE;
+
+    fn run(self, state: JobState) -> Self::Future {
+        Box::pin(async move {
+            ApiToken::record_use(self.token_id, 1, self.at, &state.pool)
+                .await
+                .map_err(|e| anyhow!("Error recording API token use: {:?}", e))
+        })
+    }
+}
+
+/// The warning sent when an API token is about to expire (see the
+/// scheduler), pointing at the tokens page.
+pub fn build_expiry_email(token: &ExpiringToken, templates: Arc<RwLock<Tera>>) -> Result<Email, Error> {
+    let domain = env::var("JELLY_DOMAIN").expect("No JELLY_DOMAIN value set!");
+    let mut context = Context::new();
+    context.insert("action_url", &format!("{}/dashboard/tokens", domain));
+    context.insert("name", &token.name.as_deref().unwrap_or("An API client"));
+    context.insert("expires", &token.expires.format("%B %-d, %Y").to_string());
+
+    Email::new(
+        "email/token-expiring",
+        &[token.email.clone()],
+        "An API token is about to expire",
+        context,
+        templates,
+    )
+}
+
+pub fn configure(config: JobConfig) -> JobConfig {
+    config.register::<Traced<RecordTokenUse>>()
+}
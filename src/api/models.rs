@@ -0,0 +1,94 @@
+use jelly::chrono::{DateTime, Utc};
+use jelly::error::Error;
+use jelly::serde::Serialize;
+use jelly::tenancy::TenantPool;
+use sqlx::postgres::PgPool;
+
+/// An API client's sign in, and how much it's been used (see
+/// `jelly::accounts::jwt`).
+#[derive(Debug, Serialize)]
+pub struct ApiToken {
+    pub id: i32,
+    pub account_id: i32,
+    pub name: Option<String>,
+    pub request_count: i64,
+    pub last_used: Option<DateTime<Utc>>,
+    pub expires: DateTime<Utc>,
+    pub created: DateTime<Utc>,
+}
+
+/// An API token about to expire, and who to tell.
+#[derive(Debug)]
+pub struct ExpiringToken {
+    pub id: i32,
+    pub account_id: i32,
+    pub name: Option<String>,
+    pub expires: DateTime<Utc>,
+    pub email: String,
+}
+
+impl ApiToken {
+    /// The tenant's API tokens that are still good, most recently used
+    /// first.
+    pub async fn for_account(db: &TenantPool<'_>) -> Result<Vec<Self>, Error> {
+        Ok(jelly::tenant_query_as!(
+            db,
+            ApiToken,
+            "
+            SELECT id, account_id, name, request_count, last_used, expires, created
+            FROM api_tokens
+            WHERE account_id = $1 AND revoked_at IS NULL AND expires > now()
+            ORDER BY last_used DESC NULLS LAST, created DESC
+        "
+        )
+        .fetch_all(db.pool())
+        .await?)
+    }
+
+    /// Counts `count` requests made with a token, the last of them `at`.
+    pub async fn record_use(id: i32, count: i64, at: DateTime<Utc>, pool: &PgPool) -> Result<(), Error> {
+        sqlx::query!(
+            "
+            UPDATE api_tokens
+            SET request_count = request_count + $2, last_used = GREATEST(last_used, $3)
+            WHERE id = $1
+        ",
+            id,
+            count,
+            at
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Tokens expiring within `days` that haven't been warned about yet,
+    /// for accounts that can still get email.
+    pub async fn expiring(days: i32, pool: &PgPool) -> Result<Vec<ExpiringToken>, Error> {
+        Ok(sqlx::query_as_unchecked!(
+            ExpiringToken,
+            "
+            SELECT t.id, t.account_id, t.name, t.expires, a.email
+            FROM api_tokens t
+            JOIN accounts a ON a.id = t.account_id
+            WHERE t.revoked_at IS NULL
+                AND t.expiry_warned_at IS NULL
+                AND t.expires > now()
+                AND t.expires <= now() + make_interval(days => $1)
+                AND a.is_active AND a.email_deliverable AND a.deleted_at IS NULL
+        ",
+            days
+        )
+        .fetch_all(pool)
+        .await?)
+    }
+
+    pub async fn mark_expiry_warned(id: i32, pool: &PgPool) -> Result<(), Error> {
+        sqlx::query!("UPDATE api_tokens SET expiry_warned_at = now() WHERE id = $1", id)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,4 @@
+//! API views.
+
+pub mod account;
+pub mod devices;
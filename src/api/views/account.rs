@@ -0,0 +1,70 @@
+use jelly::accounts::jwt;
+use jelly::actix_web::{web, HttpRequest};
+use jelly::chrono::{DateTime, Utc};
+use jelly::error::Error;
+use jelly::forms::validation::Validatable;
+use jelly::prelude::*;
+use jelly::Result;
+use serde::Serialize;
+
+use crate::accounts::Account;
+use crate::api::forms::AccountPatch;
+
+/// The public representation of an Account in the API.
+#[derive(Debug, Serialize)]
+pub struct AccountResource {
+    pub id: i32,
+    pub name: String,
+    pub email: String,
+    pub has_verified_email: bool,
+    pub created: DateTime<Utc>,
+    pub updated: DateTime<Utc>,
+}
+
+impl From<&Account> for AccountResource {
+    fn from(account: &Account) -> Self {
+        Self {
+            id: account.id,
+            name: account.name.clone(),
+            email: account.email.clone(),
+            has_verified_email: account.has_verified_email,
+            created: account.created,
+            updated: account.updated,
+        }
+    }
+}
+
+/// Returns the current account, tagged with its version.
+pub async fn show(request: HttpRequest) -> Result<HttpResponse> {
+    jwt::require_scope(&request, "account.read")?;
+    let user = request.user()?;
+    let account = Account::get(user.id, request.db_pool()?).await?;
+
+    request.versioned_json(200, account.version, AccountResource::from(&account))
+}
+
+/// Updates the current account. Clients must send the `ETag` they last saw
+/// in `If-Match`; concurrent modifications are rejected with a 412.
+pub async fn update(
+    request: HttpRequest,
+    form: web::Json<AccountPatch>,
+) -> Result<HttpResponse> {
+    jwt::require_scope(&request, "account.write")?;
+    let user = request.user()?;
+    let db = request.db_pool()?;
+    let account = Account::get(user.id, db).await?;
+    request.check_if_match(account.version)?;
+
+    let form = form.into_inner().set_keys();
+    if let Err(errors) = form.validate() {
+        return request.json(400, &errors);
+    }
+
+    // The version is checked again in the UPDATE itself, so a write that
+    // sneaks in between the read above and here still loses.
+    let account = Account::update_name_versioned(account.id, account.version, &form.name, db)
+        .await?
+        .ok_or(Error::PreconditionFailed)?;
+
+    request.versioned_json(200, account.version, AccountResource::from(&account))
+}
@@ -0,0 +1,39 @@
+use jelly::accounts::jwt;
+use jelly::actix_web::{web, HttpRequest};
+use jelly::prelude::*;
+use jelly::push;
+use jelly::serde_json::json;
+use jelly::Result;
+
+use crate::api::forms::DeviceForm;
+
+/// Device tokens are well under this; anything longer isn't one.
+const MAX_TOKEN_LENGTH: usize = 4096;
+
+/// Registers one of the current account's devices for push notifications.
+pub async fn register(request: HttpRequest, form: web::Json<DeviceForm>) -> Result<HttpResponse> {
+    jwt::require_scope(&request, "devices.write")?;
+    let user = request.user()?;
+    let form = form.into_inner();
+    if !push::providers().contains(&form.provider.as_str()) {
+        return request.json(400, json!({ "error": "unknown_provider", "providers": push::providers() }));
+    }
+    let token = form.token.trim();
+    if token.is_empty() || token.len() > MAX_TOKEN_LENGTH {
+        return request.json(400, json!({ "error": "invalid_token" }));
+    }
+
+    push::register(request.db_pool()?, user.id, &form.provider, token).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Stops push notifications to one of the current account's devices.
+pub async fn unregister(request: HttpRequest, token: web::Path<String>) -> Result<HttpResponse> {
+    jwt::require_scope(&request, "devices.write")?;
+    let user = request.user()?;
+    if push::unregister(request.db_pool()?, user.id, &token).await? {
+        Ok(HttpResponse::NoContent().finish())
+    } else {
+        Ok(HttpResponse::NotFound().finish())
+    }
+}
@@ -0,0 +1,7 @@
+//! Audit logging of security-relevant account events.
+
+pub mod anomaly;
+pub mod jobs;
+pub mod models;
+
+pub use models::AuditEvent;
@@ -0,0 +1,71 @@
+//! Heuristics for spotting logins that don't look like the account owner.
+//!
+//! What happens then is up to `jobs::AnalyzeLogin`: the owner's emailed,
+//! and, with `RESET_PASSWORD_ON_SUSPICIOUS_LOGIN` set, has to choose a new
+//! password. That's the only step up there is; there's no second factor
+//! to ask for.
+
+use jelly::chrono::Timelike;
+
+use super::AuditEvent;
+
+/// Faster than this between two logins means two different people.
+const MAX_TRAVEL_KMH: f64 = 1000.0;
+
+/// Closer than this is never impossible travel, however quick: GeoIP is
+/// only good to a city or so, and a phone and a laptop on different
+/// networks can easily be placed a few hundred kilometres apart.
+const MIN_TRAVEL_KM: f64 = 500.0;
+
+/// Logins needed before the time of day is considered meaningful.
+const MIN_LOGINS_FOR_HOURS: usize = 10;
+
+/// How close (in hours) a login has to be to a previous one's time of day.
+const HOUR_WINDOW: i64 = 2;
+
+/// Why `login` looks suspicious given the account's earlier logins
+/// (newest first). Empty if it doesn't.
+pub fn reasons(login: &AuditEvent, history: &[AuditEvent]) -> Vec<String> {
+    let mut reasons = Vec::new();
+    let location = login.location();
+
+    if let Some(location) = location.as_ref() {
+        let known: Vec<_> = history.iter().filter_map(|e| e.location()).collect();
+        if !known.is_empty()
+            && location.country.is_some()
+            && known.iter().all(|l| l.country != location.country)
+        {
+            reasons.push(format!("Signed in from a new country ({})", location.describe()));
+        }
+
+        let previous = history
+            .iter()
+            .find_map(|e| e.location().map(|l| (e.created, l)));
+        if let Some((when, previous)) = previous {
+            if let Some(km) = location.distance_km(&previous) {
+                let hours = (login.created - when).num_seconds() as f64 / 3600.0;
+                if km >= MIN_TRAVEL_KM && km / hours.max(0.01) > MAX_TRAVEL_KMH {
+                    reasons.push(format!(
+                        "Signed in {:.0} km from {} only {:.1} hours later",
+                        km,
+                        previous.describe(),
+                        hours
+                    ));
+                }
+            }
+        }
+    }
+
+    if history.len() >= MIN_LOGINS_FOR_HOURS {
+        let hour = i64::from(login.created.hour());
+        let usual = history.iter().any(|e| {
+            let diff = (i64::from(e.created.hour()) - hour).abs();
+            diff.min(24 - diff) <= HOUR_WINDOW
+        });
+        if !usual {
+            reasons.push(format!("Signed in at an unusual time ({:02}:00 UTC)", hour));
+        }
+    }
+
+    reasons
+}
@@ -0,0 +1,123 @@
+use std::env::var;
+use std::future::Future;
+use std::pin::Pin;
+
+use jelly::accounts::jwt;
+use jelly::anyhow::{anyhow, Error};
+use jelly::email::Email;
+use jelly::jobs::{Job, JobConfig, JobState, Traced, DEFAULT_QUEUE};
+use jelly::serde::{Deserialize, Serialize};
+use jelly::serde_json::json;
+use jelly::tenancy::{TenantId, TenantPool};
+use jelly::tera::Context;
+use jelly::timezones;
+
+use super::{anomaly, AuditEvent};
+use crate::accounts::Account;
+use crate::quotas::{Metric, Usage};
+
+/// Logins compared against when looking for anomalies.
+const HISTORY: i64 = 50;
+
+/// Checks a login event against the account's history, and if it looks off,
+/// records a `login.suspicious` event and lets the account owner know. With
+/// `RESET_PASSWORD_ON_SUSPICIOUS_LOGIN` set, the account also has to choose a
+/// new password.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AnalyzeLogin {
+    pub event_id: i32,
+}
+
+fn reset_on_suspicious_login() -> bool {
+    var("RESET_PASSWORD_ON_SUSPICIOUS_LOGIN").map_or(false, |v| !v.is_empty() && v != "0")
+}
+
+/// `timezone` is the account's, to show the time of the login in.
+pub fn build_context(name: &str, timezone: &str, login: &AuditEvent, reasons: &[String]) -> Context {
+    let mut context = Context::new();
+    context.insert("name", name);
+    context.insert("when", &timezones::localtime(login.created, timezone, timezones::DEFAULT_FORMAT));
+    context.insert("ip", &login.ip.clone().unwrap_or_default());
+    context.insert("location", &login.location().map(|l| l.describe()).unwrap_or_default());
+    context.insert("reasons", reasons);
+    context.insert(
+        "action_url",
+        &format!("{}/dashboard/security", var("JELLY_DOMAIN").expect("JELLY_DOMAIN not set?")),
+    );
+    context
+}
+
+impl Job for AnalyzeLogin {
+    type State = JobState;
+    type Future = Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
+
+    const NAME: &'static str = "AnalyzeLoginJob";
+    const QUEUE: &'static str = DEFAULT_QUEUE;
+
+    fn run(self, state: JobState) -> Self::Future {
+        Box::pin(async move {
+            let login = AuditEvent::get(self.event_id, &state.pool)
+                .await
+                .map_err(|e| anyhow!("Error fetching login event: {:?}", e))?;
+            let account_id = match login.account_id {
+                Some(id) => id,
+                None => return Ok(()),
+            };
+
+            let history = AuditEvent::logins_before(&login, HISTORY, &state.pool)
+                .await
+                .map_err(|e| anyhow!("Error fetching login history: {:?}", e))?;
+            let reasons = anomaly::reasons(&login, &history);
+            if reasons.is_empty() {
+                return Ok(());
+            }
+
+            let data = json!({ "login_event_id": login.id, "reasons": reasons });
+            AuditEvent::record(Some(account_id), "login.suspicious", data, &state.pool)
+                .await
+                .map_err(|e| anyhow!("Error recording suspicious login: {:?}", e))?;
+
+            // Accounts without a password have nothing to reset.
+            let flagged = reset_on_suspicious_login()
+                && Account::require_password_reset(account_id, &state.pool)
+                    .await
+                    .map_err(|e| anyhow!("Error flagging account for password reset: {:?}", e))?;
+            if flagged {
+                jwt::revoke_all(&state.pool, account_id)
+                    .await
+                    .map_err(|e| anyhow!("Error revoking refresh tokens: {:?}", e))?;
+                let data = json!({ "by": "anomaly", "login_event_id": login.id });
+                AuditEvent::record(Some(account_id), "password.reset_required", data, &state.pool)
+                    .await
+                    .map_err(|e| anyhow!("Error recording password reset requirement: {:?}", e))?;
+            }
+
+            let account = Account::get(account_id, &state.pool)
+                .await
+                .map_err(|e| anyhow!("Error fetching account for suspicious login: {:?}", e))?;
+            if !account.email_deliverable {
+                return Ok(());
+            }
+
+            let email = Email::new(
+                "email/suspicious-login",
+                &[account.email],
+                "Unusual sign in to your account",
+                build_context(&account.name, &account.profile.timezone, &login, &reasons),
+                state.templates,
+            );
+
+            email?.send()?;
+
+            Usage::record(&TenantPool::new(&state.pool, TenantId(account_id)), Metric::EmailsSent, 1)
+                .await
+                .map_err(|e| anyhow!("Error recording email usage: {:?}", e))?;
+
+            Ok(())
+        })
+    }
+}
+
+pub fn configure(config: JobConfig) -> JobConfig {
+    config.register::<Traced<AnalyzeLogin>>()
+}
@@ -0,0 +1,257 @@
+use jelly::actix_web::http::header::USER_AGENT;
+use jelly::actix_web::HttpRequest;
+use jelly::chrono::{DateTime, Utc};
+use jelly::error::Error;
+use jelly::geoip::{self, Location};
+use jelly::request::DatabasePool;
+use jelly::tenancy::TenantPool;
+use jelly::serde::{Deserialize, Serialize};
+use jelly::serde_json::{json, Value};
+use sqlx::{postgres::PgPool, types::Json};
+
+use crate::admin::AuditFilter;
+
+/// A single audit log entry. `account_id` is cleared if the account is
+/// later deleted; `data` holds whatever else is worth keeping.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditEvent {
+    pub id: i32,
+    pub account_id: Option<i32>,
+    pub kind: String,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub data: Json<Value>,
+    pub created: DateTime<Utc>,
+}
+
+impl AuditEvent {
+    /// Records an event that didn't come from a request, e.g from a job.
+    pub async fn record(
+        account_id: Option<i32>,
+        kind: &str,
+        data: Value,
+        pool: &PgPool,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            "
+            INSERT INTO audit_events (account_id, kind, data)
+            VALUES ($1, $2, $3)
+        ",
+            account_id,
+            kind,
+            Json(data) as _
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Records an event for `account_id`, along with the IP address and
+    /// user agent of the request that caused it, and where that IP is if
+    /// geolocation is set up. Returns the new event's id.
+    pub async fn record_request(
+        request: &HttpRequest,
+        account_id: i32,
+        kind: &str,
+        mut data: Value,
+    ) -> Result<i32, Error> {
+        let ip = request.connection_info().realip_remote_addr().map(String::from);
+        let location = ip.as_deref().and_then(geoip::lookup);
+        if let (Some(location), Some(fields)) = (location, data.as_object_mut()) {
+            fields.insert("location".to_string(), json!(location));
+        }
+        let user_agent = request
+            .headers()
+            .get(USER_AGENT)
+            .and_then(|ua| ua.to_str().ok())
+            .map(String::from);
+
+        Ok(sqlx::query!(
+            "
+            INSERT INTO audit_events (account_id, kind, ip, user_agent, data)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id
+        ",
+            account_id,
+            kind,
+            ip,
+            user_agent,
+            Json(data) as _
+        )
+        .fetch_one(request.db_pool()?)
+        .await?
+        .id)
+    }
+
+    pub async fn get(id: i32, pool: &PgPool) -> Result<Self, Error> {
+        Ok(sqlx::query_as_unchecked!(
+            AuditEvent,
+            "
+            SELECT
+                id, account_id, kind, ip, user_agent, data, created
+            FROM audit_events WHERE id = $1
+        ",
+            id
+        )
+        .fetch_one(pool)
+        .await?)
+    }
+
+    /// The account's most recent successful logins before `event`, newest first.
+    pub async fn logins_before(event: &AuditEvent, limit: i64, pool: &PgPool) -> Result<Vec<Self>, Error> {
+        Ok(sqlx::query_as_unchecked!(
+            AuditEvent,
+            "
+            SELECT
+                id, account_id, kind, ip, user_agent, data, created
+            FROM audit_events
+            WHERE account_id = $1
+                AND kind IN ('login', 'login.oauth')
+                AND id < $2
+            ORDER BY created DESC, id DESC
+            LIMIT $3
+        ",
+            event.account_id,
+            event.id,
+            limit
+        )
+        .fetch_all(pool)
+        .await?)
+    }
+
+    /// A page of the tenant account's events, newest first.
+    pub async fn for_account(
+        db: &TenantPool<'_>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<Self>, Error> {
+        Ok(jelly::tenant_query_as!(
+            db,
+            AuditEvent,
+            "
+            SELECT
+                id, account_id, kind, ip, user_agent, data, created
+            FROM audit_events
+            WHERE account_id = $1
+            ORDER BY created DESC, id DESC
+            LIMIT $2 OFFSET $3
+        ",
+            limit,
+            offset
+        )
+        .fetch_all(db.pool())
+        .await?)
+    }
+
+    /// Up to `limit` events matching `filter`, newest first, after the
+    /// event with id `after`. For admins; not scoped to a tenant.
+    pub async fn list(
+        filter: &AuditFilter,
+        after: Option<i64>,
+        limit: i64,
+        pool: &PgPool,
+    ) -> Result<Vec<Self>, Error> {
+        Ok(sqlx::query_as_unchecked!(
+            AuditEvent,
+            "
+            SELECT
+                e.id, e.account_id, e.kind, e.ip, e.user_agent, e.data, e.created
+            FROM audit_events e
+            LEFT JOIN accounts a ON a.id = e.account_id
+            WHERE ($1::text IS NULL OR e.kind = $1)
+                AND ($2::text IS NULL OR a.email = $2 OR e.account_id::text = $2)
+                AND ($3::bigint IS NULL OR e.id < $3)
+            ORDER BY e.id DESC
+            LIMIT $4
+        ",
+            filter.kind,
+            filter.account,
+            after,
+            limit
+        )
+        .fetch_all(pool)
+        .await?)
+    }
+
+    /// Up to `limit` of the tenant account's events, newest first, after
+    /// the event with id `after`; for exports.
+    pub async fn for_account_after(
+        db: &TenantPool<'_>,
+        after: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<Self>, Error> {
+        Ok(jelly::tenant_query_as!(
+            db,
+            AuditEvent,
+            "
+            SELECT
+                id, account_id, kind, ip, user_agent, data, created
+            FROM audit_events
+            WHERE account_id = $1 AND ($2::bigint IS NULL OR id < $2)
+            ORDER BY id DESC
+            LIMIT $3
+        ",
+            after,
+            limit
+        )
+        .fetch_all(db.pool())
+        .await?)
+    }
+
+    pub async fn count_for_account(db: &TenantPool<'_>) -> Result<i64, Error> {
+        Ok(jelly::tenant_query!(
+            db,
+            "
+            SELECT count(*) as \"count!\" FROM audit_events WHERE account_id = $1
+        "
+        )
+        .fetch_one(db.pool())
+        .await?
+        .count)
+    }
+
+    /// Where the request was made from, if it was looked up.
+    pub fn location(&self) -> Option<Location> {
+        self.data
+            .get("location")
+            .and_then(|location| jelly::serde_json::from_value(location.clone()).ok())
+    }
+
+    /// A human readable description of `kind`.
+    pub fn description(&self) -> &str {
+        match self.kind.as_str() {
+            "login" => "Signed in",
+            "login.failed" => "Failed sign in attempt",
+            "login.oauth" => "Signed in with a linked account",
+            "login.token" => "Signed in for an API token",
+            "login.recovered" => "Signed in with a recovery link",
+            "password.reset" => "Password reset",
+            "password.changed" => "Password changed",
+            "password.reset_required" => "Password reset required",
+            "email.verified" => "Email address verified",
+            "email.changed" => "Email address changed",
+            "account.deactivated" => "Account deactivated",
+            "account.reactivated" => "Account reactivated",
+            "account.deleted" => "Account deleted",
+            "upload.quarantined" => "Uploaded file quarantined",
+            "sessions.revoked" => "Signed out everywhere",
+            "login.suspicious" => "Unusual sign in flagged",
+            "login.throttled" => "Sign in blocked after too many attempts",
+            "referral.credited" => "Referral credited",
+            "admin.bulk_action" => "Bulk action on accounts",
+            "admin.bulk_action_undone" => "Bulk action on accounts undone",
+            "settings.changed" => "Setting changed",
+            "logging.changed" => "Log filter changed",
+            "roles.changed" => "Admin roles changed",
+            "role.assigned" => "Given an admin role",
+            "role.removed" => "Admin role taken away",
+            "api_token.revoked" => "API token revoked",
+            "service_account.created" => "Service account added",
+            "service_account.rotated" => "Service account secret rotated",
+            "service_account.revoked" => "Service account revoked",
+            "notifications.unsubscribed" => "Unsubscribed from notification digests",
+            kind => kind,
+        }
+    }
+}
@@ -0,0 +1,47 @@
+//! A per-account calendar feed at `/calendar.ics`, for scheduled items
+//! like renewals or maintenance windows. Signed-in users can fetch it
+//! directly; calendar apps can't sign in, so they subscribe to
+//! `feed_url`, which carries a signed token instead.
+//!
+//! ```ignore
+//! ScheduledEvent::create(Some(account.id), "Subscription renews", renews_at, None, pool).await?;
+//! ```
+
+use std::env;
+
+use jelly::actix_web::web::ServiceConfig;
+use jelly::crypto;
+use jelly::routes;
+
+pub mod models;
+pub mod views;
+
+pub use models::ScheduledEvent;
+
+/// Keeps feed tokens from being accepted anywhere else.
+const KEY_SALT: &str = "com.jelly.calendar";
+
+/// A token that lets calendar apps fetch `account_id`'s feed.
+pub fn feed_token(account_id: i32) -> String {
+    let id = account_id.to_string();
+    format!("{}.{}", id, crypto::sign(KEY_SALT, &id))
+}
+
+/// The account a feed token is for, if it's genuine.
+pub fn account_for_token(token: &str) -> Option<i32> {
+    let (id, signature) = token.split_once('.')?;
+    if !crypto::verify(KEY_SALT, id, signature) {
+        return None;
+    }
+    id.parse().ok()
+}
+
+/// The URL to subscribe to `account_id`'s feed at.
+pub fn feed_url(account_id: i32) -> String {
+    let domain = env::var("JELLY_DOMAIN").expect("No JELLY_DOMAIN value set!");
+    format!("{}/calendar.ics?token={}", domain, feed_token(account_id))
+}
+
+pub fn configure(config: &mut ServiceConfig) {
+    routes::resource("/calendar.ics").get(views::feed).mount(config);
+}
@@ -0,0 +1,80 @@
+use jelly::chrono::{DateTime, Utc};
+use jelly::error::Error;
+use jelly::ics::{Event, When};
+use jelly::serde::Serialize;
+use jelly::tenancy::TenantPool;
+use sqlx::postgres::PgPool;
+
+/// Something coming up. Without an `account_id`, everyone sees it.
+#[derive(Debug, Serialize)]
+pub struct ScheduledEvent {
+    pub id: i32,
+    pub account_id: Option<i32>,
+    pub title: String,
+    pub description: Option<String>,
+    pub starts: DateTime<Utc>,
+    pub ends: Option<DateTime<Utc>>,
+    pub created: DateTime<Utc>,
+    pub updated: DateTime<Utc>,
+}
+
+impl ScheduledEvent {
+    pub async fn create(
+        account_id: Option<i32>,
+        title: &str,
+        starts: DateTime<Utc>,
+        ends: Option<DateTime<Utc>>,
+        pool: &PgPool,
+    ) -> Result<i32, Error> {
+        Ok(sqlx::query!(
+            "
+            INSERT INTO scheduled_events (account_id, title, starts, ends)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id
+        ",
+            account_id,
+            title,
+            starts,
+            ends
+        )
+        .fetch_one(pool)
+        .await?
+        .id)
+    }
+
+    /// The tenant's events and everyone's, from the last month on.
+    pub async fn for_account(db: &TenantPool<'_>) -> Result<Vec<Self>, Error> {
+        Ok(jelly::tenant_query_as!(
+            db,
+            ScheduledEvent,
+            "
+            SELECT
+                id, account_id, title, description, starts, ends, created, updated
+            FROM scheduled_events
+            WHERE (account_id = $1 OR account_id IS NULL)
+                AND starts > now() - interval '30 days'
+            ORDER BY starts
+        "
+        )
+        .fetch_all(db.pool())
+        .await?)
+    }
+
+    /// This event, for an iCalendar feed. `host` makes the UID unique.
+    pub fn to_ics(&self, host: &str) -> Event {
+        let mut event = Event::new(
+            format!("event-{}@{}", self.id, host),
+            &self.title,
+            When::At(self.starts),
+        )
+        .updated(self.updated);
+
+        if let Some(description) = &self.description {
+            event = event.description(description);
+        }
+        if let Some(ends) = self.ends {
+            event = event.end(When::At(ends));
+        }
+        event
+    }
+}
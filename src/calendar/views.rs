@@ -0,0 +1,44 @@
+use std::env;
+
+use jelly::actix_web::{web, HttpRequest};
+use jelly::ics::{self, Calendar};
+use jelly::prelude::*;
+use jelly::tenancy::{TenantId, TenantPool};
+use jelly::Result;
+use serde::Deserialize;
+
+use super::{account_for_token, ScheduledEvent};
+
+#[derive(Deserialize)]
+pub struct FeedQuery {
+    pub token: Option<String>,
+}
+
+/// The account's upcoming events as iCalendar. Authenticated by a feed
+/// token if there is one, otherwise by the session.
+pub async fn feed(request: HttpRequest, query: web::Query<FeedQuery>) -> Result<HttpResponse> {
+    let account_id = match &query.token {
+        Some(token) => account_for_token(token),
+        None if request.is_authenticated()? => Some(request.user()?.id),
+        None => None,
+    };
+
+    let account_id = match account_id {
+        Some(account_id) => account_id,
+        None => return request.render(404, "404.html", Context::new()),
+    };
+
+    let db = TenantPool::new(request.db_pool()?, TenantId(account_id));
+    let events = ScheduledEvent::for_account(&db).await?;
+
+    let domain = env::var("JELLY_DOMAIN").expect("No JELLY_DOMAIN value set!");
+    let host = domain.rsplit("://").next().unwrap_or(&domain);
+    let mut calendar = Calendar::new(host);
+    for event in events.iter() {
+        calendar.push(event.to_ics(host));
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ics::CONTENT_TYPE)
+        .body(calendar.to_string()))
+}
@@ -1,19 +1,55 @@
 //! Admin dashboard.
 
-use jelly::actix_web::web::{resource, scope, ServiceConfig};
-use jelly::guards::Auth;
+use jelly::actix_web::web::ServiceConfig;
+use jelly::routes::{resource, scope};
 
+mod forms;
 mod views;
 
 pub fn configure(config: &mut ServiceConfig) {
-    let guard = Auth {
-        redirect_to: "/accounts/login",
-    };
-
-    config.service(
-        scope("/dashboard")
-            .wrap(guard)
-            // Index
-            .service(resource("").to(views::dashboard)),
-    );
+    scope("/dashboard")
+        .auth()
+        // Index
+        .resource(resource("").to(views::dashboard))
+        .resource(resource("/identities").get(views::identities::identities))
+        .resource(resource("/notifications").get(views::notifications::notifications))
+        .resource(resource("/notifications/read").post(views::notifications::mark_all_read))
+        .resource(
+            resource("/preferences")
+                .get(views::preferences::form)
+                .post(views::preferences::save),
+        )
+        .resource(
+            resource("/profile")
+                .get(views::profile::form)
+                .post(views::profile::save),
+        )
+        .resource(resource("/progress").get(views::progress::events))
+        .resource(resource("/referrals").get(views::referrals::referrals))
+        .resource(resource("/security").get(views::security::history))
+        .resource(resource("/security.csv").get(views::security::export))
+        .resource(
+            resource("/service-accounts")
+                .get(views::service_accounts::service_accounts)
+                .post(views::service_accounts::create),
+        )
+        .resource(resource("/service-accounts/{id}/revoke").post(views::service_accounts::revoke))
+        .resource(resource("/service-accounts/{id}/rotate").post(views::service_accounts::rotate))
+        .resource(resource("/sessions").get(views::sessions::sessions))
+        .resource(resource("/sessions/revoke").post(views::sessions::revoke_all))
+        .resource(resource("/sessions/{id}/revoke").post(views::sessions::revoke))
+        .resource(resource("/tokens").get(views::tokens::tokens))
+        .resource(resource("/tokens/{id}/revoke").post(views::tokens::revoke))
+        .resource(resource("/usage").get(views::usage::usage))
+        .resource(resource("/usage.csv").get(views::usage::export))
+        .resource(resource("/usage/receipt").post(views::usage::receipt))
+        .resource(
+            resource("/webhooks")
+                .get(views::webhooks::webhooks)
+                .post(views::webhooks::create),
+        )
+        .resource(resource("/webhooks/{id}/delete").post(views::webhooks::delete))
+        .resource(resource("/webhooks/{id}/rotate").post(views::webhooks::rotate))
+        .resource(resource("/webhooks/{id}/test").post(views::webhooks::test))
+        .mount(config);
 }
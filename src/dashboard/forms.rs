@@ -0,0 +1,228 @@
+use jelly::actix_web::web::Query;
+use jelly::crypto::Encrypted;
+use jelly::forms::BoolField;
+use jelly::forms::validation::{concat_results, Validatable, ValidationError, ValidationErrors};
+use jelly::serde_json::{json, Value};
+use jelly::timezones;
+use jelly::webhooks;
+use serde::{Deserialize, Serialize};
+
+use crate::accounts::models::{Digest, NotificationPreferences, Profile, ProfilePrivacy};
+use crate::api::SCOPES;
+
+/// Whether `url` is empty, or a http(s) one (so it's safe to link to).
+fn is_http_url(url: &str) -> bool {
+    url.is_empty() || url.starts_with("https://") || url.starts_with("http://")
+}
+
+fn is_https_url(url: &str) -> bool {
+    url.is_empty() || url.starts_with("https://")
+}
+
+/// Whether `locale` looks like a language tag: `en`, `en-GB`, `zh-Hant-TW`.
+fn is_locale(locale: &str) -> bool {
+    let mut subtags = locale.split('-');
+    let language = subtags.next().unwrap_or_default();
+    (2..=3).contains(&language.len())
+        && language.chars().all(|c| c.is_ascii_alphabetic())
+        && subtags.all(|subtag| (2..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+fn invalid(key: &str, code: &'static str, message: &'static str) -> Result<(), ValidationErrors<String>> {
+    Err(ValidationError::new(key.to_owned(), code)
+        .with_message(move |_| message.to_owned())
+        .into())
+}
+
+/// Profile details.
+#[derive(Default, Debug, Deserialize, Serialize)]
+pub struct ProfileForm {
+    #[serde(default)]
+    pub bio: String,
+    #[serde(default)]
+    pub avatar_url: String,
+    #[serde(default)]
+    pub location: String,
+    #[serde(default)]
+    pub website: String,
+    #[serde(default)]
+    pub timezone: String,
+    #[serde(default)]
+    pub locale: String,
+}
+
+impl ProfileForm {
+    pub fn from_profile(profile: &Profile) -> Self {
+        ProfileForm {
+            bio: profile.bio.clone(),
+            avatar_url: profile.avatar_url.clone(),
+            location: profile.location.plaintext().clone(),
+            website: profile.website.clone(),
+            timezone: profile.timezone.clone(),
+            locale: profile.locale.clone(),
+        }
+    }
+
+    /// The profile fields this form sets; see `Account::update_profile`.
+    pub fn changes(&self) -> Value {
+        json!({
+            "bio": self.bio.trim(),
+            "avatar_url": self.avatar_url.trim(),
+            "location": Encrypted(self.location.trim().to_string()).sealed(),
+            "website": self.website.trim(),
+            "timezone": self.timezone.trim(),
+            "locale": self.locale.trim(),
+        })
+    }
+}
+
+impl Validatable<String> for ProfileForm {
+    fn validate(&self) -> Result<(), ValidationErrors<String>> {
+        // Avatars are loaded on our (https) pages, so they have to be https
+        // too; their hosts also need allowing in the CSP's `img-src` (see
+        // `guards::csp::DEFAULT_POLICY`). Websites are only linked to.
+        let avatar_url = if is_https_url(self.avatar_url.trim()) {
+            Ok(())
+        } else {
+            invalid("avatar_url", "INVALID_URL", "avatar URL must start with https://")
+        };
+        let website = if is_http_url(self.website.trim()) {
+            Ok(())
+        } else {
+            invalid("website", "INVALID_URL", "website must start with http:// or https://")
+        };
+
+        let timezone = self.timezone.trim();
+        let timezone = if timezone.is_empty() || timezones::is_valid(timezone) {
+            Ok(())
+        } else {
+            invalid("timezone", "INVALID_TIMEZONE", "choose a time zone from the list")
+        };
+
+        let locale = self.locale.trim();
+        let locale = if locale.is_empty() || is_locale(locale) {
+            Ok(())
+        } else {
+            invalid("locale", "INVALID_LOCALE", "use a language code like en or en-GB")
+        };
+
+        concat_results(vec![avatar_url, website, timezone, locale])
+    }
+}
+
+/// What's shown on the public profile page, and how often notification
+/// digests are sent. Checkboxes are absent when unchecked, hence the
+/// defaults.
+#[derive(Default, Debug, Deserialize, Serialize)]
+pub struct PreferencesForm {
+    #[serde(default)]
+    pub public: BoolField,
+    #[serde(default)]
+    pub show_location: BoolField,
+    #[serde(default)]
+    pub show_website: BoolField,
+    /// How often to email a digest of unread notifications: `off`,
+    /// `daily` or `weekly`. Anything else is taken as off.
+    #[serde(default)]
+    pub digest: String,
+}
+
+impl PreferencesForm {
+    pub fn from_profile(profile: &Profile) -> Self {
+        PreferencesForm {
+            public: BoolField::new(profile.privacy.public),
+            show_location: BoolField::new(profile.privacy.show_location),
+            show_website: BoolField::new(profile.privacy.show_website),
+            digest: profile.notifications.digest.as_str().to_string(),
+        }
+    }
+
+    /// The profile fields this form sets; see `Account::update_profile`.
+    pub fn changes(&self) -> Value {
+        json!({
+            "privacy": ProfilePrivacy {
+                public: self.public.value,
+                show_location: self.show_location.value,
+                show_website: self.show_website.value,
+            },
+            "notifications": NotificationPreferences {
+                digest: Digest::parse(&self.digest).unwrap_or_default(),
+            },
+        })
+    }
+}
+
+/// Adding a webhook endpoint.
+#[derive(Default, Debug, Deserialize, Serialize)]
+pub struct WebhookEndpointForm {
+    #[serde(default)]
+    pub url: String,
+}
+
+impl Validatable<String> for WebhookEndpointForm {
+    fn validate(&self) -> Result<(), ValidationErrors<String>> {
+        // Events are signed, but not encrypted, so only https. Hosts are
+        // resolved and checked as well when the endpoint's added (and on
+        // every delivery); see `jelly::webhooks::check_url`.
+        match webhooks::host_and_port(&self.url) {
+            None => invalid("url", "INVALID_URL", "url must start with https://"),
+            Some((host, _)) if host == "localhost" || host.parse().map_or(false, |ip| !webhooks::is_public(ip)) => {
+                invalid("url", "PRIVATE_URL", "url must be a public address")
+            }
+            Some(_) => Ok(()),
+        }
+    }
+}
+
+/// The longest a service account's name can be.
+const MAX_SERVICE_ACCOUNT_NAME: usize = 100;
+
+/// A new service account: its name, and a checkbox per scope, each
+/// sending its name as `scopes`. `web::Form` can't read repeated fields,
+/// so it's parsed here; scopes the API doesn't have are skipped.
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ServiceAccountForm {
+    pub name: String,
+    pub scopes: Vec<&'static str>,
+}
+
+impl ServiceAccountForm {
+    pub fn parse(body: &[u8]) -> Self {
+        let mut form = ServiceAccountForm::default();
+        let fields = Query::<Vec<(String, String)>>::from_query(std::str::from_utf8(body).unwrap_or(""))
+            .map(|fields| fields.into_inner())
+            .unwrap_or_default();
+
+        for (name, value) in fields {
+            match name.as_str() {
+                "name" => form.name = value.trim().to_string(),
+                "scopes" => {
+                    let scope = SCOPES.iter().map(|(scope, _)| *scope).find(|scope| *scope == value);
+                    if let Some(scope) = scope {
+                        if !form.scopes.contains(&scope) {
+                            form.scopes.push(scope);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        form
+    }
+}
+
+impl Validatable<String> for ServiceAccountForm {
+    fn validate(&self) -> Result<(), ValidationErrors<String>> {
+        let name = if self.name.is_empty() || self.name.chars().count() > MAX_SERVICE_ACCOUNT_NAME {
+            invalid("name", "INVALID_NAME", "Give it a name, of at most 100 characters.")
+        } else {
+            Ok(())
+        };
+        let scopes = if self.scopes.is_empty() {
+            invalid("scopes", "NO_SCOPES", "Choose at least one scope.")
+        } else {
+            Ok(())
+        };
+        concat_results(vec![name, scopes])
+    }
+}
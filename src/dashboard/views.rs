@@ -2,3 +2,16 @@
 
 mod dashboard;
 pub use dashboard::dashboard;
+
+pub mod identities;
+pub mod notifications;
+pub mod preferences;
+pub mod profile;
+pub mod progress;
+pub mod referrals;
+pub mod security;
+pub mod service_accounts;
+pub mod sessions;
+pub mod tokens;
+pub mod usage;
+pub mod webhooks;
@@ -1,9 +1,21 @@
 use jelly::prelude::*;
 use jelly::Result;
 
+use crate::accounts::{oauth_only, Account};
+use crate::calendar;
+
 /// Returns an overview of everything in the system.
 pub async fn dashboard(request: HttpRequest) -> Result<HttpResponse> {
-    //let user = request.user()?;
+    let user = request.user()?;
+    let db = request.db_pool()?;
+    let account = Account::get(user.id, db).await?;
+    let oauth_only = oauth_only(db).await?;
 
-    request.render(200, "dashboard/index.html", Context::new())
+    request.render(200, "dashboard/index.html", {
+        let mut ctx = Context::new();
+        ctx.insert("account", &account);
+        ctx.insert("calendar_url", &calendar::feed_url(account.id));
+        ctx.insert("oauth_only", &oauth_only);
+        ctx
+    })
 }
@@ -0,0 +1,50 @@
+use jelly::actix_web::HttpRequest;
+use jelly::chrono::{DateTime, Utc};
+use jelly::prelude::*;
+use jelly::Result;
+use serde::Serialize;
+
+use crate::accounts::models::Identity;
+use crate::oauth::enabled_providers;
+
+/// An identity linked to the account, as shown to it. Its refresh token
+/// stays out of the page.
+#[derive(Serialize)]
+pub struct LinkedIdentity {
+    pub provider: String,
+    pub username: String,
+    pub name: Option<String>,
+    pub scopes: Vec<String>,
+    pub linked: DateTime<Utc>,
+}
+
+impl From<&Identity> for LinkedIdentity {
+    fn from(identity: &Identity) -> Self {
+        Self {
+            provider: identity.provider.clone(),
+            username: identity.username.clone(),
+            name: identity.name.clone(),
+            scopes: identity.scopes.clone(),
+            linked: identity.created,
+        }
+    }
+}
+
+/// The providers the account can sign in with, and the ones it could link.
+pub async fn identities(request: HttpRequest) -> Result<HttpResponse> {
+    let db = request.db_pool()?;
+    let identities = Identity::linked_to_account_id(request.user()?.id, db).await?;
+    let linkable: Vec<&str> = enabled_providers(db)
+        .await?
+        .into_iter()
+        .filter(|provider| !identities.iter().any(|identity| identity.provider == *provider))
+        .collect();
+    let identities: Vec<LinkedIdentity> = identities.iter().map(LinkedIdentity::from).collect();
+
+    request.render(200, "dashboard/identities.html", {
+        let mut ctx = Context::new();
+        ctx.insert("identities", &identities);
+        ctx.insert("linkable", &linkable);
+        ctx
+    })
+}
@@ -0,0 +1,24 @@
+use jelly::actix_web::HttpRequest;
+use jelly::prelude::*;
+use jelly::Result;
+
+use crate::notifications::Notification;
+
+/// The account's notifications from the last month.
+pub async fn notifications(request: HttpRequest) -> Result<HttpResponse> {
+    let notifications = Notification::for_account(&request.tenant_pool()?).await?;
+    let unread = notifications.iter().filter(|n| n.read_at.is_none()).count();
+
+    request.render(200, "dashboard/notifications.html", {
+        let mut ctx = Context::new();
+        ctx.insert("notifications", &notifications);
+        ctx.insert("unread", &unread);
+        ctx
+    })
+}
+
+/// Marks them all read, which also keeps them out of digests.
+pub async fn mark_all_read(request: HttpRequest) -> Result<HttpResponse> {
+    Notification::mark_all_read(&request.tenant_pool()?).await?;
+    request.redirect("/dashboard/notifications")
+}
@@ -0,0 +1,31 @@
+use jelly::actix_web::{web, HttpRequest};
+use jelly::prelude::*;
+use jelly::Result;
+
+use crate::accounts::Account;
+use crate::dashboard::forms::PreferencesForm;
+
+/// Privacy preferences, i.e what's shown on the public profile page, and
+/// notification digests.
+pub async fn form(request: HttpRequest) -> Result<HttpResponse> {
+    let user = request.user()?;
+    let account = Account::get(user.id, request.db_pool()?).await?;
+
+    request.render(200, "dashboard/preferences.html", {
+        let mut ctx = Context::new();
+        ctx.insert("form", &PreferencesForm::from_profile(&account.profile));
+        ctx.insert("username", &account.username);
+        ctx
+    })
+}
+
+/// POST-handler for saving preferences.
+pub async fn save(
+    request: HttpRequest,
+    form: web::Form<PreferencesForm>,
+) -> Result<HttpResponse> {
+    let user = request.user()?;
+    Account::update_profile(user.id, &form.changes(), request.db_pool()?).await?;
+    request.flash("Preferences", "Your preferences have been saved.")?;
+    request.redirect("/dashboard/preferences")
+}
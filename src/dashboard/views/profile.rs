@@ -0,0 +1,51 @@
+use jelly::actix_web::{web, HttpRequest};
+use jelly::forms::validation::Validatable;
+use jelly::prelude::*;
+use jelly::timezones;
+use jelly::Result;
+
+use crate::accounts::Account;
+use crate::dashboard::forms::ProfileForm;
+
+/// Profile details: bio, avatar, where they are and their language.
+pub async fn form(request: HttpRequest) -> Result<HttpResponse> {
+    let user = request.user()?;
+    let account = Account::get(user.id, request.db_pool()?).await?;
+
+    request.render(200, "dashboard/profile.html", {
+        let mut ctx = Context::new();
+        ctx.insert("form", &ProfileForm::from_profile(&account.profile));
+        ctx.insert("email", &account.email);
+        ctx.insert("timezones", &timezones::names().collect::<Vec<_>>());
+        ctx
+    })
+}
+
+/// POST-handler for saving the profile.
+pub async fn save(
+    request: HttpRequest,
+    form: web::Form<ProfileForm>,
+) -> Result<HttpResponse> {
+    let user = request.user()?;
+    let form = form.into_inner();
+    let db = request.db_pool()?;
+
+    if let Err(errors) = form.validate() {
+        let account = Account::get(user.id, db).await?;
+        return request.render(400, "dashboard/profile.html", {
+            let mut context = Context::new();
+
+            // ValidationErrors object is serialized into HashMap here
+            context.insert("errors", &errors);
+            context.insert("form", &form);
+            context.insert("email", &account.email);
+            context.insert("timezones", &timezones::names().collect::<Vec<_>>());
+            context
+        });
+    }
+
+    Account::update_profile(user.id, &form.changes(), db).await?;
+    request.set_timezone(form.timezone.trim())?;
+    request.flash("Profile", "Your profile has been saved.")?;
+    request.redirect("/dashboard/profile")
+}
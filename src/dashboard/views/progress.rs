@@ -0,0 +1,18 @@
+use jelly::actix_web::http::header::CACHE_CONTROL;
+use jelly::actix_web::HttpRequest;
+use jelly::prelude::*;
+use jelly::progress;
+use jelly::Result;
+
+/// Live progress of the account's background jobs, as server-sent events
+/// for the widget on dashboard pages. The stream ends once nothing's
+/// running.
+pub async fn events(request: HttpRequest) -> Result<HttpResponse> {
+    let user = request.user()?;
+    let pool = request.db_pool()?.clone();
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((CACHE_CONTROL, "no-cache"))
+        .streaming(progress::events(pool, user.id)))
+}
@@ -0,0 +1,27 @@
+use std::env::var;
+
+use jelly::actix_web::HttpRequest;
+use jelly::prelude::*;
+use jelly::Result;
+
+use crate::referrals::{ReferralCode, ReferralStats};
+
+/// The current account's referral link, and how many have signed up with it.
+pub async fn referrals(request: HttpRequest) -> Result<HttpResponse> {
+    let db = request.tenant_pool()?;
+    let code = ReferralCode::for_account(&db).await?;
+    let stats = ReferralStats::for_account(&db).await?;
+    let link = format!(
+        "{}/accounts/register?ref={}",
+        var("JELLY_DOMAIN").expect("JELLY_DOMAIN not set?"),
+        code.code
+    );
+
+    request.render(200, "dashboard/referrals.html", {
+        let mut ctx = Context::new();
+        ctx.insert("code", &code.code);
+        ctx.insert("link", &link);
+        ctx.insert("stats", &stats);
+        ctx
+    })
+}
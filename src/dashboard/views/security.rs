@@ -0,0 +1,96 @@
+use jelly::actix_web::{web, HttpRequest};
+use jelly::chrono::{DateTime, Utc};
+use jelly::export::{self, Row};
+use jelly::prelude::*;
+use jelly::tenancy::TenantPool;
+use jelly::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::audit::AuditEvent;
+
+const PER_PAGE: i64 = 25;
+
+#[derive(Deserialize)]
+pub struct PageQuery {
+    pub page: Option<i64>,
+}
+
+/// An audit log entry, as shown to the account it's about.
+#[derive(Serialize)]
+pub struct SecurityEvent {
+    pub id: i32,
+    pub created: DateTime<Utc>,
+    pub description: String,
+    pub ip: String,
+    pub location: String,
+    pub device: String,
+}
+
+impl From<&AuditEvent> for SecurityEvent {
+    fn from(event: &AuditEvent) -> Self {
+        Self {
+            id: event.id,
+            created: event.created,
+            description: event.description().to_string(),
+            ip: event.ip.clone().unwrap_or_default(),
+            location: event.location().map(|l| l.describe()).unwrap_or_default(),
+            device: event.user_agent.clone().unwrap_or_default(),
+        }
+    }
+}
+
+impl Row for SecurityEvent {
+    fn headers() -> &'static [&'static str] {
+        &["time", "event", "ip", "location", "device"]
+    }
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.created.to_rfc3339(),
+            self.description.clone(),
+            self.ip.clone(),
+            self.location.clone(),
+            self.device.clone(),
+        ]
+    }
+
+    fn cursor(&self) -> i64 {
+        self.id.into()
+    }
+}
+
+/// Recent logins and other security events for the current account.
+pub async fn history(request: HttpRequest, query: web::Query<PageQuery>) -> Result<HttpResponse> {
+    let db = request.tenant_pool()?;
+    let page = query.page.unwrap_or(1).max(1);
+
+    let total = AuditEvent::count_for_account(&db).await?;
+    let events: Vec<SecurityEvent> = AuditEvent::for_account(&db, PER_PAGE, (page - 1) * PER_PAGE)
+        .await?
+        .iter()
+        .map(SecurityEvent::from)
+        .collect();
+
+    request.render(200, "dashboard/security.html", {
+        let mut ctx = Context::new();
+        ctx.insert("events", &events);
+        ctx.insert("page", &page);
+        ctx.insert("has_next", &(page * PER_PAGE < total));
+        ctx
+    })
+}
+
+/// The full history as a CSV download, streamed a page at a time.
+pub async fn export(request: HttpRequest) -> Result<HttpResponse> {
+    let tenant = request.tenant_pool()?.tenant();
+    let pool = request.db_pool()?.clone();
+
+    Ok(export::csv("security-history.csv", move |after, limit| {
+        let pool = pool.clone();
+        async move {
+            let db = TenantPool::new(&pool, tenant);
+            let events = AuditEvent::for_account_after(&db, after, limit).await?;
+            Ok::<_, Error>(events.iter().map(SecurityEvent::from).collect::<Vec<_>>())
+        }
+    }))
+}
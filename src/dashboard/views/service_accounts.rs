@@ -0,0 +1,99 @@
+use jelly::accounts::service::{self, Credentials};
+use jelly::actix_web::web::{Bytes, Path};
+use jelly::actix_web::HttpRequest;
+use jelly::forms::validation::{Validatable, ValidationErrors};
+use jelly::prelude::*;
+use jelly::serde_json::json;
+use jelly::Result;
+
+use crate::api::SCOPES;
+use crate::audit::AuditEvent;
+use crate::dashboard::forms::ServiceAccountForm;
+
+/// The most service accounts an account can have.
+const MAX_SERVICE_ACCOUNTS: usize = 10;
+
+/// The page, with `credentials` shown when they've just been made: it's
+/// the only time the secret's shown.
+async fn render_page(
+    request: &HttpRequest,
+    status: usize,
+    form: &ServiceAccountForm,
+    errors: Option<ValidationErrors<String>>,
+    credentials: Option<&Credentials>,
+) -> Result<HttpResponse> {
+    let user = request.user()?;
+    let service_accounts = service::list(request.db_pool()?, user.id).await?;
+
+    request.render(status, "dashboard/service_accounts.html", {
+        let mut ctx = Context::new();
+        ctx.insert("service_accounts", &service_accounts);
+        ctx.insert("scopes", SCOPES);
+        ctx.insert("form", form);
+        if let Some(errors) = errors {
+            ctx.insert("errors", &errors);
+        }
+        if let Some(credentials) = credentials {
+            ctx.insert("credentials", credentials);
+        }
+        ctx
+    })
+}
+
+/// The account's service accounts, for integrations to use the API as.
+pub async fn service_accounts(request: HttpRequest) -> Result<HttpResponse> {
+    render_page(&request, 200, &ServiceAccountForm::default(), None, None).await
+}
+
+pub async fn create(request: HttpRequest, body: Bytes) -> Result<HttpResponse> {
+    let form = ServiceAccountForm::parse(&body);
+    if let Err(errors) = form.validate() {
+        return render_page(&request, 400, &form, Some(errors), None).await;
+    }
+
+    let user = request.user()?;
+    let db = request.db_pool()?;
+    if service::list(db, user.id).await?.len() >= MAX_SERVICE_ACCOUNTS {
+        request.flash(
+            "Service Accounts",
+            &format!("You can have at most {} service accounts.", MAX_SERVICE_ACCOUNTS),
+        )?;
+        return request.redirect("/dashboard/service-accounts");
+    }
+
+    let scopes: Vec<String> = form.scopes.iter().map(|scope| scope.to_string()).collect();
+    let (id, credentials) = service::create(db, user.id, &form.name, &scopes).await?;
+    AuditEvent::record_request(
+        &request,
+        user.id,
+        "service_account.created",
+        json!({ "service_account_id": id, "scopes": scopes }),
+    )
+    .await?;
+
+    render_page(&request, 200, &ServiceAccountForm::default(), None, Some(&credentials)).await
+}
+
+/// Gives a service account a new secret, shown this once. The old one
+/// keeps working for a while, so the integration can be updated meanwhile.
+pub async fn rotate(request: HttpRequest, id: Path<i32>) -> Result<HttpResponse> {
+    let (id, user) = (id.into_inner(), request.user()?);
+    let credentials = match service::rotate(request.db_pool()?, user.id, id).await? {
+        Some(credentials) => credentials,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    AuditEvent::record_request(&request, user.id, "service_account.rotated", json!({ "service_account_id": id })).await?;
+
+    render_page(&request, 200, &ServiceAccountForm::default(), None, Some(&credentials)).await
+}
+
+/// Stops a service account getting tokens. Ones it already has run out
+/// on their own, shortly.
+pub async fn revoke(request: HttpRequest, id: Path<i32>) -> Result<HttpResponse> {
+    let (id, user) = (id.into_inner(), request.user()?);
+    service::revoke(request.db_pool()?, user.id, id).await?;
+    AuditEvent::record_request(&request, user.id, "service_account.revoked", json!({ "service_account_id": id })).await?;
+
+    request.flash("Service Accounts", "That service account has been revoked.")?;
+    request.redirect("/dashboard/service-accounts")
+}
@@ -0,0 +1,46 @@
+use jelly::accounts::jwt;
+use jelly::actix_web::web::Path;
+use jelly::actix_web::HttpRequest;
+use jelly::prelude::*;
+use jelly::serde_json::json;
+use jelly::{Result, SESSION_ID};
+
+use crate::accounts::UserSession;
+use crate::audit::AuditEvent;
+
+/// Where the current account is signed in.
+pub async fn sessions(request: HttpRequest) -> Result<HttpResponse> {
+    let sessions = UserSession::for_account(&request.tenant_pool()?).await?;
+    let current = request.get_session().get::<String>(SESSION_ID)?;
+
+    request.render(200, "dashboard/sessions.html", {
+        let mut ctx = Context::new();
+        ctx.insert("sessions", &sessions);
+        ctx.insert("current", &current);
+        ctx
+    })
+}
+
+/// Signs out one session.
+pub async fn revoke(request: HttpRequest, id: Path<String>) -> Result<HttpResponse> {
+    UserSession::revoke(&request.tenant_pool()?, &id).await?;
+
+    if request.get_session().get::<String>(SESSION_ID)?.as_deref() == Some(id.as_str()) {
+        request.get_session().clear();
+        return request.redirect("/accounts/login");
+    }
+
+    request.flash("Sessions", "That session has been signed out.")?;
+    request.redirect("/dashboard/sessions")
+}
+
+/// Signs out every session, this one included.
+pub async fn revoke_all(request: HttpRequest) -> Result<HttpResponse> {
+    let user = request.user()?;
+    let count = UserSession::revoke_all(&request.tenant_pool()?).await?;
+    jwt::revoke_all(request.db_pool()?, user.id).await?;
+    AuditEvent::record_request(&request, user.id, "sessions.revoked", json!({ "count": count })).await?;
+
+    request.get_session().clear();
+    request.redirect("/accounts/login")
+}
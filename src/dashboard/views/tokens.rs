@@ -0,0 +1,30 @@
+use jelly::accounts::jwt;
+use jelly::actix_web::web::Path;
+use jelly::actix_web::HttpRequest;
+use jelly::prelude::*;
+use jelly::serde_json::json;
+use jelly::Result;
+
+use crate::api::models::ApiToken;
+use crate::audit::AuditEvent;
+
+/// The current account's API tokens, and how much they've been used.
+pub async fn tokens(request: HttpRequest) -> Result<HttpResponse> {
+    let tokens = ApiToken::for_account(&request.tenant_pool()?).await?;
+
+    request.render(200, "dashboard/tokens.html", {
+        let mut ctx = Context::new();
+        ctx.insert("tokens", &tokens);
+        ctx
+    })
+}
+
+/// Signs out one API client.
+pub async fn revoke(request: HttpRequest, id: Path<i32>) -> Result<HttpResponse> {
+    let (id, user) = (id.into_inner(), request.user()?);
+    jwt::revoke(request.db_pool()?, user.id, id).await?;
+    AuditEvent::record_request(&request, user.id, "api_token.revoked", json!({ "api_token_id": id })).await?;
+
+    request.flash("API Tokens", "That token has been revoked.")?;
+    request.redirect("/dashboard/tokens")
+}
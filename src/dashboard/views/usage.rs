@@ -0,0 +1,97 @@
+use jelly::actix_web::http::header::CONTENT_DISPOSITION;
+use jelly::actix_web::{web, HttpRequest};
+use jelly::chrono::{Datelike, NaiveDate, Utc};
+use jelly::pdf::{Notify, RenderPdf};
+use jelly::prelude::*;
+use jelly::progress;
+use jelly::Result;
+use serde::Deserialize;
+
+use crate::accounts::Account;
+use crate::metering::DailyUsage;
+use crate::quotas::Usage;
+
+#[derive(Deserialize)]
+pub struct PeriodQuery {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+impl PeriodQuery {
+    /// The period asked for, defaulting to the current month so far.
+    fn period(&self) -> (NaiveDate, NaiveDate) {
+        let today = Utc::today().naive_utc();
+        let from = self
+            .from
+            .unwrap_or_else(|| NaiveDate::from_ymd(today.year(), today.month(), 1));
+        (from, self.to.unwrap_or(today))
+    }
+}
+
+/// The current account's usage against its quotas.
+pub async fn usage(request: HttpRequest) -> Result<HttpResponse> {
+    let usage = Usage::for_account(&request.tenant_pool()?).await?;
+
+    request.render(200, "dashboard/usage.html", {
+        let mut ctx = Context::new();
+        ctx.insert("usage", &usage);
+        ctx
+    })
+}
+
+/// Metered usage as invoice line items, one per metric per day, for a CSV
+/// download. Defaults to the current month; today's usage isn't in it until
+/// the day is over.
+pub async fn export(request: HttpRequest, query: web::Query<PeriodQuery>) -> Result<HttpResponse> {
+    let (from, to) = query.period();
+    let items = DailyUsage::for_account(&request.tenant_pool()?, from, to).await?;
+
+    let rows: String = items
+        .iter()
+        .map(|item| format!("{},{},{}\r\n", item.day, item.metric, item.quantity))
+        .collect();
+    let csv = format!("date,metric,quantity\r\n{}", rows);
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header((
+            CONTENT_DISPOSITION,
+            format!("attachment; filename=\"usage-{}-{}.csv\"", from, to),
+        ))
+        .body(csv))
+}
+
+/// Queues a PDF receipt of metered usage for the same period as `export`,
+/// which is emailed to the account as a download link once it's rendered.
+pub async fn receipt(request: HttpRequest, query: web::Query<PeriodQuery>) -> Result<HttpResponse> {
+    let (from, to) = query.period();
+    let db = request.tenant_pool()?;
+    let items = DailyUsage::for_account(&db, from, to).await?;
+    let account = Account::get(db.tenant().get(), db.pool()).await?;
+
+    let mut context = Context::new();
+    context.insert("name", &account.name);
+    context.insert("from", &from);
+    context.insert("to", &to);
+    context.insert("items", &items);
+
+    let progress = progress::start(db.pool(), account.id, "Usage receipt").await?;
+    request
+        .job_queue()?
+        .queue(RenderPdf {
+            template: "receipts/usage.html".to_string(),
+            context: context.into_json(),
+            tenant: db.tenant(),
+            name: format!("receipts/usage-{}-{}.pdf", from, to),
+            notify: Some(Notify {
+                to: account.email,
+                subject: "Your usage receipt".to_string(),
+                template: "email/receipt-ready".to_string(),
+            }),
+            progress: Some(progress),
+        })
+        .await?;
+
+    request.flash("Receipt", "We're preparing your receipt, and will email you a link to it shortly.")?;
+    request.redirect("/dashboard/usage")
+}
@@ -0,0 +1,132 @@
+use jelly::actix_web::web::{Form, Path};
+use jelly::actix_web::HttpRequest;
+use jelly::chrono::{DateTime, Utc};
+use jelly::forms::validation::{Validatable, ValidationError, ValidationErrors};
+use jelly::prelude::*;
+use jelly::serde_json::json;
+use jelly::webhooks::{self, TEST_EVENT};
+use jelly::Result;
+use serde::Serialize;
+
+use crate::audit::AuditEvent;
+use crate::dashboard::forms::WebhookEndpointForm;
+use crate::webhooks::jobs::deliver;
+use crate::webhooks::{WebhookDelivery, WebhookEndpoint};
+
+/// How many deliveries the log shows.
+const SHOWN: i64 = 50;
+
+/// The most endpoints an account can have.
+const MAX_ENDPOINTS: usize = 10;
+
+/// An endpoint, as listed.
+#[derive(Serialize)]
+struct EndpointRow<'a> {
+    #[serde(flatten)]
+    endpoint: &'a WebhookEndpoint,
+    previous_secret_until: Option<DateTime<Utc>>,
+}
+
+async fn render_page(
+    request: &HttpRequest,
+    status: usize,
+    form: &WebhookEndpointForm,
+    errors: Option<ValidationErrors<String>>,
+) -> Result<HttpResponse> {
+    let user = request.user()?;
+    let db = request.db_pool()?;
+    let endpoints = WebhookEndpoint::for_account(user.id, db).await?;
+    let deliveries = WebhookDelivery::recent(user.id, SHOWN, db).await?;
+
+    request.render(status, "dashboard/webhooks.html", {
+        let mut ctx = Context::new();
+        let endpoints: Vec<EndpointRow> = endpoints
+            .iter()
+            .map(|endpoint| EndpointRow {
+                endpoint,
+                previous_secret_until: endpoint.previous_secret_until(),
+            })
+            .collect();
+        ctx.insert("endpoints", &endpoints);
+        ctx.insert("deliveries", &deliveries);
+        ctx.insert("form", form);
+        if let Some(errors) = errors {
+            ctx.insert("errors", &errors);
+        }
+        ctx
+    })
+}
+
+/// The account's endpoints, and how sending to them has gone.
+pub async fn webhooks(request: HttpRequest) -> Result<HttpResponse> {
+    render_page(&request, 200, &WebhookEndpointForm::default(), None).await
+}
+
+pub async fn create(request: HttpRequest, form: Form<WebhookEndpointForm>) -> Result<HttpResponse> {
+    let form = form.into_inner();
+    if let Err(errors) = form.validate() {
+        return render_page(&request, 400, &form, Some(errors)).await;
+    }
+
+    let user = request.user()?;
+    let db = request.db_pool()?;
+    if WebhookEndpoint::for_account(user.id, db).await?.len() >= MAX_ENDPOINTS {
+        request.flash("Webhooks", &format!("You can have at most {} endpoints.", MAX_ENDPOINTS))?;
+        return request.redirect("/dashboard/webhooks");
+    }
+
+    if let Err(reason) = webhooks::check_url(form.url.trim()).await? {
+        let errors = ValidationError::new("url".to_owned(), "PRIVATE_URL")
+            .with_message(move |_| reason.clone())
+            .into();
+        return render_page(&request, 400, &form, Some(errors)).await;
+    }
+
+    WebhookEndpoint::create(user.id, form.url.trim(), db).await?;
+    request.flash("Webhooks", "Endpoint added.")?;
+    request.redirect("/dashboard/webhooks")
+}
+
+/// Gives an endpoint a new secret. The old one keeps signing requests
+/// alongside it for a while, so the receiver can be updated meanwhile.
+pub async fn rotate(request: HttpRequest, id: Path<i32>) -> Result<HttpResponse> {
+    let user = request.user()?;
+    let id = id.into_inner();
+    if !WebhookEndpoint::rotate_secret(id, user.id, request.db_pool()?).await? {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    AuditEvent::record_request(&request, user.id, "webhooks.secret_rotated", json!({ "endpoint_id": id })).await?;
+
+    request.flash("Webhooks", "Secret rotated. The old one also signs requests until the time shown.")?;
+    request.redirect("/dashboard/webhooks")
+}
+
+/// Sends a test event right away, rather than through the queue, so the
+/// result can be shown.
+pub async fn test(request: HttpRequest, id: Path<i32>) -> Result<HttpResponse> {
+    let user = request.user()?;
+    let db = request.db_pool()?;
+    let endpoint = match WebhookEndpoint::get(id.into_inner(), db).await? {
+        Some(endpoint) if endpoint.account_id == user.id => endpoint,
+        _ => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    let attempt = deliver(&endpoint, TEST_EVENT, json!({ "endpoint_id": endpoint.id }), db).await?;
+    let message = match (attempt.status, &attempt.error) {
+        (Some(status), _) => format!("{} answered {}.", endpoint.url, status),
+        (None, Some(error)) => format!("Couldn't reach {}: {}", endpoint.url, error),
+        (None, None) => format!("Couldn't reach {}.", endpoint.url),
+    };
+    request.flash("Webhooks", &message)?;
+    request.redirect("/dashboard/webhooks")
+}
+
+pub async fn delete(request: HttpRequest, id: Path<i32>) -> Result<HttpResponse> {
+    let user = request.user()?;
+    if !WebhookEndpoint::delete(id.into_inner(), user.id, request.db_pool()?).await? {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+
+    request.flash("Webhooks", "Endpoint removed.")?;
+    request.redirect("/dashboard/webhooks")
+}
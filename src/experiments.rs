@@ -0,0 +1,18 @@
+//! A/B experiment results. Bucketing lives in `jelly::experiments`; this
+//! stores exposures and conversions, and lists them at `/admin/experiments`
+//! for anyone with `experiments.view`.
+
+use jelly::actix_web::web::ServiceConfig;
+use jelly::routes::{resource, scope};
+
+pub mod models;
+pub mod views;
+
+pub use models::{DbRecorder, VariantStats};
+
+pub fn configure(config: &mut ServiceConfig) {
+    scope("/admin/experiments")
+        .permission("experiments.")
+        .resource(resource("").get(views::list))
+        .mount(config);
+}
@@ -0,0 +1,131 @@
+use jelly::actix_rt;
+use jelly::error::Error;
+use jelly::experiments::Recorder;
+use jelly::serde::Serialize;
+use sqlx::postgres::PgPool;
+
+/// Stores exposures and conversions in `experiment_exposures`. Only the
+/// first exposure of each unit counts, and it keeps the variant it saw then.
+/// Which account each unit signed in as goes in `experiment_units`.
+pub struct DbRecorder {
+    pub pool: PgPool,
+}
+
+impl Recorder for DbRecorder {
+    fn exposure(&self, experiment: &str, variant: &str, unit: &str) {
+        let pool = self.pool.clone();
+        let (experiment, variant, unit) = (experiment.to_string(), variant.to_string(), unit.to_string());
+        actix_rt::spawn(async move {
+            if let Err(e) = VariantStats::record_exposure(&experiment, &variant, &unit, &pool).await {
+                error!("Error recording exposure to {}: {:?}", experiment, e);
+            }
+        });
+    }
+
+    fn conversion(&self, experiment: &str, unit: &str) {
+        let pool = self.pool.clone();
+        let (experiment, unit) = (experiment.to_string(), unit.to_string());
+        actix_rt::spawn(async move {
+            if let Err(e) = VariantStats::record_conversion(&experiment, &unit, &pool).await {
+                error!("Error recording conversion for {}: {:?}", experiment, e);
+            }
+        });
+    }
+
+    fn identify(&self, unit: &str, account_id: i32) {
+        let pool = self.pool.clone();
+        let unit = unit.to_string();
+        actix_rt::spawn(async move {
+            if let Err(e) = VariantStats::record_unit(&unit, account_id, &pool).await {
+                error!("Error recording experiment unit for account {}: {:?}", account_id, e);
+            }
+        });
+    }
+}
+
+/// How one variant of an experiment is doing.
+#[derive(Debug, Serialize)]
+pub struct VariantStats {
+    pub experiment: String,
+    pub variant: String,
+    pub exposures: i64,
+    pub conversions: i64,
+}
+
+impl VariantStats {
+    pub async fn record_exposure(
+        experiment: &str,
+        variant: &str,
+        unit: &str,
+        pool: &PgPool,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            "
+            INSERT INTO experiment_exposures (experiment, unit, variant)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (experiment, unit) DO NOTHING
+        ",
+            experiment,
+            unit,
+            variant
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Ties `unit` to the account it signed in as; the first account it
+    /// signs in as keeps it.
+    pub async fn record_unit(unit: &str, account_id: i32, pool: &PgPool) -> Result<(), Error> {
+        sqlx::query!(
+            "
+            INSERT INTO experiment_units (unit, account_id)
+            VALUES ($1, $2)
+            ON CONFLICT (unit) DO NOTHING
+        ",
+            unit,
+            account_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Marks `unit` converted, if it was exposed to `experiment` and hasn't
+    /// converted already.
+    pub async fn record_conversion(experiment: &str, unit: &str, pool: &PgPool) -> Result<(), Error> {
+        sqlx::query!(
+            "
+            UPDATE experiment_exposures
+            SET converted = now()
+            WHERE experiment = $1 AND unit = $2 AND converted IS NULL
+        ",
+            experiment,
+            unit
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Counts for every variant that's been seen.
+    pub async fn all(pool: &PgPool) -> Result<Vec<Self>, Error> {
+        Ok(sqlx::query_as_unchecked!(
+            VariantStats,
+            "
+            SELECT
+                experiment, variant,
+                count(*) as exposures,
+                count(converted) as conversions
+            FROM experiment_exposures
+            GROUP BY experiment, variant
+            ORDER BY experiment, variant
+        "
+        )
+        .fetch_all(pool)
+        .await?)
+    }
+}
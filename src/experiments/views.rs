@@ -0,0 +1,50 @@
+use jelly::experiments;
+use jelly::prelude::*;
+use jelly::Result;
+use serde::Serialize;
+
+use super::VariantStats;
+
+/// A configured experiment and how each of its variants is doing.
+#[derive(Serialize)]
+struct ExperimentResults {
+    name: String,
+    variants: Vec<VariantStats>,
+}
+
+/// Every configured experiment, with exposure and conversion counts.
+pub async fn list(request: HttpRequest) -> Result<HttpResponse> {
+    request.require_permission("experiments.view").await?;
+    let mut stats = VariantStats::all(request.db_pool()?).await?;
+
+    let results: Vec<ExperimentResults> = experiments::all()
+        .iter()
+        .map(|experiment| ExperimentResults {
+            name: experiment.name.clone(),
+            variants: experiment
+                .variants
+                .iter()
+                .map(|variant| {
+                    let seen = stats
+                        .iter()
+                        .position(|s| s.experiment == experiment.name && &s.variant == variant);
+                    match seen {
+                        Some(i) => stats.swap_remove(i),
+                        None => VariantStats {
+                            experiment: experiment.name.clone(),
+                            variant: variant.clone(),
+                            exposures: 0,
+                            conversions: 0,
+                        },
+                    }
+                })
+                .collect(),
+        })
+        .collect();
+
+    request.render(200, "experiments/admin.html", {
+        let mut ctx = Context::new();
+        ctx.insert("experiments", &results);
+        ctx
+    })
+}
@@ -0,0 +1,41 @@
+//! The signed in account's stored files, at `/files/{name}`. They're only
+//! ever served through here, so the storage directory itself stays private.
+//!
+//! Files are uploaded with a `PUT` to the same URL, and virus scanned in
+//! the background (see `jobs::ScanUpload`) before they can be shared -
+//! thumbnails (`jelly::thumbnails`) included.
+
+use jelly::actix_web::web::ServiceConfig;
+use jelly::async_trait::async_trait;
+use jelly::error::Error;
+use jelly::routes::{resource, scope};
+use jelly::tenancy::TenantId;
+use jelly::thumbnails;
+use sqlx::postgres::PgPool;
+
+pub mod jobs;
+pub mod models;
+pub mod views;
+
+pub use models::Upload;
+
+/// Only renders thumbnails of uploads that have been scanned and found
+/// clean; see `thumbnails::set_sources`.
+pub struct ThumbnailSources {
+    pub pool: PgPool,
+}
+
+#[async_trait]
+impl thumbnails::Sources for ThumbnailSources {
+    async fn allows(&self, tenant: TenantId, name: &str) -> Result<bool, Error> {
+        let status = Upload::status_of(tenant.get(), name, &self.pool).await?;
+        Ok(status.as_deref() == Some(models::CLEAN))
+    }
+}
+
+pub fn configure(config: &mut ServiceConfig) {
+    scope("/files")
+        .auth()
+        .resource(resource("/{name:.+}").get(views::file).put(views::upload))
+        .mount(config);
+}
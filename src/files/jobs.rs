@@ -0,0 +1,117 @@
+use std::env::var;
+use std::future::Future;
+use std::pin::Pin;
+
+use jelly::anyhow::{anyhow, Error};
+use jelly::email::Email;
+use jelly::jobs::{Job, JobConfig, JobState, Traced, DEFAULT_QUEUE};
+use jelly::push::{self, Message};
+use jelly::scan::{self, Verdict};
+use jelly::serde::{Deserialize, Serialize};
+use jelly::serde_json::json;
+use jelly::storage;
+use jelly::tenancy::TenantId;
+use jelly::tera::Context;
+
+use super::models::{Upload, CLEAN, INFECTED, UNSCANNED};
+use crate::accounts::Account;
+use crate::audit::AuditEvent;
+
+/// Virus scans an upload (see `jelly::scan`). If it's infected, the file
+/// is quarantined and the uploader gets an email saying so. Scanner
+/// errors fail the job, so it's retried.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScanUpload {
+    pub upload_id: i32,
+}
+
+pub fn build_context(name: &str, upload: &Upload, threat: &str) -> Context {
+    let mut context = Context::new();
+    context.insert("name", name);
+    context.insert("file_name", &upload.name);
+    context.insert("threat", threat);
+    context.insert(
+        "action_url",
+        &format!("{}/dashboard", var("JELLY_DOMAIN").expect("JELLY_DOMAIN not set?")),
+    );
+    context
+}
+
+impl Job for ScanUpload {
+    type State = JobState;
+    type Future = Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
+
+    const NAME: &'static str = "ScanUploadJob";
+    const QUEUE: &'static str = DEFAULT_QUEUE;
+
+    fn run(self, state: JobState) -> Self::Future {
+        Box::pin(async move {
+            let upload = Upload::get(self.upload_id, &state.pool)
+                .await
+                .map_err(|e| anyhow!("Error fetching upload: {:?}", e))?;
+            let tenant = TenantId(upload.account_id);
+            let path = storage::path_for(tenant, &upload.name).map_err(|e| anyhow!("Error finding upload: {:?}", e))?;
+            let path = match path {
+                Some(path) if path.is_file() => path,
+                // Replaced or removed since; whatever replaced it has its own scan.
+                _ => return Ok(()),
+            };
+
+            let verdict = scan::scan(path)
+                .await
+                .map_err(|e| anyhow!("Error scanning upload: {:?}", e))?;
+            let threat = match verdict {
+                Some(Verdict::Infected(threat)) => threat,
+                verdict => {
+                    let status = if verdict.is_some() { CLEAN } else { UNSCANNED };
+                    return Upload::mark_scanned(upload.id, status, None, &state.pool)
+                        .await
+                        .map_err(|e| anyhow!("Error recording upload scan: {:?}", e));
+                }
+            };
+
+            storage::quarantine(tenant, &upload.name).map_err(|e| anyhow!("Error quarantining upload: {:?}", e))?;
+            Upload::mark_scanned(upload.id, INFECTED, Some(&threat), &state.pool)
+                .await
+                .map_err(|e| anyhow!("Error recording upload scan: {:?}", e))?;
+            warn!("Quarantined upload {} ({}): {}", upload.id, upload.name, threat);
+
+            let data = json!({ "name": upload.name, "threat": threat });
+            AuditEvent::record(Some(upload.account_id), "upload.quarantined", data, &state.pool)
+                .await
+                .map_err(|e| anyhow!("Error recording quarantined upload: {:?}", e))?;
+
+            let account = Account::get(upload.account_id, &state.pool)
+                .await
+                .map_err(|e| anyhow!("Error fetching account for quarantined upload: {:?}", e))?;
+            if account.email_deliverable {
+                let email = Email::new(
+                    "email/upload-quarantined",
+                    &[account.email],
+                    "A file you uploaded was quarantined",
+                    build_context(&account.name, &upload, &threat),
+                    state.templates,
+                );
+
+                email?.send()?;
+            }
+
+            // After the email, so a failed send (and the retry) can't push
+            // twice; push failures are only logged for the same reason.
+            let mut message = Message::new(
+                "A file you uploaded was quarantined",
+                &format!("{} contained {}.", upload.name, threat),
+            );
+            message.data = Some(json!({ "upload_id": upload.id }));
+            if let Err(e) = push::notify(&state.pool, upload.account_id, &message).await {
+                error!("Error sending quarantine push notification: {:?}", e);
+            }
+
+            Ok(())
+        })
+    }
+}
+
+pub fn configure(config: JobConfig) -> JobConfig {
+    config.register::<Traced<ScanUpload>>()
+}
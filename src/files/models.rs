@@ -0,0 +1,98 @@
+use jelly::chrono::{DateTime, Utc};
+use jelly::error::Error;
+use jelly::serde::Serialize;
+use jelly::tenancy::TenantPool;
+use sqlx::postgres::PgPool;
+
+/// Where an upload's virus scan has got to.
+pub const PENDING: &str = "pending";
+pub const CLEAN: &str = "clean";
+pub const INFECTED: &str = "infected";
+pub const UNSCANNED: &str = "unscanned";
+
+/// A file an account has uploaded.
+#[derive(Debug, Serialize)]
+pub struct Upload {
+    pub id: i32,
+    pub account_id: i32,
+    pub name: String,
+    pub size: i64,
+    pub status: String,
+    pub threat: Option<String>,
+    pub created: DateTime<Utc>,
+    pub scanned_at: Option<DateTime<Utc>>,
+}
+
+impl Upload {
+    /// Records an upload of `name`, replacing any earlier one, as waiting
+    /// to be scanned. Returns its id.
+    pub async fn record(db: &TenantPool<'_>, name: &str, size: i64) -> Result<i32, Error> {
+        let row = jelly::tenant_query!(
+            db,
+            "
+            INSERT INTO uploads (account_id, name, size)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (account_id, name) DO UPDATE
+            SET size = excluded.size, status = 'pending', threat = NULL,
+                created = now(), scanned_at = NULL
+            RETURNING id
+        ",
+            name,
+            size
+        )
+        .fetch_one(db.pool())
+        .await?;
+
+        Ok(row.id)
+    }
+
+    pub async fn get(id: i32, pool: &PgPool) -> Result<Self, Error> {
+        Ok(sqlx::query_as_unchecked!(
+            Upload,
+            "
+            SELECT id, account_id, name, size, status, threat, created, scanned_at
+            FROM uploads WHERE id = $1
+        ",
+            id
+        )
+        .fetch_one(pool)
+        .await?)
+    }
+
+    /// The scan status of `account_id`'s upload `name`, if there is one.
+    pub async fn status_of(account_id: i32, name: &str, pool: &PgPool) -> Result<Option<String>, Error> {
+        Ok(sqlx::query!(
+            "
+            SELECT status FROM uploads WHERE account_id = $1 AND name = $2
+        ",
+            account_id,
+            name
+        )
+        .fetch_optional(pool)
+        .await?
+        .map(|row| row.status))
+    }
+
+    /// Records how the scan went.
+    pub async fn mark_scanned(id: i32, status: &str, threat: Option<&str>, pool: &PgPool) -> Result<(), Error> {
+        sqlx::query!(
+            "
+            UPDATE uploads SET status = $2, threat = $3, scanned_at = now()
+            WHERE id = $1
+        ",
+            id,
+            status,
+            threat
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Whether the file can be shared with other accounts: only once it's
+    /// been scanned and found clean.
+    pub fn is_shareable(&self) -> bool {
+        self.status == CLEAN
+    }
+}
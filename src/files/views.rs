@@ -0,0 +1,67 @@
+use std::env;
+
+use jelly::actix_web::web::{self, BytesMut, Path};
+use jelly::actix_web::HttpRequest;
+use jelly::error::Error;
+use jelly::futures::StreamExt;
+use jelly::prelude::*;
+use jelly::serde_json::json;
+use jelly::storage;
+use jelly::Result;
+
+use super::jobs::ScanUpload;
+use super::models::{Upload, PENDING};
+
+/// The largest upload accepted: `UPLOAD_MAX_BYTES`, 25MB by default.
+fn max_upload_bytes() -> usize {
+    env::var("UPLOAD_MAX_BYTES")
+        .ok()
+        .and_then(|bytes| bytes.parse().ok())
+        .unwrap_or(25 * 1024 * 1024)
+}
+
+/// One of the account's files, with range and conditional request support.
+pub async fn file(request: HttpRequest, name: Path<String>) -> Result<HttpResponse> {
+    let path = storage::path_for(request.tenant_pool()?.tenant(), &name)?;
+    storage::serve(&request, path).await
+}
+
+/// Stores the request body as the account's file `name`, replacing any
+/// earlier one, and queues a virus scan of it.
+pub async fn upload(
+    request: HttpRequest,
+    name: Path<String>,
+    mut payload: web::Payload,
+) -> Result<HttpResponse> {
+    let db = request.tenant_pool()?;
+    let path = match storage::path_for(db.tenant(), &name)? {
+        Some(path) => path,
+        None => return request.json(400, json!({ "error": "invalid file name" })),
+    };
+
+    let limit = max_upload_bytes();
+    let mut body = BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(jelly::actix_web::Error::from)?;
+        if body.len() + chunk.len() > limit {
+            return request.json(413, json!({ "error": "file too large" }));
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    let size = body.len() as i64;
+    web::block(move || {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, &body)
+    })
+    .await
+    .map_err(|e| Error::Generic(format!("Error storing upload: {:?}", e)))?
+    .map_err(|e| Error::Generic(format!("Error storing upload: {:?}", e)))?;
+
+    let upload_id = Upload::record(&db, &name, size).await?;
+    request.queue_job(ScanUpload { upload_id }).await?;
+
+    request.json(201, json!({ "name": name.as_str(), "size": size, "status": PENDING }))
+}
@@ -0,0 +1,40 @@
+//! Invite-only registration. With `INVITE_ONLY` set, signing up takes an
+//! invitation link; admins (or anyone with `invitations.create`) generate
+//! them from `/admin/invitations`. Each is good for one registration, with
+//! a password or through a provider, and lapses after `INVITATION_DAYS`
+//! (14 by default).
+
+use std::env;
+
+use jelly::actix_web::web::ServiceConfig;
+use jelly::routes::{resource, scope};
+
+pub mod forms;
+pub mod models;
+pub mod views;
+
+pub use models::Invitation;
+
+/// Session key holding the invitation token someone arrived with, until
+/// they register.
+pub const SESSION_INVITATION_TOKEN: &str = "invitation_token";
+
+/// Whether registration needs an invitation.
+pub fn invite_only() -> bool {
+    env::var("INVITE_ONLY").map_or(false, |v| !v.is_empty() && v != "0")
+}
+
+/// How long invitations last.
+pub fn invitation_days() -> i64 {
+    env::var("INVITATION_DAYS")
+        .ok()
+        .and_then(|days| days.parse().ok())
+        .unwrap_or(14)
+}
+
+pub fn configure(config: &mut ServiceConfig) {
+    scope("/admin/invitations")
+        .permission("invitations.")
+        .resource(resource("").get(views::admin::list).post(views::admin::create))
+        .mount(config);
+}
@@ -0,0 +1,7 @@
+use serde::Deserialize;
+
+/// Generating `count` invitations at once.
+#[derive(Debug, Deserialize)]
+pub struct CreateForm {
+    pub count: i64,
+}
@@ -0,0 +1,160 @@
+use jelly::chrono::{DateTime, Duration, Utc};
+use jelly::error::Error;
+use jelly::rand::{thread_rng, Rng};
+use jelly::serde::Serialize;
+use sqlx::postgres::PgPool;
+
+use super::invitation_days;
+use crate::accounts::forms::NewAccountForm;
+use crate::accounts::Account;
+
+/// A link that lets someone register while registration is invite-only.
+#[derive(Debug, Serialize)]
+pub struct Invitation {
+    pub id: i32,
+    pub token: String,
+    pub created_by: Option<i32>,
+    pub created: DateTime<Utc>,
+    pub expires: DateTime<Utc>,
+    pub used: Option<DateTime<Utc>>,
+    pub used_by: Option<i32>,
+}
+
+impl Invitation {
+    /// Generates `count` invitations from `created_by`. Returns their ids.
+    pub async fn create(created_by: i32, count: i64, pool: &PgPool) -> Result<Vec<i32>, Error> {
+        let expires = Utc::now() + Duration::days(invitation_days());
+        let mut tx = pool.begin().await?;
+
+        let mut ids = Vec::new();
+        for _ in 0..count {
+            let row = sqlx::query!(
+                "
+                INSERT INTO invitations (token, created_by, expires)
+                VALUES ($1, $2, $3)
+                RETURNING id
+            ",
+                new_token(),
+                created_by,
+                expires
+            )
+            .fetch_one(&mut tx)
+            .await?;
+            ids.push(row.id);
+        }
+
+        tx.commit().await?;
+        Ok(ids)
+    }
+
+    /// The newest invitations that are still good, for handing out.
+    pub async fn unused(limit: i64, pool: &PgPool) -> Result<Vec<Self>, Error> {
+        Ok(sqlx::query_as_unchecked!(
+            Invitation,
+            "
+            SELECT
+                id, token, created_by, created, expires, used, used_by
+            FROM invitations
+            WHERE used IS NULL AND expires > now()
+            ORDER BY created DESC, id DESC
+            LIMIT $1
+        ",
+            limit
+        )
+        .fetch_all(pool)
+        .await?)
+    }
+
+    /// The invitation `token` belongs to, if it's still good.
+    pub async fn by_token(token: &str, pool: &PgPool) -> Result<Option<Self>, Error> {
+        Ok(sqlx::query_as_unchecked!(
+            Invitation,
+            "
+            SELECT
+                id, token, created_by, created, expires, used, used_by
+            FROM invitations
+            WHERE token = $1 AND used IS NULL AND expires > now()
+        ",
+            token
+        )
+        .fetch_optional(pool)
+        .await?)
+    }
+
+    /// Registers `form`'s account and uses up `token`, together: if the
+    /// invitation was used (or lapsed) in the meantime, no account is made
+    /// and this returns `None`.
+    pub async fn register(form: &NewAccountForm, token: &str, pool: &PgPool) -> Result<Option<i32>, Error> {
+        let mut tx = pool.begin().await?;
+        let uid = Account::register(form, &mut tx).await?;
+
+        let result = sqlx::query!(
+            "
+            UPDATE invitations
+            SET used = now(), used_by = $2
+            WHERE token = $1 AND used IS NULL AND expires > now()
+        ",
+            token,
+            uid
+        )
+        .execute(&mut tx)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            tx.rollback().await?;
+            return Ok(None);
+        }
+
+        tx.commit().await?;
+        Ok(Some(uid))
+    }
+
+    /// Marks `token` used, for a sign up that doesn't go through
+    /// `register` (signing up with a provider), so no one else can use it
+    /// meanwhile. Returns whether it was still good. Follow up with
+    /// `used_by` or `release`.
+    pub async fn claim(token: &str, pool: &PgPool) -> Result<bool, Error> {
+        let result = sqlx::query!(
+            "
+            UPDATE invitations
+            SET used = now()
+            WHERE token = $1 AND used IS NULL AND expires > now()
+        ",
+            token
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Records the account a claimed invitation made.
+    pub async fn used_by(token: &str, uid: i32, pool: &PgPool) -> Result<(), Error> {
+        sqlx::query!("UPDATE invitations SET used_by = $2 WHERE token = $1", token, uid)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Frees a claimed invitation again, when the sign up didn't happen.
+    pub async fn release(token: &str, pool: &PgPool) -> Result<(), Error> {
+        sqlx::query!(
+            "
+            UPDATE invitations
+            SET used = NULL
+            WHERE token = $1 AND used_by IS NULL
+        ",
+            token
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+fn new_token() -> String {
+    let bytes: [u8; 24] = thread_rng().gen();
+    base64_url::encode(&bytes)
+}
@@ -0,0 +1,20 @@
+use jelly::actix_web::HttpRequest;
+use jelly::prelude::*;
+use jelly::Result;
+
+use super::{Invitation, SESSION_INVITATION_TOKEN};
+
+pub mod admin;
+
+/// The invitation in the session, if it's still good.
+pub async fn session_invitation(request: &HttpRequest) -> Result<Option<Invitation>> {
+    match request.get_session().get::<String>(SESSION_INVITATION_TOKEN)? {
+        Some(token) => Ok(Invitation::by_token(&token, request.db_pool()?).await?),
+        None => Ok(None),
+    }
+}
+
+/// Renders in place of the sign up form, for people without an invitation.
+pub fn required(request: &HttpRequest) -> Result<HttpResponse> {
+    request.render(200, "invitations/required.html", Context::new())
+}
@@ -0,0 +1,35 @@
+use jelly::actix_web::{web, HttpRequest};
+use jelly::prelude::*;
+use jelly::Result;
+
+use crate::invitations::forms::CreateForm;
+use crate::invitations::Invitation;
+
+/// How many unused invitations are listed.
+const SHOWN: i64 = 100;
+
+/// The most invitations generated at once.
+const MAX_COUNT: i64 = 50;
+
+/// Unused invitations, newest first, with their links.
+pub async fn list(request: HttpRequest) -> Result<HttpResponse> {
+    request.require_permission("invitations.view").await?;
+    let invitations = Invitation::unused(SHOWN, request.db_pool()?).await?;
+
+    request.render(200, "invitations/admin.html", {
+        let mut ctx = Context::new();
+        ctx.insert("invitations", &invitations);
+        ctx
+    })
+}
+
+/// Generates a batch of invitations, to be handed out from the list.
+pub async fn create(request: HttpRequest, form: web::Form<CreateForm>) -> Result<HttpResponse> {
+    request.require_permission("invitations.create").await?;
+    let user = request.user()?;
+    let count = form.count.max(0).min(MAX_COUNT);
+    let ids = Invitation::create(user.id, count, request.db_pool()?).await?;
+
+    request.flash("Invitations", &format!("Generated {} invitations.", ids.len()))?;
+    request.redirect("/admin/invitations")
+}
@@ -1,33 +1,154 @@
 //! Your Service Description here, etc.
 
-use actix::Actor;
 use std::io;
+use std::path::Path;
+
+use sqlx::migrate::Migrator;
 
 #[macro_use]
 extern crate log;
 
 pub mod accounts;
+pub mod admin;
+pub mod api;
+pub mod audit;
+pub mod calendar;
 pub mod dashboard;
+pub mod experiments;
+pub mod files;
+pub mod invitations;
+pub mod links;
+pub mod metering;
+pub mod notifications;
 pub mod oauth;
 pub mod pages;
+pub mod profiles;
+pub mod quotas;
+pub mod referrals;
+pub mod retention;
 pub mod scheduler;
+pub mod waitlist;
+pub mod webhooks;
+
+/// The migrations this build expects the database to have.
+pub static MIGRATOR: Migrator = sqlx::migrate!();
 
 pub async fn main() -> io::Result<()> {
+    // `cargo run -- config check` prints the settings, rather than serving.
+    if std::env::args().skip(1).eq(["config", "check"]) {
+        if !jelly::config::check() {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    // `cargo run -- export-static [dir]` pre-renders the public pages into
+    // `dir` (`dist` by default) for a CDN, rather than serving.
+    let export_dir = match std::env::args().nth(1).as_deref() {
+        Some("export-static") => Some(std::env::args().nth(2).unwrap_or_else(|| "dist".to_string())),
+        _ => None,
+    };
+
     let stdout = io::stdout();
     let _lock = stdout.lock();
 
     let config = jelly::ServerConfig::load().await;
 
-    let sched = scheduler::Scheduler { pool: config.pool.clone(), schedule: scheduler::EVERY_MINUTE.to_string() };
-    sched.start();
+    // Drift from a partial deploy is logged, or in production, refuses to
+    // start (see `MIGRATION_CHECK`).
+    jelly::migrations::check_on_startup(&config.pool, &MIGRATOR)
+        .await
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("{:?}", e)))?;
+
+    // `cargo run -- retention report` lists what the retention policies
+    // would remove now, without removing it.
+    if std::env::args().skip(1).eq(["retention", "report"]) {
+        let outcomes = retention::report(&config.pool)
+            .await
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("{:?}", e)))?;
+        for outcome in outcomes {
+            println!("{}", outcome);
+        }
+        return Ok(());
+    }
+
+    jelly::settings::register::<Vec<String>>(oauth::DISABLED_PROVIDERS);
+    jelly::settings::register::<bool>(oauth::REVOKE_ON_LOGOUT);
+    jelly::settings::register::<bool>(accounts::OAUTH_ONLY);
+    jelly::settings::register::<jelly::forms::EmailDomains>(accounts::EMAIL_DOMAINS);
+    jelly::experiments::set_recorder(experiments::DbRecorder {
+        pool: config.pool.clone(),
+    });
+    jelly::thumbnails::set_sources(files::ThumbnailSources {
+        pool: config.pool.clone(),
+    });
 
-    jelly::Server::new()
+    if std::env::var("RATE_LIMIT_STORE").map_or(false, |store| store == "postgres") {
+        jelly::ratelimit::set_store(jelly::ratelimit::PgStore {
+            pool: config.pool.clone(),
+        });
+    }
+
+    if std::env::var("IDEMPOTENCY_STORE").map_or(false, |store| store == "postgres") {
+        jelly::idempotency::set_store(jelly::idempotency::PgStore {
+            pool: config.pool.clone(),
+        });
+    }
+
+    let auth_mode = match std::env::var("AUTH_MODE").as_deref() {
+        Ok("jwt") => jelly::accounts::AuthMode::Jwt,
+        Ok("both") => jelly::accounts::AuthMode::Both,
+        _ => jelly::accounts::AuthMode::Session,
+    };
+
+    let server = jelly::Server::new()
+        .auth_mode(auth_mode)
         .register_service(pages::configure)
         .register_service(accounts::configure)
         .register_jobs(accounts::jobs::configure)
+        .register_jobs(admin::jobs::configure)
+        .register_jobs(api::jobs::configure)
+        .register_jobs(audit::jobs::configure)
+        .register_jobs(files::jobs::configure)
+        .register_jobs(referrals::jobs::configure)
+        .register_jobs(waitlist::jobs::configure)
+        .register_jobs(webhooks::jobs::configure)
+        .register_cron::<scheduler::CountAccounts>()
+        .register_cron::<scheduler::ExpireOAuthFlows>()
+        .register_cron::<scheduler::PurgeUnverified>()
+        .register_cron::<scheduler::MeterUsage>()
+        .register_cron::<scheduler::WarnExpiringTokens>()
+        .register_cron::<scheduler::SendDigests>()
+        .register_cron::<scheduler::PublishPages>()
+        .register_cron::<retention::ApplyRetention>()
+        .register_service(calendar::configure)
         .register_service(dashboard::configure)
+        .register_service(admin::configure)
+        .register_service(api::configure)
+        .register_service(experiments::configure)
+        .register_service(files::configure)
+        .register_service(invitations::configure)
+        .register_service(links::configure)
+        .register_service(notifications::configure)
         .register_service(oauth::configure)
-        .run(config)
-        .await?
-        .await
+        .register_service(profiles::configure)
+        .register_service(waitlist::configure)
+        .register_service(webhooks::configure);
+
+    // `cargo run -- routes` lists every route, with what's in front of it,
+    // rather than serving.
+    if std::env::args().skip(1).eq(["routes"]) {
+        print!("{}", jelly::routes::table(&server.routes()));
+        return Ok(());
+    }
+
+    if let Some(dir) = export_dir {
+        let paths = pages::static_paths().map_err(|e| io::Error::new(io::ErrorKind::Other, format!("{:?}", e)))?;
+        for file in server.export_static(config, &paths, Path::new(&dir)).await? {
+            println!("Wrote {}", file.display());
+        }
+        return Ok(());
+    }
+
+    server.run(config).await?.await
 }
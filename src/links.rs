@@ -0,0 +1,32 @@
+//! Short, tracked links. `/l/{code}` counts the click and redirects,
+//! adding any UTM parameters the link was made with. For campaign emails,
+//! build the context with `tracked_url`:
+//!
+//! ```ignore
+//! let utm = Utm::campaign("newsletter", "2022-04");
+//! context.insert("pricing_url", &links::tracked_url("https://example.com/pricing", &utm, pool).await?);
+//! ```
+
+use std::env;
+
+use jelly::actix_web::web::ServiceConfig;
+use jelly::error::Error;
+use jelly::routes;
+use sqlx::postgres::PgPool;
+
+pub mod models;
+pub mod views;
+
+pub use models::{with_utm, Link, Utm};
+
+/// The tracked short URL for `url`, creating the link if there isn't one
+/// for it (and `utm`) yet.
+pub async fn tracked_url(url: &str, utm: &Utm, pool: &PgPool) -> Result<String, Error> {
+    let link = Link::get_or_create(url, utm, pool).await?;
+    let domain = env::var("JELLY_DOMAIN").expect("No JELLY_DOMAIN value set!");
+    Ok(format!("{}/l/{}", domain, link.code))
+}
+
+pub fn configure(config: &mut ServiceConfig) {
+    routes::resource("/l/{code}").get(views::follow).mount(config);
+}
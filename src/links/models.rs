@@ -0,0 +1,165 @@
+use jelly::chrono::{DateTime, Utc};
+use jelly::error::Error;
+use jelly::rand::distributions::Alphanumeric;
+use jelly::rand::{thread_rng, Rng};
+use jelly::serde::{Deserialize, Serialize};
+use sqlx::postgres::PgPool;
+
+/// Length of generated codes.
+const CODE_LEN: usize = 7;
+
+/// UTM parameters added to a link's target.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Utm {
+    pub source: Option<String>,
+    pub medium: Option<String>,
+    pub campaign: Option<String>,
+}
+
+impl Utm {
+    /// Parameters for an email campaign: `utm_source=<source>`,
+    /// `utm_medium=email`, `utm_campaign=<campaign>`.
+    pub fn campaign(source: &str, campaign: &str) -> Self {
+        Utm {
+            source: Some(source.to_string()),
+            medium: Some("email".to_string()),
+            campaign: Some(campaign.to_string()),
+        }
+    }
+}
+
+/// A short link.
+#[derive(Debug, Serialize)]
+pub struct Link {
+    pub id: i32,
+    pub code: String,
+    pub url: String,
+    pub utm_source: Option<String>,
+    pub utm_medium: Option<String>,
+    pub utm_campaign: Option<String>,
+    pub clicks: i64,
+    pub last_clicked: Option<DateTime<Utc>>,
+    pub created: DateTime<Utc>,
+}
+
+impl Link {
+    /// The link to `url` with `utm`, creating it if need be.
+    pub async fn get_or_create(url: &str, utm: &Utm, pool: &PgPool) -> Result<Self, Error> {
+        loop {
+            let code: String = thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(CODE_LEN)
+                .map(char::from)
+                .collect();
+
+            // The no-op update makes an existing link come back from
+            // RETURNING; a clashing code doesn't, so try another.
+            let link = sqlx::query_as_unchecked!(
+                Link,
+                "
+                INSERT INTO links (code, url, utm_source, utm_medium, utm_campaign)
+                VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT (url, coalesce(utm_source, ''), coalesce(utm_medium, ''), coalesce(utm_campaign, ''))
+                DO UPDATE SET url = excluded.url
+                RETURNING
+                    id, code, url, utm_source, utm_medium, utm_campaign,
+                    clicks, last_clicked, created
+            ",
+                code,
+                url,
+                utm.source,
+                utm.medium,
+                utm.campaign
+            )
+            .fetch_one(pool)
+            .await;
+
+            match link {
+                Err(sqlx::Error::Database(e)) if e.code().as_deref() == Some("23505") => continue,
+                link => return Ok(link?),
+            }
+        }
+    }
+
+    /// Counts a click on `code`, returning the link if there is one.
+    pub async fn click(code: &str, pool: &PgPool) -> Result<Option<Self>, Error> {
+        Ok(sqlx::query_as_unchecked!(
+            Link,
+            "
+            UPDATE links
+            SET clicks = clicks + 1, last_clicked = now()
+            WHERE code = $1
+            RETURNING
+                id, code, url, utm_source, utm_medium, utm_campaign,
+                clicks, last_clicked, created
+        ",
+            code
+        )
+        .fetch_optional(pool)
+        .await?)
+    }
+
+    pub fn utm(&self) -> Utm {
+        Utm {
+            source: self.utm_source.clone(),
+            medium: self.utm_medium.clone(),
+            campaign: self.utm_campaign.clone(),
+        }
+    }
+
+    /// Where the link goes, UTM parameters included.
+    pub fn target(&self) -> String {
+        with_utm(&self.url, &self.utm())
+    }
+}
+
+/// `url` with `utm`'s parameters added to its query string. Parameters
+/// already in the url are left alone.
+pub fn with_utm(url: &str, utm: &Utm) -> String {
+    let (base, fragment) = match url.split_once('#') {
+        Some((base, fragment)) => (base, Some(fragment)),
+        None => (url, None),
+    };
+
+    let existing = base.split_once('?').map(|(_, query)| query).unwrap_or("");
+    let present = |key: &str| {
+        existing
+            .split('&')
+            .any(|pair| pair.split('=').next() == Some(key))
+    };
+
+    let params: Vec<String> = [
+        ("utm_source", &utm.source),
+        ("utm_medium", &utm.medium),
+        ("utm_campaign", &utm.campaign),
+    ]
+    .iter()
+    .filter_map(|(key, value)| value.as_ref().map(|value| (key, value)))
+    .filter(|(key, _)| !present(key))
+    .map(|(key, value)| format!("{}={}", key, encode(value)))
+    .collect();
+
+    let mut target = base.to_string();
+    if !params.is_empty() {
+        target.push(if base.contains('?') { '&' } else { '?' });
+        target.push_str(&params.join("&"));
+    }
+    if let Some(fragment) = fragment {
+        target.push('#');
+        target.push_str(fragment);
+    }
+    target
+}
+
+/// Percent-encodes a query string value.
+fn encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
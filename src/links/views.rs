@@ -0,0 +1,16 @@
+use jelly::actix_web::http::header::LOCATION;
+use jelly::actix_web::web::Path;
+use jelly::prelude::*;
+use jelly::Result;
+
+use super::Link;
+
+/// Counts the click and sends the visitor on to the link's target.
+pub async fn follow(request: HttpRequest, code: Path<String>) -> Result<HttpResponse> {
+    match Link::click(&code, request.db_pool()?).await? {
+        Some(link) => Ok(HttpResponse::Found()
+            .insert_header((LOCATION, link.target()))
+            .finish()),
+        None => request.render(404, "404.html", Context::new()),
+    }
+}
@@ -0,0 +1,9 @@
+//! Usage metering, for usage-based pricing. Anything billable is recorded
+//! with `metering::record`; the scheduler rolls it up per day and reports it
+//! to Stripe, and accounts can download their line items as CSV, or have a
+//! PDF receipt of them emailed.
+
+pub mod models;
+pub mod stripe;
+
+pub use models::{record, DailyUsage};
@@ -0,0 +1,131 @@
+use jelly::chrono::{NaiveDate, Utc};
+use jelly::error::Error;
+use jelly::serde::Serialize;
+use jelly::tenancy::TenantPool;
+use sqlx::postgres::PgPool;
+
+/// Records `quantity` of `metric` (e.g "api_requests") against the tenant.
+/// Metrics are free-form; each becomes its own line item.
+pub async fn record(db: &TenantPool<'_>, metric: &str, quantity: i64) -> Result<(), Error> {
+    jelly::tenant_query!(
+        db,
+        "
+        INSERT INTO usage_events (account_id, metric, quantity)
+        VALUES ($1, $2, $3)
+    ",
+        metric,
+        quantity
+    )
+    .execute(db.pool())
+    .await?;
+
+    Ok(())
+}
+
+/// A day's total of one metric for an account; an invoice line item.
+#[derive(Debug, Serialize)]
+pub struct DailyUsage {
+    pub account_id: i32,
+    pub metric: String,
+    pub day: NaiveDate,
+    pub quantity: i64,
+}
+
+/// A line item not yet reported to Stripe, with who to bill.
+#[derive(Debug)]
+pub struct Unreported {
+    pub account_id: i32,
+    pub metric: String,
+    pub day: NaiveDate,
+    pub quantity: i64,
+    pub stripe_customer_id: String,
+}
+
+impl DailyUsage {
+    /// Rolls up events from before today (UTC) into daily totals. Safe to
+    /// run as often as you like; returns the number of totals touched.
+    pub async fn aggregate(pool: &PgPool) -> Result<u64, Error> {
+        Ok(sqlx::query!(
+            "
+            WITH finished AS (
+                DELETE FROM usage_events
+                WHERE created < date_trunc('day', now() at time zone 'utc') at time zone 'utc'
+                RETURNING account_id, metric, quantity, created
+            )
+            INSERT INTO usage_daily (account_id, metric, day, quantity)
+            SELECT account_id, metric, (created at time zone 'utc')::date, sum(quantity)::bigint
+            FROM finished
+            GROUP BY 1, 2, 3
+            ON CONFLICT (account_id, metric, day) DO UPDATE
+            SET quantity = usage_daily.quantity + excluded.quantity
+        "
+        )
+        .execute(pool)
+        .await?
+        .rows_affected())
+    }
+
+    /// The tenant's line items from `from` up to and including `to`.
+    pub async fn for_account(
+        db: &TenantPool<'_>,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<Self>, Error> {
+        Ok(jelly::tenant_query_as!(
+            db,
+            DailyUsage,
+            "
+            SELECT account_id, metric, day, quantity
+            FROM usage_daily
+            WHERE account_id = $1 AND day BETWEEN $2 AND $3
+            ORDER BY day, metric
+        ",
+            from,
+            to
+        )
+        .fetch_all(db.pool())
+        .await?)
+    }
+
+    /// Line items for accounts billed through Stripe that haven't been
+    /// reported yet. Stripe won't take usage older than 35 days, so anything
+    /// before that is left alone.
+    pub async fn unreported(pool: &PgPool) -> Result<Vec<Unreported>, Error> {
+        Ok(sqlx::query_as_unchecked!(
+            Unreported,
+            "
+            SELECT d.account_id, d.metric, d.day, d.quantity, a.stripe_customer_id
+            FROM usage_daily d
+            JOIN accounts a ON a.id = d.account_id
+            WHERE d.reported IS NULL
+                AND a.stripe_customer_id IS NOT NULL
+                AND d.day >= current_date - 35
+            ORDER BY d.day
+        "
+        )
+        .fetch_all(pool)
+        .await?)
+    }
+
+    pub async fn mark_reported(
+        account_id: i32,
+        metric: &str,
+        day: NaiveDate,
+        pool: &PgPool,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            "
+            UPDATE usage_daily SET reported = $4
+            WHERE account_id = $1 AND metric = $2 AND day = $3
+        ",
+            account_id,
+            metric,
+            day,
+            Utc::now()
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
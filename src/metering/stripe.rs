@@ -0,0 +1,79 @@
+//! Reports daily usage to Stripe as billing meter events. Needs
+//! `STRIPE_API_KEY`, plus a `STRIPE_METER_<METRIC>` event name (e.g
+//! `STRIPE_METER_API_REQUESTS`) for each metric that's billed; the rest are
+//! left out.
+
+use std::env::var;
+
+use jelly::anyhow::{anyhow, Context, Error};
+use jelly::chrono::{TimeZone, Utc};
+use jelly::minreq;
+
+use super::models::Unreported;
+
+const METER_EVENTS_URL: &str = "https://api.stripe.com/v1/billing/meter_events";
+
+/// Whether there's anything to report to.
+pub fn is_configured() -> bool {
+    var("STRIPE_API_KEY").map_or(false, |key| !key.is_empty())
+}
+
+/// The meter event name for `metric`, if it's billed through Stripe.
+pub fn event_name(metric: &str) -> Option<String> {
+    var(format!("STRIPE_METER_{}", metric.to_uppercase()))
+        .ok()
+        .filter(|name| !name.is_empty())
+}
+
+/// Sends one line item. The identifier is derived from the account, metric
+/// and day, so Stripe drops it if a previous attempt got through after all.
+pub fn report(item: &Unreported, event_name: &str) -> Result<(), Error> {
+    let api_key = var("STRIPE_API_KEY").context("STRIPE_API_KEY not set!")?;
+    let timestamp = Utc.from_utc_date(&item.day).and_hms(0, 0, 0).timestamp();
+    let identifier = format!("{}-{}-{}", item.account_id, item.metric, item.day);
+
+    let body = [
+        ("event_name", event_name.to_string()),
+        ("timestamp", timestamp.to_string()),
+        ("identifier", identifier),
+        ("payload[stripe_customer_id]", item.stripe_customer_id.clone()),
+        ("payload[value]", item.quantity.to_string()),
+    ]
+    .iter()
+    .map(|(key, value)| format!("{}={}", encode(key), encode(value)))
+    .collect::<Vec<_>>()
+    .join("&");
+
+    let resp = minreq::post(METER_EVENTS_URL)
+        .with_header("Authorization", format!("Bearer {}", api_key))
+        .with_header("Content-Type", "application/x-www-form-urlencoded")
+        .with_body(body)
+        .send()
+        .context("Posting meter event to Stripe")?;
+
+    if resp.status_code == 200 {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Reporting {} usage for account {} to Stripe failed. API call returns code {} : {} \n {} ",
+            item.metric,
+            item.account_id,
+            resp.status_code,
+            resp.reason_phrase,
+            resp.as_str()?
+        ))
+    }
+}
+
+/// Percent-encodes a form field.
+fn encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
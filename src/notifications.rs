@@ -0,0 +1,90 @@
+//! In-app notifications, listed at `/dashboard/notifications`. Accounts
+//! can also have their unread ones batched into a daily or weekly digest
+//! email, grouped by category (see `scheduler::SendDigests`), from their
+//! preferences; each digest has a link to turn them off without signing
+//! in.
+//!
+//! ```ignore
+//! Notification::create(account.id, "files", "Upload quarantined", Some(&name), Some("/files"), pool).await?;
+//! ```
+
+use std::env;
+use std::sync::{Arc, RwLock};
+
+use jelly::actix_web::web::ServiceConfig;
+use jelly::anyhow::Error;
+use jelly::crypto;
+use jelly::email::{Email, EmailCategory};
+use jelly::routes;
+use jelly::tera::{Context, Tera};
+
+pub mod models;
+pub mod views;
+
+pub use models::Notification;
+
+use crate::accounts::models::Digest;
+use models::{group_by_category, DigestRecipient};
+
+/// Keeps unsubscribe tokens from being accepted anywhere else.
+const KEY_SALT: &str = "com.jelly.notifications.unsubscribe";
+
+/// A token that turns off `account_id`'s digests.
+pub fn unsubscribe_token(account_id: i32) -> String {
+    let id = account_id.to_string();
+    format!("{}.{}", id, crypto::sign(KEY_SALT, &id))
+}
+
+/// The account an unsubscribe token is for, if it's genuine.
+pub fn account_for_token(token: &str) -> Option<i32> {
+    let (id, signature) = token.split_once('.')?;
+    if !crypto::verify(KEY_SALT, id, signature) {
+        return None;
+    }
+    id.parse().ok()
+}
+
+/// The digest of `notifications` for `recipient`, as bulk mail.
+pub fn build_digest_email(
+    recipient: &DigestRecipient,
+    digest: Digest,
+    notifications: &[Notification],
+    templates: Arc<RwLock<Tera>>,
+) -> Result<Email, Error> {
+    let domain = env::var("JELLY_DOMAIN").expect("No JELLY_DOMAIN value set!");
+    let mut context = Context::new();
+    context.insert("name", &recipient.name);
+    context.insert("domain", &domain);
+    context.insert("groups", &group_by_category(notifications));
+    context.insert("count", &notifications.len());
+    context.insert("frequency", digest.as_str());
+    context.insert("action_url", &format!("{}/dashboard/notifications", domain));
+    context.insert(
+        "unsubscribe_url",
+        &format!(
+            "{}/notifications/unsubscribe?token={}",
+            domain,
+            unsubscribe_token(recipient.account_id)
+        ),
+    );
+
+    let subject = match notifications.len() {
+        1 => "You have 1 unread notification".to_string(),
+        count => format!("You have {} unread notifications", count),
+    };
+    Ok(Email::new(
+        "email/notifications-digest",
+        &[recipient.email.clone()],
+        &subject,
+        context,
+        templates,
+    )?
+    .with_category(EmailCategory::Bulk))
+}
+
+pub fn configure(config: &mut ServiceConfig) {
+    routes::resource("/notifications/unsubscribe")
+        .get(views::form)
+        .post(views::unsubscribe)
+        .mount(config);
+}
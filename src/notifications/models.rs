@@ -0,0 +1,174 @@
+use jelly::chrono::{DateTime, Utc};
+use jelly::error::Error;
+use jelly::serde::Serialize;
+use jelly::tenancy::TenantPool;
+use sqlx::postgres::PgPool;
+
+use crate::accounts::models::Digest;
+
+/// Something that happened to an account, shown in the app until it's
+/// read.
+#[derive(Debug, Serialize)]
+pub struct Notification {
+    pub id: i32,
+    pub account_id: i32,
+    /// What it's about, e.g `files` or `billing`; digests group by it.
+    pub category: String,
+    pub title: String,
+    pub body: Option<String>,
+    /// Where to go about it, if anywhere; relative to the site.
+    pub url: Option<String>,
+    pub read_at: Option<DateTime<Utc>>,
+    pub digested_at: Option<DateTime<Utc>>,
+    pub created: DateTime<Utc>,
+}
+
+/// An account that's due a digest.
+#[derive(Debug)]
+pub struct DigestRecipient {
+    pub account_id: i32,
+    pub name: String,
+    pub email: String,
+}
+
+impl Notification {
+    pub async fn create(
+        account_id: i32,
+        category: &str,
+        title: &str,
+        body: Option<&str>,
+        url: Option<&str>,
+        pool: &PgPool,
+    ) -> Result<i32, Error> {
+        Ok(sqlx::query!(
+            "
+            INSERT INTO notifications (account_id, category, title, body, url)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id
+        ",
+            account_id,
+            category,
+            title,
+            body,
+            url
+        )
+        .fetch_one(pool)
+        .await?
+        .id)
+    }
+
+    /// The tenant's notifications from the last month, newest first.
+    pub async fn for_account(db: &TenantPool<'_>) -> Result<Vec<Self>, Error> {
+        Ok(jelly::tenant_query_as!(
+            db,
+            Notification,
+            "
+            SELECT
+                id, account_id, category, title, body, url, read_at, digested_at, created
+            FROM notifications
+            WHERE account_id = $1 AND created > now() - interval '30 days'
+            ORDER BY created DESC
+        "
+        )
+        .fetch_all(db.pool())
+        .await?)
+    }
+
+    /// Marks all of the tenant's notifications read, returning how many
+    /// weren't.
+    pub async fn mark_all_read(db: &TenantPool<'_>) -> Result<u64, Error> {
+        Ok(jelly::tenant_query!(
+            db,
+            "
+            UPDATE notifications SET read_at = now()
+            WHERE account_id = $1 AND read_at IS NULL
+        "
+        )
+        .execute(db.pool())
+        .await?
+        .rows_affected())
+    }
+
+    /// Active accounts with `digest` set that have unread notifications
+    /// not yet in one, and haven't had one in its days.
+    pub async fn due_digest(digest: Digest, pool: &PgPool) -> Result<Vec<DigestRecipient>, Error> {
+        Ok(sqlx::query_as_unchecked!(
+            DigestRecipient,
+            "
+            SELECT a.id AS account_id, a.name, a.email
+            FROM accounts a
+            WHERE a.is_active AND a.email_deliverable
+                AND a.profile->'notifications'->>'digest' = $1
+                AND EXISTS (
+                    SELECT 1 FROM notifications n
+                    WHERE n.account_id = a.id AND n.read_at IS NULL AND n.digested_at IS NULL
+                )
+                AND NOT EXISTS (
+                    SELECT 1 FROM notifications n
+                    WHERE n.account_id = a.id AND n.digested_at > now() - make_interval(days => $2)
+                )
+        ",
+            digest.as_str(),
+            digest.days()
+        )
+        .fetch_all(pool)
+        .await?)
+    }
+
+    /// The account's unread notifications not yet in a digest, by category,
+    /// oldest first.
+    pub async fn undigested(account_id: i32, pool: &PgPool) -> Result<Vec<Self>, Error> {
+        Ok(sqlx::query_as_unchecked!(
+            Notification,
+            "
+            SELECT
+                id, account_id, category, title, body, url, read_at, digested_at, created
+            FROM notifications
+            WHERE account_id = $1 AND read_at IS NULL AND digested_at IS NULL
+            ORDER BY category, created
+        ",
+            account_id
+        )
+        .fetch_all(pool)
+        .await?)
+    }
+
+    /// Records that `ids` went out in a digest, so they aren't sent again.
+    pub async fn mark_digested(account_id: i32, ids: &[i32], pool: &PgPool) -> Result<(), Error> {
+        sqlx::query!(
+            "
+            UPDATE notifications SET digested_at = now()
+            WHERE account_id = $1 AND id = ANY($2)
+        ",
+            account_id,
+            ids
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// A digest's notifications in one category.
+#[derive(Debug, Serialize)]
+pub struct CategoryGroup<'a> {
+    pub category: &'a str,
+    pub notifications: Vec<&'a Notification>,
+}
+
+/// `notifications` grouped by category, in the order each category first
+/// appears.
+pub fn group_by_category(notifications: &[Notification]) -> Vec<CategoryGroup<'_>> {
+    let mut groups: Vec<CategoryGroup> = Vec::new();
+    for notification in notifications {
+        match groups.iter_mut().find(|group| group.category == notification.category) {
+            Some(group) => group.notifications.push(notification),
+            None => groups.push(CategoryGroup {
+                category: &notification.category,
+                notifications: vec![notification],
+            }),
+        }
+    }
+    groups
+}
@@ -0,0 +1,49 @@
+use jelly::actix_web::{web, HttpRequest};
+use jelly::prelude::*;
+use jelly::serde_json::json;
+use jelly::Result;
+use serde::Deserialize;
+
+use super::account_for_token;
+use crate::accounts::models::{Digest, NotificationPreferences};
+use crate::accounts::Account;
+use crate::audit::AuditEvent;
+
+#[derive(Deserialize)]
+pub struct UnsubscribeForm {
+    pub token: String,
+}
+
+/// Asks whoever followed a digest's unsubscribe link to confirm, so mail
+/// scanners fetching the link don't turn digests off.
+pub async fn form(request: HttpRequest, query: web::Query<UnsubscribeForm>) -> Result<HttpResponse> {
+    if account_for_token(&query.token).is_none() {
+        return request.render(404, "404.html", Context::new());
+    }
+
+    request.render(200, "notifications/unsubscribe.html", {
+        let mut ctx = Context::new();
+        ctx.insert("token", &query.token);
+        ctx.insert("done", &false);
+        ctx
+    })
+}
+
+/// Turns off the token's account's digests; no need to be signed in.
+pub async fn unsubscribe(request: HttpRequest, form: web::Form<UnsubscribeForm>) -> Result<HttpResponse> {
+    let account_id = match account_for_token(&form.token) {
+        Some(account_id) => account_id,
+        None => return request.render(404, "404.html", Context::new()),
+    };
+
+    let db = request.db_pool()?;
+    let changes = json!({ "notifications": NotificationPreferences { digest: Digest::Off } });
+    Account::update_profile(account_id, &changes, db).await?;
+    AuditEvent::record_request(&request, account_id, "notifications.unsubscribed", json!({})).await?;
+
+    request.render(200, "notifications/unsubscribe.html", {
+        let mut ctx = Context::new();
+        ctx.insert("done", &true);
+        ctx
+    })
+}
@@ -1,30 +1,101 @@
-//! OAuth2 authentication.
+//! OAuth2 authentication. Admins can turn providers off without a
+//! deploy by listing them in the `oauth.disabled_providers` setting.
+//!
+//! Identities' refresh tokens are revoked at the provider when they're
+//! unlinked, and with the `oauth.revoke_on_logout` setting on, when their
+//! account signs out too.
 
-use jelly::actix_web::web::{get, post, resource, scope, ServiceConfig};
+use jelly::actix_web::web::ServiceConfig;
+use jelly::error::Error;
+use jelly::oauth;
+use jelly::routes::{resource, scope};
+use jelly::settings;
+use sqlx::postgres::PgPool;
+
+use crate::accounts::models::Identity;
 
 pub mod forms;
+pub mod models;
 pub mod views;
 
+/// Session key holding who the provider said someone is (a
+/// `forms::PendingIdentity`), from the callback until they confirm.
+pub const SESSION_PENDING_IDENTITY: &str = "oauth_identity";
+
+/// The setting listing providers admins have turned off.
+pub const DISABLED_PROVIDERS: &str = "oauth.disabled_providers";
+
+/// The providers people can log in with, by name.
+pub async fn enabled_providers(pool: &PgPool) -> Result<Vec<&'static str>, Error> {
+    let disabled: Vec<String> = settings::get_or(pool, DISABLED_PROVIDERS, Vec::new()).await?;
+    Ok(oauth::client::providers()
+        .into_iter()
+        .filter(|provider| !disabled.iter().any(|d| d == provider))
+        .collect())
+}
+
+pub async fn provider_enabled(provider: &str, pool: &PgPool) -> Result<bool, Error> {
+    Ok(enabled_providers(pool).await?.contains(&provider))
+}
+
+/// The setting that has signing out revoke the account's provider tokens.
+pub const REVOKE_ON_LOGOUT: &str = "oauth.revoke_on_logout";
+
+pub async fn revoke_on_logout(pool: &PgPool) -> Result<bool, Error> {
+    settings::get_or(pool, REVOKE_ON_LOGOUT, false).await
+}
+
+/// Revokes `identity`'s refresh token at its provider (see
+/// `jelly::oauth::revoke`) and forgets it. The provider failing doesn't
+/// stop whatever's revoking it; that's logged, and the token's forgotten
+/// all the same.
+pub async fn revoke(identity: &Identity, pool: &PgPool) -> Result<(), Error> {
+    let refresh_token = match &identity.refresh_token {
+        Some(refresh_token) => refresh_token,
+        None => return Ok(()),
+    };
+
+    match oauth::client::client_for(&identity.provider) {
+        Some(client) => match oauth::revoke(&client, refresh_token).await {
+            Ok(true) => info!("Revoked {} token for identity {}", identity.provider, identity.id),
+            Ok(false) => debug!("{} has no revocation endpoint", identity.provider),
+            Err(e) => error!("Error revoking {} token for identity {}: {:?}", identity.provider, identity.id, e),
+        },
+        None => warn!("Can't revoke {} token: provider isn't configured", identity.provider),
+    }
+
+    Identity::clear_refresh_token(identity.id, pool).await
+}
+
 /// Enables oauth2 login and authentication.
 pub fn configure(config: &mut ServiceConfig) {
-    config.service(
-        scope("/oauth")
-            .service(
-                resource("/login/{provider}")
-                    .route(get().to(views::login::form)),
-            )
-            .service(
-                resource("/login")
-                    .route(post().to(views::login::authenticate)),
-            )
-            .service(
-                resource("/callback")
-                    .name("oauth-callback")
-                    .route(get().to(views::authorize::exchange_code_for_token)),
-            )
-            .service(
-                resource("/confirm")
-                    .route(post().to(views::authorize::confirm_identity)),
-            ),
-    );
+    scope("/oauth")
+        .resource(resource("/login/{provider}").get(views::login::form))
+        .resource(resource("/login").post(views::login::authenticate))
+        .resource(
+            resource("/callback")
+                .name("oauth-callback")
+                .get(views::authorize::exchange_code_for_token)
+                .post(views::authorize::form_post_callback),
+        )
+        .resource(
+            resource("/confirm")
+                .post(views::authorize::confirm_identity)
+                .limited(views::authorize::signup_limit(), "ip"),
+        )
+        .resource(resource("/link/{provider}").post(views::login::link).auth())
+        .resource(
+            resource("/unlink/{provider}")
+                .get(views::unlink::form)
+                .post(views::unlink::unlink)
+                .auth(),
+        )
+        .resource(
+            resource("/device/start")
+                .post(views::device::start)
+                .limited(views::device::start_limit(), "ip"),
+        )
+        .resource(resource("/device/token").post(views::device::poll))
+        .resource(resource("/device/{id}").get(views::device::show))
+        .mount(config);
 }
@@ -1,8 +1,11 @@
-use jelly::forms::{EmailField, TextField};
+use jelly::challenge;
+use jelly::forms::{EmailField, SlugField, TextField};
 use jelly::forms::validation::{concat_results, Validatable, ValidationErrors};
-use jelly::oauth;
+use jelly::oauth::{self, UserInfo};
 use serde::{Deserialize, Serialize};
 
+use crate::accounts::forms::usernames_enabled;
+
 fn default_provider() -> String {
     oauth::client::DEFAULT_PROVIDER.to_string()
 }
@@ -47,20 +50,95 @@ impl Validatable<String> for OAuthLoginForm {
     }
 }
 
+/// Who the provider said someone is, kept in the session from the callback
+/// to the confirm step, since the form's hidden fields could say anything.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PendingIdentity {
+    pub provider: String,
+    pub username: String,
+    pub email: Option<String>,
+}
+
 #[derive(Default, Debug, Deserialize, Serialize)]
 pub struct LinkIdentityForm {
     pub provider: String,
+    /// The provider's username for them.
     pub username: String,
+    /// The email address the provider has for them, if it shares it. Unlike
+    /// `email`, they can't change it.
+    #[serde(skip)]
+    pub provider_email: Option<String>,
     pub name: TextField,
     pub email: EmailField,
+    /// The username a new account gets, if usernames are enabled;
+    /// suggested from the provider's.
+    #[serde(default)]
+    pub account_username: SlugField,
+    /// The sign up challenge, and its answer, if there is one; see
+    /// `challenge_response`.
+    #[serde(default, skip_serializing)]
+    pub challenge: String,
+    #[serde(default, skip_serializing)]
+    pub challenge_solution: String,
+    #[serde(default, rename = "cf-turnstile-response", skip_serializing)]
+    pub turnstile_response: String,
 }
 
 impl LinkIdentityForm {
+    /// The form for confirming what the provider told us about them.
+    pub fn from_user_info(user_info: UserInfo) -> Self {
+        LinkIdentityForm {
+            provider: user_info.provider.to_string(),
+            username: user_info.username.unwrap_or(user_info.id),
+            provider_email: user_info.provider_email,
+            name: TextField::new(user_info.name),
+            email: EmailField::new(user_info.login_email),
+            ..LinkIdentityForm::default()
+        }
+    }
+
+    /// What to keep in the session for the confirm step.
+    pub fn pending_identity(&self) -> PendingIdentity {
+        PendingIdentity {
+            provider: self.provider.clone(),
+            username: self.username.clone(),
+            email: self.provider_email.clone(),
+        }
+    }
+
+    /// The form as confirmed, with the identity as the provider gave it
+    /// rather than as posted.
+    pub fn with_pending_identity(mut self, pending: PendingIdentity) -> Self {
+        self.provider = pending.provider;
+        self.username = pending.username;
+        self.provider_email = pending.email;
+        self
+    }
+
     pub fn set_keys(mut self) -> Self {
         self.name = self.name.with_key("name");
         self.email = self.email.with_key("email");
+        self.account_username = self.account_username.with_key("account_username");
         self
     }
+
+    /// What came back for the sign up challenge; see `jelly::challenge`.
+    pub fn challenge_response(&self) -> challenge::Response {
+        challenge::Response {
+            token: self.challenge.clone(),
+            solution: self.challenge_solution.clone(),
+            turnstile: self.turnstile_response.clone(),
+        }
+    }
+
+    /// The chosen username, if usernames are enabled.
+    pub fn account_username(&self) -> Option<&str> {
+        if usernames_enabled() && !self.account_username.value.is_empty() {
+            Some(&self.account_username.value)
+        } else {
+            None
+        }
+    }
 }
 
 impl Validatable<String> for LinkIdentityForm {
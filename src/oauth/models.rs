@@ -0,0 +1,206 @@
+// Server-side storage for in-progress OAuth authorizations. Keeping these
+// out of the cookie session means flows survive cookie size limits, and
+// a state value can only ever be redeemed once.
+
+use jelly::chrono::{DateTime, Duration, Utc};
+use jelly::constant_time_eq::constant_time_eq;
+use jelly::error::Error;
+use jelly::oauth::OAuthFlow;
+use serde::Serialize;
+use sqlx::postgres::PgPool;
+
+pub struct OAuthFlowRecord;
+
+impl OAuthFlowRecord {
+    /// Stores a new flow, keyed by its CSRF state.
+    pub async fn insert(flow: &OAuthFlow, pool: &PgPool) -> Result<(), Error> {
+        sqlx::query!(
+            "
+            INSERT INTO oauth_flows (state, provider, email, pkce_verifier_secret, scopes)
+            VALUES ($1, $2, $3, $4, $5)
+        ",
+            flow.csrf_token_secret,
+            flow.provider,
+            flow.email,
+            flow.pkce_verifier_secret,
+            &flow.scopes
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Removes and returns the unexpired flow for `state`, if there is one
+    /// and it's the one `session_state` (what the session was given when the
+    /// flow started) says this browser started. Otherwise anyone could send
+    /// a victim to the callback with the state of a flow of their own, and
+    /// have them sign in as, or link, the attacker's identity.
+    pub async fn take(state: &str, session_state: Option<&str>, pool: &PgPool) -> Result<Option<OAuthFlow>, Error> {
+        match session_state {
+            Some(started) if constant_time_eq(started.as_bytes(), state.as_bytes()) => {}
+            _ => return Ok(None),
+        }
+
+        Ok(sqlx::query!(
+            "
+            DELETE FROM oauth_flows
+            WHERE state = $1 AND expires > now()
+            RETURNING state, provider, email, pkce_verifier_secret, scopes
+        ",
+            state
+        )
+        .fetch_optional(pool)
+        .await?
+        .map(|row| OAuthFlow {
+            provider: row.provider,
+            email: row.email,
+            authorization_code: String::new(),
+            csrf_token_secret: row.state,
+            pkce_verifier_secret: row.pkce_verifier_secret,
+            scopes: row.scopes,
+        }))
+    }
+
+    /// Deletes abandoned flows; called from the scheduler.
+    pub async fn delete_expired(pool: &PgPool) -> Result<u64, Error> {
+        Ok(sqlx::query!(
+            "
+            DELETE FROM oauth_flows WHERE expires <= now()
+        "
+        )
+        .execute(pool)
+        .await?
+        .rows_affected())
+    }
+}
+
+/// A device authorization (see `jelly::oauth::device`) its client is
+/// polling for. `id` is public, for the page showing the user code; the
+/// client polls with a code of its own, of which only a signature's kept.
+#[derive(Debug, Serialize)]
+pub struct OAuthDeviceFlow {
+    pub id: String,
+    pub provider: String,
+    #[serde(skip)]
+    pub device_code: String,
+    pub user_code: String,
+    pub verification_uri: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verification_uri_complete: Option<String>,
+    pub interval_seconds: i32,
+    pub last_polled: Option<DateTime<Utc>>,
+    pub expires: DateTime<Utc>,
+}
+
+impl OAuthDeviceFlow {
+    /// Whether the client's polling again before its interval's up.
+    pub fn polled_too_soon(&self, now: DateTime<Utc>) -> bool {
+        self.last_polled
+            .map_or(false, |last| now < last + Duration::seconds(self.interval_seconds.into()))
+    }
+}
+
+pub struct OAuthDeviceFlowRecord;
+
+impl OAuthDeviceFlowRecord {
+    /// Stores a new flow, under the signature of the code its client polls
+    /// with.
+    pub async fn insert(flow: &OAuthDeviceFlow, code_hash: &str, pool: &PgPool) -> Result<(), Error> {
+        sqlx::query!(
+            "
+            INSERT INTO oauth_device_flows (
+                id, code_hash, provider, device_code, user_code,
+                verification_uri, verification_uri_complete, interval_seconds, expires
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        ",
+            flow.id,
+            code_hash,
+            flow.provider,
+            flow.device_code,
+            flow.user_code,
+            flow.verification_uri,
+            flow.verification_uri_complete,
+            flow.interval_seconds,
+            flow.expires
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// The unexpired flow with public `id`, for its page.
+    pub async fn get(id: &str, pool: &PgPool) -> Result<Option<OAuthDeviceFlow>, Error> {
+        Ok(sqlx::query_as!(
+            OAuthDeviceFlow,
+            "
+            SELECT
+                id, provider, device_code, user_code, verification_uri,
+                verification_uri_complete, interval_seconds, last_polled, expires
+            FROM oauth_device_flows
+            WHERE id = $1 AND expires > now()
+        ",
+            id
+        )
+        .fetch_optional(pool)
+        .await?)
+    }
+
+    /// The unexpired flow polled for with the code signed as `code_hash`.
+    pub async fn by_code(code_hash: &str, pool: &PgPool) -> Result<Option<OAuthDeviceFlow>, Error> {
+        Ok(sqlx::query_as!(
+            OAuthDeviceFlow,
+            "
+            SELECT
+                id, provider, device_code, user_code, verification_uri,
+                verification_uri_complete, interval_seconds, last_polled, expires
+            FROM oauth_device_flows
+            WHERE code_hash = $1 AND expires > now()
+        ",
+            code_hash
+        )
+        .fetch_optional(pool)
+        .await?)
+    }
+
+    /// Notes a poll, and the interval the next has to wait.
+    pub async fn polled(id: &str, interval_seconds: i32, pool: &PgPool) -> Result<(), Error> {
+        sqlx::query!(
+            "
+            UPDATE oauth_device_flows
+            SET last_polled = now(), interval_seconds = $2
+            WHERE id = $1
+        ",
+            id,
+            interval_seconds
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Removes a flow that's finished, one way or another. Returns whether
+    /// it was still there, so two polls can't both finish it.
+    pub async fn delete(id: &str, pool: &PgPool) -> Result<bool, Error> {
+        Ok(sqlx::query!("DELETE FROM oauth_device_flows WHERE id = $1", id)
+            .execute(pool)
+            .await?
+            .rows_affected()
+            > 0)
+    }
+
+    /// Deletes flows nobody finished; called from the scheduler.
+    pub async fn delete_expired(pool: &PgPool) -> Result<u64, Error> {
+        Ok(sqlx::query!(
+            "
+            DELETE FROM oauth_device_flows WHERE expires <= now()
+        "
+        )
+        .execute(pool)
+        .await?
+        .rows_affected())
+    }
+}
@@ -1,4 +1,6 @@
 //! OAuth views.
 
 pub mod authorize;
+pub mod device;
 pub mod login;
+pub mod unlink;
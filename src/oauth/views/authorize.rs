@@ -1,22 +1,92 @@
-use jelly::{oauth, Result, SESSION_OAUTH_FLOW, SESSION_OAUTH_TOKEN};
-use jelly::actix_session::Session;
+use jelly::{oauth, Result, SESSION_OAUTH_SCOPES, SESSION_OAUTH_STATE, SESSION_OAUTH_TOKEN};
+use jelly::accounts::sessions;
 use jelly::actix_web::web;
+use jelly::challenge::Challenge;
 use jelly::error::OAuthError;
-use jelly::forms::{EmailField, TextField};
-use jelly::forms::validation::{Validatable, ValidationError, ValidationErrors};
-use jelly::oauth::{ClientFlow, OAuthFlow, UserInfo};
+use jelly::forms::{EmailDomains, EmailField, SlugField};
+use jelly::forms::validation::{concat_results, Validatable, ValidationError, ValidationErrors};
+use jelly::oauth::{ClientFlow, UserInfo};
+use jelly::oauth2::url::form_urlencoded;
 use jelly::prelude::*;
+use jelly::ratelimit::{self, Limit};
+use jelly::serde_json::json;
 use serde::{Deserialize, Serialize};
 use std::{result, str};
 
-use crate::accounts::Account;
-use crate::oauth::forms::LinkIdentityForm;
+use crate::accounts::forms::usernames_enabled;
+use crate::accounts::models::Identity;
+use crate::accounts::{email_domains, Account};
+use crate::audit::jobs::AnalyzeLogin;
+use crate::audit::AuditEvent;
+use crate::invitations::{self, invite_only, Invitation, SESSION_INVITATION_TOKEN};
+use crate::oauth::forms::{LinkIdentityForm, PendingIdentity};
+use crate::oauth::models::OAuthFlowRecord;
+use crate::oauth::SESSION_PENDING_IDENTITY;
+use crate::waitlist::{self, waitlist_enabled, WaitlistEntry, SESSION_INVITE_TOKEN};
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct AuthRequest {
     code: Option<String>,
     state: Option<String>,
     error: Option<String>,
+    /// Passed on from `form_post_callback`, for providers whose profile
+    /// doesn't have it.
+    name: Option<String>,
+}
+
+/// What Apple posts to the callback (`response_mode=form_post`). `user`
+/// is only sent the first time the user signs in, as JSON:
+///   {"name":{"firstName":"Jane","lastName":"Doe"},"email":"jane@example.com"}
+#[derive(Debug, Deserialize)]
+pub struct FormPostCallback {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+    user: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FormPostUser {
+    name: Option<FormPostName>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FormPostName {
+    first_name: Option<String>,
+    last_name: Option<String>,
+}
+
+/// The full name from a form post's `user`, if it has one.
+fn form_post_name(user: &str) -> Option<String> {
+    let name = jelly::serde_json::from_str::<FormPostUser>(user).ok()?.name?;
+    let name = [name.first_name, name.last_name]
+        .iter()
+        .flatten()
+        .map(|part| part.trim())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    Some(name).filter(|name| !name.is_empty())
+}
+
+/// Handle a callback posted by the provider (Apple), by sending it on to
+/// `exchange_code_for_token`: the post comes from another site, so the
+/// session cookie isn't sent with it, but it is with the redirect.
+pub async fn form_post_callback(
+    request: HttpRequest,
+    form: web::Form<FormPostCallback>,
+) -> Result<HttpResponse> {
+    let form = form.into_inner();
+    let name = form.user.as_deref().and_then(form_post_name);
+
+    let mut query = form_urlencoded::Serializer::new(String::new());
+    for (key, value) in [("code", form.code), ("state", form.state), ("error", form.error), ("name", name)] {
+        if let Some(value) = value {
+            query.append_pair(key, &value);
+        }
+    }
+    request.redirect(&format!("{}?{}", oauth::client::CALLBACK_PATH, query.finish()))
 }
 
 /// Handle callback from Google. Query string in request is:
@@ -30,102 +100,260 @@ pub async fn exchange_code_for_token(
     query: web::Query<AuthRequest>,
 ) -> Result<HttpResponse> {
     let session = &request.get_session();
-    validate_inputs(session, query)
-        .and_then(oauth::request_token)
-        .map_err(|e| e.into())
-        .and_then(|token_info| oauth::fetch_user_info(session, token_info))
-        .and_then(|user_info| finalize_authentication(request, user_info))
+    session.remove(SESSION_OAUTH_TOKEN);
+    session.remove(SESSION_OAUTH_SCOPES);
+
+    let name = query.name.clone();
+    let client_flow = validate_inputs(&request, query).await??;
+    let token_info = oauth::request_token(client_flow).await?;
+    let mut user_info = oauth::fetch_user_info(session, token_info).await?;
+    if user_info.name.is_empty() {
+        user_info.name = name.unwrap_or_default();
+    }
+    finalize_authentication(request, user_info).await
+}
+
+/// What challenges for signing up are issued for; see `jelly::challenge`.
+const SIGNUP_CHALLENGE: &str = "oauth-signup";
+
+/// The challenge people signing up through a provider have to pass, from
+/// `OAUTH_SIGNUP_CHALLENGE`; none by default.
+fn signup_challenge() -> Challenge {
+    Challenge::from_env("OAUTH_SIGNUP_CHALLENGE")
+}
+
+/// How many times people can try signing up through providers from one IP
+/// address.
+pub(crate) fn signup_limit() -> Limit {
+    Limit::from_env("OAUTH_SIGNUPS_PER_IP", 5, "OAUTH_SIGNUP_WINDOW_MINUTES", 60)
+}
+
+fn signup_limit_key(request: &HttpRequest) -> String {
+    format!("oauth:signup:ip:{}", ratelimit::client_ip(request))
+}
+
+/// Renders the confirm step. People `registering` choose a username (if
+/// usernames are enabled) and get the sign up challenge.
+fn render_confirm(
+    request: &HttpRequest,
+    status: usize,
+    form: &LinkIdentityForm,
+    registering: bool,
+    errors: Option<ValidationErrors<String>>,
+) -> Result<HttpResponse> {
+    let choose_username = registering && usernames_enabled();
+    request.render(status, "oauth/confirm.html", {
+        let mut context = Context::new();
+        if registering {
+            context.insert("challenge", &signup_challenge().issue(SIGNUP_CHALLENGE));
+        }
+
+        // ValidationErrors object is serialized into HashMap here
+        if let Some(errors) = errors {
+            context.insert("errors", &errors);
+        }
+        context.insert("form", form);
+        context.insert("choose_username", &choose_username);
+        context
+    })
+}
+
+/// The account `form` would sign in to, or link the identity to: the
+/// signed in user's, or else the one with the email the provider has for
+/// them. Not the one typed in: anyone can type anyone's address.
+async fn existing_account_id(request: &HttpRequest, form: &LinkIdentityForm) -> Result<Option<i32>> {
+    let user = request.user()?;
+    if !user.is_anonymous {
+        return Ok(Some(user.id));
+    }
+    match &form.provider_email {
+        Some(email) => Ok(Account::id_by_email(email, request.db_pool()?).await.ok()),
+        None => Ok(None),
+    }
+}
+
+/// Checks a new account's email against the allowed domains: both the one
+/// typed in and, since that can be anything, the one the provider has for
+/// them. While only some domains are allowed, the provider has to share one.
+fn check_email_domains(form: &LinkIdentityForm, domains: &EmailDomains) -> result::Result<(), ValidationErrors<String>> {
+    form.email.validate_with(domains)?;
+    match &form.provider_email {
+        Some(email) => EmailField::new(email.as_str()).with_key("email").validate_with(domains),
+        None if domains.allowed.is_empty() => Ok(()),
+        None => Err(ValidationError::new("email".to_owned(), "EMAIL_NOT_SHARED")
+            .with_message(move |_| "sign up with an account that shares your email address".to_owned())
+            .into()),
+    }
+}
+
+/// Whether confirming `form` signs up a new account: there's no account to
+/// link to, and the identity isn't linked to one already.
+async fn is_registering(request: &HttpRequest, form: &LinkIdentityForm, account_id: Option<i32>) -> Result<bool> {
+    Ok(account_id.is_none()
+        && Identity::get_by_provider_username(&form.provider, &form.username, request.db_pool()?)
+            .await
+            .is_err())
 }
 
 pub async fn confirm_identity(
     request: HttpRequest,
     form: web::Form<LinkIdentityForm>,
 ) -> Result<HttpResponse> {
-    let form = form.into_inner().set_keys();
-    if let Err(errors) = form.validate() {
-        return request.render(400, "oauth/confirm.html", {
-            let mut context = Context::new();
+    let pending = match request.get_session().get::<PendingIdentity>(SESSION_PENDING_IDENTITY)? {
+        Some(pending) => pending,
+        None => return request.redirect("/accounts/login"),
+    };
+    let form = form.into_inner().set_keys().with_pending_identity(pending);
+    let account_id = existing_account_id(&request, &form).await?;
+    let registering = is_registering(&request, &form, account_id).await?;
+    let choose_username = registering && usernames_enabled();
 
-            // ValidationErrors object is serialized into HashMap here
-            context.insert("errors", &errors);
-            context.insert("form", &form);
-            context
-        });
+    let mut results = vec![form.validate()];
+    if choose_username {
+        results.push(form.account_username.validate_username());
+    }
+    if let Err(errors) = concat_results(results) {
+        return render_confirm(&request, 400, &form, registering, Some(errors));
     }
 
     let refresh_token = request.get_session().get::<String>(SESSION_OAUTH_TOKEN)?;
+    let scopes = request.get_session().get::<Vec<String>>(SESSION_OAUTH_SCOPES)?.unwrap_or_default();
     let db = request.db_pool()?;
-    let user = request.user()?;
-    let account_id = if user.is_anonymous {
-        Account::id_by_email(&form.email.value, db).await.ok()
-    } else {
-        Some(user.id)
-    };
+    // Only new accounts are held to the allowed domains; existing ones can
+    // still sign in and link identities.
+    if registering {
+        if let Err(errors) = check_email_domains(&form, &email_domains(db).await?) {
+            return render_confirm(&request, 400, &form, registering, Some(errors));
+        }
+    }
+    if choose_username && Account::username_taken(&form.account_username.value, db).await? {
+        let errors: ValidationErrors<String> = ValidationError::new("account_username".to_owned(), "USERNAME_TAKEN")
+            .with_message(move |_| "username is already taken".to_owned())
+            .into();
+        return render_confirm(&request, 400, &form, registering, Some(errors));
+    }
+
+    // Signing up is where scripts would mass-create accounts, so attempts
+    // are limited by IP address (counted as they're made), and can ask for
+    // a challenge too.
+    if registering {
+        if !ratelimit::attempt(&signup_limit_key(&request), &signup_limit()).await? {
+            let errors: ValidationErrors<String> = ValidationError::new("challenge".to_owned(), "TOO_MANY_SIGNUPS")
+                .with_message(move |_| "too many accounts have signed up from here; please try again later".to_owned())
+                .into();
+            return render_confirm(&request, 429, &form, registering, Some(errors));
+        }
+
+        let ip = ratelimit::client_ip(&request);
+        let response = form.challenge_response();
+        if !signup_challenge().verify(SIGNUP_CHALLENGE, &response, Some(&ip)).await? {
+            let errors: ValidationErrors<String> = ValidationError::new("challenge".to_owned(), "CHALLENGE_FAILED")
+                .with_message(move |_| "we couldn't check you're not a robot; please try again".to_owned())
+                .into();
+            return render_confirm(&request, 400, &form, registering, Some(errors));
+        }
+    }
+
+    // Signing up this way needs an invite too while the waitlist is on.
+    let mut invite = None;
+    if waitlist_enabled() && registering {
+        match waitlist::views::session_invite(&request).await? {
+            Some(entry) => invite = entry.invite_token,
+            None => return waitlist::views::form(&request),
+        }
+    }
+
+    // And an invitation while registration's invite-only, as it does for
+    // registering with a password.
+    let mut invitation = None;
+    if invite_only() && registering {
+        match invitations::views::session_invitation(&request).await? {
+            Some(found) if Invitation::claim(&found.token, db).await? => invitation = Some(found.token),
+            _ => return invitations::views::required(&request),
+        }
+    }
+
+    let merged = Account::merge_identity_and_login(&form, refresh_token, scopes, account_id, db).await;
+    if let Some(token) = &invitation {
+        match &merged {
+            Ok(user) => Invitation::used_by(token, user.id, db).await?,
+            Err(_) => Invitation::release(token, db).await?,
+        }
+    }
+    if let Ok(user) = merged {
+        if let Some(token) = invite {
+            WaitlistEntry::mark_registered(&token, db).await?;
+            request.get_session().remove(SESSION_INVITE_TOKEN);
+        }
+        if invitation.is_some() {
+            request.get_session().remove(SESSION_INVITATION_TOKEN);
+        }
+        request.get_session().remove(SESSION_PENDING_IDENTITY);
 
-    if let Ok(user) = Account::merge_identity_and_login(&form, refresh_token, account_id, db).await
-    {
         // last_login already updated, so just:
-        request.set_user(user)?;
+        let data = json!({ "provider": form.provider });
+        let event_id = AuditEvent::record_request(&request, user.id, "login.oauth", data).await?;
+        request.queue_job(AnalyzeLogin { event_id }).await?;
+        let timezone = Account::get(user.id, db).await?.profile.timezone.clone();
+        sessions::sign_in(&request, user).await?;
+        request.set_timezone(&timezone)?;
         return request.redirect("/dashboard");
     }
 
     // Create a ValidationErrors object
-    let errors: ValidationErrors<String> = ValidationError::new("email".to_owned(), "EMAIL_IS_OTHER_ACCOUNT")
-        .with_message(move |_| "address is assigned to another account".to_owned())
-        .into();
-    request.render(400, "oauth/confirm.html", {
-        let mut context = Context::new();
-
-        // ValidationErrors object is serialized into HashMap here
-        context.insert("errors", &errors);
-        context.insert("form", &form);
-        context
-    })
+    let errors: ValidationErrors<String> = match merged {
+        Err(Error::AccountInactive) => ValidationError::new("email".to_owned(), "ACCOUNT_INACTIVE")
+            .with_message(move |_| "this account has been deactivated".to_owned())
+            .into(),
+        _ => ValidationError::new("email".to_owned(), "EMAIL_IS_OTHER_ACCOUNT")
+            .with_message(move |_| "address is assigned to another account".to_owned())
+            .into(),
+    };
+    render_confirm(&request, 400, &form, registering, Some(errors))
 }
 
-fn validate_inputs(
-    session: &Session,
+/// Looks up (and consumes) the flow stored for the callback's `state`.
+/// Database errors are returned as the outer error; anything wrong with the
+/// flow itself is an `OAuthError`.
+async fn validate_inputs(
+    request: &HttpRequest,
     query: web::Query<AuthRequest>,
-) -> result::Result<ClientFlow, OAuthError> {
-    let maybe_flow = session.get::<OAuthFlow>(SESSION_OAUTH_FLOW);
-    session.remove(SESSION_OAUTH_FLOW);
-    session.remove(SESSION_OAUTH_TOKEN);
+) -> Result<result::Result<ClientFlow, OAuthError>> {
+    let state = match &query.state {
+        Some(state) => state,
+        None => return Ok(Err(OAuthError::ParseRequestError)),
+    };
 
-    match maybe_flow {
-        Ok(Some(flow)) => match &query.error {
-            Some(e) => Err(OAuthError::GrantAuthorizationError(e.to_string())),
-            _ => match (&query.state, &query.code) {
-                (Some(state), Some(auth_code)) => {
-                    if state.eq(&flow.csrf_token_secret) {
-                        match oauth::client::client_for(&flow.provider) {
-                            Some(client) => Ok(ClientFlow {
-                                client,
-                                flow: flow.set_authorization_code(auth_code),
-                            }),
-                            _ => Err(OAuthError::ParseSessionError),
-                        }
-                    } else {
-                        Err(OAuthError::VerifyStateError)
-                    }
-                }
-                _ => Err(OAuthError::ParseRequestError),
-            },
-        },
-        _ => Err(OAuthError::ParseSessionError),
-    }
+    // Taking the flow by state, for the browser that started it, is the CSRF
+    // check: unknown, expired, already-used or someone else's states simply
+    // aren't found.
+    let session = request.get_session();
+    let started = session.get::<String>(SESSION_OAUTH_STATE)?;
+    session.remove(SESSION_OAUTH_STATE);
+    let flow = OAuthFlowRecord::take(state, started.as_deref(), request.db_pool()?).await?;
+    Ok(oauth::verify_callback(
+        flow,
+        state,
+        query.code.as_deref(),
+        query.error.as_deref(),
+    ))
 }
 
-fn finalize_authentication(request: HttpRequest, user_info: UserInfo) -> Result<HttpResponse> {
-    let form = LinkIdentityForm {
-        provider: user_info.provider.to_string(),
-        username: user_info.username.unwrap_or(user_info.id),
-        name: TextField::new(user_info.name),
-        email: EmailField::new(user_info.login_email),
-    };
+/// Asks the user to confirm their details before signing in, suggesting
+/// a username from the provider's if they're signing up.
+async fn finalize_authentication(request: HttpRequest, user_info: UserInfo) -> Result<HttpResponse> {
+    let suggest_from = user_info.username.clone().unwrap_or_else(|| user_info.name.clone());
+    let mut form = LinkIdentityForm::from_user_info(user_info);
 
-    request.render(200, "oauth/confirm.html", {
-        let mut ctx = Context::new();
-        ctx.insert("form", &form);
-        ctx
-    })
+    request.get_session().insert(SESSION_PENDING_IDENTITY, form.pending_identity())?;
+
+    let account_id = existing_account_id(&request, &form).await?;
+    let registering = is_registering(&request, &form, account_id).await?;
+    if registering && usernames_enabled() {
+        if let Some(username) = Account::suggest_username(&suggest_from, request.db_pool()?).await? {
+            form.account_username = SlugField::new(username);
+        }
+    }
+
+    render_confirm(&request, 200, &form, registering, None)
 }
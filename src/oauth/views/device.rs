@@ -0,0 +1,239 @@
+//! The device flow (see `jelly::oauth::device`), for CLIs and TVs that
+//! can't show the provider's login page themselves. The client starts a
+//! flow, shows the user code (or sends the user to `display_uri`, which
+//! does), then polls for API tokens, as it would with the provider:
+//!
+//!   POST /oauth/device/start  {"provider": "github"}
+//!   POST /oauth/device/token  {"device_code": "..."}
+//!
+//! Polls answer with RFC 8628's errors (`authorization_pending`,
+//! `slow_down`, `access_denied`, `expired_token`) until the user's
+//! approved, then with the same tokens as `/accounts/token`. Only accounts
+//! the identity's already linked to can sign in this way; signing up takes
+//! the web.
+
+use std::env;
+
+use jelly::accounts::{jwt, AuthMode, User};
+use jelly::actix_web::http::header::USER_AGENT;
+use jelly::actix_web::web;
+use jelly::chrono::{Duration, Utc};
+use jelly::crypto;
+use jelly::oauth::device::{self, DevicePoll};
+use jelly::oauth::{self, UserInfo};
+use jelly::prelude::*;
+use jelly::rand::{thread_rng, Rng};
+use jelly::ratelimit::{self, Limit};
+use jelly::serde_json::json;
+use jelly::Result;
+use serde::Deserialize;
+
+use crate::accounts::models::Identity;
+use crate::accounts::Account;
+use crate::audit::jobs::AnalyzeLogin;
+use crate::audit::AuditEvent;
+use crate::oauth::models::{OAuthDeviceFlow, OAuthDeviceFlowRecord};
+use crate::oauth::provider_enabled;
+
+/// What the client's polling code is signed with, for storing.
+const DEVICE_CODE_SALT: &str = "oauth-device-code";
+
+#[derive(Debug, Deserialize)]
+pub struct StartRequest {
+    pub provider: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PollRequest {
+    pub device_code: String,
+}
+
+/// Each start asks the provider for a code, so they're limited by IP
+/// address: `OAUTH_DEVICE_STARTS_PER_IP` (10) per
+/// `OAUTH_DEVICE_START_WINDOW_MINUTES` (60).
+pub(crate) fn start_limit() -> Limit {
+    Limit::from_env("OAUTH_DEVICE_STARTS_PER_IP", 10, "OAUTH_DEVICE_START_WINDOW_MINUTES", 60)
+}
+
+fn start_limit_key(request: &HttpRequest) -> String {
+    format!("oauth-device:ip:{}", ratelimit::client_ip(request))
+}
+
+fn new_code() -> String {
+    let bytes: [u8; 24] = thread_rng().gen();
+    base64_url::encode(&bytes)
+}
+
+/// A poll's answer, other than tokens (RFC 8628 3.5).
+fn poll_error(request: &HttpRequest, error: &str) -> Result<HttpResponse> {
+    request.json(400, json!({ "error": error }))
+}
+
+/// POST-handler starting a device flow with `provider`, if it has one.
+pub async fn start(request: HttpRequest, form: web::Json<StartRequest>) -> Result<HttpResponse> {
+    if !AuthMode::of(&request).tokens() {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+
+    let db = request.db_pool()?;
+    let client = match provider_enabled(&form.provider, db).await? {
+        true => oauth::client::client_for(&form.provider).filter(device::supported),
+        false => None,
+    };
+    let client = match client {
+        Some(client) => client,
+        None => return request.json(400, json!({ "error": "unsupported_provider" })),
+    };
+
+    if !ratelimit::attempt(&start_limit_key(&request), &start_limit()).await? {
+        return request.json(429, json!({ "error": "Too many attempts; please try again later." }));
+    }
+
+    let authorization = match device::start(&client).await {
+        Ok(authorization) => authorization,
+        Err(e) => {
+            error!("Error starting {} device flow: {:?}", form.provider, e);
+            return request.json(502, json!({ "error": "provider_error" }));
+        }
+    };
+
+    let code = new_code();
+    let flow = OAuthDeviceFlow {
+        id: new_code(),
+        provider: form.provider.clone(),
+        device_code: authorization.device_code,
+        user_code: authorization.user_code,
+        verification_uri: authorization.verification_uri,
+        verification_uri_complete: authorization.verification_uri_complete,
+        interval_seconds: authorization.interval as i32,
+        last_polled: None,
+        expires: Utc::now() + Duration::seconds(authorization.expires_in as i64),
+    };
+    OAuthDeviceFlowRecord::insert(&flow, &crypto::sign(DEVICE_CODE_SALT, &code), db).await?;
+
+    let domain = env::var("JELLY_DOMAIN").expect("JELLY_DOMAIN not set!");
+    request.json(
+        200,
+        json!({
+            "device_code": code,
+            "user_code": flow.user_code,
+            "verification_uri": flow.verification_uri,
+            "verification_uri_complete": flow.verification_uri_complete,
+            "display_uri": format!("{}/oauth/device/{}", domain, flow.id),
+            "expires_in": authorization.expires_in,
+            "interval": flow.interval_seconds,
+        }),
+    )
+}
+
+/// POST-handler for the client's polls. Each one that's in time asks the
+/// provider once; ones that aren't are told to slow down, and have to
+/// wait longer from then on.
+pub async fn poll(request: HttpRequest, form: web::Json<PollRequest>) -> Result<HttpResponse> {
+    if !AuthMode::of(&request).tokens() {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+
+    let db = request.db_pool()?;
+    let code_hash = crypto::sign(DEVICE_CODE_SALT, &form.device_code);
+    let flow = match OAuthDeviceFlowRecord::by_code(&code_hash, db).await? {
+        Some(flow) => flow,
+        None => return poll_error(&request, "expired_token"),
+    };
+
+    let slower = flow.interval_seconds + device::SLOW_DOWN_SECONDS as i32;
+    if flow.polled_too_soon(Utc::now()) {
+        OAuthDeviceFlowRecord::polled(&flow.id, slower, db).await?;
+        return poll_error(&request, "slow_down");
+    }
+
+    // The provider could have been turned off since.
+    let client = match provider_enabled(&flow.provider, db).await? {
+        true => oauth::client::client_for(&flow.provider),
+        false => None,
+    };
+    let client = match client {
+        Some(client) => client,
+        None => {
+            OAuthDeviceFlowRecord::delete(&flow.id, db).await?;
+            return poll_error(&request, "expired_token");
+        }
+    };
+
+    match device::poll(&client, &flow.device_code).await? {
+        DevicePoll::Pending => {
+            OAuthDeviceFlowRecord::polled(&flow.id, flow.interval_seconds, db).await?;
+            poll_error(&request, "authorization_pending")
+        }
+        DevicePoll::SlowDown => {
+            OAuthDeviceFlowRecord::polled(&flow.id, slower, db).await?;
+            poll_error(&request, "slow_down")
+        }
+        DevicePoll::Denied => {
+            OAuthDeviceFlowRecord::delete(&flow.id, db).await?;
+            poll_error(&request, "access_denied")
+        }
+        DevicePoll::Expired => {
+            OAuthDeviceFlowRecord::delete(&flow.id, db).await?;
+            poll_error(&request, "expired_token")
+        }
+        DevicePoll::Granted(response) => {
+            // Two polls at once can both be granted; only one gets tokens.
+            if !OAuthDeviceFlowRecord::delete(&flow.id, db).await? {
+                return poll_error(&request, "expired_token");
+            }
+            let token_info = device::token_info(&client, &flow.provider, *response);
+            let user_info = oauth::fetch_user_info(&request.get_session(), token_info).await?;
+            grant_tokens(&request, &user_info).await
+        }
+    }
+}
+
+/// API tokens for the account `user_info`'s identity is linked to.
+async fn grant_tokens(request: &HttpRequest, user_info: &UserInfo) -> Result<HttpResponse> {
+    let db = request.db_pool()?;
+    let username = user_info.username.as_ref().unwrap_or(&user_info.id);
+    let identity = match Identity::get_by_provider_username(user_info.provider, username, db).await {
+        Ok(identity) => identity,
+        Err(_) => {
+            return request.json(
+                403,
+                json!({
+                    "error": "access_denied",
+                    "error_description": "No account is linked to this identity; sign in on the web first.",
+                }),
+            )
+        }
+    };
+
+    let account = match Account::get_active(identity.account_id, db).await? {
+        Some(account) => account,
+        None => {
+            return request.json(403, json!({ "error": "access_denied", "error_description": "Account deactivated." }))
+        }
+    };
+
+    Account::update_last_login(account.id, db).await?;
+    let data = json!({ "provider": user_info.provider, "grant": "device" });
+    let event_id = AuditEvent::record_request(request, account.id, "login.oauth", data).await?;
+    request.queue_job(AnalyzeLogin { event_id }).await?;
+
+    let user = User::load(db, account.id).await?;
+    let client_name = request.headers().get(USER_AGENT).and_then(|ua| ua.to_str().ok());
+    request.json(200, jwt::grant(db, &user, client_name).await?)
+}
+
+/// GET-handler for the page showing a flow's user code, and where to
+/// enter it, for clients that would rather send the user there.
+pub async fn show(request: HttpRequest, id: web::Path<String>) -> Result<HttpResponse> {
+    let flow = match OAuthDeviceFlowRecord::get(&id, request.db_pool()?).await? {
+        Some(flow) => flow,
+        None => return request.render(404, "404.html", Context::new()),
+    };
+
+    request.render(200, "oauth/device.html", {
+        let mut context = Context::new();
+        context.insert("flow", &flow);
+        context
+    })
+}
@@ -3,10 +3,12 @@ use jelly::error::OAuthError;
 use jelly::forms::validation::{Validatable};
 use jelly::oauth;
 use jelly::prelude::*;
-use jelly::Result;
-use jelly::SESSION_OAUTH_FLOW;
+use jelly::{Result, SESSION_OAUTH_STATE};
 
+use crate::accounts::Account;
 use crate::oauth::forms::OAuthLoginForm;
+use crate::oauth::models::OAuthFlowRecord;
+use crate::oauth::provider_enabled;
 
 /// The OAuth provider login form.
 /// Path contains the provider key ("google", "twitter", etc.)
@@ -16,9 +18,12 @@ pub async fn form(request: HttpRequest, path: web::Path<String>) -> Result<HttpR
     }
 
     let provider = path.into_inner();
+    if !provider_enabled(&provider, request.db_pool()?).await? {
+        request.flash("Login", "That login method isn't available.")?;
+        return request.redirect("/accounts/login");
+    }
     let form = OAuthLoginForm::new(&provider);
 
-    request.get_session().remove(SESSION_OAUTH_FLOW);
     request.render(200, "oauth/login.html", {
         let mut ctx = Context::new();
         ctx.insert("form", &form);
@@ -46,18 +51,56 @@ pub async fn authenticate(
         });
     }
 
-    request_authorization(request, &form.provider, &form.email)
+    request_authorization(request, &form.provider, &form.email, &[]).await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LinkQuery {
+    /// Scopes to ask for on top of the provider's usual ones, space
+    /// separated.
+    pub scope: Option<String>,
+}
+
+/// POST-handler for linking another provider to the signed in account,
+/// from the linked accounts page. The callback links the identity to the
+/// account rather than signing in with it. With `?scope=...`, it asks for
+/// more access than signing in takes, e.g to a calendar, for an identity
+/// that's already linked as well.
+pub async fn link(
+    request: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<LinkQuery>,
+) -> Result<HttpResponse> {
+    if !request.is_authenticated()? {
+        return request.redirect("/accounts/login");
+    }
+
+    let provider = path.into_inner();
+    let db = request.db_pool()?;
+    if !provider_enabled(&provider, db).await? {
+        request.flash("Linked Accounts", "That login method isn't available.")?;
+        return request.redirect("/dashboard/identities");
+    }
+
+    let account = Account::get(request.user()?.id, db).await?;
+    let extra_scopes: Vec<&str> = query.scope.as_deref().unwrap_or_default().split_whitespace().collect();
+    request_authorization(request, &provider, &account.email, &extra_scopes).await
 }
 
-fn request_authorization(
+async fn request_authorization(
     request: HttpRequest,
     provider: &str,
     email: &str,
+    extra_scopes: &[&str],
 ) -> Result<HttpResponse> {
+    if !provider_enabled(provider, request.db_pool()?).await? {
+        return Err(OAuthError::RegisterProviderError(provider.to_string()).into());
+    }
+
     match oauth::client::client_for(provider) {
         Some(client) => {
             let (authorization_request, pkce_code_verifier) =
-                oauth::pkce_authorization_request(&client, Some(email));
+                oauth::pkce_authorization_request(&client, Some(email), extra_scopes);
             let (authorize_url, csrf_token) = authorization_request.url();
             let flow = oauth::OAuthFlow {
                 provider: provider.to_string(),
@@ -65,9 +108,12 @@ fn request_authorization(
                 authorization_code: String::new(),
                 csrf_token_secret: csrf_token.secret().into(),
                 pkce_verifier_secret: pkce_code_verifier.secret().into(),
+                scopes: oauth::requested_scopes(&client, extra_scopes),
             };
 
-            request.get_session().insert(SESSION_OAUTH_FLOW, flow)?;
+            OAuthFlowRecord::insert(&flow, request.db_pool()?).await?;
+            // Only this browser can finish the flow; see `OAuthFlowRecord::take`.
+            request.get_session().insert(SESSION_OAUTH_STATE, &flow.csrf_token_secret)?;
             request.redirect(&authorize_url.to_string())
         }
         _ => Err(OAuthError::RegisterProviderError(provider.to_string()).into()),
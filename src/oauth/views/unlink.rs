@@ -0,0 +1,78 @@
+use jelly::actix_web::{web, HttpRequest};
+use jelly::prelude::*;
+use jelly::serde_json::json;
+use jelly::Result;
+
+use crate::accounts::models::{Identity, Unlink};
+use crate::accounts::{oauth_only, uses_password, Account};
+use crate::audit::AuditEvent;
+use crate::oauth;
+
+async fn render_form(
+    request: &HttpRequest,
+    status: usize,
+    identity: &Identity,
+    can_unlink: bool,
+) -> Result<HttpResponse> {
+    let oauth_only = oauth_only(request.db_pool()?).await?;
+    request.render(status, "oauth/unlink.html", {
+        let mut context = Context::new();
+        context.insert("provider", &identity.provider);
+        context.insert("username", &identity.username);
+        context.insert("can_unlink", &can_unlink);
+        context.insert("oauth_only", &oauth_only);
+        context
+    })
+}
+
+/// Asks the signed in account to confirm it wants to unlink its identity
+/// with `provider`, or says why it can't: it's the only way in.
+pub async fn form(request: HttpRequest, provider: web::Path<String>) -> Result<HttpResponse> {
+    if !request.is_authenticated()? {
+        return request.redirect("/accounts/login");
+    }
+
+    let db = request.db_pool()?;
+    let account = Account::get(request.user()?.id, db).await?;
+    let identities = Identity::linked_to_account_id(account.id, db).await?;
+    let identity = match identities.iter().find(|identity| identity.provider == *provider) {
+        Some(identity) => identity,
+        None => return request.render(404, "404.html", Context::new()),
+    };
+
+    let has_other_provider = identities.iter().any(|other| other.provider != *provider);
+    let can_unlink = has_other_provider || uses_password(&account, db).await?;
+    render_form(&request, 200, identity, can_unlink).await
+}
+
+/// Unlinks the identity, so it can't be used to sign in any more. The
+/// last way into an account without a password can't be unlinked.
+pub async fn unlink(request: HttpRequest, provider: web::Path<String>) -> Result<HttpResponse> {
+    if !request.is_authenticated()? {
+        return request.redirect("/accounts/login");
+    }
+
+    let db = request.db_pool()?;
+    let account_id = request.user()?.id;
+    let identities = Identity::linked_to_account_id(account_id, db).await?;
+    let identity = identities.iter().find(|identity| identity.provider == *provider);
+    match Identity::delete_for_account(&provider, account_id, db).await? {
+        Unlink::Removed => {
+            if let Some(identity) = identity {
+                oauth::revoke(identity, db).await?;
+            }
+            let data = json!({ "provider": provider.as_str() });
+            AuditEvent::record_request(&request, account_id, "identity.unlinked", data).await?;
+            request.flash(
+                "Identity Unlinked",
+                &format!("You can no longer sign in with {}.", provider),
+            )?;
+            request.redirect("/dashboard/identities")
+        }
+        Unlink::NotLinked => request.render(404, "404.html", Context::new()),
+        Unlink::LastLoginMethod => match identity {
+            Some(identity) => render_form(&request, 400, identity, false).await,
+            None => request.render(404, "404.html", Context::new()),
+        },
+    }
+}
@@ -1,11 +1,142 @@
-use jelly::actix_web::web::{resource, ServiceConfig};
+//! The homepage, and markdown pages (e.g blog posts) from `PAGES_DIR`,
+//! with an Atom feed of the published ones at `/feed.xml` and a sitemap at
+//! `/sitemap.xml`. The feed's title and author come from `FEED_TITLE` and
+//! `FEED_AUTHOR`.
+//!
+//! None of these need a signed in user, so `cargo run -- export-static`
+//! can pre-render them (see `static_paths`) for a CDN.
+//!
+//! Pages can be drafts (`status: draft`), or scheduled with a `published`
+//! date still to come. Admins see both, with a link to share for
+//! previewing them (good for `PREVIEW_DAYS`). `scheduler::PublishPages`
+//! notices when pages go live, and with `PAGES_EXPORT_DIR` set to where
+//! the export is served from, rewrites its feed and sitemap then too.
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+use jelly::actix_web::web::ServiceConfig;
+use jelly::anyhow::{self, anyhow};
+use jelly::chrono::{DateTime, Duration, TimeZone, Utc};
+use jelly::crypto;
 use jelly::prelude::*;
+use jelly::prerender;
+use jelly::routes;
 use jelly::Result;
+use sqlx::postgres::PgPool;
+
+use crate::accounts::oauth_only;
+
+pub mod models;
+pub mod posts;
+pub mod views;
+
+pub use models::PublishedPage;
+pub use posts::{Post, Status};
+
+/// Keeps preview tokens from being accepted anywhere else.
+const PREVIEW_SALT: &str = "com.jelly.pages.preview";
+
+/// How long a preview link works for.
+pub const PREVIEW_DAYS: i64 = 7;
+
+/// A token that shows `slug` to whoever has it until `expires`, whether
+/// it's published or not.
+pub fn preview_token(slug: &str, expires: DateTime<Utc>) -> String {
+    let expires = expires.timestamp().to_string();
+    let signature = crypto::sign(PREVIEW_SALT, &format!("{}:{}", slug, expires));
+    format!("{}.{}", expires, signature)
+}
+
+/// Whether `token` previews `slug`, and hasn't expired by `now`.
+pub fn preview_token_valid(slug: &str, token: &str, now: DateTime<Utc>) -> bool {
+    let (expires, signature) = match token.split_once('.') {
+        Some(parts) => parts,
+        None => return false,
+    };
+    match expires.parse::<i64>() {
+        Ok(timestamp) if Utc.timestamp_opt(timestamp, 0).single().map_or(false, |at| at > now) => {
+            crypto::verify(PREVIEW_SALT, &format!("{}:{}", slug, expires), signature)
+        }
+        _ => false,
+    }
+}
+
+/// A link previewing `slug` for the next `PREVIEW_DAYS`.
+pub fn preview_url(slug: &str) -> String {
+    let domain = env::var("JELLY_DOMAIN").expect("No JELLY_DOMAIN value set!");
+    let token = preview_token(slug, Utc::now() + Duration::days(PREVIEW_DAYS));
+    format!("{}/pages/{}?preview={}", domain, slug, token)
+}
+
+/// Where `publish` keeps the exported feed and sitemap up to date:
+/// `PAGES_EXPORT_DIR`, if it's set.
+pub fn export_dir() -> Option<PathBuf> {
+    env::var("PAGES_EXPORT_DIR").ok().filter(|dir| !dir.is_empty()).map(PathBuf::from)
+}
+
+/// Notes pages that have gone live, or been taken down, since the last
+/// look, and if any have, rewrites the feed and sitemap in `dir`. Returns
+/// the newly published pages' slugs.
+pub async fn publish(dir: Option<&Path>, pool: &PgPool) -> anyhow::Result<Vec<String>> {
+    let posts = Post::published()?;
+
+    let mut published = Vec::new();
+    for post in posts.iter() {
+        let published_at = post.published.unwrap_or_else(Utc::now);
+        if PublishedPage::record(&post.slug, published_at, pool)
+            .await
+            .map_err(|e| anyhow!("Error recording published page: {:?}", e))?
+        {
+            published.push(post.slug.clone());
+        }
+    }
+    let slugs: Vec<String> = posts.iter().map(|post| post.slug.clone()).collect();
+    let taken_down = PublishedPage::retain(&slugs, pool)
+        .await
+        .map_err(|e| anyhow!("Error forgetting unpublished pages: {:?}", e))?;
+
+    if published.is_empty() && taken_down == 0 {
+        return Ok(published);
+    }
+    for slug in published.iter() {
+        info!("Published page {}", slug);
+    }
+    if let Some(dir) = dir {
+        // The pages themselves fall through to the app until the next
+        // full export.
+        let (feed, _) = views::feed_document(&posts);
+        prerender::write(dir, "/feed.xml", feed.as_bytes())?;
+        prerender::write(dir, "/sitemap.xml", views::sitemap_document(&posts).as_bytes())?;
+    }
+    Ok(published)
+}
 
 pub async fn homepage(request: HttpRequest) -> Result<HttpResponse> {
-    request.render(200, "index.html", Context::new())
+    let oauth_only = oauth_only(request.db_pool()?).await?;
+    request.render(200, "index.html", {
+        let mut ctx = Context::new();
+        ctx.insert("oauth_only", &oauth_only);
+        ctx
+    })
+}
+
+/// The pages exported by `export-static`: everything here, as anonymous
+/// visitors see it. Drafts are left out, as only admins and preview links
+/// show them.
+pub fn static_paths() -> Result<Vec<String>> {
+    let mut paths: Vec<String> = ["/", "/feed.xml", "/sitemap.xml", "/pages"]
+        .iter()
+        .map(|path| path.to_string())
+        .collect();
+    paths.extend(Post::published()?.iter().map(|post| format!("/pages/{}", post.slug)));
+    Ok(paths)
 }
 
 pub fn configure(config: &mut ServiceConfig) {
-    config.service(resource("/").to(homepage));
+    routes::resource("/").to(homepage).mount(config);
+    routes::resource("/feed.xml").to(views::feed).mount(config);
+    routes::resource("/sitemap.xml").to(views::sitemap).mount(config);
+    routes::resource("/pages").to(views::index).mount(config);
+    routes::resource("/pages/{slug}").to(views::page).mount(config);
 }
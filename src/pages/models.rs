@@ -0,0 +1,35 @@
+use jelly::chrono::{DateTime, Utc};
+use jelly::error::Error;
+use sqlx::postgres::PgPool;
+
+/// A page that's been seen live, so going live (or being taken down) is
+/// only acted on once.
+pub struct PublishedPage;
+
+impl PublishedPage {
+    /// Records that `slug` is live, returning whether it's newly so.
+    pub async fn record(slug: &str, published_at: DateTime<Utc>, pool: &PgPool) -> Result<bool, Error> {
+        Ok(sqlx::query!(
+            "
+            INSERT INTO published_pages (slug, published_at)
+            VALUES ($1, $2)
+            ON CONFLICT (slug) DO NOTHING
+        ",
+            slug,
+            published_at
+        )
+        .execute(pool)
+        .await?
+        .rows_affected()
+            == 1)
+    }
+
+    /// Forgets pages other than `slugs`, which have been taken down,
+    /// returning how many there were.
+    pub async fn retain(slugs: &[String], pool: &PgPool) -> Result<u64, Error> {
+        Ok(sqlx::query!("DELETE FROM published_pages WHERE slug <> ALL($1)", slugs)
+            .execute(pool)
+            .await?
+            .rows_affected())
+    }
+}
@@ -0,0 +1,172 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use jelly::anyhow::{anyhow, Result};
+use jelly::chrono::{DateTime, Utc};
+use jelly::serde::Serialize;
+use pulldown_cmark::{html, Options, Parser};
+
+/// Where markdown pages live: `PAGES_DIR`, or `pages`.
+pub fn pages_dir() -> PathBuf {
+    env::var("PAGES_DIR").unwrap_or_else(|_| "pages".to_string()).into()
+}
+
+/// A markdown page. Files start with a front matter block:
+///
+/// ```text
+/// ---
+/// title: Hello, world
+/// status: published
+/// published: 2022-04-15T09:00:00Z
+/// updated: 2022-04-16T09:00:00Z
+/// author: Jane
+/// summary: Our first post.
+/// ---
+/// ```
+///
+/// Only `title` is required. Pages without a `published` date, or with
+/// `status: draft`, are drafts: they're not listed, and not in the feed. A
+/// `published` date in the future (`publish_at` works too) schedules the
+/// page to go live then; see `scheduler::PublishPages`.
+#[derive(Debug, Serialize)]
+pub struct Post {
+    pub slug: String,
+    pub title: String,
+    pub status: Status,
+    pub published: Option<DateTime<Utc>>,
+    pub updated: Option<DateTime<Utc>>,
+    pub author: Option<String>,
+    pub summary: Option<String>,
+    /// The body, rendered to HTML.
+    pub html: String,
+}
+
+impl Post {
+    pub fn parse(slug: &str, source: &str) -> Result<Self> {
+        let rest = source
+            .strip_prefix("---")
+            .ok_or_else(|| anyhow!("{}: missing front matter", slug))?;
+        let (front, body) = rest
+            .split_once("\n---")
+            .ok_or_else(|| anyhow!("{}: unterminated front matter", slug))?;
+
+        let mut post = Post {
+            slug: slug.to_string(),
+            title: String::new(),
+            status: Status::Published,
+            published: None,
+            updated: None,
+            author: None,
+            summary: None,
+            html: String::new(),
+        };
+
+        for line in front.lines().filter(|line| !line.trim().is_empty()) {
+            let (key, value) = line
+                .split_once(':')
+                .ok_or_else(|| anyhow!("{}: bad front matter line {:?}", slug, line))?;
+            let value = value.trim().to_string();
+            match key.trim() {
+                "title" => post.title = value,
+                "status" => {
+                    post.status = Status::parse(&value).ok_or_else(|| anyhow!("{}: bad status {:?}", slug, value))?
+                }
+                "published" | "publish_at" => post.published = Some(value.parse()?),
+                "updated" => post.updated = Some(value.parse()?),
+                "author" => post.author = Some(value),
+                "summary" => post.summary = Some(value),
+                _ => {}
+            }
+        }
+        if post.title.is_empty() {
+            return Err(anyhow!("{}: no title", slug));
+        }
+
+        let body = body.split_once('\n').map_or("", |(_, body)| body);
+        html::push_html(&mut post.html, Parser::new_ext(body, Options::all()));
+        Ok(post)
+    }
+
+    pub fn is_published(&self) -> bool {
+        self.status == Status::Published && self.published.map_or(false, |published| published <= Utc::now())
+    }
+
+    /// Whether the page is set to go live later.
+    pub fn is_scheduled(&self) -> bool {
+        self.status == Status::Published && self.published.map_or(false, |published| published > Utc::now())
+    }
+
+    /// When the page last changed: `updated`, or else `published`.
+    pub fn last_modified(&self) -> Option<DateTime<Utc>> {
+        self.updated.or(self.published)
+    }
+
+    /// The page for `slug`, published or not.
+    pub fn load(slug: &str) -> Result<Option<Self>> {
+        // Slugs come from URLs, so keep them to plain file names.
+        if slug.is_empty() || !slug.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Ok(None);
+        }
+
+        let path = pages_dir().join(format!("{}.md", slug));
+        match fs::read_to_string(path) {
+            Ok(source) => Ok(Some(Post::parse(slug, &source)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Published pages, newest first. Read fresh each time, so new pages
+    /// show up (and scheduled ones go live) without a restart.
+    pub fn published() -> Result<Vec<Self>> {
+        let mut posts: Vec<Self> = Post::all()?.into_iter().filter(Post::is_published).collect();
+        posts.sort_by(|a, b| b.published.cmp(&a.published));
+        Ok(posts)
+    }
+
+    /// Every page that parses, drafts and all, in no particular order.
+    pub fn all() -> Result<Vec<Self>> {
+        let mut posts = Vec::new();
+        let entries = match fs::read_dir(pages_dir()) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(posts),
+            Err(e) => return Err(e.into()),
+        };
+
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().map_or(true, |ext| ext != "md") {
+                continue;
+            }
+            let slug = match path.file_stem().and_then(|stem| stem.to_str()) {
+                Some(slug) => slug.to_string(),
+                None => continue,
+            };
+
+            match Post::parse(&slug, &fs::read_to_string(&path)?) {
+                Ok(post) => posts.push(post),
+                Err(e) => warn!("Skipping page: {:?}", e),
+            }
+        }
+        Ok(posts)
+    }
+}
+
+/// Whether a page is ready to go out, once its `published` date comes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Draft,
+    Published,
+}
+
+impl Status {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "draft" => Some(Status::Draft),
+            "published" => Some(Status::Published),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::hash::{Hash, Hasher};
+use std::time::SystemTime;
+
+use jelly::actix_web::http::header::{
+    EntityTag, ETag, HeaderName, HeaderValue, HttpDate, LastModified, CACHE_CONTROL,
+};
+use jelly::actix_web::web::{Path, Query};
+use jelly::chrono::{DateTime, TimeZone, Utc};
+use jelly::feed::{self, Entry, Feed};
+use jelly::prelude::*;
+use jelly::seo::Seo;
+use jelly::sitemap::{self, Sitemap};
+use jelly::Result;
+use serde::Deserialize;
+
+use super::{preview_token_valid, preview_url, Post, PREVIEW_DAYS};
+
+/// How many pages the feed carries.
+const FEED_LEN: usize = 20;
+
+/// Published pages, newest first.
+pub async fn index(request: HttpRequest) -> Result<HttpResponse> {
+    let posts = Post::published()?;
+
+    request.render(200, "pages/index.html", {
+        let mut ctx = Context::new();
+        ctx.insert("posts", &posts);
+        ctx
+    })
+}
+
+#[derive(Deserialize)]
+pub struct PageQuery {
+    pub preview: Option<String>,
+}
+
+/// A page. Drafts and scheduled pages are shown to admins, who get a link
+/// to share for previewing them, and to whoever has that link; they're
+/// kept out of search engines and shared caches.
+pub async fn page(request: HttpRequest, slug: Path<String>, query: Query<PageQuery>) -> Result<HttpResponse> {
+    let is_admin = request.user()?.is_admin;
+    let previewing = |post: &Post| {
+        query
+            .preview
+            .as_deref()
+            .map_or(false, |token| preview_token_valid(&post.slug, token, Utc::now()))
+    };
+    let post = match Post::load(&slug)? {
+        Some(post) if post.is_published() || is_admin || previewing(&post) => post,
+        _ => return request.render(404, "404.html", Context::new()),
+    };
+
+    let published = post.is_published();
+    let mut response = request.render(200, "pages/page.html", {
+        let mut ctx = Context::new();
+        Seo::new(&post.title)
+            .description(post.summary.clone().unwrap_or_default())
+            .url(format!("/pages/{}", post.slug))
+            .kind("article")
+            .insert(&mut ctx);
+        ctx.insert("post", &post);
+        ctx.insert("published", &published);
+        ctx.insert("scheduled", &post.is_scheduled());
+        if is_admin && !published {
+            ctx.insert("preview_url", &preview_url(&post.slug));
+            ctx.insert("preview_days", &PREVIEW_DAYS);
+        }
+        ctx
+    })?;
+
+    if !published {
+        let headers = response.headers_mut();
+        headers.insert(HeaderName::from_static("x-robots-tag"), HeaderValue::from_static("noindex"));
+        headers.insert(CACHE_CONTROL, HeaderValue::from_static("private, no-store"));
+    }
+    Ok(response)
+}
+
+/// The Atom feed of the latest of `posts` (published, newest first), and
+/// when it last changed.
+pub fn feed_document(posts: &[Post]) -> (String, DateTime<Utc>) {
+    let domain = env::var("JELLY_DOMAIN").expect("No JELLY_DOMAIN value set!");
+    let title = env::var("FEED_TITLE").unwrap_or_else(|_| domain.clone());
+    let updated = posts
+        .iter()
+        .filter_map(Post::last_modified)
+        .max()
+        .unwrap_or_else(|| Utc.timestamp(0, 0));
+
+    let mut feed = Feed::new(format!("{}/feed.xml", domain), title, updated).link(&domain);
+    if let Ok(author) = env::var("FEED_AUTHOR") {
+        feed = feed.author(author);
+    }
+    for post in posts.iter().take(FEED_LEN) {
+        let mut entry = Entry::new(
+            format!("{}/pages/{}", domain, post.slug),
+            &post.title,
+            post.published.unwrap_or(updated),
+        )
+        .content(&post.html);
+        if let Some(last_modified) = post.last_modified() {
+            entry = entry.updated(last_modified);
+        }
+        if let Some(author) = &post.author {
+            entry = entry.author(author);
+        }
+        if let Some(summary) = &post.summary {
+            entry = entry.summary(summary);
+        }
+        feed.push(entry);
+    }
+    (feed.to_string(), updated)
+}
+
+/// An Atom feed of the latest published pages. Tagged with an `ETag` and
+/// `Last-Modified`, so feed readers polling it mostly get a 304.
+pub async fn feed(request: HttpRequest) -> Result<HttpResponse> {
+    let (body, updated) = feed_document(&Post::published()?);
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+    let tag = EntityTag::new_strong(format!("{:x}", hasher.finish()));
+    let last_modified = SystemTime::from(updated);
+
+    let fresh = request.is_fresh(&tag, last_modified);
+    let mut response = if fresh {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    response
+        .insert_header(ETag(tag))
+        .insert_header(LastModified(HttpDate::from(last_modified)));
+
+    if fresh {
+        return Ok(response.finish());
+    }
+    Ok(response.content_type(feed::CONTENT_TYPE).body(body))
+}
+
+/// The sitemap of the homepage and `posts` (published ones).
+pub fn sitemap_document(posts: &[Post]) -> String {
+    let domain = env::var("JELLY_DOMAIN").expect("No JELLY_DOMAIN value set!");
+
+    let mut sitemap = Sitemap::new();
+    sitemap.push(format!("{}/", domain), None);
+    sitemap.push(format!("{}/pages", domain), posts.iter().filter_map(Post::last_modified).max());
+    for post in posts.iter() {
+        sitemap.push(format!("{}/pages/{}", domain, post.slug), post.last_modified());
+    }
+
+    sitemap.to_string()
+}
+
+/// The homepage and published pages, for search engines.
+pub async fn sitemap(_request: HttpRequest) -> Result<HttpResponse> {
+    let body = sitemap_document(&Post::published()?);
+    Ok(HttpResponse::Ok().content_type(sitemap::CONTENT_TYPE).body(body))
+}
@@ -0,0 +1,10 @@
+//! Public profile pages, for accounts with a username.
+
+use jelly::actix_web::web::ServiceConfig;
+use jelly::routes;
+
+pub mod views;
+
+pub fn configure(config: &mut ServiceConfig) {
+    routes::resource("/u/{username}/").get(views::profile).mount(config);
+}
@@ -0,0 +1,28 @@
+use jelly::actix_web::{web, HttpRequest};
+use jelly::prelude::*;
+use jelly::seo::Seo;
+use jelly::Result;
+
+use crate::accounts::models::PublicProfile;
+use crate::accounts::Account;
+
+/// Renders the public profile for `username`. Accounts that don't exist
+/// and accounts that haven't opted in look the same: a 404.
+pub async fn profile(request: HttpRequest, path: web::Path<String>) -> Result<HttpResponse> {
+    let username = path.into_inner();
+    let account = Account::get_by_username(&username, request.db_pool()?).await.ok();
+
+    match account.as_ref().and_then(PublicProfile::for_account) {
+        Some(profile) => request.render(200, "profiles/profile.html", {
+            let mut ctx = Context::new();
+            Seo::new(&profile.name)
+                .description(&profile.bio)
+                .url(format!("/u/{}/", profile.username))
+                .kind("profile")
+                .insert(&mut ctx);
+            ctx.insert("profile", &profile);
+            ctx
+        }),
+        None => jelly::utils::not_found(request).await,
+    }
+}
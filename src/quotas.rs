@@ -0,0 +1,5 @@
+//! Per-account usage tracking and quotas.
+
+pub mod models;
+
+pub use models::{Metric, Usage};
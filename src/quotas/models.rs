@@ -0,0 +1,143 @@
+use std::env;
+
+use jelly::chrono::{Datelike, NaiveDate, Utc};
+use jelly::error::Error;
+use jelly::serde::Serialize;
+use jelly::tenancy::TenantPool;
+
+/// Something we meter. Limits come from `QUOTA_<METRIC>` (e.g
+/// `QUOTA_API_REQUESTS`); unset means unlimited.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Metric {
+    /// API calls this month.
+    ApiRequests,
+
+    /// Emails sent on the account's behalf this month.
+    EmailsSent,
+
+    /// Bytes currently stored; consume a negative amount when freeing.
+    StorageBytes,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 3] = [Metric::ApiRequests, Metric::EmailsSent, Metric::StorageBytes];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Metric::ApiRequests => "api_requests",
+            Metric::EmailsSent => "emails_sent",
+            Metric::StorageBytes => "storage_bytes",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Metric::ApiRequests => "API requests this month",
+            Metric::EmailsSent => "Emails sent this month",
+            Metric::StorageBytes => "Storage used (bytes)",
+        }
+    }
+
+    pub fn limit(self) -> Option<i64> {
+        env::var(format!("QUOTA_{}", self.as_str().to_uppercase()))
+            .ok()
+            .and_then(|limit| limit.parse().ok())
+    }
+
+    /// The counter row currently being added to.
+    fn period(self) -> NaiveDate {
+        match self {
+            Metric::StorageBytes => NaiveDate::from_ymd(1970, 1, 1),
+            _ => {
+                let today = Utc::today();
+                NaiveDate::from_ymd(today.year(), today.month(), 1)
+            }
+        }
+    }
+}
+
+/// Where an account stands against one of its quotas.
+#[derive(Debug, Serialize)]
+pub struct Usage {
+    pub metric: &'static str,
+    pub description: &'static str,
+    pub used: i64,
+    pub limit: Option<i64>,
+}
+
+impl Usage {
+    /// Adds `amount` to the tenant's usage, unless that would take it over
+    /// its limit. Returns whether it fit.
+    pub async fn consume(db: &TenantPool<'_>, metric: Metric, amount: i64) -> Result<bool, Error> {
+        let consumed = jelly::tenant_query!(
+            db,
+            "
+            INSERT INTO usage_counters (account_id, metric, period, amount)
+            SELECT $1::int, $2::text, $3::date, $4::bigint
+            WHERE $5::bigint IS NULL OR $4 <= $5
+            ON CONFLICT (account_id, metric, period) DO UPDATE
+            SET amount = usage_counters.amount + excluded.amount
+            WHERE $5::bigint IS NULL OR usage_counters.amount + excluded.amount <= $5
+            RETURNING amount
+        ",
+            metric.as_str(),
+            metric.period(),
+            amount,
+            metric.limit()
+        )
+        .fetch_optional(db.pool())
+        .await?;
+
+        Ok(consumed.is_some())
+    }
+
+    /// Adds `amount` to the tenant's usage regardless of its limit, for
+    /// things that must happen anyway (e.g security emails).
+    pub async fn record(db: &TenantPool<'_>, metric: Metric, amount: i64) -> Result<(), Error> {
+        jelly::tenant_query!(
+            db,
+            "
+            INSERT INTO usage_counters (account_id, metric, period, amount)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (account_id, metric, period) DO UPDATE
+            SET amount = usage_counters.amount + excluded.amount
+        ",
+            metric.as_str(),
+            metric.period(),
+            amount
+        )
+        .execute(db.pool())
+        .await?;
+
+        Ok(())
+    }
+
+    /// The tenant's current usage of everything we meter.
+    pub async fn for_account(db: &TenantPool<'_>) -> Result<Vec<Self>, Error> {
+        let mut usage = Vec::with_capacity(Metric::ALL.len());
+        for metric in Metric::ALL {
+            let used = jelly::tenant_query!(
+                db,
+                "
+                SELECT amount FROM usage_counters
+                WHERE account_id = $1 AND metric = $2 AND period = $3
+            ",
+                metric.as_str(),
+                metric.period()
+            )
+            .fetch_optional(db.pool())
+            .await?
+            .map(|row| row.amount)
+            .unwrap_or(0);
+
+            usage.push(Usage {
+                metric: metric.as_str(),
+                description: metric.description(),
+                used,
+                limit: metric.limit(),
+            });
+        }
+
+        Ok(usage)
+    }
+}
@@ -0,0 +1,11 @@
+//! Referral codes. Each account gets a code to share; signing up through
+//! `/accounts/register?ref=<code>` attributes the new account to its owner,
+//! who is credited once the new account verifies its email.
+
+pub mod jobs;
+pub mod models;
+
+pub use models::{ReferralCode, ReferralStats};
+
+/// Session key holding the code a visitor arrived with, until they register.
+pub const SESSION_REFERRAL_CODE: &str = "referral_code";
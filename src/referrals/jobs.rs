@@ -0,0 +1,53 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use jelly::anyhow::{anyhow, Error};
+use jelly::jobs::{Job, JobConfig, JobState, Traced, DEFAULT_QUEUE};
+use jelly::serde::{Deserialize, Serialize};
+use jelly::serde_json::json;
+use sqlx::postgres::PgPool;
+
+use super::ReferralCode;
+use crate::audit::AuditEvent;
+
+/// Credits whoever referred `account_id`, dispatched once it has verified
+/// its email. Runs at most once per referred account.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreditReferrer {
+    pub account_id: i32,
+}
+
+/// Where a referrer gets rewarded for `referred_id`, e.g by extending their
+/// plan or adding billing credit. By default it's only recorded in the
+/// referrer's audit log.
+pub async fn grant_credit(referrer_id: i32, referred_id: i32, pool: &PgPool) -> Result<(), Error> {
+    let data = json!({ "referred_account_id": referred_id });
+    AuditEvent::record(Some(referrer_id), "referral.credited", data, pool)
+        .await
+        .map_err(|e| anyhow!("Error recording referral credit: {:?}", e))
+}
+
+impl Job for CreditReferrer {
+    type State = JobState;
+    type Future = Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
+
+    const NAME: &'static str = "CreditReferrerJob";
+    const QUEUE: &'static str = DEFAULT_QUEUE;
+
+    fn run(self, state: JobState) -> Self::Future {
+        Box::pin(async move {
+            let referrer_id = ReferralCode::claim_credit(self.account_id, &state.pool)
+                .await
+                .map_err(|e| anyhow!("Error claiming referral credit: {:?}", e))?;
+
+            match referrer_id {
+                Some(referrer_id) => grant_credit(referrer_id, self.account_id, &state.pool).await,
+                None => Ok(()),
+            }
+        })
+    }
+}
+
+pub fn configure(config: JobConfig) -> JobConfig {
+    config.register::<Traced<CreditReferrer>>()
+}
@@ -0,0 +1,143 @@
+use jelly::error::Error;
+use jelly::rand::distributions::Alphanumeric;
+use jelly::rand::{thread_rng, Rng};
+use jelly::serde::Serialize;
+use jelly::tenancy::TenantPool;
+use sqlx::postgres::PgPool;
+
+/// Length of generated codes.
+const CODE_LEN: usize = 8;
+
+/// An account's referral code.
+#[derive(Debug, Serialize)]
+pub struct ReferralCode {
+    pub account_id: i32,
+    pub code: String,
+}
+
+/// How an account's referrals have gone.
+#[derive(Debug, Serialize)]
+pub struct ReferralStats {
+    pub signups: i64,
+    pub verified: i64,
+}
+
+impl ReferralCode {
+    /// The tenant's code, creating one the first time it's asked for.
+    pub async fn for_account(db: &TenantPool<'_>) -> Result<Self, Error> {
+        loop {
+            let code: String = thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(CODE_LEN)
+                .map(char::from)
+                .collect::<String>()
+                .to_uppercase();
+
+            // A clash with someone else's code inserts nothing and falls
+            // through to the select, so go round again with a new one.
+            let row = jelly::tenant_query!(
+                db,
+                "
+                WITH created AS (
+                    INSERT INTO referral_codes (account_id, code)
+                    VALUES ($1, $2)
+                    ON CONFLICT DO NOTHING
+                    RETURNING code
+                )
+                SELECT code as \"code!\" FROM created
+                UNION ALL
+                SELECT code FROM referral_codes WHERE account_id = $1
+            ",
+                code
+            )
+            .fetch_optional(db.pool())
+            .await?;
+
+            if let Some(row) = row {
+                return Ok(ReferralCode {
+                    account_id: db.tenant().get(),
+                    code: row.code,
+                });
+            }
+        }
+    }
+
+    /// The account that owns `code`, if any. Codes are matched regardless
+    /// of case.
+    pub async fn account_for_code(code: &str, pool: &PgPool) -> Result<Option<i32>, Error> {
+        Ok(sqlx::query!(
+            "
+            SELECT account_id FROM referral_codes WHERE code = upper($1)
+        ",
+            code
+        )
+        .fetch_optional(pool)
+        .await?
+        .map(|row| row.account_id))
+    }
+
+    /// Attributes the new account `account_id` to the owner of `code`. Does
+    /// nothing if the code doesn't exist or is the account's own.
+    pub async fn attribute(account_id: i32, code: &str, pool: &PgPool) -> Result<bool, Error> {
+        let result = sqlx::query!(
+            "
+            UPDATE accounts
+            SET referred_by = r.account_id
+            FROM referral_codes r
+            WHERE accounts.id = $1
+                AND accounts.referred_by IS NULL
+                AND r.code = upper($2)
+                AND r.account_id <> $1
+        ",
+            account_id,
+            code
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Marks `account_id` as having earned its referrer a credit, returning
+    /// the referrer. Returns `None` if it wasn't referred, or was already
+    /// counted.
+    pub async fn claim_credit(account_id: i32, pool: &PgPool) -> Result<Option<i32>, Error> {
+        Ok(sqlx::query!(
+            "
+            UPDATE accounts
+            SET referral_credited = now()
+            WHERE id = $1
+                AND referred_by IS NOT NULL
+                AND referral_credited IS NULL
+            RETURNING referred_by as \"referred_by!\"
+        ",
+            account_id
+        )
+        .fetch_optional(pool)
+        .await?
+        .map(|row| row.referred_by))
+    }
+}
+
+impl ReferralStats {
+    pub async fn for_account(db: &TenantPool<'_>) -> Result<Self, Error> {
+        let db = db.with_column("referred_by");
+        let row = jelly::tenant_query!(
+            db,
+            "
+            SELECT
+                count(*) as \"signups!\",
+                count(*) FILTER (WHERE has_verified_email) as \"verified!\"
+            FROM accounts
+            WHERE referred_by = $1
+        "
+        )
+        .fetch_one(db.pool())
+        .await?;
+
+        Ok(ReferralStats {
+            signups: row.signups,
+            verified: row.verified,
+        })
+    }
+}
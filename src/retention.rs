@@ -0,0 +1,106 @@
+//! How long data is kept (see `jelly::retention`), applied nightly by
+//! `ApplyRetention`. Each policy's days can be changed in the
+//! `retention.policies` setting; `cargo run -- retention report` shows
+//! what the next run would remove, as does the job itself while the
+//! `retention.dry_run` setting's on.
+
+use std::future::Future;
+use std::pin::Pin;
+
+use jelly::anyhow::{anyhow, Error};
+use jelly::futures::future::BoxFuture;
+use jelly::jobs::cron::Cron;
+use jelly::jobs::{Job, JobState, DEFAULT_QUEUE};
+use jelly::retention::{self, Outcome, Policy};
+use jelly::serde::{Deserialize, Serialize};
+use jelly::serde_json::json;
+use sqlx::postgres::PgPool;
+
+use crate::accounts::{deletion_grace_days, Account};
+use crate::audit::AuditEvent;
+
+/// Nightly, at 3am UTC.
+pub const NIGHTLY: &str = "0 0 3 * * * *";
+
+pub const AUDIT_EVENTS: &str = "audit_events";
+pub const SESSIONS: &str = "sessions";
+pub const DELETED_ACCOUNTS: &str = "deleted_accounts";
+pub const NOTIFICATIONS: &str = "notifications";
+
+/// The policies, with their days before any overrides. Deleted accounts
+/// default to `DELETED_ACCOUNT_RETENTION_DAYS`.
+pub fn policies() -> Vec<Policy> {
+    vec![
+        Policy::new(AUDIT_EVENTS, "audit_events", "created", 365),
+        // Sessions still in use keep moving `last_seen` along.
+        Policy::new(SESSIONS, "user_sessions", "last_seen", 30),
+        Policy::new(NOTIFICATIONS, "notifications", "created", 90),
+        Policy::new(DELETED_ACCOUNTS, "accounts", "deleted_at", deletion_grace_days())
+            .filter("deleted_at IS NOT NULL")
+            .purge(purge_deleted_accounts),
+    ]
+}
+
+/// The days `name`'s policy keeps rows for, overrides and all.
+pub async fn days(pool: &PgPool, name: &str) -> Result<i32, jelly::error::Error> {
+    Ok(retention::resolve(pool, &policies())
+        .await?
+        .iter()
+        .find(|policy| policy.name == name)
+        .map_or(0, |policy| policy.days))
+}
+
+/// What the policies would remove now, without removing it.
+pub async fn report(pool: &PgPool) -> Result<Vec<Outcome>, jelly::error::Error> {
+    let policies = retention::resolve(pool, &policies()).await?;
+    retention::apply(pool, &policies, true).await
+}
+
+/// Accounts go with their identities, and each leaves an audit event.
+fn purge_deleted_accounts(pool: &PgPool, days: i32) -> BoxFuture<'_, Result<u64, jelly::error::Error>> {
+    Box::pin(async move {
+        let purged = Account::purge_deleted(days, pool).await?;
+        for (id, email) in purged.iter() {
+            let data = json!({ "account_id": id, "email": email, "reason": "deleted" });
+            if let Err(e) = AuditEvent::record(None, "account.purged", data, pool).await {
+                error!("Error recording account.purged for account {}: {:?}", id, e);
+            }
+        }
+        Ok(purged.len() as u64)
+    })
+}
+
+/// Applies the retention policies, or reports on them in a dry run.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ApplyRetention;
+
+impl Job for ApplyRetention {
+    type State = JobState;
+    type Future = Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
+
+    const NAME: &'static str = "ApplyRetentionJob";
+    const QUEUE: &'static str = DEFAULT_QUEUE;
+
+    fn run(self, state: JobState) -> Self::Future {
+        Box::pin(async move {
+            let pool = &state.pool;
+            let policies = retention::resolve(pool, &policies())
+                .await
+                .map_err(|e| anyhow!("Error reading retention policies: {:?}", e))?;
+            let dry_run = retention::dry_run(pool)
+                .await
+                .map_err(|e| anyhow!("Error reading retention dry run setting: {:?}", e))?;
+            let outcomes = retention::apply(pool, &policies, dry_run)
+                .await
+                .map_err(|e| anyhow!("Error applying retention policies: {:?}", e))?;
+            for outcome in outcomes.iter().filter(|outcome| outcome.rows > 0 || outcome.dry_run) {
+                info!("Retention {}", outcome);
+            }
+            Ok(())
+        })
+    }
+}
+
+impl Cron for ApplyRetention {
+    const SCHEDULE: &'static str = NIGHTLY;
+}
@@ -1,73 +1,368 @@
-use actix::prelude::*;
-use std::str::FromStr;
-use std::time::Duration;
-use chrono::Local;
-use cron::Schedule;
+//! Periodic housekeeping, as jobs run on a schedule (see
+//! `jelly::jobs::cron`). Errors fail the job, so the queue retries it.
+
+use std::env::var;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+
+use jelly::actix_rt::task::spawn_blocking;
+use jelly::anyhow::{anyhow, Error};
+use jelly::jobs::cron::Cron;
+use jelly::jobs::{Job, JobState, DEFAULT_QUEUE};
+use jelly::serde::{Deserialize, Serialize};
+use jelly::serde_json::json;
+use jelly::tenancy::{TenantId, TenantPool};
+use jelly::tera::Tera;
 use sqlx::postgres::PgPool;
+
+use crate::accounts::jobs::build_reminder_email;
+use crate::accounts::models::Digest;
 use crate::accounts::Account;
+use crate::api::jobs::build_expiry_email;
+use crate::api::models::ApiToken;
+use crate::audit::AuditEvent;
+use crate::metering::{stripe, DailyUsage};
+use crate::notifications::{build_digest_email, Notification};
+use crate::pages;
+use crate::oauth::models::{OAuthDeviceFlowRecord, OAuthFlowRecord};
+use crate::quotas::{Metric, Usage};
 
 pub const EVERY_MINUTE: &str = "0 * * * * * *";
+pub const EVERY_HOUR: &str = "0 0 * * * * *";
+
+type JobFuture = Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
+
+/// Logs how many accounts there are.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CountAccounts;
+
+impl Job for CountAccounts {
+    type State = JobState;
+    type Future = JobFuture;
+
+    const NAME: &'static str = "CountAccountsJob";
+    const QUEUE: &'static str = DEFAULT_QUEUE;
+
+    fn run(self, state: JobState) -> Self::Future {
+        Box::pin(async move {
+            let count = Account::count(&state.pool)
+                .await
+                .map_err(|e| anyhow!("Error counting accounts: {:?}", e))?;
+            info!("There are {} accounts.", count);
+            Ok(())
+        })
+    }
+}
 
-// Define Actor
-#[derive(Clone)]
-pub struct Scheduler {
-    pub pool: PgPool,
-    pub schedule: String,
+impl Cron for CountAccounts {
+    const SCHEDULE: &'static str = EVERY_MINUTE;
 }
 
-#[derive(Message)]
-#[rtype(result = "Result<i64, ()>")]
-struct CountTask {}
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ExpireOAuthFlows;
+
+impl Job for ExpireOAuthFlows {
+    type State = JobState;
+    type Future = JobFuture;
+
+    const NAME: &'static str = "ExpireOAuthFlowsJob";
+    const QUEUE: &'static str = DEFAULT_QUEUE;
+
+    fn run(self, state: JobState) -> Self::Future {
+        Box::pin(async move {
+            let count = OAuthFlowRecord::delete_expired(&state.pool)
+                .await
+                .map_err(|e| anyhow!("Error expiring OAuth flows: {:?}", e))?;
+            let device_count = OAuthDeviceFlowRecord::delete_expired(&state.pool)
+                .await
+                .map_err(|e| anyhow!("Error expiring OAuth device flows: {:?}", e))?;
+            if count + device_count > 0 {
+                info!("Removed {} expired OAuth flows.", count + device_count);
+            }
+            Ok(())
+        })
+    }
+}
+
+impl Cron for ExpireOAuthFlows {
+    const SCHEDULE: &'static str = EVERY_MINUTE;
+}
+
+/// Removes accounts that haven't verified their email within
+/// `UNVERIFIED_ACCOUNT_RETENTION_DAYS` (unset or "0" keeps them), after a
+/// reminder at the halfway point; accounts that were never reminded get the
+/// rest of the period from when they are. With
+/// `UNVERIFIED_ACCOUNT_ACTION="deactivate"` they're deactivated instead of
+/// deleted.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PurgeUnverified;
+
+impl Job for PurgeUnverified {
+    type State = JobState;
+    type Future = JobFuture;
 
-impl Handler<CountTask> for Scheduler {
-    type Result = ResponseFuture<Result<i64, ()>>;
+    const NAME: &'static str = "PurgeUnverifiedJob";
+    const QUEUE: &'static str = DEFAULT_QUEUE;
 
-    fn handle(&mut self, _msg: CountTask, _ctx: &mut Context<Self>) -> Self::Result {
-        let pool = self.pool.clone();
+    fn run(self, state: JobState) -> Self::Future {
         Box::pin(async move {
-            match Account::count(&pool).await {
-                Ok(count) => {
-                    info!("There are {} accounts.", count);
-                    Ok(count)
+            let days = var("UNVERIFIED_ACCOUNT_RETENTION_DAYS")
+                .ok()
+                .and_then(|days| days.parse::<i32>().ok())
+                .unwrap_or(0);
+            if days <= 0 {
+                return Ok(());
+            }
+            let reminder_days = (days / 2).max(1);
+
+            // A failed reminder shouldn't hold up purging; the account it
+            // was for isn't purged until one's gone out.
+            if let Err(e) = send_verification_reminders(days, reminder_days, &state.pool, &state.templates).await {
+                error!("Error sending verification reminders: {:?}", e);
+            }
+
+            let deactivate = var("UNVERIFIED_ACCOUNT_ACTION").map_or(false, |a| a == "deactivate");
+            let kind = if deactivate { "account.deactivated" } else { "account.purged" };
+            let notice_days = days - reminder_days;
+            let purged = Account::purge_unverified(days, notice_days, deactivate, &state.pool)
+                .await
+                .map_err(|e| anyhow!("Error purging unverified accounts: {:?}", e))?;
+            for (id, email) in purged.iter() {
+                let data = json!({ "account_id": id, "email": email, "reason": "unverified" });
+                let account_id = if deactivate { Some(*id) } else { None };
+                if let Err(e) = AuditEvent::record(account_id, kind, data, &state.pool).await {
+                    error!("Error recording {} for account {}: {:?}", kind, id, e);
                 }
-                Err(_) => Err(())
             }
+            if !purged.is_empty() {
+                info!("Removed {} unverified accounts.", purged.len());
+            }
+            Ok(())
+        })
+    }
+}
+
+impl Cron for PurgeUnverified {
+    const SCHEDULE: &'static str = EVERY_MINUTE;
+}
+
+/// Reminds each account that's due one; an account whose reminder fails
+/// is logged, and tried again next time, without holding up the others.
+async fn send_verification_reminders(
+    retention_days: i32,
+    reminder_days: i32,
+    pool: &PgPool,
+    templates: &Arc<RwLock<Tera>>,
+) -> Result<(), Error> {
+    let accounts = Account::needing_verification_reminder(reminder_days, pool)
+        .await
+        .map_err(|e| anyhow!("Error fetching unverified accounts: {:?}", e))?;
+
+    for account in accounts {
+        let id = account.id;
+        if let Err(e) = send_verification_reminder(account, retention_days, pool, templates).await {
+            error!("Error sending verification reminder to account {}: {:?}", id, e);
         }
-    )}
+    }
+
+    Ok(())
+}
+
+async fn send_verification_reminder(
+    account: Account,
+    retention_days: i32,
+    pool: &PgPool,
+    templates: &Arc<RwLock<Tera>>,
+) -> Result<(), Error> {
+    if account.email_deliverable {
+        let email = build_reminder_email(&account, retention_days, templates.clone())?;
+        spawn_blocking(move || email.send()).await??;
+        Usage::record(&TenantPool::new(pool, TenantId(account.id)), Metric::EmailsSent, 1)
+            .await
+            .map_err(|e| anyhow!("Error recording email usage: {:?}", e))?;
+    }
+
+    Account::mark_verification_reminder_sent(account.id, pool)
+        .await
+        .map_err(|e| anyhow!("Error marking verification reminder sent: {:?}", e))
+}
+
+/// Rolls finished days of metered usage up into line items, and reports
+/// any new ones to Stripe if it's set up.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct MeterUsage;
+
+impl Job for MeterUsage {
+    type State = JobState;
+    type Future = JobFuture;
+
+    const NAME: &'static str = "MeterUsageJob";
+    const QUEUE: &'static str = DEFAULT_QUEUE;
+
+    fn run(self, state: JobState) -> Self::Future {
+        Box::pin(async move {
+            let count = DailyUsage::aggregate(&state.pool)
+                .await
+                .map_err(|e| anyhow!("Error aggregating usage: {:?}", e))?;
+            if count > 0 {
+                info!("Aggregated {} daily usage totals.", count);
+            }
+
+            if stripe::is_configured() {
+                report_usage(&state.pool).await?;
+            }
+            Ok(())
+        })
+    }
 }
 
-// Provide Actor implementation for our actor
-impl Actor for Scheduler {
-    type Context = Context<Self>;
+impl Cron for MeterUsage {
+    const SCHEDULE: &'static str = EVERY_MINUTE;
+}
+
+async fn report_usage(pool: &PgPool) -> Result<(), Error> {
+    let items = DailyUsage::unreported(pool)
+        .await
+        .map_err(|e| anyhow!("Error fetching unreported usage: {:?}", e))?;
+
+    for item in items {
+        let event_name = match stripe::event_name(&item.metric) {
+            Some(event_name) => event_name,
+            None => continue,
+        };
+        let item = spawn_blocking(move || stripe::report(&item, &event_name).map(|_| item)).await??;
 
-    fn started(&mut self, ctx: &mut Context<Self>) {
-        info!("Scheduler is alive");
-        ctx.notify(CountTask {});
-        ctx.run_later(duration_until_next(&self.schedule), move |this, ctx| {
-            this.schedule_task(ctx)
-        });
+        DailyUsage::mark_reported(item.account_id, &item.metric, item.day, pool)
+            .await
+            .map_err(|e| anyhow!("Error marking usage reported: {:?}", e))?;
     }
 
-    fn stopped(&mut self, _ctx: &mut Context<Self>) {
-        info!("Scheduler is stopped");
+    Ok(())
+}
+
+/// Emails owners of API tokens that expire within
+/// `API_TOKEN_EXPIRY_WARNING_DAYS` (default 3, "0" to not warn), once per
+/// token; renewing one resets its warning.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct WarnExpiringTokens;
+
+impl Job for WarnExpiringTokens {
+    type State = JobState;
+    type Future = JobFuture;
+
+    const NAME: &'static str = "WarnExpiringTokensJob";
+    const QUEUE: &'static str = DEFAULT_QUEUE;
+
+    fn run(self, state: JobState) -> Self::Future {
+        Box::pin(async move {
+            let days = var("API_TOKEN_EXPIRY_WARNING_DAYS")
+                .ok()
+                .and_then(|days| days.parse::<i32>().ok())
+                .unwrap_or(3);
+            if days <= 0 {
+                return Ok(());
+            }
+
+            let tokens = ApiToken::expiring(days, &state.pool)
+                .await
+                .map_err(|e| anyhow!("Error fetching expiring API tokens: {:?}", e))?;
+            for token in tokens {
+                let email = build_expiry_email(&token, state.templates.clone())?;
+                spawn_blocking(move || email.send()).await??;
+                Usage::record(&TenantPool::new(&state.pool, TenantId(token.account_id)), Metric::EmailsSent, 1)
+                    .await
+                    .map_err(|e| anyhow!("Error recording email usage: {:?}", e))?;
+
+                ApiToken::mark_expiry_warned(token.id, &state.pool)
+                    .await
+                    .map_err(|e| anyhow!("Error marking API token warned: {:?}", e))?;
+            }
+            Ok(())
+        })
     }
 }
 
-impl Scheduler {
-    // Executes based on cron schedule
-    fn schedule_task(&self, ctx: &mut Context<Self>) {
-        info!("Scheduler::schedule_task {:?}", Local::now());
-        ctx.notify(CountTask {});
-        ctx.run_later(duration_until_next(&self.schedule), move |this, ctx| {
-            this.schedule_task(ctx)
-        });
+impl Cron for WarnExpiringTokens {
+    const SCHEDULE: &'static str = EVERY_MINUTE;
+}
+
+/// Emails accounts that want a digest their unread notifications, grouped
+/// by category, once a day or week as they've chosen. Notifications go in
+/// one digest at most; reading them first keeps them out.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SendDigests;
+
+impl Job for SendDigests {
+    type State = JobState;
+    type Future = JobFuture;
+
+    const NAME: &'static str = "SendDigestsJob";
+    const QUEUE: &'static str = DEFAULT_QUEUE;
+
+    fn run(self, state: JobState) -> Self::Future {
+        Box::pin(async move {
+            for digest in [Digest::Daily, Digest::Weekly] {
+                send_digests(digest, &state.pool, &state.templates).await?;
+            }
+            Ok(())
+        })
+    }
+}
+
+impl Cron for SendDigests {
+    const SCHEDULE: &'static str = EVERY_HOUR;
+}
+
+async fn send_digests(digest: Digest, pool: &PgPool, templates: &Arc<RwLock<Tera>>) -> Result<(), Error> {
+    let recipients = Notification::due_digest(digest, pool)
+        .await
+        .map_err(|e| anyhow!("Error fetching accounts due a {} digest: {:?}", digest.as_str(), e))?;
+
+    for recipient in recipients {
+        let notifications = Notification::undigested(recipient.account_id, pool)
+            .await
+            .map_err(|e| anyhow!("Error fetching notifications for a digest: {:?}", e))?;
+        if notifications.is_empty() {
+            continue;
+        }
+
+        let email = build_digest_email(&recipient, digest, &notifications, templates.clone())?;
+        spawn_blocking(move || email.send()).await??;
+        Usage::record(&TenantPool::new(pool, TenantId(recipient.account_id)), Metric::EmailsSent, 1)
+            .await
+            .map_err(|e| anyhow!("Error recording email usage: {:?}", e))?;
+
+        let ids: Vec<i32> = notifications.iter().map(|n| n.id).collect();
+        Notification::mark_digested(recipient.account_id, &ids, pool)
+            .await
+            .map_err(|e| anyhow!("Error marking notifications digested: {:?}", e))?;
+    }
+
+    Ok(())
+}
+
+/// Notices pages whose `published` date has come (or that have been taken
+/// down), so scheduled pages go out in the exported feed and sitemap too;
+/// see `pages::publish`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PublishPages;
+
+impl Job for PublishPages {
+    type State = JobState;
+    type Future = JobFuture;
+
+    const NAME: &'static str = "PublishPagesJob";
+    const QUEUE: &'static str = DEFAULT_QUEUE;
+
+    fn run(self, state: JobState) -> Self::Future {
+        Box::pin(async move {
+            pages::publish(pages::export_dir().as_deref(), &state.pool).await?;
+            Ok(())
+        })
     }
 }
 
-pub fn duration_until_next(schedule: &str) -> Duration {
-    let cron_schedule = Schedule::from_str(schedule).unwrap();
-    let now = Local::now();
-    let next = cron_schedule.upcoming(Local).next().unwrap();
-    let duration_until = next.signed_duration_since(now);
-    duration_until.to_std().unwrap()
+impl Cron for PublishPages {
+    const SCHEDULE: &'static str = EVERY_MINUTE;
 }
@@ -0,0 +1,36 @@
+//! Waitlist mode. With `JELLY_WAITLIST` set, the sign up page only takes an
+//! email address (and a couple of optional survey answers); admins (or
+//! anyone with `waitlist.approve`) approve people in batches from
+//! `/admin/waitlist`, and each is emailed an invite link that lets them
+//! register as normal.
+
+use std::env;
+
+use jelly::actix_web::web::ServiceConfig;
+use jelly::routes::{resource, scope};
+
+pub mod forms;
+pub mod jobs;
+pub mod models;
+pub mod views;
+
+pub use models::WaitlistEntry;
+
+/// Session key holding the invite token someone arrived with, until they
+/// register.
+pub const SESSION_INVITE_TOKEN: &str = "invite_token";
+
+/// Whether registration is closed to anyone without an invite. Being a
+/// `JELLY_` variable, templates can check it too.
+pub fn waitlist_enabled() -> bool {
+    env::var("JELLY_WAITLIST").map_or(false, |v| !v.is_empty() && v != "0")
+}
+
+pub fn configure(config: &mut ServiceConfig) {
+    resource("/waitlist").post(views::join).mount(config);
+
+    scope("/admin/waitlist")
+        .permission("waitlist.")
+        .resource(resource("").get(views::admin::list).post(views::admin::approve))
+        .mount(config);
+}
@@ -0,0 +1,41 @@
+use jelly::forms::EmailField;
+use jelly::forms::validation::{Validatable, ValidationErrors};
+use jelly::serde_json::{json, Value};
+use serde::{Deserialize, Serialize};
+
+/// Joining the waitlist. Only the email is required.
+#[derive(Default, Debug, Deserialize, Serialize)]
+pub struct WaitlistForm {
+    pub email: EmailField,
+    #[serde(default)]
+    pub company: String,
+    #[serde(default)]
+    pub use_case: String,
+}
+
+impl WaitlistForm {
+    pub fn set_keys(mut self) -> Self {
+        self.email = self.email.with_key("email");
+        self
+    }
+
+    /// The survey answers, as stored with the entry.
+    pub fn survey(&self) -> Value {
+        json!({
+            "company": self.company.trim(),
+            "use_case": self.use_case.trim(),
+        })
+    }
+}
+
+impl Validatable<String> for WaitlistForm {
+    fn validate(&self) -> Result<(), ValidationErrors<String>> {
+        self.email.validate()
+    }
+}
+
+/// Approving the next `count` people, oldest first.
+#[derive(Debug, Deserialize)]
+pub struct ApproveForm {
+    pub count: i64,
+}
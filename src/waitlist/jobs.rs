@@ -0,0 +1,70 @@
+use std::env::var;
+use std::future::Future;
+use std::pin::Pin;
+
+use jelly::anyhow::{anyhow, Error};
+use jelly::email::Email;
+use jelly::jobs::{Job, JobConfig, JobState, Traced, TRANSACTIONAL_QUEUE};
+use jelly::serde::{Deserialize, Serialize};
+use jelly::tera::Context;
+
+use super::WaitlistEntry;
+use crate::accounts::Account;
+
+/// Emails an approved waitlist entry its invite link.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SendWaitlistInviteEmail {
+    pub entry_id: i32,
+}
+
+pub fn build_context(invite_url: &str) -> Context {
+    let mut context = Context::new();
+    context.insert("action_url", invite_url);
+    context
+}
+
+impl Job for SendWaitlistInviteEmail {
+    type State = JobState;
+    type Future = Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
+
+    const NAME: &'static str = "SendWaitlistInviteEmailJob";
+    const QUEUE: &'static str = TRANSACTIONAL_QUEUE;
+
+    fn run(self, state: JobState) -> Self::Future {
+        Box::pin(async move {
+            let entry = WaitlistEntry::get(self.entry_id, &state.pool)
+                .await
+                .map_err(|e| anyhow!("Error fetching waitlist entry: {:?}", e))?;
+
+            let domain = var("JELLY_DOMAIN").expect("No JELLY_DOMAIN value set!");
+            let invite_url = match entry.invite_url(&domain) {
+                Some(url) => url,
+                None => return Ok(()),
+            };
+
+            let deliverable = Account::is_deliverable(&entry.email, &state.pool)
+                .await
+                .map_err(|e| anyhow!("Error checking deliverability: {:?}", e))?;
+            if !deliverable {
+                warn!("Not sending waitlist invite to undeliverable {}", entry.email);
+                return Ok(());
+            }
+
+            let email = Email::new(
+                "email/waitlist-invite",
+                &[entry.email],
+                "You're in! Finish signing up",
+                build_context(&invite_url),
+                state.templates,
+            );
+
+            email?.send()?;
+
+            Ok(())
+        })
+    }
+}
+
+pub fn configure(config: JobConfig) -> JobConfig {
+    config.register::<Traced<SendWaitlistInviteEmail>>()
+}
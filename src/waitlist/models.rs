@@ -0,0 +1,166 @@
+use jelly::chrono::{DateTime, Utc};
+use jelly::error::Error;
+use jelly::rand::{thread_rng, Rng};
+use jelly::serde::Serialize;
+use jelly::serde_json::Value;
+use sqlx::{postgres::PgPool, types::Json};
+
+use super::forms::WaitlistForm;
+
+/// Someone waiting to register.
+#[derive(Debug, Serialize)]
+pub struct WaitlistEntry {
+    pub id: i32,
+    pub email: String,
+    pub survey: Json<Value>,
+    pub created: DateTime<Utc>,
+    pub approved: Option<DateTime<Utc>>,
+    pub invite_token: Option<String>,
+    pub registered: Option<DateTime<Utc>>,
+}
+
+impl WaitlistEntry {
+    /// Adds `form`'s email to the waitlist. Joining twice is a no-op, so as
+    /// not to reveal who's already on it.
+    pub async fn join(form: &WaitlistForm, pool: &PgPool) -> Result<(), Error> {
+        sqlx::query!(
+            "
+            INSERT INTO waitlist (email, survey)
+            VALUES ($1, $2)
+            ON CONFLICT (email) DO NOTHING
+        ",
+            form.email.value,
+            Json(form.survey()) as _
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get(id: i32, pool: &PgPool) -> Result<Self, Error> {
+        Ok(sqlx::query_as_unchecked!(
+            WaitlistEntry,
+            "
+            SELECT
+                id, email, survey, created, approved, invite_token, registered
+            FROM waitlist WHERE id = $1
+        ",
+            id
+        )
+        .fetch_one(pool)
+        .await?)
+    }
+
+    /// Entries not yet approved, oldest first.
+    pub async fn pending(limit: i64, pool: &PgPool) -> Result<Vec<Self>, Error> {
+        Ok(sqlx::query_as_unchecked!(
+            WaitlistEntry,
+            "
+            SELECT
+                id, email, survey, created, approved, invite_token, registered
+            FROM waitlist
+            WHERE approved IS NULL
+            ORDER BY created, id
+            LIMIT $1
+        ",
+            limit
+        )
+        .fetch_all(pool)
+        .await?)
+    }
+
+    pub async fn count_pending(pool: &PgPool) -> Result<i64, Error> {
+        Ok(sqlx::query!(
+            "
+            SELECT count(*) as \"count!\" FROM waitlist WHERE approved IS NULL
+        "
+        )
+        .fetch_one(pool)
+        .await?
+        .count)
+    }
+
+    /// Approves the `count` oldest pending entries, giving each an invite
+    /// token. Returns their ids.
+    pub async fn approve_oldest(count: i64, pool: &PgPool) -> Result<Vec<i32>, Error> {
+        let mut tx = pool.begin().await?;
+
+        let ids: Vec<i32> = sqlx::query!(
+            "
+            SELECT id FROM waitlist
+            WHERE approved IS NULL
+            ORDER BY created, id
+            LIMIT $1
+            FOR UPDATE SKIP LOCKED
+        ",
+            count
+        )
+        .fetch_all(&mut tx)
+        .await?
+        .into_iter()
+        .map(|row| row.id)
+        .collect();
+
+        for id in ids.iter() {
+            sqlx::query!(
+                "
+                UPDATE waitlist
+                SET approved = now(), invite_token = $2
+                WHERE id = $1
+            ",
+                id,
+                new_invite_token()
+            )
+            .execute(&mut tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(ids)
+    }
+
+    /// The approved entry `token` belongs to, if it hasn't been used yet.
+    pub async fn by_invite_token(token: &str, pool: &PgPool) -> Result<Option<Self>, Error> {
+        Ok(sqlx::query_as_unchecked!(
+            WaitlistEntry,
+            "
+            SELECT
+                id, email, survey, created, approved, invite_token, registered
+            FROM waitlist
+            WHERE invite_token = $1 AND registered IS NULL
+        ",
+            token
+        )
+        .fetch_optional(pool)
+        .await?)
+    }
+
+    /// Uses up `token`. Returns whether it was still valid.
+    pub async fn mark_registered(token: &str, pool: &PgPool) -> Result<bool, Error> {
+        let result = sqlx::query!(
+            "
+            UPDATE waitlist
+            SET registered = now()
+            WHERE invite_token = $1 AND registered IS NULL
+        ",
+            token
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// The link that lets this entry register, once approved.
+    pub fn invite_url(&self, domain: &str) -> Option<String> {
+        self.invite_token
+            .as_ref()
+            .map(|token| format!("{}/accounts/register?invite={}", domain, token))
+    }
+}
+
+fn new_invite_token() -> String {
+    let bytes: [u8; 24] = thread_rng().gen();
+    base64_url::encode(&bytes)
+}
@@ -0,0 +1,46 @@
+use jelly::actix_web::{web, HttpRequest};
+use jelly::forms::validation::Validatable;
+use jelly::prelude::*;
+use jelly::Result;
+
+use super::forms::WaitlistForm;
+use super::{waitlist_enabled, WaitlistEntry, SESSION_INVITE_TOKEN};
+
+pub mod admin;
+
+/// The waitlist entry for the invite in the session, if it's still good.
+pub async fn session_invite(request: &HttpRequest) -> Result<Option<WaitlistEntry>> {
+    match request.get_session().get::<String>(SESSION_INVITE_TOKEN)? {
+        Some(token) => Ok(WaitlistEntry::by_invite_token(&token, request.db_pool()?).await?),
+        None => Ok(None),
+    }
+}
+
+/// Renders the waitlist form in place of the sign up form.
+pub fn form(request: &HttpRequest) -> Result<HttpResponse> {
+    request.render(200, "waitlist/join.html", {
+        let mut ctx = Context::new();
+        ctx.insert("form", &WaitlistForm::default());
+        ctx
+    })
+}
+
+pub async fn join(request: HttpRequest, form: web::Form<WaitlistForm>) -> Result<HttpResponse> {
+    if !waitlist_enabled() {
+        return request.redirect("/accounts/register");
+    }
+
+    let form = form.into_inner().set_keys();
+    if let Err(errors) = form.validate() {
+        return request.render(400, "waitlist/join.html", {
+            let mut context = Context::new();
+            context.insert("errors", &errors);
+            context.insert("form", &form);
+            context
+        });
+    }
+
+    WaitlistEntry::join(&form, request.db_pool()?).await?;
+
+    request.render(200, "waitlist/joined.html", Context::new())
+}
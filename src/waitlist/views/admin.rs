@@ -0,0 +1,38 @@
+use jelly::actix_web::{web, HttpRequest};
+use jelly::prelude::*;
+use jelly::Result;
+
+use crate::waitlist::forms::ApproveForm;
+use crate::waitlist::jobs::SendWaitlistInviteEmail;
+use crate::waitlist::WaitlistEntry;
+
+/// How many pending entries are listed.
+const SHOWN: i64 = 100;
+
+/// Pending waitlist entries, oldest first.
+pub async fn list(request: HttpRequest) -> Result<HttpResponse> {
+    request.require_permission("waitlist.view").await?;
+    let db = request.db_pool()?;
+    let entries = WaitlistEntry::pending(SHOWN, db).await?;
+    let pending = WaitlistEntry::count_pending(db).await?;
+
+    request.render(200, "waitlist/admin.html", {
+        let mut ctx = Context::new();
+        ctx.insert("entries", &entries);
+        ctx.insert("pending", &pending);
+        ctx
+    })
+}
+
+/// Approves the next batch, and emails each of them an invite.
+pub async fn approve(request: HttpRequest, form: web::Form<ApproveForm>) -> Result<HttpResponse> {
+    request.require_permission("waitlist.approve").await?;
+    let ids = WaitlistEntry::approve_oldest(form.count.max(0), request.db_pool()?).await?;
+
+    for entry_id in ids.iter() {
+        request.queue_job(SendWaitlistInviteEmail { entry_id: *entry_id }).await?;
+    }
+
+    request.flash("Waitlist", &format!("Invited {} people.", ids.len()))?;
+    request.redirect("/admin/waitlist")
+}
@@ -0,0 +1,48 @@
+//! Webhooks: inbound ones from third-party services, and outgoing ones
+//! POSTing events to the endpoints accounts set up in the dashboard (see
+//! `jelly::webhooks` for how they're signed).
+
+use std::env;
+
+use jelly::actix_web::web::ServiceConfig;
+use jelly::error::Error;
+use jelly::jobs::QueueHandle;
+use jelly::routes::{resource, scope};
+use jelly::serde_json::Value;
+use sqlx::postgres::PgPool;
+
+pub mod jobs;
+pub mod models;
+pub mod views;
+
+pub use models::{WebhookDelivery, WebhookEndpoint};
+
+/// How long, after rotating an endpoint's secret, requests are still
+/// signed with the old one too.
+pub fn secret_overlap_hours() -> i64 {
+    env::var("WEBHOOK_SECRET_OVERLAP_HOURS")
+        .ok()
+        .and_then(|hours| hours.parse().ok())
+        .unwrap_or(24)
+}
+
+/// Queues `event` for each of `account_id`'s endpoints.
+pub async fn emit(account_id: i32, event: &str, data: Value, pool: &PgPool, queue: &QueueHandle) -> Result<(), Error> {
+    for endpoint in WebhookEndpoint::for_account(account_id, pool).await? {
+        queue
+            .queue(jobs::DeliverWebhook {
+                endpoint_id: endpoint.id,
+                event: event.to_string(),
+                data: data.clone(),
+            })
+            .await?;
+    }
+    Ok(())
+}
+
+pub fn configure(config: &mut ServiceConfig) {
+    scope("/webhooks")
+        .resource(resource("/email/postmark").post(views::postmark_bounce))
+        .resource(resource("/email/sendgrid").post(views::sendgrid_events))
+        .mount(config);
+}
@@ -0,0 +1,75 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use jelly::anyhow::{anyhow, Error};
+use jelly::chrono::Utc;
+use jelly::jobs::{Job, JobConfig, JobState, Traced, DEFAULT_QUEUE};
+use jelly::serde::{Deserialize, Serialize};
+use jelly::serde_json::{json, Value};
+use jelly::webhooks::{self, Attempt};
+use sqlx::postgres::PgPool;
+
+use super::models::{WebhookDelivery, WebhookEndpoint};
+
+/// Sends `event` to `endpoint` now, logging the attempt.
+pub async fn deliver(
+    endpoint: &WebhookEndpoint,
+    event: &str,
+    data: Value,
+    pool: &PgPool,
+) -> Result<Attempt, jelly::error::Error> {
+    let now = Utc::now();
+    let body = json!({ "event": event, "created": now, "data": data }).to_string();
+    let attempt = webhooks::post(&endpoint.url, event, &endpoint.signing_secrets(), now.timestamp(), body).await?;
+    WebhookDelivery::record(endpoint.id, event, &attempt, pool).await?;
+    Ok(attempt)
+}
+
+/// Sends an event to one endpoint. Anything but a 2xx fails the job, so
+/// it's retried (and each attempt shows in the delivery log).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeliverWebhook {
+    pub endpoint_id: i32,
+    pub event: String,
+    pub data: Value,
+}
+
+impl Job for DeliverWebhook {
+    type State = JobState;
+    type Future = Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
+
+    const NAME: &'static str = "DeliverWebhookJob";
+    const QUEUE: &'static str = DEFAULT_QUEUE;
+
+    fn run(self, state: JobState) -> Self::Future {
+        Box::pin(async move {
+            let endpoint = WebhookEndpoint::get(self.endpoint_id, &state.pool)
+                .await
+                .map_err(|e| anyhow!("Error fetching webhook endpoint: {:?}", e))?;
+            // Deleted since.
+            let endpoint = match endpoint {
+                Some(endpoint) => endpoint,
+                None => return Ok(()),
+            };
+
+            let attempt = deliver(&endpoint, &self.event, self.data, &state.pool)
+                .await
+                .map_err(|e| anyhow!("Error delivering webhook: {:?}", e))?;
+            if !attempt.succeeded() {
+                return Err(anyhow!(
+                    "Webhook {} to endpoint {} failed: {:?} {:?}",
+                    self.event,
+                    endpoint.id,
+                    attempt.status,
+                    attempt.error
+                ));
+            }
+
+            Ok(())
+        })
+    }
+}
+
+pub fn configure(config: JobConfig) -> JobConfig {
+    config.register::<Traced<DeliverWebhook>>()
+}
@@ -0,0 +1,171 @@
+use jelly::chrono::{DateTime, Duration, Utc};
+use jelly::error::Error;
+use jelly::serde::Serialize;
+use jelly::webhooks::{self, Attempt};
+use sqlx::postgres::PgPool;
+
+use super::secret_overlap_hours;
+
+/// A URL an account has events POSTed to.
+#[derive(Debug, Serialize)]
+pub struct WebhookEndpoint {
+    pub id: i32,
+    pub account_id: i32,
+    pub url: String,
+    pub secret: String,
+    pub previous_secret: Option<String>,
+    pub rotated: Option<DateTime<Utc>>,
+    pub created: DateTime<Utc>,
+}
+
+impl WebhookEndpoint {
+    /// Until when the previous secret still signs requests, if it does.
+    pub fn previous_secret_until(&self) -> Option<DateTime<Utc>> {
+        self.previous_secret.as_ref()?;
+        let until = self.rotated? + Duration::hours(secret_overlap_hours());
+        Some(until).filter(|until| *until > Utc::now())
+    }
+
+    /// The secrets requests are signed with: the current one, and the
+    /// previous one during the overlap after rotating.
+    pub fn signing_secrets(&self) -> Vec<&str> {
+        let mut secrets = vec![self.secret.as_str()];
+        if let (Some(previous), Some(_)) = (&self.previous_secret, self.previous_secret_until()) {
+            secrets.push(previous);
+        }
+        secrets
+    }
+
+    pub async fn create(account_id: i32, url: &str, pool: &PgPool) -> Result<i32, Error> {
+        Ok(sqlx::query!(
+            "
+            INSERT INTO webhook_endpoints (account_id, url, secret)
+            VALUES ($1, $2, $3)
+            RETURNING id
+        ",
+            account_id,
+            url,
+            webhooks::new_secret()
+        )
+        .fetch_one(pool)
+        .await?
+        .id)
+    }
+
+    pub async fn get(id: i32, pool: &PgPool) -> Result<Option<Self>, Error> {
+        Ok(sqlx::query_as_unchecked!(
+            WebhookEndpoint,
+            "
+            SELECT
+                id, account_id, url, secret, previous_secret, rotated, created
+            FROM webhook_endpoints WHERE id = $1
+        ",
+            id
+        )
+        .fetch_optional(pool)
+        .await?)
+    }
+
+    pub async fn for_account(account_id: i32, pool: &PgPool) -> Result<Vec<Self>, Error> {
+        Ok(sqlx::query_as_unchecked!(
+            WebhookEndpoint,
+            "
+            SELECT
+                id, account_id, url, secret, previous_secret, rotated, created
+            FROM webhook_endpoints
+            WHERE account_id = $1
+            ORDER BY created, id
+        ",
+            account_id
+        )
+        .fetch_all(pool)
+        .await?)
+    }
+
+    /// Gives the endpoint a new secret, keeping the old one for the
+    /// overlap. Returns whether it's one of `account_id`'s.
+    pub async fn rotate_secret(id: i32, account_id: i32, pool: &PgPool) -> Result<bool, Error> {
+        let result = sqlx::query!(
+            "
+            UPDATE webhook_endpoints
+            SET previous_secret = secret, secret = $3, rotated = now()
+            WHERE id = $1 AND account_id = $2
+        ",
+            id,
+            account_id,
+            webhooks::new_secret()
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Returns whether it was one of `account_id`'s.
+    pub async fn delete(id: i32, account_id: i32, pool: &PgPool) -> Result<bool, Error> {
+        let result = sqlx::query!(
+            "
+            DELETE FROM webhook_endpoints WHERE id = $1 AND account_id = $2
+        ",
+            id,
+            account_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+/// One attempt at sending an event to an endpoint.
+#[derive(Debug, Serialize)]
+pub struct WebhookDelivery {
+    pub id: i32,
+    pub endpoint_id: i32,
+    pub url: String,
+    pub event: String,
+    pub status: Option<i32>,
+    pub error: Option<String>,
+    pub duration_ms: i32,
+    pub created: DateTime<Utc>,
+}
+
+impl WebhookDelivery {
+    pub async fn record(endpoint_id: i32, event: &str, attempt: &Attempt, pool: &PgPool) -> Result<(), Error> {
+        sqlx::query!(
+            "
+            INSERT INTO webhook_deliveries (endpoint_id, event, status, error, duration_ms)
+            VALUES ($1, $2, $3, $4, $5)
+        ",
+            endpoint_id,
+            event,
+            attempt.status.map(i32::from),
+            attempt.error,
+            attempt.duration.as_millis() as i32
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// The latest attempts to any of `account_id`'s endpoints.
+    pub async fn recent(account_id: i32, limit: i64, pool: &PgPool) -> Result<Vec<Self>, Error> {
+        Ok(sqlx::query_as_unchecked!(
+            WebhookDelivery,
+            "
+            SELECT
+                d.id, d.endpoint_id, e.url, d.event, d.status, d.error, d.duration_ms, d.created
+            FROM webhook_deliveries d
+            JOIN webhook_endpoints e ON e.id = d.endpoint_id
+            WHERE e.account_id = $1
+            ORDER BY d.created DESC, d.id DESC
+            LIMIT $2
+        ",
+            account_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await?)
+    }
+}
@@ -0,0 +1,90 @@
+use std::env::var;
+
+use jelly::actix_web::{web, HttpRequest};
+use jelly::constant_time_eq::constant_time_eq;
+use jelly::prelude::*;
+use jelly::Result;
+use serde::Deserialize;
+
+use crate::accounts::Account;
+
+/// Providers are configured to call `/webhooks/...?token=<EMAIL_WEBHOOK_TOKEN>`.
+#[derive(Deserialize)]
+pub struct WebhookToken {
+    #[serde(default)]
+    pub token: String,
+}
+
+impl WebhookToken {
+    fn is_valid(&self) -> bool {
+        match var("EMAIL_WEBHOOK_TOKEN") {
+            Ok(expected) if !expected.is_empty() => {
+                constant_time_eq(self.token.as_bytes(), expected.as_bytes())
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The parts of a Postmark bounce webhook we care about.
+#[derive(Deserialize)]
+pub struct PostmarkBounce {
+    #[serde(rename = "Type")]
+    pub kind: String,
+    #[serde(rename = "Email")]
+    pub email: String,
+}
+
+/// One entry of a SendGrid event webhook batch.
+#[derive(Deserialize)]
+pub struct SendgridEvent {
+    pub event: String,
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    pub email: String,
+}
+
+async fn flag_undeliverable(request: &HttpRequest, email: &str) -> Result<()> {
+    if Account::mark_undeliverable(email, request.db_pool()?).await? {
+        info!("Hard bounce for {}, flagged as undeliverable", email);
+    }
+
+    Ok(())
+}
+
+/// Postmark bounce webhook. Soft bounces (mailbox full, etc) are ignored.
+pub async fn postmark_bounce(
+    request: HttpRequest,
+    token: web::Query<WebhookToken>,
+    bounce: web::Json<PostmarkBounce>,
+) -> Result<HttpResponse> {
+    if !token.is_valid() {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    if matches!(bounce.kind.as_str(), "HardBounce" | "BadEmailAddress") {
+        flag_undeliverable(&request, &bounce.email).await?;
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// SendGrid event webhook. Only `bounce` events of type `bounce` are hard
+/// bounces; `blocked` ones are usually temporary.
+pub async fn sendgrid_events(
+    request: HttpRequest,
+    token: web::Query<WebhookToken>,
+    events: web::Json<Vec<SendgridEvent>>,
+) -> Result<HttpResponse> {
+    if !token.is_valid() {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    for event in events.iter() {
+        if event.event == "bounce" && event.kind.as_deref() == Some("bounce") {
+            flag_undeliverable(&request, &event.email).await?;
+        }
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
@@ -0,0 +1,37 @@
+use jelly::chrono::Utc;
+use jelly::serde_json;
+use mainlib::accounts::models::Profile;
+use mainlib::accounts::Account;
+use sqlx::types::Json;
+
+fn account() -> Account {
+    Account {
+        id: 1,
+        name: "Erby".to_string(),
+        email: "erby@example.com".to_string(),
+        username: Some("erby".to_string()),
+        password: Some("pbkdf2_sha256$260000$salt$hash".to_string()),
+        profile: Json(Profile::default()),
+        plan: 0,
+        is_active: true,
+        is_admin: false,
+        has_verified_email: true,
+        email_deliverable: true,
+        last_login: None,
+        created: Utc::now(),
+        updated: Utc::now(),
+        version: 1,
+    }
+}
+
+mod account_should {
+    use super::*;
+
+    #[test]
+    fn never_serialize_its_password() {
+        let serialized = serde_json::to_value(account()).unwrap();
+        assert!(serialized.get("password").is_none());
+        assert!(!serialized.to_string().contains("pbkdf2"));
+        assert_eq!(serialized["email"], "erby@example.com");
+    }
+}
@@ -0,0 +1,87 @@
+use mainlib::admin::forms::{BulkForm, RolesForm};
+use jelly::accounts::permissions::Granted;
+use mainlib::admin::models::{capability, Role, CAPABILITIES};
+
+mod bulk_form_should {
+    use super::*;
+
+    #[test]
+    fn read_every_checked_account() {
+        let form = BulkForm::parse(b"csrf_token=abc&action=deactivate&account_ids=3&account_ids=7&account_ids=3");
+        assert_eq!(form.action, "deactivate");
+        assert_eq!(form.account_ids, vec![3, 7]);
+        assert!(form.is_valid());
+    }
+
+    #[test]
+    fn skip_anything_that_is_not_an_id() {
+        let form = BulkForm::parse(b"action=export&account_ids=1%3B&account_ids=&account_ids=2");
+        assert_eq!(form.account_ids, vec![2]);
+    }
+
+    #[test]
+    fn need_an_action_and_an_account() {
+        assert!(!BulkForm::parse(b"action=export").is_valid());
+        assert!(!BulkForm::parse(b"action=delete&account_ids=1").is_valid());
+        assert!(!BulkForm::parse(b"").is_valid());
+    }
+}
+
+mod roles_form_should {
+    use super::*;
+
+    #[test]
+    fn read_every_checked_capability() {
+        let form = RolesForm::parse(b"csrf_token=abc&grants=1.manage_users&grants=2.view_audit&grants=1.view_audit&grants=1.manage_users");
+        assert_eq!(form.grants, vec![(1, "manage_users"), (2, "view_audit"), (1, "view_audit")]);
+        let names: Vec<_> = form.for_role(1).iter().map(|capability| capability.name).collect();
+        assert_eq!(names, vec!["manage_users", "view_audit"]);
+        assert!(form.for_role(3).is_empty());
+    }
+
+    #[test]
+    fn skip_unknown_capabilities() {
+        let form = RolesForm::parse(b"grants=1.edit_roles&grants=x.view_audit&grants=2&grants=2.send_campaigns");
+        assert_eq!(form.grants, vec![(2, "send_campaigns")]);
+    }
+}
+
+mod role_should {
+    use super::*;
+
+    fn role(permissions: &[&str]) -> Role {
+        Role {
+            id: 1,
+            name: "Support".to_string(),
+            permissions: permissions.iter().map(|p| p.to_string()).collect(),
+            members: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn have_capabilities_with_all_their_permissions() {
+        let support = role(&["accounts.*", "audit.view", "invitations.*", "waitlist.*"]);
+        assert_eq!(support.capabilities(), vec!["manage_users"]);
+        assert!(role(&[]).capabilities().is_empty());
+    }
+
+    #[test]
+    fn not_hand_out_role_editing() {
+        assert!(CAPABILITIES
+            .iter()
+            .all(|capability| capability.permissions.iter().all(|p| !p.starts_with("roles.") && *p != "*")));
+    }
+
+    #[test]
+    fn only_be_handed_out_by_people_with_all_it_grants() {
+        let support = role(&["accounts.*", "invitations.*", "waitlist.*", "billing.refund"]);
+        let holder = |allowed: &[&str]| Granted {
+            allowed: allowed.iter().map(|p| p.to_string()).collect(),
+            ..Granted::default()
+        };
+
+        assert!(holder(&["accounts.*", "invitations.*", "waitlist.*", "billing.*"]).includes(&support.granted()));
+        assert!(!holder(&["accounts.*", "invitations.*", "waitlist.*"]).includes(&support.granted()));
+        assert!(!holder(&["roles.edit"]).includes(&capability("manage_billing").unwrap().granted()));
+    }
+}
@@ -0,0 +1,45 @@
+use jelly::chrono::{DateTime, Duration, Utc};
+use jelly::serde_json::json;
+use mainlib::audit::{anomaly, AuditEvent};
+use sqlx::types::Json;
+
+fn login(created: DateTime<Utc>, city: &str, latitude: f64, longitude: f64) -> AuditEvent {
+    AuditEvent {
+        id: 0,
+        account_id: Some(1),
+        kind: "login".to_string(),
+        ip: None,
+        user_agent: None,
+        data: Json(json!({
+            "location": {
+                "country": "NZ",
+                "country_name": "New Zealand",
+                "city": city,
+                "latitude": latitude,
+                "longitude": longitude,
+            }
+        })),
+        created,
+    }
+}
+
+mod anomaly_should {
+    use super::*;
+
+    #[test]
+    fn flag_impossible_travel() {
+        let now = Utc::now();
+        let earlier = login(now - Duration::minutes(30), "Auckland", -36.85, 174.76);
+        let reasons = anomaly::reasons(&login(now, "Dunedin", -45.87, 170.50), &[earlier]);
+        assert_eq!(reasons.len(), 1);
+        assert!(reasons[0].contains("km from Auckland"));
+    }
+
+    #[test]
+    fn not_flag_short_hops_however_quick() {
+        let now = Utc::now();
+        let earlier = login(now - Duration::minutes(1), "Wellington", -41.29, 174.78);
+        let reasons = anomaly::reasons(&login(now, "Palmerston North", -40.35, 175.61), &[earlier]);
+        assert!(reasons.is_empty());
+    }
+}
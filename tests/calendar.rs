@@ -0,0 +1,22 @@
+use mainlib::calendar::{account_for_token, feed_token};
+
+const SECRET_KEY: &str = "test-secret-key-that-is-long-enough-for-actix-session-0.6-cookies";
+
+mod feed_token_should {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        std::env::set_var("SECRET_KEY", SECRET_KEY);
+        assert_eq!(account_for_token(&feed_token(42)), Some(42));
+    }
+
+    #[test]
+    fn reject_other_accounts() {
+        std::env::set_var("SECRET_KEY", SECRET_KEY);
+        let token = feed_token(42);
+        let (_, signature) = token.split_once('.').unwrap();
+        assert_eq!(account_for_token(&format!("43.{}", signature)), None);
+        assert_eq!(account_for_token("42"), None);
+    }
+}
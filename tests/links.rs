@@ -0,0 +1,43 @@
+use mainlib::links::{with_utm, Utm};
+
+mod with_utm_should {
+    use super::*;
+
+    #[test]
+    fn append_parameters() {
+        let utm = Utm::campaign("newsletter", "spring sale");
+        assert_eq!(
+            with_utm("https://example.com/pricing", &utm),
+            "https://example.com/pricing?utm_source=newsletter&utm_medium=email&utm_campaign=spring%20sale"
+        );
+    }
+
+    #[test]
+    fn keep_query_and_fragment() {
+        let utm = Utm {
+            source: Some("newsletter".to_string()),
+            ..Utm::default()
+        };
+        assert_eq!(
+            with_utm("https://example.com/?plan=pro#faq", &utm),
+            "https://example.com/?plan=pro&utm_source=newsletter#faq"
+        );
+    }
+
+    #[test]
+    fn not_override_existing() {
+        let utm = Utm::campaign("newsletter", "spring");
+        assert_eq!(
+            with_utm("https://example.com/?utm_source=twitter", &utm),
+            "https://example.com/?utm_source=twitter&utm_medium=email&utm_campaign=spring"
+        );
+    }
+
+    #[test]
+    fn leave_url_without_utm() {
+        assert_eq!(
+            with_utm("https://example.com/", &Utm::default()),
+            "https://example.com/"
+        );
+    }
+}
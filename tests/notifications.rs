@@ -0,0 +1,58 @@
+use jelly::chrono::Utc;
+use mainlib::notifications::models::group_by_category;
+use mainlib::notifications::{account_for_token, unsubscribe_token, Notification};
+
+const SECRET_KEY: &str = "test-secret-key-that-is-long-enough-for-actix-session-0.6-cookies";
+
+fn notification(id: i32, category: &str) -> Notification {
+    Notification {
+        id,
+        account_id: 1,
+        category: category.to_string(),
+        title: format!("Notification {}", id),
+        body: None,
+        url: None,
+        read_at: None,
+        digested_at: None,
+        created: Utc::now(),
+    }
+}
+
+mod unsubscribe_token_should {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        std::env::set_var("SECRET_KEY", SECRET_KEY);
+        assert_eq!(account_for_token(&unsubscribe_token(42)), Some(42));
+    }
+
+    #[test]
+    fn reject_other_accounts_and_calendar_tokens() {
+        std::env::set_var("SECRET_KEY", SECRET_KEY);
+        let token = unsubscribe_token(42);
+        let (_, signature) = token.split_once('.').unwrap();
+        assert_eq!(account_for_token(&format!("43.{}", signature)), None);
+        assert_eq!(account_for_token(&mainlib::calendar::feed_token(42)), None);
+    }
+}
+
+mod digest_should {
+    use super::*;
+
+    #[test]
+    fn group_notifications_by_category() {
+        let notifications = [
+            notification(1, "billing"),
+            notification(2, "files"),
+            notification(3, "billing"),
+        ];
+        let groups = group_by_category(&notifications);
+
+        let summary: Vec<(&str, Vec<i32>)> = groups
+            .iter()
+            .map(|group| (group.category, group.notifications.iter().map(|n| n.id).collect()))
+            .collect();
+        assert_eq!(summary, vec![("billing", vec![1, 3]), ("files", vec![2])]);
+    }
+}
@@ -0,0 +1,91 @@
+use jelly::chrono::{Duration, TimeZone, Utc};
+use jelly::forms::validation::Validatable;
+use jelly::oauth::UserInfo;
+use mainlib::oauth::forms::LinkIdentityForm;
+use mainlib::oauth::models::OAuthDeviceFlow;
+
+mod link_identity_form_should {
+    use super::*;
+
+    #[test]
+    fn confirm_the_providers_profile() {
+        let form = LinkIdentityForm::from_user_info(UserInfo {
+            provider: "github",
+            id: "1234".to_string(),
+            name: "Jane Doe".to_string(),
+            username: Some("janedoe".to_string()),
+            provider_email: None,
+            login_email: "jane@example.com".to_string(),
+        })
+        .set_keys();
+
+        assert_eq!(form.provider, "github");
+        assert_eq!(form.username, "janedoe");
+        assert_eq!(form.name.value, "Jane Doe");
+        assert_eq!(form.email.value, "jane@example.com");
+        assert!(form.validate().is_ok());
+    }
+
+    #[test]
+    fn fall_back_to_the_provider_id() {
+        let form = LinkIdentityForm::from_user_info(UserInfo {
+            provider: "facebook",
+            id: "1234".to_string(),
+            name: "Jane Doe".to_string(),
+            ..UserInfo::default()
+        })
+        .set_keys();
+
+        assert_eq!(form.username, "1234");
+        // The email is still to be filled in on the confirm form.
+        assert!(form.validate().is_err());
+    }
+
+    #[test]
+    fn keep_the_identity_the_provider_gave() {
+        let confirmed = LinkIdentityForm::from_user_info(UserInfo {
+            provider: "google",
+            id: "1234".to_string(),
+            provider_email: Some("jane@example.com".to_string()),
+            ..UserInfo::default()
+        });
+        let pending = confirmed.pending_identity();
+
+        let posted = LinkIdentityForm {
+            provider: "github".to_string(),
+            username: "someone-else".to_string(),
+            ..LinkIdentityForm::default()
+        };
+        let form = posted.with_pending_identity(pending);
+        assert_eq!(form.provider, "google");
+        assert_eq!(form.username, "1234");
+        assert_eq!(form.provider_email.as_deref(), Some("jane@example.com"));
+    }
+}
+
+mod device_flow_should {
+    use super::*;
+
+    fn flow(last_polled: Option<i64>) -> OAuthDeviceFlow {
+        OAuthDeviceFlow {
+            id: "id".to_string(),
+            provider: "github".to_string(),
+            device_code: "device-code".to_string(),
+            user_code: "WDJB-MJHT".to_string(),
+            verification_uri: "https://github.com/login/device".to_string(),
+            verification_uri_complete: None,
+            interval_seconds: 5,
+            last_polled: last_polled.map(|at| Utc.timestamp(at, 0)),
+            expires: Utc.timestamp(1_000_900, 0),
+        }
+    }
+
+    #[test]
+    fn wait_the_interval_between_polls() {
+        let now = Utc.timestamp(1_000_000, 0);
+        assert!(!flow(None).polled_too_soon(now));
+        assert!(flow(Some(1_000_000 - 4)).polled_too_soon(now));
+        assert!(!flow(Some(1_000_000 - 5)).polled_too_soon(now));
+        assert!(!flow(Some(1_000_000 - 60)).polled_too_soon(now + Duration::seconds(1)));
+    }
+}
@@ -0,0 +1,83 @@
+use jelly::chrono::{Duration, Utc};
+use mainlib::pages::{self, Post, Status};
+
+const SECRET_KEY: &str = "test-secret-key-that-is-long-enough-for-actix-session-0.6-cookies";
+
+mod post_should {
+    use super::*;
+
+    #[test]
+    fn parse_front_matter_and_markdown() {
+        let post = Post::parse(
+            "hello",
+            "---\ntitle: Hello: a story\npublished: 2022-04-15T09:00:00Z\nauthor: Jane\n---\n\n# Hi\n",
+        )
+        .unwrap();
+
+        assert_eq!(post.title, "Hello: a story");
+        assert_eq!(post.author.as_deref(), Some("Jane"));
+        assert!(post.is_published());
+        assert_eq!(post.last_modified(), post.published);
+        assert_eq!(post.html, "<h1>Hi</h1>\n");
+    }
+
+    #[test]
+    fn treat_undated_pages_as_drafts() {
+        let post = Post::parse("draft", "---\ntitle: Soon\n---\nText").unwrap();
+        assert!(!post.is_published());
+    }
+
+    #[test]
+    fn hold_back_drafts_whatever_their_date() {
+        let post = Post::parse("held", "---\ntitle: Held\nstatus: draft\npublished: 2022-04-15T09:00:00Z\n---\nText")
+            .unwrap();
+        assert_eq!(post.status, Status::Draft);
+        assert!(!post.is_published());
+        assert!(!post.is_scheduled());
+
+        assert!(Post::parse("odd", "---\ntitle: Odd\nstatus: pending\n---\nText").is_err());
+    }
+
+    #[test]
+    fn schedule_pages_dated_in_the_future() {
+        let publish_at = (Utc::now() + Duration::days(1)).to_rfc3339();
+        let source = format!("---\ntitle: Soon\npublish_at: {}\n---\nText", publish_at);
+        let post = Post::parse("soon", &source).unwrap();
+
+        assert!(!post.is_published());
+        assert!(post.is_scheduled());
+    }
+
+    #[test]
+    fn require_a_title() {
+        assert!(Post::parse("untitled", "---\nauthor: Jane\n---\nText").is_err());
+        assert!(Post::parse("bare", "Just text").is_err());
+    }
+}
+
+mod preview_token_should {
+    use super::*;
+
+    #[test]
+    fn preview_only_its_page_until_it_expires() {
+        std::env::set_var("SECRET_KEY", SECRET_KEY);
+        let now = Utc::now();
+        let token = pages::preview_token("hello", now + Duration::days(pages::PREVIEW_DAYS));
+
+        assert!(pages::preview_token_valid("hello", &token, now));
+        assert!(!pages::preview_token_valid("other", &token, now));
+        assert!(!pages::preview_token_valid("hello", &token, now + Duration::days(pages::PREVIEW_DAYS + 1)));
+    }
+
+    #[test]
+    fn reject_tampered_tokens() {
+        std::env::set_var("SECRET_KEY", SECRET_KEY);
+        let now = Utc::now();
+        let token = pages::preview_token("hello", now + Duration::days(1));
+        let (_, signature) = token.split_once('.').unwrap();
+        let extended = format!("{}.{}", (now + Duration::days(365)).timestamp(), signature);
+
+        assert!(!pages::preview_token_valid("hello", &extended, now));
+        assert!(!pages::preview_token_valid("hello", "nope", now));
+    }
+}
@@ -23,10 +23,34 @@ mod template_should_work_for {
     use jelly::tera::escape_html;
     use log::debug;
     use mainlib::accounts::jobs;
+    use mainlib::accounts::models::Digest;
+    use mainlib::notifications::models::DigestRecipient;
+    use mainlib::notifications::{build_digest_email, Notification};
     use std::env;
     use std::sync::{Arc, RwLock};
     use test_log::test;
 
+    #[test]
+    fn confirm_email_change() -> Result<(), anyhow::Error> {
+        dotenv::dotenv().ok();
+        let email = jelly::email::Email::new(
+            "email/confirm-email-change",
+            &["Erby Doe <new@example.com>".to_string()],
+            "Test subject",
+            jobs::build_confirm_email_change_context("/accounts/email/confirm", "new@example.com"),
+            Arc::new(RwLock::new(TEMPLATES.clone())),
+        )?;
+
+        assert_eq!(email.from, env::var("EMAIL_DEFAULT_FROM")?);
+        assert_eq!(email.to, "Erby Doe <new@example.com>");
+        debug!("{}", email.body);
+        assert!(email.body.contains("/accounts/email/confirm"));
+        assert!(email.body.contains("new@example.com"));
+        debug!("{}", email.body_html);
+        assert!(email.body_html.contains(&escape_html("/accounts/email/confirm")));
+        Ok(())
+    }
+
     #[test]
     fn odd_registration_attempt() -> Result<(), anyhow::Error> {
         dotenv::dotenv().ok();
@@ -48,6 +72,51 @@ mod template_should_work_for {
         Ok(())
     }
 
+    #[test]
+    fn notifications_digest() -> Result<(), anyhow::Error> {
+        dotenv::dotenv().ok();
+        // Unsubscribe links are signed.
+        env::set_var("SECRET_KEY", "test-secret-key-that-is-long-enough-for-actix-session-0.6-cookies");
+        let recipient = DigestRecipient {
+            account_id: 1,
+            name: "Erby Doe".to_string(),
+            email: "test@example.com".to_string(),
+        };
+        let notification = |id, category: &str, url: Option<&str>| Notification {
+            id,
+            account_id: 1,
+            category: category.to_string(),
+            title: format!("Notification {}", id),
+            body: None,
+            url: url.map(String::from),
+            read_at: None,
+            digested_at: None,
+            created: jelly::chrono::Utc::now(),
+        };
+        let notifications = [
+            notification(1, "billing", Some("/dashboard/usage")),
+            notification(2, "files", None),
+        ];
+        let email = build_digest_email(
+            &recipient,
+            Digest::Daily,
+            &notifications,
+            Arc::new(RwLock::new(TEMPLATES.clone())),
+        )?;
+
+        assert_eq!(email.to, "test@example.com");
+        assert_eq!(email.subject, "You have 2 unread notifications");
+        assert_eq!(email.category, jelly::email::EmailCategory::Bulk);
+        debug!("{}", email.body);
+        assert!(email.body.contains("Billing"));
+        assert!(email.body.contains("Notification 2"));
+        assert!(email.body.contains("/notifications/unsubscribe?token=1."));
+        debug!("{}", email.body_html);
+        assert!(email.body_html.contains("/dashboard/usage"));
+        assert!(email.body_html.contains("/notifications/unsubscribe?token=1."));
+        Ok(())
+    }
+
     #[test]
     fn reset_password() -> Result<(), anyhow::Error> {
         dotenv::dotenv().ok();
@@ -69,6 +138,58 @@ mod template_should_work_for {
         Ok(())
     }
 
+    #[test]
+    fn recover_account() -> Result<(), anyhow::Error> {
+        dotenv::dotenv().ok();
+        let email = jelly::email::Email::new(
+            "email/recover-account",
+            &["Erby Doe <test@example.com>".to_string()],
+            "Test subject",
+            jobs::build_recover_account_context("/accounts/recover/xxxx", &["github".to_string()]),
+            Arc::new(RwLock::new(TEMPLATES.clone())),
+        )?;
+
+        assert_eq!(email.to, "Erby Doe <test@example.com>");
+        debug!("{}", email.body);
+        assert!(email.body.contains("/accounts/recover/xxxx"));
+        assert!(email.body.contains("github"));
+        debug!("{}", email.body_html);
+        assert!(email.body_html.contains(&escape_html("/accounts/recover/xxxx")));
+        Ok(())
+    }
+
+    #[test]
+    fn suspicious_login() -> Result<(), anyhow::Error> {
+        dotenv::dotenv().ok();
+        let login = mainlib::audit::AuditEvent {
+            id: 1,
+            account_id: Some(1),
+            kind: "login".to_string(),
+            ip: Some("203.0.113.7".to_string()),
+            user_agent: None,
+            data: sqlx::types::Json(jelly::serde_json::json!({})),
+            created: jelly::chrono::Utc::now(),
+        };
+        let reasons = vec!["Signed in from a new country (Narnia)".to_string()];
+        let email = jelly::email::Email::new(
+            "email/suspicious-login",
+            &["Erby Doe <test@example.com>".to_string()],
+            "Test subject",
+            mainlib::audit::jobs::build_context("Erby Doe", "UTC", &login, &reasons),
+            Arc::new(RwLock::new(TEMPLATES.clone())),
+        )?;
+
+        assert_eq!(email.from, env::var("EMAIL_DEFAULT_FROM")?);
+        debug!("{}", email.body);
+        assert!(email.body.contains("203.0.113.7"));
+        assert!(email.body.contains(" UTC"));
+        assert!(email.body.contains("new country (Narnia)"));
+        assert!(email.body.contains("/dashboard/security"));
+        debug!("{}", email.body_html);
+        assert!(email.body_html.contains("new country (Narnia)"));
+        Ok(())
+    }
+
     #[test]
     fn verify_account() -> Result<(), anyhow::Error> {
         dotenv::dotenv().ok();
@@ -90,6 +211,49 @@ mod template_should_work_for {
         Ok(())
     }
 
+    #[test]
+    fn verify_reminder() -> Result<(), anyhow::Error> {
+        dotenv::dotenv().ok();
+        let mut context = jobs::build_verify_context("/verify/account");
+        context.insert("retention_days", &14);
+        let email = jelly::email::Email::new(
+            "email/verify-reminder",
+            &["Erby Doe <test@example.com>".to_string()],
+            "Test subject",
+            context,
+            Arc::new(RwLock::new(TEMPLATES.clone())),
+        )?;
+
+        assert_eq!(email.from, env::var("EMAIL_DEFAULT_FROM")?);
+        assert_eq!(email.to, "Erby Doe <test@example.com>");
+        debug!("{}", email.body);
+        assert!(email.body.contains("/verify/account"));
+        assert!(email.body.contains("removed 14 days"));
+        debug!("{}", email.body_html);
+        assert!(email.body_html.contains(&escape_html("/verify/account")));
+        Ok(())
+    }
+
+    #[test]
+    fn waitlist_invite() -> Result<(), anyhow::Error> {
+        dotenv::dotenv().ok();
+        let email = jelly::email::Email::new(
+            "email/waitlist-invite",
+            &["Erby Doe <test@example.com>".to_string()],
+            "Test subject",
+            mainlib::waitlist::jobs::build_context("/accounts/register?invite=abc"),
+            Arc::new(RwLock::new(TEMPLATES.clone())),
+        )?;
+
+        assert_eq!(email.from, env::var("EMAIL_DEFAULT_FROM")?);
+        assert_eq!(email.to, "Erby Doe <test@example.com>");
+        debug!("{}", email.body);
+        assert!(email.body.contains("/accounts/register?invite=abc"));
+        debug!("{}", email.body_html);
+        assert!(email.body_html.contains(&escape_html("/accounts/register?invite=abc")));
+        Ok(())
+    }
+
     #[test]
     fn welcome() -> Result<(), anyhow::Error> {
         dotenv::dotenv().ok();